@@ -0,0 +1,142 @@
+/// User-invokable commands shared by keybindings and the command palette.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    NewTab,
+    /// Give the active tab a name of the user's own.
+    RenameTab,
+    SplitVertical,
+    SplitHorizontal,
+    ClosePane,
+    ToggleZoom,
+    /// Turn the split around the active pane between side by side and
+    /// stacked.
+    RotateSplit,
+    /// Give every pane in the active tab an even share again.
+    EqualizePanes,
+    ToggleTheme,
+    /// Fade the panes without focus, or stop.
+    ToggleDimInactive,
+    /// Send typing and pastes to every pane in the active tab, or stop.
+    ToggleBroadcast,
+    Search,
+    About,
+    /// Hard reset (RIS) of the active pane's terminal without killing its shell.
+    ResetPane,
+    /// Hang up the active pane's shell and start a new one in its place.
+    RestartShell,
+    /// Drop the active pane's scrollback history, keeping the visible screen.
+    ClearScrollback,
+    /// Move pane dividers with the keyboard.
+    ResizePanes,
+    /// Mark or unmark the active pane's cursor line.
+    ToggleMark,
+    PrevMark,
+    NextMark,
+    /// Scroll the shell's previous or next prompt (OSC 133) to the top.
+    PrevPrompt,
+    NextPrompt,
+    /// Select and copy what the last command printed, between the last two
+    /// prompts.
+    CopyLastOutput,
+    /// Copy the selection with its colors and attributes as SGR escapes.
+    CopyAnsi,
+    /// Fill the screen with the window, or go back.
+    ToggleFullscreen,
+    /// Switch this window to the theme at this index in its `ThemeSet`.
+    SetTheme(usize),
+}
+
+impl Action {
+    /// Actions listed in the command palette, in display order.
+    pub const PALETTE: &'static [Action] = &[
+        Action::NewTab,
+        Action::RenameTab,
+        Action::SplitVertical,
+        Action::SplitHorizontal,
+        Action::ClosePane,
+        Action::ToggleZoom,
+        Action::RotateSplit,
+        Action::EqualizePanes,
+        Action::ToggleTheme,
+        Action::ToggleDimInactive,
+        Action::ToggleBroadcast,
+        Action::Search,
+        Action::ResetPane,
+        Action::RestartShell,
+        Action::ClearScrollback,
+        Action::ResizePanes,
+        Action::ToggleMark,
+        Action::PrevMark,
+        Action::NextMark,
+        Action::PrevPrompt,
+        Action::NextPrompt,
+        Action::CopyLastOutput,
+        Action::CopyAnsi,
+        Action::ToggleFullscreen,
+        Action::About,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Action::NewTab => "New Tab",
+            Action::RenameTab => "Rename Tab",
+            Action::SplitVertical => "Split Pane Vertically",
+            Action::SplitHorizontal => "Split Pane Horizontally",
+            Action::ClosePane => "Close Pane",
+            Action::ToggleZoom => "Toggle Pane Zoom",
+            Action::RotateSplit => "Rotate Split",
+            Action::EqualizePanes => "Equalize Pane Sizes",
+            Action::ToggleTheme => "Next Theme",
+            Action::ToggleDimInactive => "Toggle Inactive Pane Dimming",
+            Action::ToggleBroadcast => "Toggle Broadcast Input to All Panes",
+            Action::Search => "Search Scrollback",
+            Action::About => "About Koi",
+            Action::ResetPane => "Reset Pane (RIS)",
+            Action::RestartShell => "Restart Shell",
+            Action::ClearScrollback => "Clear Pane Scrollback",
+            Action::ResizePanes => "Resize Panes",
+            Action::ToggleMark => "Toggle Mark",
+            Action::PrevMark => "Jump to Previous Mark",
+            Action::NextMark => "Jump to Next Mark",
+            Action::PrevPrompt => "Jump to Previous Prompt",
+            Action::NextPrompt => "Jump to Next Prompt",
+            Action::CopyLastOutput => "Copy Last Command Output",
+            Action::CopyAnsi => "Copy as ANSI",
+            Action::ToggleFullscreen => "Toggle Fullscreen",
+            Action::SetTheme(_) => "Set Theme",
+        }
+    }
+
+    /// Shortcut hint shown next to the label in the palette.
+    pub fn shortcut(&self) -> &'static str {
+        match self {
+            Action::NewTab => "Cmd+T",
+            Action::RenameTab => "Cmd+Shift+I",
+            Action::SplitVertical => "Cmd+D",
+            Action::SplitHorizontal => "Cmd+Shift+D",
+            Action::ClosePane => "Cmd+W",
+            Action::ToggleZoom => "Cmd+Shift+Enter",
+            Action::RotateSplit => "Cmd+Shift+O",
+            Action::EqualizePanes => "Cmd+Alt+=",
+            Action::ToggleTheme => "Cmd+Shift+T",
+            Action::ToggleDimInactive => "Cmd+Shift+U",
+            Action::ToggleBroadcast => "Cmd+Shift+B",
+            Action::Search => "Cmd+F",
+            Action::About => "Cmd+,",
+            Action::ResetPane => "Cmd+Alt+R",
+            Action::ClearScrollback => "Cmd+Shift+K",
+            Action::ResizePanes => "Cmd+Shift+R",
+            Action::ToggleMark => "Cmd+M",
+            Action::PrevMark => "Cmd+Shift+M",
+            Action::NextMark => "Cmd+Alt+M",
+            Action::PrevPrompt => "Cmd+Up",
+            Action::NextPrompt => "Cmd+Down",
+            Action::CopyLastOutput => "Cmd+Shift+A",
+            #[cfg(target_os = "macos")]
+            Action::ToggleFullscreen => "Cmd+Ctrl+F",
+            #[cfg(not(target_os = "macos"))]
+            Action::ToggleFullscreen => "F11",
+            Action::RestartShell | Action::CopyAnsi | Action::SetTheme(_) => "",
+        }
+    }
+}
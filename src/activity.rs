@@ -0,0 +1,146 @@
+//! Long-running command detection, for the tab bar's "finished" dot: start
+//! a build, switch tabs, and see when its prompt comes back. Also the
+//! tab bar's marks for output and bells in background tabs.
+//!
+//! A command is running while something other than the shell owns the
+//! PTY's foreground process group (see `Pane::shell_in_foreground`). The
+//! panes are polled, so start and end times are accurate to `POLL_INTERVAL`.
+
+use std::time::{Duration, Instant};
+
+/// How often panes are checked for a running command.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Per-pane command timing.
+#[derive(Debug, Default)]
+pub struct CommandTimer {
+    started: Option<Instant>,
+}
+
+impl CommandTimer {
+    /// Record whether a command owns the foreground at `now`. When one is
+    /// seen to have finished, returns how long it ran.
+    pub fn observe(&mut self, running: bool, now: Instant) -> Option<Duration> {
+        match (running, self.started) {
+            (true, None) => {
+                self.started = Some(now);
+                None
+            }
+            (false, Some(start)) => {
+                self.started = None;
+                Some(now.saturating_duration_since(start))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// What happened in a tab while it was in the background: a long command
+/// in one of its panes ended, a pane printed something, or one rang the
+/// bell. Cleared when the tab is focused.
+#[derive(Debug, Default)]
+pub struct TabActivity {
+    finished: bool,
+    output: bool,
+    bell: bool,
+}
+
+impl TabActivity {
+    /// A command that ran for `ran` just finished in this tab. Sets the
+    /// indicator if it ran at least `threshold` (zero disables) and the tab
+    /// isn't the one being looked at; returns whether it did.
+    pub fn command_finished(&mut self, ran: Duration, threshold: Duration, tab_active: bool) -> bool {
+        if threshold.is_zero() || ran < threshold || tab_active {
+            return false;
+        }
+        self.finished = true;
+        true
+    }
+
+    /// A pane in the tab printed something. Returns whether that set the
+    /// indicator.
+    pub fn output(&mut self, tab_active: bool) -> bool {
+        !tab_active && !std::mem::replace(&mut self.output, true)
+    }
+
+    /// A pane in the tab rang the bell. Returns whether that set the
+    /// indicator.
+    pub fn bell(&mut self, tab_active: bool) -> bool {
+        !tab_active && !std::mem::replace(&mut self.bell, true)
+    }
+
+    /// The tab was focused. Returns whether that cleared any indicator.
+    pub fn focused(&mut self) -> bool {
+        let set = self.finished || self.output || self.bell;
+        *self = Self::default();
+        set
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    pub fn has_output(&self) -> bool {
+        self.output
+    }
+
+    pub fn has_bell(&self) -> bool {
+        self.bell
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECS: fn(u64) -> Duration = Duration::from_secs;
+
+    #[test]
+    fn timer_measures_from_first_busy_poll_to_first_idle_poll() {
+        let t0 = Instant::now();
+        let mut timer = CommandTimer::default();
+        assert_eq!(timer.observe(false, t0), None);
+        assert_eq!(timer.observe(true, t0 + SECS(1)), None);
+        assert_eq!(timer.observe(true, t0 + SECS(5)), None);
+        assert_eq!(timer.observe(false, t0 + SECS(31)), Some(SECS(30)));
+        assert_eq!(timer.observe(false, t0 + SECS(32)), None);
+    }
+
+    #[test]
+    fn only_long_commands_in_background_tabs_set_the_indicator() {
+        let threshold = SECS(10);
+        let mut tab = TabActivity::default();
+        assert!(!tab.command_finished(SECS(3), threshold, false), "too short");
+        assert!(!tab.command_finished(SECS(60), threshold, true), "tab in view");
+        assert!(!tab.command_finished(SECS(60), Duration::ZERO, false), "disabled");
+        assert!(!tab.is_finished());
+
+        assert!(tab.command_finished(SECS(10), threshold, false));
+        assert!(tab.is_finished());
+    }
+
+    #[test]
+    fn focusing_the_tab_clears_the_indicator() {
+        let mut tab = TabActivity::default();
+        assert!(!tab.focused());
+        tab.command_finished(SECS(20), SECS(10), false);
+        assert!(tab.focused());
+        assert!(!tab.is_finished());
+        assert!(!tab.focused());
+    }
+
+    #[test]
+    fn output_and_bells_mark_background_tabs_until_focused() {
+        let mut tab = TabActivity::default();
+        assert!(!tab.output(true));
+        assert!(!tab.bell(true));
+        assert!(!tab.has_output() && !tab.has_bell());
+
+        assert!(tab.output(false));
+        assert!(!tab.output(false), "already marked");
+        assert!(tab.bell(false));
+        assert!(tab.has_output() && tab.has_bell());
+        assert!(tab.focused());
+        assert!(!tab.has_output() && !tab.has_bell());
+    }
+}
@@ -0,0 +1,116 @@
+//! Timing shared by koi's animations (tab slide, focus pulse, scrollback
+//! bar fade). Progress comes from wall time, so it doesn't matter how often
+//! frames are drawn; the pacer only decides when the next one is worth it,
+//! for animations and for panes printing faster than the display shows.
+
+use std::time::{Duration, Instant};
+
+/// Assumed when the monitor doesn't report a refresh rate.
+const DEFAULT_REFRESH_MILLIHERTZ: u32 = 60_000;
+
+/// Fraction of `duration` elapsed between `start` and `now`, in 0..=1.
+pub fn progress(start: Instant, duration: Duration, now: Instant) -> f32 {
+    if duration.is_zero() {
+        return 1.0;
+    }
+    let elapsed = now.saturating_duration_since(start);
+    (elapsed.as_secs_f32() / duration.as_secs_f32()).min(1.0)
+}
+
+/// Ease-out cubic: fast start, smooth deceleration.
+pub fn ease_out_cubic(t: f32) -> f32 {
+    1.0 - (1.0 - t).powi(3)
+}
+
+/// Paces redraws to one per display frame of the window's current
+/// monitor, or fewer with a frame rate cap.
+#[derive(Clone, Copy, Debug)]
+pub struct FramePacer {
+    interval: Duration,
+    /// Shortest interval `max_fps` allows; zero for no cap.
+    min_interval: Duration,
+    last_frame: Option<Instant>,
+}
+
+impl FramePacer {
+    /// `refresh_millihertz` as reported by winit's
+    /// `MonitorHandle::refresh_rate_millihertz`; `max_fps` caps it, 0 for
+    /// no cap.
+    pub fn new(refresh_millihertz: Option<u32>, max_fps: u32) -> Self {
+        let min_interval = if max_fps == 0 { Duration::ZERO } else { Duration::from_secs(1) / max_fps };
+        let mut pacer = Self { interval: Duration::ZERO, min_interval, last_frame: None };
+        pacer.set_refresh_rate(refresh_millihertz);
+        pacer
+    }
+
+    /// Re-pace for a new monitor (the window moved or the mode changed).
+    pub fn set_refresh_rate(&mut self, refresh_millihertz: Option<u32>) {
+        let mhz = refresh_millihertz.filter(|&r| r >= 1000).unwrap_or(DEFAULT_REFRESH_MILLIHERTZ);
+        self.interval = Duration::from_nanos(1_000_000_000_000 / u64::from(mhz)).max(self.min_interval);
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Note that a frame was drawn at `now`.
+    pub fn frame_drawn(&mut self, now: Instant) {
+        self.last_frame = Some(now);
+    }
+
+    /// When the next frame is due: a frame after the last one, or `now` if
+    /// that has already passed.
+    pub fn next_frame(&self, now: Instant) -> Instant {
+        self.last_frame.map_or(now, |t| t + self.interval).max(now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: fn(u64) -> Duration = Duration::from_millis;
+
+    #[test]
+    fn interval_follows_refresh_rate() {
+        assert_eq!(FramePacer::new(Some(60_000), 0).interval(), Duration::from_nanos(16_666_666));
+        assert_eq!(FramePacer::new(Some(120_000), 0).interval(), Duration::from_nanos(8_333_333));
+        // Unknown or nonsense rates fall back to 60 Hz.
+        assert_eq!(FramePacer::new(None, 0).interval(), FramePacer::new(Some(60_000), 0).interval());
+        assert_eq!(FramePacer::new(Some(0), 0).interval(), FramePacer::new(Some(60_000), 0).interval());
+
+        let mut pacer = FramePacer::new(Some(60_000), 0);
+        pacer.set_refresh_rate(Some(144_000));
+        assert_eq!(pacer.interval(), Duration::from_nanos(6_944_444));
+    }
+
+    #[test]
+    fn a_frame_rate_cap_only_slows_frames_down() {
+        let mut pacer = FramePacer::new(Some(144_000), 30);
+        assert_eq!(pacer.interval(), Duration::from_nanos(33_333_333));
+        pacer.set_refresh_rate(Some(24_000));
+        assert_eq!(pacer.interval(), Duration::from_nanos(41_666_666));
+    }
+
+    #[test]
+    fn frames_are_spaced_one_interval_apart() {
+        let t0 = Instant::now();
+        let mut pacer = FramePacer::new(Some(100_000), 0);
+        assert_eq!(pacer.next_frame(t0), t0);
+        pacer.frame_drawn(t0);
+        assert_eq!(pacer.next_frame(t0 + MS(3)), t0 + MS(10));
+        // Running late: draw right away rather than in the past.
+        assert_eq!(pacer.next_frame(t0 + MS(25)), t0 + MS(25));
+    }
+
+    #[test]
+    fn progress_depends_only_on_wall_time() {
+        let t0 = Instant::now();
+        assert_eq!(progress(t0, MS(200), t0), 0.0);
+        assert!((progress(t0, MS(200), t0 + MS(50)) - 0.25).abs() < 1e-6);
+        assert_eq!(progress(t0, MS(200), t0 + MS(500)), 1.0);
+        assert_eq!(progress(t0, Duration::ZERO, t0), 1.0);
+        assert_eq!(ease_out_cubic(0.0), 0.0);
+        assert_eq!(ease_out_cubic(1.0), 1.0);
+    }
+}
@@ -0,0 +1,172 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Bells closer together than this ring once.
+pub const COALESCE: Duration = Duration::from_millis(100);
+/// Window over which a pane's bells are counted toward auto-mute.
+pub const MUTE_WINDOW: Duration = Duration::from_secs(5);
+/// A muted pane unmutes after this long without a bell.
+pub const QUIET_PERIOD: Duration = Duration::from_secs(5);
+/// Audible beeps allowed per second across all panes.
+pub const MAX_BEEPS_PER_SEC: usize = 3;
+
+/// Per-pane bell state: coalescing and auto-mute for bell floods.
+#[derive(Debug, Default)]
+pub struct PaneBell {
+    recent: VecDeque<Instant>,
+    last_ring: Option<Instant>,
+    last_bell: Option<Instant>,
+    muted: bool,
+}
+
+impl PaneBell {
+    /// Record a bell at `now`. True if it should ring (flash, and beep if the
+    /// global limit allows); false if it was coalesced or the pane is muted.
+    /// `mute_threshold` bells within `MUTE_WINDOW` mute the pane; 0 never
+    /// mutes.
+    pub fn on_bell(&mut self, now: Instant, mute_threshold: usize) -> bool {
+        self.last_bell = Some(now);
+        while self.recent.front().is_some_and(|&t| now.saturating_duration_since(t) >= MUTE_WINDOW) {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(now);
+        if mute_threshold > 0 && self.recent.len() >= mute_threshold {
+            self.muted = true;
+        }
+        if self.muted {
+            return false;
+        }
+        if self.last_ring.is_some_and(|t| now.saturating_duration_since(t) < COALESCE) {
+            return false;
+        }
+        self.last_ring = Some(now);
+        true
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    /// Unmute once the pane has been quiet for `QUIET_PERIOD`. Returns true
+    /// if this call unmuted it.
+    pub fn unmute_if_quiet(&mut self, now: Instant) -> bool {
+        let quiet = self.last_bell.is_none_or(|t| now.saturating_duration_since(t) >= QUIET_PERIOD);
+        if self.muted && quiet {
+            self.unmute();
+            return true;
+        }
+        false
+    }
+
+    /// Unmute now (the user clicked the badge). The flood count starts over.
+    pub fn unmute(&mut self) {
+        self.muted = false;
+        self.recent.clear();
+    }
+}
+
+/// Global cap on audible beeps, so several noisy panes can't chime
+/// continuously between them.
+#[derive(Debug, Default)]
+pub struct BeepLimiter {
+    recent: VecDeque<Instant>,
+}
+
+impl BeepLimiter {
+    /// True if a beep at `now` is allowed, recording it if so.
+    pub fn allow(&mut self, now: Instant) -> bool {
+        let second = Duration::from_secs(1);
+        while self.recent.front().is_some_and(|&t| now.saturating_duration_since(t) >= second) {
+            self.recent.pop_front();
+        }
+        if self.recent.len() >= MAX_BEEPS_PER_SEC {
+            return false;
+        }
+        self.recent.push_back(now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: fn(u64) -> Duration = Duration::from_millis;
+
+    #[test]
+    fn bells_within_coalesce_window_ring_once() {
+        let t0 = Instant::now();
+        let mut bell = PaneBell::default();
+        assert!(bell.on_bell(t0, 20));
+        assert!(!bell.on_bell(t0 + MS(50), 20));
+        assert!(!bell.on_bell(t0 + MS(99), 20));
+        assert!(bell.on_bell(t0 + MS(100), 20));
+    }
+
+    #[test]
+    fn flood_mutes_then_quiet_unmutes() {
+        let t0 = Instant::now();
+        let mut bell = PaneBell::default();
+        // 20 bells 200 ms apart: each rings until the 20th mutes the pane.
+        for i in 0..19 {
+            assert!(bell.on_bell(t0 + MS(200 * i), 20), "bell {i}");
+        }
+        assert!(!bell.on_bell(t0 + MS(200 * 19), 20));
+        assert!(bell.is_muted());
+
+        // Still flooding: stays muted, and keeps pushing the quiet deadline.
+        let last = t0 + MS(200 * 25);
+        assert!(!bell.on_bell(last, 20));
+        assert!(!bell.unmute_if_quiet(last + QUIET_PERIOD - MS(1)));
+        assert!(bell.is_muted());
+
+        assert!(bell.unmute_if_quiet(last + QUIET_PERIOD));
+        assert!(!bell.is_muted());
+        assert!(bell.on_bell(last + QUIET_PERIOD + MS(1), 20));
+    }
+
+    #[test]
+    fn slow_bells_never_mute() {
+        let t0 = Instant::now();
+        let mut bell = PaneBell::default();
+        // 3 per second stays under 20 per 5 s.
+        for i in 0..60 {
+            assert!(bell.on_bell(t0 + MS(334 * i), 20));
+        }
+        assert!(!bell.is_muted());
+    }
+
+    #[test]
+    fn zero_threshold_disables_mute() {
+        let t0 = Instant::now();
+        let mut bell = PaneBell::default();
+        for i in 0..100 {
+            bell.on_bell(t0 + MS(10 * i), 0);
+        }
+        assert!(!bell.is_muted());
+    }
+
+    #[test]
+    fn badge_click_unmutes_and_resets_count() {
+        let t0 = Instant::now();
+        let mut bell = PaneBell::default();
+        for i in 0..3 {
+            bell.on_bell(t0 + MS(200 * i), 3);
+        }
+        assert!(bell.is_muted());
+        bell.unmute();
+        assert!(bell.on_bell(t0 + MS(700), 3));
+        assert!(!bell.is_muted());
+    }
+
+    #[test]
+    fn beeps_are_capped_per_second() {
+        let t0 = Instant::now();
+        let mut beeps = BeepLimiter::default();
+        for i in 0..MAX_BEEPS_PER_SEC as u64 {
+            assert!(beeps.allow(t0 + MS(100 * i)));
+        }
+        assert!(!beeps.allow(t0 + MS(900)));
+        assert!(beeps.allow(t0 + MS(1000)));
+    }
+}
@@ -0,0 +1,281 @@
+use winit::keyboard::{Key, KeyCode, NamedKey, PhysicalKey};
+
+use crate::actions::Action;
+
+/// What a primary-modifier (Cmd / Ctrl) shortcut does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    Action(Action),
+    OpenPalette,
+    NewWindow,
+    /// Zero-based tab index.
+    GotoTab(usize),
+    PrevTab,
+    NextTab,
+    FocusNextPane,
+    FocusPrevPane,
+    Copy,
+    Paste,
+    /// Paste without asking first, however many lines.
+    PasteUnconfirmed,
+    ClearScreen,
+    ZoomIn,
+    ZoomOut,
+    ZoomReset,
+    Quit,
+}
+
+/// How a binding recognizes its key.
+#[derive(Clone, Copy, Debug)]
+pub enum Trigger {
+    /// The character the layout produces, compared case-insensitively. Used
+    /// for mnemonic letters, which should follow the layout (Cmd+Z is "z"
+    /// wherever the layout puts it).
+    Char(&'static str),
+    /// The key's position, ignoring what the layout prints on it. Used for
+    /// the digit row and zoom keys, which produce other characters (or dead
+    /// keys) unshifted on AZERTY, QWERTZ and friends.
+    Code(KeyCode),
+    Named(NamedKey),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShiftMatch {
+    Any,
+    Required,
+    Forbidden,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Binding {
+    pub trigger: Trigger,
+    pub shift: ShiftMatch,
+    pub command: Command,
+}
+
+const fn bind(trigger: Trigger, shift: ShiftMatch, command: Command) -> Binding {
+    Binding { trigger, shift, command }
+}
+
+use Command as C;
+use ShiftMatch::{Any, Forbidden, Required};
+use Trigger::{Char, Code, Named};
+
+/// Primary-modifier shortcuts. Digit-row bindings are tried first, so a digit
+/// position that produces a bound character (AZERTY's 6 key prints "-")
+/// keeps its positional meaning. Character bindings come next, and the other
+/// physical (zoom) bindings last, for keys whose character nothing is bound
+/// to (QWERTZ's "ß" and dead "´"). Within each kind, earlier entries win.
+pub const BINDINGS: &[Binding] = &[
+    bind(Char("t"), Required, C::Action(Action::ToggleTheme)),
+    bind(Char("p"), Required, C::OpenPalette),
+    bind(Char("k"), Required, C::Action(Action::ClearScrollback)),
+    bind(Char("r"), Required, C::Action(Action::ResizePanes)),
+    bind(Char("m"), Forbidden, C::Action(Action::ToggleMark)),
+    bind(Char("m"), Required, C::Action(Action::PrevMark)),
+    bind(Named(NamedKey::ArrowUp), Forbidden, C::Action(Action::PrevPrompt)),
+    bind(Named(NamedKey::ArrowDown), Forbidden, C::Action(Action::NextPrompt)),
+    bind(Char("a"), Required, C::Action(Action::CopyLastOutput)),
+    bind(Char("n"), Forbidden, C::NewWindow),
+    bind(Char("t"), Forbidden, C::Action(Action::NewTab)),
+    bind(Char("i"), Required, C::Action(Action::RenameTab)),
+    bind(Char("u"), Required, C::Action(Action::ToggleDimInactive)),
+    bind(Char("b"), Required, C::Action(Action::ToggleBroadcast)),
+    bind(Char("w"), Forbidden, C::Action(Action::ClosePane)),
+    bind(Char("{"), Required, C::PrevTab),
+    bind(Char("}"), Required, C::NextTab),
+    bind(Code(KeyCode::Digit1), Any, C::GotoTab(0)),
+    bind(Code(KeyCode::Digit2), Any, C::GotoTab(1)),
+    bind(Code(KeyCode::Digit3), Any, C::GotoTab(2)),
+    bind(Code(KeyCode::Digit4), Any, C::GotoTab(3)),
+    bind(Code(KeyCode::Digit5), Any, C::GotoTab(4)),
+    bind(Code(KeyCode::Digit6), Any, C::GotoTab(5)),
+    bind(Code(KeyCode::Digit7), Any, C::GotoTab(6)),
+    bind(Code(KeyCode::Digit8), Any, C::GotoTab(7)),
+    bind(Code(KeyCode::Digit9), Any, C::GotoTab(8)),
+    bind(Char("d"), Forbidden, C::Action(Action::SplitVertical)),
+    bind(Char("d"), Required, C::Action(Action::SplitHorizontal)),
+    bind(Named(NamedKey::Enter), Required, C::Action(Action::ToggleZoom)),
+    bind(Char("o"), Required, C::Action(Action::RotateSplit)),
+    bind(Char("]"), Forbidden, C::FocusNextPane),
+    bind(Char("["), Forbidden, C::FocusPrevPane),
+    bind(Char("c"), Any, C::Copy),
+    bind(Char("f"), Forbidden, C::Action(Action::Search)),
+    bind(Char("v"), Forbidden, C::Paste),
+    bind(Char("v"), Required, C::PasteUnconfirmed),
+    bind(Char(","), Forbidden, C::Action(Action::About)),
+    bind(Char("q"), Forbidden, C::Quit),
+    bind(Code(KeyCode::Equal), Any, C::ZoomIn),
+    bind(Code(KeyCode::NumpadAdd), Any, C::ZoomIn),
+    bind(Char("="), Any, C::ZoomIn),
+    bind(Char("+"), Any, C::ZoomIn),
+    bind(Code(KeyCode::Minus), Any, C::ZoomOut),
+    bind(Code(KeyCode::NumpadSubtract), Any, C::ZoomOut),
+    bind(Char("-"), Any, C::ZoomOut),
+    bind(Code(KeyCode::Digit0), Any, C::ZoomReset),
+    bind(Code(KeyCode::Numpad0), Any, C::ZoomReset),
+    bind(Char("k"), Forbidden, C::ClearScreen),
+];
+
+/// The parts of a key event a binding looks at.
+#[derive(Clone, Copy, Debug)]
+pub struct KeyInput<'a> {
+    pub logical: &'a Key,
+    pub physical: PhysicalKey,
+    pub shift: bool,
+}
+
+impl Binding {
+    fn shift_ok(&self, shift: bool) -> bool {
+        match self.shift {
+            ShiftMatch::Any => true,
+            ShiftMatch::Required => shift,
+            ShiftMatch::Forbidden => !shift,
+        }
+    }
+
+    fn is_physical(&self) -> bool {
+        matches!(self.trigger, Trigger::Code(_))
+    }
+
+    fn is_digit_row(&self) -> bool {
+        matches!(
+            self.trigger,
+            Trigger::Code(
+                KeyCode::Digit0
+                    | KeyCode::Digit1
+                    | KeyCode::Digit2
+                    | KeyCode::Digit3
+                    | KeyCode::Digit4
+                    | KeyCode::Digit5
+                    | KeyCode::Digit6
+                    | KeyCode::Digit7
+                    | KeyCode::Digit8
+                    | KeyCode::Digit9
+            )
+        )
+    }
+
+    fn matches(&self, input: &KeyInput) -> bool {
+        if !self.shift_ok(input.shift) {
+            return false;
+        }
+        match self.trigger {
+            Trigger::Char(c) => match input.logical {
+                Key::Character(s) => s.eq_ignore_ascii_case(c),
+                _ => false,
+            },
+            Trigger::Code(code) => input.physical == PhysicalKey::Code(code),
+            Trigger::Named(named) => *input.logical == Key::Named(named),
+        }
+    }
+}
+
+/// The command bound to `input` with the primary modifier held, if any.
+pub fn lookup(input: &KeyInput) -> Option<Command> {
+    let digits = BINDINGS.iter().filter(|b| b.is_digit_row());
+    let logical = BINDINGS.iter().filter(|b| !b.is_physical());
+    let zoom = BINDINGS.iter().filter(|b| b.is_physical() && !b.is_digit_row());
+    digits.chain(logical).chain(zoom).find(|b| b.matches(input)).map(|b| b.command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(logical: &str, code: KeyCode, shift: bool) -> Option<Command> {
+        let logical = Key::Character(logical.into());
+        lookup(&KeyInput { logical: &logical, physical: PhysicalKey::Code(code), shift })
+    }
+
+    fn press_dead(code: KeyCode) -> Option<Command> {
+        let logical = Key::Dead(None);
+        lookup(&KeyInput { logical: &logical, physical: PhysicalKey::Code(code), shift: false })
+    }
+
+    #[test]
+    fn us_layout() {
+        assert_eq!(press("1", KeyCode::Digit1, false), Some(C::GotoTab(0)));
+        assert_eq!(press("9", KeyCode::Digit9, false), Some(C::GotoTab(8)));
+        assert_eq!(press("=", KeyCode::Equal, false), Some(C::ZoomIn));
+        assert_eq!(press("+", KeyCode::Equal, true), Some(C::ZoomIn));
+        assert_eq!(press("-", KeyCode::Minus, false), Some(C::ZoomOut));
+        assert_eq!(press("0", KeyCode::Digit0, false), Some(C::ZoomReset));
+        assert_eq!(press("t", KeyCode::KeyT, false), Some(C::Action(Action::NewTab)));
+        assert_eq!(press("T", KeyCode::KeyT, true), Some(C::Action(Action::ToggleTheme)));
+    }
+
+    #[test]
+    fn azerty_digit_row_switches_tabs() {
+        // Unshifted AZERTY digit row: & é " ' ( - è _ ç à
+        let row = [
+            ("&", KeyCode::Digit1),
+            ("\u{e9}", KeyCode::Digit2),
+            ("\"", KeyCode::Digit3),
+            ("'", KeyCode::Digit4),
+            ("(", KeyCode::Digit5),
+            ("-", KeyCode::Digit6),
+            ("\u{e8}", KeyCode::Digit7),
+            ("_", KeyCode::Digit8),
+            ("\u{e7}", KeyCode::Digit9),
+        ];
+        for (i, (ch, code)) in row.into_iter().enumerate() {
+            assert_eq!(press(ch, code, false), Some(C::GotoTab(i)), "{ch}");
+        }
+        assert_eq!(press("\u{e0}", KeyCode::Digit0, false), Some(C::ZoomReset));
+    }
+
+    #[test]
+    fn azerty_zoom_keys_by_position() {
+        // The US "-" and "=" positions print ")" and "=" on AZERTY.
+        assert_eq!(press(")", KeyCode::Minus, false), Some(C::ZoomOut));
+        assert_eq!(press("=", KeyCode::Equal, false), Some(C::ZoomIn));
+    }
+
+    #[test]
+    fn qwertz_zoom_keys() {
+        // German: "ß" and a dead "´" sit where US has "-" and "="; the "+"
+        // and "-" characters are on other keys and still match by character.
+        assert_eq!(press("\u{df}", KeyCode::Minus, false), Some(C::ZoomOut));
+        assert_eq!(press_dead(KeyCode::Equal), Some(C::ZoomIn));
+        assert_eq!(press("+", KeyCode::BracketRight, false), Some(C::ZoomIn));
+        assert_eq!(press("-", KeyCode::Slash, false), Some(C::ZoomOut));
+        assert_eq!(press("1", KeyCode::Digit1, false), Some(C::GotoTab(0)));
+    }
+
+    #[test]
+    fn dvorak_brackets_are_not_zoom_keys() {
+        // Dvorak prints "[" and "]" (shifted "{" and "}") where US has "-"
+        // and "=", and "-" and "=" on the US quote and "]" keys.
+        assert_eq!(press("[", KeyCode::Minus, false), Some(C::FocusPrevPane));
+        assert_eq!(press("]", KeyCode::Equal, false), Some(C::FocusNextPane));
+        assert_eq!(press("{", KeyCode::Minus, true), Some(C::PrevTab));
+        assert_eq!(press("}", KeyCode::Equal, true), Some(C::NextTab));
+        assert_eq!(press("-", KeyCode::Quote, false), Some(C::ZoomOut));
+        assert_eq!(press("=", KeyCode::BracketRight, false), Some(C::ZoomIn));
+        assert_eq!(press("!", KeyCode::Digit1, true), Some(C::GotoTab(0)));
+        assert_eq!(press("N", KeyCode::KeyL, true), None);
+        assert_eq!(press("n", KeyCode::KeyL, false), Some(C::NewWindow));
+    }
+
+    #[test]
+    fn letters_follow_the_layout() {
+        // QWERTZ swaps Y and Z; AZERTY puts "w" on the US Z key.
+        assert_eq!(press("w", KeyCode::KeyZ, false), Some(C::Action(Action::ClosePane)));
+        assert_eq!(press("z", KeyCode::KeyW, false), None);
+        assert_eq!(press("q", KeyCode::KeyA, false), Some(C::Quit));
+    }
+
+    #[test]
+    fn shift_requirements() {
+        assert_eq!(press("k", KeyCode::KeyK, false), Some(C::ClearScreen));
+        assert_eq!(press("K", KeyCode::KeyK, true), Some(C::Action(Action::ClearScrollback)));
+        assert_eq!(press("D", KeyCode::KeyD, true), Some(C::Action(Action::SplitHorizontal)));
+        assert_eq!(press("W", KeyCode::KeyW, true), None);
+        assert_eq!(press("V", KeyCode::KeyV, true), Some(C::PasteUnconfirmed));
+        let enter = Key::Named(NamedKey::Enter);
+        let input = |shift| KeyInput { logical: &enter, physical: PhysicalKey::Code(KeyCode::Enter), shift };
+        assert_eq!(lookup(&input(true)), Some(C::Action(Action::ToggleZoom)));
+        assert_eq!(lookup(&input(false)), None);
+    }
+}
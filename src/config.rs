@@ -0,0 +1,757 @@
+use std::path::{Path, PathBuf};
+
+use alacritty_terminal::term::{Config as TermConfig, SEMANTIC_ESCAPE_CHARS};
+use alacritty_terminal::vte::ansi;
+use serde::{Deserialize, Deserializer};
+
+use crate::renderer::color::parse_hex;
+use crate::renderer::Theme;
+
+/// User configuration, read once at startup from `koi.toml`.
+///
+/// Every field has a default, so a missing file, a missing table, or a
+/// missing key all mean "built-in behavior".
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub window: WindowConfig,
+    pub scrolling: ScrollingConfig,
+    pub font: FontConfig,
+    pub mouse: MouseConfig,
+    pub bell: BellConfig,
+    pub working_directory: WorkingDirectoryConfig,
+    pub ruler: RulerConfig,
+    pub selection: SelectionConfig,
+    pub terminal: TerminalConfig,
+    pub activity: ActivityConfig,
+    pub search: SearchConfig,
+    pub keyboard: KeyboardConfig,
+    pub paste: PasteConfig,
+    pub tabs: TabsConfig,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowConfig {
+    /// Keep the column/row count when the font size changes and resize the
+    /// window instead (iTerm2-style). Off: the window keeps its pixel size
+    /// and the grid reflows.
+    pub resize_on_font_change: bool,
+    /// Briefly thicken and brighten the border of a newly focused pane.
+    pub focus_pulse: bool,
+    /// Zoom the active pane while the window is too small for the split
+    /// layout, and unzoom when it grows back. Off: only show a warning.
+    pub auto_zoom_when_small: bool,
+    /// Initial grid size; the window is sized to fit it. Unset: 960x600
+    /// logical pixels.
+    pub dimensions: Option<WindowDimensions>,
+    /// Grow new panes in and closed panes' neighbors out over a moment.
+    /// Off: splits and closes snap into place.
+    pub pane_animation: bool,
+    /// Fade the panes without focus when a tab is split. Cmd+Shift+U
+    /// toggles it while koi runs.
+    pub dim_inactive_panes: bool,
+    /// How strongly, as the alpha of the background drawn over them.
+    pub inactive_pane_dim: f32,
+    /// Most frames a second to draw while panes print. 0: as many as the
+    /// display shows.
+    pub max_fps: u32,
+    /// Opacity of the background, 0.0 (clear) to 1.0; text and colored
+    /// cells stay opaque. Below 1.0 the window is made transparent.
+    #[serde(deserialize_with = "opacity")]
+    pub opacity: f32,
+    /// Theme to open with, by name: a built-in or one from the themes dir.
+    /// Unset: the theme koi last closed with.
+    pub theme: Option<String>,
+    /// Switch between `light_theme` and `dark_theme` with the system's
+    /// light or dark appearance, overriding `theme`. Picking a theme by
+    /// hand stops it until koi restarts.
+    pub follow_system_appearance: bool,
+    pub light_theme: String,
+    pub dark_theme: String,
+    /// Give each pane of a split tab a header naming the program running
+    /// in it and its directory.
+    pub pane_headers: bool,
+    /// On macOS, fullscreen without a Space of its own or the animation
+    /// into it. Elsewhere fullscreen is always this kind.
+    pub simple_fullscreen: bool,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self {
+            resize_on_font_change: false,
+            focus_pulse: true,
+            auto_zoom_when_small: false,
+            dimensions: None,
+            pane_animation: true,
+            dim_inactive_panes: false,
+            inactive_pane_dim: 0.25,
+            max_fps: 0,
+            opacity: 1.0,
+            theme: None,
+            follow_system_appearance: false,
+            light_theme: "Catppuccin Latte".into(),
+            dark_theme: "Catppuccin Mocha".into(),
+            pane_headers: false,
+            simple_fullscreen: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WindowDimensions {
+    pub columns: usize,
+    pub lines: usize,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScrollingConfig {
+    /// Dim a pane while it shows scrollback instead of live output, and flash
+    /// a "SCROLLBACK" bar when first scrolling up.
+    pub dim_history: bool,
+    /// Lines of history each pane keeps; 0 keeps none. Capped at
+    /// `MAX_SCROLLBACK_LINES`.
+    #[serde(deserialize_with = "scrollback_lines")]
+    pub lines: usize,
+    /// Lines one wheel notch scrolls; trackpads scale the same way.
+    #[serde(deserialize_with = "scroll_multiplier")]
+    pub multiplier: f64,
+    /// Scroll the other way from what the system reports.
+    pub invert: bool,
+    /// Send the wheel as arrow keys on the alternate screen even when the
+    /// app hasn't turned on alternate scroll (DECSET 1007).
+    pub force_alternate_scroll: bool,
+}
+
+impl Default for ScrollingConfig {
+    fn default() -> Self {
+        Self {
+            dim_history: false,
+            lines: crate::terminal::DEFAULT_HISTORY_SIZE,
+            multiplier: DEFAULT_SCROLL_MULTIPLIER,
+            invert: false,
+            force_alternate_scroll: false,
+        }
+    }
+}
+
+/// `window.opacity`, clamped to 0.0..=1.0.
+fn opacity<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    let opacity = f32::deserialize(deserializer)?;
+    let clamped = if opacity.is_nan() { 1.0 } else { opacity.clamp(0.0, 1.0) };
+    if clamped != opacity {
+        log::warn!("window.opacity = {} is out of range; using {}", opacity, clamped);
+    }
+    Ok(clamped)
+}
+
+/// Three lines a notch, as in most terminals.
+const DEFAULT_SCROLL_MULTIPLIER: f64 = 3.0;
+
+/// `scrolling.multiplier`, which must be positive and finite. Anything else
+/// means the default, since 0 or a negative number would leave the wheel
+/// dead or backwards (`invert` is for that).
+fn scroll_multiplier<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    let multiplier = f64::deserialize(deserializer)?;
+    if multiplier > 0.0 && multiplier.is_finite() {
+        return Ok(multiplier);
+    }
+    log::warn!("scrolling.multiplier = {} must be above 0; using {}", multiplier, DEFAULT_SCROLL_MULTIPLIER);
+    Ok(DEFAULT_SCROLL_MULTIPLIER)
+}
+
+/// The most history a pane may keep. A full line of 200 columns takes
+/// several KB, so this is already gigabytes for a pane that fills it.
+pub const MAX_SCROLLBACK_LINES: usize = 1_000_000;
+
+/// `scrolling.lines`, clamped to 0..=`MAX_SCROLLBACK_LINES`.
+fn scrollback_lines<'de, D: Deserializer<'de>>(deserializer: D) -> Result<usize, D::Error> {
+    let lines = i64::deserialize(deserializer)?;
+    let clamped = lines.clamp(0, MAX_SCROLLBACK_LINES as i64) as usize;
+    if clamped as i64 != lines {
+        log::warn!("scrolling.lines = {} is out of range; using {}", lines, clamped);
+    }
+    Ok(clamped)
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RulerConfig {
+    /// Draw a vertical guide after each of these columns in every pane.
+    pub columns: Vec<usize>,
+    /// Keep the guides while a full-screen app has the alternate screen up.
+    pub show_on_alt_screen: bool,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SelectionConfig {
+    pub style: SelectionStyle,
+    /// Selected text and background with the "colors" style, as
+    /// `#rrggbb`, in place of the theme's.
+    #[serde(deserialize_with = "hex_color")]
+    pub foreground: Option<[f32; 3]>,
+    #[serde(deserialize_with = "hex_color")]
+    pub background: Option<[f32; 3]>,
+    /// Characters that end a double-click's word. Paths stay whole with the
+    /// default, which has no `/`, `.` or `-`; URLs split at the `:` after
+    /// their scheme unless it's left out.
+    pub semantic_escape_chars: String,
+}
+
+impl Default for SelectionConfig {
+    fn default() -> Self {
+        Self {
+            style: SelectionStyle::default(),
+            foreground: None,
+            background: None,
+            semantic_escape_chars: SEMANTIC_ESCAPE_CHARS.to_string(),
+        }
+    }
+}
+
+impl SelectionConfig {
+    /// `theme` with these colors in place of its own.
+    pub fn apply(&self, mut theme: Theme) -> Theme {
+        if let Some(fg) = self.foreground {
+            theme.selection_fg = Some(fg);
+        }
+        if let Some([r, g, b]) = self.background {
+            theme.selection = [r, g, b, theme.selection[3]];
+        }
+        theme
+    }
+}
+
+/// An optional `#rrggbb` color. One that doesn't parse is left unset, so
+/// the theme's color is used.
+fn hex_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<[f32; 3]>, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    let color = parse_hex(&s);
+    if color.is_none() {
+        log::warn!("{:?} is not a #rrggbb color; using the theme's", s);
+    }
+    Ok(color)
+}
+
+/// How selected cells are colored. Either way the selection replaces the
+/// cell's colors (including inverse video) rather than tinting them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SelectionStyle {
+    /// The theme's selection background and foreground.
+    #[default]
+    Colors,
+    /// Swap each cell's foreground and background.
+    Invert,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TerminalConfig {
+    /// Unwrap tmux passthrough (`ESC P tmux; ... ESC \`) so OSC 52 and
+    /// titles from apps inside tmux reach koi. Needs `allow-passthrough`
+    /// in tmux.
+    pub tmux_passthrough: bool,
+    /// Cursor shape until an app sets one with DECSCUSR (`CSI Ps q`), and
+    /// after it resets it with `CSI 0 q`.
+    pub cursor_style: CursorShape,
+    /// Whether that default cursor blinks. Apps pick steady or blinking
+    /// along with the shape.
+    pub cursor_blinking: bool,
+}
+
+impl Default for TerminalConfig {
+    fn default() -> Self {
+        Self { tmux_passthrough: true, cursor_style: CursorShape::Block, cursor_blinking: true }
+    }
+}
+
+impl TerminalConfig {
+    /// The `Term` configuration for a new pane.
+    pub fn term_config(&self) -> TermConfig {
+        let shape = match self.cursor_style {
+            CursorShape::Block => ansi::CursorShape::Block,
+            CursorShape::Beam => ansi::CursorShape::Beam,
+            CursorShape::Underline => ansi::CursorShape::Underline,
+        };
+        let default_cursor_style = ansi::CursorStyle { shape, blinking: self.cursor_blinking };
+        TermConfig { default_cursor_style, ..TermConfig::default() }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CursorShape {
+    #[default]
+    Block,
+    /// A vertical bar at the cell's left edge.
+    Beam,
+    Underline,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SearchConfig {
+    pub on_focus_change: SearchFocus,
+}
+
+/// What an open search does when focus moves to another pane.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchFocus {
+    /// Search the newly focused pane for the same query.
+    #[default]
+    Follow,
+    /// Close the search.
+    Close,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeyboardConfig {
+    pub ctrl_keys: CtrlKeys,
+    /// macOS-style editing keys: Option+Left/Right by word, Cmd+Left/Right
+    /// to the line's ends, Option/Cmd+Backspace to delete a word or the
+    /// line, sent as the readline bytes for each. Tab switching moves to
+    /// Cmd+Shift+Left/Right.
+    pub natural_text_editing: bool,
+    /// Cmd+Enter toggles fullscreen as well as Cmd+Ctrl+F, as in iTerm2.
+    /// Off: Cmd+Enter goes to the shell.
+    pub cmd_enter_fullscreen: bool,
+}
+
+/// How Ctrl combinations without a distinct control character reach the
+/// shell.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum CtrlKeys {
+    /// C0 control characters as xterm sends them; Ctrl+Shift+letter is
+    /// the same as Ctrl+letter, and combos with no C0 code send nothing.
+    #[default]
+    #[serde(rename = "legacy")]
+    Legacy,
+    /// Those combos as `CSI codepoint ; modifiers u`, so apps that parse
+    /// it can tell them apart. Combos with a C0 code still send it.
+    #[serde(rename = "csi-u")]
+    CsiU,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ActivityConfig {
+    /// A command that runs at least this many seconds and finishes in a
+    /// background tab marks that tab as finished. 0 turns this off.
+    pub long_command_seconds: u64,
+}
+
+impl Default for ActivityConfig {
+    fn default() -> Self {
+        Self { long_command_seconds: 10 }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BellConfig {
+    /// Mute a pane's bell after this many bells within 5 seconds, until it
+    /// has been quiet for 5 seconds or its badge is clicked. 0 never mutes.
+    pub mute_threshold: usize,
+}
+
+impl Default for BellConfig {
+    fn default() -> Self {
+        Self { mute_threshold: 20 }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PasteConfig {
+    /// Ask before Cmd+V pastes text with a newline into a shell, which
+    /// would run it line by line. Cmd+Shift+V never asks.
+    pub confirm_multiline: bool,
+}
+
+impl Default for PasteConfig {
+    fn default() -> Self {
+        Self { confirm_multiline: true }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TabsConfig {
+    /// What each tab in the tab bar reads: `{index}` is its position from
+    /// 1, `{title}` its name.
+    pub title_template: String,
+}
+
+impl Default for TabsConfig {
+    fn default() -> Self {
+        Self { title_template: "{title}".into() }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MouseConfig {
+    /// macOS: Ctrl+click acts as a right click, both for koi and in mouse
+    /// reports to apps. Off: Ctrl+click is reported as a Ctrl-modified left
+    /// click.
+    pub ctrl_click_as_right_click: bool,
+}
+
+impl Default for MouseConfig {
+    fn default() -> Self {
+        Self { ctrl_click_as_right_click: true }
+    }
+}
+
+/// Where the shell of a new tab, split or window starts.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorkingDirectoryConfig {
+    pub new_tab: WorkingDirectory,
+    pub new_split: WorkingDirectory,
+    pub new_window: WorkingDirectory,
+}
+
+impl Default for WorkingDirectoryConfig {
+    fn default() -> Self {
+        Self {
+            new_tab: WorkingDirectory::Inherit,
+            new_split: WorkingDirectory::Inherit,
+            new_window: WorkingDirectory::Home,
+        }
+    }
+}
+
+/// `"home"`, `"inherit"` (the focused pane's directory), or a path, which
+/// may start with `~`.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(from = "String")]
+pub enum WorkingDirectory {
+    Home,
+    Inherit,
+    Path(PathBuf),
+}
+
+impl From<String> for WorkingDirectory {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "home" => Self::Home,
+            "inherit" => Self::Inherit,
+            _ => Self::Path(PathBuf::from(s)),
+        }
+    }
+}
+
+impl WorkingDirectory {
+    /// The directory to start in. `inherited` is the focused pane's
+    /// directory, if known; anything unusable falls back to `home`.
+    pub fn resolve(&self, inherited: Option<&Path>, home: Option<&Path>) -> Option<PathBuf> {
+        match self {
+            Self::Home => home.map(Path::to_path_buf),
+            Self::Inherit => inherited.or(home).map(Path::to_path_buf),
+            Self::Path(path) => {
+                let expanded = expand_tilde(path, home);
+                match expanded {
+                    Some(dir) if dir.is_absolute() && dir.is_dir() => Some(dir),
+                    _ => {
+                        log::warn!(
+                            "working_directory {} is not an absolute directory; using home",
+                            path.display()
+                        );
+                        home.map(Path::to_path_buf)
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Replace a leading `~` component with `home`. None if the path needs a
+/// home directory and there isn't one.
+fn expand_tilde(path: &Path, home: Option<&Path>) -> Option<PathBuf> {
+    match path.strip_prefix("~") {
+        Ok(rest) => home.map(|h| h.join(rest)),
+        Err(_) => Some(path.to_path_buf()),
+    }
+}
+
+/// The user's home directory, from `$HOME`.
+pub fn home_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME").filter(|v| !v.is_empty()).map(PathBuf::from)
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FontConfig {
+    pub underline: UnderlineConfig,
+    /// Cut each glyph off at its cell's edges instead of letting italics
+    /// and wide bearings overhang into the next cell.
+    pub strict_clipping: bool,
+}
+
+/// Overrides for fonts that report bad underline metrics. Unset values come
+/// from the font.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UnderlineConfig {
+    /// Distance in points from the baseline down to the underline's center.
+    pub position: Option<f32>,
+    /// Line thickness in points.
+    pub thickness: Option<f32>,
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/koi/koi.toml`, falling back to `~/.config/koi/koi.toml`
+    /// (`%APPDATA%\koi\koi.toml` on Windows).
+    pub fn path() -> Option<PathBuf> {
+        #[cfg(windows)]
+        let base = std::env::var_os("APPDATA").map(PathBuf::from);
+        #[cfg(not(windows))]
+        let base = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")));
+        base.map(|b| b.join("koi").join("koi.toml"))
+    }
+
+    /// Load the config file, falling back to defaults if it's missing or
+    /// invalid. A broken config shouldn't keep the terminal from opening.
+    pub fn load() -> Self {
+        let Some(path) = Self::path() else {
+            return Self::default();
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(text) => Self::parse(&text).unwrap_or_else(|e| {
+                log::error!("Ignoring invalid config {}: {}", path.display(), e);
+                Self::default()
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(e) => {
+                log::error!("Failed to read config {}: {}", path.display(), e);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(text)
+    }
+}
+
+/// Where koi keeps files it writes itself: `$XDG_STATE_HOME/koi`, falling
+/// back to `~/.local/state/koi` (`%LOCALAPPDATA%\koi` on Windows).
+pub fn state_dir() -> Option<PathBuf> {
+    #[cfg(windows)]
+    let base = std::env::var_os("LOCALAPPDATA").map(PathBuf::from);
+    #[cfg(not(windows))]
+    let base = std::env::var_os("XDG_STATE_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".local").join("state")));
+    base.map(|b| b.join("koi"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_config_is_default() {
+        let config = Config::parse("").unwrap();
+        assert!(!config.window.resize_on_font_change);
+    }
+
+    #[test]
+    fn window_section_parses() {
+        let config = Config::parse("[window]\nresize_on_font_change = true\n").unwrap();
+        assert!(config.window.resize_on_font_change);
+        assert!(config.window.focus_pulse);
+    }
+
+    #[test]
+    fn scrolling_section_parses() {
+        let config = Config::parse("[scrolling]\ndim_history = true\n").unwrap();
+        assert!(config.scrolling.dim_history);
+        assert!(!config.window.resize_on_font_change);
+    }
+
+    #[test]
+    fn underline_overrides_parse() {
+        let config = Config::parse("[font.underline]\nthickness = 2\n").unwrap();
+        assert_eq!(config.font.underline.thickness, Some(2.0));
+        assert_eq!(config.font.underline.position, None);
+        assert!(!config.font.strict_clipping);
+        assert!(Config::parse("[font]\nstrict_clipping = true\n").unwrap().font.strict_clipping);
+    }
+
+    #[test]
+    fn ruler_columns_parse() {
+        assert!(Config::parse("").unwrap().ruler.columns.is_empty());
+        let config = Config::parse("[ruler]\ncolumns = [80, 120]\n").unwrap();
+        assert_eq!(config.ruler.columns, [80, 120]);
+        assert!(!config.ruler.show_on_alt_screen);
+    }
+
+    #[test]
+    fn selection_style_parses() {
+        assert_eq!(Config::parse("").unwrap().selection.style, SelectionStyle::Colors);
+        let config = Config::parse("[selection]\nstyle = \"invert\"\n").unwrap();
+        assert_eq!(config.selection.style, SelectionStyle::Invert);
+        assert!(Config::parse("[selection]\nstyle = \"xor\"\n").is_err());
+    }
+
+    #[test]
+    fn opacity_is_clamped() {
+        assert_eq!(Config::parse("").unwrap().window.opacity, 1.0);
+        assert_eq!(Config::parse("[window]\nopacity = 0.85\n").unwrap().window.opacity, 0.85);
+        assert_eq!(Config::parse("[window]\nopacity = 1.5\n").unwrap().window.opacity, 1.0);
+        assert_eq!(Config::parse("[window]\nopacity = -1.0\n").unwrap().window.opacity, 0.0);
+    }
+
+    #[test]
+    fn selection_colors_override_the_theme() {
+        let config = Config::parse("[selection]\nforeground = \"#ffffff\"\nbackground = \"nope\"\n").unwrap();
+        let theme = config.selection.apply(Theme::mocha());
+        assert_eq!(theme.selection_fg, Some([1.0, 1.0, 1.0]));
+        assert_eq!(theme.selection, Theme::mocha().selection);
+    }
+
+    #[test]
+    fn ctrl_click_translation_defaults_on() {
+        assert!(Config::parse("").unwrap().mouse.ctrl_click_as_right_click);
+        let config = Config::parse("[mouse]\nctrl_click_as_right_click = false\n").unwrap();
+        assert!(!config.mouse.ctrl_click_as_right_click);
+    }
+
+    #[test]
+    fn working_directory_values_parse() {
+        let config = Config::parse(
+            "[working_directory]\nnew_tab = \"home\"\nnew_window = \"~/src\"\n",
+        )
+        .unwrap();
+        let wd = config.working_directory;
+        assert_eq!(wd.new_tab, WorkingDirectory::Home);
+        assert_eq!(wd.new_split, WorkingDirectory::Inherit);
+        assert_eq!(wd.new_window, WorkingDirectory::Path(PathBuf::from("~/src")));
+    }
+
+    #[test]
+    fn working_directory_precedence() {
+        let home = std::env::temp_dir();
+        let cwd = Path::new("/some/project");
+        let home = Some(home.as_path());
+        assert_eq!(WorkingDirectory::Inherit.resolve(Some(cwd), home).as_deref(), Some(cwd));
+        assert_eq!(WorkingDirectory::Inherit.resolve(None, home).as_deref(), home);
+        assert_eq!(WorkingDirectory::Home.resolve(Some(cwd), home).as_deref(), home);
+        assert_eq!(WorkingDirectory::Home.resolve(None, None), None);
+    }
+
+    #[test]
+    fn configured_paths_expand_tilde_and_fall_back_to_home() {
+        let tmp = std::env::temp_dir();
+        let (parent, name) = (tmp.parent().unwrap(), tmp.file_name().unwrap());
+        let tilde = WorkingDirectory::Path(Path::new("~").join(name));
+        assert_eq!(tilde.resolve(None, Some(parent)), Some(tmp.clone()));
+        assert_eq!(tilde.resolve(None, None), None);
+
+        let home = Some(Path::new("/home/koi"));
+        let missing = WorkingDirectory::Path(tmp.join("koi-no-such-dir"));
+        assert_eq!(missing.resolve(None, home).as_deref(), home);
+        let relative = WorkingDirectory::Path(PathBuf::from("src"));
+        assert_eq!(relative.resolve(None, home).as_deref(), home);
+        assert_eq!(WorkingDirectory::Path(tmp.clone()).resolve(None, home), Some(tmp));
+    }
+
+    #[test]
+    fn tmux_passthrough_defaults_on() {
+        assert!(Config::parse("").unwrap().terminal.tmux_passthrough);
+        let config = Config::parse("[terminal]\ntmux_passthrough = false\n").unwrap();
+        assert!(!config.terminal.tmux_passthrough);
+    }
+
+    #[test]
+    fn window_dimensions() {
+        assert_eq!(Config::parse("").unwrap().window.dimensions, None);
+        let config = Config::parse("[window]\ndimensions = { columns = 120, lines = 34 }\n").unwrap();
+        assert_eq!(config.window.dimensions, Some(WindowDimensions { columns: 120, lines: 34 }));
+        assert!(Config::parse("[window]\ndimensions = { columns = 120 }\n").is_err());
+    }
+
+    #[test]
+    fn pane_animation_defaults_on() {
+        assert!(Config::parse("").unwrap().window.pane_animation);
+        assert!(!Config::parse("[window]\npane_animation = false\n").unwrap().window.pane_animation);
+    }
+
+    #[test]
+    fn multiline_paste_confirmation_defaults_on() {
+        assert!(Config::parse("").unwrap().paste.confirm_multiline);
+        let config = Config::parse("[paste]\nconfirm_multiline = false\n").unwrap();
+        assert!(!config.paste.confirm_multiline);
+    }
+
+    #[test]
+    fn search_focus_policy() {
+        assert_eq!(Config::parse("").unwrap().search.on_focus_change, SearchFocus::Follow);
+        let config = Config::parse("[search]\non_focus_change = \"close\"\n").unwrap();
+        assert_eq!(config.search.on_focus_change, SearchFocus::Close);
+    }
+
+    #[test]
+    fn ctrl_key_encoding() {
+        assert_eq!(Config::parse("").unwrap().keyboard.ctrl_keys, CtrlKeys::Legacy);
+        let config = Config::parse("[keyboard]\nctrl_keys = \"csi-u\"\n").unwrap();
+        assert_eq!(config.keyboard.ctrl_keys, CtrlKeys::CsiU);
+        assert!(!config.keyboard.natural_text_editing);
+        let config = Config::parse("[keyboard]\nnatural_text_editing = true\n").unwrap();
+        assert!(config.keyboard.natural_text_editing);
+    }
+
+    #[test]
+    fn default_cursor_style() {
+        let style = Config::parse("").unwrap().terminal.term_config().default_cursor_style;
+        assert_eq!(style, ansi::CursorStyle { shape: ansi::CursorShape::Block, blinking: true });
+        let config = Config::parse("[terminal]\ncursor_style = \"beam\"\ncursor_blinking = false\n").unwrap();
+        let style = config.terminal.term_config().default_cursor_style;
+        assert_eq!(style, ansi::CursorStyle { shape: ansi::CursorShape::Beam, blinking: false });
+    }
+
+    #[test]
+    fn scrollback_lines_are_clamped() {
+        let lines = |text: &str| Config::parse(text).unwrap().scrolling.lines;
+        assert_eq!(lines(""), 10_000);
+        assert_eq!(lines("[scrolling]\nlines = 100000\n"), 100_000);
+        assert_eq!(lines("[scrolling]\nlines = 0\n"), 0);
+        assert_eq!(lines("[scrolling]\nlines = -5\n"), 0);
+        assert_eq!(lines("[scrolling]\nlines = 99999999999\n"), MAX_SCROLLBACK_LINES);
+        assert!(Config::parse("[scrolling]\nlines = \"lots\"\n").is_err());
+    }
+
+    #[test]
+    fn scroll_multiplier_and_direction() {
+        let scrolling = Config::parse("").unwrap().scrolling;
+        assert_eq!((scrolling.multiplier, scrolling.invert), (3.0, false));
+        let scrolling = Config::parse("[scrolling]\nmultiplier = 1.5\ninvert = true\n").unwrap().scrolling;
+        assert_eq!((scrolling.multiplier, scrolling.invert), (1.5, true));
+        assert_eq!(Config::parse("[scrolling]\nmultiplier = -2.0\n").unwrap().scrolling.multiplier, 3.0);
+    }
+
+    #[test]
+    fn long_command_threshold() {
+        assert_eq!(Config::parse("").unwrap().activity.long_command_seconds, 10);
+        let config = Config::parse("[activity]\nlong_command_seconds = 0\n").unwrap();
+        assert_eq!(config.activity.long_command_seconds, 0);
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        assert!(Config::parse("[window]\nresize_on_font_chnage = true\n").is_err());
+    }
+}
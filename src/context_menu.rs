@@ -0,0 +1,143 @@
+//! The right-click menu: a small panel of pane commands opened at the
+//! pointer.
+
+use crate::actions::Action;
+use crate::bindings::Command;
+
+/// A row of the menu.
+pub struct MenuEntry {
+    pub label: &'static str,
+    pub hint: &'static str,
+    pub command: Command,
+}
+
+/// The menu's rows, top to bottom. Each runs the same command as its
+/// shortcut.
+pub const ENTRIES: [MenuEntry; 5] = [
+    MenuEntry { label: "Copy", hint: "Cmd+C", command: Command::Copy },
+    MenuEntry { label: "Paste", hint: "Cmd+V", command: Command::Paste },
+    MenuEntry { label: "Split Vertical", hint: "Cmd+D", command: Command::Action(Action::SplitVertical) },
+    MenuEntry { label: "Split Horizontal", hint: "Cmd+Shift+D", command: Command::Action(Action::SplitHorizontal) },
+    MenuEntry { label: "Close Pane", hint: "Cmd+W", command: Command::Action(Action::ClosePane) },
+];
+
+/// Columns between the longest label and its hint.
+const GAP: usize = 3;
+
+/// State for the open right-click menu.
+pub struct ContextMenu {
+    /// Top-left corner, in window pixels.
+    pub x: f32,
+    pub y: f32,
+    /// Index into `ENTRIES` of the highlighted row, under the pointer or
+    /// picked with the arrow keys.
+    pub hovered: Option<usize>,
+}
+
+impl ContextMenu {
+    /// Open at `point`, shifted left and up as needed to fit in `window`.
+    pub fn open(point: (f32, f32), window: (f32, f32), cell: (f32, f32)) -> Self {
+        let (w, h) = Self::size(cell);
+        Self {
+            x: point.0.min(window.0 - w).max(0.0),
+            y: point.1.min(window.1 - h).max(0.0),
+            hovered: None,
+        }
+    }
+
+    /// Width in columns: a cell of padding either side of the widest row.
+    pub fn cols() -> usize {
+        let label = ENTRIES.iter().map(|e| e.label.len()).max().unwrap_or(0);
+        let hint = ENTRIES.iter().map(|e| e.hint.len()).max().unwrap_or(0);
+        label + GAP + hint + 2
+    }
+
+    /// Width and height in pixels, with half a row of padding above and
+    /// below the entries.
+    pub fn size((cw, ch): (f32, f32)) -> (f32, f32) {
+        (Self::cols() as f32 * cw, (ENTRIES.len() + 1) as f32 * ch)
+    }
+
+    /// Top of row `index`.
+    pub fn row_y(&self, index: usize, ch: f32) -> f32 {
+        self.y + ch * (0.5 + index as f32)
+    }
+
+    /// The entry under `point`, if any.
+    pub fn entry_at(&self, (px, py): (f32, f32), cell: (f32, f32)) -> Option<usize> {
+        let (w, _) = Self::size(cell);
+        if px < self.x || px >= self.x + w {
+            return None;
+        }
+        let row = (py - self.row_y(0, cell.1)) / cell.1;
+        (row >= 0.0 && (row as usize) < ENTRIES.len()).then_some(row as usize)
+    }
+
+    /// Highlight the entry under `point`. True if the highlight moved.
+    pub fn hover(&mut self, point: (f32, f32), cell: (f32, f32)) -> bool {
+        let hovered = self.entry_at(point, cell);
+        let moved = hovered != self.hovered;
+        self.hovered = hovered;
+        moved
+    }
+
+    pub fn select_next(&mut self) {
+        self.hovered = Some(self.hovered.map_or(0, |i| (i + 1) % ENTRIES.len()));
+    }
+
+    pub fn select_prev(&mut self) {
+        self.hovered = Some(self.hovered.map_or(ENTRIES.len() - 1, |i| (i + ENTRIES.len() - 1) % ENTRIES.len()));
+    }
+
+    /// What the highlighted entry runs.
+    pub fn selected_command(&self) -> Option<Command> {
+        self.hovered.map(|i| ENTRIES[i].command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CELL: (f32, f32) = (10.0, 20.0);
+
+    #[test]
+    fn opens_at_the_pointer_and_stays_on_screen() {
+        let (w, h) = ContextMenu::size(CELL);
+        let menu = ContextMenu::open((100.0, 50.0), (800.0, 600.0), CELL);
+        assert_eq!((menu.x, menu.y), (100.0, 50.0));
+        let menu = ContextMenu::open((790.0, 590.0), (800.0, 600.0), CELL);
+        assert_eq!((menu.x, menu.y), (800.0 - w, 600.0 - h));
+        // A window smaller than the menu pins it to the top left.
+        let menu = ContextMenu::open((5.0, 5.0), (w / 2.0, h / 2.0), CELL);
+        assert_eq!((menu.x, menu.y), (0.0, 0.0));
+    }
+
+    #[test]
+    fn rows_under_the_pointer_are_hit_and_padding_is_not() {
+        let mut menu = ContextMenu::open((100.0, 100.0), (800.0, 600.0), CELL);
+        // Rows start half a row below the top.
+        assert_eq!(menu.entry_at((105.0, 105.0), CELL), None);
+        assert_eq!(menu.entry_at((105.0, 110.0), CELL), Some(0));
+        assert_eq!(menu.entry_at((105.0, 135.0), CELL), Some(1));
+        assert_eq!(menu.entry_at((105.0, 110.0 + 20.0 * 5.0), CELL), None, "bottom padding");
+        assert_eq!(menu.entry_at((95.0, 110.0), CELL), None, "left of the menu");
+
+        assert!(menu.hover((105.0, 135.0), CELL));
+        assert!(!menu.hover((110.0, 138.0), CELL), "same row");
+        assert_eq!(menu.selected_command(), Some(Command::Paste));
+        assert!(menu.hover((0.0, 0.0), CELL));
+        assert_eq!(menu.selected_command(), None);
+    }
+
+    #[test]
+    fn arrow_keys_wrap() {
+        let mut menu = ContextMenu::open((0.0, 0.0), (800.0, 600.0), CELL);
+        menu.select_prev();
+        assert_eq!(menu.hovered, Some(ENTRIES.len() - 1));
+        menu.select_next();
+        assert_eq!(menu.hovered, Some(0));
+        menu.select_next();
+        assert_eq!(menu.hovered, Some(1));
+    }
+}
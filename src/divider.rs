@@ -0,0 +1,68 @@
+//! How thick pane dividers are drawn and how close the pointer must come
+//! to grab one, sized for the window's scale factor so they stay visible
+//! and easy to hit on HiDPI displays.
+
+use winit::window::CursorIcon;
+
+use crate::panes::{DividerInfo, Split};
+
+/// Divider sizes in physical pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DividerStyle {
+    /// Width of the line between panes.
+    pub thickness: f32,
+    /// Width of the line under the pointer or being dragged.
+    pub hover_thickness: f32,
+    /// How far either side of the line still grabs it.
+    pub grab: f32,
+}
+
+impl DividerStyle {
+    /// Sizes for a window at `scale` (2.0 on Retina).
+    pub fn scaled(scale: f64) -> Self {
+        let scale = scale.max(1.0) as f32;
+        Self {
+            thickness: (2.0 * scale).round(),
+            hover_thickness: (4.0 * scale).round(),
+            grab: (4.0 * scale).round(),
+        }
+    }
+}
+
+/// The divider within `grab` pixels of `(x, y)`, in pane viewport
+/// coordinates.
+pub fn hit(dividers: &[DividerInfo], x: f32, y: f32, grab: f32) -> Option<&DividerInfo> {
+    dividers.iter().find(|div| {
+        let (along, perp) = match div.split {
+            Split::Vertical => (x, y),
+            Split::Horizontal => (y, x),
+        };
+        (along - div.position).abs() <= grab && perp >= div.perp_start && perp <= div.perp_end
+    })
+}
+
+/// The pointer over a divider of `split`: it moves across the line.
+pub fn cursor(split: Split) -> CursorIcon {
+    match split {
+        Split::Vertical => CursorIcon::ColResize,
+        Split::Horizontal => CursorIcon::RowResize,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::panes::PaneTree;
+
+    #[test]
+    fn retina_dividers_are_thicker_and_easier_to_grab() {
+        assert_eq!(DividerStyle::scaled(1.0), DividerStyle { thickness: 2.0, hover_thickness: 4.0, grab: 4.0 });
+        assert_eq!(DividerStyle::scaled(2.0), DividerStyle { thickness: 4.0, hover_thickness: 8.0, grab: 8.0 });
+        let mut tree = PaneTree::new(0);
+        tree.split_active(Split::Vertical, 1);
+        let dividers = tree.collect_dividers(800.0, 600.0);
+        assert!(hit(&dividers, 406.0, 300.0, 4.0).is_none());
+        assert!(hit(&dividers, 406.0, 300.0, 8.0).is_some());
+        assert!(hit(&dividers, 400.0, 601.0, 8.0).is_none());
+    }
+}
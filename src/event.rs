@@ -1,100 +1,312 @@
 use std::borrow::Cow;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
 
 use alacritty_terminal::event::{Event as TermEvent, EventListener, Notify, WindowSize};
-use alacritty_terminal::event_loop::Msg;
+use alacritty_terminal::event_loop::{EventLoopSendError, EventLoopSender, Msg};
+use alacritty_terminal::vte::ansi::Rgb;
 use winit::event_loop::EventLoopProxy;
 
 /// Custom event sent from terminal threads to the winit event loop.
 pub enum KoiEvent {
-    /// Terminal content changed, needs redraw.
-    Wakeup,
+    /// Terminal content changed, needs redraw (pane_id).
+    Wakeup(usize),
     /// Terminal title changed (title, pane_id).
     Title(String, usize),
     /// Child process exited (pane_id, exit_code).
     ChildExit(usize, i32),
-    /// Terminal bell.
-    Bell,
+    /// Terminal bell (pane_id).
+    Bell(usize),
     /// OSC 52: remote app wants to set the local clipboard.
     ClipboardStore(String),
     /// OSC 52: remote app wants to read the local clipboard (pane_id, formatter).
     ClipboardLoad(usize, Arc<dyn Fn(&str) -> String + Sync + Send + 'static>),
+    /// OSC 4/10/11/12 query: an app asks for a color (pane_id, color index,
+    /// formatter for the reply).
+    ColorRequest(usize, usize, Arc<dyn Fn(Rgb) -> String + Sync + Send + 'static>),
+    /// The terminal's reply to a query an app made, for its PTY (pane_id, bytes).
+    PtyWrite(usize, Vec<u8>),
 }
 
 impl std::fmt::Debug for KoiEvent {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::Wakeup => write!(f, "Wakeup"),
+            Self::Wakeup(id) => write!(f, "Wakeup({id})"),
             Self::Title(t, id) => write!(f, "Title({t}, {id})"),
             Self::ChildExit(id, code) => write!(f, "ChildExit({id}, {code})"),
-            Self::Bell => write!(f, "Bell"),
+            Self::Bell(id) => write!(f, "Bell({id})"),
             Self::ClipboardStore(text) => write!(f, "ClipboardStore({text})"),
             Self::ClipboardLoad(id, _) => write!(f, "ClipboardLoad({id})"),
+            Self::ColorRequest(id, index, _) => write!(f, "ColorRequest({id}, {index})"),
+            Self::PtyWrite(id, bytes) => write!(f, "PtyWrite({id}, {:?})", String::from_utf8_lossy(bytes)),
         }
     }
 }
 
+impl KoiEvent {
+    /// The pane the event came from, which says whose window it is for.
+    /// None for the clipboard store, which any window can take.
+    pub fn pane_id(&self) -> Option<usize> {
+        match self {
+            Self::Wakeup(id)
+            | Self::Title(_, id)
+            | Self::ChildExit(id, _)
+            | Self::Bell(id)
+            | Self::ClipboardLoad(id, _)
+            | Self::ColorRequest(id, _, _)
+            | Self::PtyWrite(id, _) => Some(*id),
+            Self::ClipboardStore(_) => None,
+        }
+    }
+}
+
+/// Events that arrive before the window state exists (a fast shell can
+/// title itself or draw its prompt during startup), held for replay.
+#[derive(Debug, Default)]
+pub struct PendingEvents {
+    events: Vec<KoiEvent>,
+}
+
+impl PendingEvents {
+    pub fn push(&mut self, event: KoiEvent) {
+        // One redraw covers any number of wakeups from a pane.
+        if let KoiEvent::Wakeup(id) = event {
+            if self.events.iter().any(|e| matches!(e, KoiEvent::Wakeup(queued) if *queued == id)) {
+                return;
+            }
+        }
+        self.events.push(event);
+    }
+
+    /// The queued events in arrival order, leaving the queue empty.
+    pub fn take(&mut self) -> Vec<KoiEvent> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+/// Where an `EventProxy` delivers events: the winit event loop, or a
+/// recorder in tests.
+pub trait EventSink: Send + Sync {
+    fn send(&self, event: KoiEvent);
+}
+
+impl EventSink for EventLoopProxy<KoiEvent> {
+    fn send(&self, event: KoiEvent) {
+        let _ = self.send_event(event);
+    }
+}
+
 /// Bridges alacritty_terminal events to winit's event loop.
 #[derive(Clone)]
 pub struct EventProxy {
-    proxy: EventLoopProxy<KoiEvent>,
+    sink: Arc<dyn EventSink>,
     pane_id: usize,
+    exit_code: Arc<OnceLock<i32>>,
 }
 
 impl EventProxy {
-    pub fn new(proxy: EventLoopProxy<KoiEvent>) -> Self {
-        Self { proxy, pane_id: 0 }
+    pub fn new(sink: impl EventSink + 'static) -> Self {
+        Self { sink: Arc::new(sink), pane_id: 0, exit_code: Arc::default() }
     }
 
     /// Create a proxy tagged with a specific pane ID.
     pub fn with_pane_id(&self, pane_id: usize) -> Self {
         Self {
-            proxy: self.proxy.clone(),
+            sink: self.sink.clone(),
             pane_id,
+            exit_code: Arc::default(),
         }
     }
+
+    /// Where the pane's exit code is kept once its PTY loop reports it,
+    /// ahead of the `ChildExit` event reaching the event loop.
+    pub fn exit_code(&self) -> Arc<OnceLock<i32>> {
+        self.exit_code.clone()
+    }
 }
 
 impl EventListener for EventProxy {
     fn send_event(&self, event: TermEvent) {
         let koi_event = match event {
-            TermEvent::Wakeup => KoiEvent::Wakeup,
+            TermEvent::Wakeup => KoiEvent::Wakeup(self.pane_id),
             TermEvent::Title(title) => KoiEvent::Title(title, self.pane_id),
-            TermEvent::ChildExit(code) => KoiEvent::ChildExit(self.pane_id, code),
-            TermEvent::Bell => KoiEvent::Bell,
+            TermEvent::ChildExit(code) => {
+                let _ = self.exit_code.set(code);
+                KoiEvent::ChildExit(self.pane_id, code)
+            }
+            TermEvent::Bell => KoiEvent::Bell(self.pane_id),
             // OSC 52: remote app sets local clipboard (e.g. vim yank over SSH).
             TermEvent::ClipboardStore(_, text) => KoiEvent::ClipboardStore(text),
             // OSC 52: remote app reads local clipboard.
             TermEvent::ClipboardLoad(_, formatter) => KoiEvent::ClipboardLoad(self.pane_id, formatter),
+            // Apps like vim and delta ask for the background to pick light
+            // or dark colors.
+            TermEvent::ColorRequest(index, formatter) => KoiEvent::ColorRequest(self.pane_id, index, formatter),
+            // Device attributes, cursor position and the like, which tmux
+            // and friends wait on.
+            TermEvent::PtyWrite(text) if is_query_reply(&text) => KoiEvent::PtyWrite(self.pane_id, text.into_bytes()),
             // Security: intentionally block these events.
-            // - PtyWrite: blocks DECRQSS echo-back attacks
-            // - ColorRequest: blocks terminal color information leaks
+            // - PtyWrite of anything but a query reply: nothing an app
+            //   printed may be echoed back as input (DECRQSS-style attacks)
             _ => return,
         };
-        let _ = self.proxy.send_event(koi_event);
+        self.sink.send(koi_event);
     }
 }
 
-/// Writes input to the PTY via the event loop channel.
-pub struct Notifier(pub alacritty_terminal::event_loop::EventLoopSender);
+/// Whether `text` is a reply the terminal writes back for an app's query:
+/// device attributes (`CSI ? 6 c`, `CSI > 0 ; v ; 1 c`), status and cursor
+/// position (`CSI 0 n`, `CSI l ; c R`), modes (`CSI ? m ; s $ y`), the
+/// keyboard protocol (`CSI ? f u`) and the text area size
+/// (`CSI 8 ; l ; c t`). These hold only numbers, so no text from the
+/// screen can reach the shell through them.
+pub fn is_query_reply(text: &str) -> bool {
+    let Some(body) = text.strip_prefix("\x1b[") else { return false };
+    let mut chars = body.chars();
+    let Some(final_byte) = chars.next_back() else { return false };
+    "cnRyut".contains(final_byte) && chars.all(|c| c.is_ascii_digit() || ";?>$".contains(c))
+}
+
+/// The sending half of a PTY event loop's channel. Tests stand in a
+/// recorder for the real loop.
+pub trait PtyChannel {
+    fn send(&self, msg: Msg) -> Result<(), EventLoopSendError>;
+}
+
+impl PtyChannel for EventLoopSender {
+    fn send(&self, msg: Msg) -> Result<(), EventLoopSendError> {
+        EventLoopSender::send(self, msg)
+    }
+}
+
+/// Bytes of input handed to a PTY thread that it hasn't written to the
+/// shell yet. The pane adds what it sends; the PTY's writer takes off what
+/// goes through.
+#[derive(Clone, Debug, Default)]
+pub struct Backlog(Arc<AtomicUsize>);
+
+impl Backlog {
+    pub fn get(&self) -> usize {
+        self.0.load(Ordering::Acquire)
+    }
+
+    fn queued(&self, n: usize) {
+        self.0.fetch_add(n, Ordering::AcqRel);
+    }
+
+    pub fn written(&self, n: usize) {
+        let _ = self.0.fetch_update(Ordering::AcqRel, Ordering::Acquire, |queued| Some(queued.saturating_sub(n)));
+    }
+}
+
+/// Writes input to the PTY via the event loop channel, counting it into
+/// the backlog until the PTY thread writes it.
+pub struct Notifier(pub Box<dyn PtyChannel>, pub Backlog);
 
 impl Notify for Notifier {
     fn notify<B: Into<Cow<'static, [u8]>>>(&self, bytes: B) {
-        let _ = self.0.send(Msg::Input(bytes.into()));
+        let _ = self.send_bytes(bytes.into().into_owned());
     }
 }
 
-impl Notifier {
-    pub fn send_input(&self, data: &[u8]) {
-        let _ = self.0.send(Msg::Input(Cow::Owned(data.to_vec())));
+/// Input the PTY thread will never see: it has stopped and dropped its
+/// end of the channel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InputClosed;
+
+/// Where a pane's input goes: the PTY event loop in production, a stub in
+/// tests.
+pub trait PtyInput {
+    /// Queue `bytes` for the shell.
+    fn write(&self, bytes: Vec<u8>) -> Result<(), InputClosed>;
+
+    /// False while the shell is behind on reading what it was already sent,
+    /// so more should wait.
+    fn ready(&self) -> bool {
+        true
+    }
+}
+
+/// Receiver of a pane's PTY size changes: the PTY event loop in production,
+/// a recorder in tests.
+pub trait PtyResize {
+    fn send_resize(&self, size: WindowSize);
+}
+
+/// A pane's PTY thread as seen by shutdown: told to stop, then waited on.
+pub trait PtyShutdown {
+    /// Hang up the shell and ask the PTY thread to stop.
+    fn request_shutdown(&mut self);
+    /// True once the PTY thread has exited, or if there is none.
+    fn is_finished(&self) -> bool;
+    /// Join the PTY thread, or if `detach`, let it finish on its own.
+    fn release(&mut self, detach: bool);
+}
+
+impl PtyResize for Notifier {
+    fn send_resize(&self, size: WindowSize) {
+        Notifier::send_resize(self, size);
+    }
+}
+
+impl PtyInput for Notifier {
+    fn write(&self, bytes: Vec<u8>) -> Result<(), InputClosed> {
+        self.send_bytes(bytes)
     }
+}
 
+impl Notifier {
     /// Send owned bytes without copying — use with format!().into_bytes().
-    pub fn send_bytes(&self, data: Vec<u8>) {
-        let _ = self.0.send(Msg::Input(Cow::Owned(data)));
+    pub fn send_bytes(&self, data: Vec<u8>) -> Result<(), InputClosed> {
+        self.1.queued(data.len());
+        match self.0.send(Msg::Input(Cow::Owned(data))) {
+            Ok(()) => Ok(()),
+            Err(EventLoopSendError::Send(_)) => Err(InputClosed),
+            // Queued, but the loop wasn't woken; it picks the bytes up on
+            // its next wakeup.
+            Err(EventLoopSendError::Io(e)) => {
+                log::warn!("PTY event loop wakeup failed: {}", e);
+                Ok(())
+            }
+        }
     }
 
     pub fn send_resize(&self, size: WindowSize) {
         let _ = self.0.send(Msg::Resize(size));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pending_events_replay_in_order_with_one_wakeup_per_pane() {
+        let mut pending = PendingEvents::default();
+        pending.push(KoiEvent::Wakeup(0));
+        pending.push(KoiEvent::Title("zsh".into(), 0));
+        pending.push(KoiEvent::Wakeup(0));
+        pending.push(KoiEvent::Wakeup(1));
+        pending.push(KoiEvent::Bell(0));
+        let replay: Vec<_> = pending.take().iter().map(|e| format!("{e:?}")).collect();
+        assert_eq!(replay, ["Wakeup(0)", "Title(zsh, 0)", "Wakeup(1)", "Bell(0)"]);
+        assert!(pending.take().is_empty());
+    }
+
+    #[test]
+    fn only_numeric_query_replies_are_written_back() {
+        for reply in ["\x1b[?6c", "\x1b[>0;2501;1c", "\x1b[0n", "\x1b[12;40R", "\x1b[?2004;1$y", "\x1b[?0u", "\x1b[8;24;80t"] {
+            assert!(is_query_reply(reply), "{reply:?}");
+        }
+        for other in ["\x1b[", "\x1bP1$r0m\x1b\\", "\x1b]l title\x1b\\", "\x1b[1;2Rrm -rf ~\r", "ls\r"] {
+            assert!(!is_query_reply(other), "{other:?}");
+        }
+    }
+
+    #[test]
+    fn events_name_their_pane_except_clipboard_stores() {
+        assert_eq!(KoiEvent::Title("vim".into(), 4).pane_id(), Some(4));
+        assert_eq!(KoiEvent::ChildExit(2, 0).pane_id(), Some(2));
+        assert_eq!(KoiEvent::ClipboardStore("x".into()).pane_id(), None);
+    }
+}
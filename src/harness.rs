@@ -0,0 +1,1683 @@
+//! Drives `KoiState` the way the event loop does, with no window, GPU or
+//! shell behind it. The window records redraws and titles, the canvas
+//! records what each frame drew, and each pane's "shell" records the bytes
+//! and sizes it was sent. Tests feed in window and terminal events and
+//! read those back.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::num::NonZeroU32;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+use alacritty_terminal::event::{EventListener, WindowSize};
+use alacritty_terminal::event_loop::{EventLoopSendError, Msg};
+use alacritty_terminal::index::Point;
+use alacritty_terminal::sync::FairMutex;
+use alacritty_terminal::term::Term;
+use alacritty_terminal::tty;
+use alacritty_terminal::vte::ansi::{CursorShape, Processor, StdSyncHandler};
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+use winit::event::{DeviceId, ElementState, MouseButton, MouseScrollDelta, TouchPhase, WindowEvent};
+use winit::keyboard::{Key, ModifiersState, NamedKey, NativeKeyCode, PhysicalKey};
+use winit::window::{CursorIcon, Theme as Appearance};
+
+use crate::config::{Config, FontConfig, SelectionStyle, TerminalConfig};
+use crate::event::{Backlog, EventProxy, EventSink, KoiEvent, PtyChannel};
+use crate::keyboard::KeyPress;
+use crate::panes::PaneLayout;
+use crate::renderer::{Canvas, Theme};
+use crate::tabs::{Launcher, PaneProcess, TabManager};
+use crate::window::WindowOps;
+use crate::{Exit, KoiState};
+
+/// Cmd on macOS, Ctrl elsewhere: the modifier koi's shortcuts use.
+#[cfg(target_os = "macos")]
+pub const PRIMARY: ModifiersState = ModifiersState::SUPER;
+#[cfg(not(target_os = "macos"))]
+pub const PRIMARY: ModifiersState = ModifiersState::CONTROL;
+
+/// A window that remembers what it was asked to do.
+pub struct TestWindow {
+    pub size: Cell<PhysicalSize<u32>>,
+    pub redraws: Cell<usize>,
+    pub title: RefCell<Option<String>>,
+    pub frames: Cell<usize>,
+    pub opened_urls: RefCell<Vec<String>>,
+    /// Calls to `request_user_attention`, the bell on Linux.
+    pub attention_requests: Cell<usize>,
+    pub cursor: Cell<CursorIcon>,
+    pub appearance: Cell<Option<Appearance>>,
+    /// While fullscreen, whether it's the simple kind.
+    pub fullscreen: Cell<Option<bool>>,
+}
+
+impl TestWindow {
+    fn new(width: u32, height: u32) -> Self {
+        TestWindow {
+            size: Cell::new(PhysicalSize::new(width, height)),
+            redraws: Cell::new(0),
+            title: RefCell::new(None),
+            frames: Cell::new(0),
+            opened_urls: RefCell::new(Vec::new()),
+            attention_requests: Cell::new(0),
+            cursor: Cell::new(CursorIcon::Default),
+            appearance: Cell::new(None),
+            fullscreen: Cell::new(None),
+        }
+    }
+}
+
+impl WindowOps for TestWindow {
+    fn inner_size(&self) -> PhysicalSize<u32> {
+        self.size.get()
+    }
+
+    fn request_redraw(&self) {
+        self.redraws.set(self.redraws.get() + 1);
+    }
+
+    fn set_title(&self, title: &str) {
+        *self.title.borrow_mut() = Some(title.to_string());
+    }
+
+    fn set_min_inner_size(&self, _size: PhysicalSize<u32>) {}
+
+    fn set_cursor(&self, icon: CursorIcon) {
+        self.cursor.set(icon);
+    }
+
+    fn scale_factor(&self) -> f64 {
+        1.0
+    }
+
+    fn outer_position(&self) -> Option<PhysicalPosition<i32>> {
+        Some(PhysicalPosition::new(40, 60))
+    }
+
+    fn request_inner_size(&self, size: PhysicalSize<u32>) -> Option<PhysicalSize<u32>> {
+        self.size.set(size);
+        Some(size)
+    }
+
+    fn is_free_size(&self) -> bool {
+        self.fullscreen.get().is_none()
+    }
+
+    fn is_fullscreen(&self) -> bool {
+        self.fullscreen.get().is_some()
+    }
+
+    fn toggle_fullscreen(&self, simple: bool) {
+        self.fullscreen.set(if self.is_fullscreen() { None } else { Some(simple) });
+    }
+
+    fn appearance(&self) -> Option<Appearance> {
+        self.appearance.get()
+    }
+
+    fn refresh_rate_millihertz(&self) -> Option<u32> {
+        None
+    }
+
+    fn request_user_attention(&self) {
+        self.attention_requests.set(self.attention_requests.get() + 1);
+    }
+
+    fn resize_surface(&self, _width: NonZeroU32, _height: NonZeroU32) {}
+
+    fn swap_buffers(&self) {
+        self.frames.set(self.frames.get() + 1);
+    }
+
+    fn open_url(&self, url: &str) {
+        self.opened_urls.borrow_mut().push(url.to_string());
+    }
+}
+
+/// A glyph drawn outside a terminal grid: tab titles, bars, badges.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DrawnGlyph {
+    pub x: f32,
+    pub y: f32,
+    pub c: char,
+    pub fg: [f32; 4],
+}
+
+/// A canvas with fixed-size cells that keeps the last frame's draws.
+pub struct TestCanvas {
+    theme: Theme,
+    cell: (f32, f32),
+    pub rects: Vec<[f32; 4]>,
+    pub glyphs: Vec<DrawnGlyph>,
+    /// Where each pane's grid was drawn, and its size in cells.
+    pub grids: Vec<((f32, f32), (usize, usize))>,
+    /// The link underlined in each grid drawn.
+    pub links: Vec<Option<(Point, Point)>>,
+    /// The cursor shape drawn in each grid, if any.
+    pub cursors: Vec<Option<CursorShape>>,
+    /// What the frame was cleared to.
+    pub clear: [f32; 4],
+    /// Rects drawn in a blended pass, over what was flushed before them.
+    pub blended: Vec<[f32; 4]>,
+    /// How many of `rects` have been flushed.
+    flushed: usize,
+}
+
+impl TestCanvas {
+    fn new(font_size: f32) -> Self {
+        TestCanvas {
+            theme: Theme::latte(),
+            cell: Self::cell_for(font_size),
+            rects: Vec::new(),
+            glyphs: Vec::new(),
+            grids: Vec::new(),
+            links: Vec::new(),
+            cursors: Vec::new(),
+            clear: [0.0; 4],
+            blended: Vec::new(),
+            flushed: 0,
+        }
+    }
+
+    /// 7x14 px cells at the default font size.
+    fn cell_for(font_size: f32) -> (f32, f32) {
+        (font_size / 2.0, font_size)
+    }
+
+    /// The text drawn on the row of cells starting at `y`, left to right,
+    /// with gaps between glyphs kept as spaces.
+    pub fn row_text(&self, y: f32) -> String {
+        let mut row: Vec<_> = self.glyphs.iter().filter(|g| g.y == y).collect();
+        row.sort_by(|a, b| a.x.total_cmp(&b.x));
+        let mut text = String::new();
+        let mut next_x = row.first().map_or(0.0, |g| g.x);
+        for glyph in row {
+            while next_x + self.cell.0 / 2.0 < glyph.x {
+                text.push(' ');
+                next_x += self.cell.0;
+            }
+            text.push(glyph.c);
+            next_x = glyph.x + self.cell.0;
+        }
+        text
+    }
+}
+
+impl Canvas for TestCanvas {
+    fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+    }
+
+    fn cell_width(&self) -> f32 {
+        self.cell.0
+    }
+
+    fn cell_height(&self) -> f32 {
+        self.cell.1
+    }
+
+    fn draw_rect(&mut self, x: f32, y: f32, w: f32, h: f32, _color: [f32; 4]) {
+        self.rects.push([x, y, w, h]);
+    }
+
+    fn draw_glyph(&mut self, cell_x: f32, y: f32, c: char, fg: [f32; 4]) {
+        self.glyphs.push(DrawnGlyph { x: cell_x, y, c, fg });
+    }
+
+    fn draw_grid<T: EventListener>(
+        &mut self,
+        _pane_id: usize,
+        term: &FairMutex<Term<T>>,
+        offset_x: f32,
+        offset_y: f32,
+        cursor: crate::renderer::cursor::CursorView,
+        _selection: SelectionStyle,
+        link: Option<(Point, Point)>,
+    ) {
+        use alacritty_terminal::grid::Dimensions;
+        let term = term.lock();
+        self.grids.push(((offset_x, offset_y), (term.columns(), term.screen_lines())));
+        self.links.push(link);
+        self.cursors.push(cursor.shape(&*term));
+    }
+
+    fn begin_frame(&mut self, _width: u32, _height: u32, clear: [f32; 4]) {
+        self.clear = clear;
+        self.rects.clear();
+        self.glyphs.clear();
+        self.grids.clear();
+        self.links.clear();
+        self.cursors.clear();
+        self.blended.clear();
+        self.flushed = 0;
+    }
+
+    fn flush(&mut self, _width: f32, _height: f32) {
+        self.flushed = self.rects.len();
+    }
+
+    fn flush_clipped(&mut self, width: f32, height: f32, _clip: (f32, f32, f32, f32)) {
+        self.flush(width, height);
+    }
+
+    fn flush_blended(&mut self, _width: f32, _height: f32) {
+        self.blended.extend_from_slice(&self.rects[self.flushed..]);
+        self.flushed = self.rects.len();
+    }
+
+    fn invalidate_glyphs(&mut self) {}
+
+    fn rebuild(&mut self, font_size: f32, scale: f32, _font: &FontConfig) {
+        let (w, h) = Self::cell_for(font_size);
+        self.cell = (w * scale, h * scale);
+    }
+}
+
+/// What each pane's stand-in shell was sent, by pane id.
+#[derive(Debug, Default)]
+pub struct PtyLog {
+    pub input: HashMap<usize, Vec<u8>>,
+    /// Every size sent, as (columns, lines), oldest first.
+    pub resizes: HashMap<usize, Vec<(u16, u16)>>,
+    /// The environment each shell was started with.
+    pub env: HashMap<usize, HashMap<String, String>>,
+    /// What each pane ran in place of the login shell.
+    pub programs: HashMap<usize, Option<tty::Shell>>,
+    /// Input waiting for a shell that has stopped reading, by pane id.
+    /// It stays in the pane's backlog until `Harness::resume`.
+    stalled: HashMap<usize, Vec<u8>>,
+    backlogs: HashMap<usize, Backlog>,
+}
+
+struct RecordingChannel {
+    pane_id: usize,
+    log: Rc<RefCell<PtyLog>>,
+    backlog: Backlog,
+}
+
+impl PtyChannel for RecordingChannel {
+    fn send(&self, msg: Msg) -> Result<(), EventLoopSendError> {
+        let mut log = self.log.borrow_mut();
+        match msg {
+            Msg::Input(bytes) => match log.stalled.get_mut(&self.pane_id) {
+                Some(held) => held.extend_from_slice(&bytes),
+                None => {
+                    log.input.entry(self.pane_id).or_default().extend_from_slice(&bytes);
+                    self.backlog.written(bytes.len());
+                }
+            },
+            Msg::Resize(size) => {
+                log.resizes.entry(self.pane_id).or_default().push((size.num_cols, size.num_lines));
+            }
+            Msg::Shutdown => {}
+        }
+        Ok(())
+    }
+}
+
+/// Starts panes whose shell is a `RecordingChannel`.
+struct RecordingLauncher(Rc<RefCell<PtyLog>>);
+
+impl Launcher for RecordingLauncher {
+    fn launch(
+        &self,
+        _terminal: &TerminalConfig,
+        _term: &Arc<FairMutex<Term<EventProxy>>>,
+        _proxy: EventProxy,
+        size: WindowSize,
+        opts: &tty::Options,
+    ) -> PaneProcess {
+        // Learn the pane's id the way its shell would.
+        let pane_id = opts.env["KOI_PANE_ID"].parse().expect("KOI_PANE_ID");
+        let mut log = self.0.borrow_mut();
+        log.resizes.entry(pane_id).or_default().push((size.num_cols, size.num_lines));
+        log.env.insert(pane_id, opts.env.clone());
+        log.programs.insert(pane_id, opts.shell.clone());
+        let backlog = Backlog::default();
+        log.backlogs.insert(pane_id, backlog.clone());
+        PaneProcess::detached(RecordingChannel { pane_id, log: self.0.clone(), backlog: backlog.clone() }, backlog)
+    }
+}
+
+/// Terminal events the panes sent, waiting for `Harness::pump`.
+#[derive(Clone, Default)]
+struct EventQueue(Arc<Mutex<Vec<KoiEvent>>>);
+
+impl EventSink for EventQueue {
+    fn send(&self, event: KoiEvent) {
+        self.0.lock().unwrap().push(event);
+    }
+}
+
+/// A `KoiState` in an 800x600 window, with one tab.
+pub struct Harness {
+    pub state: KoiState<TestWindow, TestCanvas>,
+    pub pty: Rc<RefCell<PtyLog>>,
+    events: EventQueue,
+}
+
+impl Harness {
+    pub fn new() -> Self {
+        let mut config = Config::default();
+        // Splits resize their panes at once rather than at the end of an
+        // animation.
+        config.window.pane_animation = false;
+        Self::with_config(config)
+    }
+
+    pub fn with_config(config: Config) -> Self {
+        Self::window(config, 1, crate::tabs::PaneIds::default())
+    }
+
+    /// Window `number` of several, its panes numbered from the counter the
+    /// others share.
+    pub fn window(config: Config, number: u64, pane_ids: crate::tabs::PaneIds) -> Self {
+        Self::build(config, number, pane_ids, crate::tabs::FirstPane::Shell)
+    }
+
+    /// A window whose first pane runs `first`, as from koi's command line.
+    pub fn running(first: crate::tabs::FirstPane) -> Self {
+        Self::build(Config::default(), 1, crate::tabs::PaneIds::default(), first)
+    }
+
+    fn build(config: Config, number: u64, pane_ids: crate::tabs::PaneIds, first: crate::tabs::FirstPane) -> Self {
+        let pty = Rc::new(RefCell::new(PtyLog::default()));
+        let events = EventQueue::default();
+        let event_proxy = EventProxy::new(events.clone());
+        let window = TestWindow::new(800, 600);
+        let canvas = TestCanvas::new(crate::DEFAULT_FONT_SIZE);
+        let size = window.inner_size();
+        let (cw, ch) = (canvas.cell_width(), canvas.cell_height());
+        let (cols, rows) = crate::tabs::pane_grid_size(size.width as f32, size.height as f32, cw, ch);
+        let tab_manager = TabManager::new(
+            cols,
+            rows,
+            cw,
+            ch,
+            &event_proxy,
+            config.working_directory.clone(),
+            config.terminal.clone(),
+            config.scrolling.lines,
+            config.window.pane_animation,
+            first,
+            number,
+            pane_ids,
+            Box::new(RecordingLauncher(pty.clone())),
+        );
+        let state = KoiState::new(config, window, canvas, event_proxy, 1.0, tab_manager);
+        Harness { state, pty, events }
+    }
+
+    /// Deliver a window event, saying whether koi would close the window
+    /// or quit.
+    pub fn event(&mut self, event: WindowEvent) -> Exit {
+        self.state.window_event(event)
+    }
+
+    /// Deliver the events panes have sent since the last pump.
+    pub fn pump(&mut self) -> bool {
+        let events = std::mem::take(&mut *self.events.0.lock().unwrap());
+        let mut exit = false;
+        for event in events {
+            exit |= self.state.user_event(event);
+        }
+        exit
+    }
+
+    /// Hold `mods` (and nothing else) from now on.
+    pub fn hold(&mut self, mods: ModifiersState) {
+        self.event(WindowEvent::ModifiersChanged(mods.into()));
+    }
+
+    /// Press `key` with `mods` held, then let go of the modifiers.
+    pub fn key(&mut self, mods: ModifiersState, key: Key) -> Exit {
+        self.key_at(mods, key, PhysicalKey::Unidentified(NativeKeyCode::Unidentified))
+    }
+
+    /// Press `key` on the keyboard key `physical`, for bindings made by
+    /// key position.
+    pub fn key_at(&mut self, mods: ModifiersState, key: Key, physical: PhysicalKey) -> Exit {
+        self.hold(mods);
+        let text = match &key {
+            Key::Character(s) if !mods.control_key() && !mods.super_key() => Some(s.clone()),
+            Key::Named(NamedKey::Enter) => Some("\r".into()),
+            _ => None,
+        };
+        let press = KeyPress {
+            state: ElementState::Pressed,
+            logical_key: key.clone(),
+            physical_key: physical,
+            text,
+            key_without_modifiers: key,
+            repeat: false,
+        };
+        let exit = self.state.handle_keyboard(press);
+        self.hold(ModifiersState::empty());
+        exit
+    }
+
+    /// Type `text` one unmodified key at a time.
+    pub fn type_text(&mut self, text: &str) {
+        for c in text.chars() {
+            self.key(ModifiersState::empty(), Key::Character(c.to_string().into()));
+        }
+    }
+
+    pub fn named(&mut self, mods: ModifiersState, key: NamedKey) -> Exit {
+        self.key(mods, Key::Named(key))
+    }
+
+    pub fn mouse_to(&mut self, x: f64, y: f64) {
+        let device_id = DeviceId::dummy();
+        self.event(WindowEvent::CursorMoved { device_id, position: PhysicalPosition::new(x, y) });
+    }
+
+    pub fn mouse(&mut self, button: MouseButton, state: ElementState) {
+        let device_id = DeviceId::dummy();
+        self.event(WindowEvent::MouseInput { device_id, state, button });
+    }
+
+    /// Turn the mouse wheel `lines` notches; positive scrolls up.
+    pub fn wheel(&mut self, lines: f32) {
+        let device_id = DeviceId::dummy();
+        let delta = MouseScrollDelta::LineDelta(0.0, lines);
+        self.event(WindowEvent::MouseWheel { device_id, delta, phase: TouchPhase::Moved });
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.state.window.size.set(PhysicalSize::new(width, height));
+        self.event(WindowEvent::Resized(PhysicalSize::new(width, height)));
+    }
+
+    /// Draw a frame, as the window would after a redraw request.
+    pub fn render(&mut self) -> &TestCanvas {
+        self.state.needs_redraw = true;
+        self.event(WindowEvent::RedrawRequested);
+        &self.state.renderer
+    }
+
+    /// Show `bytes` in pane `pane_id` as if its shell had printed them,
+    /// waking koi as the PTY thread does.
+    pub fn output(&mut self, pane_id: usize, bytes: &[u8]) {
+        let pane = self.state.tab_manager.pane(pane_id).expect("pane");
+        let mut parser: Processor<StdSyncHandler> = Processor::new();
+        parser.advance(&mut *pane.term.lock(), bytes);
+        self.state.user_event(KoiEvent::Wakeup(pane_id));
+    }
+
+    /// Everything pane `pane_id` has been sent, leaving the record empty.
+    pub fn take_input(&mut self, pane_id: usize) -> Vec<u8> {
+        self.pty.borrow_mut().input.remove(&pane_id).unwrap_or_default()
+    }
+
+    /// Stop pane `pane_id`'s shell reading its input.
+    pub fn stall(&mut self, pane_id: usize) {
+        self.pty.borrow_mut().stalled.insert(pane_id, Vec::new());
+    }
+
+    /// Let pane `pane_id`'s shell read what it was sent while stalled.
+    pub fn resume(&mut self, pane_id: usize) {
+        let mut log = self.pty.borrow_mut();
+        let Some(held) = log.stalled.remove(&pane_id) else { return };
+        log.backlogs[&pane_id].written(held.len());
+        log.input.entry(pane_id).or_default().extend_from_slice(&held);
+    }
+
+    /// The last size pane `pane_id` was told, as (columns, lines).
+    pub fn pty_size(&self, pane_id: usize) -> Option<(u16, u16)> {
+        self.pty.borrow().resizes.get(&pane_id)?.last().copied()
+    }
+
+    /// The active tab's pane layouts below the tab bar.
+    pub fn layouts(&self) -> Vec<PaneLayout> {
+        let (w, h) = self.state.pane_viewport();
+        self.state.tab_manager.active_layouts(w, h)
+    }
+
+    pub fn active_pane(&self) -> usize {
+        self.state.tab_manager.active_pane_id().expect("an active pane")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alacritty_terminal::grid::Dimensions;
+    use winit::keyboard::KeyCode;
+
+    use super::*;
+
+    fn ch(s: &str) -> Key {
+        Key::Character(s.into())
+    }
+
+    /// The grid size koi gives a pane laid out in `layout`.
+    fn grid_of(h: &Harness, layout: &PaneLayout) -> (u16, u16) {
+        let (cw, chh) = (h.state.renderer.cell_width(), h.state.renderer.cell_height());
+        let (cols, rows) = crate::tabs::pane_grid_size(layout.width, layout.height, cw, chh);
+        (cols as u16, rows as u16)
+    }
+
+    #[test]
+    fn new_tab_and_switching_route_keys_and_draw_the_tab_bar() {
+        let mut h = Harness::new();
+        assert_eq!(h.pty_size(0), Some((114, 42)));
+
+        h.key(PRIMARY, ch("t"));
+        assert_eq!((h.state.tab_manager.count(), h.state.tab_manager.active_index()), (2, 1));
+        // The tab bar took a row from the first tab's pane.
+        assert_eq!(h.pty_size(0), Some((114, 41)));
+        assert_eq!(h.pty_size(1), Some((114, 41)));
+
+        let redraws = h.state.window.redraws.get();
+        h.named(ModifiersState::CONTROL, NamedKey::Tab);
+        assert_eq!(h.state.tab_manager.active_index(), 0);
+        assert!(h.state.tab_animation.is_some());
+        assert!(h.state.window.redraws.get() > redraws);
+
+        h.type_text("ls");
+        assert_eq!(h.take_input(0), b"ls");
+        assert_eq!(h.take_input(1), b"");
+
+        let bar = h.render().row_text(0.0);
+        assert!(bar.starts_with("Tab 1") && bar.contains("Tab 2"), "{bar:?}");
+        assert_eq!(h.state.window.frames.get(), 1);
+    }
+
+    #[test]
+    fn untitled_tabs_and_pane_headers_name_what_runs() {
+        let mut config = Config::default();
+        config.window.pane_animation = false;
+        config.window.pane_headers = true;
+        let mut h = Harness::with_config(config);
+        h.key(PRIMARY, ch("t"));
+        let running = |name: &str| Some(crate::process::Foreground { name: name.into(), cwd: Some("/tmp".into()) });
+        h.state.tab_manager.pane_mut(0).unwrap().foreground = running("htop");
+        let bar = h.render().row_text(0.0);
+        assert!(bar.starts_with("htop") && bar.contains("Tab 2"), "{bar:?}");
+        // A title from the shell wins.
+        h.output(0, b"\x1b]2;build\x07");
+        h.pump();
+        assert!(h.render().row_text(0.0).starts_with("build"));
+
+        // Split panes start a row down, under their headers.
+        h.key(PRIMARY, ch("d"));
+        let cell_height = h.state.renderer.cell_height();
+        assert!(h.layouts().iter().all(|l| l.y == cell_height));
+        assert_eq!(h.pty_size(2), Some((57, 40)));
+        h.state.tab_manager.pane_mut(2).unwrap().foreground = running("vim");
+        let headers = h.render().row_text(cell_height);
+        assert!(headers.contains("vim \u{2014} /tmp"), "{headers:?}");
+    }
+
+    #[test]
+    fn split_and_window_resize_keep_every_pty_in_step_with_its_grid() {
+        let mut h = Harness::new();
+        h.key(PRIMARY, ch("d"));
+        let layouts = h.layouts();
+        assert_eq!(layouts.len(), 2);
+        assert!(layouts[0].x < layouts[1].x, "a vertical split sits side by side");
+        assert_eq!(h.active_pane(), 1, "focus moves to the new pane");
+
+        h.resize(1000, 500);
+        for layout in h.layouts() {
+            let expected = grid_of(&h, &layout);
+            assert_eq!(h.pty_size(layout.pane_id), Some(expected));
+            let term = h.state.tab_manager.pane(layout.pane_id).unwrap().term.lock();
+            assert_eq!((term.columns() as u16, term.screen_lines() as u16), expected);
+        }
+        let frame = h.render();
+        assert_eq!(frame.grids.len(), 2);
+    }
+
+    #[test]
+    fn search_opens_takes_a_query_and_steps_through_matches() {
+        let mut h = Harness::new();
+        h.output(0, b"foo bar\r\nbaz foo\r\n");
+
+        h.key(PRIMARY, ch("f"));
+        assert!(h.state.search.is_some());
+        h.type_text("foo");
+        h.named(ModifiersState::empty(), NamedKey::Enter);
+        let search = h.state.search.as_ref().unwrap();
+        assert_eq!((search.query.as_str(), search.matches.len(), search.current), ("foo", 2, 1));
+        assert!(h.take_input(0).is_empty(), "keys went to the search bar, not the shell");
+
+        // The bar sits on the window's bottom row.
+        let bottom = 600.0 - h.state.renderer.cell_height();
+        assert_eq!(h.render().row_text(bottom), "Search: foo (2/2)");
+
+        h.named(ModifiersState::SHIFT, NamedKey::Enter);
+        assert_eq!(h.state.search.as_ref().unwrap().current, 0);
+        h.named(ModifiersState::empty(), NamedKey::Escape);
+        assert!(h.state.search.is_none());
+        h.type_text("x");
+        assert_eq!(h.take_input(0), b"x");
+    }
+
+    #[test]
+    fn search_follows_new_output_and_closes_with_its_pane() {
+        let mut h = Harness::new();
+        h.key(PRIMARY, ch("d"));
+        h.output(1, b"foo\r\n");
+        h.key(PRIMARY, ch("f"));
+        h.type_text("foo");
+        assert_eq!(h.state.search.as_ref().unwrap().matches.len(), 1);
+
+        // Output elsewhere leaves the search alone; output in its pane is
+        // searched again before the next frame.
+        h.output(0, b"foo foo\r\n");
+        h.output(1, b"more foo\r\n");
+        let bottom = h.layouts()[1].height - h.state.renderer.cell_height();
+        assert_eq!(h.render().row_text(bottom), "Search: foo (1/2)");
+
+        h.state.user_event(KoiEvent::ChildExit(1, 0));
+        assert_eq!(h.layouts().len(), 1);
+        h.render();
+        assert!(h.state.search.is_none(), "its pane is gone");
+    }
+
+    #[test]
+    fn color_queries_are_answered_with_the_panes_own_colors() {
+        let mut h = Harness::new();
+        h.key(PRIMARY, ch("d"));
+        h.output(1, b"\x1b]11;rgb:1e/1e/2e\x07\x1b]11;?\x07");
+        h.output(0, b"\x1b]11;?\x1b\\\x1b]4;196;?\x07");
+        h.pump();
+        assert_eq!(h.take_input(1), b"\x1b]11;rgb:1e1e/1e1e/2e2e\x07");
+        // Latte's background, and xterm's red from the 256-color cube.
+        assert_eq!(h.take_input(0), b"\x1b]11;rgb:efef/f1f1/f5f5\x1b\\\x1b]4;196;rgb:ffff/0000/0000\x07");
+    }
+
+    #[test]
+    fn device_queries_are_answered_on_the_asking_pane() {
+        let mut h = Harness::new();
+        h.key(PRIMARY, ch("d"));
+        h.output(1, b"hi\x1b[6n\x1b[c");
+        h.pump();
+        assert_eq!(h.take_input(1), b"\x1b[1;3R\x1b[?6c");
+        assert!(h.take_input(0).is_empty());
+    }
+
+    #[test]
+    fn clear_drops_the_screen_and_history_and_asks_for_a_repaint() {
+        let mut h = Harness::new();
+        let lines: String = (0..100).map(|i| format!("line {i}\r\n")).collect();
+        h.output(0, lines.as_bytes());
+        let mut term = h.state.tab_manager.pane(0).unwrap().term.lock();
+        term.scroll_display(alacritty_terminal::grid::Scroll::Delta(5));
+        assert!(term.history_size() > 0 && term.grid().display_offset() > 0);
+        drop(term);
+
+        h.key(PRIMARY, ch("k"));
+        let term = h.state.tab_manager.pane(0).unwrap().term.lock();
+        assert_eq!((term.history_size(), term.grid().display_offset()), (0, 0));
+        let cursor = term.grid().cursor.point;
+        assert_eq!((cursor.line.0, cursor.column.0), (0, 0));
+        assert!(term.grid().display_iter().all(|cell| cell.c == ' '));
+        drop(term);
+        // No shell in the foreground here, so a resize nudge, not Ctrl+L.
+        let resizes = h.pty.borrow().resizes[&0].clone();
+        assert_eq!(resizes[resizes.len() - 2..], [(114, 41), (114, 42)]);
+        assert!(h.take_input(0).is_empty());
+    }
+
+    #[test]
+    fn clear_scrollback_under_a_full_screen_app_drops_the_shell_history() {
+        let mut h = Harness::new();
+        let lines: String = (0..100).map(|i| format!("line {i}\r\n")).collect();
+        h.output(0, lines.as_bytes());
+        h.output(0, b"\x1b[?1049hvim");
+
+        h.key(PRIMARY | ModifiersState::SHIFT, ch("K"));
+        let term = h.state.tab_manager.pane(0).unwrap().term.lock();
+        assert!(term.grid().display_iter().any(|cell| cell.c == 'v'));
+        drop(term);
+        h.output(0, b"\x1b[?1049l");
+        assert_eq!(h.state.tab_manager.pane(0).unwrap().term.lock().history_size(), 0);
+        // The limit is back: new output scrolls into history again.
+        h.output(0, lines.as_bytes());
+        assert!(h.state.tab_manager.pane(0).unwrap().term.lock().history_size() > 0);
+    }
+
+    #[test]
+    fn clear_under_a_full_screen_app_drops_the_shell_history() {
+        let mut h = Harness::new();
+        let lines: String = (0..100).map(|i| format!("line {i}\r\n")).collect();
+        h.output(0, lines.as_bytes());
+        h.output(0, b"\x1b[?1049h");
+        h.key(PRIMARY, ch("k"));
+        h.output(0, b"\x1b[?1049l");
+        assert_eq!(h.state.tab_manager.pane(0).unwrap().term.lock().history_size(), 0);
+    }
+
+    #[test]
+    fn scrolling_up_shows_a_scrollbar_whose_thumb_drags_back_to_the_bottom() {
+        use crate::scrollbar;
+        let mut h = Harness::new();
+        let lines: String = (0..500).map(|i| format!("y {i}\r\n")).collect();
+        h.output(0, lines.as_bytes());
+        h.wheel(10.0);
+        let extent = scrollbar::Extent::of(&*h.state.tab_manager.pane(0).unwrap().term.lock());
+        assert!(extent.display_offset > 0);
+
+        let layout = h.layouts()[0].clone();
+        let thumb = scrollbar::thumb((layout.x, layout.y, layout.width, layout.height), extent).unwrap();
+        assert!(thumb.h < layout.height / 10.0, "42 of 500 lines is a short thumb");
+        assert!(h.render().rects.contains(&[thumb.x, thumb.y, thumb.w, thumb.h]));
+
+        h.mouse_to((thumb.x + 2.0) as f64, (thumb.y + 4.0) as f64);
+        h.mouse(MouseButton::Left, ElementState::Pressed);
+        assert!(h.state.scrollbar_drag.is_some());
+        h.mouse_to((thumb.x + 2.0) as f64, 590.0);
+        h.mouse(MouseButton::Left, ElementState::Released);
+        let term = h.state.tab_manager.pane(0).unwrap().term.lock();
+        assert_eq!(term.grid().display_offset(), 0);
+        assert!(term.selection.is_none(), "grabbing the thumb doesn't select");
+        drop(term);
+
+        // Back in history, the bar fades out a while after the last scroll.
+        h.wheel(10.0);
+        let faded = std::time::Instant::now() - std::time::Duration::from_secs(2);
+        h.state.last_scroll = h.state.last_scroll.map(|(id, _)| (id, faded));
+        assert!(!h.render().rects.iter().any(|r| r[2] == scrollbar::WIDTH));
+    }
+
+    #[test]
+    fn shift_page_keys_scroll_the_view_except_on_the_alt_screen() {
+        let mut h = Harness::new();
+        let lines: String = (0..200).map(|i| format!("line {i}\r\n")).collect();
+        h.output(0, lines.as_bytes());
+        let offset = |h: &Harness| h.state.tab_manager.pane(0).unwrap().term.lock().grid().display_offset();
+        let shift = ModifiersState::SHIFT;
+
+        h.named(shift, NamedKey::PageUp);
+        assert_eq!(offset(&h), 42);
+        h.named(shift, NamedKey::Home);
+        let history = h.state.tab_manager.pane(0).unwrap().term.lock().history_size();
+        assert_eq!(offset(&h), history);
+        h.named(shift, NamedKey::PageDown);
+        assert_eq!(offset(&h), history - 42);
+        h.named(shift, NamedKey::End);
+        assert_eq!(offset(&h), 0);
+        assert!(h.take_input(0).is_empty());
+
+        h.output(0, b"\x1b[?1049h");
+        h.named(shift, NamedKey::PageUp);
+        assert_eq!(offset(&h), 0);
+        assert_eq!(h.take_input(0), b"\x1b[5;2~");
+    }
+
+    #[test]
+    fn the_wheel_sends_arrows_on_the_alternate_screen_only_when_asked() {
+        let mut h = Harness::new();
+        h.output(0, b"\x1b[?1049h");
+        h.wheel(1.0);
+        assert!(h.take_input(0).is_empty());
+        // less turns alternate scroll on, so the wheel pages through it.
+        h.output(0, b"\x1b[?1007h");
+        h.wheel(-1.0);
+        assert_eq!(h.take_input(0), b"\x1b[B\x1b[B\x1b[B");
+        // A reset puts it back off.
+        h.key(PRIMARY | ModifiersState::ALT, ch("r"));
+        h.output(0, b"\x1b[?1049h");
+        h.wheel(1.0);
+        assert!(h.take_input(0).is_empty());
+
+        let mut config = Config::default();
+        config.scrolling.force_alternate_scroll = true;
+        let mut h = Harness::with_config(config);
+        h.output(0, b"\x1b[?1049h");
+        h.wheel(1.0);
+        assert_eq!(h.take_input(0), b"\x1b[A\x1b[A\x1b[A");
+    }
+
+    #[test]
+    fn window_state_records_logical_size_zoom_and_theme() {
+        let mut h = Harness::new();
+        h.key(PRIMARY, ch("="));
+        let state = h.state.window_state();
+        assert_eq!((state.width, state.height), (800.0, 600.0));
+        assert_eq!(state.position(), Some((40, 60)));
+        assert_eq!(state.font_size, 15.0);
+        assert_eq!(state.theme.as_deref(), Some("Catppuccin Latte"));
+
+        h.key(PRIMARY | ModifiersState::SHIFT, ch("T"));
+        assert_eq!(h.state.window_state().theme.as_deref(), Some("Catppuccin Mocha"));
+    }
+
+    #[test]
+    fn a_translucent_window_clears_to_a_premultiplied_background() {
+        let mut config = Config::default();
+        config.window.opacity = 0.5;
+        let mut h = Harness::with_config(config);
+        let bg = Theme::latte().bg;
+        assert_eq!(h.render().clear, [bg[0] * 0.5, bg[1] * 0.5, bg[2] * 0.5, 0.5]);
+        assert_eq!(Harness::new().render().clear, [bg[0], bg[1], bg[2], 1.0]);
+    }
+
+    #[test]
+    fn the_theme_follows_the_system_appearance_until_picked_by_hand() {
+        let mut config = Config::default();
+        config.window.follow_system_appearance = true;
+        let mut h = Harness::with_config(config);
+        let theme = |h: &Harness| h.state.window_state().theme;
+
+        h.state.window.appearance.set(Some(Appearance::Dark));
+        h.event(WindowEvent::ThemeChanged(Appearance::Dark));
+        assert_eq!(theme(&h).as_deref(), Some("Catppuccin Mocha"));
+        // Switched while koi was in the background: caught on focus.
+        h.state.window.appearance.set(Some(Appearance::Light));
+        h.event(WindowEvent::Focused(true));
+        assert_eq!(theme(&h).as_deref(), Some("Catppuccin Latte"));
+
+        h.key(PRIMARY | ModifiersState::SHIFT, ch("T"));
+        assert_eq!(theme(&h).as_deref(), Some("Catppuccin Mocha"));
+        h.state.window.appearance.set(Some(Appearance::Light));
+        h.event(WindowEvent::ThemeChanged(Appearance::Light));
+        assert_eq!(theme(&h).as_deref(), Some("Catppuccin Mocha"), "a theme picked by hand stays");
+    }
+
+    #[test]
+    fn dragging_a_divider_moves_it_and_resizes_both_panes() {
+        let mut h = Harness::new();
+        h.key(PRIMARY, ch("d"));
+        let (w, vh) = h.state.pane_viewport();
+        let divider = h.state.tab_manager.active_dividers(w, vh)[0].position;
+
+        h.mouse_to(divider as f64, 300.0);
+        h.mouse(MouseButton::Left, ElementState::Pressed);
+        assert!(h.state.divider_drag.is_some());
+        h.mouse_to(600.0, 300.0);
+        h.mouse(MouseButton::Left, ElementState::Released);
+        assert!(h.state.divider_drag.is_none());
+
+        let layouts = h.layouts();
+        let moved = h.state.tab_manager.active_dividers(w, vh)[0].position;
+        assert!((moved - 600.0).abs() < 8.0, "divider at {moved}");
+        assert!(layouts[0].width > layouts[1].width);
+        for layout in &layouts {
+            assert_eq!(h.pty_size(layout.pane_id), Some(grid_of(&h, layout)));
+        }
+    }
+
+    #[test]
+    fn hovering_a_divider_highlights_it_with_a_resize_cursor() {
+        let mut h = Harness::new();
+        h.key(PRIMARY, ch("d"));
+        let (w, vh) = h.state.pane_viewport();
+        let divider = h.state.tab_manager.active_dividers(w, vh)[0].position;
+        let highlight = [divider - 2.0, 0.0, 4.0, vh];
+
+        h.mouse_to(divider as f64 + 3.0, 300.0);
+        assert_eq!(h.state.window.cursor.get(), CursorIcon::ColResize);
+        assert!(h.render().rects.contains(&highlight));
+
+        // Still held while dragging past the grab distance.
+        h.mouse(MouseButton::Left, ElementState::Pressed);
+        h.mouse_to(divider as f64 + 40.0, 300.0);
+        assert_eq!(h.state.window.cursor.get(), CursorIcon::ColResize);
+        h.mouse(MouseButton::Left, ElementState::Released);
+        assert_eq!(h.state.window.cursor.get(), CursorIcon::ColResize, "the divider followed the pointer");
+
+        h.mouse_to(100.0, 300.0);
+        assert_eq!(h.state.window.cursor.get(), CursorIcon::Default);
+        assert!(!h.render().rects.iter().any(|r| r[2] == 4.0 && r[3] == vh));
+
+        // Closing a pane takes its divider's highlight with it.
+        let divider = h.state.tab_manager.active_dividers(w, vh)[0].position;
+        h.mouse_to(divider as f64, 300.0);
+        assert!(h.state.divider_hover.is_some());
+        h.key(PRIMARY, ch("w"));
+        assert_eq!(h.state.divider_hover, None);
+        assert_eq!(h.state.window.cursor.get(), CursorIcon::Default);
+    }
+
+    #[test]
+    fn a_long_paste_waits_on_the_shell_and_keys_typed_meanwhile_follow_it() {
+        let mut h = Harness::new();
+        h.stall(0);
+        let text = "y".repeat(8 * crate::paste::CHUNK);
+        h.state.paste(text.clone(), false);
+        h.type_text("x");
+        h.resume(0);
+        let sent = h.take_input(0);
+        assert!(!sent.is_empty() && sent.len() < text.len(), "held to what the shell had taken");
+
+        while h.state.feed_pastes() {}
+        let mut all = sent;
+        all.extend(h.take_input(0));
+        assert_eq!(all, format!("{text}x").into_bytes());
+    }
+
+    #[test]
+    fn multi_line_paste_waits_for_confirmation() {
+        let mut h = Harness::new();
+        let script = "echo a\necho b\necho c\n";
+        h.state.paste(script.into(), true);
+        assert!(h.take_input(0).is_empty(), "nothing reaches the shell yet");
+        let canvas = h.render();
+        let top = 600.0 - 4.0 * 14.0;
+        assert!(canvas.row_text(top).starts_with("Paste 3 lines? Enter to paste"));
+        assert_eq!(canvas.row_text(top + 14.0), "echo a");
+        // Keys go to the panel, not the shell.
+        h.type_text("x");
+        h.named(ModifiersState::empty(), NamedKey::Enter);
+        assert_eq!(h.take_input(0), b"echo a\recho b\recho c\r");
+        assert!(h.state.pending_paste.is_none());
+
+        h.state.paste(script.into(), true);
+        h.type_text("e");
+        assert_eq!(h.take_input(0), b"echo a echo b echo c");
+        h.state.paste(script.into(), true);
+        h.named(ModifiersState::empty(), NamedKey::Escape);
+        assert!(h.take_input(0).is_empty());
+
+        // Cmd+Shift+V, one-line text and full-screen apps don't ask.
+        h.state.paste("ls\n".into(), false);
+        h.state.paste("ls".into(), true);
+        h.output(0, b"\x1b[?1049h");
+        h.state.paste(":wq\n".into(), true);
+        assert_eq!(h.take_input(0), b"ls\rls:wq\r");
+        assert!(h.state.pending_paste.is_none());
+    }
+
+    #[test]
+    fn right_drags_and_plain_motion_are_reported_to_mouse_mode_apps() {
+        let mut h = Harness::new();
+        // Button-event tracking, legacy encoding (as tmux without SGR).
+        h.output(0, b"\x1b[?1002h");
+        h.mouse_to(3.0, 3.0);
+        h.mouse(MouseButton::Right, ElementState::Pressed);
+        assert_eq!(h.take_input(0), b"\x1b[M\x22!!");
+        h.mouse_to(5.0, 3.0);
+        assert!(h.take_input(0).is_empty(), "same cell");
+        h.mouse_to(10.0, 3.0);
+        assert_eq!(h.take_input(0), b"\x1b[M\x42\x22!");
+        h.mouse(MouseButton::Right, ElementState::Released);
+        assert_eq!(h.take_input(0), b"\x1b[M\x23\x22!");
+        h.mouse_to(20.0, 3.0);
+        assert!(h.take_input(0).is_empty(), "1002 reports drags only");
+
+        // Any-event tracking reports motion with no button held.
+        h.output(0, b"\x1b[?1003h\x1b[?1006h");
+        h.mouse_to(30.0, 20.0);
+        assert_eq!(h.take_input(0), b"\x1b[<35;5;2M");
+    }
+
+    #[test]
+    fn focus_reports_follow_the_window_and_the_active_pane() {
+        let mut h = Harness::new();
+        h.output(0, b"\x1b[?1004h");
+        h.render();
+        assert_eq!(h.take_input(0), b"\x1b[I");
+
+        h.event(WindowEvent::Focused(false));
+        assert_eq!(h.take_input(0), b"\x1b[O");
+        assert_eq!(h.render().cursors, [Some(CursorShape::HollowBlock)]);
+        h.event(WindowEvent::Focused(true));
+        assert_eq!(h.take_input(0), b"\x1b[I");
+        assert_eq!(h.render().cursors, [Some(CursorShape::Block)]);
+
+        h.key(PRIMARY, ch("d"));
+        h.render();
+        assert_eq!(h.take_input(0), b"\x1b[O");
+        h.key(PRIMARY, ch("["));
+        assert_eq!(h.take_input(0), b"\x1b[I");
+    }
+
+    #[test]
+    fn in_the_background_the_loop_wakes_only_for_housekeeping() {
+        let mut h = Harness::new();
+        h.event(WindowEvent::Focused(false));
+        let now = std::time::Instant::now();
+        h.state.next_pty_check = now + std::time::Duration::from_secs(1);
+        h.state.next_command_poll = now + std::time::Duration::from_secs(1);
+        assert!(h.state.background_wake() <= h.state.next_pty_check);
+        let flash_ends = now + std::time::Duration::from_millis(100);
+        h.state.bell_flashes.insert(0, flash_ends);
+        assert_eq!(h.state.background_wake(), flash_ends);
+    }
+
+    #[test]
+    fn output_faster_than_the_display_draws_once_a_frame() {
+        let mut h = Harness::new();
+        h.render();
+        let redraws = h.state.window.redraws.get();
+        for _ in 0..100 {
+            h.output(0, b"y\r\n");
+        }
+        // Just drawn, so the next frame waits for the display.
+        assert_eq!(h.state.window.redraws.get(), redraws);
+        let due = h.state.output_redraw.expect("a frame is owed");
+        assert!(due <= std::time::Instant::now() + h.state.frame_pacer.interval());
+        h.render();
+        assert!(h.state.output_redraw.is_none());
+    }
+
+    #[test]
+    fn middle_click_pastes_bracketed_only_when_the_app_asks() {
+        let mut h = Harness::new();
+        h.state.primary_selection = Some("echo hi\n".into());
+
+        h.mouse_to(100.0, 100.0);
+        h.mouse(MouseButton::Middle, ElementState::Pressed);
+        // A line break waits for confirmation, as with Cmd+V.
+        assert!(h.take_input(0).is_empty());
+        h.named(ModifiersState::empty(), NamedKey::Enter);
+        // Outside bracketed paste, newlines go as the CR Enter sends.
+        assert_eq!(h.take_input(0), b"echo hi\r");
+
+        h.output(0, b"\x1b[?2004h");
+        h.state.primary_selection = Some("rm -rf \x1b[201~ /\n".into());
+        h.mouse(MouseButton::Middle, ElementState::Pressed);
+        h.named(ModifiersState::empty(), NamedKey::Enter);
+        // An end marker inside the text can't close the bracket early.
+        assert_eq!(h.take_input(0), b"\x1b[200~rm -rf  /\n\x1b[201~");
+    }
+
+    #[test]
+    fn right_click_menu_copies_the_selection_and_splits_from_the_keyboard() {
+        let mut h = Harness::new();
+        h.output(0, b"hello world");
+        h.mouse_to(3.0, 5.0);
+        h.mouse(MouseButton::Left, ElementState::Pressed);
+        h.mouse_to(36.0, 5.0);
+        h.mouse(MouseButton::Left, ElementState::Released);
+        assert!(h.state.tab_manager.active_pane().unwrap().term.lock().selection.is_some());
+
+        h.mouse_to(100.0, 100.0);
+        h.mouse(MouseButton::Right, ElementState::Pressed);
+        h.mouse(MouseButton::Right, ElementState::Released);
+        let menu = h.state.context_menu.as_ref().expect("right-click opens the menu");
+        let copy_y = menu.row_y(0, h.state.renderer.cell_height());
+        assert!(h.render().row_text(copy_y).starts_with("Copy"));
+
+        h.mouse_to(120.0, copy_y as f64 + 2.0);
+        assert_eq!(h.state.context_menu.as_ref().unwrap().hovered, Some(0));
+        h.mouse(MouseButton::Left, ElementState::Pressed);
+        h.mouse(MouseButton::Left, ElementState::Released);
+        assert!(h.state.context_menu.is_none());
+        let term = h.state.tab_manager.active_pane().unwrap().term.lock();
+        assert!(term.selection.is_none(), "Copy took the selection");
+        drop(term);
+        assert!(h.take_input(0).is_empty(), "right-click no longer pastes");
+
+        h.mouse(MouseButton::Right, ElementState::Pressed);
+        h.named(ModifiersState::empty(), NamedKey::ArrowDown);
+        h.named(ModifiersState::empty(), NamedKey::ArrowDown);
+        h.named(ModifiersState::empty(), NamedKey::ArrowDown);
+        h.named(ModifiersState::empty(), NamedKey::Enter);
+        assert_eq!(h.layouts().len(), 2, "the third entry splits the pane");
+
+        h.mouse(MouseButton::Right, ElementState::Pressed);
+        h.named(ModifiersState::empty(), NamedKey::Escape);
+        assert!(h.state.context_menu.is_none());
+        h.type_text("x");
+        assert_eq!(h.take_input(1), b"x");
+    }
+
+    #[test]
+    fn dragging_a_tab_reorders_the_tab_bar() {
+        let mut h = Harness::new();
+        h.key(PRIMARY, ch("t"));
+        h.key(PRIMARY, ch("t"));
+        let ids = |h: &Harness| h.state.tab_manager.iter().map(|t| t.id).collect::<Vec<_>>();
+        assert_eq!(ids(&h), [0, 1, 2]);
+
+        // Drag the third tab to the front; it stays the selected tab.
+        h.mouse_to(700.0, 5.0);
+        h.mouse(MouseButton::Left, ElementState::Pressed);
+        h.mouse_to(697.0, 5.0);
+        assert_eq!(ids(&h), [0, 1, 2], "moved before passing the threshold");
+        h.mouse_to(20.0, 5.0);
+        assert_eq!(ids(&h), [2, 0, 1]);
+        assert_eq!(h.state.tab_manager.active_index(), 0);
+        // Outlined while dragged.
+        let tab_w = 800.0 / 3.0;
+        assert!(h.render().rects.contains(&[0.0, 0.0, tab_w, 1.0]));
+        h.mouse(MouseButton::Left, ElementState::Released);
+        assert_eq!(ids(&h), [2, 0, 1]);
+        assert!(!h.render().rects.contains(&[0.0, 0.0, tab_w, 1.0]));
+
+        h.key(PRIMARY, ch("t"));
+        h.key_at(PRIMARY, ch("1"), PhysicalKey::Code(KeyCode::Digit1));
+        assert_eq!(h.state.tab_manager.active_tab().map(|t| t.id), Some(2));
+
+        // Letting go below the tab bar puts the tab back.
+        h.mouse_to(10.0, 5.0);
+        h.mouse(MouseButton::Left, ElementState::Pressed);
+        h.mouse_to(500.0, 5.0);
+        assert_eq!(ids(&h), [0, 1, 2, 3]);
+        h.mouse_to(500.0, 300.0);
+        h.mouse(MouseButton::Left, ElementState::Released);
+        assert_eq!(ids(&h), [2, 0, 1, 3]);
+        assert_eq!(h.state.tab_manager.active_tab().map(|t| t.id), Some(2));
+    }
+
+    #[test]
+    fn the_tab_bar_switches_and_closes_tabs_with_the_mouse() {
+        let mut h = Harness::new();
+        h.key(PRIMARY, ch("t"));
+        h.key(PRIMARY, ch("t"));
+        let ids = |h: &Harness| h.state.tab_manager.iter().map(|t| t.id).collect::<Vec<_>>();
+        let click = |h: &mut Harness, button, x| {
+            h.mouse_to(x, 5.0);
+            h.mouse(button, ElementState::Pressed);
+            h.mouse(button, ElementState::Released);
+        };
+
+        click(&mut h, MouseButton::Left, 300.0);
+        assert_eq!(h.state.tab_manager.active_index(), 1);
+        assert!(h.render().row_text(0.0).contains('\u{d7}'));
+
+        // Each 266px tab's close button sits at its right end.
+        click(&mut h, MouseButton::Left, 260.0);
+        assert_eq!(ids(&h), [1, 2]);
+        assert_eq!(h.state.tab_manager.active_tab().map(|t| t.id), Some(1));
+
+        // Middle-click anywhere on a tab closes it; the last tab gives the
+        // bar's row back.
+        click(&mut h, MouseButton::Middle, 100.0);
+        assert_eq!(ids(&h), [2]);
+        assert_eq!(h.pty_size(2), Some((114, 42)));
+        assert_eq!(h.take_input(2), b"");
+    }
+
+    #[test]
+    fn natural_text_editing_sends_readline_keys_and_moves_tab_switching_to_shift() {
+        let mut config = Config::default();
+        config.keyboard.natural_text_editing = true;
+        let mut h = Harness::with_config(config);
+        h.key(PRIMARY, ch("t"));
+        assert_eq!(h.state.tab_manager.active_index(), 1);
+
+        h.named(ModifiersState::ALT, NamedKey::ArrowLeft);
+        h.named(ModifiersState::ALT, NamedKey::Backspace);
+        h.named(PRIMARY, NamedKey::ArrowRight);
+        h.named(PRIMARY, NamedKey::Backspace);
+        assert_eq!(h.take_input(1), b"\x1bb\x1b\x7f\x05\x15");
+        assert_eq!(h.state.tab_manager.active_index(), 1);
+
+        h.named(PRIMARY | ModifiersState::SHIFT, NamedKey::ArrowLeft);
+        assert_eq!(h.state.tab_manager.active_index(), 0);
+        // Option+Shift+Left still reaches the shell in its CSI form.
+        h.named(ModifiersState::ALT | ModifiersState::SHIFT, NamedKey::ArrowLeft);
+        assert_eq!(h.take_input(0), b"\x1b[1;4D");
+    }
+
+    #[test]
+    fn primary_hover_underlines_an_osc8_link_and_click_opens_its_target() {
+        let mut h = Harness::new();
+        h.output(0, b"\x1b]8;;https://github.com/o/r/pull/7\x1b\\PR #7\x1b]8;;\x1b\\ merged");
+        h.mouse_to(10.0, 5.0);
+        assert!(h.state.hovered_link.is_none(), "no underline without the modifier");
+
+        h.hold(PRIMARY);
+        let (pane, link) = h.state.hovered_link.clone().expect("holding the modifier finds the link");
+        assert_eq!((pane, link.uri.as_str()), (0, "https://github.com/o/r/pull/7"));
+        assert_eq!(h.render().links, [Some((link.start, link.end))]);
+
+        h.mouse(MouseButton::Left, ElementState::Pressed);
+        h.mouse(MouseButton::Left, ElementState::Released);
+        assert_eq!(*h.state.window.opened_urls.borrow(), ["https://github.com/o/r/pull/7"]);
+        assert!(h.state.tab_manager.active_pane().unwrap().term.lock().selection.is_none());
+
+        h.hold(ModifiersState::empty());
+        assert!(h.state.hovered_link.is_none());
+        assert_eq!(h.render().links, [None]);
+    }
+
+    #[test]
+    fn titles_from_the_active_tab_reach_the_window() {
+        let mut h = Harness::new();
+        h.output(0, b"\x1b]2;vim notes.md\x07");
+        h.pump();
+        assert_eq!(h.state.window.title.borrow().as_deref(), Some("vim notes.md"));
+    }
+
+    #[test]
+    fn a_renamed_tab_keeps_its_name_through_shell_titles() {
+        let mut config = Config::default();
+        config.tabs.title_template = "{index}: {title}".into();
+        let mut h = Harness::with_config(config);
+        h.key(PRIMARY, ch("t"));
+        assert!(h.render().row_text(0.0).starts_with("1: Tab 1"));
+
+        h.key(PRIMARY | ModifiersState::SHIFT, ch("I"));
+        h.type_text("buil");
+        h.named(ModifiersState::empty(), NamedKey::Backspace);
+        h.type_text("ld");
+        assert!(h.render().row_text(600.0 - 14.0).starts_with("Rename tab: build"));
+        h.named(ModifiersState::empty(), NamedKey::Enter);
+        assert_eq!(h.take_input(1), b"", "typing went to the rename bar");
+
+        h.output(1, b"\x1b]2;me@remote: ~\x07");
+        h.pump();
+        let bar = h.render().row_text(0.0);
+        assert!(bar.contains("2: build") && !bar.contains("remote"), "{bar:?}");
+        // The window still follows the shell.
+        assert_eq!(h.state.window.title.borrow().as_deref(), Some("me@remote: ~"));
+
+        // Renaming to nothing goes back to the shell's title.
+        h.key(PRIMARY | ModifiersState::SHIFT, ch("I"));
+        for _ in 0.."build".len() {
+            h.named(ModifiersState::empty(), NamedKey::Backspace);
+        }
+        h.named(ModifiersState::empty(), NamedKey::Enter);
+        assert!(h.render().row_text(0.0).contains("2: me@remote"));
+    }
+
+    #[test]
+    fn background_tabs_show_output_and_bells() {
+        let mut h = Harness::new();
+        h.key(PRIMARY, ch("t"));
+        let red = h.state.renderer.theme().colors[1];
+        let red = [red[0], red[1], red[2], 1.0];
+        let dot = (14.0_f32 * 0.3).round();
+        let first_dot = [400.0 - 4.0 - 7.0 - 4.0 - dot, ((14.0 - dot) / 2.0_f32).round(), dot, dot];
+
+        h.output(1, b"in view");
+        assert!(!h.render().rects.contains(&first_dot));
+        h.output(0, b"make: done");
+        assert!(h.render().rects.contains(&first_dot));
+
+        h.output(0, b"\x07");
+        h.pump();
+        let canvas = h.render();
+        let label: Vec<_> = canvas.glyphs.iter().filter(|g| g.y == 0.0 && g.x < 100.0).collect();
+        assert!(!label.is_empty() && label.iter().all(|g| g.fg == red));
+
+        // Looking at the tab clears both.
+        h.named(ModifiersState::CONTROL, NamedKey::Tab);
+        let canvas = h.render();
+        assert!(canvas.glyphs.iter().filter(|g| g.y == 0.0).all(|g| g.fg != red));
+        assert!(!canvas.rects.contains(&first_dot));
+    }
+
+    #[test]
+    fn panes_swap_and_rotate_without_restarting() {
+        let mut h = Harness::new();
+        let shift = PRIMARY | ModifiersState::SHIFT;
+        h.key(PRIMARY, ch("d"));
+        let before = h.layouts();
+
+        h.named(shift, NamedKey::ArrowLeft);
+        let swapped = h.layouts();
+        assert_eq!((swapped[0].pane_id, swapped[1].pane_id), (1, 0));
+        assert_eq!(h.state.tab_manager.active_pane_id(), Some(1));
+        assert_eq!(h.pty_size(1), Some(grid_of(&h, &swapped[0])));
+        // Nothing further left.
+        h.named(shift, NamedKey::ArrowLeft);
+        assert_eq!(h.layouts()[0].pane_id, 1);
+
+        h.key(shift, ch("O"));
+        let stacked = h.layouts();
+        assert_eq!((stacked[0].x, stacked[1].x), (0.0, 0.0));
+        assert!(stacked[1].y > 0.0);
+        assert_eq!(stacked[0].width, before[0].width + before[1].width);
+        for layout in &stacked {
+            assert_eq!(h.pty_size(layout.pane_id), Some(grid_of(&h, layout)));
+        }
+        assert_eq!(h.state.tab_manager.active_tab().unwrap().panes.len(), 2);
+    }
+
+    #[test]
+    fn a_restarted_shell_gets_a_pane_id_of_its_own() {
+        let mut h = Harness::new();
+        h.key(PRIMARY, ch("d"));
+        let before = h.layouts();
+        h.state.tab_manager.pane_mut(1).unwrap().io_crashed = true;
+        h.named(ModifiersState::empty(), NamedKey::Enter);
+
+        let after = h.layouts();
+        assert_eq!(after.iter().map(|l| l.pane_id).collect::<Vec<_>>(), [0, 2]);
+        assert_eq!((after[1].x, after[1].width), (before[1].x, before[1].width));
+        assert_eq!(h.state.tab_manager.active_pane_id(), Some(2));
+        assert!(h.state.tab_manager.pane(1).is_none());
+        assert!(!h.state.tab_manager.pane(2).unwrap().io_crashed);
+        assert_eq!(h.pty_size(2), Some(grid_of(&h, &after[1])));
+
+        // The old shell's exit, arriving late, closes nothing.
+        h.state.user_event(KoiEvent::ChildExit(1, 0));
+        assert_eq!(h.state.tab_manager.active_tab().unwrap().panes.len(), 2);
+        h.type_text("x");
+        assert_eq!(h.take_input(2), b"x");
+    }
+
+    #[test]
+    fn a_restarted_program_pane_runs_its_program_again() {
+        let mut h = Harness::running(crate::tabs::FirstPane::Command("htop".into(), vec!["-d".into(), "5".into()]));
+        let htop = Some(tty::Shell::new("htop".into(), vec!["-d".into(), "5".into()]));
+        assert_eq!(h.pty.borrow().programs[&0], htop);
+        // Full-screen modes are htop's own, not left behind by a crash.
+        h.output(0, b"\x1b[?1049h\x1b[?1000h");
+        assert_eq!(h.state.tab_manager.pane(0).unwrap().stuck_modes(), None);
+
+        h.state.tab_manager.pane_mut(0).unwrap().io_crashed = true;
+        h.named(ModifiersState::empty(), NamedKey::Enter);
+        assert_eq!(h.pty.borrow().programs[&1], htop);
+        // A split beside it gets the login shell.
+        h.key(PRIMARY, ch("d"));
+        assert_eq!(h.pty.borrow().programs[&2], None);
+    }
+
+    #[test]
+    fn splitting_stops_at_the_minimum_pane_size() {
+        let mut h = Harness::new();
+        for _ in 0..8 {
+            h.key(PRIMARY, ch("d"));
+        }
+        let layouts = h.layouts();
+        // 800px halves down to 50px, which can't hold two 4-column panes.
+        assert_eq!(layouts.len(), 5);
+        assert_eq!(h.state.tab_manager.active_tab().unwrap().panes.len(), 5);
+        assert!(layouts.iter().all(|l| l.width >= 28.0));
+        #[cfg(all(unix, not(target_os = "macos")))]
+        assert!(h.state.window.attention_requests.get() > 0, "a refused split beeps");
+    }
+
+    #[test]
+    fn broadcast_sends_typing_and_pastes_to_every_pane_in_the_tab() {
+        let mut h = Harness::new();
+        let shift = PRIMARY | ModifiersState::SHIFT;
+        h.key(PRIMARY, ch("d"));
+        h.key(shift, ch("D"));
+        h.key(PRIMARY, ch("["));
+        h.key(PRIMARY, ch("["));
+        h.key(shift, ch("D"));
+        h.key(shift, ch("B"));
+        assert!(h.render().row_text(0.0).contains("BROADCAST"));
+
+        h.type_text("uptime");
+        h.named(ModifiersState::empty(), NamedKey::Enter);
+        for pane in 0..4 {
+            assert_eq!(h.take_input(pane), b"uptime\r", "pane {pane}");
+        }
+
+        // Each pane's paste is bracketed only if its app asked.
+        h.output(2, b"\x1b[?2004h");
+        h.state.paste("ls".into(), true);
+        assert_eq!(h.take_input(2), b"\x1b[200~ls\x1b[201~");
+        for pane in [0, 1, 3] {
+            assert_eq!(h.take_input(pane), b"ls", "pane {pane}");
+        }
+        // Likewise each pane's cursor keys follow its own DECCKM.
+        h.output(1, b"\x1b[?1h");
+        h.named(ModifiersState::empty(), NamedKey::ArrowUp);
+        assert_eq!(h.take_input(1), b"\x1bOA");
+        for pane in [0, 2, 3] {
+            assert_eq!(h.take_input(pane), b"\x1b[A", "pane {pane}");
+        }
+
+        // Another tab doesn't broadcast, and turning it off stops it.
+        h.key(PRIMARY, ch("t"));
+        h.type_text("a");
+        assert_eq!(h.take_input(4), b"a");
+        assert!(h.take_input(0).is_empty());
+        h.key_at(PRIMARY, ch("1"), PhysicalKey::Code(KeyCode::Digit1));
+        h.key(shift, ch("B"));
+        h.type_text("b");
+        let typed: Vec<_> = (0..4).map(|pane| h.take_input(pane)).collect();
+        assert_eq!(typed.iter().filter(|t| t.as_slice() == b"b").count(), 1);
+        let header = h.state.renderer.cell_height();
+        assert!(!h.render().row_text(header).contains("BROADCAST"));
+    }
+
+    #[test]
+    fn panes_keep_the_configured_scrollback() {
+        let log: String = (0..500).map(|i| format!("line {i}\r\n")).collect();
+        let history = |lines: usize| {
+            let mut config = Config::default();
+            config.scrolling.lines = lines;
+            let mut h = Harness::with_config(config);
+            h.output(0, log.as_bytes());
+            // Splits get it too, and resizing keeps it.
+            h.key(PRIMARY, ch("d"));
+            h.output(1, log.as_bytes());
+            let term = h.state.tab_manager.pane(1).unwrap().term.lock();
+            term.grid().history_size()
+        };
+        assert_eq!(history(100), 100);
+        // All 500 lines and the empty one after, less the 42 on screen.
+        assert_eq!(history(2000), 501 - 600 / 14);
+        assert_eq!(history(0), 0);
+    }
+
+    #[test]
+    fn equalizing_undoes_a_dragged_divider() {
+        let mut h = Harness::new();
+        h.key(PRIMARY, ch("d"));
+        let even = h.layouts();
+        let (w, vh) = h.state.pane_viewport();
+        let divider = h.state.tab_manager.active_dividers(w, vh)[0].position;
+        h.mouse_to(divider as f64, 300.0);
+        h.mouse(MouseButton::Left, ElementState::Pressed);
+        h.mouse_to(650.0, 300.0);
+        h.mouse(MouseButton::Left, ElementState::Released);
+        assert!(h.layouts()[0].width > even[0].width);
+
+        h.key(PRIMARY | ModifiersState::ALT, ch("="));
+        let layouts = h.layouts();
+        assert_eq!(layouts[0].width, even[0].width);
+        assert_eq!(h.pty_size(0), Some(grid_of(&h, &layouts[0])));
+    }
+
+    #[test]
+    fn dimming_fades_every_pane_but_the_focused_one() {
+        let mut h = Harness::new();
+        let covered = |h: &mut Harness, pane: usize| {
+            let l = h.layouts().into_iter().find(|l| l.pane_id == pane).unwrap();
+            // Washed over the text, not painted beneath it.
+            h.render().blended.contains(&[l.x, l.y, l.width, l.height])
+        };
+        h.key(PRIMARY | ModifiersState::SHIFT, ch("U"));
+        assert!(!covered(&mut h, 0), "a lone pane isn't dimmed");
+
+        h.key(PRIMARY, ch("d"));
+        h.key(PRIMARY | ModifiersState::SHIFT, ch("D"));
+        assert!(covered(&mut h, 0));
+        assert!(covered(&mut h, 1));
+        assert!(!covered(&mut h, 2));
+
+        // Not the pane under a divider drag.
+        let (w, vh) = h.state.pane_viewport();
+        let divider = h.state.tab_manager.active_dividers(w, vh)[0].position;
+        h.mouse_to(divider as f64, 100.0);
+        h.mouse(MouseButton::Left, ElementState::Pressed);
+        h.mouse_to(divider as f64 - 20.0, 100.0);
+        let hovered = h.state.pane_under_cursor().unwrap();
+        assert_ne!(hovered, 2);
+        assert!(!covered(&mut h, hovered));
+        assert!(covered(&mut h, 1 - hovered));
+        h.mouse(MouseButton::Left, ElementState::Released);
+
+        h.key(PRIMARY | ModifiersState::SHIFT, ch("U"));
+        assert!(!covered(&mut h, 0));
+    }
+
+    #[test]
+    fn a_bell_flashes_only_the_pane_that_rang() {
+        let mut h = Harness::new();
+        h.key(PRIMARY, ch("d"));
+        let tint = |h: &mut Harness, pane: usize| {
+            let l = h.layouts().into_iter().find(|l| l.pane_id == pane).unwrap();
+            h.render().blended.contains(&[l.x, l.y, l.width, l.height])
+        };
+
+        h.output(0, b"\x07");
+        h.pump();
+        assert!(tint(&mut h, 0));
+        assert!(!tint(&mut h, 1));
+        // Pane 1 has focus, so pane 0's bell isn't heard.
+        #[cfg(all(unix, not(target_os = "macos")))]
+        assert_eq!(h.state.window.attention_requests.get(), 0);
+
+        // In a background tab it marks the tab instead.
+        h.key(PRIMARY, ch("t"));
+        h.output(1, b"\x07");
+        h.pump();
+        assert!(h.state.tab_manager.iter().next().unwrap().activity.has_bell());
+        assert!(!h.state.bell_flashes.contains_key(&1));
+    }
+
+    #[test]
+    fn prompt_keys_jump_between_commands_and_copy_the_last_output() {
+        let mut h = Harness::new();
+        let session: String = (0..5).map(|i| format!("\x1b]133;A\x07$ cmd{i}\r\n{}", "out\r\n".repeat(20))).collect();
+        let mut tagged = Vec::new();
+        let mut tagger = crate::prompts::PromptTagger::default();
+        tagger.feed(format!("{session}\x1b]133;A\x07$ ").as_bytes(), &mut tagged);
+        h.output(0, &tagged);
+        let offset = |h: &Harness| h.state.tab_manager.pane(0).unwrap().term.lock().grid().display_offset();
+
+        // 42 rows: cmd3's prompt is just above the screen, cmd2's 21 lines up.
+        h.named(PRIMARY, NamedKey::ArrowUp);
+        assert_eq!(offset(&h), 1);
+        h.named(PRIMARY, NamedKey::ArrowUp);
+        assert_eq!(offset(&h), 22);
+        h.named(PRIMARY, NamedKey::ArrowDown);
+        assert_eq!(offset(&h), 1);
+        h.named(PRIMARY, NamedKey::ArrowDown);
+        assert_eq!(offset(&h), 0);
+
+        h.key(PRIMARY | ModifiersState::SHIFT, ch("A"));
+        let mut term = h.state.tab_manager.pane(0).unwrap().term.lock();
+        let text = crate::selection::selection_text(&mut term).unwrap();
+        assert_eq!(text.trim_end(), ["out"; 20].join("\n"));
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    #[test]
+    fn prompt_keys_reach_the_app_when_there_are_no_prompts() {
+        let mut h = Harness::new();
+        h.output(0, b"no integration\r\n");
+        h.named(PRIMARY, NamedKey::ArrowUp);
+        assert_eq!(h.take_input(0), b"\x1b[1;5A");
+
+        let mut tagged = Vec::new();
+        crate::prompts::PromptTagger::default().feed(b"\x1b]133;A\x07$ ", &mut tagged);
+        h.output(0, &tagged);
+        h.named(PRIMARY, NamedKey::ArrowDown);
+        assert!(h.take_input(0).is_empty());
+        // A full-screen app hides the prompts.
+        h.output(0, b"\x1b[?1049h");
+        h.named(PRIMARY, NamedKey::ArrowDown);
+        assert_eq!(h.take_input(0), b"\x1b[1;5B");
+    }
+
+    #[test]
+    fn double_clicks_split_words_at_the_configured_chars_in_every_pane() {
+        let mut config = Config::default();
+        config.selection.semantic_escape_chars = " /".into();
+        let mut h = Harness::with_config(config);
+        h.key(PRIMARY, ch("d"));
+        let clicks = |h: &mut Harness, count| {
+            h.state.last_click_time -= std::time::Duration::from_secs(1);
+            h.mouse_to(14.0 * 7.0 + 3.0, 5.0);
+            for _ in 0..count {
+                h.mouse(MouseButton::Left, ElementState::Pressed);
+                h.mouse(MouseButton::Left, ElementState::Released);
+            }
+            let mut term = h.state.tab_manager.pane(0).unwrap().term.lock();
+            crate::selection::selection_text(&mut term)
+        };
+        h.output(0, b"$ /usr/local/bin/python3");
+        assert_eq!(clicks(&mut h, 2).as_deref(), Some("bin"));
+        h.state.tab_manager.set_semantic_escape_chars(" ");
+        assert_eq!(clicks(&mut h, 2).as_deref(), Some("/usr/local/bin/python3"));
+        assert_eq!(h.state.tab_manager.pane(1).unwrap().term.lock().semantic_escape_chars(), " ");
+    }
+
+    #[test]
+    fn cmd_n_asks_for_a_window_of_its_own() {
+        let mut h = Harness::new();
+        h.key(PRIMARY, ch("n"));
+        assert!(h.state.new_window);
+        assert_eq!(h.state.tab_manager.count(), 1);
+        assert!(h.take_input(0).is_empty());
+    }
+
+    #[test]
+    fn shells_are_told_their_window_tab_and_pane() {
+        let mut h = Harness::new();
+        let env = |h: &Harness, pane: usize| {
+            let log = h.pty.borrow();
+            let env = &log.env[&pane];
+            ["KOI_WINDOW_ID", "KOI_TAB_ID", "KOI_PANE_ID"].map(|k| env[k].clone())
+        };
+        assert_eq!(env(&h, 0), ["1", "0", "0"]);
+        h.key(PRIMARY, ch("t"));
+        h.key(PRIMARY, ch("t"));
+        assert_eq!(env(&h, 2), ["1", "2", "2"]);
+
+        // Closing the first tab shifts the others' indices but not their ids.
+        h.key_at(PRIMARY, ch("1"), PhysicalKey::Code(KeyCode::Digit1));
+        h.key(PRIMARY, ch("w"));
+        h.key_at(PRIMARY, ch("2"), PhysicalKey::Code(KeyCode::Digit2));
+        // A split joins the active tab.
+        h.key(PRIMARY, ch("d"));
+        assert_eq!(env(&h, 3), ["1", "2", "3"]);
+        // A new tab gets the next id, never a closed tab's.
+        h.key(PRIMARY, ch("t"));
+        assert_eq!(env(&h, 4), ["1", "3", "4"]);
+    }
+
+    #[test]
+    fn cmd_q_quits_rather_than_closing_the_window() {
+        let mut h = Harness::new();
+        assert_eq!(h.key(PRIMARY, ch("q")), Exit::App);
+        assert_eq!(h.event(WindowEvent::CloseRequested), Exit::Window);
+        assert_eq!(h.key(PRIMARY, ch("t")), Exit::Stay);
+    }
+
+    #[test]
+    fn pane_events_go_to_the_window_holding_the_pane() {
+        let ids = crate::tabs::PaneIds::default();
+        let mut first = Harness::window(Config::default(), 1, ids.clone());
+        let second = Harness::window(Config::default(), 2, ids);
+        first.key(PRIMARY, ch("d"));
+        first.state.window_focused = false;
+        // Panes 0 and 2 are the first window's, 1 the second's.
+        let windows = HashMap::from([(1, first.state), (2, second.state)]);
+        let route = |event| crate::window_for(&windows, &event);
+        assert_eq!(route(KoiEvent::Title("vim".into(), 2)), Some(1));
+        assert_eq!(route(KoiEvent::ChildExit(1, 0)), Some(2));
+        assert_eq!(route(KoiEvent::ChildExit(0, 0)), Some(1));
+        assert_eq!(route(KoiEvent::Wakeup(3)), None);
+        assert_eq!(route(KoiEvent::ClipboardStore("x".into())), Some(2));
+    }
+
+    #[test]
+    fn fullscreen_toggles_and_returns_to_the_windowed_size() {
+        let mut config = Config::default();
+        config.keyboard.cmd_enter_fullscreen = true;
+        let mut h = Harness::with_config(config);
+        h.resize(700, 500);
+        #[cfg(target_os = "macos")]
+        h.key(PRIMARY | ModifiersState::CONTROL, ch("f"));
+        #[cfg(not(target_os = "macos"))]
+        h.named(ModifiersState::empty(), NamedKey::F11);
+        assert_eq!(h.state.window.fullscreen.get(), Some(false));
+        h.resize(1440, 900);
+        assert_eq!((h.state.window_state().width, h.state.window_state().height), (700.0, 500.0));
+
+        h.named(PRIMARY, NamedKey::Enter);
+        assert_eq!(h.state.window.fullscreen.get(), None);
+        assert_eq!(h.state.window.size.get(), PhysicalSize::new(700, 500));
+        assert_eq!(h.pty_size(0), Some((100, 35)));
+        assert!(h.take_input(0).is_empty());
+
+        // Shift+F11 is the app's.
+        #[cfg(not(target_os = "macos"))]
+        {
+            h.named(ModifiersState::SHIFT, NamedKey::F11);
+            assert_eq!(h.state.window.fullscreen.get(), None);
+            assert_eq!(h.take_input(0), b"\x1b[23;2~");
+        }
+    }
+}
@@ -0,0 +1,514 @@
+//! Who gets a key press. Modal overlays get first refusal, topmost first;
+//! a key none of them takes goes to koi's shortcuts, and only then to the
+//! active pane's PTY. Keeping the overlay side pure lets the order be
+//! tested without a window.
+//!
+//! Also here: the bytes a Ctrl combination sends to the PTY, the bytes
+//! for macOS-style editing keys and application cursor keys, and the
+//! Shift keys that page through scrollback instead.
+
+use std::borrow::Cow;
+
+use alacritty_terminal::grid::Scroll;
+use winit::event::{ElementState, KeyEvent};
+use winit::keyboard::{Key, NamedKey, PhysicalKey, SmolStr};
+
+use crate::config::CtrlKeys;
+
+/// The parts of a key event koi acts on. Unlike winit's `KeyEvent`, which
+/// only winit can build, tests can make one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyPress {
+    pub state: ElementState,
+    pub logical_key: Key,
+    pub physical_key: PhysicalKey,
+    pub text: Option<SmolStr>,
+    /// The key as if no modifiers were held (Option+R is still "r").
+    pub key_without_modifiers: Key,
+    /// Sent by the key being held down.
+    pub repeat: bool,
+}
+
+impl From<KeyEvent> for KeyPress {
+    fn from(event: KeyEvent) -> Self {
+        use winit::platform::modifier_supplement::KeyEventExtModifierSupplement;
+        KeyPress {
+            state: event.state,
+            key_without_modifiers: event.key_without_modifiers(),
+            logical_key: event.logical_key,
+            physical_key: event.physical_key,
+            text: event.text,
+            repeat: event.repeat,
+        }
+    }
+}
+
+/// Modifier state for a key press. `primary` is Cmd on macOS and Ctrl
+/// elsewhere.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Mods {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub primary: bool,
+}
+
+/// Overlays that take keyboard input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Overlay {
+    /// Confirmation for a multi-line paste.
+    Paste,
+    Menu,
+    About,
+    Palette,
+    /// Naming the active tab.
+    Rename,
+    Resize,
+    Search,
+}
+
+impl Overlay {
+    /// Order in which open overlays are offered a key.
+    pub const ORDER: [Overlay; 7] = [
+        Overlay::Paste,
+        Overlay::Menu,
+        Overlay::About,
+        Overlay::Palette,
+        Overlay::Rename,
+        Overlay::Resize,
+        Overlay::Search,
+    ];
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PasteKey {
+    /// Enter: paste as is.
+    Paste,
+    /// `e`: paste joined into one line, to edit before running.
+    SingleLine,
+    Cancel,
+    Swallow,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MenuKey {
+    Close,
+    Run,
+    Next,
+    Prev,
+    Swallow,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PaletteKey {
+    Close,
+    Run,
+    Next,
+    Prev,
+    Pop,
+    Insert(String),
+    Swallow,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RenameKey {
+    /// Enter: keep the name typed.
+    Commit,
+    Cancel,
+    Pop,
+    Insert(String),
+    Swallow,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResizeKey {
+    Left,
+    Right,
+    Up,
+    Down,
+    Cycle { backwards: bool },
+    Commit,
+    Revert,
+    Swallow,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SearchKey {
+    Close,
+    Next,
+    Prev,
+    Pop,
+    Insert(String),
+    /// Cmd+V: paste into the query, never into the shell.
+    Paste,
+    /// Cmd+R: switch between text and regex search.
+    ToggleRegex,
+    Swallow,
+}
+
+/// A key taken by an overlay, and what it means there.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OverlayKey {
+    Paste(PasteKey),
+    Menu(MenuKey),
+    /// Any key closes the about card.
+    About,
+    Palette(PaletteKey),
+    Rename(RenameKey),
+    Resize(ResizeKey),
+    Search(SearchKey),
+}
+
+/// Offer `key` to the open overlays in `Overlay::ORDER`. None if no
+/// overlay takes it, so it goes on to shortcuts and the PTY.
+pub fn route(open: &[Overlay], key: &Key, mods: Mods) -> Option<OverlayKey> {
+    Overlay::ORDER
+        .into_iter()
+        .filter(|o| open.contains(o))
+        .find_map(|o| offer(o, key, mods))
+}
+
+/// What `overlay` does with `key`, or None to pass it on. Every current
+/// overlay is modal and swallows keys it has no use for.
+fn offer(overlay: Overlay, key: &Key, mods: Mods) -> Option<OverlayKey> {
+    Some(match overlay {
+        Overlay::Paste => OverlayKey::Paste(paste_key(key, mods)),
+        Overlay::Menu => OverlayKey::Menu(menu_key(key)),
+        Overlay::About => OverlayKey::About,
+        Overlay::Palette => OverlayKey::Palette(palette_key(key, mods)),
+        Overlay::Rename => OverlayKey::Rename(rename_key(key, mods)),
+        Overlay::Resize => OverlayKey::Resize(resize_key(key, mods)),
+        Overlay::Search => OverlayKey::Search(search_key(key, mods)),
+    })
+}
+
+fn paste_key(key: &Key, mods: Mods) -> PasteKey {
+    match key {
+        Key::Named(NamedKey::Enter) => PasteKey::Paste,
+        Key::Named(NamedKey::Escape) => PasteKey::Cancel,
+        Key::Character(s) if !mods.primary && !mods.ctrl && s.eq_ignore_ascii_case("e") => PasteKey::SingleLine,
+        _ => PasteKey::Swallow,
+    }
+}
+
+fn menu_key(key: &Key) -> MenuKey {
+    match key {
+        Key::Named(NamedKey::Escape) => MenuKey::Close,
+        Key::Named(NamedKey::Enter) => MenuKey::Run,
+        Key::Named(NamedKey::ArrowDown) => MenuKey::Next,
+        Key::Named(NamedKey::ArrowUp) => MenuKey::Prev,
+        _ => MenuKey::Swallow,
+    }
+}
+
+fn palette_key(key: &Key, mods: Mods) -> PaletteKey {
+    match key {
+        Key::Named(NamedKey::Escape) => PaletteKey::Close,
+        Key::Named(NamedKey::Enter) => PaletteKey::Run,
+        Key::Named(NamedKey::ArrowDown) => PaletteKey::Next,
+        Key::Named(NamedKey::ArrowUp) => PaletteKey::Prev,
+        Key::Named(NamedKey::Tab) if mods.shift => PaletteKey::Prev,
+        Key::Named(NamedKey::Tab) => PaletteKey::Next,
+        Key::Named(NamedKey::Backspace) => PaletteKey::Pop,
+        Key::Named(NamedKey::Space) => PaletteKey::Insert(" ".into()),
+        Key::Character(s) if !mods.primary && !mods.ctrl => PaletteKey::Insert(s.to_string()),
+        _ => PaletteKey::Swallow,
+    }
+}
+
+fn rename_key(key: &Key, mods: Mods) -> RenameKey {
+    match key {
+        Key::Named(NamedKey::Escape) => RenameKey::Cancel,
+        Key::Named(NamedKey::Enter) => RenameKey::Commit,
+        Key::Named(NamedKey::Backspace) => RenameKey::Pop,
+        Key::Named(NamedKey::Space) => RenameKey::Insert(" ".into()),
+        Key::Character(s) if !mods.primary && !mods.ctrl => RenameKey::Insert(s.to_string()),
+        _ => RenameKey::Swallow,
+    }
+}
+
+fn resize_key(key: &Key, mods: Mods) -> ResizeKey {
+    match key {
+        Key::Named(NamedKey::ArrowLeft) => ResizeKey::Left,
+        Key::Named(NamedKey::ArrowRight) => ResizeKey::Right,
+        Key::Named(NamedKey::ArrowUp) => ResizeKey::Up,
+        Key::Named(NamedKey::ArrowDown) => ResizeKey::Down,
+        Key::Named(NamedKey::Tab) => ResizeKey::Cycle { backwards: mods.shift },
+        Key::Named(NamedKey::Enter) => ResizeKey::Commit,
+        Key::Named(NamedKey::Escape) => ResizeKey::Revert,
+        _ => ResizeKey::Swallow,
+    }
+}
+
+fn search_key(key: &Key, mods: Mods) -> SearchKey {
+    match key {
+        Key::Named(NamedKey::Escape) => SearchKey::Close,
+        // Enter / Cmd+G: next match; with Shift, previous.
+        Key::Named(NamedKey::Enter) if mods.shift => SearchKey::Prev,
+        Key::Named(NamedKey::Enter) => SearchKey::Next,
+        Key::Named(NamedKey::Backspace) => SearchKey::Pop,
+        Key::Named(NamedKey::Space) => SearchKey::Insert(" ".into()),
+        Key::Character(s) if mods.primary && s.eq_ignore_ascii_case("g") => {
+            if mods.shift {
+                SearchKey::Prev
+            } else {
+                SearchKey::Next
+            }
+        }
+        Key::Character(s) if mods.primary && s.eq_ignore_ascii_case("v") => SearchKey::Paste,
+        Key::Character(s) if mods.primary && s.eq_ignore_ascii_case("r") => SearchKey::ToggleRegex,
+        Key::Character(s) if !mods.primary && !mods.ctrl => SearchKey::Insert(s.to_string()),
+        _ => SearchKey::Swallow,
+    }
+}
+
+/// The C0 control character xterm sends for Ctrl+`c`: Ctrl+@ through
+/// Ctrl+_ and their lowercase twins, the VT220 digit aliases (Ctrl+2 is
+/// NUL up to Ctrl+7 = US, Ctrl+8 = DEL), Ctrl+Space, Ctrl+/ and Ctrl+?.
+fn c0(c: char) -> Option<u8> {
+    Some(match c {
+        ' ' | '2' => 0x00,
+        '@'..='_' | '`'..='~' => c as u8 & 0x1f,
+        '3' => 0x1b,
+        '4' => 0x1c,
+        '5' => 0x1d,
+        '6' => 0x1e,
+        '7' | '/' => 0x1f,
+        '8' | '?' => 0x7f,
+        _ => return None,
+    })
+}
+
+/// Bytes for a Ctrl combination. `base` is the key's character without
+/// any modifiers (`key_without_modifiers`), so the result doesn't depend
+/// on how the OS applied Ctrl or a remapped Caps Lock to `logical`, the
+/// character with Shift applied. Alt prefixes ESC.
+///
+/// With Shift, a symbol that has its own code sends it (Ctrl+Shift+/ is
+/// Ctrl+?, DEL); Ctrl+Shift+letter sends the letter's code, or with
+/// `CtrlKeys::CsiU` a CSI u sequence, as does any combo without a code.
+pub fn ctrl_key(base: char, logical: Option<char>, shift: bool, alt: bool, mode: CtrlKeys) -> Option<Vec<u8>> {
+    let shifted = logical.filter(|c| shift && !c.is_alphabetic()).and_then(c0);
+    let ambiguous = shift && base.is_alphabetic();
+    let code = match shifted.or_else(|| c0(base)) {
+        Some(_) if ambiguous && mode == CtrlKeys::CsiU => None,
+        code => code,
+    };
+    match code {
+        Some(byte) if alt => Some(vec![0x1b, byte]),
+        Some(byte) => Some(vec![byte]),
+        None if mode == CtrlKeys::CsiU => {
+            let modifier = 5 + u8::from(shift) + 2 * u8::from(alt);
+            let codepoint = base.to_lowercase().next().unwrap_or(base) as u32;
+            Some(format!("\x1b[{codepoint};{modifier}u").into_bytes())
+        }
+        None => None,
+    }
+}
+
+/// Readline bytes for the editing keys of macOS text fields
+/// (`keyboard.natural_text_editing`), with `alt` for Option. Each takes
+/// its modifier alone: Option+Shift+Left still selects in apps that read
+/// the CSI form.
+pub fn natural_edit(key: &Key, mods: Mods, alt: bool) -> Option<&'static [u8]> {
+    let Key::Named(named) = key else { return None };
+    if mods.shift {
+        return None;
+    }
+    // Off macOS the primary modifier is Ctrl itself.
+    let lone_primary = mods.primary && !alt && (mods.ctrl == cfg!(not(target_os = "macos")));
+    let lone_alt = alt && !mods.primary && !mods.ctrl;
+    match named {
+        NamedKey::ArrowLeft if lone_alt => Some(b"\x1bb"),
+        NamedKey::ArrowRight if lone_alt => Some(b"\x1bf"),
+        NamedKey::Backspace if lone_alt => Some(b"\x1b\x7f"),
+        NamedKey::ArrowLeft if lone_primary => Some(b"\x01"),
+        NamedKey::ArrowRight if lone_primary => Some(b"\x05"),
+        NamedKey::Backspace if lone_primary => Some(b"\x15"),
+        _ => None,
+    }
+}
+
+/// `bytes` for a pane with DECCKM (application cursor keys) on: the
+/// unmodified arrow, Home and End keys send `ESC O` rather than `ESC [`.
+/// Anything else is the same either way.
+pub fn app_cursor(bytes: &[u8]) -> Cow<'_, [u8]> {
+    match *bytes {
+        [0x1b, b'[', key @ (b'A' | b'B' | b'C' | b'D' | b'H' | b'F')] => Cow::Owned(vec![0x1b, b'O', key]),
+        _ => Cow::Borrowed(bytes),
+    }
+}
+
+/// How Shift+`key` moves a pane's view through its scrollback, for the
+/// keys koi keeps from the shell: Shift+PageUp/PageDown by a page,
+/// Shift+Home/End to the top and bottom.
+pub fn shift_scroll(key: &Key) -> Option<Scroll> {
+    match key {
+        Key::Named(NamedKey::PageUp) => Some(Scroll::PageUp),
+        Key::Named(NamedKey::PageDown) => Some(Scroll::PageDown),
+        Key::Named(NamedKey::Home) => Some(Scroll::Top),
+        Key::Named(NamedKey::End) => Some(Scroll::Bottom),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRIMARY: Mods = Mods { shift: false, ctrl: false, primary: true };
+    const NONE: Mods = Mods { shift: false, ctrl: false, primary: false };
+
+    fn ch(s: &str) -> Key {
+        Key::Character(s.into())
+    }
+
+    #[test]
+    fn paste_in_search_stays_in_search() {
+        let open = [Overlay::Search];
+        assert_eq!(route(&open, &ch("v"), PRIMARY), Some(OverlayKey::Search(SearchKey::Paste)));
+        // With no overlay open, Cmd+V is left to the paste shortcut.
+        assert_eq!(route(&[], &ch("v"), PRIMARY), None);
+    }
+
+    #[test]
+    fn each_overlay_swallows_other_shortcuts() {
+        let quit = ch("q");
+        assert_eq!(route(&[Overlay::Search], &quit, PRIMARY), Some(OverlayKey::Search(SearchKey::Swallow)));
+        assert_eq!(route(&[Overlay::Palette], &quit, PRIMARY), Some(OverlayKey::Palette(PaletteKey::Swallow)));
+        assert_eq!(route(&[Overlay::Resize], &quit, NONE), Some(OverlayKey::Resize(ResizeKey::Swallow)));
+        assert_eq!(route(&[Overlay::About], &quit, PRIMARY), Some(OverlayKey::About));
+        assert_eq!(route(&[Overlay::Menu], &quit, PRIMARY), Some(OverlayKey::Menu(MenuKey::Swallow)));
+        assert_eq!(route(&[Overlay::Paste], &quit, PRIMARY), Some(OverlayKey::Paste(PasteKey::Swallow)));
+        assert_eq!(route(&[Overlay::Rename], &quit, PRIMARY), Some(OverlayKey::Rename(RenameKey::Swallow)));
+    }
+
+    #[test]
+    fn typing_goes_to_the_topmost_overlay() {
+        let open = [Overlay::Search, Overlay::Palette];
+        let key = ch("x");
+        assert_eq!(route(&open, &key, NONE), Some(OverlayKey::Palette(PaletteKey::Insert("x".into()))));
+        let open = [Overlay::Search, Overlay::Resize];
+        assert_eq!(route(&open, &key, NONE), Some(OverlayKey::Resize(ResizeKey::Swallow)));
+        let open = [Overlay::Search, Overlay::About];
+        assert_eq!(route(&open, &key, NONE), Some(OverlayKey::About));
+        let open = [Overlay::Search, Overlay::Menu];
+        let escape = Key::Named(NamedKey::Escape);
+        assert_eq!(route(&open, &escape, NONE), Some(OverlayKey::Menu(MenuKey::Close)), "Escape closes the menu first");
+    }
+
+    #[test]
+    fn search_keys() {
+        let open = [Overlay::Search];
+        let enter = Key::Named(NamedKey::Enter);
+        let shift = Mods { shift: true, ..NONE };
+        assert_eq!(route(&open, &enter, NONE), Some(OverlayKey::Search(SearchKey::Next)));
+        assert_eq!(route(&open, &enter, shift), Some(OverlayKey::Search(SearchKey::Prev)));
+        let shift_primary = Mods { shift: true, ..PRIMARY };
+        assert_eq!(route(&open, &ch("G"), shift_primary), Some(OverlayKey::Search(SearchKey::Prev)));
+        let space = Key::Named(NamedKey::Space);
+        assert_eq!(route(&open, &space, NONE), Some(OverlayKey::Search(SearchKey::Insert(" ".into()))));
+        // Ctrl+letters aren't typed into the query.
+        let ctrl = Mods { ctrl: true, ..NONE };
+        assert_eq!(route(&open, &ch("c"), ctrl), Some(OverlayKey::Search(SearchKey::Swallow)));
+        assert_eq!(route(&open, &ch("r"), PRIMARY), Some(OverlayKey::Search(SearchKey::ToggleRegex)));
+    }
+
+    /// xterm's Ctrl+key output for every printable ASCII key, from its
+    /// ctlseqs "Special Keyboard Keys" and VT220 digit aliases.
+    const XTERM_C0: &[(char, u8)] = &[
+        (' ', 0x00), ('2', 0x00), ('@', 0x00), ('`', 0x00),
+        ('3', 0x1b), ('[', 0x1b), ('{', 0x1b),
+        ('4', 0x1c), ('\\', 0x1c), ('|', 0x1c),
+        ('5', 0x1d), (']', 0x1d), ('}', 0x1d),
+        ('6', 0x1e), ('^', 0x1e), ('~', 0x1e),
+        ('7', 0x1f), ('_', 0x1f), ('/', 0x1f),
+        ('8', 0x7f), ('?', 0x7f),
+    ];
+
+    #[test]
+    fn c0_table_matches_xterm() {
+        for c in (0x20u8..0x7f).map(char::from) {
+            let expected = match c {
+                'a'..='z' => Some(c as u8 - b'a' + 1),
+                'A'..='Z' => Some(c as u8 - b'A' + 1),
+                _ => XTERM_C0.iter().find(|&&(k, _)| k == c).map(|&(_, b)| b),
+            };
+            assert_eq!(c0(c), expected, "Ctrl+{c:?}");
+        }
+    }
+
+    #[test]
+    fn ctrl_keys_use_the_unmodified_key() {
+        let legacy = |base, logical, shift| ctrl_key(base, logical, shift, false, CtrlKeys::Legacy);
+        assert_eq!(legacy('c', Some('c'), false), Some(vec![0x03]));
+        // A remapped Caps Lock can leave `logical` as the control
+        // character itself; the base key still decides.
+        assert_eq!(legacy('c', Some('\x03'), false), Some(vec![0x03]));
+        assert_eq!(legacy('a', Some('A'), true), Some(vec![0x01]));
+        // Shifted symbols with their own code (US layout).
+        assert_eq!(legacy('/', Some('?'), true), Some(vec![0x7f]));
+        assert_eq!(legacy('2', Some('@'), true), Some(vec![0x00]));
+        assert_eq!(legacy('-', Some('_'), true), Some(vec![0x1f]));
+        assert_eq!(legacy('6', Some('^'), true), Some(vec![0x1e]));
+        assert_eq!(legacy('1', Some('!'), true), None);
+        assert_eq!(legacy('.', Some('.'), false), None);
+        assert_eq!(ctrl_key('[', None, false, true, CtrlKeys::Legacy), Some(vec![0x1b, 0x1b]));
+    }
+
+    #[test]
+    fn csi_u_disambiguates_what_legacy_cannot() {
+        let csi_u = |base, logical, shift, alt| ctrl_key(base, logical, shift, alt, CtrlKeys::CsiU);
+        assert_eq!(csi_u('a', Some('A'), true, false), Some(b"\x1b[97;6u".to_vec()));
+        assert_eq!(csi_u('1', Some('1'), false, false), Some(b"\x1b[49;5u".to_vec()));
+        assert_eq!(csi_u('.', Some('>'), true, true), Some(b"\x1b[46;8u".to_vec()));
+        // Combos with a code are unchanged.
+        assert_eq!(csi_u('a', Some('a'), false, false), Some(vec![0x01]));
+        assert_eq!(csi_u('/', Some('?'), true, false), Some(vec![0x7f]));
+    }
+
+    #[test]
+    fn application_cursor_keys_use_ss3() {
+        assert_eq!(&*app_cursor(b"\x1b[A"), b"\x1bOA");
+        assert_eq!(&*app_cursor(b"\x1b[F"), b"\x1bOF");
+        assert_eq!(&*app_cursor(b"\x1b[1;5A"), b"\x1b[1;5A");
+        assert_eq!(&*app_cursor(b"\x1b[Z"), b"\x1b[Z");
+    }
+
+    #[test]
+    fn natural_editing_keys_take_their_modifier_alone() {
+        let left = Key::Named(NamedKey::ArrowLeft);
+        let backspace = Key::Named(NamedKey::Backspace);
+        assert_eq!(natural_edit(&left, NONE, true), Some(&b"\x1bb"[..]));
+        assert_eq!(natural_edit(&backspace, NONE, true), Some(&b"\x1b\x7f"[..]));
+        let primary = Mods { ctrl: cfg!(not(target_os = "macos")), ..PRIMARY };
+        assert_eq!(natural_edit(&left, primary, false), Some(&b"\x01"[..]));
+        assert_eq!(natural_edit(&backspace, primary, false), Some(&b"\x15"[..]));
+
+        assert_eq!(natural_edit(&left, Mods { shift: true, ..NONE }, true), None);
+        assert_eq!(natural_edit(&left, primary, true), None);
+        assert_eq!(natural_edit(&left, NONE, false), None);
+        assert_eq!(natural_edit(&Key::Named(NamedKey::ArrowUp), NONE, true), None);
+    }
+
+    #[test]
+    fn paste_confirmation_keys() {
+        let open = [Overlay::Paste, Overlay::Search];
+        let enter = Key::Named(NamedKey::Enter);
+        assert_eq!(route(&open, &enter, NONE), Some(OverlayKey::Paste(PasteKey::Paste)));
+        assert_eq!(route(&open, &ch("e"), NONE), Some(OverlayKey::Paste(PasteKey::SingleLine)));
+        assert_eq!(route(&open, &ch("e"), PRIMARY), Some(OverlayKey::Paste(PasteKey::Swallow)));
+        let escape = Key::Named(NamedKey::Escape);
+        assert_eq!(route(&open, &escape, NONE), Some(OverlayKey::Paste(PasteKey::Cancel)));
+    }
+
+    #[test]
+    fn resize_keys() {
+        let open = [Overlay::Resize];
+        let tab = Key::Named(NamedKey::Tab);
+        let shift = Mods { shift: true, ..NONE };
+        let cycle_back = OverlayKey::Resize(ResizeKey::Cycle { backwards: true });
+        assert_eq!(route(&open, &tab, shift), Some(cycle_back));
+        let escape = Key::Named(NamedKey::Escape);
+        assert_eq!(route(&open, &escape, NONE), Some(OverlayKey::Resize(ResizeKey::Revert)));
+    }
+}
@@ -0,0 +1,167 @@
+//! Links under the pointer: OSC 8 hyperlinks, and http(s) URLs in the
+//! text for programs that don't emit them.
+
+use alacritty_terminal::event::EventListener;
+use alacritty_terminal::grid::Dimensions;
+use alacritty_terminal::index::{Column, Line, Point};
+use alacritty_terminal::term::cell::Flags;
+use alacritty_terminal::term::Term;
+
+/// A link and the cells it covers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Link {
+    pub uri: String,
+    /// First and last cell, in grid coordinates. The link may wrap onto
+    /// later lines in between.
+    pub start: Point,
+    pub end: Point,
+}
+
+/// The link at `point`: its OSC 8 hyperlink if it has one, or else a
+/// URL written out in the text around it.
+pub fn link_at<T: EventListener>(term: &Term<T>, point: Point) -> Option<Link> {
+    hyperlink_at(term, point).or_else(|| url_at(term, point))
+}
+
+/// The cell before `point`, wrapping to the end of the line above.
+fn prev_point<T: EventListener>(term: &Term<T>, point: Point) -> Option<Point> {
+    if point.column.0 > 0 {
+        Some(Point::new(point.line, point.column - 1))
+    } else if point.line > term.topmost_line() {
+        Some(Point::new(point.line - 1, term.last_column()))
+    } else {
+        None
+    }
+}
+
+/// The cell after `point`, wrapping to the start of the line below.
+fn next_point<T: EventListener>(term: &Term<T>, point: Point) -> Option<Point> {
+    if point.column < term.last_column() {
+        Some(Point::new(point.line, point.column + 1))
+    } else if point.line < term.bottommost_line() {
+        Some(Point::new(point.line + 1, Column(0)))
+    } else {
+        None
+    }
+}
+
+/// The OSC 8 hyperlink at `point`, stretched over the neighbouring cells
+/// that carry the same link. Prompt marks aren't links.
+fn hyperlink_at<T: EventListener>(term: &Term<T>, point: Point) -> Option<Link> {
+    let grid = term.grid();
+    let hyperlink = grid[point].hyperlink().filter(|link| !crate::prompts::is_mark(link))?;
+    let same = |p: Point| grid[p].hyperlink().as_ref() == Some(&hyperlink);
+
+    let mut start = point;
+    while let Some(p) = prev_point(term, start).filter(|&p| same(p)) {
+        start = p;
+    }
+    let mut end = point;
+    while let Some(p) = next_point(term, end).filter(|&p| same(p)) {
+        end = p;
+    }
+    Some(Link { uri: hyperlink.uri().to_owned(), start, end })
+}
+
+/// An http(s) URL in the text around `point`. A line the terminal wrapped
+/// is read together with its neighbours, so a URL too long for one row is
+/// found whole.
+fn url_at<T: EventListener>(term: &Term<T>, point: Point) -> Option<Link> {
+    let grid = term.grid();
+    let last = term.last_column();
+    let wraps = |line: Line| grid[line][last].flags.contains(Flags::WRAPLINE);
+
+    let mut first_line = point.line;
+    while first_line > term.topmost_line() && wraps(first_line - 1) {
+        first_line -= 1;
+    }
+    let mut last_line = point.line;
+    while last_line < term.bottommost_line() && wraps(last_line) {
+        last_line += 1;
+    }
+
+    // The logical line's characters and the cell each came from.
+    let mut chars = Vec::new();
+    let mut cells = Vec::new();
+    for line in first_line.0..=last_line.0 {
+        for col in 0..term.columns() {
+            let p = Point::new(Line(line), Column(col));
+            let cell = &grid[p];
+            if cell.flags.intersects(Flags::WIDE_CHAR_SPACER | Flags::LEADING_WIDE_CHAR_SPACER) {
+                continue;
+            }
+            chars.push(cell.c);
+            cells.push(p);
+        }
+    }
+    let clicked = cells.iter().position(|&p| p >= point)?;
+
+    let url_chars = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-._~:/?#[]@!$&'()*+,;=%";
+    let starts_with = |i: usize, prefix: &str| prefix.chars().enumerate().all(|(j, c)| chars.get(i + j) == Some(&c));
+    let mut i = 0;
+    while i < chars.len() {
+        if !starts_with(i, "https://") && !starts_with(i, "http://") {
+            i += 1;
+            continue;
+        }
+        let end = chars[i..].iter().position(|&c| !url_chars.contains(c)).map_or(chars.len(), |e| i + e);
+        // Trim trailing punctuation.
+        let mut trimmed = end;
+        while trimmed > i && ".,;:!?)>".contains(chars[trimmed - 1]) {
+            trimmed -= 1;
+        }
+        if clicked >= i && clicked < trimmed {
+            return Some(Link { uri: chars[i..trimmed].iter().collect(), start: cells[i], end: cells[trimmed - 1] });
+        }
+        i = end.max(i + 1);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use alacritty_terminal::event::VoidListener;
+    use alacritty_terminal::term::Config;
+    use alacritty_terminal::vte::ansi::{Processor, StdSyncHandler};
+
+    use super::*;
+    use crate::terminal::TerminalSize;
+
+    fn term(cols: usize, text: &str) -> Term<VoidListener> {
+        let mut term = Term::new(Config::default(), &TerminalSize::new(cols, 4), VoidListener);
+        let mut parser: Processor<StdSyncHandler> = Processor::new();
+        parser.advance(&mut term, text.as_bytes());
+        term
+    }
+
+    fn at(line: i32, col: usize) -> Point {
+        Point::new(Line(line), Column(col))
+    }
+
+    #[test]
+    fn osc8_links_open_their_target_not_their_text() {
+        let t = term(20, "see \x1b]8;;https://github.com/o/r/pull/7\x1b\\PR #7\x1b]8;;\x1b\\ now");
+        let link = link_at(&t, at(0, 6)).unwrap();
+        assert_eq!(link.uri, "https://github.com/o/r/pull/7");
+        assert_eq!((link.start, link.end), (at(0, 4), at(0, 8)));
+        assert_eq!(link_at(&t, at(0, 10)), None);
+    }
+
+    #[test]
+    fn osc8_links_span_wrapped_lines() {
+        let t = term(10, "abcdefg\x1b]8;;file:///tmp/x\x1b\\0123456\x1b]8;;\x1b\\");
+        let link = link_at(&t, at(1, 2)).unwrap();
+        assert_eq!(link.uri, "file:///tmp/x");
+        assert_eq!((link.start, link.end), (at(0, 7), at(1, 3)));
+    }
+
+    #[test]
+    fn plain_urls_are_read_across_wrapped_lines() {
+        let t = term(16, "go https://example.com/a/b. ok");
+        let expected = Link { uri: "https://example.com/a/b".into(), start: at(0, 3), end: at(1, 9) };
+        assert_eq!(link_at(&t, at(0, 5)).as_ref(), Some(&expected));
+        assert_eq!(link_at(&t, at(1, 2)).as_ref(), Some(&expected));
+        assert_eq!(link_at(&t, at(1, 10)), None, "trailing punctuation isn't part of it");
+        assert_eq!(link_at(&t, at(0, 1)), None);
+    }
+}
@@ -1,17 +1,54 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod actions;
+mod activity;
+mod animation;
+mod bell;
+mod bindings;
+mod config;
+mod context_menu;
+mod divider;
 mod event;
 mod fonts;
 mod fonts_registrar;
 mod gl;
+#[cfg(test)]
+mod harness;
+mod keyboard;
+mod links;
+mod marks;
+mod mouse;
+mod overlay;
+mod pager;
+mod palette;
 mod panes;
+mod passthrough;
+mod paste;
+mod process;
+mod profile;
+mod prompts;
 mod renderer;
+mod resize_mode;
+mod ruler;
+mod scroll;
+mod scrollbar;
+mod search;
+mod selection;
+mod styled;
 mod tabs;
 mod terminal;
+mod themes;
+mod title;
+mod wheel;
+mod window;
+mod window_state;
 
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::num::NonZeroU32;
 
+use alacritty_terminal::grid::Dimensions;
+use alacritty_terminal::vte::ansi::Rgb;
 use glutin::config::ConfigTemplateBuilder;
 use glutin::context::{ContextApi, ContextAttributesBuilder, Version};
 use glutin::display::GetGlDisplay;
@@ -23,16 +60,37 @@ use winit::application::ApplicationHandler;
 use winit::event::{ElementState, MouseButton, WindowEvent};
 use winit::event_loop::{ActiveEventLoop, EventLoop};
 use winit::keyboard::{Key, ModifiersState, NamedKey};
-use winit::window::{Window, WindowAttributes};
+use winit::window::{CursorIcon, WindowAttributes};
 
+use actions::Action;
+use config::Config;
+use context_menu::ContextMenu;
 use event::{EventProxy, KoiEvent};
-use renderer::Renderer;
+use mouse::MouseReport;
+use palette::PaletteState;
+use renderer::layout::TextOptions;
+use renderer::{Canvas, Renderer};
+use search::SearchState;
 use tabs::TabManager;
+use window::{GlWindow, WindowOps};
 
 fn clipboard_paste() -> Option<String> {
     arboard::Clipboard::new().ok()?.get_text().ok()
 }
 
+/// Files on the clipboard (copied in Finder or a file manager) as
+/// shell-escaped paths.
+fn clipboard_paste_files() -> Option<String> {
+    let paths = arboard::Clipboard::new().ok()?.get().file_list().ok()?;
+    paste::file_list(&paths)
+}
+
+/// What Cmd+V and right/middle-click paste insert, in order of preference:
+/// text, then copied files as paths, then an image saved to a temp file.
+fn clipboard_paste_any() -> Option<String> {
+    clipboard_paste().or_else(clipboard_paste_files).or_else(clipboard_paste_image)
+}
+
 /// Try to get an image from the clipboard, save it as a PNG temp file,
 /// and return the file path. Used as fallback when Cmd+V has no text.
 fn clipboard_paste_image() -> Option<String> {
@@ -53,43 +111,30 @@ fn clipboard_paste_image() -> Option<String> {
     Some(path.to_string_lossy().into_owned())
 }
 
-/// Extract a URL from grid cells around a given column on a given line.
-fn extract_url_at<T: alacritty_terminal::event::EventListener>(
-    term: &alacritty_terminal::term::Term<T>,
-    point: alacritty_terminal::index::Point,
-) -> Option<String> {
-    use alacritty_terminal::grid::Dimensions;
-    let cols = term.grid().columns();
-    let line = point.line;
-
-    // Collect the full line text.
-    let mut text = String::new();
-    for col in 0..cols {
-        let cell = &term.grid()[line][alacritty_terminal::index::Column(col)];
-        text.push(cell.c);
-    }
-
-    // Find URL containing the clicked column.
-    let click_col = point.column.0;
-    let url_chars = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-._~:/?#[]@!$&'()*+,;=%";
-    for prefix in &["https://", "http://"] {
-        let mut start = 0;
-        while let Some(pos) = text[start..].find(prefix) {
-            let abs_start = start + pos;
-            let end = text[abs_start..]
-                .find(|c: char| !url_chars.contains(c))
-                .map(|e| abs_start + e)
-                .unwrap_or(text.len());
-            // Trim trailing punctuation.
-            let trimmed = text[abs_start..end].trim_end_matches(|c| ".,;:!?)>".contains(c));
-            let abs_end = abs_start + trimmed.len();
-            if click_col >= abs_start && click_col < abs_end {
-                return Some(trimmed.to_string());
-            }
-            start = end;
-        }
-    }
-    None
+/// Badge drawn at the top-left of a pane whose bell was auto-muted.
+const BELL_MUTED_BADGE: &str = " BELL MUTED ";
+
+/// Badge drawn at the top of every pane while its tab broadcasts input.
+const BROADCAST_BADGE: &str = " BROADCAST ";
+
+/// Shown over a pane whose PTY thread panicked.
+const IO_CRASHED_BANNER: &str = " terminal I/O thread crashed \u{2014} press Enter to restart ";
+
+/// Font size in points at startup and after Cmd+0.
+const DEFAULT_FONT_SIZE: f32 = 14.0;
+
+/// How often panes' PTY threads are checked for having died.
+const PTY_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// How soon to come back to a paste the shell isn't keeping up with.
+const PASTE_RETRY: std::time::Duration = std::time::Duration::from_millis(5);
+
+/// How long a held key must be quiet before the search bar searches.
+const SEARCH_DEBOUNCE: std::time::Duration = std::time::Duration::from_millis(80);
+
+/// Smallest window koi allows: 2 cells wide, 1 row plus room for the tab bar.
+fn min_window_size(cell_width: f32, cell_height: f32) -> winit::dpi::PhysicalSize<u32> {
+    winit::dpi::PhysicalSize::new((cell_width * 2.0) as u32, (cell_height * 2.0) as u32)
 }
 
 fn clipboard_copy(text: &str) {
@@ -112,48 +157,33 @@ struct DividerDrag {
     span: f32,
 }
 
-/// Scan terminal grid (visible + scrollback) for all occurrences of `query`.
-/// Returns matches as (grid Line, start column) pairs, topmost first.
-fn search_grid<T: alacritty_terminal::event::EventListener>(
-    term: &alacritty_terminal::term::Term<T>,
-    query: &str,
-) -> Vec<(alacritty_terminal::index::Line, usize)> {
-    use alacritty_terminal::grid::Dimensions;
-    if query.is_empty() {
-        return Vec::new();
-    }
-    let cols = term.grid().columns();
-    let topmost = term.topmost_line();
-    let bottommost = term.bottommost_line();
-    let mut results = Vec::new();
-    let mut line = topmost;
-    while line <= bottommost {
-        // Collect line text.
-        let mut text = String::with_capacity(cols);
-        for col in 0..cols {
-            text.push(term.grid()[line][alacritty_terminal::index::Column(col)].c);
-        }
-        let lower = text.to_lowercase();
-        let q = query.to_lowercase();
-        let mut start = 0;
-        while let Some(pos) = lower[start..].find(&q) {
-            results.push((line, start + pos));
-            start += pos + 1;
-        }
-        line += 1;
-    }
-    results
+/// State for an in-progress scrollbar thumb drag.
+struct ScrollbarDrag {
+    pane_id: usize,
+    /// Pointer y less the thumb's top when it was grabbed.
+    grab: f32,
+}
+
+/// State for a press on the tab bar, which becomes a drag once the
+/// pointer moves far enough.
+struct TabDrag {
+    /// Where the pressed tab sits now; follows it as it moves.
+    index: usize,
+    /// Where it sat when pressed, for putting it back on cancel.
+    origin: usize,
+    start_x: f32,
+    dragging: bool,
 }
 
-/// State for Cmd+F scrollback search.
-struct SearchState {
-    query: String,
-    /// Grid points of all match starts (line, column).
-    matches: Vec<(alacritty_terminal::index::Line, usize)>,
-    /// Index into matches for the current/focused match.
-    current: usize,
+/// The name being typed for a tab.
+struct TabRename {
+    tab_id: usize,
+    text: String,
 }
 
+/// How far the pointer moves along the tab bar before a press drags the tab.
+const TAB_DRAG_THRESHOLD: f32 = 6.0;
+
 /// State for tab-switch slide animation.
 struct TabAnimation {
     start: std::time::Instant,
@@ -162,18 +192,14 @@ struct TabAnimation {
 }
 
 impl TabAnimation {
-    const DURATION_MS: f32 = 180.0;
+    const DURATION: std::time::Duration = std::time::Duration::from_millis(180);
 
     fn progress(&self) -> f32 {
-        let elapsed = self.start.elapsed().as_secs_f32() * 1000.0;
-        (elapsed / Self::DURATION_MS).min(1.0)
+        animation::progress(self.start, Self::DURATION, std::time::Instant::now())
     }
 
-    /// Ease-out cubic for smooth deceleration.
     fn offset_fraction(&self) -> f32 {
-        let t = self.progress();
-        let ease = 1.0 - (1.0 - t).powi(3);
-        self.direction * (1.0 - ease)
+        self.direction * (1.0 - animation::ease_out_cubic(self.progress()))
     }
 
     fn done(&self) -> bool {
@@ -181,32 +207,217 @@ impl TabAnimation {
     }
 }
 
+/// What a key or window event leaves koi to do once it's handled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Exit {
+    /// Carry on.
+    Stay,
+    /// Close this window.
+    Window,
+    /// Quit, closing every window.
+    App,
+}
+
 /// Initialized application state — only exists after `resumed()`.
-struct KoiState {
-    window: Window,
-    gl_context: glutin::context::PossiblyCurrentContext,
-    gl_surface: glutin::surface::Surface<WindowSurface>,
-    renderer: Renderer,
+/// Generic over the window and what draws in it, so tests can drive it
+/// without either.
+struct KoiState<W = GlWindow, R = Renderer> {
+    config: Config,
+    window: W,
+    renderer: R,
+    event_proxy: EventProxy,
+    font_size: f32,
+    /// Display scale factor the renderer rasterizes for.
+    scale: f32,
     tab_manager: TabManager,
     modifiers: ModifiersState,
     cursor_pos: (f64, f64),
     cursor_blink: std::time::Instant,
     last_blink_on: bool,
+    /// The focused pane's cursor blinks (its DECSCUSR style, or the
+    /// configured default), so the blink phase needs redraws.
+    cursor_blinks: bool,
+    /// The window has keyboard focus.
+    window_focused: bool,
+    /// The pane last sent a focus-in report (DECSET 1004), or None while
+    /// the window is in the background.
+    focus_reported: Option<usize>,
     mouse_left_pressed: bool,
+    /// Right or middle button reported pressed to a mouse-mode app and not
+    /// yet released, so motion is reported as a drag with it.
+    mouse_button_held: Option<u8>,
+    /// Cell the last motion report was for; moves within a cell aren't
+    /// reported again.
+    last_motion_cell: Option<(usize, usize)>,
+    /// A Ctrl+click is being handled as a right click until the left release.
+    secondary_click: bool,
     needs_redraw: bool,
-    scroll_accumulator: f64,
+    /// The last frame drew an animation that isn't finished; the next one
+    /// is paced by `frame_pacer`.
+    animating: bool,
+    frame_pacer: animation::FramePacer,
+    /// When the frame showing panes' latest output is to be drawn, if it
+    /// was put off to keep to `frame_pacer`.
+    output_redraw: Option<std::time::Instant>,
+    /// When panes are next checked for long commands finishing.
+    next_command_poll: std::time::Instant,
+    /// When panes are next asked what they're running, for tab titles and
+    /// pane headers.
+    next_foreground_poll: std::time::Instant,
+    /// When to next look for panes whose PTY thread died.
+    next_pty_check: std::time::Instant,
+    /// Wheel and trackpad scrolling short of a whole line.
+    wheel: wheel::Wheel,
     auto_scroll_delta: i32,
+    /// What put each pane's view into scrollback.
+    scroll_origin: scroll::ScrollTracker,
+    /// The pane the user last scrolled and when, for its scrollbar.
+    last_scroll: Option<(usize, std::time::Instant)>,
     divider_drag: Option<DividerDrag>,
+    /// The divider under the pointer or being dragged, by path:
+    /// highlighted, with a resize cursor.
+    divider_hover: Option<(Vec<bool>, panes::Split)>,
+    scrollbar_drag: Option<ScrollbarDrag>,
+    tab_drag: Option<TabDrag>,
     last_click_time: std::time::Instant,
     click_count: u8,
-    bell_flash_until: Option<std::time::Instant>,
+    /// Fade the panes without focus (`window.dim_inactive_panes`, toggled
+    /// with Cmd+Shift+U).
+    dim_inactive: bool,
+    /// Panes flashing for a bell, and until when.
+    bell_flashes: std::collections::HashMap<usize, std::time::Instant>,
+    /// Global cap on audible beeps across panes.
+    beeps: bell::BeepLimiter,
+    /// Rate limit on `window.set_title` for title-spamming shells.
+    window_title: title::WindowTitle,
+    /// Text of the last mouse selection, pasted by middle-click whatever
+    /// has since been copied to the clipboard.
+    primary_selection: Option<String>,
+    /// Pastes still being fed to their pane, oldest first.
+    pastes: Vec<paste::PasteFeeder>,
+    /// "SCROLLBACK" bar shown when the active pane first scrolls into
+    /// history, keyed by pane id (`scrolling.dim_history`).
+    scrollback_bar: Option<(usize, overlay::Fade)>,
     search: Option<SearchState>,
+    palette: Option<PaletteState>,
+    /// The right-click menu, while it's open.
+    context_menu: Option<ContextMenu>,
+    /// A multi-line paste waiting for the user to confirm it.
+    pending_paste: Option<paste::PendingPaste>,
+    /// The link under the pointer while Cmd (Ctrl elsewhere) is held, and
+    /// the pane it's in.
+    hovered_link: Option<(usize, links::Link)>,
+    /// This window's theme from before the palette opened, restored if a
+    /// previewed theme isn't committed with Enter.
+    palette_theme_origin: Option<usize>,
+    /// The themes the palette lists and Cmd+Shift+T cycles through.
+    themes: themes::ThemeSet,
+    /// Which of `themes` the window shows. The renderer starts on the
+    /// first, the built-in Catppuccin Latte.
+    theme: usize,
+    /// The system appearance the theme last followed.
+    appearance: Option<winit::window::Theme>,
+    /// A theme was picked by hand, so the system appearance no longer
+    /// switches it.
+    theme_pinned: bool,
     tab_animation: Option<TabAnimation>,
+    /// Border pulse on the pane that just gained focus.
+    focus_pulse: Option<overlay::Fade>,
+    /// Keyboard divider adjustment (Cmd+Shift+R), while active.
+    resize_mode: Option<resize_mode::ResizeMode>,
+    /// Naming a tab (Cmd+Shift+I), while active.
+    tab_rename: Option<TabRename>,
     show_about: bool,
     about_opened_at: Option<std::time::Instant>,
+    /// Cmd+N asked for another window, which the app opens.
+    new_window: bool,
+    /// The window's size when it last wasn't fullscreen: where leaving
+    /// fullscreen goes back to, and what closing it then saves.
+    windowed_size: winit::dpi::PhysicalSize<u32>,
 }
 
-impl KoiState {
+impl<W: WindowOps, R: Canvas> KoiState<W, R> {
+    /// State for a window that has just opened with one tab, at the
+    /// default font size.
+    fn new(
+        config: Config,
+        window: W,
+        mut renderer: R,
+        event_proxy: EventProxy,
+        scale: f32,
+        mut tab_manager: TabManager,
+    ) -> Self {
+        tab_manager.set_pane_headers(config.window.pane_headers);
+        tab_manager.set_semantic_escape_chars(&config.selection.semantic_escape_chars);
+        renderer.set_theme(config.selection.apply(renderer.theme().clone()));
+        window.set_min_inner_size(min_window_size(renderer.cell_width(), renderer.cell_height()));
+        let frame_pacer = animation::FramePacer::new(window.refresh_rate_millihertz(), config.window.max_fps);
+        let dim_inactive = config.window.dim_inactive_panes;
+        let windowed_size = window.inner_size();
+        KoiState {
+            config,
+            window,
+            renderer,
+            event_proxy,
+            font_size: DEFAULT_FONT_SIZE,
+            scale,
+            tab_manager,
+            modifiers: ModifiersState::empty(),
+            cursor_pos: (0.0, 0.0),
+            cursor_blink: std::time::Instant::now(),
+            last_blink_on: true,
+            cursor_blinks: true,
+            window_focused: true,
+            focus_reported: None,
+            mouse_left_pressed: false,
+            mouse_button_held: None,
+            last_motion_cell: None,
+            secondary_click: false,
+            needs_redraw: true,
+            animating: false,
+            frame_pacer,
+            output_redraw: None,
+            next_command_poll: std::time::Instant::now(),
+            next_foreground_poll: std::time::Instant::now(),
+            next_pty_check: std::time::Instant::now(),
+            wheel: wheel::Wheel::default(),
+            auto_scroll_delta: 0,
+            scroll_origin: scroll::ScrollTracker::default(),
+            last_scroll: None,
+            divider_drag: None,
+            divider_hover: None,
+            scrollbar_drag: None,
+            tab_drag: None,
+            last_click_time: std::time::Instant::now(),
+            click_count: 0,
+            dim_inactive,
+            bell_flashes: std::collections::HashMap::new(),
+            beeps: bell::BeepLimiter::default(),
+            window_title: title::WindowTitle::default(),
+            primary_selection: None,
+            pastes: Vec::new(),
+            scrollback_bar: None,
+            search: None,
+            palette: None,
+            context_menu: None,
+            pending_paste: None,
+            hovered_link: None,
+            palette_theme_origin: None,
+            themes: themes::ThemeSet::builtin(),
+            theme: 0,
+            appearance: None,
+            theme_pinned: false,
+            tab_animation: None,
+            focus_pulse: None,
+            resize_mode: None,
+            tab_rename: None,
+            show_about: false,
+            about_opened_at: None,
+            new_window: false,
+            windowed_size,
+        }
+    }
+
     /// Map current cursor position to terminal grid coordinates.
     fn mouse_hit(&self) -> Option<MouseHit> {
         let cw = self.renderer.cell_width();
@@ -225,34 +436,268 @@ impl KoiState {
         Some(MouseHit { col, line })
     }
 
+    /// Cmd on macOS, Ctrl elsewhere: held, a click opens the link under it.
+    fn link_modifier_held(&self) -> bool {
+        #[cfg(target_os = "macos")]
+        return self.modifiers.super_key();
+        #[cfg(not(target_os = "macos"))]
+        return self.modifiers.control_key();
+    }
+
+    /// The link under the mouse cursor in the active tab, and its pane.
+    fn link_under_cursor(&self) -> Option<(usize, links::Link)> {
+        let cw = self.renderer.cell_width();
+        let ch = self.renderer.cell_height();
+        let tab_bar_h = if self.tab_manager.count() > 1 { ch } else { 0.0 };
+        let size = self.window.inner_size();
+        let viewport_h = (size.height as f32 - tab_bar_h).max(0.0);
+        let (cx, cy) = (self.cursor_pos.0 as f32, self.cursor_pos.1 as f32 - tab_bar_h);
+        let layouts = self.tab_manager.active_layouts(size.width as f32, viewport_h);
+        let layout = layouts.iter().find(|l| l.contains(cx, cy))?;
+        let term = self.tab_manager.pane(layout.pane_id)?.term.lock();
+        let col = (((cx - layout.x) / cw).max(0.0) as usize).min(term.last_column().0);
+        let line = (((cy - layout.y) / ch).max(0.0) as usize).min(term.screen_lines() - 1);
+        let point = alacritty_terminal::term::viewport_to_point(
+            term.grid().display_offset(),
+            alacritty_terminal::index::Point::new(line, alacritty_terminal::index::Column(col)),
+        );
+        links::link_at(&*term, point).map(|link| (layout.pane_id, link))
+    }
+
+    /// Underline the link under the pointer while the link modifier is
+    /// held, and stop when it's let go or the pointer moves off the link.
+    fn update_hovered_link(&mut self) {
+        let link = if self.link_modifier_held() { self.link_under_cursor() } else { None };
+        if link != self.hovered_link {
+            self.hovered_link = link;
+            self.needs_redraw = true;
+            self.window.request_redraw();
+        }
+    }
+
+    /// The system alert sound; on Linux, a request for the user's attention.
+    fn beep(&self) {
+        #[cfg(target_os = "macos")]
+        {
+            extern "C" { fn NSBeep(); }
+            unsafe { NSBeep(); }
+        }
+        #[cfg(target_os = "windows")]
+        {
+            use windows::Win32::System::Diagnostics::Debug::MessageBeep;
+            use windows::Win32::UI::WindowsAndMessaging::MB_OK;
+            unsafe { let _ = MessageBeep(MB_OK); }
+        }
+        #[cfg(all(unix, not(target_os = "macos")))]
+        self.window.request_user_attention();
+    }
+
+    /// The pane nearest the active one in the arrow's direction, comparing
+    /// centers.
+    fn pane_toward(&self, arrow: NamedKey) -> Option<usize> {
+        let (w, h) = self.pane_viewport();
+        let layouts = self.tab_manager.active_layouts(w, h);
+        let active_id = self.tab_manager.active_tab()?.pane_tree.active_pane_id();
+        let active = layouts.iter().find(|l| l.pane_id == active_id)?;
+        let center = |l: &panes::PaneLayout| (l.x + l.width / 2.0, l.y + l.height / 2.0);
+        let (ax, ay) = center(active);
+        layouts
+            .iter()
+            .filter(|l| l.pane_id != active_id)
+            .filter(|l| {
+                let (lx, ly) = center(l);
+                match arrow {
+                    NamedKey::ArrowLeft => lx < ax,
+                    NamedKey::ArrowRight => lx > ax,
+                    NamedKey::ArrowUp => ly < ay,
+                    NamedKey::ArrowDown => ly > ay,
+                    _ => false,
+                }
+            })
+            .min_by(|a, b| {
+                let distance = |l| {
+                    let (lx, ly) = center(l);
+                    (lx - ax).powi(2) + (ly - ay).powi(2)
+                };
+                distance(a).total_cmp(&distance(b))
+            })
+            .map(|l| l.pane_id)
+    }
+
+    /// The active tab's pane under the mouse cursor.
+    fn pane_under_cursor(&self) -> Option<usize> {
+        let ch = self.renderer.cell_height();
+        let tab_bar_h = if self.tab_manager.count() > 1 { ch } else { 0.0 };
+        let size = self.window.inner_size();
+        let viewport_h = (size.height as f32 - tab_bar_h).max(0.0);
+        let (cx, cy) = (self.cursor_pos.0 as f32, self.cursor_pos.1 as f32 - tab_bar_h);
+        let layouts = self.tab_manager.active_layouts(size.width as f32, viewport_h);
+        layouts.iter().find(|l| l.contains(cx, cy)).map(|l| l.pane_id)
+    }
+
+    /// The tab whose part of the tab bar is under the mouse, close
+    /// button included.
+    fn tab_under_cursor(&self) -> Option<usize> {
+        let count = self.tab_manager.count();
+        let (x, y) = (self.cursor_pos.0 as f32, self.cursor_pos.1 as f32);
+        if count < 2 || !(0.0..self.renderer.cell_height()).contains(&y) {
+            return None;
+        }
+        let width = self.window.inner_size().width as f32;
+        match renderer::tab_bar::hit(x, count, width, self.renderer.cell_width())? {
+            renderer::tab_bar::TabHit::Tab(idx) | renderer::tab_bar::TabHit::Close(idx) => Some(idx),
+        }
+    }
+
+    /// Close the tab at `index` from the tab bar, handing the bar's row
+    /// back to the panes if only one tab is left.
+    fn close_tab(&mut self, index: usize) {
+        self.cancel_tab_drag();
+        if self.tab_manager.close_tab(index) {
+            // The bar only shows with two or more tabs, so one always stays.
+            return;
+        }
+        self.needs_redraw = true;
+        let size = self.window.inner_size();
+        let (cw, ch) = (self.renderer.cell_width(), self.renderer.cell_height());
+        let tab_bar_h = if self.tab_manager.count() > 1 { ch } else { 0.0 };
+        let h = (size.height as f32 - tab_bar_h).max(0.0);
+        self.tab_manager.resize_all(size.width as f32, h, cw, ch);
+        self.fit_panes();
+        self.window.request_redraw();
+    }
+
+    /// Move pane focus with `f`, pulsing the new pane's border if focus
+    /// actually moved.
+    fn change_focus(&mut self, f: impl FnOnce(&mut TabManager)) {
+        let active_id = |tabs: &TabManager| tabs.active_tab().map(|t| t.pane_tree.active_pane_id());
+        let before = active_id(&self.tab_manager);
+        f(&mut self.tab_manager);
+        self.sync_search_focus();
+        self.sync_focus_reports();
+        if self.config.window.focus_pulse && active_id(&self.tab_manager) != before {
+            self.focus_pulse = Some(overlay::Fade::new(
+                std::time::Duration::ZERO,
+                std::time::Duration::from_millis(120),
+            ));
+            self.needs_redraw = true;
+        }
+    }
+
+    /// Modifier bits for mouse reports.
+    fn mouse_modifiers(&self) -> u8 {
+        mouse::modifier_bits(
+            self.modifiers.shift_key(),
+            self.modifiers.alt_key(),
+            self.modifiers.control_key(),
+        )
+    }
+
+    /// Whether a left press now should act as a right click (macOS Ctrl+click).
+    fn is_ctrl_click(&self) -> bool {
+        let enabled = cfg!(target_os = "macos") && self.config.mouse.ctrl_click_as_right_click;
+        mouse::is_ctrl_click(mouse::BUTTON_LEFT, self.mouse_modifiers(), enabled)
+    }
+
     fn grid_size(&self) -> (usize, usize) {
         let size = self.window.inner_size();
         let cw = self.renderer.cell_width();
         let ch = self.renderer.cell_height();
         let tab_bar_h = if self.tab_manager.count() > 1 { ch } else { 0.0 };
-        let cols = (size.width as f32 / cw) as usize;
-        let rows = ((size.height as f32 - tab_bar_h).max(0.0) / ch) as usize;
-        (cols.max(2), rows.max(1))
+        let height = (size.height as f32 - tab_bar_h).max(0.0);
+        tabs::pane_grid_size(size.width as f32, height, cw, ch)
     }
 
-    fn rebuild_renderer(&mut self, font_size: f32, scale: f32) {
-        let theme = self.renderer.theme.clone();
-        self.renderer = Renderer::with_theme("IBM Plex Mono", font_size, scale, theme);
+    /// Recreate the renderer after `font_size` or `scale` changed.
+    ///
+    /// With `keep_grid`, the window is resized to hold the current column/row
+    /// count in the new cell size instead of reflowing the grid. Split ratios
+    /// are unchanged, so every pane keeps (roughly) its own grid as well.
+    fn rebuild_renderer(&mut self, keep_grid: bool) {
+        let (old_cols, old_rows) = self.grid_size();
+        self.renderer.rebuild(self.font_size, self.scale, &self.config.font);
         let cw = self.renderer.cell_width();
         let ch = self.renderer.cell_height();
-        let size = self.window.inner_size();
         let tab_bar_h = if self.tab_manager.count() > 1 { ch } else { 0.0 };
+        self.window.set_min_inner_size(min_window_size(cw, ch));
+        self.needs_redraw = true;
+        self.window.request_redraw();
+
+        let mut size = self.window.inner_size();
+        if keep_grid && self.window.is_free_size() {
+            let (w, h) = terminal::window_size_for_grid(old_cols, old_rows, cw, ch, tab_bar_h);
+            let min = min_window_size(cw, ch);
+            let target = winit::dpi::PhysicalSize::new(w.max(min.width), h.max(min.height));
+            match self.window.request_inner_size(target) {
+                Some(applied) => size = applied,
+                // Applied asynchronously: the Resized event carries whatever
+                // the OS granted (possibly clamped to the screen) and resizes
+                // the PTYs then. Resizing now would reflow twice.
+                None => return,
+            }
+        }
         let vp_h = (size.height as f32 - tab_bar_h).max(0.0);
         self.tab_manager.resize_all(size.width as f32, vp_h, cw, ch);
+        self.fit_panes();
+    }
+
+    /// A pane's shell is gone: close the pane and give its space to the
+    /// others. Returns true if that was the last pane and koi should quit.
+    fn pane_exited(&mut self, pane_id: usize) -> bool {
         self.needs_redraw = true;
+        self.auto_scroll_delta = 0;
+        self.mouse_left_pressed = false;
+        self.divider_drag = None;
+        self.scrollbar_drag = None;
+        self.tab_drag = None;
+        if self.pending_paste.as_ref().is_some_and(|p| p.pane_id == pane_id) {
+            self.pending_paste = None;
+        }
+        if self.tab_manager.close_pane_by_id(pane_id) {
+            return true;
+        }
+        // Resize surviving panes to fill freed space.
+        let cw = self.renderer.cell_width();
+        let ch = self.renderer.cell_height();
+        let size = self.window.inner_size();
+        let tab_bar_h = if self.tab_manager.count() > 1 { ch } else { 0.0 };
+        let h = size.height as f32 - tab_bar_h;
+        self.tab_manager.resize_all(size.width as f32, h, cw, ch);
+        self.fit_panes();
         self.window.request_redraw();
+        false
+    }
+
+    /// With `window.auto_zoom_when_small`, zoom the active pane of any tab
+    /// whose split layout no longer fits the window, and unzoom those that
+    /// fit again. Then find the divider under the pointer afresh: the one
+    /// highlighted may have moved or gone. Call after anything that changes
+    /// pane sizes.
+    fn fit_panes(&mut self) {
+        if self.config.window.auto_zoom_when_small {
+            let (vw, vh) = self.pane_viewport();
+            let (cw, ch) = (self.renderer.cell_width(), self.renderer.cell_height());
+            if self.tab_manager.fit_auto_zoom(vw, vh, cw, ch) {
+                self.tab_manager.resize_all(vw, vh, cw, ch);
+            }
+        }
+        self.update_divider_hover();
     }
 
     fn handle_cursor_moved(&mut self, position: winit::dpi::PhysicalPosition<f64>) {
         self.cursor_pos = (position.x, position.y);
 
+        let cell = (self.renderer.cell_width(), self.renderer.cell_height());
+        let point = (position.x as f32, position.y as f32);
+        if self.context_menu.as_mut().is_some_and(|menu| menu.hover(point, cell)) {
+            self.window.request_redraw();
+        }
+        self.update_hovered_link();
+        self.update_divider_hover();
+
         // Skip expensive layout/lock work when not dragging.
         if !self.mouse_left_pressed {
+            self.report_mouse_motion();
             return;
         }
         self.needs_redraw = true;
@@ -263,6 +708,11 @@ impl KoiState {
         let cx = self.cursor_pos.0 as f32;
         let cy = self.cursor_pos.1 as f32 - tab_bar_h;
 
+        if self.tab_drag.is_some() {
+            self.drag_tab(cx);
+            return;
+        }
+
         // Handle divider drag — update ratio and resize panes.
         if let Some(ref drag) = self.divider_drag {
             if drag.span < 1.0 {
@@ -281,6 +731,10 @@ impl KoiState {
             self.window.request_redraw();
             return;
         }
+        if let Some(drag) = self.scrollbar_drag.as_ref() {
+            self.drag_scrollbar(drag.pane_id, cy - drag.grab);
+            return;
+        }
         let size = self.window.inner_size();
         let viewport_h = (size.height as f32 - tab_bar_h).max(0.0);
         let layouts = self.tab_manager.active_layouts(size.width as f32, viewport_h);
@@ -318,45 +772,85 @@ impl KoiState {
             alacritty_terminal::index::Side::Left
         };
 
-        // Single lock: check mode + update selection in one scope.
+        // A drag in a mouse-mode app is the app's, not a selection.
+        if self.report_mouse_motion() {
+            self.window.request_redraw();
+            return;
+        }
+
+        // Single lock: update selection in one scope.
         if let Some(pane) = self.tab_manager.active_pane() {
-            use alacritty_terminal::term::TermMode;
             let mut term = pane.term.lock();
-            let mode = term.mode();
-            let mouse_mode = mode.intersects(TermMode::MOUSE_MODE);
-            let sgr = mode.contains(TermMode::SGR_MOUSE);
-            let motion = mode.contains(TermMode::MOUSE_MOTION)
-                || mode.contains(TermMode::MOUSE_DRAG);
-
-            if mouse_mode && motion && sgr {
-                drop(term);
-                pane.notifier.send_bytes(
-                    format!("\x1b[<32;{};{}M", col, line as usize + 1).into_bytes(),
-                );
-            } else {
-                // Scroll immediately if OOB, then update selection.
-                if self.auto_scroll_delta != 0 {
-                    use alacritty_terminal::grid::Scroll;
-                    term.scroll_display(Scroll::Delta(self.auto_scroll_delta));
-                }
-                // Convert viewport-relative point to grid coordinates
-                // (must be after scroll_display so display_offset is current).
-                let display_offset = term.grid().display_offset();
-                let point = alacritty_terminal::term::viewport_to_point(
-                    display_offset,
-                    alacritty_terminal::index::Point::new(
-                        viewport_line,
-                        alacritty_terminal::index::Column(grid_col),
-                    ),
-                );
-                if let Some(ref mut sel) = term.selection {
-                    sel.update(point, side);
+            // Scroll immediately if OOB, then update selection.
+            if self.auto_scroll_delta != 0 {
+                use alacritty_terminal::grid::Scroll;
+                term.scroll_display(Scroll::Delta(self.auto_scroll_delta));
+                if let Some(id) = self.tab_manager.active_pane_id() {
+                    self.scroll_origin.scrolled(id, scroll::ScrollOrigin::AutoScroll);
                 }
             }
+            // Convert viewport-relative point to grid coordinates
+            // (must be after scroll_display so display_offset is current).
+            let display_offset = term.grid().display_offset();
+            let point = alacritty_terminal::term::viewport_to_point(
+                display_offset,
+                alacritty_terminal::index::Point::new(
+                    viewport_line,
+                    alacritty_terminal::index::Column(grid_col),
+                ),
+            );
+            if let Some(ref mut sel) = term.selection {
+                sel.update(point, side);
+            }
         }
         self.window.request_redraw();
     }
 
+    /// Report pointer motion to the active pane's app if it asked for it:
+    /// drags under DECSET 1002, any motion under 1003. Returns whether the
+    /// pane is in mouse mode at all, in which case the motion belongs to
+    /// the app rather than to a selection.
+    fn report_mouse_motion(&mut self) -> bool {
+        use alacritty_terminal::term::TermMode;
+        let Some(pane) = self.tab_manager.active_pane() else { return false };
+        let mode = *pane.term.lock().mode();
+        if !mode.intersects(TermMode::MOUSE_MODE) {
+            self.last_motion_cell = None;
+            return false;
+        }
+        let held = if self.mouse_left_pressed { Some(mouse::BUTTON_LEFT) } else { self.mouse_button_held };
+        let button = match held {
+            Some(button) if mode.intersects(TermMode::MOUSE_DRAG | TermMode::MOUSE_MOTION) => button,
+            // Plain motion only over the pane itself, not clamped to its edge.
+            None if mode.contains(TermMode::MOUSE_MOTION)
+                && self.pane_under_cursor() == self.tab_manager.active_pane_id() =>
+            {
+                mouse::NO_BUTTON
+            }
+            _ => return true,
+        };
+        let Some(hit) = self.mouse_hit() else { return true };
+        if self.last_motion_cell.replace((hit.col, hit.line)) == Some((hit.col, hit.line)) {
+            return true;
+        }
+        let report = MouseReport {
+            button: button + mouse::MOTION,
+            col: hit.col,
+            line: hit.line,
+            pressed: true,
+            modifiers: self.mouse_modifiers(),
+        };
+        if let Some(pane) = self.tab_manager.active_pane() {
+            let term = pane.term.lock();
+            let bytes = mouse::encode(report, mode, term.columns(), term.screen_lines());
+            drop(term);
+            if let Some(bytes) = bytes {
+                pane.send_bytes(bytes);
+            }
+        }
+        true
+    }
+
     fn handle_mouse_press(&mut self) {
         self.mouse_left_pressed = true;
         self.needs_redraw = true;
@@ -369,6 +863,24 @@ impl KoiState {
             return;
         }
 
+        // Dismiss command palette on click
+        if self.palette.is_some() {
+            self.dismiss_palette();
+            self.window.request_redraw();
+            return;
+        }
+
+        // A click on a menu entry runs it; anywhere else just closes the menu.
+        if let Some(menu) = self.context_menu.take() {
+            let cell = (self.renderer.cell_width(), self.renderer.cell_height());
+            let point = (self.cursor_pos.0 as f32, self.cursor_pos.1 as f32);
+            if let Some(i) = menu.entry_at(point, cell) {
+                self.run_command(context_menu::ENTRIES[i].command);
+            }
+            self.window.request_redraw();
+            return;
+        }
+
         let size = self.window.inner_size();
         let cw = self.renderer.cell_width();
         let ch = self.renderer.cell_height();
@@ -377,26 +889,33 @@ impl KoiState {
         let cx = self.cursor_pos.0 as f32;
         let raw_cy = self.cursor_pos.1 as f32;
 
-        // Tab bar click: switch tabs. Don't count toward multi-click selection.
+        // Tab bar click: close or switch tabs, and maybe start dragging the
+        // tab. Don't count toward multi-click selection.
         if tab_bar_h > 0.0 && raw_cy < tab_bar_h {
-            let tab_width = size.width as f32 / tab_count as f32;
-            let idx = (cx / tab_width) as usize;
-            let current = self.tab_manager.active_index();
-            if idx < tab_count && idx != current {
-                self.tab_animation = Some(TabAnimation {
-                    start: std::time::Instant::now(),
-                    direction: if idx > current { 1.0 } else { -1.0 },
-                });
-                self.tab_manager.goto_tab(idx);
-                self.window.request_redraw();
+            match renderer::tab_bar::hit(cx, tab_count, size.width as f32, cw) {
+                Some(renderer::tab_bar::TabHit::Close(idx)) => self.close_tab(idx),
+                Some(renderer::tab_bar::TabHit::Tab(idx)) => {
+                    let current = self.tab_manager.active_index();
+                    if idx != current {
+                        self.tab_animation = Some(TabAnimation {
+                            start: std::time::Instant::now(),
+                            direction: if idx > current { 1.0 } else { -1.0 },
+                        });
+                        self.tab_manager.goto_tab(idx);
+                        self.window.request_redraw();
+                    }
+                    self.tab_drag = Some(TabDrag { index: idx, origin: idx, start_x: cx, dragging: false });
+                }
+                None => {}
             }
             return;
         }
 
-        // Track multi-click: double-click = word, triple-click = line.
+        // Track multi-click: double-click = word, triple-click = line; a
+        // fourth click keeps the line rather than starting over.
         let now = std::time::Instant::now();
         if now.duration_since(self.last_click_time).as_millis() < 400 {
-            self.click_count = (self.click_count % 3) + 1;
+            self.click_count = (self.click_count % 4) + 1;
         } else {
             self.click_count = 1;
         }
@@ -405,37 +924,51 @@ impl KoiState {
         let cy = raw_cy - tab_bar_h;
         let viewport_h = (size.height as f32 - tab_bar_h).max(0.0);
 
-        // Check if cursor is on a divider (4px threshold).
-        let dividers = self.tab_manager.active_dividers(size.width as f32, viewport_h);
-        const THRESHOLD: f32 = 4.0;
-        for div in &dividers {
-            let (along, perp) = match div.split {
-                panes::Split::Vertical => (cx, cy),
-                panes::Split::Horizontal => (cy, cx),
-            };
-            if (along - div.position).abs() <= THRESHOLD
-                && perp >= div.perp_start
-                && perp <= div.perp_end
-            {
-                self.divider_drag = Some(DividerDrag {
-                    path: div.path.clone(),
-                    split: div.split,
-                    origin: div.origin,
-                    span: div.span,
-                });
+        // Muted-bell badge click: unmute that pane.
+        let badge_w = BELL_MUTED_BADGE.len() as f32 * cw;
+        let badge_pane = self
+            .tab_manager
+            .active_layouts(size.width as f32, viewport_h)
+            .into_iter()
+            .find(|l| cx >= l.x && cx < l.x + badge_w && cy >= l.y && cy < l.y + ch)
+            .map(|l| l.pane_id);
+        if let Some(pane) = badge_pane.and_then(|id| self.tab_manager.pane_mut(id)) {
+            if pane.bell.is_muted() {
+                pane.bell.unmute();
+                self.window.request_redraw();
                 return;
             }
         }
 
+        // Grabbing a pane's scrollbar thumb scrolls it by dragging.
+        let layouts = self.tab_manager.active_layouts(size.width as f32, viewport_h);
+        let grabbed = layouts.iter().find_map(|layout| {
+            let thumb = self.scrollbar_thumb(layout)?;
+            thumb.hit(cx, cy).then_some(ScrollbarDrag { pane_id: layout.pane_id, grab: cy - thumb.y })
+        });
+        if let Some(drag) = grabbed {
+            self.last_scroll = Some((drag.pane_id, std::time::Instant::now()));
+            self.scrollbar_drag = Some(drag);
+            return;
+        }
+
+        let dividers = self.tab_manager.active_dividers(size.width as f32, viewport_h);
+        let grab = divider::DividerStyle::scaled(self.window.scale_factor()).grab;
+        if let Some(div) = divider::hit(&dividers, cx, cy, grab) {
+            self.divider_drag = Some(DividerDrag {
+                path: div.path.clone(),
+                split: div.split,
+                origin: div.origin,
+                span: div.span,
+            });
+            return;
+        }
+
         let layouts = self.tab_manager.active_layouts(size.width as f32, viewport_h);
 
         for layout in &layouts {
-            if cx >= layout.x
-                && cx < layout.x + layout.width
-                && cy >= layout.y
-                && cy < layout.y + layout.height
-            {
-                self.tab_manager.focus_pane(layout.pane_id);
+            if layout.contains(cx, cy) {
+                self.change_focus(|tabs| tabs.focus_pane(layout.pane_id));
                 let col = ((cx - layout.x) / cw).max(0.0) as usize + 1;
                 let line = ((cy - layout.y) / ch).max(0.0) as usize + 1;
 
@@ -451,12 +984,12 @@ impl KoiState {
                     use alacritty_terminal::term::TermMode;
                     let mut term = pane.term.lock();
 
+                    // Option+drag (Alt elsewhere) selects a rectangle, even
+                    // with Cmd/Ctrl held too.
+                    let block = self.modifiers.alt_key();
+
                     // Cmd+click (macOS) / Ctrl+click (Windows/Linux): open URL.
-                    #[cfg(target_os = "macos")]
-                    let open_url_click = self.modifiers.super_key();
-                    #[cfg(not(target_os = "macos"))]
-                    let open_url_click = self.modifiers.control_key();
-                    if open_url_click {
+                    if self.link_modifier_held() && !block {
                         let display_offset = term.grid().display_offset();
                         let point = alacritty_terminal::term::viewport_to_point(
                             display_offset,
@@ -465,40 +998,34 @@ impl KoiState {
                                 alacritty_terminal::index::Column(grid_col),
                             ),
                         );
-                        if let Some(url) = extract_url_at(&*term, point) {
+                        if let Some(link) = links::link_at(&*term, point) {
                             drop(term);
-                            #[cfg(target_os = "macos")]
-                            let _ = std::process::Command::new("open").arg(&url).spawn();
-                            #[cfg(target_os = "windows")]
-                            let _ = std::process::Command::new("cmd")
-                                .args(["/c", "start", "", &url])
-                                .spawn();
-                            #[cfg(all(unix, not(target_os = "macos")))]
-                            let _ = std::process::Command::new("xdg-open").arg(&url).spawn();
+                            self.window.open_url(&link.uri);
                             self.window.request_redraw();
                             return;
                         }
                     }
 
-                    let mode = term.mode();
+                    let mode = *term.mode();
                     let mouse_mode = mode.intersects(TermMode::MOUSE_MODE);
-                    let sgr = mode.contains(TermMode::SGR_MOUSE);
                     log::debug!(
-                        "click col={} line={} mode={:?} mouse_mode={} sgr={}",
-                        col, line, mode, mouse_mode, sgr
+                        "click col={} line={} mode={:?} mouse_mode={}",
+                        col, line, mode, mouse_mode
                     );
-                    if mouse_mode && sgr {
-                        drop(term);
-                        pane.notifier.send_bytes(
-                            format!("\x1b[<0;{};{}M", col, line).into_bytes(),
-                        );
-                    } else if mouse_mode {
-                        // X10/normal mouse protocol: CSI M cb cx cy (each byte offset by 32).
+                    if mouse_mode {
+                        let report = MouseReport {
+                            button: mouse::BUTTON_LEFT,
+                            col,
+                            line,
+                            pressed: true,
+                            modifiers: self.mouse_modifiers(),
+                        };
+                        let bytes = mouse::encode(report, mode, term.columns(), term.screen_lines());
                         drop(term);
-                        let cb = (0u8).saturating_add(32);
-                        let cx = (col as u8).saturating_add(32);
-                        let cy = (line as u8).saturating_add(32);
-                        pane.notifier.send_bytes(vec![0x1b, b'[', b'M', cb, cx, cy]);
+                        self.last_motion_cell = Some((col, line));
+                        if let Some(bytes) = bytes {
+                            pane.send_bytes(bytes);
+                        }
                     } else {
                         let display_offset = term.grid().display_offset();
                         let point = alacritty_terminal::term::viewport_to_point(
@@ -508,11 +1035,7 @@ impl KoiState {
                                 alacritty_terminal::index::Column(grid_col),
                             ),
                         );
-                        let sel_type = match self.click_count {
-                            2 => alacritty_terminal::selection::SelectionType::Semantic,
-                            3 => alacritty_terminal::selection::SelectionType::Lines,
-                            _ => alacritty_terminal::selection::SelectionType::Simple,
-                        };
+                        let sel_type = selection::click_type(self.click_count, block);
                         term.selection = Some(alacritty_terminal::selection::Selection::new(
                             sel_type,
                             point,
@@ -529,222 +1052,1303 @@ impl KoiState {
     fn handle_mouse_release(&mut self) {
         self.mouse_left_pressed = false;
         self.auto_scroll_delta = 0;
+        self.scroll_origin.drag_ended();
         self.divider_drag = None;
+        self.update_divider_hover();
+        self.scrollbar_drag = None;
+        if self.tab_drag.is_some() {
+            // Letting go off the tab bar puts the tab back.
+            let tab_bar_h = self.renderer.cell_height();
+            if self.cursor_pos.1 as f32 >= tab_bar_h || self.cursor_pos.1 < 0.0 {
+                self.cancel_tab_drag();
+            }
+            self.tab_drag = None;
+            self.window.request_redraw();
+            return;
+        }
         if let Some(pane) = self.tab_manager.active_pane() {
             use alacritty_terminal::term::TermMode;
-            let term = pane.term.lock();
-            let mode = term.mode();
+            let mut term = pane.term.lock();
+            let mode = *term.mode();
             let mouse_mode = mode.intersects(TermMode::MOUSE_MODE);
-            let sgr = mode.contains(TermMode::SGR_MOUSE);
-            // Auto-copy selection to clipboard on mouse release.
-            if let Some(text) = term.selection_to_string() {
+            // Auto-copy selection to clipboard on mouse release, and keep it
+            // for middle-click paste.
+            if let Some(text) = selection::selection_text(&mut term) {
                 if !text.is_empty() {
                     clipboard_copy(&text);
+                    self.primary_selection = Some(text);
                 }
             }
+            let release = mouse_mode
+                .then(|| self.mouse_hit())
+                .flatten()
+                .and_then(|hit| {
+                    let report = MouseReport {
+                        button: mouse::BUTTON_LEFT,
+                        col: hit.col,
+                        line: hit.line,
+                        pressed: false,
+                        modifiers: self.mouse_modifiers(),
+                    };
+                    mouse::encode(report, mode, term.columns(), term.screen_lines())
+                });
             drop(term);
-            if mouse_mode && sgr {
-                if let Some(hit) = self.mouse_hit() {
-                    pane.notifier.send_bytes(
-                        format!("\x1b[<0;{};{}m", hit.col, hit.line).into_bytes(),
-                    );
-                }
+            if let Some(bytes) = release {
+                pane.send_bytes(bytes);
             }
         }
     }
 
     /// Handle right-click (button=2) and middle-click (button=1).
-    /// In mouse mode: forward SGR events to the PTY (tmux, vim, etc.).
-    /// Outside mouse mode: right-click pastes, middle-click pastes.
+    /// In mouse mode: report them to the app (tmux, vim, etc.).
+    /// Outside mouse mode: right-click pastes the clipboard, middle-click
+    /// the last selection (or the clipboard, if nothing was selected yet).
+    /// A translated Ctrl+click (`secondary_click`) arrives here as a right click.
     fn handle_other_mouse_button(&mut self, button: u8, state: ElementState) {
         if let Some(pane) = self.tab_manager.active_pane() {
             use alacritty_terminal::term::TermMode;
             let term = pane.term.lock();
-            let mouse_mode = term.mode().intersects(TermMode::MOUSE_MODE);
-            let sgr = term.mode().contains(TermMode::SGR_MOUSE);
+            let mode = *term.mode();
+            let mouse_mode = mode.intersects(TermMode::MOUSE_MODE);
+            let grid = (term.columns(), term.screen_lines());
             drop(term);
 
-            if mouse_mode && sgr {
+            if mouse_mode {
                 if let Some(hit) = self.mouse_hit() {
-                    let suffix = if state == ElementState::Pressed { 'M' } else { 'm' };
-                    pane.notifier.send_bytes(
-                        format!("\x1b[<{};{};{}{}", button, hit.col, hit.line, suffix)
-                            .into_bytes(),
-                    );
+                    let pressed = state == ElementState::Pressed;
+                    let mut report = MouseReport {
+                        button,
+                        col: hit.col,
+                        line: hit.line,
+                        pressed,
+                        modifiers: self.mouse_modifiers(),
+                    };
+                    if self.secondary_click {
+                        report = mouse::as_secondary_click(report);
+                    }
+                    if let Some(bytes) = mouse::encode(report, mode, grid.0, grid.1) {
+                        pane.send_bytes(bytes);
+                    }
+                    self.mouse_button_held = pressed.then_some(report.button);
+                    self.last_motion_cell = Some((hit.col, hit.line));
                 }
+            } else if state == ElementState::Pressed && button == mouse::BUTTON_RIGHT {
+                // Outside mouse mode: right-click opens the menu
+                let size = self.window.inner_size();
+                let cell = (self.renderer.cell_width(), self.renderer.cell_height());
+                let point = (self.cursor_pos.0 as f32, self.cursor_pos.1 as f32);
+                self.context_menu = Some(ContextMenu::open(point, (size.width as f32, size.height as f32), cell));
+                self.window.request_redraw();
+            } else if state == ElementState::Pressed && self.context_menu.take().is_some() {
+                // Clicking elsewhere only closes the menu.
+                self.window.request_redraw();
             } else if state == ElementState::Pressed {
-                // Outside mouse mode: paste on right-click or middle-click
-                if let Some(text) = clipboard_paste() {
-                    use alacritty_terminal::term::TermMode;
-                    let bracketed = pane.term.lock().mode()
-                        .contains(TermMode::BRACKETED_PASTE);
-                    if bracketed {
-                        let sanitized = text
-                            .replace("\x1b[200~", "")
-                            .replace("\x1b[201~", "");
-                        let mut bytes = Vec::new();
-                        bytes.extend_from_slice(b"\x1b[200~");
-                        bytes.extend_from_slice(sanitized.as_bytes());
-                        bytes.extend_from_slice(b"\x1b[201~");
-                        pane.notifier.send_input(&bytes);
-                    } else {
-                        pane.notifier.send_input(text.as_bytes());
-                    }
+                // Outside mouse mode: paste the last selection on middle-click
+                if let Some(text) = self.primary_selection.clone().or_else(clipboard_paste_any) {
+                    self.paste(text, true);
                 }
             }
         }
     }
 
-    /// Handle keyboard input. Returns `true` if the application should exit.
-    fn handle_keyboard(
-        &mut self,
-        event: winit::event::KeyEvent,
-        event_proxy: &EventProxy,
-        font_size: &mut f32,
-        scale: f32,
-    ) -> bool {
-        if event.state != ElementState::Pressed {
-            return false;
+    /// Paste `text` into the active pane. With `confirm`, text that would
+    /// run commands line by line in a shell waits for the user to say how
+    /// to send it (`paste.confirm_multiline`).
+    fn paste(&mut self, text: String, confirm: bool) {
+        use alacritty_terminal::term::TermMode;
+        let Some(pane_id) = self.tab_manager.active_pane_id() else { return };
+        let Some(pane) = self.tab_manager.pane(pane_id) else { return };
+        let alt_screen = pane.term.lock().mode().contains(TermMode::ALT_SCREEN);
+        if confirm
+            && self.config.paste.confirm_multiline
+            && !alt_screen
+            && paste::PendingPaste::needs_confirmation(&text)
+        {
+            self.pending_paste = Some(paste::PendingPaste { pane_id, text });
+            self.needs_redraw = true;
+            self.window.request_redraw();
+            return;
         }
+        self.send_paste(pane_id, &text);
+    }
 
-        // Any keypress cancels an in-progress divider drag.
-        self.divider_drag = None;
-        self.needs_redraw = true;
-
-        // Reset cursor blink so it's visible while typing
-        self.cursor_blink = std::time::Instant::now();
+    /// Send `text` to `pane_id` as a paste, bracketed if the app asked;
+    /// with broadcast on, to each pane in its tab, bracketed as each asked.
+    fn send_paste(&mut self, pane_id: usize, text: &str) {
+        use alacritty_terminal::term::TermMode;
+        for id in self.tab_manager.input_panes(pane_id) {
+            let Some(pane) = self.tab_manager.pane(id) else { continue };
+            let bracketed = pane.term.lock().mode().contains(TermMode::BRACKETED_PASTE);
+            self.start_paste(id, paste::prepare_paste(text, bracketed));
+        }
+    }
 
-        let shift_pressed = self.modifiers.shift_key();
-        let ctrl_pressed = self.modifiers.control_key();
-        let alt_pressed = self.modifiers.alt_key();
-        // "Primary" modifier for koi's own shortcuts: Cmd on macOS,
-        // bare Ctrl on Windows/Linux. Ctrl+C / Ctrl+D shell-signal conflicts
-        // are handled per-shortcut below (Ctrl+C is only copy if there's a
-        // text selection, else passes through as SIGINT).
-        #[cfg(target_os = "macos")]
-        let super_pressed = self.modifiers.super_key();
-        #[cfg(not(target_os = "macos"))]
-        let super_pressed = ctrl_pressed;
+    /// Send a paste to `pane_id`. What the shell can't take straight away
+    /// is fed from `about_to_wait`, after any paste already going to it.
+    fn start_paste(&mut self, pane_id: usize, chunks: Vec<Vec<u8>>) {
+        self.pastes.push(paste::PasteFeeder::new(pane_id, chunks));
+        self.feed_pastes();
+    }
 
-        // --- About overlay: any key dismisses ---
-        if self.show_about {
-            self.show_about = false;
-            self.about_opened_at = None;
-            self.window.request_redraw();
-            return false;
+    /// Send what the user typed to `pane_id`. With a paste still on its way
+    /// there, the keys follow it rather than landing in the middle.
+    fn send_typed(&mut self, pane_id: usize, bytes: &[u8]) {
+        if let Some(feeder) = self.pastes.iter_mut().rev().find(|f| f.pane_id == pane_id) {
+            feeder.push(bytes.to_vec());
+        } else if let Some(pane) = self.tab_manager.pane(pane_id) {
+            pane.send_input(bytes);
         }
+    }
 
-        // --- Search mode input handling ---
-        if self.search.is_some() {
-            match event.logical_key {
-                Key::Named(NamedKey::Escape) => {
-                    self.search = None;
-                    self.window.request_redraw();
-                    return false;
+    /// Move each pane's oldest paste along. Returns true while any remain.
+    fn feed_pastes(&mut self) -> bool {
+        let shown = |pastes: &[paste::PasteFeeder]| pastes.iter().any(|p| p.progress().is_some());
+        let was_shown = shown(&self.pastes);
+        let mut waiting = Vec::new();
+        let tab_manager = &self.tab_manager;
+        self.pastes.retain_mut(|feeder| {
+            if waiting.contains(&feeder.pane_id) {
+                return true;
+            }
+            let Some(pane) = tab_manager.pane(feeder.pane_id) else { return false };
+            match feeder.feed(pane) {
+                paste::Feed::Pending => {
+                    waiting.push(feeder.pane_id);
+                    true
                 }
-                Key::Named(NamedKey::Enter) => {
-                    // Enter: next match. Shift+Enter: previous match.
-                    if let Some(ref mut search) = self.search {
-                        if !search.matches.is_empty() {
-                            if shift_pressed {
-                                search.current = search.current.checked_sub(1)
-                                    .unwrap_or(search.matches.len() - 1);
-                            } else {
-                                search.current = (search.current + 1) % search.matches.len();
-                            }
-                            // Scroll to make the current match visible.
-                            let (match_line, _) = search.matches[search.current];
-                            if let Some(pane) = self.tab_manager.active_pane() {
-                                use alacritty_terminal::grid::{Dimensions, Scroll};
-                                let mut term = pane.term.lock();
-                                let screen_lines = term.screen_lines() as i32;
-                                let target_offset = -(match_line.0) - screen_lines / 2;
-                                let target_offset = target_offset.max(0) as usize;
-                                let current_offset = term.grid().display_offset();
-                                let delta = target_offset as i32 - current_offset as i32;
-                                if delta != 0 {
-                                    term.scroll_display(Scroll::Delta(delta));
-                                }
-                            }
-                        }
-                    }
+                paste::Feed::Done => false,
+                paste::Feed::Closed => {
+                    log::warn!("Pane {}: paste cut short, the shell's PTY is gone", feeder.pane_id);
+                    false
+                }
+            }
+        });
+        if was_shown || shown(&self.pastes) {
+            self.needs_redraw = true;
+            self.window.request_redraw();
+        }
+        !self.pastes.is_empty()
+    }
+
+    /// Run a bound shortcut. Returns `Some(exit)` if the key was consumed, or
+    /// None to let it through to the shell.
+    fn run_command(
+        &mut self,
+        command: bindings::Command,
+    ) -> Option<Exit> {
+        use bindings::Command;
+        match command {
+            // With no prompts to jump between (a full-screen app, or a shell
+            // without integration), the arrows are the app's.
+            Command::Action(Action::PrevPrompt | Action::NextPrompt)
+                if self
+                    .tab_manager
+                    .active_pane()
+                    .is_none_or(|pane| prompts::prompt_lines(&pane.term.lock()).is_empty()) =>
+            {
+                return None;
+            }
+            Command::Action(action) => return Some(self.perform_action(action)),
+            Command::OpenPalette => {
+                self.palette = Some(PaletteState::with_actions(&self.themes));
+                self.palette_theme_origin = Some(self.theme);
+                self.window.request_redraw();
+            }
+            Command::NewWindow => self.new_window = true,
+            Command::GotoTab(index) => {
+                self.tab_manager.goto_tab(index);
+                self.window.request_redraw();
+            }
+            Command::PrevTab => {
+                self.tab_animation = Some(TabAnimation {
+                    start: std::time::Instant::now(),
+                    direction: -1.0,
+                });
+                self.tab_manager.prev_tab();
+                self.window.request_redraw();
+            }
+            Command::NextTab => {
+                self.tab_animation = Some(TabAnimation {
+                    start: std::time::Instant::now(),
+                    direction: 1.0,
+                });
+                self.tab_manager.next_tab();
+                self.window.request_redraw();
+            }
+            Command::FocusNextPane => {
+                self.change_focus(TabManager::focus_next_pane);
+                self.window.request_redraw();
+            }
+            Command::FocusPrevPane => {
+                self.change_focus(TabManager::focus_prev_pane);
+                self.window.request_redraw();
+            }
+            // Cmd+C (macOS) / Ctrl+C (win/linux): Copy selection to clipboard.
+            // On win/linux, only intercept when there IS a selection, so
+            // bare Ctrl+C without a selection still reaches the shell as SIGINT.
+            Command::Copy => {
+                let pane = self.tab_manager.active_pane()?;
+                let mut term = pane.term.lock();
+                if let Some(text) = selection::selection_text(&mut term) {
+                    clipboard_copy(&text);
+                    term.selection = None;
                     self.window.request_redraw();
-                    return false;
+                    return Some(Exit::Stay);
+                }
+                #[cfg(target_os = "macos")]
+                {
+                    term.selection = None;
+                    self.window.request_redraw();
+                }
+                #[cfg(not(target_os = "macos"))]
+                {
+                    // No selection on win/linux — fall through to shell.
+                    return None;
+                }
+            }
+            // Paste from clipboard (text, copied files, or image as temp file path)
+            Command::Paste | Command::PasteUnconfirmed => {
+                if let Some(text) = clipboard_paste_any() {
+                    self.paste(text, command == Command::Paste);
+                }
+            }
+            Command::ClearScreen => {
+                if let Some(pane) = self.tab_manager.active_pane() {
+                    let (cw, ch) = (self.renderer.cell_width(), self.renderer.cell_height());
+                    pane.clear(&self.tab_manager.term_config(), cw, ch);
+                    self.needs_redraw = true;
+                    self.window.request_redraw();
+                }
+            }
+            Command::ZoomIn => {
+                self.font_size = (self.font_size + 1.0).min(*window_state::FONT_SIZES.end());
+                self.rebuild_renderer(self.config.window.resize_on_font_change);
+            }
+            Command::ZoomOut => {
+                self.font_size = (self.font_size - 1.0).max(*window_state::FONT_SIZES.start());
+                self.rebuild_renderer(self.config.window.resize_on_font_change);
+            }
+            Command::ZoomReset => {
+                self.font_size = DEFAULT_FONT_SIZE;
+                self.rebuild_renderer(self.config.window.resize_on_font_change);
+            }
+            Command::Quit => return Some(Exit::App),
+        }
+        Some(Exit::Stay)
+    }
+
+    /// Switch to theme `index` of `themes`, with any selection colors from
+    /// the config.
+    fn set_theme(&mut self, index: usize) {
+        self.theme = index;
+        self.renderer.set_theme(self.config.selection.apply(self.themes.theme(index)));
+    }
+
+    /// With `window.follow_system_appearance`, switch to the light or dark
+    /// theme when the system's appearance has changed since last time.
+    fn follow_appearance(&mut self) {
+        if !self.config.window.follow_system_appearance || self.theme_pinned {
+            return;
+        }
+        let appearance = self.window.appearance();
+        if appearance.is_none() || appearance == self.appearance {
+            return;
+        }
+        self.appearance = appearance;
+        let name = match appearance {
+            Some(winit::window::Theme::Light) => &self.config.window.light_theme,
+            _ => &self.config.window.dark_theme,
+        };
+        match self.themes.find(name) {
+            Some(i) => self.set_theme(i),
+            None => log::warn!("No theme called {:?}", name),
+        }
+        self.needs_redraw = true;
+        self.window.request_redraw();
+    }
+
+    /// Live-preview the theme under the palette selection, or put this
+    /// window's own theme back when a non-theme entry is highlighted.
+    fn preview_palette_theme(&mut self) {
+        let selected = self.palette.as_ref().and_then(|p| p.selected_entry()).map(|e| e.action);
+        match selected {
+            Some(Action::SetTheme(i)) => self.set_theme(i),
+            _ => {
+                if let Some(origin) = self.palette_theme_origin {
+                    self.set_theme(origin);
+                }
+            }
+        }
+    }
+
+    /// Viewport below the tab bar, in physical pixels.
+    fn pane_viewport(&self) -> (f32, f32) {
+        let size = self.window.inner_size();
+        let tab_bar_h = if self.tab_manager.count() > 1 { self.renderer.cell_height() } else { 0.0 };
+        (size.width as f32, (size.height as f32 - tab_bar_h).max(0.0))
+    }
+
+    /// Follow the divider under the pointer, or the one being dragged:
+    /// redraw to highlight it and switch to a resize cursor over it.
+    fn update_divider_hover(&mut self) {
+        let hover = match self.divider_drag.as_ref() {
+            Some(drag) => Some((drag.path.clone(), drag.split)),
+            None => {
+                let (vw, vh) = self.pane_viewport();
+                let tab_bar_h = self.window.inner_size().height as f32 - vh;
+                let (x, y) = (self.cursor_pos.0 as f32, self.cursor_pos.1 as f32 - tab_bar_h);
+                let grab = divider::DividerStyle::scaled(self.window.scale_factor()).grab;
+                let dividers = self.tab_manager.active_dividers(vw, vh);
+                divider::hit(&dividers, x, y, grab).map(|d| (d.path.clone(), d.split))
+            }
+        };
+        if hover == self.divider_hover {
+            return;
+        }
+        let icon = hover.as_ref().map_or(CursorIcon::Default, |(_, split)| divider::cursor(*split));
+        self.window.set_cursor(icon);
+        self.divider_hover = hover;
+        self.window.request_redraw();
+    }
+
+    /// Enter resize mode on the divider nearest the active pane.
+    fn enter_resize_mode(&mut self) {
+        let (vw, vh) = self.pane_viewport();
+        let Some(tab) = self.tab_manager.active_tab() else { return };
+        if tab.pane_tree.is_zoomed() {
+            return;
+        }
+        let active_id = tab.pane_tree.active_pane_id();
+        let layouts = tab.pane_tree.calculate_layouts(vw, vh);
+        let Some(active) = layouts.iter().find(|l| l.pane_id == active_id) else { return };
+        let dividers = tab.pane_tree.collect_dividers(vw, vh);
+        let (index, ids) = (self.tab_manager.active_index(), tab.pane_tree.pane_ids());
+        self.resize_mode = resize_mode::ResizeMode::new(&dividers, active, index, ids);
+    }
+
+    /// The active tab's dividers, if resize mode still applies to it. Ends
+    /// the mode (keeping the current ratios) if the tab or its panes changed.
+    fn resize_mode_dividers(&mut self) -> Option<Vec<panes::DividerInfo>> {
+        let mode = self.resize_mode.as_ref()?;
+        let (vw, vh) = self.pane_viewport();
+        let tab = self.tab_manager.active_tab();
+        let current = tab.filter(|t| !t.pane_tree.is_zoomed()).map(|t| t.pane_tree.pane_ids());
+        if self.tab_manager.active_index() != mode.tab || current.as_ref() != Some(&mode.pane_ids) {
+            self.resize_mode = None;
+            return None;
+        }
+        Some(self.tab_manager.active_dividers(vw, vh))
+    }
+
+    /// Keys while in resize mode: arrows move the selected divider a cell at
+    /// a time, Tab picks another, Enter keeps the layout and Escape restores
+    /// the ratios from when the mode was entered.
+    fn on_resize_key(&mut self, key: keyboard::ResizeKey) {
+        use keyboard::ResizeKey;
+        let Some(dividers) = self.resize_mode_dividers() else { return };
+        if dividers.is_empty() {
+            // No divider left to select: nothing for the mode to do.
+            self.resize_mode = None;
+            return;
+        }
+        let Some(mode) = self.resize_mode.as_mut() else { return };
+        let (cw, ch) = (self.renderer.cell_width(), self.renderer.cell_height());
+        let divider = &dividers[mode.selected.min(dividers.len() - 1)];
+        let delta = match (key, divider.split) {
+            (ResizeKey::Left, panes::Split::Vertical) => -cw,
+            (ResizeKey::Right, panes::Split::Vertical) => cw,
+            (ResizeKey::Up, panes::Split::Horizontal) => -ch,
+            (ResizeKey::Down, panes::Split::Horizontal) => ch,
+            (ResizeKey::Cycle { backwards }, _) => {
+                mode.cycle(dividers.len(), backwards);
+                return;
+            }
+            (ResizeKey::Commit, _) => {
+                self.resize_mode = None;
+                return;
+            }
+            (ResizeKey::Revert, _) => {
+                let original = mode.original().to_vec();
+                self.resize_mode = None;
+                for (path, ratio) in original {
+                    self.tab_manager.set_split_ratio(&path, ratio);
+                }
+                0.0
+            }
+            _ => return,
+        };
+        if delta != 0.0 {
+            let ratio = resize_mode::step_ratio(divider, delta);
+            self.tab_manager.set_split_ratio(&divider.path, ratio);
+        }
+        let (vw, vh) = self.pane_viewport();
+        self.tab_manager.resize_active_tab(vw, vh, cw, ch);
+    }
+
+    /// One-line notice along the top when the window is too small for the
+    /// active tab's split layout: a zoom hint, or what auto-zoom did.
+    fn draw_too_small_warning(&mut self, tab_bar_height: f32, w: f32, viewport_h: f32) {
+        let (cw, ch) = (self.renderer.cell_width(), self.renderer.cell_height());
+        let Some(tree) = self.tab_manager.active_tab().map(|t| &t.pane_tree) else { return };
+        let count = tree.pane_count();
+        if count < 2 || self.tab_manager.active_layout_fits(w, viewport_h, cw, ch) {
+            return;
+        }
+        let text = if tree.is_auto_zoomed() {
+            format!(" Window too small for {count} panes \u{2014} zoomed until it grows ")
+        } else if !tree.is_zoomed() {
+            let zoom = Action::ToggleZoom.shortcut();
+            format!(" Window too small for {count} panes \u{2014} {zoom} zooms the active pane ")
+        } else {
+            return;
+        };
+        let b = self.renderer.theme().border;
+        let opts = TextOptions::clipped((w / cw) as usize);
+        self.renderer.draw_rect(0.0, tab_bar_height, w, ch, [b[0], b[1], b[2], 0.95]);
+        self.renderer.draw_text(0.0, tab_bar_height, &text, [1.0, 1.0, 1.0, 1.0], [0.0; 4], &opts);
+    }
+
+    /// Resize mode overlay: the selected divider in the accent color, each
+    /// pane's grid size, and a key hint along the bottom.
+    fn draw_resize_mode(&mut self, tab_bar_height: f32, w: f32, h: f32) {
+        let Some(dividers) = self.resize_mode_dividers() else { return };
+        let Some(mode) = self.resize_mode.as_ref() else { return };
+        let (cw, ch) = (self.renderer.cell_width(), self.renderer.cell_height());
+        let accent = self.renderer.theme().border;
+        let accent = [accent[0], accent[1], accent[2], 1.0];
+        if let Some(d) = dividers.get(mode.selected) {
+            let len = d.perp_end - d.perp_start;
+            match d.split {
+                panes::Split::Vertical => self.renderer.draw_rect(
+                    d.position - 2.0, d.perp_start + tab_bar_height, 4.0, len, accent,
+                ),
+                panes::Split::Horizontal => self.renderer.draw_rect(
+                    d.perp_start, d.position - 2.0 + tab_bar_height, len, 4.0, accent,
+                ),
+            }
+        }
+
+        let o = self.renderer.theme().overlay0;
+        let badge_bg = [o[0], o[1], o[2], 0.9];
+        let badge_fg = self.renderer.theme().fg4();
+        let (vw, vh) = self.pane_viewport();
+        for layout in self.tab_manager.active_layouts(vw, vh) {
+            let (cols, rows) = tabs::pane_grid_size(layout.width, layout.height, cw, ch);
+            let label = format!(" {cols}\u{d7}{rows} ");
+            let label_w = label.chars().count() as f32 * cw;
+            let x = layout.x + ((layout.width - label_w) / 2.0).max(0.0);
+            let y = layout.y + tab_bar_height + ((layout.height - ch) / 2.0).max(0.0);
+            self.renderer.draw_string(x, y, &label, badge_fg, badge_bg);
+        }
+
+        let b = self.renderer.theme().border;
+        let hint = " RESIZE \u{2014} arrows move the divider, Tab selects another, Enter keeps, Esc reverts ";
+        let opts = TextOptions::clipped((w / cw) as usize);
+        self.renderer.draw_rect(0.0, h - ch, w, ch, [b[0], b[1], b[2], 0.95]);
+        self.renderer.draw_text(0.0, h - ch, hint, [1.0, 1.0, 1.0, 1.0], [0.0; 4], &opts);
+    }
+
+    /// Panel along the bottom of the pane a held paste is for, styled like
+    /// the search bar: what to do on top, then the paste's first lines.
+    fn draw_paste_confirmation(&mut self, layouts: &[panes::PaneLayout], tab_bar_height: f32, w: f32) {
+        let Some(pending) = self.pending_paste.as_ref() else { return };
+        let (cw, ch) = (self.renderer.cell_width(), self.renderer.cell_height());
+        let (x, bottom, width) = match layouts.iter().find(|l| l.pane_id == pending.pane_id) {
+            Some(l) => (l.x, l.y + tab_bar_height + l.height, l.width),
+            None => (0.0, self.window.inner_size().height as f32, w),
+        };
+
+        let (lines, more) = pending.preview();
+        let count = lines.len() + more;
+        let noun = if count == 1 { "line" } else { "lines" };
+        let mut rows = vec![format!(
+            "Paste {count} {noun}? Enter to paste, e to edit single-line, Esc to cancel"
+        )];
+        rows.extend(lines.iter().map(|line| format!("  {line}")));
+        if more > 0 {
+            rows.push(format!("  \u{2026} {more} more"));
+        }
+
+        let top = bottom - rows.len() as f32 * ch;
+        let s0 = self.renderer.theme().surface0;
+        let bg = [s0[0], s0[1], s0[2], 0.95];
+        let fg = self.renderer.theme().fg4();
+        let dim = [fg[0], fg[1], fg[2], 0.7];
+        self.renderer.draw_rect(x, top, width, rows.len() as f32 * ch, bg);
+        let opts = TextOptions::clipped(((width - 8.0).max(0.0) / cw) as usize);
+        for (i, row) in rows.iter().enumerate() {
+            let color = if i == 0 { fg } else { dim };
+            self.renderer.draw_text(x + 8.0, top + i as f32 * ch, row, color, bg, &opts);
+        }
+    }
+
+    fn overlay_open(&self, overlay: keyboard::Overlay) -> bool {
+        match overlay {
+            keyboard::Overlay::Paste => self.pending_paste.is_some(),
+            keyboard::Overlay::Menu => self.context_menu.is_some(),
+            keyboard::Overlay::About => self.show_about,
+            keyboard::Overlay::Palette => self.palette.is_some(),
+            keyboard::Overlay::Rename => self.tab_rename.is_some(),
+            keyboard::Overlay::Resize => self.resize_mode.is_some(),
+            keyboard::Overlay::Search => self.search.is_some(),
+        }
+    }
+
+    /// Apply a key an overlay took; `repeat` if the key is being held.
+    /// A palette or context menu entry can close the window or quit.
+    fn on_overlay_key(&mut self, key: keyboard::OverlayKey, repeat: bool) -> Exit {
+        match key {
+            keyboard::OverlayKey::Paste(key) => self.on_paste_key(key),
+            keyboard::OverlayKey::Menu(key) => return self.on_menu_key(key),
+            keyboard::OverlayKey::About => {
+                self.show_about = false;
+                self.about_opened_at = None;
+            }
+            keyboard::OverlayKey::Palette(key) => return self.on_palette_key(key),
+            keyboard::OverlayKey::Rename(key) => self.on_rename_key(key),
+            keyboard::OverlayKey::Resize(key) => self.on_resize_key(key),
+            keyboard::OverlayKey::Search(key) => self.on_search_key(key, repeat),
+        }
+        Exit::Stay
+    }
+
+    fn on_paste_key(&mut self, key: keyboard::PasteKey) {
+        use keyboard::PasteKey;
+        if key == PasteKey::Swallow {
+            return;
+        }
+        let Some(pending) = self.pending_paste.take() else { return };
+        match key {
+            PasteKey::Paste => self.send_paste(pending.pane_id, &pending.text),
+            PasteKey::SingleLine => self.send_paste(pending.pane_id, &pending.single_line()),
+            PasteKey::Cancel | PasteKey::Swallow => {}
+        }
+        self.needs_redraw = true;
+    }
+
+    fn on_rename_key(&mut self, key: keyboard::RenameKey) {
+        use keyboard::RenameKey;
+        let Some(rename) = self.tab_rename.as_mut() else { return };
+        match key {
+            RenameKey::Insert(s) => rename.text.push_str(&s),
+            RenameKey::Pop => {
+                rename.text.pop();
+            }
+            RenameKey::Commit => {
+                // An empty name hands the tab back to the shell's titles.
+                let name = rename.text.trim();
+                let name = (!name.is_empty()).then(|| name.to_string());
+                self.tab_manager.set_user_title(rename.tab_id, name);
+                self.tab_rename = None;
+            }
+            RenameKey::Cancel => self.tab_rename = None,
+            RenameKey::Swallow => return,
+        }
+        self.needs_redraw = true;
+    }
+
+    fn on_menu_key(&mut self, key: keyboard::MenuKey) -> Exit {
+        use keyboard::MenuKey;
+        let Some(menu) = self.context_menu.as_mut() else { return Exit::Stay };
+        match key {
+            MenuKey::Close => self.context_menu = None,
+            MenuKey::Run => {
+                let command = menu.selected_command();
+                self.context_menu = None;
+                if let Some(command) = command {
+                    return self.run_command(command).unwrap_or(Exit::Stay);
+                }
+            }
+            MenuKey::Next => menu.select_next(),
+            MenuKey::Prev => menu.select_prev(),
+            MenuKey::Swallow => {}
+        }
+        Exit::Stay
+    }
+
+    fn on_palette_key(&mut self, key: keyboard::PaletteKey) -> Exit {
+        use keyboard::PaletteKey;
+        let Some(palette) = self.palette.as_mut() else { return Exit::Stay };
+        match key {
+            PaletteKey::Close => self.dismiss_palette(),
+            PaletteKey::Run => {
+                let action = palette.selected_entry().map(|e| e.action);
+                self.dismiss_palette();
+                if let Some(action) = action {
+                    return self.perform_action(action);
+                }
+            }
+            PaletteKey::Next => palette.select_next(),
+            PaletteKey::Prev => palette.select_prev(),
+            PaletteKey::Pop => palette.pop(),
+            PaletteKey::Insert(s) => palette.push_str(&s),
+            PaletteKey::Swallow => {}
+        }
+        self.preview_palette_theme();
+        self.needs_redraw = true;
+        Exit::Stay
+    }
+
+    fn on_search_key(&mut self, key: keyboard::SearchKey, repeat: bool) {
+        use keyboard::SearchKey;
+        // Step through the matches of what's been typed, even if a held
+        // key's search hasn't run yet.
+        if matches!(key, SearchKey::Next | SearchKey::Prev) && self.search.as_ref().is_some_and(|s| s.due.is_some()) {
+            self.rerun_search(true);
+        }
+        let Some(search) = self.search.as_mut() else { return };
+        match key {
+            SearchKey::Close => {
+                self.search = None;
+                self.scroll_origin.search_closed();
+            }
+            SearchKey::Next | SearchKey::Prev if !search.matches.is_empty() => {
+                let count = search.matches.len();
+                search.current = if key == SearchKey::Prev {
+                    (search.current + count - 1) % count
+                } else {
+                    (search.current + 1) % count
+                };
+                self.scroll_to_current_match();
+            }
+            SearchKey::Pop => {
+                search.query.pop();
+                self.query_changed(repeat, false);
+            }
+            SearchKey::Insert(s) => {
+                search.query.push_str(&s);
+                self.query_changed(repeat, true);
+            }
+            SearchKey::ToggleRegex => {
+                search.regex = !search.regex;
+                self.rerun_search(true);
+            }
+            SearchKey::Paste => {
+                // The query is a single line; take the clipboard's first.
+                let text = clipboard_paste().unwrap_or_default();
+                search.query.push_str(text.lines().next().unwrap_or_default());
+                self.rerun_search(true);
+            }
+            SearchKey::Next | SearchKey::Prev | SearchKey::Swallow => {}
+        }
+    }
+
+    /// Rerun the search for an edited query, or, while the key that edited
+    /// it is held, once the key has been quiet for `SEARCH_DEBOUNCE`.
+    fn query_changed(&mut self, repeat: bool, scroll: bool) {
+        match self.search.as_mut() {
+            Some(search) if repeat => search.due = Some(std::time::Instant::now() + SEARCH_DEBOUNCE),
+            _ => self.rerun_search(scroll),
+        }
+    }
+
+    /// Run a search put off by a held key if it's due. Returns when the
+    /// next one is.
+    fn run_due_search(&mut self, now: std::time::Instant) -> Option<std::time::Instant> {
+        let due = self.search.as_ref()?.due?;
+        if due > now {
+            return Some(due);
+        }
+        self.rerun_search(true);
+        self.needs_redraw = true;
+        self.window.request_redraw();
+        None
+    }
+
+    /// Search the searched pane for the current query again, starting over
+    /// at the first match, and scroll to it if `scroll`.
+    fn rerun_search(&mut self, scroll: bool) {
+        let Some(search) = self.search.as_mut() else { return };
+        let Some(pane) = self.tab_manager.pane(search.pane_id) else { return };
+        search.rerun(&*pane.term.lock());
+        if scroll {
+            self.scroll_to_current_match();
+        }
+    }
+
+    /// Redraw for new output: now if a frame is due, else once at the next
+    /// frame. A pane printing flat out reads far more often than the
+    /// display refreshes; drawing every read would pin a core.
+    fn request_output_redraw(&mut self) {
+        if self.output_redraw.is_some() {
+            return;
+        }
+        let now = std::time::Instant::now();
+        let due = self.frame_pacer.next_frame(now);
+        if due <= now {
+            self.window.request_redraw();
+        } else {
+            self.output_redraw = Some(due);
+        }
+    }
+
+    /// When the event loop next has work of its own with the window in the
+    /// background: the PTY thread check, the long-command poll, or a bell
+    /// flash running out. The foreground poll rides along with these.
+    fn background_wake(&self) -> std::time::Instant {
+        let mut wake = self.next_pty_check;
+        if self.config.activity.long_command_seconds > 0 {
+            wake = wake.min(self.next_command_poll);
+        }
+        self.bell_flashes.values().fold(wake, |wake, until| wake.min(*until))
+    }
+
+    /// The work between events: auto-scroll, polls, expiring bell flashes
+    /// and redraws held back for the frame rate. Returns when the loop must
+    /// next wake for this window, or None once its last pane has exited.
+    fn housekeeping(&mut self) -> Option<std::time::Instant> {
+        let pasting = self.feed_pastes();
+        let search_due = self.run_due_search(std::time::Instant::now());
+
+        // Auto-scroll during selection drag past viewport edge.
+        if self.mouse_left_pressed && self.auto_scroll_delta != 0 {
+            if let Some(pane) = self.tab_manager.active_pane() {
+                use alacritty_terminal::grid::{Dimensions, Scroll};
+                use alacritty_terminal::term::TermMode;
+                let mut term = pane.term.lock();
+
+                // Skip auto-scroll when the app owns mouse input (vim, tmux).
+                let mode = term.mode();
+                if mode.intersects(TermMode::MOUSE_MODE) {
+                    drop(term);
+                    self.auto_scroll_delta = 0;
+                } else {
+                    term.scroll_display(Scroll::Delta(self.auto_scroll_delta));
+                    if let Some(id) = self.tab_manager.active_pane_id() {
+                        self.scroll_origin.scrolled(id, scroll::ScrollOrigin::AutoScroll);
+                    }
+
+                    // Extend selection to the edge row.
+                    let ch = self.renderer.cell_height();
+                    let rows = {
+                        let tab_bar_h = if self.tab_manager.count() > 1 { ch } else { 0.0 };
+                        let size = self.window.inner_size();
+                        let viewport_h = (size.height as f32 - tab_bar_h).max(0.0);
+                        let layouts = self.tab_manager.active_layouts(size.width as f32, viewport_h);
+                        let active_id = self.tab_manager.active_tab()
+                            .map(|t| t.pane_tree.active_pane_id());
+                        active_id
+                            .and_then(|id| layouts.iter().find(|l| l.pane_id == id))
+                            .map(|l| (l.height / ch) as i32)
+                            .unwrap_or(1)
+                    };
+
+                    let edge_line = if self.auto_scroll_delta < 0 { 0usize } else { (rows - 1).max(0) as usize };
+                    let cols = term.grid().columns();
+                    let edge_col = if self.auto_scroll_delta < 0 { 0 } else { cols.saturating_sub(1) };
+                    let edge_side = if self.auto_scroll_delta < 0 {
+                        alacritty_terminal::index::Side::Left
+                    } else {
+                        alacritty_terminal::index::Side::Right
+                    };
+                    let display_offset = term.grid().display_offset();
+                    let point = alacritty_terminal::term::viewport_to_point(
+                        display_offset,
+                        alacritty_terminal::index::Point::new(
+                            edge_line,
+                            alacritty_terminal::index::Column(edge_col),
+                        ),
+                    );
+                    if let Some(ref mut sel) = term.selection {
+                        sel.update(point, edge_side);
+                    }
+                    drop(term);
+                }
+            }
+            self.needs_redraw = true;
+            self.window.request_redraw();
+            // Tick faster while auto-scrolling for smooth UX.
+            return Some(std::time::Instant::now() + std::time::Duration::from_millis(50));
+        }
+
+        let now = std::time::Instant::now();
+        // Set the window title held back by the rate limit.
+        if let Some(title) = self.window_title.flush(now) {
+            self.window.set_title(&title);
+        }
+
+        // Unmute panes whose bell flood has stopped.
+        let mut unmuted = false;
+        for pane in self.tab_manager.panes_mut() {
+            unmuted |= pane.bell.unmute_if_quiet(now);
+        }
+        if unmuted {
+            self.needs_redraw = true;
+            self.window.request_redraw();
+        }
+
+        // Flag background tabs whose long command just finished.
+        if self.config.activity.long_command_seconds > 0 && now >= self.next_command_poll {
+            self.next_command_poll = now + activity::POLL_INTERVAL;
+            let threshold = std::time::Duration::from_secs(self.config.activity.long_command_seconds);
+            if self.tab_manager.poll_commands(threshold, now) {
+                self.needs_redraw = true;
+                self.window.request_redraw();
+            }
+        }
+
+        // Rename untitled tabs after what they run.
+        if now >= self.next_foreground_poll {
+            self.next_foreground_poll = now + activity::POLL_INTERVAL;
+            if self.tab_manager.poll_foreground() {
+                self.needs_redraw = true;
+                self.window.request_redraw();
+            }
+        }
+
+        // Watch for PTY threads that stopped without their shell's exit
+        // being reported.
+        if now >= self.next_pty_check || self.tab_manager.input_lost() {
+            self.next_pty_check = now + PTY_CHECK_INTERVAL;
+            for (pane_id, end) in self.tab_manager.reap_pty_threads() {
+                self.needs_redraw = true;
+                self.window.request_redraw();
+                if end == tabs::PtyEnd::Exited && self.pane_exited(pane_id) {
+                    return None;
+                }
+            }
+        }
+
+        // Expire bell flashes and trigger a redraw to clear them.
+        let flashing = self.bell_flashes.len();
+        let now = std::time::Instant::now();
+        self.bell_flashes.retain(|_, until| now < *until);
+        if self.bell_flashes.len() != flashing {
+            self.needs_redraw = true;
+            self.window.request_redraw();
+        }
+
+        // Output held back to keep to the frame rate.
+        if self.output_redraw.is_some_and(|due| due <= now) {
+            self.output_redraw = None;
+            self.window.request_redraw();
+        }
+
+        // One last redraw to clear a finished focus pulse.
+        if self.focus_pulse.is_some_and(|p| p.alpha().is_none()) {
+            self.focus_pulse = None;
+            self.needs_redraw = true;
+            self.window.request_redraw();
+        }
+
+        // Animations draw once per display frame, however often the
+        // loop wakes; their progress comes from wall time.
+        if self.animating {
+            let now = std::time::Instant::now();
+            let mut next = self.frame_pacer.next_frame(now);
+            if next <= now {
+                self.needs_redraw = true;
+                self.window.request_redraw();
+                next = now + self.frame_pacer.interval();
+            }
+            return Some(next);
+        }
+
+        // Only redraw when cursor blink phase actually changes.
+        let blink_on = (self.cursor_blink.elapsed().as_millis() % 1000) < 500;
+        if self.cursor_blinks && blink_on != self.last_blink_on {
+            self.last_blink_on = blink_on;
+            self.needs_redraw = true;
+            self.window.request_redraw();
+        }
+        // Wake for a held window title, an unfinished paste or a put-off
+        // search rather than leaving them for the next blink tick. In
+        // the background there's no blink to keep, only housekeeping.
+        let now = std::time::Instant::now();
+        let tick = if pasting {
+            now + PASTE_RETRY
+        } else if self.window_focused {
+            now + std::time::Duration::from_millis(500)
+        } else {
+            self.background_wake()
+        };
+        let wake = self.window_title.due().map_or(tick, |due| due.min(tick));
+        let wake = search_due.map_or(wake, |due| due.min(wake));
+        let wake = self.output_redraw.map_or(wake, |due| due.min(wake));
+        Some(wake)
+    }
+
+    /// Tell apps that asked for focus reports (DECSET 1004) when their pane
+    /// gains or loses focus, whether by pane or tab switching or by the
+    /// window itself coming and going.
+    fn sync_focus_reports(&mut self) {
+        use alacritty_terminal::term::TermMode;
+        let focused = self.tab_manager.active_pane_id().filter(|_| self.window_focused);
+        let lost = std::mem::replace(&mut self.focus_reported, focused);
+        if lost == focused {
+            return;
+        }
+        for (pane_id, report) in [(lost, b"\x1b[O"), (focused, b"\x1b[I")] {
+            let Some(pane) = pane_id.and_then(|id| self.tab_manager.pane(id)) else { continue };
+            if pane.term.lock().mode().contains(TermMode::FOCUS_IN_OUT) {
+                pane.send_input(report);
+            }
+        }
+    }
+
+    /// Move an open search to the focused pane, or close it, per
+    /// `search.on_focus_change`. A search whose pane has closed closes too.
+    fn sync_search_focus(&mut self) {
+        let (Some(search), Some(active)) = (self.search.as_mut(), self.tab_manager.active_pane_id()) else {
+            return;
+        };
+        if self.tab_manager.pane(search.pane_id).is_none() {
+            self.search = None;
+            self.scroll_origin.search_closed();
+            return;
+        }
+        match search.focus_changed(active, self.config.search.on_focus_change) {
+            search::FocusChange::Unchanged => {}
+            search::FocusChange::Rebound => self.rerun_search(false),
+            search::FocusChange::Close => {
+                self.search = None;
+                self.scroll_origin.search_closed();
+            }
+        }
+    }
+
+    /// Opacity of `pane_id`'s scrollbar: full while its thumb is dragged,
+    /// fading after the user last scrolled it.
+    fn scrollbar_alpha(&self, pane_id: usize) -> Option<f32> {
+        if self.scrollbar_drag.as_ref().is_some_and(|d| d.pane_id == pane_id) {
+            return Some(1.0);
+        }
+        let (id, at) = self.last_scroll?;
+        (id == pane_id).then(|| scrollbar::alpha(at)).flatten()
+    }
+
+    /// The thumb of the scrollbar showing on the pane at `layout`, in
+    /// pane-area coordinates (below the tab bar).
+    fn scrollbar_thumb(&self, layout: &panes::PaneLayout) -> Option<scrollbar::Thumb> {
+        self.scrollbar_alpha(layout.pane_id)?;
+        let pane = self.tab_manager.pane(layout.pane_id)?;
+        let extent = scrollbar::Extent::of(&*pane.term.lock());
+        if !self.scroll_origin.show_scrollbar(layout.pane_id, extent.display_offset) {
+            return None;
+        }
+        scrollbar::thumb((layout.x, layout.y, layout.width, layout.height), extent)
+    }
+
+    /// Scroll `pane_id` so its scrollbar thumb's top is at `top`.
+    fn drag_scrollbar(&mut self, pane_id: usize, top: f32) {
+        use alacritty_terminal::grid::Scroll;
+        let (vw, vh) = self.pane_viewport();
+        let layouts = self.tab_manager.active_layouts(vw, vh);
+        let Some(layout) = layouts.iter().find(|l| l.pane_id == pane_id) else {
+            self.scrollbar_drag = None;
+            return;
+        };
+        let Some(pane) = self.tab_manager.pane(pane_id) else { return };
+        let mut term = pane.term.lock();
+        let extent = scrollbar::Extent::of(&*term);
+        let target = scrollbar::offset_at(top, (layout.y, layout.height), extent);
+        let delta = target as i32 - extent.display_offset as i32;
+        if delta != 0 {
+            term.scroll_display(Scroll::Delta(delta));
+            self.window.request_redraw();
+        }
+        drop(term);
+        self.scroll_origin.scrolled(pane_id, scroll::ScrollOrigin::User);
+        self.last_scroll = Some((pane_id, std::time::Instant::now()));
+    }
+
+    /// Follow the pointer at `x` with the pressed tab, once it has moved
+    /// far enough to count as a drag.
+    fn drag_tab(&mut self, x: f32) {
+        let count = self.tab_manager.count();
+        let Some(drag) = self.tab_drag.as_mut() else { return };
+        if !drag.dragging && (x - drag.start_x).abs() < TAB_DRAG_THRESHOLD {
+            return;
+        }
+        drag.dragging = true;
+        let tab_width = self.window.inner_size().width as f32 / count.max(1) as f32;
+        let target = ((x.max(0.0) / tab_width) as usize).min(count.saturating_sub(1));
+        if target != drag.index {
+            self.tab_manager.move_tab(drag.index, target);
+            drag.index = target;
+        }
+        self.window.request_redraw();
+    }
+
+    /// Stop a tab drag, putting the tab back where it was pressed.
+    fn cancel_tab_drag(&mut self) {
+        if let Some(drag) = self.tab_drag.take() {
+            self.tab_manager.move_tab(drag.index, drag.origin);
+        }
+    }
+
+    /// Scroll the active pane so the current search match is centered.
+    fn scroll_to_current_match(&mut self) {
+        let current = self.search.as_ref().and_then(|s| s.matches.get(s.current));
+        if let Some(m) = current {
+            self.scroll_to_line(m.line, scroll::ScrollOrigin::Search);
+        }
+    }
+
+    /// Scroll the active pane so grid `line` is centered, as far as the
+    /// history allows.
+    fn scroll_to_line(&mut self, line: alacritty_terminal::index::Line, origin: scroll::ScrollOrigin) {
+        use alacritty_terminal::grid::{Dimensions, Scroll};
+        let Some(pane) = self.tab_manager.active_pane() else { return };
+        let mut term = pane.term.lock();
+        let screen_lines = term.screen_lines() as i32;
+        let target_offset = (screen_lines / 2 - line.0).max(0);
+        let delta = target_offset - term.grid().display_offset() as i32;
+        if delta != 0 {
+            term.scroll_display(Scroll::Delta(delta));
+        }
+        drop(term);
+        if let Some(id) = self.tab_manager.active_pane_id() {
+            self.scroll_origin.scrolled(id, origin);
+            if origin == scroll::ScrollOrigin::User {
+                self.last_scroll = Some((id, std::time::Instant::now()));
+            }
+        }
+    }
+
+    /// Cmd+M and the jumps between marks.
+    fn mark_action(&mut self, action: Action) {
+        let Some(pane) = self.tab_manager.active_pane_id().and_then(|id| self.tab_manager.pane_mut(id)) else {
+            return;
+        };
+        let term = pane.term.lock();
+        let target = match action {
+            Action::ToggleMark => {
+                pane.marks.toggle(&term, term.grid().cursor.point.line);
+                None
+            }
+            Action::PrevMark => pane.marks.step(&term, false),
+            _ => pane.marks.step(&term, true),
+        };
+        drop(term);
+        if let Some(line) = target {
+            self.scroll_to_line(line, scroll::ScrollOrigin::User);
+        }
+    }
+
+    /// Cmd+Up / Cmd+Down: bring the shell's previous or next prompt to the
+    /// top of the view. Past the last prompt is the bottom.
+    fn jump_to_prompt(&mut self, forward: bool) {
+        use alacritty_terminal::grid::Scroll;
+        let Some(pane) = self.tab_manager.active_pane() else { return };
+        let mut term = pane.term.lock();
+        let offset = term.grid().display_offset() as i32;
+        let target = prompts::step(&prompts::prompt_lines(&term), alacritty_terminal::index::Line(-offset), forward);
+        let delta = match target {
+            Some(line) => -line.0.min(0) - offset,
+            None if forward => -offset,
+            None => return,
+        };
+        if delta != 0 {
+            term.scroll_display(Scroll::Delta(delta));
+        }
+        drop(term);
+        if let Some(id) = self.tab_manager.active_pane_id() {
+            self.scroll_origin.scrolled(id, scroll::ScrollOrigin::User);
+        }
+    }
+
+    /// Gutter ticks on marked lines, over the text like the rulers.
+    fn draw_marks(&mut self, marked: &[(panes::PaneLayout, Vec<usize>)], x_offset: f32, y_offset: f32, w: f32, h: f32) {
+        self.renderer.flush(w, h);
+        let ch = self.renderer.cell_height();
+        let tick = (self.renderer.cell_width() / 4.0).max(2.0).round();
+        let color = self.renderer.theme().border;
+        for (layout, rows) in marked {
+            for &row in rows {
+                let y = layout.y + y_offset + row as f32 * ch;
+                self.renderer.draw_rect(layout.x + x_offset, y, tick, ch, color);
+            }
+        }
+        self.renderer.flush_blended(w, h);
+    }
+
+    /// Close the palette, reverting any theme preview.
+    fn dismiss_palette(&mut self) {
+        self.palette = None;
+        if let Some(origin) = self.palette_theme_origin.take() {
+            self.set_theme(origin);
+        }
+        self.needs_redraw = true;
+    }
+
+    /// Run a user command from a keybinding or the command palette.
+    /// Returns `Exit::Window` when that closed the window's last pane.
+    fn perform_action(&mut self, action: Action) -> Exit {
+        let cw = self.renderer.cell_width();
+        let ch = self.renderer.cell_height();
+        match action {
+            Action::NewTab => {
+                let (cols, rows) = self.grid_size();
+                let was_single = self.tab_manager.count() == 1;
+                self.tab_manager.add_tab(cols, rows, cw, ch, &self.event_proxy);
+                // Tab bar just appeared — resize all panes for reduced viewport
+                if was_single {
+                    let size = self.window.inner_size();
+                    let vp_h = (size.height as f32 - ch).max(0.0);
+                    self.tab_manager.resize_all(size.width as f32, vp_h, cw, ch);
+                }
+                self.fit_panes();
+            }
+            Action::SplitVertical | Action::SplitHorizontal => {
+                let split = if action == Action::SplitVertical {
+                    panes::Split::Vertical
+                } else {
+                    panes::Split::Horizontal
+                };
+                let (cols, rows) = self.grid_size();
+                let vp = self.window.inner_size();
+                let tab_bar_h = if self.tab_manager.count() > 1 { ch } else { 0.0 };
+                let split = self.tab_manager.split_active(
+                    split,
+                    cols, rows, cw, ch,
+                    vp.width as f32, (vp.height as f32 - tab_bar_h).max(0.0),
+                    &self.event_proxy,
+                );
+                if !split {
+                    // Too small for two panes of the minimum size.
+                    self.beep();
                 }
-                Key::Named(NamedKey::Backspace) => {
-                    if let Some(ref mut search) = self.search {
-                        search.query.pop();
-                        // Re-search.
-                        if let Some(pane) = self.tab_manager.active_pane() {
-                            let term = pane.term.lock();
-                            search.matches = search_grid(&*term, &search.query);
-                            search.current = 0;
-                        }
-                    }
-                    self.window.request_redraw();
-                    return false;
+                self.fit_panes();
+            }
+            Action::ClosePane => {
+                if self.tab_manager.close_active_pane() {
+                    return Exit::Window;
                 }
-                // Cmd+G: next match, Cmd+Shift+G: previous match.
-                Key::Character(ref s) if super_pressed && (s == "g" || s == "G") => {
-                    if let Some(ref mut search) = self.search {
-                        if !search.matches.is_empty() {
-                            if shift_pressed {
-                                search.current = search.current.checked_sub(1)
-                                    .unwrap_or(search.matches.len() - 1);
-                            } else {
-                                search.current = (search.current + 1) % search.matches.len();
-                            }
-                            let (match_line, _) = search.matches[search.current];
-                            if let Some(pane) = self.tab_manager.active_pane() {
-                                use alacritty_terminal::grid::{Dimensions, Scroll};
-                                let mut term = pane.term.lock();
-                                let screen_lines = term.screen_lines() as i32;
-                                let target_offset = -(match_line.0) - screen_lines / 2;
-                                let target_offset = target_offset.max(0) as usize;
-                                let current_offset = term.grid().display_offset();
-                                let delta = target_offset as i32 - current_offset as i32;
-                                if delta != 0 {
-                                    term.scroll_display(Scroll::Delta(delta));
-                                }
-                            }
+                // Resize surviving panes to fill the freed space.
+                let size = self.window.inner_size();
+                let tab_bar_h = if self.tab_manager.count() > 1 { ch } else { 0.0 };
+                let h = size.height as f32 - tab_bar_h;
+                self.tab_manager.resize_all(size.width as f32, h, cw, ch);
+                self.fit_panes();
+            }
+            Action::ToggleZoom => {
+                self.tab_manager.toggle_zoom();
+                // The zoomed pane's layout changed size; so did the others'
+                // on unzoom.
+                let size = self.window.inner_size();
+                let tab_bar_h = if self.tab_manager.count() > 1 { ch } else { 0.0 };
+                let h = (size.height as f32 - tab_bar_h).max(0.0);
+                self.tab_manager.resize_active_tab(size.width as f32, h, cw, ch);
+            }
+            Action::ToggleTheme => {
+                self.set_theme(self.themes.next(self.theme));
+                self.theme_pinned = true;
+            }
+            Action::ToggleDimInactive => self.dim_inactive = !self.dim_inactive,
+            Action::ToggleBroadcast => self.tab_manager.toggle_broadcast(),
+            Action::EqualizePanes => {
+                let (w, h) = self.pane_viewport();
+                self.tab_manager.equalize_active_tab(w, h, cw, ch);
+            }
+            Action::RotateSplit => {
+                let (w, h) = self.pane_viewport();
+                self.tab_manager.rotate_active_split(w, h, cw, ch);
+            }
+            Action::SetTheme(i) => {
+                self.set_theme(i);
+                self.theme_pinned = true;
+            }
+            Action::Search => {
+                self.search = self.tab_manager.active_pane_id().map(SearchState::new);
+            }
+            Action::ResizePanes => self.enter_resize_mode(),
+            Action::RenameTab => {
+                if let Some(tab) = self.tab_manager.active_tab() {
+                    let text = tab.user_title.clone().unwrap_or_default();
+                    self.tab_rename = Some(TabRename { tab_id: tab.id, text });
+                }
+            }
+            Action::About => {
+                self.show_about = !self.show_about;
+                if self.show_about {
+                    self.about_opened_at = Some(std::time::Instant::now());
+                } else {
+                    self.about_opened_at = None;
+                }
+            }
+            Action::ResetPane => {
+                if let Some(pane) = self.tab_manager.active_pane_id().and_then(|id| self.tab_manager.pane_mut(id)) {
+                    pane.reset(cw, ch);
+                    pane.marks.clear();
+                }
+            }
+            Action::ToggleMark | Action::PrevMark | Action::NextMark => self.mark_action(action),
+            Action::PrevPrompt | Action::NextPrompt => self.jump_to_prompt(action == Action::NextPrompt),
+            Action::CopyLastOutput => {
+                if let Some(pane) = self.tab_manager.active_pane() {
+                    let mut term = pane.term.lock();
+                    if prompts::select_last_output(&mut term) {
+                        if let Some(text) = selection::selection_text(&mut term) {
+                            clipboard_copy(&text);
                         }
                     }
-                    self.window.request_redraw();
-                    return false;
                 }
-                Key::Character(ref s) if !super_pressed && !ctrl_pressed => {
-                    if let Some(ref mut search) = self.search {
-                        search.query.push_str(s);
-                        // Re-search.
-                        if let Some(pane) = self.tab_manager.active_pane() {
-                            let term = pane.term.lock();
-                            search.matches = search_grid(&*term, &search.query);
-                            search.current = 0;
-                            // Scroll to first match.
-                            if let Some(&(match_line, _)) = search.matches.first() {
-                                use alacritty_terminal::grid::{Dimensions, Scroll};
-                                let screen_lines = term.screen_lines() as i32;
-                                let target_offset = -(match_line.0) - screen_lines / 2;
-                                let target_offset = target_offset.max(0) as usize;
-                                let current_offset = term.grid().display_offset();
-                                let delta = target_offset as i32 - current_offset as i32;
-                                if delta != 0 {
-                                    drop(term);
-                                    let pane = self.tab_manager.active_pane().unwrap();
-                                    pane.term.lock().scroll_display(Scroll::Delta(delta));
-                                }
-                            }
-                        }
+            }
+            Action::CopyAnsi => {
+                if let Some(pane) = self.tab_manager.active_pane() {
+                    let mut term = pane.term.lock();
+                    if let Some(lines) = styled::styled_selection(&mut term) {
+                        clipboard_copy(&styled::to_ansi(&lines));
+                        term.selection = None;
                     }
-                    self.window.request_redraw();
-                    return false;
                 }
-                _ => {
-                    self.window.request_redraw();
-                    return false;
+            }
+            Action::RestartShell => {
+                self.tab_manager.restart_active_pane(cw, ch, &self.event_proxy);
+            }
+            Action::ToggleFullscreen => self.toggle_fullscreen(),
+            Action::ClearScrollback => {
+                if let Some(pane) = self.tab_manager.active_pane() {
+                    pane.clear_scrollback(&self.tab_manager.term_config(), cw, ch);
                 }
             }
         }
+        self.needs_redraw = true;
+        self.window.request_redraw();
+        Exit::Stay
+    }
+
+    /// Handle keyboard input, saying whether that closes the window or
+    /// quits koi.
+    fn handle_keyboard(
+        &mut self,
+        event: keyboard::KeyPress,
+    ) -> Exit {
+        if event.state != ElementState::Pressed {
+            return Exit::Stay;
+        }
+
+        // Any keypress cancels an in-progress divider, scrollbar or tab drag.
+        self.divider_drag = None;
+        self.scrollbar_drag = None;
+        self.cancel_tab_drag();
+        self.needs_redraw = true;
+
+        // Reset cursor blink so it's visible while typing
+        self.cursor_blink = std::time::Instant::now();
+
+        let shift_pressed = self.modifiers.shift_key();
+        let ctrl_pressed = self.modifiers.control_key();
+        let alt_pressed = self.modifiers.alt_key();
+        // "Primary" modifier for koi's own shortcuts: Cmd on macOS,
+        // bare Ctrl on Windows/Linux. Ctrl+C / Ctrl+D shell-signal conflicts
+        // are handled per-shortcut below (Ctrl+C is only copy if there's a
+        // text selection, else passes through as SIGINT).
+        #[cfg(target_os = "macos")]
+        let super_pressed = self.modifiers.super_key();
+        #[cfg(not(target_os = "macos"))]
+        let super_pressed = ctrl_pressed;
+
+        // Open overlays get first refusal. They're modal, so a key one takes
+        // never reaches koi's shortcuts or the shell.
+        let mods = keyboard::Mods { shift: shift_pressed, ctrl: ctrl_pressed, primary: super_pressed };
+        let open: Vec<_> = keyboard::Overlay::ORDER.into_iter().filter(|&o| self.overlay_open(o)).collect();
+        if let Some(key) = keyboard::route(&open, &event.logical_key, mods) {
+            let exit = self.on_overlay_key(key, event.repeat);
+            self.window.request_redraw();
+            return exit;
+        }
 
         // Ctrl+Tab / Ctrl+Shift+Tab: Cycle tabs
         if ctrl_pressed && matches!(event.logical_key, Key::Named(NamedKey::Tab)) {
@@ -762,351 +2366,170 @@ impl KoiState {
                 self.tab_manager.next_tab();
             }
             self.window.request_redraw();
-            return false;
+            return Exit::Stay;
         }
 
-        // Cmd+Left/Right: Cycle tabs (iTerm2-style) with slide animation
+        // macOS-style editing keys, sent to the shell as readline bytes.
+        let natural = self.config.keyboard.natural_text_editing;
+        let natural_edit = keyboard::natural_edit(&event.logical_key, mods, alt_pressed).filter(|_| natural);
+
+        // Cmd+Left/Right: Cycle tabs (iTerm2-style) with slide animation.
+        // With natural text editing those move along the line, and tabs
+        // take Cmd+Shift+Left/Right instead.
         if super_pressed && !alt_pressed {
             match event.logical_key {
-                Key::Named(NamedKey::ArrowLeft) if !shift_pressed => {
+                Key::Named(NamedKey::ArrowLeft) if shift_pressed == natural => {
                     self.tab_animation = Some(TabAnimation {
                         start: std::time::Instant::now(),
                         direction: -1.0,
                     });
                     self.tab_manager.prev_tab();
                     self.window.request_redraw();
-                    return false;
+                    return Exit::Stay;
                 }
-                Key::Named(NamedKey::ArrowRight) if !shift_pressed => {
+                Key::Named(NamedKey::ArrowRight) if shift_pressed == natural => {
                     self.tab_animation = Some(TabAnimation {
                         start: std::time::Instant::now(),
                         direction: 1.0,
                     });
                     self.tab_manager.next_tab();
                     self.window.request_redraw();
-                    return false;
+                    return Exit::Stay;
                 }
                 _ => {}
             }
         }
 
+        // Cmd+Shift+Arrow: Swap the active pane with its neighbor that way,
+        // unless Cmd+Shift+Left/Right cycled tabs above.
+        if super_pressed && shift_pressed && !alt_pressed {
+            if let Key::Named(arrow @ (NamedKey::ArrowLeft | NamedKey::ArrowRight | NamedKey::ArrowUp | NamedKey::ArrowDown)) =
+                event.logical_key
+            {
+                if let Some(target) = self.pane_toward(arrow) {
+                    let (w, h) = self.pane_viewport();
+                    let (cw, ch) = (self.renderer.cell_width(), self.renderer.cell_height());
+                    self.tab_manager.swap_active_pane(target, w, h, cw, ch);
+                }
+                self.window.request_redraw();
+                return Exit::Stay;
+            }
+        }
+
         // Cmd+Option+Arrow: Directional pane navigation
         if super_pressed && alt_pressed {
-            match event.logical_key {
-                Key::Named(NamedKey::ArrowLeft)
-                | Key::Named(NamedKey::ArrowRight)
-                | Key::Named(NamedKey::ArrowUp)
-                | Key::Named(NamedKey::ArrowDown) => {
-                    let size = self.window.inner_size();
-                    let tab_bar_h = if self.tab_manager.count() > 1 {
-                        self.renderer.cell_height()
-                    } else {
-                        0.0
-                    };
-                    let vp_h = (size.height as f32 - tab_bar_h).max(0.0);
-                    let layouts =
-                        self.tab_manager.active_layouts(size.width as f32, vp_h);
-                    if let Some(active_tab) = self.tab_manager.active_tab() {
-                    let active_id = active_tab.pane_tree.active_pane_id();
-
-                    // Find active pane's center
-                    if let Some(active_layout) =
-                        layouts.iter().find(|l| l.pane_id == active_id)
-                    {
-                        let ax = active_layout.x + active_layout.width / 2.0;
-                        let ay = active_layout.y + active_layout.height / 2.0;
-
-                        let target = layouts
-                            .iter()
-                            .filter(|l| l.pane_id != active_id)
-                            .filter(|l| {
-                                let lx = l.x + l.width / 2.0;
-                                let ly = l.y + l.height / 2.0;
-                                match event.logical_key {
-                                    Key::Named(NamedKey::ArrowLeft) => lx < ax,
-                                    Key::Named(NamedKey::ArrowRight) => lx > ax,
-                                    Key::Named(NamedKey::ArrowUp) => ly < ay,
-                                    Key::Named(NamedKey::ArrowDown) => ly > ay,
-                                    _ => false,
-                                }
-                            })
-                            .min_by(|a, b| {
-                                let da = (a.x + a.width / 2.0 - ax).powi(2)
-                                    + (a.y + a.height / 2.0 - ay).powi(2);
-                                let db = (b.x + b.width / 2.0 - ax).powi(2)
-                                    + (b.y + b.height / 2.0 - ay).powi(2);
-                                da.partial_cmp(&db).unwrap()
-                            });
-
-                        if let Some(target) = target {
-                            self.tab_manager.focus_pane(target.pane_id);
-                        }
-                    }
-                    } // if let Some(active_tab)
-                    self.window.request_redraw();
-                    return false;
+            if let Key::Named(arrow @ (NamedKey::ArrowLeft | NamedKey::ArrowRight | NamedKey::ArrowUp | NamedKey::ArrowDown)) =
+                event.logical_key
+            {
+                if let Some(target) = self.pane_toward(arrow) {
+                    self.change_focus(|tabs| tabs.focus_pane(target));
                 }
-                _ => {}
+                self.window.request_redraw();
+                return Exit::Stay;
             }
         }
 
-        // Handle tab keybindings (Cmd+...)
-        if super_pressed {
-            match event.logical_key {
-                // Cmd+Shift+T: Toggle dark/light theme
-                Key::Character(ref s) if (s == "T" || (s == "t" && shift_pressed)) => {
-                    use renderer::Theme;
-                    // Toggle: if current bg is dark (mocha), switch to latte, else mocha.
-                    let is_dark = self.renderer.theme.bg[0] < 0.5;
-                    self.renderer.theme = if is_dark { Theme::latte() } else { Theme::mocha() };
-                    self.needs_redraw = true;
-                    self.window.request_redraw();
-                    return false;
-                }
-                // Cmd+N: New window (spawn a new koi process)
-                Key::Character(ref s) if s == "n" => {
-                    if let Ok(exe) = std::env::current_exe() {
-                        let _ = std::process::Command::new(exe)
-                            .stdin(std::process::Stdio::null())
-                            .stdout(std::process::Stdio::null())
-                            .stderr(std::process::Stdio::null())
-                            .spawn();
-                    }
-                    return false;
-                }
-                // Cmd+T: New tab
-                Key::Character(ref s) if s == "t" => {
-                    let (cols, rows) = self.grid_size();
-                    let cw = self.renderer.cell_width();
-                    let ch = self.renderer.cell_height();
-                    let was_single = self.tab_manager.count() == 1;
-                    self.tab_manager.add_tab(cols, rows, cw, ch, event_proxy);
-                    // Tab bar just appeared — resize all panes for reduced viewport
-                    if was_single {
-                        let size = self.window.inner_size();
-                        let vp_h = size.height as f32 - ch;
-                        self.tab_manager.resize_all(size.width as f32, vp_h, cw, ch);
-                    }
-                    self.window.request_redraw();
-                    return false;
-                }
-                // Cmd+W: Close active pane (or tab if last pane)
-                Key::Character(ref s) if s == "w" => {
-                    if self.tab_manager.close_active_pane() {
-                        return true; // signal exit
-                    }
-                    // Resize surviving panes to fill the freed space.
-                    let cw = self.renderer.cell_width();
-                    let ch = self.renderer.cell_height();
-                    let size = self.window.inner_size();
-                    let tab_bar_h = if self.tab_manager.count() > 1 { ch } else { 0.0 };
-                    let h = size.height as f32 - tab_bar_h;
-                    self.tab_manager.resize_all(size.width as f32, h, cw, ch);
-                    self.window.request_redraw();
-                    return false;
-                }
-                // Cmd+Shift+[ : Previous tab
-                Key::Character(ref s) if s == "{" && shift_pressed => {
-                    self.tab_animation = Some(TabAnimation {
-                        start: std::time::Instant::now(),
-                        direction: -1.0,
-                    });
-                    self.tab_manager.prev_tab();
-                    self.window.request_redraw();
-                    return false;
-                }
-                // Cmd+Shift+] : Next tab
-                Key::Character(ref s) if s == "}" && shift_pressed => {
-                    self.tab_animation = Some(TabAnimation {
-                        start: std::time::Instant::now(),
-                        direction: 1.0,
-                    });
-                    self.tab_manager.next_tab();
-                    self.window.request_redraw();
-                    return false;
-                }
-                // Cmd+1-9: Go to tab
-                Key::Character(ref s)
-                    if s.len() == 1
-                        && s.chars().next().unwrap().is_ascii_digit() =>
-                {
-                    let digit = s.chars().next().unwrap() as usize - '0' as usize;
-                    if digit >= 1 {
-                        self.tab_manager.goto_tab(digit - 1);
-                        self.window.request_redraw();
-                    }
-                    return false;
-                }
-                // Cmd+D: Split pane vertically
-                Key::Character(ref s) if s == "d" && !shift_pressed => {
-                    let (cols, rows) = self.grid_size();
-                    let cw = self.renderer.cell_width();
-                    let ch = self.renderer.cell_height();
-                    let vp = self.window.inner_size();
-                    let tab_bar_h = if self.tab_manager.count() > 1 { ch } else { 0.0 };
-                    self.tab_manager.split_active(
-                        panes::Split::Vertical,
-                        cols, rows, cw, ch,
-                        vp.width as f32, (vp.height as f32 - tab_bar_h).max(0.0),
-                        event_proxy,
-                    );
-                    self.window.request_redraw();
-                    return false;
-                }
-                // Cmd+Shift+D: Split pane horizontally
-                Key::Character(ref s) if s == "D" && shift_pressed => {
-                    let (cols, rows) = self.grid_size();
-                    let cw = self.renderer.cell_width();
-                    let ch = self.renderer.cell_height();
-                    let vp = self.window.inner_size();
-                    let tab_bar_h = if self.tab_manager.count() > 1 { ch } else { 0.0 };
-                    self.tab_manager.split_active(
-                        panes::Split::Horizontal,
-                        cols, rows, cw, ch,
-                        vp.width as f32, (vp.height as f32 - tab_bar_h).max(0.0),
-                        event_proxy,
-                    );
-                    self.window.request_redraw();
-                    return false;
-                }
-                // Cmd+Shift+Enter: Toggle zoom on active pane
-                Key::Named(NamedKey::Enter) if shift_pressed => {
-                    self.tab_manager.toggle_zoom();
-                    self.window.request_redraw();
-                    return false;
-                }
-                // Cmd+]: Focus next pane
-                Key::Character(ref s) if s == "]" && !shift_pressed => {
-                    self.tab_manager.focus_next_pane();
-                    self.window.request_redraw();
-                    return false;
-                }
-                // Cmd+[: Focus previous pane
-                Key::Character(ref s) if s == "[" && !shift_pressed => {
-                    self.tab_manager.focus_prev_pane();
-                    self.window.request_redraw();
-                    return false;
-                }
-                // Cmd+C (macOS) / Ctrl+C (win/linux): Copy selection to clipboard.
-                // On win/linux, only intercept when there IS a selection, so
-                // bare Ctrl+C without a selection still reaches the shell as SIGINT.
-                Key::Character(ref s) if s.eq_ignore_ascii_case("c") => {
-                    if let Some(pane) = self.tab_manager.active_pane() {
-                        let mut term = pane.term.lock();
-                        if let Some(text) = term.selection_to_string() {
-                            clipboard_copy(&text);
-                            term.selection = None;
-                            self.window.request_redraw();
-                            return false;
-                        }
-                        #[cfg(target_os = "macos")]
-                        {
-                            term.selection = None;
-                            self.window.request_redraw();
-                            return false;
-                        }
-                        #[cfg(not(target_os = "macos"))]
-                        {
-                            // No selection on win/linux — fall through to shell.
-                        }
-                    }
-                }
-                // Cmd+F: Search scrollback
-                Key::Character(ref s) if s == "f" => {
-                    self.search = Some(SearchState {
-                        query: String::new(),
-                        matches: Vec::new(),
-                        current: 0,
-                    });
-                    self.window.request_redraw();
-                    return false;
-                }
-                // Cmd+V: Paste from clipboard (text, or image as temp file path)
-                Key::Character(ref s) if s == "v" => {
-                    if let Some(pane) = self.tab_manager.active_pane() {
-                        let text = clipboard_paste().or_else(clipboard_paste_image);
-                        if let Some(text) = text {
-                            use alacritty_terminal::term::TermMode;
-                            let bracketed = pane.term.lock().mode()
-                                .contains(TermMode::BRACKETED_PASTE);
-                            if bracketed {
-                                // Sanitize: strip both bracket markers from content.
-                                let sanitized = text
-                                    .replace("\x1b[200~", "")
-                                    .replace("\x1b[201~", "");
-                                let mut bytes = Vec::new();
-                                bytes.extend_from_slice(b"\x1b[200~");
-                                bytes.extend_from_slice(sanitized.as_bytes());
-                                bytes.extend_from_slice(b"\x1b[201~");
-                                pane.notifier.send_input(&bytes);
-                            } else {
-                                pane.notifier.send_input(text.as_bytes());
-                            }
-                        }
-                    }
-                    return false;
-                }
-                // Cmd+,: About overlay
-                Key::Character(ref s) if s == "," => {
-                    self.show_about = !self.show_about;
-                    if self.show_about {
-                        self.about_opened_at = Some(std::time::Instant::now());
-                    } else {
-                        self.about_opened_at = None;
-                    }
-                    self.window.request_redraw();
-                    return false;
-                }
-                // Cmd+Q: Quit
-                Key::Character(ref s) if s == "q" => {
-                    return true; // signal exit
-                }
-                // Cmd+=: Zoom in
-                Key::Character(ref s) if s == "=" || s == "+" => {
-                    *font_size = (*font_size + 1.0).min(32.0);
-                    self.rebuild_renderer(*font_size, scale);
-                    return false;
-                }
-                // Cmd+-: Zoom out
-                Key::Character(ref s) if s == "-" => {
-                    *font_size = (*font_size - 1.0).max(8.0);
-                    self.rebuild_renderer(*font_size, scale);
-                    return false;
+        // Cmd+Alt+R: Reset pane (RIS) — unwedges stuck mouse/alt-screen modes.
+        // Cmd+Alt+M: Jump to the next mark.
+        // Cmd+Alt+=: Equalize pane sizes (Cmd+Shift+= is Cmd+Plus, zoom in).
+        // Matched on the unmodified key: Option rewrites the character on macOS
+        // (Option+R produces "®").
+        if super_pressed && alt_pressed {
+            if let Key::Character(ref s) = event.key_without_modifiers {
+                if s.eq_ignore_ascii_case("r") {
+                    return self.perform_action(Action::ResetPane);
                 }
-                // Cmd+0: Reset zoom
-                Key::Character(ref s) if s == "0" => {
-                    *font_size = 14.0;
-                    self.rebuild_renderer(*font_size, scale);
-                    return false;
+                if s.eq_ignore_ascii_case("m") {
+                    return self.perform_action(Action::NextMark);
                 }
-                // Cmd+K: Clear screen
-                Key::Character(ref s) if s == "k" => {
-                    if let Some(pane) = self.tab_manager.active_pane() {
-                        // Send clear screen + move cursor home
-                        pane.notifier.send_input(b"\x1b[2J\x1b[H");
-                    }
-                    return false;
+                if s == "=" {
+                    return self.perform_action(Action::EqualizePanes);
                 }
-                _ => {
-                    #[cfg(target_os = "macos")]
-                    return false;
-                    #[cfg(not(target_os = "macos"))]
-                    {}
+            }
+        }
+
+        // Cmd+Ctrl+F: fullscreen, matched on the unmodified key as Ctrl
+        // rewrites the character. Where Ctrl is the primary modifier, F11
+        // alone; with modifiers it goes to the app. Cmd+Enter too if
+        // configured.
+        #[cfg(target_os = "macos")]
+        let fullscreen_key = super_pressed
+            && ctrl_pressed
+            && matches!(&event.key_without_modifiers, Key::Character(s) if s.eq_ignore_ascii_case("f"));
+        #[cfg(not(target_os = "macos"))]
+        let fullscreen_key = event.logical_key == Key::Named(NamedKey::F11) && self.modifiers.is_empty();
+        let cmd_enter = super_pressed
+            && !shift_pressed
+            && event.logical_key == Key::Named(NamedKey::Enter)
+            && self.config.keyboard.cmd_enter_fullscreen;
+        if fullscreen_key || cmd_enter {
+            return self.perform_action(Action::ToggleFullscreen);
+        }
+
+        // Primary-modifier shortcuts, from the binding table
+        if super_pressed && natural_edit.is_none() {
+            let input = bindings::KeyInput {
+                logical: &event.logical_key,
+                physical: event.physical_key,
+                shift: shift_pressed,
+            };
+            if let Some(command) = bindings::lookup(&input) {
+                if let Some(exit) = self.run_command(command) {
+                    return exit;
                 }
             }
+            #[cfg(target_os = "macos")]
+            return Exit::Stay;
         }
 
         // Forward to active pane's PTY
         let Some(pane) = self.tab_manager.active_pane() else {
-            return false;
+            return Exit::Stay;
         };
-        let notifier = &pane.notifier;
 
-        // Check DECCKM (application cursor keys) mode
-        let app_cursor = {
-            use alacritty_terminal::term::TermMode;
-            pane.term.lock().mode().contains(TermMode::APP_CURSOR)
-        };
+        // A pane whose PTY thread crashed takes nothing but Enter, which
+        // starts a new shell.
+        if pane.io_crashed {
+            if event.logical_key == Key::Named(NamedKey::Enter) {
+                return self.perform_action(Action::RestartShell);
+            }
+            return Exit::Stay;
+        }
+
+        // A buffer pane has no shell: its keys page through the text.
+        if pane.is_buffer() {
+            match pager::pager_key(&event.logical_key) {
+                Some(pager::PagerKey::Quit) => return Exit::Window,
+                Some(key) => {
+                    if let Some(scroll) = key.scroll() {
+                        pane.term.lock().scroll_display(scroll);
+                        self.last_scroll = self.tab_manager.active_pane_id().map(|id| (id, std::time::Instant::now()));
+                    }
+                }
+                None => {}
+            }
+            return Exit::Stay;
+        }
+
+        // Shift+PageUp/PageDown/Home/End page through scrollback instead of
+        // reaching the shell. A full-screen app on the alt screen has no
+        // scrollback, so it gets them as usual.
+        if shift_pressed && !alt_pressed && !ctrl_pressed && !super_pressed {
+            if let Some(scroll) = keyboard::shift_scroll(&event.logical_key) {
+                use alacritty_terminal::term::TermMode;
+                let mut term = pane.term.lock();
+                if !term.mode().contains(TermMode::ALT_SCREEN) {
+                    term.scroll_display(scroll);
+                    drop(term);
+                    if let Some(id) = self.tab_manager.active_pane_id() {
+                        self.scroll_origin.scrolled(id, scroll::ScrollOrigin::User);
+                        self.last_scroll = Some((id, std::time::Instant::now()));
+                    }
+                    self.window.request_redraw();
+                    return Exit::Stay;
+                }
+            }
+        }
 
         // CSI modifier parameter: 1 + (shift?1:0) + (alt?2:0) + (ctrl?4:0)
         // When modifier > 1, named keys use forms like \x1b[1;3A (Alt+Up)
@@ -1117,6 +2540,7 @@ impl KoiState {
         let has_modifier = modifier > 1;
 
         let bytes: Option<Cow<'static, [u8]>> = match event.logical_key {
+            _ if natural_edit.is_some() => natural_edit.map(Cow::Borrowed),
             Key::Named(NamedKey::Enter) => Some(Cow::Borrowed(b"\r")),
             Key::Named(NamedKey::Backspace) => Some(Cow::Borrowed(b"\x7f")),
             Key::Named(NamedKey::Tab) if shift_pressed => Some(Cow::Borrowed(b"\x1b[Z")),
@@ -1134,24 +2558,12 @@ impl KoiState {
                 Some(Cow::Owned(format!("\x1b[1;{}H", modifier).into_bytes())),
             Key::Named(NamedKey::End) if has_modifier =>
                 Some(Cow::Owned(format!("\x1b[1;{}F", modifier).into_bytes())),
-            Key::Named(NamedKey::ArrowUp) => Some(Cow::Borrowed(
-                if app_cursor { b"\x1bOA" } else { b"\x1b[A" }
-            )),
-            Key::Named(NamedKey::ArrowDown) => Some(Cow::Borrowed(
-                if app_cursor { b"\x1bOB" } else { b"\x1b[B" }
-            )),
-            Key::Named(NamedKey::ArrowRight) => Some(Cow::Borrowed(
-                if app_cursor { b"\x1bOC" } else { b"\x1b[C" }
-            )),
-            Key::Named(NamedKey::ArrowLeft) => Some(Cow::Borrowed(
-                if app_cursor { b"\x1bOD" } else { b"\x1b[D" }
-            )),
-            Key::Named(NamedKey::Home) => Some(Cow::Borrowed(
-                if app_cursor { b"\x1bOH" } else { b"\x1b[H" }
-            )),
-            Key::Named(NamedKey::End) => Some(Cow::Borrowed(
-                if app_cursor { b"\x1bOF" } else { b"\x1b[F" }
-            )),
+            Key::Named(NamedKey::ArrowUp) => Some(Cow::Borrowed(b"\x1b[A")),
+            Key::Named(NamedKey::ArrowDown) => Some(Cow::Borrowed(b"\x1b[B")),
+            Key::Named(NamedKey::ArrowRight) => Some(Cow::Borrowed(b"\x1b[C")),
+            Key::Named(NamedKey::ArrowLeft) => Some(Cow::Borrowed(b"\x1b[D")),
+            Key::Named(NamedKey::Home) => Some(Cow::Borrowed(b"\x1b[H")),
+            Key::Named(NamedKey::End) => Some(Cow::Borrowed(b"\x1b[F")),
             Key::Named(NamedKey::Delete) if has_modifier =>
                 Some(Cow::Owned(format!("\x1b[3;{}~", modifier).into_bytes())),
             Key::Named(NamedKey::Delete) => Some(Cow::Borrowed(b"\x1b[3~")),
@@ -1197,32 +2609,24 @@ impl KoiState {
             Key::Named(NamedKey::F12) if has_modifier =>
                 Some(Cow::Owned(format!("\x1b[24;{}~", modifier).into_bytes())),
             Key::Named(NamedKey::F12) => Some(Cow::Borrowed(b"\x1b[24~")),
-            Key::Named(NamedKey::Space) => {
-                if ctrl_pressed {
-                    Some(Cow::Borrowed(b"\x00")) // Ctrl+Space = NUL
-                } else {
-                    Some(Cow::Borrowed(b" "))
-                }
-            }
+            Key::Named(NamedKey::Space) if !ctrl_pressed => Some(Cow::Borrowed(b" ")),
             _ => {
                 // For text input, use event.text (canonical winit 0.30 path).
-                // Ctrl+key: compute control byte from logical_key.
+                // Ctrl+key: control byte from the unmodified key.
                 if ctrl_pressed {
-                    if let Key::Character(ref s) = event.logical_key {
-                        if s.len() == 1 {
-                            let c = s.chars().next().unwrap();
-                            if c.is_ascii_lowercase() || ('@'..='_').contains(&c) {
-                                let ctrl_byte = (c.to_ascii_uppercase() as u8) & 0x1f;
-                                Some(Cow::Owned(vec![ctrl_byte]))
-                            } else {
-                                None
-                            }
-                        } else {
-                            None
+                    let single = |key: &Key| match key {
+                        Key::Named(NamedKey::Space) => Some(' '),
+                        Key::Character(s) => {
+                            let mut chars = s.chars();
+                            chars.next().filter(|_| chars.next().is_none())
                         }
-                    } else {
-                        None
-                    }
+                        _ => None,
+                    };
+                    single(&event.key_without_modifiers).and_then(|base| {
+                        let logical = single(&event.logical_key);
+                        let mode = self.config.keyboard.ctrl_keys;
+                        keyboard::ctrl_key(base, logical, shift_pressed, alt_pressed, mode).map(Cow::Owned)
+                    })
                 } else if alt_pressed {
                     let s = event.text.as_deref().or_else(|| match event.logical_key {
                         Key::Character(ref s) => Some(s.as_str()),
@@ -1243,87 +2647,89 @@ impl KoiState {
         };
 
         if let Some(bytes) = bytes {
-            // Snap to bottom when typing while scrolled up (like iTerm2/Alacritty).
+            let pane_id = self.tab_manager.active_pane_id().unwrap_or_default();
+            // Snap to bottom when typing while scrolled up (like iTerm2/Alacritty),
+            // unless search put the view there.
             {
                 use alacritty_terminal::grid::Scroll;
                 let mut term = pane.term.lock();
-                if term.grid().display_offset() != 0 {
+                if self.scroll_origin.snap_on_typing(pane_id, term.grid().display_offset()) {
                     term.scroll_display(Scroll::Bottom);
                     self.needs_redraw = true;
                 }
             }
-            notifier.send_input(&bytes);
+            // With broadcast on, the other panes in the tab get the same key,
+            // each in its own cursor key mode.
+            for id in self.tab_manager.input_panes(pane_id) {
+                use alacritty_terminal::term::TermMode;
+                let Some(pane) = self.tab_manager.pane(id) else { continue };
+                let app_cursor = pane.term.lock().mode().contains(TermMode::APP_CURSOR);
+                let bytes = if app_cursor { keyboard::app_cursor(&bytes) } else { Cow::Borrowed(&*bytes) };
+                self.send_typed(id, &bytes);
+            }
         }
-        false
+        Exit::Stay
     }
 
-    fn handle_scroll(&mut self, delta: winit::event::MouseScrollDelta) {
+    fn handle_scroll(&mut self, delta: winit::event::MouseScrollDelta, phase: winit::event::TouchPhase) {
         self.needs_redraw = true;
-        // Unified pixel accumulator: works for wired mice (LineDelta, often
-        // fractional on macOS with smooth scrolling) and trackpads (PixelDelta).
-        // Multiplier of 3 matches Alacritty/Terminal.app's feel of ~3 lines
-        // per wheel detent.
-        const SCROLL_MULTIPLIER: f64 = 3.0;
         let ch = self.renderer.cell_height() as f64;
-        let delta_px = match delta {
-            winit::event::MouseScrollDelta::LineDelta(_, y) => y as f64 * ch,
-            winit::event::MouseScrollDelta::PixelDelta(pos) => pos.y,
-        };
-        self.scroll_accumulator += delta_px * SCROLL_MULTIPLIER;
-        let scroll_lines = (self.scroll_accumulator / ch).trunc() as i32;
-        self.scroll_accumulator -= scroll_lines as f64 * ch;
+        let scrolling = &self.config.scrolling;
+        let scroll_lines = self.wheel.lines(delta, phase, ch, scrolling.multiplier, scrolling.invert);
         if scroll_lines != 0 {
             if let Some(pane) = self.tab_manager.active_pane() {
                 use alacritty_terminal::term::TermMode;
                 let term = pane.term.lock();
-                let mode = term.mode();
+                let mode = *term.mode();
                 let mouse_mode = mode.intersects(TermMode::MOUSE_MODE);
-                let sgr = mode.contains(TermMode::SGR_MOUSE);
                 let alt_screen = mode.contains(TermMode::ALT_SCREEN);
+                let alternate_scroll =
+                    mode.contains(TermMode::ALTERNATE_SCROLL) || self.config.scrolling.force_alternate_scroll;
+                let grid = (term.columns(), term.screen_lines());
                 drop(term);
 
                 // Standard terminal behavior (matches Alacritty/iTerm2):
                 //   mouse_mode active → forward scroll to app (tmux, vim, etc.)
-                //   alt_screen (no mouse) → send arrow keys for alternate scroll
-                //   shift overrides either → use koi's own scrollback instead
+                //   alt_screen with 1007 set (no mouse) → send arrow keys
+                //   alt_screen otherwise → nothing; the app didn't ask
+                //   shift overrides all of these → use koi's own scrollback instead
                 let shift = self.modifiers.shift_key();
 
-                if !shift && mouse_mode && sgr {
-                    // Forward scroll as SGR mouse events to the app.
-                    if let Some(hit) = self.mouse_hit() {
-                        let button = if scroll_lines > 0 { 64 } else { 65 };
-                        let count = scroll_lines.unsigned_abs();
-                        for _ in 0..count {
-                            pane.notifier.send_bytes(
-                                format!("\x1b[<{};{};{}M", button, hit.col, hit.line)
-                                    .into_bytes(),
-                            );
-                        }
-                    }
-                } else if !shift && mouse_mode {
-                    // X10/normal mouse mode: no SGR extension.
-                    // CSI M cb cx cy with each byte offset by 32.
+                if !shift && mouse_mode {
+                    // Forward scroll as mouse wheel events to the app.
                     if let Some(hit) = self.mouse_hit() {
-                        let button: u8 = if scroll_lines > 0 { 64 } else { 65 };
-                        let cb = button.saturating_add(32);
-                        let cx = (hit.col as u8).saturating_add(32);
-                        let cy = (hit.line as u8).saturating_add(32);
-                        let count = scroll_lines.unsigned_abs();
-                        for _ in 0..count {
-                            pane.notifier.send_bytes(vec![0x1b, b'[', b'M', cb, cx, cy]);
+                        let button = if scroll_lines > 0 { mouse::WHEEL_UP } else { mouse::WHEEL_DOWN };
+                        let report = MouseReport {
+                            button,
+                            col: hit.col,
+                            line: hit.line,
+                            pressed: true,
+                            modifiers: self.mouse_modifiers(),
+                        };
+                        if let Some(bytes) = mouse::encode(report, mode, grid.0, grid.1) {
+                            for _ in 0..scroll_lines.unsigned_abs() {
+                                pane.send_bytes(bytes.clone());
+                            }
                         }
                     }
                 } else if !shift && alt_screen {
-                    // Alt screen without mouse mode: send arrow keys (less, man, etc.)
-                    let key = if scroll_lines > 0 { b"\x1b[A" } else { b"\x1b[B" };
-                    let count = scroll_lines.unsigned_abs();
-                    for _ in 0..count {
-                        pane.notifier.send_input(key);
+                    // Alt screen without mouse mode: arrow keys (less, man,
+                    // etc.), if the app turned on alternate scroll.
+                    if alternate_scroll {
+                        let key = if scroll_lines > 0 { b"\x1b[A" } else { b"\x1b[B" };
+                        let keys = key.repeat(scroll_lines.unsigned_abs() as usize);
+                        if let Some(id) = self.tab_manager.active_pane_id() {
+                            self.send_typed(id, &keys);
+                        }
                     }
                 } else {
                     // Normal screen or shift override: scroll koi's own scrollback.
                     use alacritty_terminal::grid::Scroll;
                     pane.term.lock().scroll_display(Scroll::Delta(scroll_lines));
+                    if let Some(id) = self.tab_manager.active_pane_id() {
+                        self.scroll_origin.scrolled(id, scroll::ScrollOrigin::User);
+                        self.last_scroll = Some((id, std::time::Instant::now()));
+                    }
                 }
             }
             self.window.request_redraw();
@@ -1331,20 +2737,111 @@ impl KoiState {
     }
 
     fn handle_resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
+        let _span = tracing::info_span!("resize", width = new_size.width, height = new_size.height).entered();
         self.needs_redraw = true;
+        if !self.window.is_fullscreen() {
+            self.windowed_size = new_size;
+        }
         let cw = self.renderer.cell_width();
         let ch = self.renderer.cell_height();
         let w = new_size.width as f32;
         let tab_bar_h = if self.tab_manager.count() > 1 { ch } else { 0.0 };
         let h = (new_size.height as f32 - tab_bar_h).max(0.0);
         self.tab_manager.resize_all(w, h, cw, ch);
+        self.fit_panes();
 
         // Resize GL surface
         let nw = NonZeroU32::new(new_size.width.max(1)).unwrap();
         let nh = NonZeroU32::new(new_size.height.max(1)).unwrap();
-        self.gl_surface.resize(&self.gl_context, nw, nh);
+        self.window.resize_surface(nw, nh);
+
+        self.window.request_redraw();
+    }
+
+    /// Dim panes that show scrollback rather than live output, and show the
+    /// "SCROLLBACK" bar for a moment when the active pane first enters it.
+    #[allow(clippy::too_many_arguments)]
+    fn draw_history_cue(
+        &mut self,
+        layouts: &[panes::PaneLayout],
+        in_history: &[usize],
+        active_pane_id: usize,
+        x_offset: f32,
+        y_offset: f32,
+        w: f32,
+        h: f32,
+    ) {
+        let active_in_history = in_history.contains(&active_pane_id);
+        match self.scrollback_bar {
+            Some((id, _)) if active_in_history && id == active_pane_id => {}
+            _ if active_in_history => {
+                let fade = overlay::Fade::new(
+                    std::time::Duration::from_millis(1000),
+                    std::time::Duration::from_millis(300),
+                );
+                self.scrollback_bar = Some((active_pane_id, fade));
+            }
+            _ => self.scrollback_bar = None,
+        }
+        if in_history.is_empty() {
+            return;
+        }
+
+        // The wash has to land above the glyphs, so flush the grid first.
+        self.renderer.flush(w, h);
+        let bg = self.renderer.theme().bg;
+        for layout in layouts.iter().filter(|l| in_history.contains(&l.pane_id)) {
+            self.renderer.draw_rect(
+                layout.x + x_offset,
+                layout.y + y_offset,
+                layout.width,
+                layout.height,
+                [bg[0], bg[1], bg[2], 0.35],
+            );
+        }
 
-        self.window.request_redraw();
+        let bar_alpha = self.scrollback_bar.as_ref().and_then(|(_, fade)| fade.alpha());
+        let layout = layouts.iter().find(|l| l.pane_id == active_pane_id);
+        if let (Some(alpha), Some(layout)) = (bar_alpha, layout) {
+            let cw = self.renderer.cell_width();
+            let ch = self.renderer.cell_height();
+            let b = self.renderer.theme().border;
+            let bar_bg = overlay::with_alpha([b[0], b[1], b[2], 0.95], alpha);
+            let fg = overlay::with_alpha([1.0, 1.0, 1.0, 1.0], alpha);
+            let x = layout.x + x_offset;
+            let y = layout.y + y_offset + layout.height - ch;
+            self.renderer.draw_rect(x, y, layout.width, ch, bar_bg);
+            let text = " SCROLLBACK \u{2014} type or scroll down to return to live output";
+            let opts = TextOptions::clipped((layout.width / cw) as usize);
+            self.renderer.draw_text(x, y, text, fg, [0.0; 4], &opts);
+            // Keep frames coming until the bar has faded out.
+            self.animating = true;
+        }
+        self.renderer.flush_blended(w, h);
+    }
+
+    /// Thin vertical guides at `ruler.columns` in each pane of `ruled`,
+    /// drawn over the text so colored backgrounds can't hide them.
+    fn draw_rulers(
+        &mut self,
+        layouts: &[panes::PaneLayout],
+        ruled: &[usize],
+        x_offset: f32,
+        y_offset: f32,
+        w: f32,
+        h: f32,
+    ) {
+        self.renderer.flush(w, h);
+        let cw = self.renderer.cell_width();
+        let o = self.renderer.theme().overlay0;
+        let color = [o[0], o[1], o[2], 0.35];
+        for layout in layouts.iter().filter(|l| ruled.contains(&l.pane_id)) {
+            for dx in ruler::ruler_offsets(&self.config.ruler.columns, cw, layout.width) {
+                let x = layout.x + x_offset + dx;
+                self.renderer.draw_rect(x, layout.y + y_offset, 1.0, layout.height, color);
+            }
+        }
+        self.renderer.flush_blended(w, h);
     }
 
     fn render(&mut self) {
@@ -1352,42 +2849,44 @@ impl KoiState {
             return;
         }
         self.needs_redraw = false;
+        self.animating = false;
+        self.output_redraw = None;
+        let _span = tracing::info_span!("render").entered();
+
+        // A tab switch or closed pane may have moved focus off the searched pane.
+        self.sync_search_focus();
+        self.sync_focus_reports();
+        // New output or a resize since the search ran would put its
+        // highlights on the wrong cells.
+        if let Some(search) = self.search.as_mut() {
+            if let Some(pane) = self.tab_manager.pane(search.pane_id) {
+                let term = pane.term.lock();
+                if !search.is_fresh(&*term) {
+                    search.refresh(&*term);
+                }
+            }
+        }
+
+        // Follow history trimmed since the last frame before placing marks.
+        for pane in self.tab_manager.panes_mut().filter(|p| !p.marks.is_empty()) {
+            let term = pane.term.lock();
+            pane.marks.sync(&term);
+        }
 
         let size = self.window.inner_size();
         let w = size.width as f32;
         let h = size.height as f32;
 
-        let bell_active = self.bell_flash_until
-            .map(|t| std::time::Instant::now() < t)
-            .unwrap_or(false);
-        if !bell_active {
-            self.bell_flash_until = None;
-        }
-
-        unsafe {
-            gl::Viewport(0, 0, size.width as i32, size.height as i32);
-            if bell_active {
-                // Bell flash: blend theme bg with warm orange tint
-                let bg = &self.renderer.theme.bg;
-                gl::ClearColor(
-                    (bg[0] + 1.0) / 2.0,
-                    (bg[1] + 0.85) / 2.0,
-                    (bg[2] + 0.6) / 2.0,
-                    1.0,
-                );
-            } else {
-                let bg = &self.renderer.theme.bg;
-                gl::ClearColor(bg[0], bg[1], bg[2], 1.0);
-            }
-            gl::Clear(gl::COLOR_BUFFER_BIT);
-        }
-
-        // Regrow atlas between frames if it filled up during the last render.
-        self.renderer.glyph_cache.try_regrow();
+        // Premultiplied, as the compositor blends a transparent window.
+        let bg = self.renderer.theme().bg;
+        let opacity = self.config.window.opacity;
+        self.renderer.begin_frame(size.width, size.height, [bg[0] * opacity, bg[1] * opacity, bg[2] * opacity, opacity]);
 
         // Calculate viewport offset for tab bar
+        self.tab_manager.focus_active_tab();
         let tab_bar_height = if self.tab_manager.count() > 1 {
-            self.renderer.draw_tab_bar(&self.tab_manager, w);
+            let dragged = self.tab_drag.as_ref().filter(|d| d.dragging).map(|d| d.index);
+            self.renderer.draw_tab_bar(&self.tab_manager, w, &self.config.tabs.title_template, dragged);
             self.renderer.cell_height()
         } else {
             0.0
@@ -1395,7 +2894,11 @@ impl KoiState {
 
         // Render all panes in the active tab
         let viewport_h = (h - tab_bar_height).max(0.0);
-        let layouts = self.tab_manager.active_layouts(w, viewport_h);
+        let now = std::time::Instant::now();
+        let (cw, ch) = (self.renderer.cell_width(), self.renderer.cell_height());
+        let panes_animating = self.tab_manager.settle_transitions(now, w, viewport_h, cw, ch);
+        self.animating |= panes_animating;
+        let layouts = self.tab_manager.active_animated_layouts(w, viewport_h, now);
 
         // Tab switch slide animation offset
         let anim_done = self.tab_animation.as_ref().map(|a| a.done()).unwrap_or(true);
@@ -1403,71 +2906,254 @@ impl KoiState {
             self.tab_animation = None;
             0.0
         } else {
-            let offset = self.tab_animation.as_ref().unwrap().offset_fraction() * w;
-            // Keep requesting redraws during animation
-            self.needs_redraw = true;
-            self.window.request_redraw();
-            offset
+            self.animating = true;
+            self.tab_animation.as_ref().unwrap().offset_fraction() * w
         };
 
+        // Inactive panes' fade, except on the pane a divider is being
+        // dragged over, which the user is sizing and wants to see.
+        let dim = (self.dim_inactive && layouts.len() > 1).then(|| {
+            let bg = self.renderer.theme().bg;
+            [bg[0], bg[1], bg[2], self.config.window.inactive_pane_dim.clamp(0.0, 1.0)]
+        });
+        let drag_hover = self.divider_drag.as_ref().and_then(|_| self.pane_under_cursor());
+
         if let Some(tab) = self.tab_manager.active_tab() {
             let active_pane_id = tab.pane_tree.active_pane_id();
 
             // Cursor blink: 500ms on, 500ms off — only in active pane
             let blink_on = (self.cursor_blink.elapsed().as_millis() % 1000) < 500;
 
+            // Panes showing scrollback, to wash over once their text is drawn.
+            let mut in_history = Vec::new();
+            // Panes that get column rulers on top of their text.
+            let mut ruled = Vec::new();
+            // Marked rows on screen, per pane.
+            let mut marked = Vec::new();
+            // Tints over whole panes (inactive fade, bell flash), laid over
+            // the text once every grid is drawn.
+            let mut washes = Vec::new();
+            // Mid-animation, panes' grids are still their old size: clip
+            // each to the rect it is drawn in.
+            if panes_animating {
+                self.renderer.flush(w, h);
+            }
             for layout in &layouts {
                 if let Some(pane) = tab.panes.get(&layout.pane_id) {
+                    let _span = tracing::info_span!("draw_grid", pane = layout.pane_id).entered();
                     let is_active = layout.pane_id == active_pane_id;
-                    let term = pane.term.lock();
+                    let mut term = pane.term.lock();
+                    // History may have wrapped past the selection since last frame.
+                    selection::revalidate(&mut term);
+                    if term.grid().display_offset() > 0 {
+                        in_history.push(layout.pane_id);
+                    }
                     use alacritty_terminal::term::TermMode;
-                    let dec_show = term.mode().contains(TermMode::SHOW_CURSOR);
-                    let show_cursor = is_active && blink_on && dec_show;
+                    let ruler = &self.config.ruler;
+                    let alt_screen = term.mode().contains(TermMode::ALT_SCREEN);
+                    if !ruler.columns.is_empty() && (ruler.show_on_alt_screen || !alt_screen) {
+                        ruled.push(layout.pane_id);
+                    }
+                    if !pane.marks.is_empty() {
+                        marked.push((layout.clone(), pane.marks.visible_rows(&*term)));
+                    }
+                    let cursor = renderer::cursor::CursorView {
+                        active: is_active,
+                        blink_on,
+                        window_focused: self.window_focused,
+                    };
+                    if is_active {
+                        self.cursor_blinks = cursor.blinks(&*term);
+                    }
+                    // The renderer takes the lock again only to snapshot
+                    // what changed; the PTY thread can parse meanwhile.
+                    let grid_lines = (term.grid().display_offset(), term.screen_lines());
+                    drop(term);
                     self.renderer.draw_grid(
-                        &*term,
+                        layout.pane_id,
+                        &pane.term,
                         layout.x + anim_x_offset,
                         layout.y + tab_bar_height,
-                        show_cursor,
+                        cursor,
+                        self.config.selection.style,
+                        self.hovered_link
+                            .as_ref()
+                            .filter(|(pane_id, _)| *pane_id == layout.pane_id)
+                            .map(|(_, link)| (link.start, link.end)),
                     );
-                    drop(term);
+                    // Highlight matches in the same rect pass as the cell
+                    // backgrounds: queued after a later flush, they'd paint
+                    // over glyphs, and over italics overhanging the match.
+                    if let Some(search) = self.search.as_ref().filter(|s| s.pane_id == layout.pane_id) {
+                        let origin = (layout.x + anim_x_offset, layout.y + tab_bar_height);
+                        let cell = (self.renderer.cell_width(), self.renderer.cell_height());
+                        let (display_offset, screen_lines) = grid_lines;
+                        for r in search.highlights(display_offset, screen_lines, origin, cell) {
+                            self.renderer.draw_rect(r.x, r.y, r.w, r.h, [r.r, r.g, r.b, r.a]);
+                        }
+                    }
+                    let rect = [layout.x + anim_x_offset, layout.y + tab_bar_height, layout.width, layout.height];
+                    if let Some(dim) = dim.filter(|_| !is_active && drag_hover != Some(layout.pane_id)) {
+                        washes.push((rect, dim));
+                    }
+                    // Bell flash: a warm orange tint over the pane that rang.
+                    if self.bell_flashes.get(&layout.pane_id).is_some_and(|&until| now < until) {
+                        washes.push((rect, [1.0, 0.85, 0.6, 0.35]));
+                    }
+                    if panes_animating {
+                        let rect = (layout.x + anim_x_offset, layout.y + tab_bar_height, layout.width, layout.height);
+                        self.renderer.flush_clipped(w, h, rect);
+                    }
+                }
+            }
+
+            if !washes.is_empty() {
+                self.renderer.flush(w, h);
+                for ([x, y, width, height], color) in washes {
+                    self.renderer.draw_rect(x, y, width, height, color);
+                }
+                self.renderer.flush_blended(w, h);
+            }
+            if !ruled.is_empty() {
+                self.draw_rulers(&layouts, &ruled, anim_x_offset, tab_bar_height, w, h);
+            }
+            if !marked.is_empty() {
+                self.draw_marks(&marked, anim_x_offset, tab_bar_height, w, h);
+            }
+            if self.config.scrolling.dim_history {
+                self.draw_history_cue(&layouts, &in_history, active_pane_id, anim_x_offset, tab_bar_height, w, h);
+            }
+            // Re-borrow: drawing the cue needed `self` mutably.
+            let Some(tab) = self.tab_manager.active_tab() else { return };
+
+            // Scrollbar on panes the user has scrolled into history.
+            for layout in &layouts {
+                let Some(alpha) = self.scrollbar_alpha(layout.pane_id) else { continue };
+                let Some(pane) = tab.panes.get(&layout.pane_id) else { continue };
+                let extent = scrollbar::Extent::of(&*pane.term.lock());
+                if !self.scroll_origin.show_scrollbar(layout.pane_id, extent.display_offset) {
+                    continue;
+                }
+                let rect = (layout.x + anim_x_offset, layout.y + tab_bar_height, layout.width, layout.height);
+                if let Some(t) = scrollbar::thumb(rect, extent) {
+                    let o = self.renderer.theme().overlay0;
+                    self.renderer.draw_rect(t.x, t.y, t.w, t.h, overlay::with_alpha([o[0], o[1], o[2], 0.8], alpha));
+                    // Keep frames coming until it has faded out.
+                    self.animating = true;
+                }
+            }
+
+            // Muted-bell badge, top-left; clicking it unmutes.
+            for layout in &layouts {
+                if tab.panes.get(&layout.pane_id).is_some_and(|p| p.bell.is_muted()) {
+                    let o = self.renderer.theme().overlay0;
+                    let badge_bg = [o[0], o[1], o[2], 0.85];
+                    let badge_fg = self.renderer.theme().fg4();
+                    let ly = layout.y + tab_bar_height;
+                    self.renderer.draw_string(layout.x, ly, BELL_MUTED_BADGE, badge_fg, badge_bg);
+                }
+            }
+
+            for layout in &layouts {
+                if tab.panes.get(&layout.pane_id).is_some_and(|p| p.io_crashed) {
+                    let cw = self.renderer.cell_width();
+                    let ch = self.renderer.cell_height();
+                    let red = self.renderer.theme().colors[1];
+                    let banner_bg = [red[0], red[1], red[2], 0.95];
+                    let y = layout.y + tab_bar_height + ((layout.height - ch) / 2.0).max(0.0);
+                    let opts = TextOptions::clipped((layout.width / cw) as usize);
+                    self.renderer.draw_text(layout.x, y, IO_CRASHED_BANNER, [1.0, 1.0, 1.0, 1.0], banner_bg, &opts);
+                }
+            }
+
+            // Broadcast: every pane that gets the typing says so, top center.
+            if tab.broadcast {
+                let cw = self.renderer.cell_width();
+                let red = self.renderer.theme().colors[1];
+                let badge_bg = [red[0], red[1], red[2], 0.95];
+                for layout in &layouts {
+                    let (x, y) = (layout.x + anim_x_offset, layout.y + tab_bar_height);
+                    let lx = x + ((layout.width - BROADCAST_BADGE.len() as f32 * cw) / 2.0).max(0.0);
+                    let opts = TextOptions::clipped((layout.width / cw) as usize);
+                    self.renderer.draw_text(lx, y, BROADCAST_BADGE, [1.0, 1.0, 1.0, 1.0], badge_bg, &opts);
                 }
             }
 
-            // Draw scroll position indicator when scrolled up.
+            // Long pastes still being fed, top-right.
             for layout in &layouts {
+                let feeder = self.pastes.iter().find(|p| p.pane_id == layout.pane_id);
+                if let Some(done) = feeder.and_then(|p| p.progress()) {
+                    let cw = self.renderer.cell_width();
+                    let label = format!(" PASTING {:.0}% ", done * 100.0);
+                    let lx = layout.x + layout.width - label.len() as f32 * cw;
+                    let b = self.renderer.theme().border;
+                    let badge_bg = [b[0], b[1], b[2], 0.9];
+                    self.renderer.draw_string(lx, layout.y + tab_bar_height, &label, [1.0, 1.0, 1.0, 1.0], badge_bg);
+                }
+            }
+
+            // Active pane badges: mouse reporting on, and a reset hint when an
+            // exited app left mouse reporting / the alt screen switched on.
+            if let Some(layout) = layouts.iter().find(|l| l.pane_id == active_pane_id) {
                 if let Some(pane) = tab.panes.get(&layout.pane_id) {
-                    let term = pane.term.lock();
-                    let offset = term.grid().display_offset();
-                    if offset > 0 {
-                        use alacritty_terminal::grid::Dimensions;
-                        let total = term.grid().history_size();
-                        let label = format!(" [{}/{}] ", offset, total);
-                        let label_w = label.len() as f32 * self.renderer.cell_width();
-                        let lx = layout.x + layout.width - label_w;
-                        let ly = layout.y + tab_bar_height;
-                        let badge_bg = [
-                            self.renderer.theme.border[0],
-                            self.renderer.theme.border[1],
-                            self.renderer.theme.border[2],
-                            0.9,
-                        ];
-                        let badge_fg = [1.0, 1.0, 1.0, 1.0];
-                        self.renderer.draw_string(lx, ly, &label, badge_fg, badge_bg);
+                    use alacritty_terminal::term::TermMode;
+                    let mouse_on = pane.term.lock().mode().intersects(TermMode::MOUSE_MODE);
+                    let cw = self.renderer.cell_width();
+                    let ch = self.renderer.cell_height();
+                    let bottom = layout.y + tab_bar_height + layout.height - ch;
+                    let o = self.renderer.theme().overlay0;
+                    let badge_bg = [o[0], o[1], o[2], 0.85];
+                    let badge_fg = self.renderer.theme().fg4();
+                    if mouse_on {
+                        let label = " MOUSE ";
+                        let lx = layout.x + layout.width - label.len() as f32 * cw;
+                        self.renderer.draw_string(lx, bottom, label, badge_fg, badge_bg);
+                    }
+                    if let Some(what) = pane.stuck_modes() {
+                        let hint = format!(" {} \u{2014} {} to reset pane ", what, Action::ResetPane.shortcut());
+                        let b = self.renderer.theme().border;
+                        let hint_bg = [b[0], b[1], b[2], 0.9];
+                        let max_cells = (layout.width / cw) as usize;
+                        let opts = TextOptions::clipped(max_cells);
+                        self.renderer.draw_text(layout.x, bottom, &hint, [1.0, 1.0, 1.0, 1.0], hint_bg, &opts);
                     }
                 }
             }
 
-            // Draw pane dividers (2px lines between panes)
+            // Headers over split panes: what runs there, and where.
+            if self.config.window.pane_headers && layouts.len() > 1 {
+                let cw = self.renderer.cell_width();
+                let ch = self.renderer.cell_height();
+                let theme = self.renderer.theme();
+                let (bg, fg, dim) = (theme.surface0, theme.fg4(), theme.overlay0);
+                let home = config::home_dir();
+                for layout in &layouts {
+                    let Some(pane) = tab.panes.get(&layout.pane_id) else { continue };
+                    let header = match &pane.foreground {
+                        Some(running) => title::pane_header(&running.name, running.cwd.as_deref(), home.as_deref()),
+                        None => format!(" {}", self.tab_manager.title_for_pane(layout.pane_id).unwrap_or_default()),
+                    };
+                    let (x, y) = (layout.x + anim_x_offset, layout.y + tab_bar_height - ch);
+                    let fg = if layout.pane_id == active_pane_id { fg } else { [dim[0], dim[1], dim[2], 1.0] };
+                    self.renderer.draw_rect(x, y, layout.width, ch, [bg[0], bg[1], bg[2], 1.0]);
+                    let opts = TextOptions::clipped((layout.width / cw) as usize);
+                    self.renderer.draw_text(x, y, &header, fg, [bg[0], bg[1], bg[2], 1.0], &opts);
+                }
+            }
+
+            // Draw pane dividers (lines between panes, thicker on HiDPI)
             if layouts.len() > 1 {
-                let o = &self.renderer.theme.overlay0;
-                let divider_color = [o[0], o[1], o[2], 1.0];
+                let style = divider::DividerStyle::scaled(self.window.scale_factor());
+                let t = style.thickness;
+                let d = self.renderer.theme().divider;
+                let divider_color = [d[0], d[1], d[2], 1.0];
                 for layout in &layouts {
                     // Right edge divider
                     if layout.x + layout.width < w - 1.0 {
                         self.renderer.draw_rect(
-                            layout.x + layout.width - 1.0,
+                            layout.x + layout.width - t / 2.0,
                             layout.y + tab_bar_height,
-                            2.0,
+                            t,
                             layout.height,
                             divider_color,
                         );
@@ -1476,92 +3162,162 @@ impl KoiState {
                     if layout.y + layout.height < viewport_h - 1.0 {
                         self.renderer.draw_rect(
                             layout.x,
-                            layout.y + layout.height + tab_bar_height - 1.0,
+                            layout.y + layout.height + tab_bar_height - t / 2.0,
                             layout.width,
-                            2.0,
+                            t,
                             divider_color,
                         );
                     }
                 }
 
+                // The divider under the pointer, brighter and wider.
+                let hovered = self.divider_hover.as_ref().and_then(|(path, _)| {
+                    self.tab_manager.active_dividers(w, viewport_h).into_iter().find(|d| &d.path == path)
+                });
+                if let Some(d) = hovered {
+                    let c = self.renderer.theme().divider_hover;
+                    let color = [c[0], c[1], c[2], 1.0];
+                    let (t, len) = (style.hover_thickness, d.perp_end - d.perp_start);
+                    match d.split {
+                        panes::Split::Vertical => self.renderer.draw_rect(
+                            d.position - t / 2.0, d.perp_start + tab_bar_height, t, len, color,
+                        ),
+                        panes::Split::Horizontal => self.renderer.draw_rect(
+                            d.perp_start, d.position - t / 2.0 + tab_bar_height, len, t, color,
+                        ),
+                    }
+                }
+
                 // Highlight the active pane with a border
                 if let Some(active_layout) =
                     layouts.iter().find(|l| l.pane_id == active_pane_id)
                 {
-                    let border_color = self.renderer.theme.border;
+                    let mut border_color = self.renderer.theme().border;
+                    let mut thickness = 2.0;
+                    // Focus pulse: thicker and brighter, fading back.
+                    if let Some(a) = self.focus_pulse.as_ref().and_then(|p| p.alpha()) {
+                        self.animating = true;
+                        for c in &mut border_color[..3] {
+                            *c += (1.0 - *c) * 0.4 * a;
+                        }
+                        border_color[3] = border_color[3].max(a);
+                        thickness += (2.0 * a).round();
+                    }
                     self.renderer.draw_pane_border(
                         active_layout.x,
                         active_layout.y + tab_bar_height,
                         active_layout.width,
                         active_layout.height,
-                        2.0,
+                        thickness,
                         border_color,
                     );
                 }
             }
+
+            // Broadcast: every pane gets a red border, so it's never on
+            // unnoticed.
+            if tab.broadcast {
+                let red = self.renderer.theme().colors[1];
+                let red = [red[0], red[1], red[2], 1.0];
+                for layout in &layouts {
+                    let y = layout.y + tab_bar_height;
+                    self.renderer.draw_pane_border(layout.x + anim_x_offset, y, layout.width, layout.height, 2.0, red);
+                }
+            }
+        }
+
+        self.draw_too_small_warning(tab_bar_height, w, viewport_h);
+        if self.resize_mode.is_some() {
+            self.draw_resize_mode(tab_bar_height, w, h);
         }
 
-        // Draw search bar and match highlights.
+        // Draw the search bar.
         if let Some(ref search) = self.search {
             let ch = self.renderer.cell_height();
             let cw = self.renderer.cell_width();
+            let layout = layouts.iter().find(|l| l.pane_id == search.pane_id);
 
-            // Highlight matches in the visible viewport.
-            if let Some(pane) = self.tab_manager.active_pane() {
-                let term = pane.term.lock();
-                let display_offset = term.grid().display_offset() as i32;
-                use alacritty_terminal::grid::Dimensions;
-                let screen_lines = term.screen_lines() as i32;
-                let viewport_top = -display_offset;
-                let viewport_bottom = viewport_top + screen_lines - 1;
-
-                // Find the active pane layout for positioning.
-                let layouts = self.tab_manager.active_layouts(w, (h - tab_bar_height).max(0.0));
-                let active_id = self.tab_manager.active_tab()
-                    .map(|t| t.pane_tree.active_pane_id());
-                let layout = active_id.and_then(|id| layouts.iter().find(|l| l.pane_id == id));
-
-                if let Some(layout) = layout {
-                    let qlen = search.query.len();
-                    for (i, &(line, col)) in search.matches.iter().enumerate() {
-                        if line.0 >= viewport_top && line.0 <= viewport_bottom {
-                            let vy = (line.0 - viewport_top) as f32;
-                            let is_current = i == search.current;
-                            let color = if is_current {
-                                [1.0, 0.6, 0.0, 0.5] // orange for current
-                            } else {
-                                [1.0, 0.9, 0.0, 0.3] // yellow for others
-                            };
-                            for j in 0..qlen {
-                                self.renderer.draw_rect(
-                                    layout.x + (col + j) as f32 * cw,
-                                    layout.y + tab_bar_height + vy * ch,
-                                    cw,
-                                    ch,
-                                    color,
-                                );
-                            }
-                        }
-                    }
-                }
-            }
+            // Search bar: along the searched pane's bottom edge when the tab
+            // is split, so it's clear which pane it searches.
+            let (bar_x, bar_y, bar_w) = SearchState::bar_rect(layout, layouts.len() > 1, (w, h), tab_bar_height, ch);
+            let s0 = self.renderer.theme().surface0;
+            let bar_bg = [s0[0], s0[1], s0[2], 0.95];
+            let bar_fg = self.renderer.theme().fg4();
+            self.renderer.draw_rect(bar_x, bar_y, bar_w, ch, bar_bg);
+            let opts = TextOptions::clipped(((bar_w - 8.0).max(0.0) / cw) as usize);
+            self.renderer.draw_text(bar_x + 8.0, bar_y, &search.status(), bar_fg, bar_bg, &opts);
+        }
 
-            // Search bar at the bottom.
-            let bar_y = h - ch;
-            let s0 = &self.renderer.theme.surface0;
+        if let Some(ref rename) = self.tab_rename {
+            let (cw, ch) = (self.renderer.cell_width(), self.renderer.cell_height());
+            let s0 = self.renderer.theme().surface0;
             let bar_bg = [s0[0], s0[1], s0[2], 0.95];
-            let bar_fg = self.renderer.theme.fg4();
-            self.renderer.draw_rect(0.0, bar_y, w, ch, bar_bg);
-            let count_str = if search.matches.is_empty() {
-                if search.query.is_empty() {
-                    "Search: ".to_string()
-                } else {
-                    format!("Search: {} (no matches)", search.query)
+            let fg = self.renderer.theme().fg4();
+            let dim = [fg[0], fg[1], fg[2], 0.6];
+            let label = format!("Rename tab: {}", rename.text);
+            let hint = "Enter keeps, empty follows the shell, Esc cancels";
+            let cols = ((w - 16.0).max(0.0) / cw) as usize;
+            self.renderer.draw_rect(0.0, h - ch, w, ch, bar_bg);
+            self.renderer.draw_two_columns(8.0, h - ch, cols, (&label, fg), (hint, dim), bar_bg);
+        }
+
+        if self.pending_paste.is_some() {
+            self.draw_paste_confirmation(&layouts, tab_bar_height, w);
+        }
+
+        // --- Command palette ---
+        if let Some(ref palette) = self.palette {
+            // Flush terminal content first so the palette draws on top.
+            self.renderer.flush(w, h);
+
+            let cw = self.renderer.cell_width();
+            let ch = self.renderer.cell_height();
+            const PALETTE_COLS: usize = 56;
+            const PALETTE_ROWS: usize = 10;
+
+            let card_w = (PALETTE_COLS as f32 * cw).min(w);
+            let card_x = ((w - card_w) / 2.0).max(0.0);
+            let card_y = ch * 2.0;
+            let rows = palette.visible().count().clamp(1, PALETTE_ROWS);
+            let card_h = (rows + 2) as f32 * ch;
+
+            let s0 = self.renderer.theme().surface0;
+            let card_bg = [s0[0], s0[1], s0[2], 0.97];
+            let fg = self.renderer.theme().fg4();
+            let dim = [fg[0], fg[1], fg[2], 0.6];
+            let clear = [0.0, 0.0, 0.0, 0.0];
+            self.renderer.draw_rect(card_x, card_y, card_w, card_h, card_bg);
+            let border = self.renderer.theme().border;
+            self.renderer.draw_pane_border(card_x, card_y, card_w, card_h, 1.0, border);
+
+            // Text runs from one cell inside the left edge to one inside the right.
+            let inner_cols = ((card_w / cw) as usize).saturating_sub(2);
+            let prompt = format!("> {}", palette.query);
+            self.renderer.draw_text(card_x + cw, card_y, &prompt, fg, clear, &TextOptions::clipped(inner_cols));
+
+            // Keep the selected row in view when the list is longer than the card.
+            let first = palette.selected.saturating_sub(PALETTE_ROWS - 1);
+            let mut row_y = card_y + ch * 1.5;
+            if palette.visible().next().is_none() {
+                self.renderer.draw_string(card_x + cw, row_y, "No matching commands", dim, clear);
+            }
+            for (i, entry) in palette.visible().enumerate().skip(first).take(PALETTE_ROWS) {
+                if i == palette.selected {
+                    let sel = self.renderer.theme().selection;
+                    self.renderer.draw_rect(card_x + 1.0, row_y, card_w - 2.0, ch, [sel[0], sel[1], sel[2], 0.6]);
                 }
-            } else {
-                format!("Search: {} ({}/{})", search.query, search.current + 1, search.matches.len())
-            };
-            self.renderer.draw_string(8.0, bar_y, &count_str, bar_fg, bar_bg);
+                self.renderer.draw_two_columns(
+                    card_x + cw,
+                    row_y,
+                    inner_cols,
+                    (&entry.label, fg),
+                    (&entry.hint, dim),
+                    clear,
+                );
+                row_y += ch;
+            }
+
+            self.renderer.flush_blended(w, h);
         }
 
         // --- About overlay ---
@@ -1593,12 +3349,12 @@ impl KoiState {
             let card_y = (h - card_h) / 2.0;
 
             // Card background with subtle border
-            let bg = self.renderer.theme.bg4();
+            let bg = self.renderer.theme().bg4();
             let card_bg = [bg[0], bg[1], bg[2], 0.97 * alpha];
             self.renderer.draw_rect(card_x, card_y, card_w, card_h, card_bg);
 
             // Accent border (1px)
-            let accent = self.renderer.theme.border;
+            let accent = self.renderer.theme().border;
             let border_c = [accent[0], accent[1], accent[2], 0.6 * alpha];
             self.renderer.draw_pane_border(card_x, card_y, card_w, card_h, 1.0, border_c);
 
@@ -1642,115 +3398,459 @@ impl KoiState {
                     }
                 }
             }
-
-            // --- Text below the fish ---
-            let fg = self.renderer.theme.fg4();
-            let fg_a = [fg[0], fg[1], fg[2], alpha];
-            let dim = [fg[0], fg[1], fg[2], 0.5 * alpha];
-            let clear = [0.0, 0.0, 0.0, 0.0]; // transparent bg for text
-
-            let text_x = card_x + cw * 2.0;
-            let mut row = card_y + fish_h_cells + ch * 4.0;
-
-            // Title + version
-            let version_line = format!("Koi v{}", env!("CARGO_PKG_VERSION"));
-            let title_x = card_x + (card_w - version_line.len() as f32 * cw) / 2.0;
-            self.renderer.draw_string(title_x, row, &version_line, fg_a, clear);
-            row += ch * 1.5;
-
-            // Tagline
-            let tagline = "GPU-accelerated terminal emulator";
-            let tag_x = card_x + (card_w - tagline.len() as f32 * cw) / 2.0;
-            self.renderer.draw_string(tag_x, row, tagline, dim, clear);
-            row += ch * 2.5;
-
-            // Shortcuts in two columns
-            let shortcuts: &[(&str, &str)] = &[
-                ("Cmd+T",         "New tab"),
-                ("Cmd+D / +Shift+D", "Split V / H"),
-                ("Cmd+W",         "Close pane"),
-                ("Cmd+F",         "Search"),
-                ("Cmd+Shift+T",   "Dark / light"),
-                ("Cmd+=/-/0",     "Zoom"),
-            ];
-
-            for (key, desc) in shortcuts {
-                let line = format!("  {:22}{}", key, desc);
-                self.renderer.draw_string(text_x, row, &line, dim, clear);
-                row += ch;
+
+            // --- Text below the fish ---
+            let fg = self.renderer.theme().fg4();
+            let fg_a = [fg[0], fg[1], fg[2], alpha];
+            let dim = [fg[0], fg[1], fg[2], 0.5 * alpha];
+            let clear = [0.0, 0.0, 0.0, 0.0]; // transparent bg for text
+
+            let text_x = card_x + cw * 2.0;
+            let mut row = card_y + fish_h_cells + ch * 4.0;
+
+            // Title + version
+            let version_line = format!("Koi v{}", env!("CARGO_PKG_VERSION"));
+            let title_x = card_x + (card_w - version_line.len() as f32 * cw) / 2.0;
+            self.renderer.draw_string(title_x, row, &version_line, fg_a, clear);
+            row += ch * 1.5;
+
+            // Tagline
+            let tagline = "GPU-accelerated terminal emulator";
+            let tag_x = card_x + (card_w - tagline.len() as f32 * cw) / 2.0;
+            self.renderer.draw_string(tag_x, row, tagline, dim, clear);
+            row += ch * 2.5;
+
+            // Shortcuts in two columns
+            let shortcuts: &[(&str, &str)] = &[
+                ("Cmd+T",         "New tab"),
+                ("Cmd+D / +Shift+D", "Split V / H"),
+                ("Cmd+W",         "Close pane"),
+                ("Cmd+F",         "Search"),
+                ("Cmd+Shift+T",   "Dark / light"),
+                ("Cmd+=/-/0",     "Zoom"),
+            ];
+
+            for (key, desc) in shortcuts {
+                let line = format!("  {:22}{}", key, desc);
+                self.renderer.draw_string(text_x, row, &line, dim, clear);
+                row += ch;
+            }
+
+            row += ch;
+            let dismiss = "Press any key to close";
+            let dx = card_x + (card_w - dismiss.len() as f32 * cw) / 2.0;
+            self.renderer.draw_string(dx, row, dismiss, dim, clear);
+
+            // Flush overlay with alpha blending for scrim/card transparency.
+            self.renderer.flush_blended(w, h);
+        } else {
+            self.renderer.flush(w, h);
+        }
+
+        // --- Right-click menu, above everything else ---
+        if let Some(menu) = &self.context_menu {
+            let cw = self.renderer.cell_width();
+            let ch = self.renderer.cell_height();
+            let (menu_w, menu_h) = ContextMenu::size((cw, ch));
+
+            let s0 = self.renderer.theme().surface0;
+            self.renderer.draw_rect(menu.x, menu.y, menu_w, menu_h, [s0[0], s0[1], s0[2], 0.97]);
+            let border = self.renderer.theme().border;
+            self.renderer.draw_pane_border(menu.x, menu.y, menu_w, menu_h, 1.0, border);
+
+            let fg = self.renderer.theme().fg4();
+            let dim = [fg[0], fg[1], fg[2], 0.6];
+            let clear = [0.0, 0.0, 0.0, 0.0];
+            let inner_cols = ContextMenu::cols() - 2;
+            for (i, entry) in context_menu::ENTRIES.iter().enumerate() {
+                let row_y = menu.row_y(i, ch);
+                if menu.hovered == Some(i) {
+                    let sel = self.renderer.theme().selection;
+                    self.renderer.draw_rect(menu.x + 1.0, row_y, menu_w - 2.0, ch, [sel[0], sel[1], sel[2], 0.6]);
+                }
+                self.renderer.draw_two_columns(menu.x + cw, row_y, inner_cols, (entry.label, fg), (entry.hint, dim), clear);
+            }
+
+            self.renderer.flush_blended(w, h);
+        }
+
+        self.window.swap_buffers();
+        self.frame_pacer.frame_drawn(std::time::Instant::now());
+    }
+
+    /// Handle an event for the window, saying whether that closes it or
+    /// quits koi.
+    fn window_event(&mut self, event: WindowEvent) -> Exit {
+        match event {
+            WindowEvent::CloseRequested => return Exit::Window,
+            WindowEvent::ModifiersChanged(mods) => {
+                self.modifiers = mods.state();
+                self.update_hovered_link();
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.handle_cursor_moved(position);
+            }
+            WindowEvent::Focused(focused) => {
+                self.window_focused = focused;
+                // Coming back, start the blink on so the cursor shows at once.
+                self.cursor_blink = std::time::Instant::now();
+                self.sync_focus_reports();
+                self.follow_appearance();
+                self.needs_redraw = true;
+                self.window.request_redraw();
+            }
+            WindowEvent::ThemeChanged(_) => self.follow_appearance(),
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } => {
+                if self.is_ctrl_click() {
+                    self.secondary_click = true;
+                    self.handle_other_mouse_button(mouse::BUTTON_RIGHT, ElementState::Pressed);
+                } else {
+                    self.handle_mouse_press();
+                }
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Released,
+                button: MouseButton::Left,
+                ..
+            } => {
+                // Release as whatever the press was, even if Ctrl is up by now.
+                if self.secondary_click {
+                    self.handle_other_mouse_button(mouse::BUTTON_RIGHT, ElementState::Released);
+                    self.secondary_click = false;
+                } else {
+                    self.handle_mouse_release();
+                }
+            }
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Right,
+                ..
+            } => {
+                self.handle_other_mouse_button(mouse::BUTTON_RIGHT, state);
+            }
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Middle,
+                ..
+            } => {
+                // Middle-click on a tab closes it.
+                if let Some(idx) = self.tab_under_cursor() {
+                    if state == ElementState::Pressed {
+                        self.close_tab(idx);
+                    }
+                    return Exit::Stay;
+                }
+                // Middle-click goes to the pane it lands on.
+                if state == ElementState::Pressed {
+                    if let Some(pane_id) = self.pane_under_cursor() {
+                        self.change_focus(|tabs| tabs.focus_pane(pane_id));
+                        self.window.request_redraw();
+                    }
+                }
+                self.handle_other_mouse_button(mouse::BUTTON_MIDDLE, state);
+            }
+            WindowEvent::Resized(new_size) => {
+                self.handle_resize(new_size);
+            }
+            WindowEvent::RedrawRequested => {
+                self.render();
+            }
+            WindowEvent::KeyboardInput { event, .. } => return self.handle_keyboard(event.into()),
+            WindowEvent::MouseWheel { delta, phase, .. } => {
+                self.handle_scroll(delta, phase);
+            }
+            WindowEvent::Moved(_) => self.update_refresh_rate(),
+            WindowEvent::ScaleFactorChanged { .. } => {
+                self.update_refresh_rate();
+                let new_scale = self.window.scale_factor() as f32;
+                if (new_scale - self.scale).abs() > 0.01 {
+                    self.scale = new_scale;
+                    self.rebuild_renderer(false);
+                }
+            }
+            _ => {}
+        }
+        Exit::Stay
+    }
+
+    /// Handle an event from a pane's terminal. Returns true if that closed
+    /// the window's last pane.
+    fn user_event(&mut self, event: KoiEvent) -> bool {
+        match event {
+            KoiEvent::Wakeup(pane_id) => {
+                let _span = tracing::info_span!("wakeup").entered();
+                if let Some(search) = self.search.as_mut().filter(|s| s.pane_id == pane_id) {
+                    search.invalidate();
+                }
+                self.tab_manager.note_output(pane_id);
+                self.needs_redraw = true;
+                self.request_output_redraw();
+            }
+            KoiEvent::Title(title, pane_id) => {
+                // Sanitize: strip control chars, limit length.
+                let title: String = title.chars()
+                    .filter(|c| !c.is_control())
+                    .take(256)
+                    .collect();
+                // Prompts that retitle on every keystroke mostly repeat themselves.
+                if self.tab_manager.title_for_pane(pane_id) == Some(title.as_str()) {
+                    return false;
+                }
+                let count = self.tab_manager.count();
+                let label_chars = (count > 1)
+                    .then(|| self.renderer.tab_label_chars(self.window.inner_size().width as f32, count));
+                let tabs::Retitle::Changed { tab, label_changed } =
+                    self.tab_manager.set_tab_title_by_pane(pane_id, title.clone(), label_chars)
+                else {
+                    return false;
+                };
+                if label_changed {
+                    self.needs_redraw = true;
+                    self.window.request_redraw();
+                }
+                // Only update window title if the event came from the active tab.
+                if tab == self.tab_manager.active_index() {
+                    if let Some(title) = self.window_title.update(&title, std::time::Instant::now()) {
+                        self.window.set_title(&title);
+                    }
+                }
+            }
+            KoiEvent::ChildExit(pane_id, code) => {
+                log::info!("Pane {} exited with code {}", pane_id, code);
+                return self.pane_exited(pane_id);
+            }
+            KoiEvent::Bell(pane_id) => {
+                let now = std::time::Instant::now();
+                let threshold = self.config.bell.mute_threshold;
+                let Some(pane) = self.tab_manager.pane_mut(pane_id) else { return false };
+                let was_muted = pane.bell.is_muted();
+                let ring = pane.bell.on_bell(now, threshold);
+                if pane.bell.is_muted() != was_muted {
+                    log::info!("Pane {} bell muted after a bell flood", pane_id);
+                    self.needs_redraw = true;
+                    self.window.request_redraw();
+                }
+                if !ring {
+                    return false;
+                }
+                // A bell from a tab out of view only marks the tab bar.
+                let in_view = self.tab_manager.active_tab().is_some_and(|t| t.panes.contains_key(&pane_id));
+                if !in_view {
+                    self.tab_manager.note_bell(pane_id);
+                    self.needs_redraw = true;
+                    self.window.request_redraw();
+                    return false;
+                }
+                // Only the focused pane is heard; the others just flash.
+                if self.tab_manager.active_pane_id() == Some(pane_id) && self.beeps.allow(now) {
+                    self.beep();
+                }
+                self.bell_flashes.insert(pane_id, now + std::time::Duration::from_millis(150));
+                self.needs_redraw = true;
+                self.window.request_redraw();
+            }
+            KoiEvent::ClipboardStore(text) => {
+                clipboard_copy(&text);
+            }
+            KoiEvent::ClipboardLoad(_pane_id, formatter) => {
+                if let Some(text) = clipboard_paste() {
+                    let response = formatter(&text);
+                    if let Some(pane) = self.tab_manager.active_pane() {
+                        pane.send_bytes(response.into_bytes());
+                    }
+                }
+            }
+            KoiEvent::ColorRequest(pane_id, index, formatter) => {
+                // Answer with what the pane shows: its own colors over the theme.
+                if let Some(pane) = self.tab_manager.pane(pane_id) {
+                    let colors = renderer::grid::PaneColors::of(pane.term.lock().colors());
+                    let [r, g, b] = colors.get(self.renderer.theme(), index).map(|c| (c * 255.0).round() as u8);
+                    pane.send_bytes(formatter(Rgb { r, g, b }).into_bytes());
+                }
+            }
+            KoiEvent::PtyWrite(pane_id, bytes) => {
+                if let Some(pane) = self.tab_manager.pane(pane_id) {
+                    pane.send_bytes(bytes);
+                }
             }
+        }
+        false
+    }
 
-            row += ch;
-            let dismiss = "Press any key to close";
-            let dx = card_x + (card_w - dismiss.len() as f32 * cw) / 2.0;
-            self.renderer.draw_string(dx, row, dismiss, dim, clear);
+    /// Stop every pane's shell and PTY thread before the event loop exits,
+    /// rather than leaving background tabs to process teardown.
+    fn shutdown(&mut self) {
+        self.window_state().save();
+        let stuck = self.tab_manager.shutdown_all(std::time::Duration::from_secs(2));
+        if stuck > 0 {
+            log::warn!("{} PTY thread(s) still running at exit", stuck);
+        }
+    }
 
-            // Flush overlay with alpha blending for scrim/card transparency.
-            self.renderer.flush_blended(w, h);
-        } else {
-            self.renderer.flush(w, h);
+    /// This window's geometry, zoom and theme, for the next launch. A
+    /// theme still being previewed in the palette isn't the one kept.
+    fn window_state(&self) -> window_state::WindowState {
+        let size = if self.window.is_fullscreen() { self.windowed_size } else { self.window.inner_size() };
+        let size = size.to_logical::<f64>(self.window.scale_factor());
+        let position = self.window.outer_position();
+        let theme = self.palette_theme_origin.unwrap_or(self.theme);
+        window_state::WindowState {
+            width: size.width,
+            height: size.height,
+            x: position.map(|p| p.x),
+            y: position.map(|p| p.y),
+            font_size: self.font_size,
+            theme: Some(self.themes.name(theme).to_string()),
         }
+    }
 
-        if let Err(e) = self.gl_surface.swap_buffers(&self.gl_context) {
-            log::error!("swap_buffers failed: {}", e);
+    /// Fill the screen (the simple way if configured), or leave fullscreen
+    /// for the size the window had before. The grid follows in the
+    /// `Resized` that either brings.
+    fn toggle_fullscreen(&mut self) {
+        let leaving = self.window.is_fullscreen();
+        self.window.toggle_fullscreen(self.config.window.simple_fullscreen);
+        if leaving {
+            if let Some(size) = self.window.request_inner_size(self.windowed_size) {
+                self.handle_resize(size);
+            }
         }
     }
+
+    /// Pace animations to the monitor the window is on now.
+    fn update_refresh_rate(&mut self) {
+        self.frame_pacer.set_refresh_rate(self.window.refresh_rate_millihertz());
+    }
 }
 
 struct Koi {
     event_proxy: EventProxy,
-    font_size: f32,
-    scale: f32,
-    state: Option<KoiState>,
+    config: Config,
+    /// What the first window's first pane runs: the shell, stdin
+    /// (`--pager`) or `-e`'s command. Later windows start a shell.
+    first_pane: tabs::FirstPane,
+    /// The built-in themes and the user's, loaded at startup.
+    themes: themes::ThemeSet,
+    windows: HashMap<winit::window::WindowId, KoiState>,
+    /// The window whose GL context is current.
+    current: Option<winit::window::WindowId>,
+    /// Shared by every window's tabs, so pane ids say which window an
+    /// event is for.
+    pane_ids: tabs::PaneIds,
+    /// The GL config the first window picked, for the ones after it.
+    gl_config: Option<glutin::config::Config>,
+    /// User events sent before `resumed` created the first window.
+    pending: event::PendingEvents,
 }
 
 impl Koi {
-    fn new(event_proxy: EventProxy) -> Self {
+    fn new(event_proxy: EventProxy, config: Config, first_pane: tabs::FirstPane) -> Self {
         Self {
             event_proxy,
-            font_size: 14.0,
-            scale: 1.0,
-            state: None,
+            config,
+            first_pane,
+            themes: themes::ThemeSet::load(),
+            windows: HashMap::new(),
+            current: None,
+            pane_ids: tabs::PaneIds::default(),
+            gl_config: None,
+            pending: event::PendingEvents::default(),
         }
     }
-}
 
-impl ApplicationHandler<KoiEvent> for Koi {
-    fn resumed(&mut self, _event_loop: &ActiveEventLoop) {
-        // On macOS sleep/wake, GPU textures can be purged from VRAM.
-        // Invalidate the glyph atlas so all glyphs are re-uploaded.
-        if let Some(ref mut s) = self.state {
-            s.renderer.glyph_cache.invalidate();
-            s.needs_redraw = true;
-            s.window.request_redraw();
-            return;
+    /// Window `id`'s state, with its GL context made current for whatever
+    /// it draws or frees.
+    fn window(&mut self, id: winit::window::WindowId) -> Option<&mut KoiState> {
+        let s = self.windows.get_mut(&id)?;
+        if self.current != Some(id) {
+            s.window.make_current();
+            self.current = Some(id);
         }
-        let event_loop = _event_loop;
+        Some(s)
+    }
 
-        let window_attrs = WindowAttributes::default()
+    /// Open a window with one tab: at startup, and for Cmd+N.
+    fn open_window(&mut self, event_loop: &ActiveEventLoop) {
+        // Open as the last window closed: its size unless a grid is
+        // configured, its zoom and theme, and for the first window its
+        // position if that's still on a monitor.
+        let saved = window_state::WindowState::load();
+        let font_size = saved.as_ref().map_or(DEFAULT_FONT_SIZE, |s| s.font_size);
+
+        // A configured grid sizes the window from the font's cell size,
+        // measured here at the primary monitor's scale. The window may open
+        // at another scale; that is corrected once the renderer exists.
+        let grid = self.config.window.dimensions.map(|d| (d.columns, d.lines));
+        let inner_size: winit::dpi::Size = match grid {
+            Some(grid) => {
+                let monitor = event_loop.primary_monitor();
+                let scale = monitor.as_ref().map_or(1.0, |m| m.scale_factor());
+                let (cw, ch) = Renderer::measure_cell(renderer::FONT_FAMILY, font_size, scale as f32);
+                let max = monitor.map(|m| (m.size().width, m.size().height));
+                let (w, h) = terminal::initial_window_size(Some(grid), cw, ch, 0.0, scale, max);
+                winit::dpi::PhysicalSize::new(w, h).into()
+            }
+            None => {
+                let (w, h) = saved.as_ref().map_or(terminal::DEFAULT_WINDOW_SIZE, |s| (s.width, s.height));
+                winit::dpi::LogicalSize::new(w, h).into()
+            }
+        };
+        let translucent = self.config.window.opacity < 1.0;
+        let mut window_attrs = WindowAttributes::default()
             .with_title("Koi")
-            .with_inner_size(winit::dpi::LogicalSize::new(960, 600));
-
-        let template = ConfigTemplateBuilder::new().with_alpha_size(8);
-        let display_builder = DisplayBuilder::new().with_window_attributes(Some(window_attrs));
-
-        let (window, gl_config) = display_builder
-            .build(event_loop, template, |configs| {
-                configs
-                    .reduce(|accum, config| {
-                        // Prefer fewest MSAA samples — MSAA conflicts with
-                        // dual-source subpixel blending and wastes VRAM.
-                        if config.num_samples() < accum.num_samples() {
-                            config
-                        } else {
-                            accum
-                        }
-                    })
-                    .unwrap()
+            .with_inner_size(inner_size)
+            .with_transparent(translucent);
+        let on_screen = |&(x, y): &(i32, i32)| {
+            event_loop.available_monitors().any(|m| {
+                let (origin, size) = (m.position(), m.size());
+                (origin.x..origin.x + size.width as i32).contains(&x)
+                    && (origin.y..origin.y + size.height as i32).contains(&y)
             })
-            .unwrap();
+        };
+        let first = self.gl_config.is_none();
+        if let Some((x, y)) = saved.as_ref().and_then(|s| s.position()).filter(|_| first).filter(on_screen) {
+            window_attrs = window_attrs.with_position(winit::dpi::PhysicalPosition::new(x, y));
+        }
 
-        let window = window.unwrap();
+        // Later windows use the first one's GL config, on its display.
+        let (window, gl_config) = match &self.gl_config {
+            Some(gl_config) => {
+                let window = glutin_winit::finalize_window(event_loop, window_attrs, gl_config).expect("create window");
+                (window, gl_config.clone())
+            }
+            None => {
+                let template = ConfigTemplateBuilder::new().with_alpha_size(8).with_transparency(translucent);
+                let display_builder = DisplayBuilder::new().with_window_attributes(Some(window_attrs));
+
+                let (window, gl_config) = display_builder
+                    .build(event_loop, template, |configs| {
+                        configs
+                            .reduce(|accum, config| {
+                                // A config the compositor can see through, if asked
+                                // for one.
+                                let see_through = |c: &glutin::config::Config| c.supports_transparency().unwrap_or(false);
+                                if translucent && see_through(&config) != see_through(&accum) {
+                                    return if see_through(&config) { config } else { accum };
+                                }
+                                // Prefer fewest MSAA samples — MSAA conflicts with
+                                // dual-source subpixel blending and wastes VRAM.
+                                if config.num_samples() < accum.num_samples() {
+                                    config
+                                } else {
+                                    accum
+                                }
+                            })
+                            .unwrap()
+                    })
+                    .unwrap();
+                (window.unwrap(), gl_config)
+            }
+        };
+        self.gl_config = Some(gl_config.clone());
         let gl_display = gl_config.display();
 
         let context_attrs = ContextAttributesBuilder::new()
@@ -1819,293 +3919,170 @@ impl ApplicationHandler<KoiEvent> for Koi {
         // Setup terminal environment (TERM, COLORTERM).
         alacritty_terminal::tty::setup_env();
 
-        // Store scale factor for DPI-aware font rendering.
+        // Scale factor for DPI-aware font rendering.
         let scale = window.scale_factor() as f32;
-        self.scale = scale;
 
         // Create renderer — font is rasterized at font_size * scale for HiDPI.
-        let renderer = Renderer::new("IBM Plex Mono", self.font_size, scale);
+        let renderer =
+            Renderer::new(renderer::FONT_FAMILY, font_size, scale, &self.config.font);
         let cw = renderer.cell_width();
         let ch = renderer.cell_height();
         log::info!("Cell size: {}x{} (scale={})", cw, ch, scale);
 
+        // Start the shell at exactly the configured grid: re-fit the window
+        // with the real cell size, and size the grid from that rather than
+        // from a resize the platform may apply later. One tab, so no tab bar.
+        let mut size = size;
+        if grid.is_some() {
+            let max = window.current_monitor().map(|m| (m.size().width, m.size().height));
+            let (w, h) = terminal::initial_window_size(grid, cw, ch, 0.0, window.scale_factor(), max);
+            let fitted = winit::dpi::PhysicalSize::new(w, h);
+            if fitted != size {
+                let _ = window.request_inner_size(fitted);
+                size = fitted;
+            }
+        }
+
         // Cell dimensions are in physical pixels, so divide viewport directly.
-        let cols = (size.width as f32 / cw) as usize;
-        let rows = (size.height as f32 / ch) as usize;
-        let cols = cols.max(2);
-        let rows = rows.max(1);
+        let (cols, rows) = tabs::pane_grid_size(size.width as f32, size.height as f32, cw, ch);
         log::info!("Terminal grid: {}x{}", cols, rows);
 
         // Create tab manager with one initial tab
-        let tab_manager = TabManager::new(cols, rows, cw, ch, &self.event_proxy);
+        let tab_manager = TabManager::new(
+            cols,
+            rows,
+            cw,
+            ch,
+            &self.event_proxy,
+            self.config.working_directory.clone(),
+            self.config.terminal.clone(),
+            self.config.scrolling.lines,
+            self.config.window.pane_animation,
+            std::mem::take(&mut self.first_pane),
+            u64::from(window.id()),
+            self.pane_ids.clone(),
+            Box::new(tabs::PtyLauncher),
+        );
 
-        // Enforce minimum window size: 2 cells wide × 1 cell tall + room for tab bar.
-        let min_w = (cw * 2.0) as u32;
-        let min_h = (ch * 2.0) as u32; // 1 row + 1 row for tab bar
-        window.set_min_inner_size(Some(winit::dpi::PhysicalSize::new(min_w, min_h)));
+        let window = GlWindow { window, gl_context, gl_surface };
+        let mut state = KoiState::new(self.config.clone(), window, renderer, self.event_proxy.clone(), scale, tab_manager);
+        state.font_size = font_size;
+        state.themes = self.themes.clone();
+        let theme = self.config.window.theme.clone().or_else(|| saved.and_then(|s| s.theme));
+        match theme.map(|name| (state.themes.find(&name), name)) {
+            Some((Some(i), _)) => state.set_theme(i),
+            Some((None, name)) => log::warn!("No theme called {:?}", name),
+            None => {}
+        }
+        state.follow_appearance();
+        state.needs_redraw = true;
+        state.window.request_redraw();
+        let id = state.window.window.id();
+        self.windows.insert(id, state);
+        // The new window's context is current now.
+        self.current = Some(id);
+    }
 
-        self.state = Some(KoiState {
-            window,
-            gl_context,
-            gl_surface,
-            renderer,
-            tab_manager,
-            modifiers: ModifiersState::empty(),
-            cursor_pos: (0.0, 0.0),
-            cursor_blink: std::time::Instant::now(),
-            last_blink_on: true,
-            mouse_left_pressed: false,
-            needs_redraw: true,
-            scroll_accumulator: 0.0,
-            auto_scroll_delta: 0,
-            divider_drag: None,
-            last_click_time: std::time::Instant::now(),
-            click_count: 0,
-            bell_flash_until: None,
-            search: None,
-            tab_animation: None,
-            show_about: false,
-            about_opened_at: None,
-        });
+    /// Shut window `id`'s panes down and close it. Koi quits with its last
+    /// window.
+    fn close_window(&mut self, event_loop: &ActiveEventLoop, id: winit::window::WindowId) {
+        let Some(s) = self.window(id) else { return };
+        s.shutdown();
+        if let Some(state) = self.windows.remove(&id) {
+            // The renderer frees its GL objects before the context goes.
+            let KoiState { renderer, window, .. } = state;
+            drop(renderer);
+            drop(window);
+        }
+        self.current = None;
+        if self.windows.is_empty() {
+            event_loop.exit();
+        }
+    }
+}
+
+/// The window `event` is for: the one holding its pane, or for a
+/// clipboard store the focused one.
+fn window_for<K: Copy, W, R>(windows: &HashMap<K, KoiState<W, R>>, event: &KoiEvent) -> Option<K> {
+    let found = match event.pane_id() {
+        Some(pane_id) => windows.iter().find(|(_, s)| s.tab_manager.pane(pane_id).is_some()),
+        None => windows.iter().find(|(_, s)| s.window_focused).or_else(|| windows.iter().next()),
+    };
+    found.map(|(&id, _)| id)
+}
+
+impl ApplicationHandler<KoiEvent> for Koi {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        // On macOS sleep/wake, GPU textures can be purged from VRAM.
+        // Invalidate the glyph atlas so all glyphs are re-uploaded.
+        if !self.windows.is_empty() {
+            let ids: Vec<_> = self.windows.keys().copied().collect();
+            for id in ids {
+                let Some(s) = self.window(id) else { continue };
+                s.renderer.invalidate_glyphs();
+                s.needs_redraw = true;
+                s.window.request_redraw();
+            }
+            return;
+        }
+        self.open_window(event_loop);
 
-        // Trigger initial draw
-        if let Some(s) = &self.state {
-            s.window.request_redraw();
+        // The PTYs started above may already have sent a title or wakeup.
+        for event in self.pending.take() {
+            self.user_event(event_loop, event);
         }
     }
 
     fn window_event(
         &mut self,
         event_loop: &ActiveEventLoop,
-        _window_id: winit::window::WindowId,
+        window_id: winit::window::WindowId,
         event: WindowEvent,
     ) {
-        let Some(s) = &mut self.state else { return };
-        match event {
-            WindowEvent::CloseRequested => {
-                event_loop.exit();
-            }
-            WindowEvent::ModifiersChanged(mods) => {
-                s.modifiers = mods.state();
-            }
-            WindowEvent::CursorMoved { position, .. } => {
-                s.handle_cursor_moved(position);
-            }
-            WindowEvent::MouseInput {
-                state: ElementState::Pressed,
-                button: MouseButton::Left,
-                ..
-            } => {
-                s.handle_mouse_press();
-            }
-            WindowEvent::MouseInput {
-                state: ElementState::Released,
-                button: MouseButton::Left,
-                ..
-            } => {
-                s.handle_mouse_release();
-            }
-            WindowEvent::MouseInput {
-                state,
-                button: MouseButton::Right,
-                ..
-            } => {
-                s.handle_other_mouse_button(2, state);
-            }
-            WindowEvent::MouseInput {
-                state,
-                button: MouseButton::Middle,
-                ..
-            } => {
-                s.handle_other_mouse_button(1, state);
-            }
-            WindowEvent::Resized(new_size) => {
-                s.handle_resize(new_size);
-            }
-            WindowEvent::RedrawRequested => {
-                s.render();
-            }
-            WindowEvent::KeyboardInput { event: key_event, .. } => {
-                // Bind the result before branching: folding this `if` into a match guard
-                // (as clippy's collapsible_match suggests) would move `key_event` inside the
-                // guard, which the compiler rejects (E0507) since it is consumed by value.
-                let should_exit =
-                    s.handle_keyboard(key_event, &self.event_proxy, &mut self.font_size, self.scale);
-                if should_exit {
-                    event_loop.exit();
-                }
-            }
-            WindowEvent::MouseWheel { delta, .. } => {
-                s.handle_scroll(delta);
-            }
-            WindowEvent::ScaleFactorChanged { .. } => {
-                let new_scale = s.window.scale_factor() as f32;
-                if (new_scale - self.scale).abs() > 0.01 {
-                    self.scale = new_scale;
-                    s.rebuild_renderer(self.font_size, self.scale);
+        let Some(s) = self.window(window_id) else { return };
+        let exit = s.window_event(event);
+        let open = std::mem::take(&mut s.new_window);
+        match exit {
+            Exit::Stay => {}
+            Exit::Window => self.close_window(event_loop, window_id),
+            Exit::App => {
+                let ids: Vec<_> = self.windows.keys().copied().collect();
+                for id in ids {
+                    self.close_window(event_loop, id);
                 }
+                return;
             }
-            _ => {}
+        }
+        if open {
+            self.open_window(event_loop);
         }
     }
 
     fn user_event(&mut self, event_loop: &ActiveEventLoop, event: KoiEvent) {
-        let Some(s) = &mut self.state else { return };
-        match event {
-            KoiEvent::Wakeup => {
-                s.needs_redraw = true;
-                s.window.request_redraw();
-            }
-            KoiEvent::Title(title, pane_id) => {
-                s.needs_redraw = true;
-                // Sanitize: strip control chars, limit length.
-                let title: String = title.chars()
-                    .filter(|c| !c.is_control())
-                    .take(256)
-                    .collect();
-                s.tab_manager.set_tab_title_by_pane(pane_id, title.clone());
-                // Only update window title if the event came from the active tab.
-                if s.tab_manager.active_tab().is_some_and(|t| t.panes.contains_key(&pane_id)) {
-                    s.window.set_title(&title);
-                }
-            }
-            KoiEvent::ChildExit(pane_id, code) => {
-                s.needs_redraw = true;
-                s.auto_scroll_delta = 0;
-                s.mouse_left_pressed = false;
-                s.divider_drag = None;
-                log::info!("Pane {} exited with code {}", pane_id, code);
-                if s.tab_manager.close_pane_by_id(pane_id) {
-                    event_loop.exit();
-                    return;
-                }
-                // Resize surviving panes to fill freed space.
-                let cw = s.renderer.cell_width();
-                let ch = s.renderer.cell_height();
-                let size = s.window.inner_size();
-                let tab_bar_h = if s.tab_manager.count() > 1 { ch } else { 0.0 };
-                let h = size.height as f32 - tab_bar_h;
-                s.tab_manager.resize_all(size.width as f32, h, cw, ch);
-                s.window.request_redraw();
-            }
-            KoiEvent::Bell => {
-                #[cfg(target_os = "macos")]
-                {
-                    extern "C" { fn NSBeep(); }
-                    unsafe { NSBeep(); }
-                }
-                #[cfg(target_os = "windows")]
-                {
-                    use windows::Win32::System::Diagnostics::Debug::MessageBeep;
-                    use windows::Win32::UI::WindowsAndMessaging::MB_OK;
-                    unsafe { let _ = MessageBeep(MB_OK); }
-                }
-                #[cfg(all(unix, not(target_os = "macos")))]
-                {
-                    use winit::window::UserAttentionType;
-                    s.window.request_user_attention(Some(UserAttentionType::Critical));
-                }
-                s.bell_flash_until = Some(std::time::Instant::now()
-                    + std::time::Duration::from_millis(150));
-                s.needs_redraw = true;
-                s.window.request_redraw();
-            }
-            KoiEvent::ClipboardStore(text) => {
-                clipboard_copy(&text);
-            }
-            KoiEvent::ClipboardLoad(_pane_id, formatter) => {
-                if let Some(text) = clipboard_paste() {
-                    let response = formatter(&text);
-                    if let Some(pane) = s.tab_manager.active_pane() {
-                        pane.notifier.send_bytes(response.into_bytes());
-                    }
-                }
-            }
+        if self.windows.is_empty() {
+            self.pending.push(event);
+            return;
+        }
+        // Events from a closed window's panes have nowhere to go.
+        let Some(id) = window_for(&self.windows, &event) else { return };
+        let Some(s) = self.window(id) else { return };
+        if s.user_event(event) {
+            self.close_window(event_loop, id);
         }
     }
 
     fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
-        if let Some(s) = &mut self.state {
-            // Auto-scroll during selection drag past viewport edge.
-            if s.mouse_left_pressed && s.auto_scroll_delta != 0 {
-                if let Some(pane) = s.tab_manager.active_pane() {
-                    use alacritty_terminal::grid::{Dimensions, Scroll};
-                    use alacritty_terminal::term::TermMode;
-                    let mut term = pane.term.lock();
-
-                    // Skip auto-scroll when the app owns mouse input (vim, tmux).
-                    let mode = term.mode();
-                    if mode.intersects(TermMode::MOUSE_MODE) && mode.contains(TermMode::SGR_MOUSE) {
-                        drop(term);
-                        s.auto_scroll_delta = 0;
-                    } else {
-                        term.scroll_display(Scroll::Delta(s.auto_scroll_delta));
-
-                        // Extend selection to the edge row.
-                        let ch = s.renderer.cell_height();
-                        let rows = {
-                            let tab_bar_h = if s.tab_manager.count() > 1 { ch } else { 0.0 };
-                            let size = s.window.inner_size();
-                            let viewport_h = (size.height as f32 - tab_bar_h).max(0.0);
-                            let layouts = s.tab_manager.active_layouts(size.width as f32, viewport_h);
-                            let active_id = s.tab_manager.active_tab()
-                                .map(|t| t.pane_tree.active_pane_id());
-                            active_id
-                                .and_then(|id| layouts.iter().find(|l| l.pane_id == id))
-                                .map(|l| (l.height / ch) as i32)
-                                .unwrap_or(1)
-                        };
-
-                        let edge_line = if s.auto_scroll_delta < 0 { 0usize } else { (rows - 1).max(0) as usize };
-                        let cols = term.grid().columns();
-                        let edge_col = if s.auto_scroll_delta < 0 { 0 } else { cols.saturating_sub(1) };
-                        let edge_side = if s.auto_scroll_delta < 0 {
-                            alacritty_terminal::index::Side::Left
-                        } else {
-                            alacritty_terminal::index::Side::Right
-                        };
-                        let display_offset = term.grid().display_offset();
-                        let point = alacritty_terminal::term::viewport_to_point(
-                            display_offset,
-                            alacritty_terminal::index::Point::new(
-                                edge_line,
-                                alacritty_terminal::index::Column(edge_col),
-                            ),
-                        );
-                        if let Some(ref mut sel) = term.selection {
-                            sel.update(point, edge_side);
-                        }
-                        drop(term);
-                    }
-                }
-                s.needs_redraw = true;
-                s.window.request_redraw();
-                // Tick faster while auto-scrolling for smooth UX.
-                event_loop.set_control_flow(winit::event_loop::ControlFlow::WaitUntil(
-                    std::time::Instant::now() + std::time::Duration::from_millis(50),
-                ));
-                return;
-            }
-
-            // Expire bell flash and trigger a redraw to clear it.
-            if let Some(until) = s.bell_flash_until {
-                if std::time::Instant::now() >= until {
-                    s.bell_flash_until = None;
-                    s.needs_redraw = true;
-                    s.window.request_redraw();
-                }
-            }
-
-            // Only redraw when cursor blink phase actually changes.
-            let blink_on = (s.cursor_blink.elapsed().as_millis() % 1000) < 500;
-            if blink_on != s.last_blink_on {
-                s.last_blink_on = blink_on;
-                s.needs_redraw = true;
-                s.window.request_redraw();
+        let ids: Vec<_> = self.windows.keys().copied().collect();
+        let mut wake: Option<std::time::Instant> = None;
+        for id in ids {
+            let Some(s) = self.window(id) else { continue };
+            match s.housekeeping() {
+                Some(due) => wake = Some(wake.map_or(due, |wake| wake.min(due))),
+                None => self.close_window(event_loop, id),
             }
-            event_loop.set_control_flow(winit::event_loop::ControlFlow::WaitUntil(
-                std::time::Instant::now() + std::time::Duration::from_millis(500),
-            ));
+        }
+        if let Some(wake) = wake {
+            event_loop.set_control_flow(winit::event_loop::ControlFlow::WaitUntil(wake));
         }
     }
 }
@@ -2120,10 +4097,16 @@ fn main() {
         let _ = AttachConsole(ATTACH_PARENT_PROCESS);
         let _ = SetProcessDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2);
     }
+    // For `eval "$(koi --shell-integration)"` in the shell's rc file.
+    if tabs::has_flag(std::env::args(), "--shell-integration") {
+        print!("{}", prompts::SHELL_SNIPPET);
+        return;
+    }
     env_logger::init();
+    let _profile = profile::requested().then(profile::install).flatten();
     fonts_registrar::register_bundled_fonts();
     let event_loop = EventLoop::<KoiEvent>::with_user_event().build().unwrap();
     let event_proxy = EventProxy::new(event_loop.create_proxy());
-    let mut app = Koi::new(event_proxy);
+    let mut app = Koi::new(event_proxy, Config::load(), tabs::FirstPane::from_args(std::env::args()));
     event_loop.run_app(&mut app).unwrap();
 }
@@ -0,0 +1,319 @@
+//! Line marks in a pane's scrollback: dropped by hand with Cmd+M, and
+//! jumped between with Cmd+Shift+M / Cmd+Option+M.
+//!
+//! A mark is a line's index counted from the top of the scrollback, which
+//! stays put while output pushes lines into history. Once the history is
+//! full, every new line drops one off the top and all indices shift by one
+//! without the grid saying so; `sync` measures that shift by finding where
+//! the newest history lines of the previous sync went. It looks for several
+//! lines in a row, as one line alone could be any of a run of repeats.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use alacritty_terminal::grid::Dimensions;
+use alacritty_terminal::index::{Column, Line};
+use alacritty_terminal::term::{Term, TermMode};
+
+/// Marks kept per pane; adding one more drops the topmost.
+pub const MAX_MARKS: usize = 100;
+
+/// History lines an anchor is matched by.
+const ANCHOR_LINES: usize = 8;
+
+/// The newest history lines as of the last sync.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Anchor {
+    /// Index of the newest.
+    index: usize,
+    /// Of the lines ending at `index`, oldest first.
+    fingerprints: Vec<u64>,
+}
+
+#[derive(Debug, Default)]
+pub struct Marks {
+    /// Indices from the top of the scrollback, ascending.
+    lines: Vec<usize>,
+    anchor: Option<Anchor>,
+    /// The mark last jumped to, so repeated jumps walk the list even when
+    /// the view can't scroll any further.
+    jumped: Option<usize>,
+}
+
+fn fingerprint<T>(term: &Term<T>, line: Line) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    let row = &term.grid()[line];
+    for col in 0..term.columns() {
+        row[Column(col)].c.hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// Marks only apply to the primary screen; the alternate screen has no
+/// history and is redrawn wholesale.
+fn on_primary<T>(term: &Term<T>) -> bool {
+    !term.mode().contains(TermMode::ALT_SCREEN)
+}
+
+impl Marks {
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Mark `line`, or unmark it if it already is.
+    pub fn toggle<T>(&mut self, term: &Term<T>, line: Line) {
+        if !on_primary(term) {
+            return;
+        }
+        self.sync(term);
+        let Ok(index) = usize::try_from(term.grid().history_size() as i32 + line.0) else { return };
+        match self.lines.binary_search(&index) {
+            Ok(i) => {
+                self.lines.remove(i);
+            }
+            Err(i) => {
+                self.lines.insert(i, index);
+                if self.lines.len() > MAX_MARKS {
+                    self.lines.remove(0);
+                }
+            }
+        }
+        self.set_anchor(term);
+    }
+
+    /// Follow lines trimmed off the top of a full history since the last
+    /// sync. Marks that went with them are dropped.
+    pub fn sync<T>(&mut self, term: &Term<T>) {
+        if self.lines.is_empty() || !on_primary(term) {
+            return;
+        }
+        let history = term.grid().history_size();
+        if let Some(anchor) = self.anchor.take() {
+            let top = -(history as i32);
+            let fingerprint_at = |index: usize| fingerprint(term, Line(top + index as i32));
+            // Lines above the top that have since been trimmed can't be
+            // compared; the rest must all match.
+            let matches = |index: usize| {
+                anchor.fingerprints.iter().rev().enumerate().all(|(back, &fingerprint)| {
+                    index.checked_sub(back).is_none_or(|i| fingerprint_at(i) == fingerprint)
+                })
+            };
+            // History only grows or loses lines off the top, so the anchor
+            // can only have moved up; the nearest match is the likeliest.
+            let found = (0..=anchor.index.min(history.saturating_sub(1))).rev().find(|&i| matches(i));
+            match found {
+                Some(index) => {
+                    let shift = anchor.index - index;
+                    self.lines.retain_mut(|l| match l.checked_sub(shift) {
+                        Some(moved) => {
+                            *l = moved;
+                            true
+                        }
+                        None => false,
+                    });
+                    self.jumped = self.jumped.and_then(|j| j.checked_sub(shift));
+                }
+                // Cleared, or everything scrolled away since.
+                None => self.lines.clear(),
+            }
+        }
+        self.set_anchor(term);
+    }
+
+    fn set_anchor<T>(&mut self, term: &Term<T>) {
+        let history = term.grid().history_size();
+        self.anchor = (history > 0 && !self.lines.is_empty()).then(|| Anchor {
+            index: history - 1,
+            fingerprints: (history.saturating_sub(ANCHOR_LINES)..history)
+                .map(|i| fingerprint(term, Line(i as i32 - history as i32)))
+                .collect(),
+        });
+    }
+
+    /// Viewport rows (0 = top) of marks currently on screen.
+    pub fn visible_rows<T>(&self, term: &Term<T>) -> Vec<usize> {
+        if !on_primary(term) {
+            return Vec::new();
+        }
+        let top = term.grid().history_size() - term.grid().display_offset();
+        let rows = term.screen_lines();
+        self.lines
+            .iter()
+            .filter_map(|&l| l.checked_sub(top).filter(|&row| row < rows))
+            .collect()
+    }
+
+    /// The next mark below (`forward`) or above the last one jumped to, or
+    /// the viewport's middle if that is off screen. Returned as a grid line.
+    pub fn step<T>(&mut self, term: &Term<T>, forward: bool) -> Option<Line> {
+        if !on_primary(term) {
+            return None;
+        }
+        self.sync(term);
+        let history = term.grid().history_size();
+        let top = history - term.grid().display_offset();
+        let rows = term.screen_lines();
+        let from = self.jumped.filter(|j| (top..top + rows).contains(j)).unwrap_or(top + rows / 2);
+        let target = if forward {
+            self.lines.iter().copied().find(|&l| l > from)
+        } else {
+            self.lines.iter().copied().rev().find(|&l| l < from)
+        }?;
+        self.jumped = Some(target);
+        Some(Line(target as i32 - history as i32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alacritty_terminal::event::VoidListener;
+    use alacritty_terminal::grid::Scroll;
+    use alacritty_terminal::term::Config as TermConfig;
+    use alacritty_terminal::vte::ansi::{Processor, StdSyncHandler};
+
+    use super::*;
+    use crate::terminal::TerminalSize;
+
+    fn term(history: usize) -> Term<VoidListener> {
+        let config = TermConfig { scrolling_history: history, ..TermConfig::default() };
+        Term::new(config, &TerminalSize::new(20, 4), VoidListener)
+    }
+
+    fn print(term: &mut Term<VoidListener>, text: &str) {
+        let mut parser: Processor<StdSyncHandler> = Processor::new();
+        parser.advance(term, text.as_bytes());
+    }
+
+    /// The text of the line each mark is on.
+    fn marked(marks: &Marks, term: &Term<VoidListener>) -> Vec<String> {
+        let top = -(term.grid().history_size() as i32);
+        marks
+            .lines
+            .iter()
+            .map(|&l| {
+                let row = &term.grid()[Line(top + l as i32)];
+                (0..term.columns()).map(|c| row[Column(c)].c).collect::<String>().trim_end().to_string()
+            })
+            .collect()
+    }
+
+    fn cursor_line(term: &Term<VoidListener>) -> Line {
+        term.grid().cursor.point.line
+    }
+
+    #[test]
+    fn marks_follow_output_into_history() {
+        let mut term = term(100);
+        let mut marks = Marks::default();
+        print(&mut term, "one\r\ntwo");
+        marks.toggle(&term, cursor_line(&term));
+        for i in 0..10 {
+            print(&mut term, &format!("\r\nline {i}"));
+        }
+        marks.sync(&term);
+        assert_eq!(marked(&marks, &term), ["two"]);
+
+        marks.toggle(&term, Line(-7));
+        assert!(marks.is_empty(), "toggling a marked line unmarks it");
+    }
+
+    #[test]
+    fn marks_survive_history_trimming() {
+        let mut term = term(20);
+        let mut marks = Marks::default();
+        for i in 0..30 {
+            print(&mut term, &format!("line {i}\r\n"));
+        }
+        print(&mut term, "keep");
+        marks.toggle(&term, cursor_line(&term));
+        marks.toggle(&term, Line(-12));
+        assert_eq!(marked(&marks, &term), ["line 15", "keep"]);
+
+        // 5 more lines push 5 off the top of the full history.
+        for i in 0..5 {
+            print(&mut term, &format!("\r\nmore {i}"));
+        }
+        marks.sync(&term);
+        assert_eq!(marked(&marks, &term), ["line 15", "keep"]);
+
+        // Enough to trim "line 15" away too.
+        for i in 0..8 {
+            print(&mut term, &format!("\r\nlast {i}"));
+        }
+        marks.sync(&term);
+        assert_eq!(marked(&marks, &term), ["keep"]);
+    }
+
+    #[test]
+    fn repeated_lines_dont_hide_trimming() {
+        let mut term = term(20);
+        let mut marks = Marks::default();
+        for i in 0..16 {
+            print(&mut term, &format!("a{i}\r\n"));
+        }
+        for _ in 0..8 {
+            print(&mut term, "same\r\n");
+        }
+        marks.toggle(&term, Line(-16));
+        assert_eq!(marked(&marks, &term), ["a5"]);
+
+        // The newest history line is "same" before and after.
+        for _ in 0..3 {
+            print(&mut term, "same\r\n");
+        }
+        marks.sync(&term);
+        assert_eq!(marked(&marks, &term), ["a5"]);
+    }
+
+    #[test]
+    fn visible_rows_and_jumps() {
+        let mut term = term(100);
+        let mut marks = Marks::default();
+        for i in 0..20 {
+            print(&mut term, &format!("line {i}\r\n"));
+        }
+        // Lines 3 and 10, and the live screen's top row (line 17).
+        for line in [-14, -7, 0] {
+            marks.toggle(&term, Line(line));
+        }
+        assert_eq!(marks.visible_rows(&term), [0]);
+
+        // Center `line` the way a jump does.
+        let center = |term: &mut Term<VoidListener>, line: Line| {
+            let offset = (term.screen_lines() as i32 / 2 - line.0).max(0);
+            term.scroll_display(Scroll::Delta(offset - term.grid().display_offset() as i32));
+        };
+
+        // Up from the middle of the screen: the on-screen mark, then the
+        // next one up, even though the view hasn't moved.
+        assert_eq!(marks.step(&term, false), Some(Line(0)));
+        assert_eq!(marks.step(&term, false), Some(Line(-7)));
+        center(&mut term, Line(-7));
+        assert_eq!(marks.visible_rows(&term), [2]);
+        assert_eq!(marks.step(&term, false), Some(Line(-14)));
+        center(&mut term, Line(-14));
+        assert_eq!(marks.step(&term, false), None);
+        assert_eq!(marks.step(&term, true), Some(Line(-7)));
+        center(&mut term, Line(-7));
+        assert_eq!(marks.step(&term, true), Some(Line(0)));
+        center(&mut term, Line(0));
+        assert_eq!(marks.step(&term, true), None);
+    }
+
+    #[test]
+    fn count_is_capped() {
+        let mut term = term(500);
+        let mut marks = Marks::default();
+        print(&mut term, "\r\n\r\n\r\n\r\n");
+        for i in 0..=MAX_MARKS {
+            print(&mut term, &format!("line {i}\r\n"));
+            marks.toggle(&term, cursor_line(&term) - 1);
+        }
+        assert_eq!(marks.lines.len(), MAX_MARKS);
+        assert_eq!(marked(&marks, &term)[0], "line 1");
+    }
+}
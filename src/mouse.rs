@@ -0,0 +1,206 @@
+use alacritty_terminal::term::TermMode;
+
+/// Button codes for mouse reports (Cb before encoding).
+pub const BUTTON_LEFT: u8 = 0;
+pub const BUTTON_MIDDLE: u8 = 1;
+pub const BUTTON_RIGHT: u8 = 2;
+/// No button: releases in the legacy encodings, and motion with nothing held.
+pub const NO_BUTTON: u8 = 3;
+pub const WHEEL_UP: u8 = 64;
+pub const WHEEL_DOWN: u8 = 65;
+/// Added to the button code for motion while a button is held.
+pub const MOTION: u8 = 32;
+/// Modifier bits added to the button code.
+pub const MOD_SHIFT: u8 = 4;
+pub const MOD_ALT: u8 = 8;
+pub const MOD_CTRL: u8 = 16;
+
+/// Highest coordinate the legacy X10 encoding can carry in a single byte.
+const X10_MAX_COORD: usize = 255 - 32;
+/// Highest coordinate the UTF-8 encoding (DECSET 1005) can carry in two bytes.
+const UTF8_MAX_COORD: usize = 0x7ff - 32;
+
+/// A mouse event to report to the app in a pane.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MouseReport {
+    pub button: u8,
+    /// 1-based column, as computed from the pane layout. May lie past the
+    /// grid when the pointer is in the partial-cell margin.
+    pub col: usize,
+    /// 1-based line within the pane's viewport.
+    pub line: usize,
+    pub pressed: bool,
+    /// `MOD_*` bits held during the event.
+    pub modifiers: u8,
+}
+
+pub fn modifier_bits(shift: bool, alt: bool, ctrl: bool) -> u8 {
+    (if shift { MOD_SHIFT } else { 0 })
+        | (if alt { MOD_ALT } else { 0 })
+        | (if ctrl { MOD_CTRL } else { 0 })
+}
+
+/// Whether a left press is a macOS-style secondary click (Ctrl+click).
+pub fn is_ctrl_click(button: u8, modifiers: u8, enabled: bool) -> bool {
+    enabled && button == BUTTON_LEFT && modifiers & MOD_CTRL != 0
+}
+
+/// Rewrite a Ctrl+click report as the right click it stands for. Ctrl was
+/// consumed by the translation, so it isn't reported as a modifier too
+/// (matching xterm).
+pub fn as_secondary_click(report: MouseReport) -> MouseReport {
+    MouseReport { button: BUTTON_RIGHT, modifiers: report.modifiers & !MOD_CTRL, ..report }
+}
+
+/// Encode `report` for the app's current mouse mode, clamping coordinates to
+/// the pane's `cols` x `rows` grid. None when mouse reporting is off.
+pub fn encode(report: MouseReport, mode: TermMode, cols: usize, rows: usize) -> Option<Vec<u8>> {
+    if !mode.intersects(TermMode::MOUSE_MODE) {
+        return None;
+    }
+    let col = report.col.clamp(1, cols.max(1));
+    let line = report.line.clamp(1, rows.max(1));
+
+    if mode.contains(TermMode::SGR_MOUSE) {
+        let suffix = if report.pressed { 'M' } else { 'm' };
+        let button = report.button + report.modifiers;
+        return Some(format!("\x1b[<{};{};{}{}", button, col, line, suffix).into_bytes());
+    }
+
+    // X10/normal protocol: CSI M Cb Cx Cy, each byte offset by 32. Releases
+    // don't say which button; they're all reported as button 3.
+    let button = if report.pressed { report.button } else { NO_BUTTON } + report.modifiers;
+    let mut bytes = vec![0x1b, b'[', b'M', button + 32];
+    if mode.contains(TermMode::UTF8_MOUSE) {
+        // The same, with coordinates past 95 as two-byte UTF-8.
+        for v in [col, line] {
+            let c = char::from_u32((v.min(UTF8_MAX_COORD) + 32) as u32).unwrap_or(' ');
+            bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+        }
+    } else {
+        bytes.extend([col, line].map(|v| (v.min(X10_MAX_COORD) as u8) + 32));
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(col: usize, line: usize) -> MouseReport {
+        MouseReport { button: BUTTON_LEFT, col, line, pressed: true, modifiers: 0 }
+    }
+
+    fn sgr() -> TermMode {
+        TermMode::MOUSE_REPORT_CLICK | TermMode::SGR_MOUSE
+    }
+
+    #[test]
+    fn no_report_without_mouse_mode() {
+        assert_eq!(encode(press(1, 1), TermMode::SGR_MOUSE, 80, 24), None);
+    }
+
+    #[test]
+    fn sgr_inside_grid_is_unchanged() {
+        let bytes = encode(press(80, 24), sgr(), 80, 24).unwrap();
+        assert_eq!(bytes, b"\x1b[<0;80;24M");
+        let bytes = encode(press(1, 1), sgr(), 80, 24).unwrap();
+        assert_eq!(bytes, b"\x1b[<0;1;1M");
+    }
+
+    #[test]
+    fn sgr_clamps_margin_to_last_cell() {
+        // Right/bottom partial-cell margin reports one past the grid.
+        let bytes = encode(press(81, 25), sgr(), 80, 24).unwrap();
+        assert_eq!(bytes, b"\x1b[<0;80;24M");
+    }
+
+    #[test]
+    fn sgr_clamps_zero_to_first_cell() {
+        let bytes = encode(press(0, 0), sgr(), 80, 24).unwrap();
+        assert_eq!(bytes, b"\x1b[<0;1;1M");
+    }
+
+    #[test]
+    fn sgr_release_and_motion() {
+        let release = MouseReport { pressed: false, ..press(3, 4) };
+        assert_eq!(encode(release, sgr(), 80, 24).unwrap(), b"\x1b[<0;3;4m");
+        let drag = MouseReport { button: BUTTON_LEFT + MOTION, ..press(90, 2) };
+        assert_eq!(encode(drag, sgr(), 80, 24).unwrap(), b"\x1b[<32;80;2M");
+        let wheel = MouseReport { button: WHEEL_DOWN, ..press(5, 30) };
+        assert_eq!(encode(wheel, sgr(), 80, 24).unwrap(), b"\x1b[<65;5;24M");
+    }
+
+    #[test]
+    fn x10_clamps_and_offsets() {
+        let mode = TermMode::MOUSE_REPORT_CLICK;
+        let bytes = encode(press(81, 25), mode, 80, 24).unwrap();
+        assert_eq!(bytes, vec![0x1b, b'[', b'M', 32, 80 + 32, 24 + 32]);
+        let release = MouseReport { pressed: false, ..press(1, 1) };
+        assert_eq!(encode(release, mode, 80, 24).unwrap(), vec![0x1b, b'[', b'M', 35, 33, 33]);
+    }
+
+    #[test]
+    fn modifiers_are_added_to_button() {
+        let shift_alt = MouseReport { modifiers: modifier_bits(true, true, false), ..press(1, 1) };
+        assert_eq!(encode(shift_alt, sgr(), 80, 24).unwrap(), b"\x1b[<12;1;1M");
+        let ctrl_wheel = MouseReport { button: WHEEL_UP, modifiers: MOD_CTRL, ..press(1, 1) };
+        assert_eq!(encode(ctrl_wheel, sgr(), 80, 24).unwrap(), b"\x1b[<80;1;1M");
+        let release = MouseReport { pressed: false, modifiers: MOD_SHIFT, ..press(1, 1) };
+        let bytes = encode(release, TermMode::MOUSE_REPORT_CLICK, 80, 24).unwrap();
+        assert_eq!(bytes[3], 3 + 4 + 32);
+    }
+
+    #[test]
+    fn ctrl_click_reports_right_button_without_ctrl() {
+        let click = MouseReport { modifiers: MOD_CTRL, ..press(5, 6) };
+        assert!(is_ctrl_click(click.button, click.modifiers, true));
+        let right = as_secondary_click(click);
+        assert_eq!(encode(right, sgr(), 80, 24).unwrap(), b"\x1b[<2;5;6M");
+        let release = MouseReport { pressed: false, ..right };
+        assert_eq!(encode(release, sgr(), 80, 24).unwrap(), b"\x1b[<2;5;6m");
+
+        // Other modifiers survive the translation.
+        let shifted = MouseReport { modifiers: MOD_CTRL | MOD_SHIFT, ..press(5, 6) };
+        let bytes = encode(as_secondary_click(shifted), sgr(), 80, 24).unwrap();
+        assert_eq!(bytes, b"\x1b[<6;5;6M");
+    }
+
+    #[test]
+    fn ctrl_click_translation_can_be_disabled() {
+        assert!(!is_ctrl_click(BUTTON_LEFT, MOD_CTRL, false));
+        assert!(!is_ctrl_click(BUTTON_LEFT, MOD_SHIFT, true));
+        assert!(!is_ctrl_click(BUTTON_MIDDLE, MOD_CTRL, true));
+        // Without translation Ctrl+click is a modified left click.
+        let click = MouseReport { modifiers: MOD_CTRL, ..press(1, 1) };
+        assert_eq!(encode(click, sgr(), 80, 24).unwrap(), b"\x1b[<16;1;1M");
+    }
+
+    #[test]
+    fn x10_saturates_past_encodable_range() {
+        let mode = TermMode::MOUSE_REPORT_CLICK;
+        let bytes = encode(press(300, 2), mode, 400, 24).unwrap();
+        assert_eq!(bytes[4], 255);
+        // Column 223 is the last one with its own byte.
+        assert_eq!(encode(press(222, 1), mode, 400, 24).unwrap()[4], 254);
+        assert_eq!(encode(press(223, 1), mode, 400, 24).unwrap()[4], 255);
+        assert_eq!(encode(press(224, 1), mode, 400, 24).unwrap()[4], 255);
+    }
+
+    #[test]
+    fn utf8_encodes_wide_coordinates_in_two_bytes() {
+        let mode = TermMode::MOUSE_REPORT_CLICK | TermMode::UTF8_MOUSE;
+        assert_eq!(encode(press(95, 3), mode, 400, 24).unwrap(), vec![0x1b, b'[', b'M', 32, 127, 35]);
+        // 224 + 32 = U+0100.
+        assert_eq!(encode(press(224, 3), mode, 400, 24).unwrap(), vec![0x1b, b'[', b'M', 32, 0xc4, 0x80, 35]);
+        let right = MouseReport { button: BUTTON_RIGHT, modifiers: MOD_CTRL, ..press(1, 1) };
+        assert_eq!(encode(right, mode, 80, 24).unwrap(), vec![0x1b, b'[', b'M', 2 + 16 + 32, 33, 33]);
+    }
+
+    #[test]
+    fn motion_without_a_button() {
+        let moved = MouseReport { button: NO_BUTTON + MOTION, ..press(4, 5) };
+        let mode = TermMode::MOUSE_MOTION | TermMode::SGR_MOUSE;
+        assert_eq!(encode(moved, mode, 80, 24).unwrap(), b"\x1b[<35;4;5M");
+    }
+}
@@ -0,0 +1,73 @@
+use std::time::{Duration, Instant};
+
+/// A transient overlay that shows at full opacity for `hold`, then fades out
+/// linearly over `fade`.
+#[derive(Clone, Copy, Debug)]
+pub struct Fade {
+    start: Instant,
+    hold: Duration,
+    fade: Duration,
+}
+
+impl Fade {
+    pub fn new(hold: Duration, fade: Duration) -> Self {
+        Self::starting_at(Instant::now(), hold, fade)
+    }
+
+    pub fn starting_at(start: Instant, hold: Duration, fade: Duration) -> Self {
+        Self { start, hold, fade }
+    }
+
+    /// Opacity at `now` in 0..=1, or None once fully faded.
+    pub fn alpha_at(&self, now: Instant) -> Option<f32> {
+        let elapsed = now.saturating_duration_since(self.start);
+        if elapsed < self.hold {
+            return Some(1.0);
+        }
+        let into_fade = elapsed - self.hold;
+        if into_fade >= self.fade {
+            return None;
+        }
+        Some(1.0 - into_fade.as_secs_f32() / self.fade.as_secs_f32())
+    }
+
+    pub fn alpha(&self) -> Option<f32> {
+        self.alpha_at(Instant::now())
+    }
+}
+
+/// Scale the alpha channel of `color` by `alpha`.
+pub fn with_alpha(color: [f32; 4], alpha: f32) -> [f32; 4] {
+    [color[0], color[1], color[2], color[3] * alpha]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: fn(u64) -> Duration = Duration::from_millis;
+
+    #[test]
+    fn holds_then_fades_then_ends() {
+        let t0 = Instant::now();
+        let fade = Fade::starting_at(t0, MS(1000), MS(200));
+        assert_eq!(fade.alpha_at(t0), Some(1.0));
+        assert_eq!(fade.alpha_at(t0 + MS(999)), Some(1.0));
+        let mid = fade.alpha_at(t0 + MS(1100)).unwrap();
+        assert!((mid - 0.5).abs() < 1e-3);
+        assert_eq!(fade.alpha_at(t0 + MS(1200)), None);
+    }
+
+    #[test]
+    fn zero_fade_ends_after_hold() {
+        let t0 = Instant::now();
+        let fade = Fade::starting_at(t0, MS(100), Duration::ZERO);
+        assert_eq!(fade.alpha_at(t0 + MS(50)), Some(1.0));
+        assert_eq!(fade.alpha_at(t0 + MS(100)), None);
+    }
+
+    #[test]
+    fn with_alpha_scales_existing_alpha() {
+        assert_eq!(with_alpha([0.1, 0.2, 0.3, 0.5], 0.5), [0.1, 0.2, 0.3, 0.25]);
+    }
+}
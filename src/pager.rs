@@ -0,0 +1,167 @@
+//! `some-command | koi --pager`: a window whose one pane shows koi's stdin
+//! instead of running a shell. The pane scrolls, searches, selects and
+//! copies like any other; its keys page through the text, and `q` quits.
+
+use std::io::{self, Read};
+
+use alacritty_terminal::event::{Event as TermEvent, EventListener};
+use alacritty_terminal::grid::Scroll;
+use alacritty_terminal::sync::FairMutex;
+use alacritty_terminal::term::Term;
+use alacritty_terminal::vte::ansi::{Processor, StdSyncHandler};
+use winit::keyboard::{Key, NamedKey};
+
+/// Parse everything `input` yields into `term`, telling `listener` to
+/// redraw after each read. Returns at end of input.
+///
+/// Piped text ends lines with a bare LF, which only moves a terminal's
+/// cursor down; each one gets a CR first, as a PTY's `onlcr` would add.
+pub fn feed<L: EventListener, W: EventListener>(
+    mut input: impl Read,
+    term: &FairMutex<Term<L>>,
+    listener: &W,
+) -> io::Result<()> {
+    let mut parser: Processor<StdSyncHandler> = Processor::new();
+    let mut buf = [0u8; 0x1_0000];
+    let mut translated = Vec::with_capacity(buf.len());
+    loop {
+        let n = match input.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        translated.clear();
+        for &byte in &buf[..n] {
+            if byte == b'\n' {
+                translated.push(b'\r');
+            }
+            translated.push(byte);
+        }
+        parser.advance(&mut *term.lock(), &translated);
+        listener.send_event(TermEvent::Wakeup);
+    }
+}
+
+/// What a key does in a buffer pane.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PagerKey {
+    LineUp,
+    LineDown,
+    PageUp,
+    PageDown,
+    Top,
+    Bottom,
+    Quit,
+}
+
+impl PagerKey {
+    /// How the key moves the view, if it does.
+    pub fn scroll(self) -> Option<Scroll> {
+        Some(match self {
+            PagerKey::LineUp => Scroll::Delta(1),
+            PagerKey::LineDown => Scroll::Delta(-1),
+            PagerKey::PageUp => Scroll::PageUp,
+            PagerKey::PageDown => Scroll::PageDown,
+            PagerKey::Top => Scroll::Top,
+            PagerKey::Bottom => Scroll::Bottom,
+            PagerKey::Quit => return None,
+        })
+    }
+}
+
+/// `less`-style keys. None for keys with no meaning here; they are
+/// dropped, since there is no shell to send them to.
+pub fn pager_key(key: &Key) -> Option<PagerKey> {
+    Some(match key {
+        Key::Named(NamedKey::ArrowUp) => PagerKey::LineUp,
+        Key::Named(NamedKey::ArrowDown | NamedKey::Enter) => PagerKey::LineDown,
+        Key::Named(NamedKey::PageUp) => PagerKey::PageUp,
+        Key::Named(NamedKey::PageDown | NamedKey::Space) => PagerKey::PageDown,
+        Key::Named(NamedKey::Home) => PagerKey::Top,
+        Key::Named(NamedKey::End) => PagerKey::Bottom,
+        Key::Character(s) => match s.as_str() {
+            "k" => PagerKey::LineUp,
+            "j" => PagerKey::LineDown,
+            "b" => PagerKey::PageUp,
+            "f" => PagerKey::PageDown,
+            "g" => PagerKey::Top,
+            "G" => PagerKey::Bottom,
+            "q" => PagerKey::Quit,
+            _ => return None,
+        },
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use alacritty_terminal::event::VoidListener;
+    use alacritty_terminal::grid::Dimensions;
+    use alacritty_terminal::index::{Column, Line};
+    use alacritty_terminal::term::Config;
+
+    use super::*;
+    use crate::terminal::TerminalSize;
+
+    #[derive(Default)]
+    struct Wakeups(AtomicUsize);
+
+    impl EventListener for Wakeups {
+        fn send_event(&self, event: TermEvent) {
+            if matches!(event, TermEvent::Wakeup) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn text(term: &Term<VoidListener>, line: i32) -> String {
+        let row = &term.grid()[Line(line)];
+        (0..term.columns()).map(|c| row[Column(c)].c).collect::<String>().trim_end().to_string()
+    }
+
+    /// Hands out its input a few bytes per read, like a slow pipe.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.0.len().min(buf.len()).min(7);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn piped_lines_reach_the_term_through_the_reader() {
+        let term = FairMutex::new(Term::new(Config::default(), &TerminalSize::new(20, 3), VoidListener));
+        let wakeups = Wakeups::default();
+        let input: String = (1..=5).map(|i| format!("line {i}\n")).collect();
+        let input = format!("{input}\x1b[31mred\x1b[0m tail");
+        let reader = std::thread::scope(|scope| {
+            scope.spawn(|| feed(Trickle(input.as_bytes()), &term, &wakeups)).join().unwrap()
+        });
+        reader.unwrap();
+
+        let term = term.lock();
+        // Bare LFs started new lines at column 0; the rest went to history.
+        assert_eq!(term.grid().history_size(), 3);
+        let lines: Vec<_> = (-3..3).map(|l| text(&term, l)).collect();
+        assert_eq!(lines, ["line 1", "line 2", "line 3", "line 4", "line 5", "red tail"]);
+        assert!(wakeups.0.load(Ordering::Relaxed) >= input.len() / 7);
+    }
+
+    #[test]
+    fn pager_keys() {
+        let ch = |s: &str| Key::Character(s.into());
+        assert_eq!(pager_key(&ch("q")), Some(PagerKey::Quit));
+        assert_eq!(pager_key(&ch("G")), Some(PagerKey::Bottom));
+        assert_eq!(pager_key(&Key::Named(NamedKey::ArrowUp)), Some(PagerKey::LineUp));
+        assert_eq!(pager_key(&Key::Named(NamedKey::Space)), Some(PagerKey::PageDown));
+        assert_eq!(pager_key(&ch("x")), None);
+        assert!(matches!(PagerKey::LineUp.scroll(), Some(Scroll::Delta(1))));
+        assert!(PagerKey::Quit.scroll().is_none());
+    }
+}
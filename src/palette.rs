@@ -0,0 +1,184 @@
+use crate::actions::Action;
+use crate::themes::ThemeSet;
+
+/// A single selectable row in the command palette.
+#[derive(Clone, Debug)]
+pub struct PaletteEntry {
+    pub label: String,
+    pub hint: String,
+    pub action: Action,
+}
+
+impl PaletteEntry {
+    pub fn from_action(action: Action) -> Self {
+        Self {
+            label: action.label().to_string(),
+            hint: action.shortcut().to_string(),
+            action,
+        }
+    }
+
+    /// The entry switching to theme `index` of `themes`.
+    pub fn theme(themes: &ThemeSet, index: usize) -> Self {
+        Self {
+            label: format!("Theme: {}", themes.name(index)),
+            hint: String::new(),
+            action: Action::SetTheme(index),
+        }
+    }
+}
+
+/// State for the Cmd+Shift+P command palette overlay.
+pub struct PaletteState {
+    pub query: String,
+    entries: Vec<PaletteEntry>,
+    /// Indices into `entries` matching the current query, best match first.
+    filtered: Vec<usize>,
+    /// Index into `filtered` of the highlighted row.
+    pub selected: usize,
+}
+
+impl PaletteState {
+    pub fn new(entries: Vec<PaletteEntry>) -> Self {
+        let mut state = Self {
+            query: String::new(),
+            entries,
+            filtered: Vec::new(),
+            selected: 0,
+        };
+        state.refilter();
+        state
+    }
+
+    /// Palette listing every built-in action, then a "Theme: …" entry per
+    /// theme in `themes`.
+    pub fn with_actions(themes: &ThemeSet) -> Self {
+        let actions = Action::PALETTE.iter().copied().map(PaletteEntry::from_action);
+        let themes = (0..themes.len()).map(|i| PaletteEntry::theme(themes, i));
+        Self::new(actions.chain(themes).collect())
+    }
+
+    pub fn push_str(&mut self, s: &str) {
+        self.query.push_str(s);
+        self.refilter();
+    }
+
+    pub fn pop(&mut self) {
+        self.query.pop();
+        self.refilter();
+    }
+
+    pub fn select_next(&mut self) {
+        if !self.filtered.is_empty() {
+            self.selected = (self.selected + 1) % self.filtered.len();
+        }
+    }
+
+    pub fn select_prev(&mut self) {
+        if !self.filtered.is_empty() {
+            self.selected = self.selected.checked_sub(1).unwrap_or(self.filtered.len() - 1);
+        }
+    }
+
+    /// Entries matching the query, in display order.
+    pub fn visible(&self) -> impl Iterator<Item = &PaletteEntry> {
+        self.filtered.iter().map(|&i| &self.entries[i])
+    }
+
+    pub fn selected_entry(&self) -> Option<&PaletteEntry> {
+        self.filtered.get(self.selected).map(|&i| &self.entries[i])
+    }
+
+    fn refilter(&mut self) {
+        let mut scored: Vec<(u32, usize)> = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter_map(|(i, e)| match_score(&e.label, &self.query).map(|s| (s, i)))
+            .collect();
+        // Stable sort keeps declaration order among equal scores.
+        scored.sort_by_key(|&(score, _)| score);
+        self.filtered = scored.into_iter().map(|(_, i)| i).collect();
+        self.selected = 0;
+    }
+}
+
+/// Case-insensitive fuzzy match. Returns None if `query` is not a subsequence
+/// of `label`; lower scores are better (substring matches rank first, earlier
+/// positions rank higher).
+fn match_score(label: &str, query: &str) -> Option<u32> {
+    if query.is_empty() {
+        return Some(0);
+    }
+    let label = label.to_lowercase();
+    let query = query.to_lowercase();
+    if let Some(pos) = label.find(&query) {
+        return Some(pos as u32);
+    }
+    let mut chars = label.char_indices();
+    let mut last = 0;
+    for q in query.chars() {
+        let (pos, _) = chars.find(|&(_, c)| c == q)?;
+        last = pos;
+    }
+    Some(1000 + last as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_query_lists_everything() {
+        let p = PaletteState::with_actions(&ThemeSet::builtin());
+        assert_eq!(p.visible().count(), Action::PALETTE.len() + ThemeSet::builtin().len());
+        assert_eq!(p.selected_entry().unwrap().action, Action::PALETTE[0]);
+    }
+
+    #[test]
+    fn substring_matches_rank_before_subsequences() {
+        let mut p = PaletteState::with_actions(&ThemeSet::builtin());
+        p.push_str("reset");
+        assert_eq!(p.selected_entry().unwrap().action, Action::ResetPane);
+    }
+
+    #[test]
+    fn subsequence_query_matches() {
+        let mut p = PaletteState::with_actions(&ThemeSet::builtin());
+        p.push_str("clrsb");
+        let actions: Vec<Action> = p.visible().map(|e| e.action).collect();
+        assert_eq!(actions, vec![Action::ClearScrollback]);
+    }
+
+    #[test]
+    fn theme_prefix_lists_themes() {
+        let mut p = PaletteState::with_actions(&ThemeSet::builtin());
+        p.push_str("theme:");
+        let actions: Vec<Action> = p.visible().map(|e| e.action).collect();
+        assert_eq!(actions, [Action::SetTheme(0), Action::SetTheme(1)]);
+    }
+
+    #[test]
+    fn no_match_has_no_selection() {
+        let mut p = PaletteState::with_actions(&ThemeSet::builtin());
+        p.push_str("zzzz");
+        assert!(p.selected_entry().is_none());
+        p.select_next();
+        assert!(p.selected_entry().is_none());
+    }
+
+    #[test]
+    fn selection_wraps_and_resets_on_edit() {
+        let mut p = PaletteState::with_actions(&ThemeSet::builtin());
+        let total = p.visible().count();
+        p.select_prev();
+        assert_eq!(p.selected, total - 1);
+        p.select_next();
+        assert_eq!(p.selected, 0);
+        p.select_next();
+        p.push_str("s");
+        assert_eq!(p.selected, 0);
+        p.pop();
+        assert_eq!(p.visible().count(), total);
+    }
+}
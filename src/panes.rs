@@ -1,3 +1,11 @@
+use std::time::{Duration, Instant};
+
+use crate::animation::{ease_out_cubic, progress};
+
+/// How long a pane takes to grow in when opened, or for its neighbor to
+/// grow into its space when closed.
+pub const TRANSITION: Duration = Duration::from_millis(120);
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Split {
     Vertical,
@@ -13,6 +21,14 @@ pub struct PaneLayout {
     pub height: f32,
 }
 
+impl PaneLayout {
+    /// Whether the point `(x, y)` falls in this pane. Right and bottom
+    /// edges belong to the neighbor.
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+}
+
 /// Describes a draggable divider between two pane regions.
 pub struct DividerInfo {
     pub split: Split,
@@ -25,10 +41,98 @@ pub struct DividerInfo {
     /// Perpendicular bounds for hit-testing.
     pub perp_start: f32,
     pub perp_end: f32,
+    /// The split's current ratio.
+    pub ratio: f32,
     /// Path from root to this split node (false=left, true=right at each ancestor).
     pub path: Vec<bool>,
 }
 
+/// A pane opening or closing, animated by moving one split's ratio.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TransitionKind {
+    /// The split at the path was just made; its new right/bottom pane grows
+    /// in from nothing.
+    Open,
+    /// The pane beside the node at the path closed. Until the transition
+    /// ends, the node still shares its rect with the closed pane's empty
+    /// space, as if the split were still there.
+    Close { split: Split, survivor_first: bool },
+}
+
+#[derive(Debug)]
+struct Transition {
+    path: Vec<bool>,
+    kind: TransitionKind,
+    /// Left/top share of the animated split at the start and the end.
+    from: f32,
+    to: f32,
+    start: Instant,
+}
+
+impl Transition {
+    fn progress(&self, now: Instant) -> f32 {
+        progress(self.start, TRANSITION, now)
+    }
+
+    fn ratio(&self, now: Instant) -> f32 {
+        self.from + (self.to - self.from) * ease_out_cubic(self.progress(now))
+    }
+}
+
+/// A transition's state on its way down the tree to the node it animates.
+#[derive(Clone, Copy)]
+struct Frame<'a> {
+    path: &'a [bool],
+    kind: TransitionKind,
+    ratio: f32,
+}
+
+/// Split rect (x, y, w, h) into its left/top and right/bottom parts.
+fn split_rect(split: Split, ratio: f32, rect: Rect) -> (Rect, Rect) {
+    let span = along(split, rect);
+    split_rect_at(split, (span * ratio).floor(), rect)
+}
+
+/// Split rect with `first` pixels on the left/top.
+fn split_rect_at(split: Split, first: f32, (x, y, w, h): Rect) -> (Rect, Rect) {
+    match split {
+        Split::Vertical => ((x, y, first, h), (x + first, y, w - first, h)),
+        Split::Horizontal => ((x, y, w, first), (x, y + first, w, h - first)),
+    }
+}
+
+/// Split rect like `split_rect`, but moving the divider so neither side is
+/// under its minimum size along the split. If the rect can't hold both,
+/// the ratio stands and the window is too small for the layout.
+fn split_rect_min(split: Split, ratio: f32, rect: Rect, min_first: f32, min_second: f32) -> (Rect, Rect) {
+    let span = along(split, rect);
+    let first = (span * ratio).floor();
+    let (lo, hi) = (min_first.ceil(), (span - min_second).floor());
+    if lo > hi {
+        return split_rect_at(split, first, rect);
+    }
+    split_rect_at(split, first.clamp(lo, hi), rect)
+}
+
+/// Rect's size along the axis `split` divides.
+fn along(split: Split, (_, _, w, h): Rect) -> f32 {
+    match split {
+        Split::Vertical => w,
+        Split::Horizontal => h,
+    }
+}
+
+type Rect = (f32, f32, f32, f32);
+
+/// A split node's two halves of `rect` at `ratio`, with the divider kept
+/// clear of either side's minimum.
+fn divide(split: Split, ratio: f32, left: &Node, right: &Node, rect: Rect, min: MinSize) -> (Rect, Rect) {
+    split_rect_min(split, ratio, rect, left.min_extent(split, min), right.min_extent(split, min))
+}
+
+/// Smallest width and height, in pixels, a split may leave a pane.
+type MinSize = (f32, f32);
+
 enum Node {
     Leaf { pane_id: usize },
     Split {
@@ -57,49 +161,45 @@ impl Node {
         }
     }
 
-    fn collect_dividers(
-        &self, x: f32, y: f32, w: f32, h: f32,
-        path: &mut Vec<bool>, dividers: &mut Vec<DividerInfo>,
-    ) {
+    fn collect_dividers(&self, rect: Rect, min: MinSize, path: &mut Vec<bool>, dividers: &mut Vec<DividerInfo>) {
         if let Node::Split { split, ratio, left, right } = self {
-            match split {
-                Split::Vertical => {
-                    let left_w = (w * ratio).floor();
-                    dividers.push(DividerInfo {
-                        split: Split::Vertical,
-                        position: x + left_w,
-                        origin: x,
-                        span: w,
-                        perp_start: y,
-                        perp_end: y + h,
-                        path: path.clone(),
-                    });
-                    path.push(false);
-                    left.collect_dividers(x, y, left_w, h, path, dividers);
-                    path.pop();
-                    path.push(true);
-                    right.collect_dividers(x + left_w, y, w - left_w, h, path, dividers);
-                    path.pop();
-                }
-                Split::Horizontal => {
-                    let top_h = (h * ratio).floor();
-                    dividers.push(DividerInfo {
-                        split: Split::Horizontal,
-                        position: y + top_h,
-                        origin: y,
-                        span: h,
-                        perp_start: x,
-                        perp_end: x + w,
-                        path: path.clone(),
-                    });
-                    path.push(false);
-                    left.collect_dividers(x, y, w, top_h, path, dividers);
-                    path.pop();
-                    path.push(true);
-                    right.collect_dividers(x, y + top_h, w, h - top_h, path, dividers);
-                    path.pop();
-                }
+            let (first, second) = divide(*split, *ratio, left, right, rect, min);
+            let (x, y, w, h) = rect;
+            let (position, origin, span, perp_start, perp_end) = match split {
+                Split::Vertical => (x + first.2, x, w, y, y + h),
+                Split::Horizontal => (y + first.3, y, h, x, x + w),
+            };
+            dividers.push(DividerInfo {
+                split: *split,
+                position,
+                origin,
+                span,
+                perp_start,
+                perp_end,
+                ratio: *ratio,
+                path: path.clone(),
+            });
+            path.push(false);
+            left.collect_dividers(first, min, path, dividers);
+            path.pop();
+            path.push(true);
+            right.collect_dividers(second, min, path, dividers);
+            path.pop();
+        }
+    }
+
+    /// The least this node can be along `split`'s axis with every pane at
+    /// least `min`.
+    fn min_extent(&self, along: Split, min: MinSize) -> f32 {
+        match self {
+            Node::Leaf { .. } => match along {
+                Split::Vertical => min.0,
+                Split::Horizontal => min.1,
+            },
+            Node::Split { split, left, right, .. } if *split == along => {
+                left.min_extent(along, min) + right.min_extent(along, min)
             }
+            Node::Split { left, right, .. } => left.min_extent(along, min).max(right.min_extent(along, min)),
         }
     }
 
@@ -118,36 +218,102 @@ impl Node {
         }
     }
 
-    fn calculate_layouts(&self, x: f32, y: f32, w: f32, h: f32, layouts: &mut Vec<PaneLayout>) {
+    fn calculate_layouts(&self, rect: Rect, min: MinSize, anim: Option<Frame>, layouts: &mut Vec<PaneLayout>) {
+        let here = anim.filter(|f| f.path.is_empty());
+        let rect = match here {
+            Some(Frame { kind: TransitionKind::Close { split, survivor_first }, ratio, .. }) => {
+                let (first, second) = split_rect(split, ratio, rect);
+                if survivor_first { first } else { second }
+            }
+            _ => rect,
+        };
         match self {
             Node::Leaf { pane_id } => {
-                layouts.push(PaneLayout {
-                    pane_id: *pane_id,
-                    x,
-                    y,
-                    width: w,
-                    height: h,
-                });
+                let (x, y, width, height) = rect;
+                layouts.push(PaneLayout { pane_id: *pane_id, x, y, width, height });
             }
-            Node::Split {
-                split,
-                ratio,
-                left,
-                right,
-            } => match split {
-                Split::Vertical => {
-                    let left_w = (w * ratio).floor();
-                    let right_w = w - left_w;
-                    left.calculate_layouts(x, y, left_w, h, layouts);
-                    right.calculate_layouts(x + left_w, y, right_w, h, layouts);
-                }
-                Split::Horizontal => {
-                    let top_h = (h * ratio).floor();
-                    let bottom_h = h - top_h;
-                    left.calculate_layouts(x, y, w, top_h, layouts);
-                    right.calculate_layouts(x, y + top_h, w, bottom_h, layouts);
+            Node::Split { split, ratio, left, right } => {
+                // A pane opening grows from nothing, minimum or not.
+                let (first, second) = match here {
+                    Some(Frame { kind: TransitionKind::Open, ratio, .. }) => split_rect(*split, ratio, rect),
+                    _ => divide(*split, *ratio, left, right, rect, min),
+                };
+                let child = |side: bool| {
+                    anim.filter(|f| f.path.first() == Some(&side)).map(|f| Frame { path: &f.path[1..], ..f })
+                };
+                left.calculate_layouts(first, min, child(false), layouts);
+                right.calculate_layouts(second, min, child(true), layouts);
+            }
+        }
+    }
+
+    /// Path from this node to the leaf `target` (false=left, true=right).
+    fn path_to(&self, target: usize, path: &mut Vec<bool>) -> bool {
+        match self {
+            Node::Leaf { pane_id } => *pane_id == target,
+            Node::Split { left, right, .. } => {
+                for (side, child) in [(false, left), (true, right)] {
+                    path.push(side);
+                    if child.path_to(target, path) {
+                        return true;
+                    }
+                    path.pop();
                 }
-            },
+                false
+            }
+        }
+    }
+
+    /// How many panes sit in a row across this node along `split`'s axis:
+    /// side by side for Vertical, stacked for Horizontal.
+    fn span(&self, along: Split) -> usize {
+        match self {
+            Node::Leaf { .. } => 1,
+            Node::Split { split, left, right, .. } if *split == along => left.span(along) + right.span(along),
+            Node::Split { left, right, .. } => left.span(along).max(right.span(along)),
+        }
+    }
+
+    /// Set every split's ratio so the panes in a row share it evenly.
+    fn equalize(&mut self) {
+        if let Node::Split { split, ratio, left, right } = self {
+            let (l, r) = (left.span(*split), right.span(*split));
+            *ratio = l as f32 / (l + r) as f32;
+            left.equalize();
+            right.equalize();
+        }
+    }
+
+    /// Give leaf `a` pane `b` and leaf `b` pane `a`.
+    fn swap_panes(&mut self, a: usize, b: usize) {
+        match self {
+            Node::Leaf { pane_id } if *pane_id == a => *pane_id = b,
+            Node::Leaf { pane_id } if *pane_id == b => *pane_id = a,
+            Node::Leaf { .. } => {}
+            Node::Split { left, right, .. } => {
+                left.swap_panes(a, b);
+                right.swap_panes(a, b);
+            }
+        }
+    }
+
+    fn node_at_mut(&mut self, path: &[bool]) -> Option<&mut Node> {
+        match (path.split_first(), self) {
+            (None, node) => Some(node),
+            (Some((&side, rest)), Node::Split { left, right, .. }) => {
+                if side { right.node_at_mut(rest) } else { left.node_at_mut(rest) }
+            }
+            (Some(_), Node::Leaf { .. }) => None,
+        }
+    }
+
+    fn node_at(&self, path: &[bool]) -> Option<&Node> {
+        match (path.split_first(), self) {
+            (None, _) => Some(self),
+            (Some((&side, rest)), Node::Split { left, right, .. }) => {
+                if side { right.node_at(rest) } else { left.node_at(rest) }
+            }
+            (Some(_), Node::Leaf { .. }) => None,
         }
     }
 
@@ -242,6 +408,20 @@ pub struct PaneTree {
     root: Node,
     active: usize,
     zoomed: bool,
+    /// Zoomed because the window is too small for the split layout, not at
+    /// the user's request; released once the layout fits again.
+    auto_zoomed: bool,
+    /// The user unzoomed an auto-zoom, so this tree stays split however
+    /// small the window gets.
+    auto_zoom_off: bool,
+    /// The last split or close, while it animates and until it is settled.
+    transition: Option<Transition>,
+    /// Dividers stop short of making a pane smaller than this, and a pane
+    /// that can't hold two this size won't split.
+    min_pane: MinSize,
+    /// Height of the header strip over each pane once there are two or
+    /// more; the pane's layout starts below it.
+    header: f32,
 }
 
 impl PaneTree {
@@ -250,9 +430,52 @@ impl PaneTree {
             root: Node::Leaf { pane_id },
             active: pane_id,
             zoomed: false,
+            auto_zoomed: false,
+            auto_zoom_off: false,
+            transition: None,
+            min_pane: (0.0, 0.0),
+            header: 0.0,
         }
     }
 
+    /// Set the smallest pane size in pixels, as whole cells.
+    pub fn set_min_pane_size(&mut self, width: f32, height: f32) {
+        self.min_pane = (width, height);
+    }
+
+    /// Leave `height` pixels over each split pane for its header.
+    pub fn set_header_height(&mut self, height: f32) {
+        self.header = height;
+    }
+
+    /// Shift split panes' layouts below their header strips.
+    fn inset_headers(&self, layouts: &mut [PaneLayout]) {
+        if layouts.len() < 2 {
+            return;
+        }
+        for l in layouts {
+            let header = self.header.min(l.height);
+            l.y += header;
+            l.height -= header;
+        }
+    }
+
+    /// Whether the active pane, as laid out in `width` x `height`, has
+    /// room to split in two.
+    pub fn can_split_active(&self, split: Split, width: f32, height: f32) -> bool {
+        let layouts = self.split_layouts(width, height);
+        let Some(l) = layouts.iter().find(|l| l.pane_id == self.active) else { return false };
+        match split {
+            Split::Vertical => l.width >= 2.0 * self.min_pane.0,
+            Split::Horizontal => l.height >= 2.0 * self.min_pane.1,
+        }
+    }
+
+    fn path_to(&self, pane_id: usize) -> Option<Vec<bool>> {
+        let mut path = Vec::new();
+        self.root.path_to(pane_id, &mut path).then_some(path)
+    }
+
     pub fn pane_count(&self) -> usize {
         self.root.pane_count()
     }
@@ -265,13 +488,45 @@ impl PaneTree {
         self.active = pane_id;
     }
 
+    /// Zoom or unzoom at the user's request. Unzooming an auto-zoom turns
+    /// auto-zoom off for this tree.
     pub fn toggle_zoom(&mut self) {
-        self.zoomed = !self.zoomed;
+        if self.auto_zoomed {
+            self.auto_zoomed = false;
+            self.auto_zoom_off = true;
+            self.zoomed = false;
+        } else {
+            self.zoomed = !self.zoomed;
+        }
+    }
+
+    pub fn is_zoomed(&self) -> bool {
+        self.zoomed || self.auto_zoomed
+    }
+
+    pub fn is_auto_zoomed(&self) -> bool {
+        self.auto_zoomed
+    }
+
+    /// Set the too-small zoom, unless the user turned it off. Returns true
+    /// if it changed.
+    pub fn set_auto_zoomed(&mut self, on: bool) -> bool {
+        let on = on && !self.auto_zoom_off;
+        std::mem::replace(&mut self.auto_zoomed, on) != on
     }
 
     /// Split the active pane. The new pane gets `new_id` and becomes active.
     pub fn split_active(&mut self, split: Split, new_id: usize) {
-        self.root.split_pane(self.active, split, new_id);
+        let path = self.path_to(self.active);
+        if self.root.split_pane(self.active, split, new_id) {
+            self.transition = path.map(|path| Transition {
+                path,
+                kind: TransitionKind::Open,
+                from: 1.0,
+                to: 0.5,
+                start: Instant::now(),
+            });
+        }
         self.active = new_id;
         self.zoomed = false;
     }
@@ -285,6 +540,15 @@ impl PaneTree {
         let ids = self.pane_ids();
         let current_idx = ids.iter().position(|&id| id == self.active).unwrap_or(0);
 
+        // The split the pane leaves, for the survivor's transition.
+        let closing = self.path_to(self.active).and_then(|mut path| {
+            let side = path.pop()?;
+            match self.root.node_at(&path)? {
+                &Node::Split { split, ratio, .. } => Some((path, split, ratio, side)),
+                Node::Leaf { .. } => None,
+            }
+        });
+
         // Take ownership of root to perform removal
         let old_root = std::mem::replace(&mut self.root, Node::Leaf { pane_id: 0 });
         match old_root.remove_pane(self.active) {
@@ -301,6 +565,18 @@ impl PaneTree {
             }
         }
 
+        if let Some((path, split, ratio, closed_side)) = closing {
+            // Closing the right/bottom pane leaves the left/top one.
+            let survivor_first = closed_side;
+            self.transition = Some(Transition {
+                path,
+                kind: TransitionKind::Close { split, survivor_first },
+                from: ratio,
+                to: if survivor_first { 1.0 } else { 0.0 },
+                start: Instant::now(),
+            });
+        }
+
         // Move focus to an adjacent pane
         let new_ids = self.pane_ids();
         self.active = if current_idx > 0 && current_idx <= new_ids.len() {
@@ -312,6 +588,51 @@ impl PaneTree {
         false
     }
 
+    /// Give the active pane a new id, keeping its place and focus.
+    pub fn renumber_active(&mut self, new_id: usize) {
+        let Some(path) = self.path_to(self.active) else { return };
+        if let Some(Node::Leaf { pane_id }) = self.root.node_at_mut(&path) {
+            *pane_id = new_id;
+            self.active = new_id;
+        }
+    }
+
+    /// Trade places between the active pane and `pane_id`. The active pane
+    /// keeps focus in its new spot. Returns false if `pane_id` is the
+    /// active pane or not in this tree.
+    pub fn swap_active_with(&mut self, pane_id: usize) -> bool {
+        if pane_id == self.active || self.path_to(pane_id).is_none() {
+            return false;
+        }
+        self.root.swap_panes(self.active, pane_id);
+        self.transition = None;
+        true
+    }
+
+    /// Even out every split, undoing divider drags: three panes side by
+    /// side get a third of the width each.
+    pub fn equalize(&mut self) {
+        self.transition = None;
+        self.root.equalize();
+    }
+
+    /// Turn the split holding the active pane the other way: side by side
+    /// becomes stacked and back, each side keeping its share. Returns false
+    /// if there's no split.
+    pub fn rotate(&mut self) -> bool {
+        let Some(mut path) = self.path_to(self.active) else { return false };
+        if path.pop().is_none() {
+            return false;
+        }
+        let Some(Node::Split { split, .. }) = self.root.node_at_mut(&path) else { return false };
+        *split = match *split {
+            Split::Vertical => Split::Horizontal,
+            Split::Horizontal => Split::Vertical,
+        };
+        self.transition = None;
+        true
+    }
+
     pub fn focus_next(&mut self) {
         let ids = self.pane_ids();
         if ids.len() <= 1 {
@@ -342,18 +663,46 @@ impl PaneTree {
     pub fn collect_dividers(&self, width: f32, height: f32) -> Vec<DividerInfo> {
         let mut dividers = Vec::new();
         let mut path = Vec::new();
-        self.root.collect_dividers(0.0, 0.0, width, height, &mut path, &mut dividers);
+        self.root.collect_dividers((0.0, 0.0, width, height), self.min_pane, &mut path, &mut dividers);
         dividers
     }
 
     /// Update the ratio of a split node identified by its tree path.
     pub fn set_ratio_at(&mut self, path: &[bool], ratio: f32) {
+        // Dragging a divider takes over from any animation.
+        self.transition = None;
         self.root.set_ratio_at(path, ratio);
     }
 
+    /// A split or close is animating or hasn't been settled yet. Its panes'
+    /// grids keep their old size until then.
+    pub fn in_transition(&self) -> bool {
+        self.transition.is_some()
+    }
+
+    /// Whether the transition is still animating at `now`.
+    pub fn transition_running(&self, now: Instant) -> bool {
+        self.transition.as_ref().is_some_and(|t| t.progress(now) < 1.0)
+    }
+
+    /// End a finished transition. True if there was one, and the panes now
+    /// need resizing to their layouts.
+    pub fn settle_transition(&mut self, now: Instant) -> bool {
+        if self.transition.is_none() || self.transition_running(now) {
+            return false;
+        }
+        self.transition = None;
+        true
+    }
+
+    /// Drop the last change's transition, for instant layout changes.
+    pub fn skip_transition(&mut self) {
+        self.transition = None;
+    }
+
     /// Calculate pixel layouts for all panes in the given viewport.
     pub fn calculate_layouts(&self, width: f32, height: f32) -> Vec<PaneLayout> {
-        if self.zoomed {
+        if self.is_zoomed() {
             // Only show active pane, full viewport
             return vec![PaneLayout {
                 pane_id: self.active,
@@ -364,8 +713,30 @@ impl PaneTree {
             }];
         }
 
+        self.split_layouts(width, height)
+    }
+
+    /// Layouts of every pane as split, whether or not a pane is zoomed.
+    pub fn split_layouts(&self, width: f32, height: f32) -> Vec<PaneLayout> {
         let mut layouts = Vec::new();
-        self.root.calculate_layouts(0.0, 0.0, width, height, &mut layouts);
+        self.root.calculate_layouts((0.0, 0.0, width, height), self.min_pane, None, &mut layouts);
+        self.inset_headers(&mut layouts);
+        layouts
+    }
+
+    /// Layouts as drawn at `now`: mid-transition, an opening pane is still
+    /// growing and a closed one's neighbor hasn't filled its space yet.
+    /// Panes with no area yet are left out. Everything else (hit testing,
+    /// grid sizes) uses the final `calculate_layouts`.
+    pub fn animated_layouts(&self, width: f32, height: f32, now: Instant) -> Vec<PaneLayout> {
+        let Some(t) = self.transition.as_ref().filter(|_| !self.is_zoomed()) else {
+            return self.calculate_layouts(width, height);
+        };
+        let frame = Frame { path: &t.path, kind: t.kind, ratio: t.ratio(now) };
+        let mut layouts = Vec::new();
+        self.root.calculate_layouts((0.0, 0.0, width, height), self.min_pane, Some(frame), &mut layouts);
+        layouts.retain(|l| l.width >= 1.0 && l.height >= 1.0);
+        self.inset_headers(&mut layouts);
         layouts
     }
 }
@@ -374,6 +745,79 @@ impl PaneTree {
 mod tests {
     use super::*;
 
+    fn widths(layouts: &[PaneLayout]) -> Vec<(usize, f32)> {
+        layouts.iter().map(|l| (l.pane_id, l.width)).collect()
+    }
+
+    #[test]
+    fn points_on_a_shared_edge_belong_to_one_pane() {
+        let mut tree = PaneTree::new(0);
+        tree.split_active(Split::Vertical, 1);
+        let layouts = tree.calculate_layouts(800.0, 600.0);
+        let at = |x, y| layouts.iter().filter(|l| l.contains(x, y)).map(|l| l.pane_id).collect::<Vec<_>>();
+        assert_eq!(at(399.9, 10.0), [0]);
+        assert_eq!(at(400.0, 10.0), [1]);
+        assert_eq!(at(800.0, 10.0), Vec::<usize>::new());
+        assert_eq!(at(10.0, -1.0), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn opening_pane_grows_from_nothing() {
+        let before = Instant::now();
+        let mut tree = PaneTree::new(0);
+        tree.split_active(Split::Vertical, 1);
+        let done = before + TRANSITION * 2;
+        assert!(tree.in_transition());
+
+        // At the start the old pane still fills the viewport.
+        assert_eq!(widths(&tree.animated_layouts(800.0, 600.0, before)), [(0, 800.0)]);
+        let mid = widths(&tree.animated_layouts(800.0, 600.0, before + TRANSITION / 2));
+        assert!(mid[0].1 > 400.0 && mid[0].1 < 800.0, "{mid:?}");
+        // At the end, the final layout.
+        assert_eq!(widths(&tree.animated_layouts(800.0, 600.0, done)), [(0, 400.0), (1, 400.0)]);
+        assert_eq!(widths(&tree.calculate_layouts(800.0, 600.0)), [(0, 400.0), (1, 400.0)]);
+    }
+
+    #[test]
+    fn survivor_grows_into_closed_space() {
+        let mut tree = PaneTree::new(0);
+        tree.split_active(Split::Horizontal, 1);
+        tree.split_active(Split::Vertical, 2);
+        tree.skip_transition();
+        // Close the top pane: the bottom row takes over from y = 300.
+        tree.set_active(0);
+        let before = Instant::now();
+        assert!(!tree.close_active());
+
+        let start = tree.animated_layouts(800.0, 600.0, before);
+        let rects: Vec<_> = start.iter().map(|l| (l.pane_id, l.y, l.width, l.height)).collect();
+        assert_eq!(rects, [(1, 300.0, 400.0, 300.0), (2, 300.0, 400.0, 300.0)]);
+        let end = tree.animated_layouts(800.0, 600.0, before + TRANSITION * 2);
+        let rects: Vec<_> = end.iter().map(|l| (l.pane_id, l.y, l.width, l.height)).collect();
+        assert_eq!(rects, [(1, 0.0, 400.0, 600.0), (2, 0.0, 400.0, 600.0)]);
+    }
+
+    #[test]
+    fn transition_settles_once_finished() {
+        let before = Instant::now();
+        let mut tree = PaneTree::new(0);
+        tree.split_active(Split::Vertical, 1);
+        assert!(tree.transition_running(before));
+        assert!(!tree.settle_transition(before), "still animating");
+        assert!(tree.in_transition());
+
+        let done = before + TRANSITION * 2;
+        assert!(!tree.transition_running(done));
+        assert!(tree.settle_transition(done));
+        assert!(!tree.in_transition());
+        assert!(!tree.settle_transition(done), "settles once");
+
+        // Dragging a divider or skipping ends it without a settle.
+        tree.split_active(Split::Vertical, 2);
+        tree.set_ratio_at(&[], 0.3);
+        assert!(!tree.in_transition());
+    }
+
     #[test]
     fn single_pane_fills_viewport() {
         let tree = PaneTree::new(0);
@@ -576,4 +1020,169 @@ mod tests {
         let pane1 = layouts.iter().find(|l| l.pane_id == 1).unwrap();
         assert!((pane1.height - 150.0).abs() < 1.0);
     }
+
+    #[test]
+    fn auto_zoom_is_released_when_the_layout_fits() {
+        let mut tree = PaneTree::new(0);
+        tree.split_active(Split::Vertical, 1);
+        assert!(tree.set_auto_zoomed(true));
+        assert_eq!(tree.calculate_layouts(150.0, 600.0).len(), 1);
+        // The split is still there underneath.
+        assert_eq!(tree.split_layouts(150.0, 600.0).len(), 2);
+        assert!(!tree.set_auto_zoomed(true));
+        assert!(tree.set_auto_zoomed(false));
+        assert_eq!(tree.calculate_layouts(800.0, 600.0).len(), 2);
+        // User zoom is separate and survives.
+        tree.toggle_zoom();
+        tree.set_auto_zoomed(true);
+        tree.set_auto_zoomed(false);
+        assert!(tree.is_zoomed());
+    }
+
+    #[test]
+    fn unzooming_an_auto_zoom_turns_it_off() {
+        let mut tree = PaneTree::new(0);
+        tree.split_active(Split::Vertical, 1);
+        tree.set_auto_zoomed(true);
+        tree.toggle_zoom();
+        assert!(!tree.is_zoomed());
+        assert!(!tree.set_auto_zoomed(true));
+        assert!(!tree.is_zoomed());
+        // Zooming by hand still works.
+        tree.toggle_zoom();
+        assert!(tree.is_zoomed());
+    }
+
+    #[test]
+    fn swap_trades_places_and_keeps_focus() {
+        let mut tree = PaneTree::new(1);
+        tree.split_active(Split::Vertical, 2);
+        tree.split_active(Split::Horizontal, 3);
+        tree.skip_transition();
+        let before = tree.calculate_layouts(800.0, 600.0);
+
+        assert!(tree.swap_active_with(1));
+        assert_eq!(tree.active_pane_id(), 3);
+        let after = tree.calculate_layouts(800.0, 600.0);
+        let rect = |layouts: &[PaneLayout], id| {
+            let l = layouts.iter().find(|l| l.pane_id == id).unwrap();
+            (l.x, l.y, l.width, l.height)
+        };
+        assert_eq!(rect(&after, 3), rect(&before, 1));
+        assert_eq!(rect(&after, 1), rect(&before, 3));
+        assert_eq!(rect(&after, 2), rect(&before, 2));
+
+        assert!(!tree.swap_active_with(3));
+        assert!(!tree.swap_active_with(9));
+    }
+
+    #[test]
+    fn equalize_shares_each_row_evenly() {
+        let mut tree = PaneTree::new(1);
+        for id in 2..=4 {
+            tree.split_active(Split::Vertical, id);
+        }
+        tree.skip_transition();
+        tree.set_ratio_at(&[], 0.8);
+        tree.set_ratio_at(&[true, true], 0.1);
+
+        tree.equalize();
+        for (_, width) in widths(&tree.calculate_layouts(900.0, 600.0)) {
+            assert!((width - 225.0).abs() <= 1.0, "width {width}");
+        }
+
+        // A stack beside one pane: the stack's panes split its height.
+        let mut tree = PaneTree::new(1);
+        tree.split_active(Split::Vertical, 2);
+        tree.split_active(Split::Horizontal, 3);
+        tree.split_active(Split::Horizontal, 4);
+        tree.skip_transition();
+        tree.set_ratio_at(&[true], 0.9);
+        tree.equalize();
+        let layouts = tree.calculate_layouts(800.0, 600.0);
+        assert_eq!(widths(&layouts), [(1, 400.0), (2, 400.0), (3, 400.0), (4, 400.0)]);
+        let heights: Vec<_> = layouts.iter().skip(1).map(|l| l.height).collect();
+        assert_eq!(heights, [200.0, 200.0, 200.0]);
+    }
+
+    #[test]
+    fn rotate_flips_the_active_panes_split() {
+        let mut tree = PaneTree::new(1);
+        assert!(!tree.rotate());
+        tree.split_active(Split::Vertical, 2);
+        tree.skip_transition();
+        tree.set_ratio_at(&[], 0.25);
+
+        assert!(tree.rotate());
+        let layouts = tree.calculate_layouts(800.0, 600.0);
+        let rects: Vec<_> = layouts.iter().map(|l| (l.pane_id, l.x, l.y, l.width, l.height)).collect();
+        assert_eq!(rects, [(1, 0.0, 0.0, 800.0, 150.0), (2, 0.0, 150.0, 800.0, 450.0)]);
+
+        assert!(tree.rotate());
+        assert_eq!(widths(&tree.calculate_layouts(800.0, 600.0)), [(1, 200.0), (2, 600.0)]);
+    }
+
+    #[test]
+    fn dividers_stop_at_the_minimum_pane_size() {
+        let mut tree = PaneTree::new(1);
+        tree.set_min_pane_size(28.0, 28.0);
+        // 1 | (2 / (3 | 4)), dragged hard toward pane 1.
+        tree.split_active(Split::Vertical, 2);
+        tree.split_active(Split::Horizontal, 3);
+        tree.split_active(Split::Vertical, 4);
+        tree.skip_transition();
+        tree.set_ratio_at(&[], 0.01);
+        tree.set_ratio_at(&[true], 0.99);
+
+        let layouts = tree.calculate_layouts(300.0, 200.0);
+        assert_eq!(widths(&layouts)[0], (1, 28.0));
+        let heights: Vec<_> = layouts.iter().map(|l| (l.pane_id, l.height)).collect();
+        assert_eq!(heights, [(1, 200.0), (2, 172.0), (3, 28.0), (4, 28.0)]);
+        // The dividers are where the panes meet.
+        let dividers = tree.collect_dividers(300.0, 200.0);
+        assert_eq!(dividers[0].position, 28.0);
+        assert_eq!(dividers[1].position, 172.0);
+
+        // The other way, the right column keeps room for panes 3 and 4.
+        tree.set_ratio_at(&[], 0.99);
+        let layouts = tree.calculate_layouts(300.0, 200.0);
+        assert_eq!(widths(&layouts)[0], (1, 244.0));
+        assert!(layouts.iter().all(|l| l.width >= 28.0 && l.height >= 28.0));
+    }
+
+    #[test]
+    fn deep_nesting_in_a_small_viewport_never_overlaps() {
+        let mut tree = PaneTree::new(0);
+        tree.set_min_pane_size(28.0, 28.0);
+        let mut id = 1;
+        for split in [Split::Vertical, Split::Horizontal].into_iter().cycle().take(8) {
+            if tree.can_split_active(split, 160.0, 120.0) {
+                tree.split_active(split, id);
+                id += 1;
+            }
+        }
+        tree.skip_transition();
+        for path in [vec![], vec![true], vec![true, true], vec![true, true, true]] {
+            tree.set_ratio_at(&path, 0.02);
+        }
+
+        let layouts = tree.calculate_layouts(160.0, 120.0);
+        assert!(layouts.len() < 9, "some splits were refused");
+        let area: f32 = layouts.iter().map(|l| l.width * l.height).sum();
+        assert_eq!(area, 160.0 * 120.0);
+        for l in &layouts {
+            assert!(l.width >= 28.0 && l.height >= 28.0, "pane {} is {}x{}", l.pane_id, l.width, l.height);
+            assert!(l.x + l.width <= 160.0 && l.y + l.height <= 120.0);
+        }
+    }
+
+    #[test]
+    fn a_pane_too_small_to_halve_wont_split() {
+        let mut tree = PaneTree::new(1);
+        tree.set_min_pane_size(28.0, 28.0);
+        assert!(tree.can_split_active(Split::Vertical, 56.0, 40.0));
+        assert!(!tree.can_split_active(Split::Horizontal, 56.0, 40.0));
+        tree.split_active(Split::Vertical, 2);
+        assert!(!tree.can_split_active(Split::Vertical, 56.0, 40.0));
+    }
 }
@@ -0,0 +1,396 @@
+//! tmux passthrough (`set -g allow-passthrough on`): tmux forwards sequences
+//! it doesn't understand wrapped as `ESC P tmux; <payload> ESC \`, with every
+//! ESC in the payload doubled. alacritty_terminal's parser ignores DCS, so
+//! the PTY reader unwraps them before the bytes reach it and the inner
+//! sequence (OSC 52, a title, ...) is handled as if it arrived directly.
+//! The same reader rewrites shell prompt marks (see `prompts`), and the
+//! PTY's writer keeps count of the input still to go.
+
+use std::io::{self, Read};
+
+use crate::prompts::PromptTagger;
+
+const PREFIX: &[u8] = b"\x1bPtmux;";
+const ESC: u8 = 0x1b;
+/// Payloads larger than this are dropped whole.
+const MAX_PAYLOAD: usize = 1 << 20;
+/// tmux inside tmux wraps twice; deeper than this is dropped.
+const MAX_DEPTH: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Outside a passthrough, `matched` bytes into a possible prefix.
+    Ground { matched: usize },
+    Payload,
+    /// After an ESC inside the payload: a second ESC is a literal one, a
+    /// backslash ends the passthrough.
+    PayloadEsc,
+    /// Inside an oversized payload, skipping to its end.
+    Discard,
+    DiscardEsc,
+}
+
+/// Streaming unwrapper; sequences may be split across reads.
+#[derive(Debug)]
+pub struct Unwrapper {
+    enabled: bool,
+    depth: usize,
+    state: State,
+    payload: Vec<u8>,
+}
+
+impl Unwrapper {
+    pub fn new(enabled: bool) -> Self {
+        Self::with_depth(enabled, 0)
+    }
+
+    fn with_depth(enabled: bool, depth: usize) -> Self {
+        Self { enabled, depth, state: State::Ground { matched: 0 }, payload: Vec::new() }
+    }
+
+    /// Append `input` to `out` with passthrough sequences unwrapped. A
+    /// partial prefix at the end is held back until the next call.
+    pub fn feed(&mut self, input: &[u8], out: &mut Vec<u8>) {
+        if !self.enabled {
+            out.extend_from_slice(input);
+            return;
+        }
+        for &byte in input {
+            self.advance(byte, out);
+        }
+    }
+
+    /// Release a held-back partial prefix (at EOF).
+    pub fn flush(&mut self, out: &mut Vec<u8>) {
+        if let State::Ground { matched } = self.state {
+            out.extend_from_slice(&PREFIX[..matched]);
+            self.state = State::Ground { matched: 0 };
+        }
+    }
+
+    fn advance(&mut self, byte: u8, out: &mut Vec<u8>) {
+        self.state = match self.state {
+            State::Ground { matched } if byte == PREFIX[matched] => {
+                if matched + 1 == PREFIX.len() {
+                    self.payload.clear();
+                    State::Payload
+                } else {
+                    State::Ground { matched: matched + 1 }
+                }
+            }
+            State::Ground { matched: 0 } => {
+                out.push(byte);
+                State::Ground { matched: 0 }
+            }
+            State::Ground { matched } => {
+                // Not a passthrough after all: pass the prefix bytes on and
+                // look at this byte afresh (it may be the ESC of the next).
+                out.extend_from_slice(&PREFIX[..matched]);
+                self.state = State::Ground { matched: 0 };
+                return self.advance(byte, out);
+            }
+            State::Payload if byte == ESC => State::PayloadEsc,
+            State::Payload if self.payload.len() >= MAX_PAYLOAD => {
+                self.payload = Vec::new();
+                State::Discard
+            }
+            State::Payload => {
+                self.payload.push(byte);
+                State::Payload
+            }
+            State::PayloadEsc if byte == ESC => {
+                self.payload.push(ESC);
+                State::Payload
+            }
+            State::PayloadEsc if byte == b'\\' => {
+                let payload = std::mem::take(&mut self.payload);
+                self.dispatch(&payload, out);
+                State::Ground { matched: 0 }
+            }
+            State::PayloadEsc => {
+                // A lone ESC can't appear in a well-formed payload; drop it.
+                self.payload.clear();
+                self.state = State::Ground { matched: 0 };
+                return self.advance(byte, out);
+            }
+            State::Discard if byte == ESC => State::DiscardEsc,
+            State::Discard => State::Discard,
+            State::DiscardEsc if byte == b'\\' => State::Ground { matched: 0 },
+            State::DiscardEsc => State::Discard,
+        };
+    }
+
+    /// Hand an unwrapped payload on, unwrapping it again if it is itself a
+    /// passthrough (nested tmux).
+    fn dispatch(&self, payload: &[u8], out: &mut Vec<u8>) {
+        if self.depth + 1 >= MAX_DEPTH {
+            log::debug!("Dropped tmux passthrough nested {} deep", self.depth + 1);
+            return;
+        }
+        let mut inner = Unwrapper::with_depth(true, self.depth + 1);
+        inner.feed(payload, out);
+        inner.flush(out);
+    }
+}
+
+/// A PTY reader that unwraps passthrough sequences and tags prompts.
+pub struct UnwrapReader<R> {
+    inner: R,
+    unwrapper: Unwrapper,
+    tagger: PromptTagger,
+    scratch: Vec<u8>,
+    unwrapped: Vec<u8>,
+    pending: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read> UnwrapReader<R> {
+    pub fn new(inner: R, enabled: bool) -> Self {
+        Self {
+            inner,
+            unwrapper: Unwrapper::new(enabled),
+            tagger: PromptTagger::default(),
+            scratch: Vec::new(),
+            unwrapped: Vec::new(),
+            pending: Vec::new(),
+            pos: 0,
+        }
+    }
+}
+
+impl<R: Read> Read for UnwrapReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Ok(0) means EOF to the PTY event loop, so never return it while
+        // the underlying reader still has data; a read that produced only
+        // held-back bytes reads again (or reports WouldBlock).
+        while self.pos == self.pending.len() {
+            self.pending.clear();
+            self.pos = 0;
+            self.scratch.resize(buf.len().max(1), 0);
+            let n = self.inner.read(&mut self.scratch)?;
+            self.unwrapped.clear();
+            if n == 0 {
+                self.unwrapper.flush(&mut self.unwrapped);
+                self.tagger.feed(&self.unwrapped, &mut self.pending);
+                self.tagger.flush(&mut self.pending);
+                if self.pending.is_empty() {
+                    return Ok(0);
+                }
+                break;
+            }
+            self.unwrapper.feed(&self.scratch[..n], &mut self.unwrapped);
+            self.tagger.feed(&self.unwrapped, &mut self.pending);
+        }
+        let n = buf.len().min(self.pending.len() - self.pos);
+        buf[..n].copy_from_slice(&self.pending[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(unix)]
+pub use pty::PassthroughPty;
+
+#[cfg(unix)]
+mod pty {
+    use std::fs::File;
+    use std::io::{self, Write};
+    use std::sync::Arc;
+
+    use alacritty_terminal::event::{OnResize, WindowSize};
+    use alacritty_terminal::tty::{ChildEvent, EventedPty, EventedReadWrite, Pty};
+    use polling::{Event, PollMode, Poller};
+
+    use super::UnwrapReader;
+    use crate::event::Backlog;
+
+    /// `tty::Pty` with its output read through an [`UnwrapReader`], and its
+    /// input written through a [`BacklogWriter`]. Both are dups of the
+    /// master, so polling the original still wakes them.
+    pub struct PassthroughPty {
+        pty: Pty,
+        reader: UnwrapReader<File>,
+        writer: BacklogWriter,
+    }
+
+    impl PassthroughPty {
+        pub fn new(pty: Pty, enabled: bool, backlog: Backlog) -> io::Result<Self> {
+            let reader = UnwrapReader::new(pty.file().try_clone()?, enabled);
+            let writer = BacklogWriter { file: pty.file().try_clone()?, backlog };
+            Ok(Self { pty, reader, writer })
+        }
+    }
+
+    /// The PTY master as input goes to the shell, taking what it writes off
+    /// the pane's backlog.
+    pub struct BacklogWriter {
+        file: File,
+        backlog: Backlog,
+    }
+
+    impl Write for BacklogWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let n = self.file.write(buf)?;
+            self.backlog.written(n);
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.file.flush()
+        }
+    }
+
+    impl EventedReadWrite for PassthroughPty {
+        type Reader = UnwrapReader<File>;
+        type Writer = BacklogWriter;
+
+        unsafe fn register(&mut self, poll: &Arc<Poller>, interest: Event, mode: PollMode) -> io::Result<()> {
+            unsafe { self.pty.register(poll, interest, mode) }
+        }
+
+        fn reregister(&mut self, poll: &Arc<Poller>, interest: Event, mode: PollMode) -> io::Result<()> {
+            self.pty.reregister(poll, interest, mode)
+        }
+
+        fn deregister(&mut self, poll: &Arc<Poller>) -> io::Result<()> {
+            self.pty.deregister(poll)
+        }
+
+        fn reader(&mut self) -> &mut Self::Reader {
+            &mut self.reader
+        }
+
+        fn writer(&mut self) -> &mut Self::Writer {
+            &mut self.writer
+        }
+    }
+
+    impl OnResize for PassthroughPty {
+        fn on_resize(&mut self, size: WindowSize) {
+            self.pty.on_resize(size);
+        }
+    }
+
+    impl EventedPty for PassthroughPty {
+        fn next_child_event(&mut self) -> Option<ChildEvent> {
+            self.pty.next_child_event()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use alacritty_terminal::event::{Event, EventListener};
+    use alacritty_terminal::term::{Config as TermConfig, Term};
+    use alacritty_terminal::vte::ansi::{Processor, StdSyncHandler};
+
+    use super::*;
+    use crate::terminal::TerminalSize;
+
+    fn unwrap(chunks: &[&[u8]]) -> Vec<u8> {
+        let mut unwrapper = Unwrapper::new(true);
+        let mut out = Vec::new();
+        for chunk in chunks {
+            unwrapper.feed(chunk, &mut out);
+        }
+        unwrapper.flush(&mut out);
+        out
+    }
+
+    /// `payload` wrapped the way tmux does it.
+    fn wrap(payload: &[u8]) -> Vec<u8> {
+        let mut out = PREFIX.to_vec();
+        for &b in payload {
+            if b == ESC {
+                out.push(ESC);
+            }
+            out.push(b);
+        }
+        out.extend_from_slice(b"\x1b\\");
+        out
+    }
+
+    const OSC52: &[u8] = b"\x1b]52;c;aGVsbG8=\x07";
+    const OSC0: &[u8] = b"\x1b]0;from tmux\x1b\\";
+
+    #[test]
+    fn unwraps_osc52_and_title() {
+        assert_eq!(unwrap(&[&wrap(OSC52)]), OSC52);
+        assert_eq!(unwrap(&[&wrap(OSC0)]), OSC0);
+        let mixed = [b"ls\r\n".as_slice(), &wrap(OSC0), b"$ "].concat();
+        assert_eq!(unwrap(&[&mixed]), [b"ls\r\n".as_slice(), OSC0, b"$ "].concat());
+    }
+
+    #[test]
+    fn sequences_split_across_reads() {
+        let wrapped = wrap(OSC0);
+        let bytes: Vec<&[u8]> = wrapped.chunks(1).collect();
+        assert_eq!(unwrap(&bytes), OSC0);
+    }
+
+    #[test]
+    fn other_sequences_pass_untouched() {
+        // Another DCS, a near-miss prefix, and an ESC right after a partial
+        // prefix.
+        let decrqss = b"\x1bP$qm\x1b\\";
+        assert_eq!(unwrap(&[decrqss]), decrqss);
+        assert_eq!(unwrap(&[b"\x1bPtmx;"]), b"\x1bPtmx;");
+        assert_eq!(unwrap(&[b"\x1bPt\x1b[1m"]), b"\x1bPt\x1b[1m");
+        assert_eq!(unwrap(&[b"\x1bPtm"]), b"\x1bPtm");
+
+        let mut off = Unwrapper::new(false);
+        let mut out = Vec::new();
+        off.feed(&wrap(OSC52), &mut out);
+        assert_eq!(out, wrap(OSC52));
+    }
+
+    #[test]
+    fn nested_passthrough_and_guards() {
+        assert_eq!(unwrap(&[&wrap(&wrap(OSC52))]), OSC52);
+        let too_deep = (0..MAX_DEPTH).fold(OSC52.to_vec(), |p, _| wrap(&p));
+        assert_eq!(unwrap(&[&too_deep]), b"");
+
+        let huge = [wrap(&vec![b'x'; MAX_PAYLOAD + 1]), b"after".to_vec()].concat();
+        assert_eq!(unwrap(&[&huge]), b"after");
+    }
+
+    #[test]
+    fn reader_never_reports_eof_early() {
+        let wrapped = [wrap(OSC0), b"tail".to_vec()].concat();
+        let mut reader = UnwrapReader::new(wrapped.as_slice(), true);
+        let mut out = Vec::new();
+        let mut buf = [0; 3];
+        loop {
+            match reader.read(&mut buf).unwrap() {
+                0 => break,
+                n => out.extend_from_slice(&buf[..n]),
+            }
+        }
+        assert_eq!(out, [OSC0, b"tail"].concat());
+    }
+
+    #[derive(Default)]
+    struct Events(RefCell<Vec<String>>);
+
+    impl EventListener for &Events {
+        fn send_event(&self, event: Event) {
+            let text = match event {
+                Event::Title(title) => format!("title {title}"),
+                Event::ClipboardStore(_, text) => format!("clipboard {text}"),
+                _ => return,
+            };
+            self.0.borrow_mut().push(text);
+        }
+    }
+
+    #[test]
+    fn unwrapped_sequences_reach_the_terminal() {
+        let events = Events::default();
+        let mut term = Term::new(TermConfig::default(), &TerminalSize::new(80, 24), &events);
+        let mut parser: Processor<StdSyncHandler> = Processor::new();
+        parser.advance(&mut term, &unwrap(&[&wrap(OSC0), &wrap(OSC52)]));
+        assert_eq!(*events.0.borrow(), ["title from tmux", "clipboard hello"]);
+    }
+}
@@ -0,0 +1,335 @@
+use std::path::{Path, PathBuf};
+
+use crate::event::{InputClosed, PtyInput};
+
+/// Most bytes of pasted text handed to the PTY thread at a time.
+pub const CHUNK: usize = 4096;
+
+/// Most chunks sent in one turn of the event loop, so a huge paste can't
+/// hold up input and drawing.
+const CHUNKS_PER_TURN: usize = 16;
+
+/// Pastes at least this long show their progress.
+pub const PROGRESS_THRESHOLD: usize = 64 * 1024;
+
+/// What `PasteFeeder::feed` got done.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Feed {
+    /// Everything was sent.
+    Done,
+    /// Bytes remain: the turn's budget ran out, or the shell is behind.
+    Pending,
+    /// The PTY thread is gone; the rest of the paste is dropped.
+    Closed,
+}
+
+/// A paste on its way to a pane, sent a chunk at a time as the shell
+/// keeps up rather than queued all at once.
+#[derive(Debug)]
+pub struct PasteFeeder {
+    pub pane_id: usize,
+    chunks: Vec<Vec<u8>>,
+    next: usize,
+    len: usize,
+    sent: usize,
+}
+
+impl PasteFeeder {
+    /// Feed `chunks`, as from `prepare_paste`, in order.
+    pub fn new(pane_id: usize, chunks: Vec<Vec<u8>>) -> Self {
+        let len = chunks.iter().map(Vec::len).sum();
+        PasteFeeder { pane_id, chunks, next: 0, len, sent: 0 }
+    }
+
+    /// Send what `input` will take this turn.
+    pub fn feed(&mut self, input: &impl PtyInput) -> Feed {
+        for _ in 0..CHUNKS_PER_TURN {
+            if self.next == self.chunks.len() {
+                return Feed::Done;
+            }
+            if !input.ready() {
+                return Feed::Pending;
+            }
+            let chunk = std::mem::take(&mut self.chunks[self.next]);
+            let chunk_len = chunk.len();
+            if let Err(InputClosed) = input.write(chunk) {
+                return Feed::Closed;
+            }
+            self.next += 1;
+            self.sent += chunk_len;
+        }
+        if self.next == self.chunks.len() {
+            Feed::Done
+        } else {
+            Feed::Pending
+        }
+    }
+
+    /// Send `bytes` once the rest of the paste has gone.
+    pub fn push(&mut self, bytes: Vec<u8>) {
+        self.len += bytes.len();
+        self.chunks.push(bytes);
+    }
+
+    /// How much has been sent, 0..=1, for pastes long enough to show it.
+    pub fn progress(&self) -> Option<f32> {
+        (self.len >= PROGRESS_THRESHOLD).then(|| self.sent as f32 / self.len as f32)
+    }
+}
+
+/// Pasted text as the writes that take it to the shell, each at most
+/// `CHUNK` bytes of text and split between characters.
+///
+/// When the app asked for bracketed paste, the start and end markers are
+/// writes of their own around the text, so however many chunks it takes
+/// the app sees one paste; any markers in the text itself are stripped so
+/// it can't end early. Otherwise line endings become CR, which is what
+/// Enter sends.
+pub fn prepare_paste(text: &str, bracketed: bool) -> Vec<Vec<u8>> {
+    let body = if bracketed {
+        text.replace("\x1b[200~", "").replace("\x1b[201~", "")
+    } else {
+        text.replace("\r\n", "\r").replace('\n', "\r")
+    };
+    let mut chunks = Vec::with_capacity(body.len() / CHUNK + 3);
+    if bracketed {
+        chunks.push(b"\x1b[200~".to_vec());
+    }
+    let mut rest = body.as_str();
+    while !rest.is_empty() {
+        let mut end = CHUNK.min(rest.len());
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        let (chunk, tail) = rest.split_at(end);
+        chunks.push(chunk.as_bytes().to_vec());
+        rest = tail;
+    }
+    if bracketed {
+        chunks.push(b"\x1b[201~".to_vec());
+    }
+    chunks
+}
+
+/// A paste with line breaks, held until the user says how to send it:
+/// into a shell, each line would run as it arrived.
+#[derive(Debug)]
+pub struct PendingPaste {
+    pub pane_id: usize,
+    pub text: String,
+}
+
+impl PendingPaste {
+    /// Lines shown in the confirmation panel.
+    pub const PREVIEW_LINES: usize = 3;
+
+    /// Whether pasting `text` into a shell would run something.
+    pub fn needs_confirmation(text: &str) -> bool {
+        text.contains(['\n', '\r'])
+    }
+
+    /// The paste's lines, broken at "\r\n", a lone "\r" or "\n" alike: any
+    /// of them would end a line in the shell. A final break adds no line.
+    fn lines(&self) -> impl Iterator<Item = &str> {
+        let text = ["\r\n", "\n", "\r"].iter().find_map(|end| self.text.strip_suffix(end)).unwrap_or(&self.text);
+        text.split("\r\n").flat_map(|line| line.split(['\r', '\n']))
+    }
+
+    /// The first lines of the paste, and how many more there are.
+    pub fn preview(&self) -> (Vec<&str>, usize) {
+        let lines: Vec<&str> = self.lines().collect();
+        let shown = lines.len().min(Self::PREVIEW_LINES);
+        let rest = lines.len() - shown;
+        (lines.into_iter().take(shown).collect(), rest)
+    }
+
+    /// The paste as one line to edit before running: lines trimmed and
+    /// joined with spaces, blank ones and backslash continuations dropped.
+    pub fn single_line(&self) -> String {
+        self.lines()
+            .map(|line| {
+                let line = line.trim();
+                line.strip_suffix('\\').map_or(line, str::trim_end)
+            })
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Quote `path` for a POSIX shell. Paths made only of characters no shell
+/// treats specially are left bare; anything else is single-quoted.
+pub fn shell_escape(path: &Path) -> String {
+    let s = path.to_string_lossy();
+    let safe = |c: char| c.is_ascii_alphanumeric() || "/._-+,:@%=".contains(c);
+    if !s.is_empty() && s.chars().all(safe) {
+        return s.into_owned();
+    }
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+/// Files from the clipboard as shell words separated by spaces, with a
+/// trailing space so the next argument can be typed straight away.
+pub fn file_list(paths: &[PathBuf]) -> Option<String> {
+    if paths.is_empty() {
+        return None;
+    }
+    let mut text = paths.iter().map(|p| shell_escape(p)).collect::<Vec<_>>().join(" ");
+    text.push(' ');
+    Some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+
+    use super::*;
+
+    fn escape(s: &str) -> String {
+        shell_escape(Path::new(s))
+    }
+
+    #[test]
+    fn plain_paths_stay_bare() {
+        assert_eq!(escape("/Users/me/notes.txt"), "/Users/me/notes.txt");
+        assert_eq!(escape("/tmp/a-b_c+d,e:f@g%h=i"), "/tmp/a-b_c+d,e:f@g%h=i");
+    }
+
+    #[test]
+    fn special_characters_are_quoted() {
+        assert_eq!(escape("/Users/me/My Documents/a b.txt"), "'/Users/me/My Documents/a b.txt'");
+        assert_eq!(escape("/tmp/$HOME`x`;rm"), "'/tmp/$HOME`x`;rm'");
+        assert_eq!(escape("/tmp/~x*?[]"), "'/tmp/~x*?[]'");
+        assert_eq!(escape("/tmp/caf\u{e9}"), "'/tmp/caf\u{e9}'");
+        assert_eq!(escape(""), "''");
+    }
+
+    #[test]
+    fn single_quotes_are_closed_and_escaped() {
+        assert_eq!(escape("/tmp/it's"), r"'/tmp/it'\''s'");
+    }
+
+    #[test]
+    fn multiple_files_are_space_separated() {
+        let paths = [PathBuf::from("/tmp/a.txt"), PathBuf::from("/tmp/b c.txt")];
+        assert_eq!(file_list(&paths).as_deref(), Some("/tmp/a.txt '/tmp/b c.txt' "));
+        assert_eq!(file_list(&[]), None);
+    }
+
+    /// A PTY thread that reads `per_turn` chunks between feeds, or has
+    /// stopped.
+    #[derive(Default)]
+    struct StubInput {
+        received: RefCell<Vec<u8>>,
+        writes: Cell<usize>,
+        per_turn: Option<usize>,
+        closed: bool,
+    }
+
+    impl PtyInput for StubInput {
+        fn write(&self, bytes: Vec<u8>) -> Result<(), InputClosed> {
+            if self.closed {
+                return Err(InputClosed);
+            }
+            self.writes.set(self.writes.get() + 1);
+            self.received.borrow_mut().extend(bytes);
+            Ok(())
+        }
+
+        fn ready(&self) -> bool {
+            self.per_turn.is_none_or(|n| self.writes.get() < n)
+        }
+    }
+
+    #[test]
+    fn slow_consumer_pauses_the_feeder_until_it_catches_up() {
+        let text: Vec<u8> = (0..10 * CHUNK).map(|i| (i % 251) as u8).collect();
+        let input = StubInput { per_turn: Some(3), ..Default::default() };
+        let mut feeder = PasteFeeder::new(7, text.chunks(CHUNK).map(<[u8]>::to_vec).collect());
+        assert_eq!(feeder.progress(), None, "short pastes don't show progress");
+
+        let mut turns = 0;
+        loop {
+            turns += 1;
+            match feeder.feed(&input) {
+                Feed::Pending => assert_eq!(input.writes.get(), 3, "stopped at backpressure"),
+                Feed::Done => break,
+                Feed::Closed => panic!("channel is open"),
+            }
+            // The shell reads what it was sent.
+            input.writes.set(0);
+        }
+        assert_eq!(turns, 4);
+        assert_eq!(*input.received.borrow(), text, "nothing lost or reordered");
+        assert_eq!(feeder.feed(&input), Feed::Done);
+    }
+
+    #[test]
+    fn turns_are_capped_and_progress_reported() {
+        let input = StubInput::default();
+        let text = "x".repeat(PROGRESS_THRESHOLD * 2);
+        let mut feeder = PasteFeeder::new(0, prepare_paste(&text, false));
+        assert_eq!(feeder.progress(), Some(0.0));
+        assert_eq!(feeder.feed(&input), Feed::Pending);
+        assert_eq!(input.received.borrow().len(), CHUNKS_PER_TURN * CHUNK);
+        assert_eq!(feeder.progress(), Some(0.5));
+        assert_eq!(feeder.feed(&input), Feed::Done);
+        assert_eq!(feeder.progress(), Some(1.0));
+    }
+
+    #[test]
+    fn closed_channel_ends_the_paste() {
+        let input = StubInput { closed: true, ..Default::default() };
+        let mut feeder = PasteFeeder::new(0, prepare_paste("echo hi\n", false));
+        assert_eq!(feeder.feed(&input), Feed::Closed);
+        assert!(input.received.borrow().is_empty());
+    }
+
+    #[test]
+    fn bracketed_paste_strips_markers() {
+        assert_eq!(prepare_paste("a\x1b[201~b", true), [&b"\x1b[200~"[..], b"ab", b"\x1b[201~"]);
+        assert_eq!(prepare_paste("", true), [b"\x1b[200~", b"\x1b[201~"]);
+    }
+
+    #[test]
+    fn pending_paste_previews_and_joins_its_lines() {
+        assert!(PendingPaste::needs_confirmation("ls\n"));
+        assert!(PendingPaste::needs_confirmation("a\rb"));
+        assert!(!PendingPaste::needs_confirmation("echo hi"));
+
+        let text = "curl -fsSL \\\n  https://example.com/install.sh \\\n  | sh\n\necho done\n";
+        let paste = PendingPaste { pane_id: 0, text: text.into() };
+        assert_eq!(paste.preview(), (vec!["curl -fsSL \\", "  https://example.com/install.sh \\", "  | sh"], 2));
+        assert_eq!(paste.single_line(), "curl -fsSL https://example.com/install.sh | sh echo done");
+
+        let paste = PendingPaste { pane_id: 0, text: "make\rmake install\r\nls\r".into() };
+        assert_eq!(paste.preview(), (vec!["make", "make install", "ls"], 0));
+        assert_eq!(paste.single_line(), "make make install ls");
+    }
+
+    #[test]
+    fn plain_paste_ends_lines_with_cr() {
+        assert_eq!(prepare_paste("ls\n", false), [b"ls\r"]);
+        assert_eq!(prepare_paste("a\r\nb\nc\r", false), [b"a\rb\rc\r"]);
+        // Bracketed pastes keep their newlines for the app to read.
+        assert_eq!(prepare_paste("a\r\nb\n", true)[1], b"a\r\nb\n");
+        assert!(prepare_paste("", false).is_empty());
+    }
+
+    #[test]
+    fn large_pastes_are_chunked_between_characters() {
+        // Three-byte characters don't divide CHUNK evenly: each chunk
+        // stops a byte short, and a fourth takes the remainder.
+        let text = "\u{2603}".repeat(CHUNK);
+        let chunks = prepare_paste(&text, true);
+        assert_eq!(chunks.first().unwrap(), b"\x1b[200~");
+        assert_eq!(chunks.last().unwrap(), b"\x1b[201~");
+        let body = &chunks[1..chunks.len() - 1];
+        assert_eq!(body.len(), 4);
+        for chunk in body {
+            assert!(chunk.len() <= CHUNK);
+            assert!(std::str::from_utf8(chunk).is_ok());
+        }
+        assert_eq!(body.concat(), text.as_bytes());
+    }
+}
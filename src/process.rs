@@ -0,0 +1,147 @@
+use alacritty_terminal::term::TermMode;
+
+/// Foreground process group of the terminal whose PTY master is `fd`.
+#[cfg(unix)]
+pub fn foreground_pgid(fd: std::os::unix::io::RawFd) -> Option<i32> {
+    let pgid = unsafe { libc::tcgetpgrp(fd) };
+    (pgid > 0).then_some(pgid)
+}
+
+/// What a pane's terminal is running in the foreground.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Foreground {
+    /// The process's name, as `ps` shows it.
+    pub name: String,
+    pub cwd: Option<std::path::PathBuf>,
+}
+
+/// Name of process `pid`.
+#[cfg(all(unix, not(target_os = "macos")))]
+pub fn name(pid: i32) -> Option<String> {
+    let comm = std::fs::read_to_string(format!("/proc/{pid}/comm")).ok()?;
+    let name = comm.trim_end();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// Name of process `pid`.
+#[cfg(target_os = "macos")]
+pub fn name(pid: i32) -> Option<String> {
+    let mut buf = [0u8; 256];
+    let len = unsafe { libc::proc_name(pid, buf.as_mut_ptr().cast(), buf.len() as u32) };
+    (len > 0).then(|| String::from_utf8_lossy(&buf[..len as usize]).into_owned())
+}
+
+/// Current directory of process `pid`, where the OS will say.
+#[cfg(all(unix, not(target_os = "macos")))]
+pub fn cwd(pid: i32) -> Option<std::path::PathBuf> {
+    std::fs::read_link(format!("/proc/{pid}/cwd")).ok()
+}
+
+/// Current directory of process `pid`, where the OS will say.
+#[cfg(target_os = "macos")]
+pub fn cwd(pid: i32) -> Option<std::path::PathBuf> {
+    use std::os::unix::ffi::OsStrExt;
+    let mut info: libc::proc_vnodepathinfo = unsafe { std::mem::zeroed() };
+    let size = std::mem::size_of::<libc::proc_vnodepathinfo>() as libc::c_int;
+    let buf = (&mut info as *mut libc::proc_vnodepathinfo).cast();
+    if unsafe { libc::proc_pidinfo(pid, libc::PROC_PIDVNODEPATHINFO, 0, buf, size) } != size {
+        return None;
+    }
+    let path = unsafe { std::ffi::CStr::from_ptr(info.pvi_cdir.vip_path.as_ptr().cast()) };
+    let path = std::ffi::OsStr::from_bytes(path.to_bytes());
+    (!path.is_empty()).then(|| path.into())
+}
+
+/// Whether a write to `fd` would go through now rather than block. Also
+/// true when the fd is in error, so the write is made and fails.
+#[cfg(unix)]
+pub fn writable(fd: std::os::unix::io::RawFd) -> bool {
+    let mut pollfd = libc::pollfd { fd, events: libc::POLLOUT, revents: 0 };
+    match unsafe { libc::poll(&mut pollfd, 1, 0) } {
+        0 => false,
+        n if n < 0 => true,
+        _ => pollfd.revents & (libc::POLLOUT | libc::POLLERR | libc::POLLHUP | libc::POLLNVAL) != 0,
+    }
+}
+
+/// Terminal modes an app left behind after handing the terminal back to the
+/// shell. `shell_in_foreground` is the heuristic for "the prompt returned":
+/// the shell leads its own process group, so it owns the foreground exactly
+/// when no job is running.
+///
+/// Returns a short description of what's stuck, or None if nothing is (or
+/// an app is still running and legitimately owns those modes).
+pub fn stuck_modes(mode: TermMode, shell_in_foreground: bool) -> Option<&'static str> {
+    if !shell_in_foreground {
+        return None;
+    }
+    match (mode.intersects(TermMode::MOUSE_MODE), mode.contains(TermMode::ALT_SCREEN)) {
+        (true, true) => Some("Mouse reporting and alternate screen left on"),
+        (true, false) => Some("Mouse reporting left on"),
+        (false, true) => Some("Alternate screen left on"),
+        (false, false) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn running_app_owns_its_modes() {
+        let mode = TermMode::MOUSE_REPORT_CLICK | TermMode::ALT_SCREEN;
+        assert_eq!(stuck_modes(mode, false), None);
+    }
+
+    #[test]
+    fn clean_shell_is_not_stuck() {
+        assert_eq!(stuck_modes(TermMode::default(), true), None);
+        assert_eq!(stuck_modes(TermMode::BRACKETED_PASTE, true), None);
+    }
+
+    #[test]
+    fn leftover_modes_are_reported() {
+        assert_eq!(stuck_modes(TermMode::MOUSE_DRAG, true), Some("Mouse reporting left on"));
+        assert_eq!(stuck_modes(TermMode::ALT_SCREEN, true), Some("Alternate screen left on"));
+        assert_eq!(
+            stuck_modes(TermMode::MOUSE_MOTION | TermMode::ALT_SCREEN, true),
+            Some("Mouse reporting and alternate screen left on"),
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_tty_has_no_foreground_group() {
+        let file = std::fs::File::open("/dev/null").unwrap();
+        use std::os::unix::io::AsRawFd;
+        assert_eq!(foreground_pgid(file.as_raw_fd()), None);
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    #[test]
+    fn reads_a_process_working_directory() {
+        let here = std::env::current_dir().unwrap();
+        assert_eq!(cwd(std::process::id() as i32), Some(here));
+        assert_eq!(cwd(-1), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn full_pipe_is_not_writable() {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let [read, write] = fds;
+        unsafe { libc::fcntl(write, libc::F_SETFL, libc::O_NONBLOCK) };
+        assert!(writable(write));
+        let chunk = [0u8; 4096];
+        while unsafe { libc::write(write, chunk.as_ptr().cast(), chunk.len()) } > 0 {}
+        assert!(!writable(write), "nothing reads the pipe");
+        let mut sink = [0u8; 0x1_0000];
+        assert!(unsafe { libc::read(read, sink.as_mut_ptr().cast(), sink.len()) } > 0);
+        assert!(writable(write));
+        unsafe {
+            libc::close(read);
+            libc::close(write);
+        }
+    }
+}
@@ -0,0 +1,323 @@
+//! `koi --profile`: record tracing spans to a Chrome trace file
+//! (chrome://tracing, Perfetto). Without it no subscriber is installed and
+//! the spans cost a relaxed atomic load each.
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+/// True if profiling was asked for with `--profile` or `KOI_PROFILE=1`.
+pub fn requested() -> bool {
+    crate::tabs::has_flag(std::env::args(), "--profile")
+        || std::env::var_os("KOI_PROFILE").is_some_and(|v| !v.is_empty() && v != "0")
+}
+
+/// Install the trace subscriber, writing to a new file in the state dir.
+/// Keep the guard alive until exit; dropping it finishes the file.
+pub fn install() -> Option<ProfileGuard> {
+    let dir = crate::config::state_dir()?;
+    let stamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let path = dir.join(format!("koi-trace-{stamp}.json"));
+    let open = std::fs::create_dir_all(&dir).and_then(|_| File::create(&path));
+    let file = match open {
+        Ok(file) => file,
+        Err(e) => {
+            log::error!("Profiling disabled: can't create {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    let trace = ChromeTrace::new(Box::new(BufWriter::new(file)));
+    let out = trace.out.clone();
+    if tracing::subscriber::set_global_default(trace).is_err() {
+        return None;
+    }
+    log::info!("Profiling to {}", path.display());
+    Some(ProfileGuard { out })
+}
+
+/// Flushes and closes the trace file on drop.
+pub struct ProfileGuard {
+    out: Arc<Mutex<Output>>,
+}
+
+impl Drop for ProfileGuard {
+    fn drop(&mut self) {
+        if let Ok(mut out) = self.out.lock() {
+            out.finish();
+        }
+    }
+}
+
+struct Output {
+    writer: Box<dyn Write + Send>,
+    first: bool,
+    finished: bool,
+}
+
+impl Output {
+    fn record(&mut self, json: &str) {
+        if self.finished {
+            return;
+        }
+        let sep = if self.first { "[\n" } else { ",\n" };
+        self.first = false;
+        let _ = self.writer.write_all(sep.as_bytes());
+        let _ = self.writer.write_all(json.as_bytes());
+    }
+
+    fn finish(&mut self) {
+        if self.finished {
+            return;
+        }
+        if self.first {
+            let _ = self.writer.write_all(b"[");
+        }
+        let _ = self.writer.write_all(b"\n]\n");
+        let _ = self.writer.flush();
+        self.finished = true;
+    }
+}
+
+struct SpanData {
+    name: &'static str,
+    args: String,
+    refs: usize,
+}
+
+/// Subscriber that writes span enter/exit as Chrome trace B/E events.
+struct ChromeTrace {
+    start: Instant,
+    next_id: AtomicU64,
+    spans: Mutex<HashMap<u64, SpanData>>,
+    out: Arc<Mutex<Output>>,
+}
+
+impl ChromeTrace {
+    fn new(writer: Box<dyn Write + Send>) -> Self {
+        Self {
+            start: Instant::now(),
+            next_id: AtomicU64::new(1),
+            spans: Mutex::new(HashMap::new()),
+            out: Arc::new(Mutex::new(Output { writer, first: true, finished: false })),
+        }
+    }
+
+    fn emit(&self, phase: char, name: &str, args: &str) {
+        let ts = self.start.elapsed().as_micros();
+        let mut json = format!(
+            r#"{{"name":"{}","ph":"{}","ts":{},"pid":1,"tid":{}"#,
+            escape(name),
+            phase,
+            ts,
+            thread_id()
+        );
+        if phase == 'i' {
+            json.push_str(r#","s":"t""#);
+        }
+        if !args.is_empty() {
+            let _ = write!(json, r#","args":{{{args}}}"#);
+        }
+        json.push('}');
+        if let Ok(mut out) = self.out.lock() {
+            out.record(&json);
+        }
+    }
+}
+
+impl Subscriber for ChromeTrace {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, attrs: &Attributes<'_>) -> Id {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut args = JsonArgs::default();
+        attrs.record(&mut args);
+        let data = SpanData { name: attrs.metadata().name(), args: args.0, refs: 1 };
+        self.spans.lock().unwrap().insert(id, data);
+        Id::from_u64(id)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        if let Some(data) = self.spans.lock().unwrap().get_mut(&span.into_u64()) {
+            let mut args = JsonArgs(std::mem::take(&mut data.args));
+            values.record(&mut args);
+            data.args = args.0;
+        }
+    }
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut args = JsonArgs::default();
+        event.record(&mut args);
+        self.emit('i', event.metadata().name(), &args.0);
+    }
+
+    fn enter(&self, span: &Id) {
+        let spans = self.spans.lock().unwrap();
+        if let Some(data) = spans.get(&span.into_u64()) {
+            let (name, args) = (data.name, data.args.clone());
+            drop(spans);
+            self.emit('B', name, &args);
+        }
+    }
+
+    fn exit(&self, span: &Id) {
+        let name = self.spans.lock().unwrap().get(&span.into_u64()).map(|d| d.name);
+        if let Some(name) = name {
+            self.emit('E', name, "");
+        }
+    }
+
+    fn clone_span(&self, span: &Id) -> Id {
+        if let Some(data) = self.spans.lock().unwrap().get_mut(&span.into_u64()) {
+            data.refs += 1;
+        }
+        span.clone()
+    }
+
+    fn try_close(&self, span: Id) -> bool {
+        let mut spans = self.spans.lock().unwrap();
+        let id = span.into_u64();
+        match spans.get_mut(&id) {
+            Some(data) if data.refs > 1 => {
+                data.refs -= 1;
+                false
+            }
+            Some(_) => {
+                spans.remove(&id);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Span/event fields as the inside of a JSON object.
+#[derive(Default)]
+struct JsonArgs(String);
+
+impl Visit for JsonArgs {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.record_str(field, &format!("{value:?}"));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if !self.0.is_empty() {
+            self.0.push(',');
+        }
+        let _ = write!(self.0, r#""{}":"{}""#, escape(field.name()), escape(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        if !self.0.is_empty() {
+            self.0.push(',');
+        }
+        let _ = write!(self.0, r#""{}":{}"#, escape(field.name()), value);
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        if !self.0.is_empty() {
+            self.0.push(',');
+        }
+        let _ = write!(self.0, r#""{}":{}"#, escape(field.name()), value);
+    }
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Small stable per-thread number for the trace's `tid`.
+fn thread_id() -> u64 {
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+    thread_local! {
+        static TID: u64 = NEXT.fetch_add(1, Ordering::Relaxed);
+    }
+    TID.with(|t| *t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writer that shares its buffer with the test.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn trace<F: FnOnce()>(f: F) -> String {
+        let buf = Shared::default();
+        let trace = ChromeTrace::new(Box::new(buf.clone()));
+        let out = trace.out.clone();
+        tracing::subscriber::with_default(trace, f);
+        out.lock().unwrap().finish();
+        let bytes = buf.0.lock().unwrap().clone();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn spans_become_begin_end_pairs() {
+        let json = trace(|| {
+            let _frame = tracing::info_span!("render").entered();
+            let _pane = tracing::info_span!("draw_grid", pane = 3u64).entered();
+        });
+        assert!(json.starts_with("[\n"));
+        assert!(json.trim_end().ends_with(']'));
+        let begins: Vec<_> = json.lines().filter(|l| l.contains(r#""ph":"B""#)).collect();
+        let ends = json.lines().filter(|l| l.contains(r#""ph":"E""#)).count();
+        assert_eq!(begins.len(), 2);
+        assert_eq!(ends, 2);
+        assert!(begins[0].contains(r#""name":"render""#));
+        assert!(begins[1].contains(r#""args":{"pane":3}"#));
+        // Inner span closes before the outer one.
+        let order: Vec<_> = json
+            .lines()
+            .filter_map(|l| ["render", "draw_grid"].into_iter().find(|n| l.contains(&format!(r#""name":"{n}""#))))
+            .collect();
+        assert_eq!(order, ["render", "draw_grid", "draw_grid", "render"]);
+    }
+
+    #[test]
+    fn empty_trace_is_valid_json_array() {
+        assert_eq!(trace(|| {}), "[\n]\n");
+    }
+
+    #[test]
+    fn escapes_strings() {
+        assert_eq!(escape("a\"b\\c\n"), "a\\\"b\\\\c\\u000a");
+    }
+}
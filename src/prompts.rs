@@ -0,0 +1,320 @@
+//! Shell integration: prompts marked with OSC 133 (the FinalTerm protocol
+//! iTerm2, WezTerm and kitty speak too), for hopping between commands with
+//! Cmd+Up / Cmd+Down and copying the last one's output with Cmd+Shift+A.
+//!
+//! alacritty_terminal ignores OSC 133, so the PTY reader rewrites each
+//! prompt-start mark (`OSC 133 ; A`) into an OSC 8 hyperlink over the
+//! prompt's first character. The mark then lives in that cell: it moves
+//! into history with its line and goes when the line drops off the top,
+//! and `prompt_lines` finds it again by looking for the link.
+
+use alacritty_terminal::grid::Dimensions;
+use alacritty_terminal::index::{Column, Line, Point, Side};
+use alacritty_terminal::selection::{Selection, SelectionType};
+use alacritty_terminal::term::cell::Hyperlink;
+use alacritty_terminal::term::{Term, TermMode};
+
+/// For `eval "$(koi --shell-integration)"` in `.bashrc` or `.zshrc`: starts
+/// each prompt with the mark.
+pub const SHELL_SNIPPET: &str = r#"# koi shell integration: mark each prompt with OSC 133;A.
+if [ -n "$ZSH_VERSION" ]; then
+  case "$PS1" in *'133;A'*) ;; *) PS1=$'%{\e]133;A\a%}'"$PS1" ;; esac
+elif [ -n "$BASH_VERSION" ]; then
+  case "$PS1" in *'133;A'*) ;; *) PS1='\[\e]133;A\a\]'"$PS1" ;; esac
+fi
+"#;
+
+/// The link a prompt's first cell carries.
+const URI: &str = "koi-prompt:";
+const OPEN: &[u8] = b"\x1b]8;;koi-prompt:\x1b\\";
+const CLOSE: &[u8] = b"\x1b]8;;\x1b\\";
+/// The OSC body of a prompt-start mark; parameters may follow after a `;`.
+const MARK: &[u8] = b"133;A";
+/// An OSC this long is no mark, and is passed on rather than held.
+const MAX_MARK: usize = 256;
+const ESC: u8 = 0x1b;
+const BEL: u8 = 0x07;
+
+/// Whether `link` is a prompt mark rather than a link from an app.
+pub fn is_mark(link: &Hyperlink) -> bool {
+    link.uri() == URI
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    /// After an ESC, not yet passed on: it may open an OSC.
+    Esc,
+    /// In an escape sequence's intermediates.
+    EscIntermediate,
+    Csi,
+    /// In an OSC; `osc` holds it while it may be a mark.
+    Osc,
+    /// After an ESC in an OSC, not yet passed on: `\` ends the OSC.
+    OscEsc,
+    /// In a DCS, SOS, PM or APC string.
+    Str,
+    StrEsc,
+}
+
+/// Streaming rewriter of prompt marks into links; sequences may be split
+/// across reads.
+#[derive(Debug)]
+pub struct PromptTagger {
+    state: State,
+    osc: Vec<u8>,
+    /// A mark was seen: the next printable character is the prompt's first.
+    pending: bool,
+    /// UTF-8 continuation bytes of the tagged character still to come
+    /// before the link is closed.
+    tagging: u8,
+}
+
+impl Default for PromptTagger {
+    fn default() -> Self {
+        Self { state: State::Ground, osc: Vec::new(), pending: false, tagging: 0 }
+    }
+}
+
+impl PromptTagger {
+    /// Append `input` to `out` with prompt marks rewritten. A sequence that
+    /// may still turn out to be a mark is held back until the next call.
+    pub fn feed(&mut self, input: &[u8], out: &mut Vec<u8>) {
+        for &byte in input {
+            self.advance(byte, out);
+        }
+    }
+
+    /// Release whatever is held back (at EOF).
+    pub fn flush(&mut self, out: &mut Vec<u8>) {
+        out.append(&mut self.osc);
+        if matches!(self.state, State::Esc | State::OscEsc) {
+            out.push(ESC);
+        }
+        self.state = State::Ground;
+    }
+
+    fn advance(&mut self, byte: u8, out: &mut Vec<u8>) {
+        if self.tagging > 0 {
+            if (0x80..0xc0).contains(&byte) {
+                out.push(byte);
+                self.tagging -= 1;
+                if self.tagging == 0 {
+                    out.extend_from_slice(CLOSE);
+                }
+                return;
+            }
+            // A broken character: close the link before whatever this is.
+            self.tagging = 0;
+            out.extend_from_slice(CLOSE);
+        }
+        self.state = match self.state {
+            State::Ground if byte == ESC => State::Esc,
+            State::Ground if byte < 0x20 || byte == 0x7f => {
+                out.push(byte);
+                State::Ground
+            }
+            State::Ground => {
+                self.print(byte, out);
+                State::Ground
+            }
+            State::Esc if byte == b']' => {
+                self.osc.extend_from_slice(b"\x1b]");
+                State::Osc
+            }
+            State::Esc => {
+                out.extend_from_slice(&[ESC, byte]);
+                match byte {
+                    ESC => {
+                        out.pop();
+                        State::Esc
+                    }
+                    b'[' => State::Csi,
+                    b'P' | b'X' | b'^' | b'_' => State::Str,
+                    0x20..=0x2f => State::EscIntermediate,
+                    _ => State::Ground,
+                }
+            }
+            State::EscIntermediate => {
+                out.push(byte);
+                if (0x20..=0x2f).contains(&byte) { State::EscIntermediate } else { State::Ground }
+            }
+            State::Csi => {
+                out.push(byte);
+                if (0x40..=0x7e).contains(&byte) { State::Ground } else { State::Csi }
+            }
+            State::Osc if byte == BEL => {
+                self.end_osc(&[BEL], out);
+                State::Ground
+            }
+            State::Osc if byte == ESC => State::OscEsc,
+            State::Osc if self.osc.is_empty() => {
+                out.push(byte);
+                State::Osc
+            }
+            State::Osc => {
+                self.osc.push(byte);
+                if !self.may_be_mark() {
+                    out.append(&mut self.osc);
+                }
+                State::Osc
+            }
+            State::OscEsc if byte == b'\\' => {
+                self.end_osc(b"\x1b\\", out);
+                State::Ground
+            }
+            State::OscEsc => {
+                // Cut short by another sequence.
+                out.append(&mut self.osc);
+                self.state = State::Esc;
+                return self.advance(byte, out);
+            }
+            State::Str => {
+                out.push(byte);
+                if byte == ESC { State::StrEsc } else { State::Str }
+            }
+            State::StrEsc => {
+                out.push(byte);
+                if byte == b'\\' { State::Ground } else { State::Str }
+            }
+        };
+    }
+
+    /// Pass on a printable byte, inside the link if it starts a prompt.
+    fn print(&mut self, byte: u8, out: &mut Vec<u8>) {
+        if !self.pending {
+            out.push(byte);
+            return;
+        }
+        self.pending = false;
+        out.extend_from_slice(OPEN);
+        out.push(byte);
+        self.tagging = match byte {
+            0xf0.. => 3,
+            0xe0.. => 2,
+            0xc0.. => 1,
+            _ => 0,
+        };
+        if self.tagging == 0 {
+            out.extend_from_slice(CLOSE);
+        }
+    }
+
+    /// The held OSC so far could still be a mark.
+    fn may_be_mark(&self) -> bool {
+        let body = &self.osc[2..];
+        self.osc.len() <= MAX_MARK && (MARK.starts_with(body) || body.starts_with(b"133;A;"))
+    }
+
+    /// The OSC ended with `terminator`: a mark is dropped and remembered,
+    /// anything else passed on.
+    fn end_osc(&mut self, terminator: &[u8], out: &mut Vec<u8>) {
+        let body = self.osc.get(2..).unwrap_or_default();
+        if body == MARK || body.starts_with(b"133;A;") {
+            self.pending = true;
+            self.osc.clear();
+            return;
+        }
+        out.append(&mut self.osc);
+        out.extend_from_slice(terminator);
+    }
+}
+
+/// Grid lines that start a prompt, top to bottom. None on the alternate
+/// screen, which has no history and no shell prompt.
+pub fn prompt_lines<T>(term: &Term<T>) -> Vec<Line> {
+    if term.mode().contains(TermMode::ALT_SCREEN) {
+        return Vec::new();
+    }
+    let grid = term.grid();
+    let top = -(grid.history_size() as i32);
+    let cols = term.columns();
+    (top..term.screen_lines() as i32)
+        .map(Line)
+        .filter(|&line| {
+            let row = &grid[line];
+            (0..cols).any(|col| row[Column(col)].hyperlink().is_some_and(|link| is_mark(&link)))
+        })
+        .collect()
+}
+
+/// The prompt to bring to the top of a view whose top line is `top`: the
+/// nearest one above it, or below it when `forward`.
+pub fn step(prompts: &[Line], top: Line, forward: bool) -> Option<Line> {
+    if forward {
+        prompts.iter().copied().find(|&line| line > top)
+    } else {
+        prompts.iter().copied().rev().find(|&line| line < top)
+    }
+}
+
+/// The lines the last finished command printed: from below the
+/// next-to-last prompt to above the last. None if it printed nothing.
+pub fn last_output(prompts: &[Line]) -> Option<(Line, Line)> {
+    let [.., prev, last] = prompts else { return None };
+    (last.0 - prev.0 >= 2).then(|| (Line(prev.0 + 1), Line(last.0 - 1)))
+}
+
+/// Select the last finished command's output, whole lines. False, leaving
+/// the selection alone, if there's none.
+pub fn select_last_output<T>(term: &mut Term<T>) -> bool {
+    let Some((start, end)) = last_output(&prompt_lines(term)) else { return false };
+    let mut selection = Selection::new(SelectionType::Lines, Point::new(start, Column(0)), Side::Left);
+    selection.update(Point::new(end, term.last_column()), Side::Right);
+    term.selection = Some(selection);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use alacritty_terminal::event::VoidListener;
+    use alacritty_terminal::term::Config as TermConfig;
+    use alacritty_terminal::vte::ansi::{Processor, StdSyncHandler};
+
+    use super::*;
+    use crate::terminal::TerminalSize;
+
+    fn tag(chunks: &[&[u8]]) -> Vec<u8> {
+        let mut tagger = PromptTagger::default();
+        let mut out = Vec::new();
+        for chunk in chunks {
+            tagger.feed(chunk, &mut out);
+        }
+        tagger.flush(&mut out);
+        out
+    }
+
+    #[test]
+    fn marks_become_a_link_over_the_prompts_first_character() {
+        let tagged = b"\x1b]8;;koi-prompt:\x1b\\$\x1b]8;;\x1b\\ ls";
+        assert_eq!(tag(&[b"\x1b]133;A\x07$ ls"]), tagged);
+        // Split anywhere, with ST, parameters and colors in between.
+        assert_eq!(tag(&[b"\x1b]13", b"3;A;aid=7\x1b", b"\\\r\n\x1b[1;3", b"2m\xce", b"\xbb"]),
+            b"\r\n\x1b[1;32m\x1b]8;;koi-prompt:\x1b\\\xce\xbb\x1b]8;;\x1b\\");
+    }
+
+    #[test]
+    fn everything_else_passes_untouched() {
+        let input: &[&[u8]] = &[b"\x1b]0;title\x07\x1b]133;B\x07\x1b]13", b"3x\x1b\\\x1bPq\x1b\\\x1b(B\x1b[?25h\x1b\x1b]8;;u\x07x"];
+        assert_eq!(tag(input), input.concat());
+        assert_eq!(tag(&[b"\x1b]133;"]), b"\x1b]133;");
+        assert_eq!(tag(&[b"\x1b"]), b"\x1b");
+    }
+
+    #[test]
+    fn prompts_are_found_and_stepped_through() {
+        let mut term = Term::new(TermConfig::default(), &TerminalSize::new(20, 5), VoidListener);
+        let mut parser: Processor<StdSyncHandler> = Processor::new();
+        let session: Vec<u8> = (0..4).flat_map(|i| format!("\x1b]133;A\x07$ cmd{i}\r\nout\r\nout\r\n").into_bytes()).collect();
+        parser.advance(&mut term, &tag(&[&session, b"\x1b]133;A\x07$ "]));
+
+        let prompts = prompt_lines(&term);
+        assert_eq!(prompts, [-8, -5, -2, 1, 4].map(Line));
+        assert_eq!(step(&prompts, Line(0), false), Some(Line(-2)));
+        assert_eq!(step(&prompts, Line(-2), false), Some(Line(-5)));
+        assert_eq!(step(&prompts, Line(-8), false), None);
+        assert_eq!(step(&prompts, Line(-5), true), Some(Line(-2)));
+        assert_eq!(last_output(&prompts), Some((Line(2), Line(3))));
+        assert_eq!(last_output(&[Line(1), Line(2)]), None);
+    }
+}
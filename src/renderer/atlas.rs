@@ -1,9 +1,17 @@
 use crate::gl;
 use crate::gl::types::*;
 
+/// Width and height of each atlas page.
+pub const PAGE_SIZE: i32 = 2048;
+
 /// A glyph stored in the atlas.
 #[derive(Clone, Copy, Debug)]
 pub struct Glyph {
+    /// The atlas page holding the glyph's pixels.
+    pub page: usize,
+    /// A color bitmap (emoji), drawn in its own colors rather than as
+    /// coverage in the text color.
+    pub colored: bool,
     pub uv_x: f32,
     pub uv_y: f32,
     pub uv_w: f32,
@@ -14,21 +22,95 @@ pub struct Glyph {
     pub height: f32,
 }
 
-/// Row-based glyph packing into an OpenGL texture.
-pub struct Atlas {
-    tex_id: GLuint,
-    width: i32,
-    height: i32,
+impl Glyph {
+    /// A glyph with nothing to draw.
+    pub fn empty(left: f32, top: f32) -> Self {
+        Glyph {
+            page: 0,
+            colored: false,
+            uv_x: 0.0,
+            uv_y: 0.0,
+            uv_w: 0.0,
+            uv_h: 0.0,
+            left,
+            top,
+            width: 0.0,
+            height: 0.0,
+        }
+    }
+}
+
+/// Where a glyph was packed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Slot {
+    pub page: usize,
+    pub x: i32,
+    pub y: i32,
+}
+
+/// Row-based packing across square pages: glyphs fill a page row by row,
+/// and a glyph that doesn't fit in what's left starts the next page.
+#[derive(Debug)]
+pub struct PagePacker {
+    size: i32,
+    page: usize,
     row_extent: i32,
     row_baseline: i32,
     row_tallest: i32,
 }
 
+impl PagePacker {
+    pub fn new(size: i32) -> Self {
+        PagePacker { size, page: 0, row_extent: 0, row_baseline: 0, row_tallest: 0 }
+    }
+
+    /// How many pages are in use.
+    pub fn pages(&self) -> usize {
+        self.page + 1
+    }
+
+    /// Find room for a `width` x `height` glyph. None only if it is larger
+    /// than a page.
+    pub fn place(&mut self, width: i32, height: i32) -> Option<Slot> {
+        if width > self.size || height > self.size {
+            return None;
+        }
+
+        // Check if glyph fits in current row
+        if self.row_extent + width > self.size {
+            // Move to next row
+            self.row_baseline += self.row_tallest;
+            self.row_extent = 0;
+            self.row_tallest = 0;
+        }
+
+        // Check if glyph fits vertically, or start a new page
+        if self.row_baseline + height > self.size {
+            self.page += 1;
+            self.row_baseline = 0;
+            self.row_extent = 0;
+            self.row_tallest = 0;
+        }
+
+        let slot = Slot { page: self.page, x: self.row_extent, y: self.row_baseline };
+        self.row_extent += width;
+        self.row_tallest = self.row_tallest.max(height);
+        Some(slot)
+    }
+}
+
+/// Glyph pixels in a chain of OpenGL textures, one per `PagePacker` page.
+pub struct Atlas {
+    packer: PagePacker,
+    textures: Vec<GLuint>,
+}
+
 impl Atlas {
-    fn alloc_texture(tex_id: &mut GLuint, size: i32) {
+    fn alloc_texture(size: i32) -> GLuint {
+        let mut tex_id: GLuint = 0;
         unsafe {
-            gl::GenTextures(1, tex_id);
-            gl::BindTexture(gl::TEXTURE_2D, *tex_id);
+            gl::GenTextures(1, &mut tex_id);
+            gl::BindTexture(gl::TEXTURE_2D, tex_id);
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
@@ -37,52 +119,36 @@ impl Atlas {
             gl::TexImage2D(
                 gl::TEXTURE_2D,
                 0,
-                gl::RGB8 as i32,
+                gl::RGBA8 as i32,
                 size,
                 size,
                 0,
-                gl::RGB,
+                gl::RGBA,
                 gl::UNSIGNED_BYTE,
                 std::ptr::null(),
             );
         }
+        tex_id
     }
 
-    pub fn new(size: i32) -> Self {
-        let mut tex_id: GLuint = 0;
-        Self::alloc_texture(&mut tex_id, size);
-
-        Atlas {
-            tex_id,
-            width: size,
-            height: size,
-            row_extent: 0,
-            row_baseline: 0,
-            row_tallest: 0,
-        }
-    }
-
-    pub fn tex_id(&self) -> GLuint {
-        self.tex_id
+    pub fn new() -> Self {
+        Atlas { packer: PagePacker::new(PAGE_SIZE), textures: vec![Self::alloc_texture(PAGE_SIZE)] }
     }
 
-    pub fn width(&self) -> i32 {
-        self.width
+    /// The texture of each page, by page index.
+    pub fn tex_ids(&self) -> &[GLuint] {
+        &self.textures
     }
 
-    /// Destroy the current texture and allocate a new one at `new_size`.
-    /// Resets all packing state — callers must clear their glyph caches.
-    pub fn regrow(&mut self, new_size: i32) {
-        unsafe { gl::DeleteTextures(1, &self.tex_id); }
-        Self::alloc_texture(&mut self.tex_id, new_size);
-        self.width = new_size;
-        self.height = new_size;
-        self.row_extent = 0;
-        self.row_baseline = 0;
-        self.row_tallest = 0;
+    /// Destroy every page and start over with one empty page.
+    /// Callers must clear their glyph caches.
+    pub fn reset(&mut self) {
+        unsafe { gl::DeleteTextures(self.textures.len() as i32, self.textures.as_ptr()); }
+        *self = Atlas { packer: PagePacker::new(PAGE_SIZE), textures: vec![Self::alloc_texture(PAGE_SIZE)] };
     }
 
-    /// Insert a glyph into the atlas. Returns None if atlas is full.
+    /// Insert a glyph's RGBA pixels into the atlas, adding a page if the
+    /// current one is full. Returns None if the glyph is larger than a page.
     pub fn insert(
         &mut self,
         glyph_width: i32,
@@ -90,66 +156,42 @@ impl Atlas {
         buffer: &[u8],
         left: f32,
         top: f32,
+        colored: bool,
     ) -> Option<Glyph> {
         if glyph_width == 0 || glyph_height == 0 {
-            return Some(Glyph {
-
-                uv_x: 0.0,
-                uv_y: 0.0,
-                uv_w: 0.0,
-                uv_h: 0.0,
-                left,
-                top,
-                width: 0.0,
-                height: 0.0,
-            });
-        }
-
-        // Check if glyph fits in current row
-        if self.row_extent + glyph_width > self.width {
-            // Move to next row
-            self.row_baseline += self.row_tallest;
-            self.row_extent = 0;
-            self.row_tallest = 0;
+            return Some(Glyph::empty(left, top));
         }
 
-        // Check if glyph fits vertically
-        if self.row_baseline + glyph_height > self.height {
-            return None; // Atlas full
+        let slot = self.packer.place(glyph_width, glyph_height)?;
+        if self.packer.pages() > self.textures.len() {
+            log::info!("Glyph atlas page {} full, adding page {}", slot.page, slot.page + 1);
+            self.textures.push(Self::alloc_texture(PAGE_SIZE));
         }
 
-        let x = self.row_extent;
-        let y = self.row_baseline;
-
         unsafe {
-            gl::BindTexture(gl::TEXTURE_2D, self.tex_id);
+            gl::BindTexture(gl::TEXTURE_2D, self.textures[slot.page]);
             gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
             gl::TexSubImage2D(
                 gl::TEXTURE_2D,
                 0,
-                x,
-                y,
+                slot.x,
+                slot.y,
                 glyph_width,
                 glyph_height,
-                gl::RGB,
+                gl::RGBA,
                 gl::UNSIGNED_BYTE,
                 buffer.as_ptr() as *const _,
             );
         }
 
-        self.row_extent += glyph_width;
-        if glyph_height > self.row_tallest {
-            self.row_tallest = glyph_height;
-        }
-
-        let w = self.width as f32;
-        let h = self.height as f32;
-
+        let size = PAGE_SIZE as f32;
         Some(Glyph {
-            uv_x: x as f32 / w,
-            uv_y: y as f32 / h,
-            uv_w: glyph_width as f32 / w,
-            uv_h: glyph_height as f32 / h,
+            page: slot.page,
+            colored,
+            uv_x: slot.x as f32 / size,
+            uv_y: slot.y as f32 / size,
+            uv_w: glyph_width as f32 / size,
+            uv_h: glyph_height as f32 / size,
             left,
             top,
             width: glyph_width as f32,
@@ -161,7 +203,42 @@ impl Atlas {
 impl Drop for Atlas {
     fn drop(&mut self) {
         unsafe {
-            gl::DeleteTextures(1, &self.tex_id);
+            gl::DeleteTextures(self.textures.len() as i32, self.textures.as_ptr());
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn full_pages_spill_onto_new_ones() {
+        // 200k codepoints in four faces, each in a 12x24 cell: far past
+        // what one page holds.
+        let (w, h) = (12, 24);
+        let mut packer = PagePacker::new(PAGE_SIZE);
+        let mut slots = HashSet::new();
+        for _ in 0..200_000 * 4 {
+            let slot = packer.place(w, h).expect("every glyph gets a slot");
+            assert!(slot.x + w <= PAGE_SIZE && slot.y + h <= PAGE_SIZE, "{slot:?} overflows its page");
+            assert!(slots.insert(slot), "{slot:?} handed out twice");
+        }
+        let per_page = ((PAGE_SIZE / w) * (PAGE_SIZE / h)) as usize;
+        assert_eq!(packer.pages(), 800_000usize.div_ceil(per_page));
+        assert_eq!(slots.iter().filter(|s| s.page == 0).count(), per_page);
+    }
+
+    #[test]
+    fn mixed_heights_start_rows_below_the_tallest() {
+        let mut packer = PagePacker::new(100);
+        assert_eq!(packer.place(60, 10), Some(Slot { page: 0, x: 0, y: 0 }));
+        assert_eq!(packer.place(30, 40), Some(Slot { page: 0, x: 60, y: 0 }));
+        assert_eq!(packer.place(20, 10), Some(Slot { page: 0, x: 0, y: 40 }));
+        assert_eq!(packer.place(100, 60), Some(Slot { page: 1, x: 0, y: 0 }));
+        assert_eq!(packer.place(101, 1), None, "wider than a page");
+        assert_eq!(packer.pages(), 2);
+    }
+}
@@ -0,0 +1,207 @@
+//! The theme-independent half of color resolution: where each ANSI color
+//! lives in a theme's 16-entry palette, and the fixed xterm colors above it.
+
+use alacritty_terminal::vte::ansi::NamedColor;
+
+/// Palette slot (0-15) of one of the sixteen ANSI colors, or None for the
+/// other named colors (foreground, background, cursor, dim variants).
+/// Spelled out rather than cast so a reordering of `NamedColor` can't
+/// shuffle the palette.
+pub fn palette_index(named: NamedColor) -> Option<usize> {
+    let idx = match named {
+        NamedColor::Black => 0,
+        NamedColor::Red => 1,
+        NamedColor::Green => 2,
+        NamedColor::Yellow => 3,
+        NamedColor::Blue => 4,
+        NamedColor::Magenta => 5,
+        NamedColor::Cyan => 6,
+        NamedColor::White => 7,
+        NamedColor::BrightBlack => 8,
+        NamedColor::BrightRed => 9,
+        NamedColor::BrightGreen => 10,
+        NamedColor::BrightYellow => 11,
+        NamedColor::BrightBlue => 12,
+        NamedColor::BrightMagenta => 13,
+        NamedColor::BrightCyan => 14,
+        NamedColor::BrightWhite => 15,
+        NamedColor::Foreground
+        | NamedColor::Background
+        | NamedColor::Cursor
+        | NamedColor::DimBlack
+        | NamedColor::DimRed
+        | NamedColor::DimGreen
+        | NamedColor::DimYellow
+        | NamedColor::DimBlue
+        | NamedColor::DimMagenta
+        | NamedColor::DimCyan
+        | NamedColor::DimWhite
+        | NamedColor::BrightForeground
+        | NamedColor::DimForeground => return None,
+    };
+    Some(idx)
+}
+
+pub fn rgb_to_f32(rgb: [u8; 3]) -> [f32; 3] {
+    rgb.map(|c| c as f32 / 255.0)
+}
+
+/// Perceived brightness, 0.0 (black) to 1.0 (white).
+pub fn luminance([r, g, b]: [f32; 3]) -> f32 {
+    0.299 * r + 0.587 * g + 0.114 * b
+}
+
+/// The first of `candidates` that reads clearly on `bg`, else black or
+/// white, whichever does.
+pub fn legible_on(bg: [f32; 3], candidates: &[[f32; 3]]) -> [f32; 3] {
+    let l = luminance(bg);
+    let fallback = if l > 0.5 { [0.0; 3] } else { [1.0; 3] };
+    candidates.iter().copied().find(|&c| (luminance(c) - l).abs() >= 0.3).unwrap_or(fallback)
+}
+
+/// A `#rrggbb` color, the `#` optional.
+pub fn parse_hex(s: &str) -> Option<[f32; 3]> {
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let byte = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some(rgb_to_f32([byte(0)?, byte(2)?, byte(4)?]))
+}
+
+/// Map a single 6-level color-cube axis value (0-5) to its xterm byte value.
+/// xterm uses: [0x00, 0x5f, 0x87, 0xaf, 0xd7, 0xff]
+/// which is: if v == 0 { 0 } else { 55 + v * 40 }.
+fn cube_component(v: u8) -> u8 {
+    if v == 0 { 0 } else { 55 + v * 40 }
+}
+
+/// The xterm palette's byte values for 256-color indices 16-255. Indices
+/// below 16 belong to the theme; they come back as mid gray.
+pub fn index_to_rgb8(idx: u8) -> [u8; 3] {
+    if idx < 16 {
+        return [128, 128, 128];
+    }
+    if idx < 232 {
+        // Color cube: 6x6x6 — each axis maps through cube_component()
+        let i = idx - 16;
+        [cube_component(i / 36), cube_component((i % 36) / 6), cube_component(i % 6)]
+    } else {
+        // Grayscale ramp: 24 shades, 8 + index * 10
+        let level = 8 + (idx - 232) * 10;
+        [level; 3]
+    }
+}
+
+/// Convert 256-color index (16-255) to RGB floats using the standard xterm palette.
+pub fn index_to_rgb(idx: u8) -> [f32; 3] {
+    rgb_to_f32(index_to_rgb8(idx))
+}
+
+#[cfg(test)]
+mod tests {
+    use alacritty_terminal::vte::ansi::{Color, Rgb};
+
+    use super::*;
+    use crate::renderer::Theme;
+
+    const ANSI: [NamedColor; 16] = [
+        NamedColor::Black,
+        NamedColor::Red,
+        NamedColor::Green,
+        NamedColor::Yellow,
+        NamedColor::Blue,
+        NamedColor::Magenta,
+        NamedColor::Cyan,
+        NamedColor::White,
+        NamedColor::BrightBlack,
+        NamedColor::BrightRed,
+        NamedColor::BrightGreen,
+        NamedColor::BrightYellow,
+        NamedColor::BrightBlue,
+        NamedColor::BrightMagenta,
+        NamedColor::BrightCyan,
+        NamedColor::BrightWhite,
+    ];
+
+    fn rgb(c: [f32; 4]) -> [f32; 3] {
+        [c[0], c[1], c[2]]
+    }
+
+    #[test]
+    fn named_colors_resolve_to_their_theme_slot() {
+        for &(name, theme) in Theme::BUILTIN {
+            let theme = theme();
+            for (slot, named) in ANSI.into_iter().enumerate() {
+                assert_eq!(palette_index(named), Some(slot));
+                let resolved = theme.resolve_color(&Color::Named(named));
+                assert_eq!(rgb(resolved), theme.colors[slot], "{name}: {named:?}");
+                assert_eq!(resolved[3], 1.0);
+                let indexed = theme.resolve_color(&Color::Indexed(slot as u8));
+                assert_eq!(indexed, resolved, "{name}: index {slot}");
+            }
+            assert_eq!(theme.resolve_color(&Color::Named(NamedColor::Foreground)), theme.fg4(), "{name}");
+            assert_eq!(theme.resolve_color(&Color::Named(NamedColor::Background)), theme.bg4(), "{name}");
+            let dim_red = theme.resolve_color(&Color::Named(NamedColor::DimRed));
+            assert_eq!(rgb(dim_red), theme.colors[1].map(|c| c * 0.66), "{name}");
+        }
+    }
+
+    #[test]
+    fn only_the_sixteen_ansi_colors_have_slots() {
+        for named in [
+            NamedColor::Foreground,
+            NamedColor::Background,
+            NamedColor::Cursor,
+            NamedColor::DimBlack,
+            NamedColor::DimWhite,
+            NamedColor::BrightForeground,
+            NamedColor::DimForeground,
+        ] {
+            assert_eq!(palette_index(named), None, "{named:?}");
+        }
+    }
+
+    #[test]
+    fn index_to_rgb_matches_xterm_palette() {
+        // Reference values from xterm's 256colres.h.
+        const CUBE: [u8; 6] = [0x00, 0x5f, 0x87, 0xaf, 0xd7, 0xff];
+        for idx in 16..=255u8 {
+            let expected = if idx < 232 {
+                let i = (idx - 16) as usize;
+                [CUBE[i / 36], CUBE[(i / 6) % 6], CUBE[i % 6]]
+            } else {
+                [0x08 + (idx - 232) * 0x0a; 3]
+            };
+            assert_eq!(index_to_rgb8(idx), expected, "index {idx}");
+        }
+        assert_eq!(index_to_rgb8(196), [0xff, 0, 0]);
+        assert_eq!(index_to_rgb8(231), [0xff; 3]);
+        assert_eq!(index_to_rgb8(255), [0xee; 3]);
+    }
+
+    #[test]
+    fn truecolor_passes_through_in_every_theme() {
+        let specs = [
+            (Rgb { r: 0, g: 0, b: 0 }, [0.0, 0.0, 0.0, 1.0]),
+            (Rgb { r: 255, g: 255, b: 255 }, [1.0, 1.0, 1.0, 1.0]),
+            (Rgb { r: 255, g: 0, b: 51 }, [1.0, 0.0, 0.2, 1.0]),
+            (Rgb { r: 30, g: 102, b: 242 }, [30.0 / 255.0, 0.4, 242.0 / 255.0, 1.0]),
+        ];
+        for &(_, theme) in Theme::BUILTIN {
+            let theme = theme();
+            for (spec, expected) in specs {
+                assert_eq!(theme.resolve_color(&Color::Spec(spec)), expected, "{spec:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn hex_colors_parse() {
+        assert_eq!(parse_hex("#ff8000"), Some([1.0, 128.0 / 255.0, 0.0]));
+        assert_eq!(parse_hex("FF8000"), parse_hex("#ff8000"));
+        assert_eq!(parse_hex("#ff80"), None);
+        assert_eq!(parse_hex("#gg8000"), None);
+        assert_eq!(parse_hex("#ff80\u{e9}"), None);
+    }
+}
@@ -0,0 +1,128 @@
+//! Cursor placement, kept free of GL so it can be tested against a real
+//! `Term`.
+
+use alacritty_terminal::event::EventListener;
+use alacritty_terminal::grid::Dimensions;
+use alacritty_terminal::term::{Term, TermMode};
+use alacritty_terminal::vte::ansi::CursorShape;
+
+/// Viewport cell the cursor is drawn in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CursorCell {
+    pub col: usize,
+    pub line: usize,
+}
+
+/// What decides a pane's cursor besides its terminal's own mode and style,
+/// which are read in the same lock as the grid.
+#[derive(Clone, Copy, Debug)]
+pub struct CursorView {
+    /// The pane is the focused one; the others draw no cursor.
+    pub active: bool,
+    /// The blink is in its visible half.
+    pub blink_on: bool,
+    pub window_focused: bool,
+}
+
+impl CursorView {
+    /// Whether `term`'s cursor blinks here. Steady styles (DECSCUSR 2, 4,
+    /// 6) don't, and in the background it holds still.
+    pub fn blinks<T: EventListener>(&self, term: &Term<T>) -> bool {
+        term.cursor_style().blinking && self.window_focused
+    }
+
+    /// The cursor to draw over `term`, or None while the app hides it
+    /// (DECTCEM) or the blink does. In the background it's an outline.
+    pub fn shape<T: EventListener>(&self, term: &Term<T>) -> Option<CursorShape> {
+        let shown = term.mode().contains(TermMode::SHOW_CURSOR);
+        let shape = if self.window_focused { term.cursor_style().shape } else { CursorShape::HollowBlock };
+        (self.active && (self.blink_on || !self.blinks(term)) && shown).then_some(shape)
+    }
+}
+
+/// The cell to draw the cursor in, or None when it's scrolled out of view.
+///
+/// After writing the last column the cursor is "pending wrap": the grid
+/// keeps it on the last column and the next character lands at the start
+/// of the next line. It is drawn in that last cell, as xterm does. The
+/// result is clamped to the grid so a cursor left past the edge by a
+/// resize race never draws outside the pane.
+pub fn cursor_cell<T: EventListener>(term: &Term<T>) -> Option<CursorCell> {
+    let point = term.grid().cursor.point;
+    let line = point.line.0 + term.grid().display_offset() as i32;
+    if line < 0 || line >= term.screen_lines() as i32 {
+        return None;
+    }
+    Some(clamp_to_grid(point.column.0, line as usize, term.columns(), term.screen_lines()))
+}
+
+fn clamp_to_grid(col: usize, line: usize, cols: usize, rows: usize) -> CursorCell {
+    CursorCell { col: col.min(cols.saturating_sub(1)), line: line.min(rows.saturating_sub(1)) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alacritty_terminal::event::VoidListener;
+    use alacritty_terminal::grid::Scroll;
+    use alacritty_terminal::term::Config;
+    use alacritty_terminal::vte::ansi::{Processor, StdSyncHandler};
+
+    use crate::terminal::TerminalSize;
+
+    fn term_with(cols: usize, rows: usize, output: &str) -> Term<VoidListener> {
+        let mut term = Term::new(Config::default(), &TerminalSize::new(cols, rows), VoidListener);
+        let mut parser: Processor<StdSyncHandler> = Processor::new();
+        parser.advance(&mut term, output.as_bytes());
+        term
+    }
+
+    #[test]
+    fn cursor_follows_output() {
+        let term = term_with(10, 3, "abc");
+        assert_eq!(cursor_cell(&term), Some(CursorCell { col: 3, line: 0 }));
+    }
+
+    #[test]
+    fn pending_wrap_stays_in_last_cell() {
+        let term = term_with(10, 3, "0123456789");
+        assert!(term.grid().cursor.input_needs_wrap);
+        assert_eq!(cursor_cell(&term), Some(CursorCell { col: 9, line: 0 }));
+
+        // The next character wraps to the following line.
+        let term = term_with(10, 3, "0123456789x");
+        assert!(!term.grid().cursor.input_needs_wrap);
+        assert_eq!(cursor_cell(&term), Some(CursorCell { col: 1, line: 1 }));
+    }
+
+    #[test]
+    fn pending_wrap_on_last_line() {
+        let term = term_with(4, 2, "\r\nabcd");
+        assert_eq!(cursor_cell(&term), Some(CursorCell { col: 3, line: 1 }));
+    }
+
+    #[test]
+    fn hidden_while_scrolled_into_history() {
+        let mut term = term_with(10, 2, "a\r\nb\r\nc\r\nd");
+        term.scroll_display(Scroll::Delta(2));
+        assert_eq!(cursor_cell(&term), None);
+    }
+
+    #[test]
+    fn the_view_and_the_terminal_decide_the_shape() {
+        let view = CursorView { active: true, blink_on: false, window_focused: true };
+        // DECSCUSR 2: a steady block shows through the blink's off half.
+        assert_eq!(view.shape(&term_with(10, 2, "\x1b[2 q")), Some(CursorShape::Block));
+        assert_eq!(view.shape(&term_with(10, 2, "\x1b[1 q")), None);
+        assert_eq!(view.shape(&term_with(10, 2, "\x1b[2 q\x1b[?25l")), None);
+        let background = CursorView { window_focused: false, ..view };
+        assert_eq!(background.shape(&term_with(10, 2, "\x1b[5 q")), Some(CursorShape::HollowBlock));
+        assert_eq!(CursorView { active: false, ..background }.shape(&term_with(10, 2, "")), None);
+    }
+
+    #[test]
+    fn clamps_past_grid_edge() {
+        assert_eq!(clamp_to_grid(10, 2, 10, 3), CursorCell { col: 9, line: 2 });
+        assert_eq!(clamp_to_grid(12, 5, 10, 3), CursorCell { col: 9, line: 2 });
+    }
+}
@@ -5,9 +5,7 @@ use crossfont::{
 };
 
 use super::atlas::{Atlas, Glyph};
-
-const INITIAL_ATLAS_SIZE: i32 = 2048;
-const MAX_ATLAS_SIZE: i32 = 8192;
+use crate::config::UnderlineConfig;
 
 // Platform-specific fallback font. Each name must be a font that ships with
 // the OS by default so `load_font` cannot panic on a clean install.
@@ -23,6 +21,63 @@ const FALLBACK_FONT: &str = "Consolas";
 #[cfg(all(unix, not(target_os = "macos")))]
 const FALLBACK_FONT: &str = "DejaVu Sans Mono";
 
+/// Underline placement in physical pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UnderlineMetrics {
+    /// Distance from the baseline down to the center of the line.
+    pub position: f32,
+    pub thickness: f32,
+}
+
+impl UnderlineMetrics {
+    /// Combine the font's metrics (crossfont reports the position relative to
+    /// the baseline, negative below it) with config overrides given in points.
+    /// Fonts that report no usable metrics get a size-proportional default.
+    pub fn resolve(
+        font_px: f32,
+        font_position: f32,
+        font_thickness: f32,
+        overrides: &UnderlineConfig,
+        scale: f32,
+    ) -> Self {
+        let thickness = match overrides.thickness {
+            Some(t) => t * scale,
+            None if font_thickness > 0.0 => font_thickness,
+            None => font_px / 14.0,
+        };
+        let position = match overrides.position {
+            Some(p) => p * scale,
+            None if font_position < 0.0 => -font_position,
+            None => font_px / 10.0,
+        };
+        Self { position, thickness: thickness.round().max(1.0) }
+    }
+}
+
+/// Load the regular face of `font_family`, or of the platform fallback if
+/// it isn't installed.
+fn load_regular(rasterizer: &mut Rasterizer, font_family: &str, size: Size) -> FontKey {
+    let regular = |family| FontDesc::new(family, Style::Description { slant: Slant::Normal, weight: Weight::Normal });
+    rasterizer.load_font(&regular(font_family), size).unwrap_or_else(|_| {
+        log::warn!("Font '{}' not found, falling back to {}", font_family, FALLBACK_FONT);
+        rasterizer.load_font(&regular(FALLBACK_FONT), size).expect("load fallback font")
+    })
+}
+
+/// Cell size in whole pixels, so the grid lands on pixel boundaries.
+fn cell_size(metrics: &crossfont::Metrics) -> (f32, f32) {
+    ((metrics.average_advance as f32).ceil(), (metrics.line_height as f32).ceil())
+}
+
+/// The cell size a `GlyphCache` for this font would have, without creating
+/// one (and its GL atlas). For sizing the window before it exists.
+pub fn measure_cell(font_family: &str, font_size: f32) -> (f32, f32) {
+    let mut rasterizer = Rasterizer::new().expect("create rasterizer");
+    let size = Size::new(font_size);
+    let font_key = load_regular(&mut rasterizer, font_family, size);
+    cell_size(&rasterizer.metrics(font_key, size).expect("font metrics"))
+}
+
 pub struct GlyphCache {
     rasterizer: Rasterizer,
     font_key: FontKey,
@@ -32,41 +87,20 @@ pub struct GlyphCache {
     size: Size,
     cache: HashMap<GlyphKey, Glyph>,
     atlas: Atlas,
-    needs_regrow: bool,
     pub cell_width: f32,
     pub cell_height: f32,
     pub descent: f32,
+    pub underline: UnderlineMetrics,
 }
 
 impl GlyphCache {
-    pub fn new(font_family: &str, font_size: f32) -> Self {
+    /// `font_size` is in physical pixels; `scale` converts the point-based
+    /// underline overrides to match.
+    pub fn new(font_family: &str, font_size: f32, underline: &UnderlineConfig, scale: f32) -> Self {
         let mut rasterizer = Rasterizer::new().expect("create rasterizer");
         let size = Size::new(font_size);
 
-        let font_desc = FontDesc::new(
-            font_family,
-            Style::Description {
-                slant: Slant::Normal,
-                weight: Weight::Normal,
-            },
-        );
-
-        let font_key = rasterizer
-            .load_font(&font_desc, size)
-            .unwrap_or_else(|_| {
-                log::warn!(
-                    "Font '{}' not found, falling back to {}",
-                    font_family, FALLBACK_FONT
-                );
-                let fallback = FontDesc::new(
-                    FALLBACK_FONT,
-                    Style::Description {
-                        slant: Slant::Normal,
-                        weight: Weight::Normal,
-                    },
-                );
-                rasterizer.load_font(&fallback, size).expect("load fallback font")
-            });
+        let font_key = load_regular(&mut rasterizer, font_family, size);
 
         let bold_key = rasterizer
             .load_font(
@@ -90,16 +124,23 @@ impl GlyphCache {
             .unwrap_or(font_key);
 
         let metrics = rasterizer.metrics(font_key, size).expect("font metrics");
-        let cell_width = metrics.average_advance;
-        let cell_height = metrics.line_height;
+        let (cell_width, cell_height) = cell_size(&metrics);
         let descent = metrics.descent;
+        let underline = UnderlineMetrics::resolve(
+            font_size,
+            metrics.underline_position,
+            metrics.underline_thickness,
+            underline,
+            scale,
+        );
 
         log::info!(
-            "Font loaded: {}pt, cell={}x{}, descent={}",
+            "Font loaded: {}pt, cell={}x{}, descent={}, underline={:?}",
             font_size,
             cell_width,
             cell_height,
-            descent
+            descent,
+            underline
         );
 
         GlyphCache {
@@ -110,50 +151,25 @@ impl GlyphCache {
             bold_italic_key,
             size,
             cache: HashMap::new(),
-            atlas: Atlas::new(INITIAL_ATLAS_SIZE),
-            needs_regrow: false,
-            cell_width: (cell_width as f32).ceil(),
-            cell_height: (cell_height as f32).ceil(),
+            atlas: Atlas::new(),
+            cell_width,
+            cell_height,
             descent,
+            underline,
         }
     }
 
-    pub fn atlas_tex_id(&self) -> u32 {
-        self.atlas.tex_id()
+    /// The atlas texture of each page a `Glyph::page` can name.
+    pub fn atlas_tex_ids(&self) -> &[u32] {
+        self.atlas.tex_ids()
     }
 
-    /// Recreate the atlas texture and clear the glyph cache.
+    /// Recreate the atlas textures and clear the glyph cache.
     /// Call this after GPU context loss (e.g. macOS sleep/wake) to force
     /// all glyphs to be re-rasterized and re-uploaded.
     pub fn invalidate(&mut self) {
         log::info!("Glyph atlas invalidated — rebuilding texture");
-        self.atlas.regrow(self.atlas.width());
-        self.cache.clear();
-    }
-
-    /// Regrow the atlas if it filled up during the previous frame.
-    /// Must be called before any draw calls to avoid mid-batch texture swaps.
-    pub fn try_regrow(&mut self) {
-        if !self.needs_regrow {
-            return;
-        }
-        self.needs_regrow = false;
-
-        let cur = self.atlas.width();
-        if cur >= MAX_ATLAS_SIZE {
-            log::error!(
-                "Glyph atlas at max {}x{}, cannot grow further",
-                cur, cur
-            );
-            return;
-        }
-
-        let next = (cur * 2).min(MAX_ATLAS_SIZE);
-        log::warn!(
-            "Glyph atlas full at {}x{}, regrowing to {}x{}",
-            cur, cur, next, next
-        );
-        self.atlas.regrow(next);
+        self.atlas.reset();
         self.cache.clear();
     }
 
@@ -178,50 +194,83 @@ impl GlyphCache {
             Ok(r) => r,
             Err(e) => {
                 log::debug!("Failed to rasterize '{}': {}", c, e);
-                let empty = Glyph {
-                    width: 0.0, height: 0.0, left: 0.0, top: 0.0,
-                    uv_x: 0.0, uv_y: 0.0, uv_w: 0.0, uv_h: 0.0,
-                };
+                let empty = Glyph::empty(0.0, 0.0);
                 self.cache.insert(key, empty);
                 return empty;
             }
         };
 
-        let buffer: Vec<u8> = match &rasterized.buffer {
-            BitmapBuffer::Rgb(data) => data.clone(),
-            BitmapBuffer::Rgba(data) => data
-                .chunks(4)
-                .flat_map(|rgba| &rgba[..3])
-                .copied()
-                .collect(),
-        };
-
-        let glyph = match self.atlas.insert(
-            rasterized.width as i32,
-            rasterized.height as i32,
-            &buffer,
-            rasterized.left as f32,
-            rasterized.top as f32,
-        ) {
-            Some(g) => g,
-            None => {
-                // Don't regrow mid-frame — batched glyphs already reference the
-                // current atlas texture.  Flag for regrow before the next frame.
-                self.needs_regrow = true;
-                return Glyph {
-                    width: 0.0,
-                    height: 0.0,
-                    left: 0.0,
-                    top: 0.0,
-                    uv_x: 0.0,
-                    uv_y: 0.0,
-                    uv_w: 0.0,
-                    uv_h: 0.0,
-                };
+        // The atlas is RGBA. Coverage masks get an opaque alpha the text
+        // shader ignores; color emoji keep theirs.
+        let (buffer, colored): (Vec<u8>, bool) = match &rasterized.buffer {
+            BitmapBuffer::Rgb(data) => {
+                (data.chunks(3).flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 0xff]).collect(), false)
             }
+            BitmapBuffer::Rgba(data) => (data.clone(), true),
         };
 
+        let glyph = self
+            .atlas
+            .insert(
+                rasterized.width as i32,
+                rasterized.height as i32,
+                &buffer,
+                rasterized.left as f32,
+                rasterized.top as f32,
+                colored,
+            )
+            .unwrap_or_else(|| {
+                log::warn!("Glyph '{}' is {}x{}, larger than an atlas page", c, rasterized.width, rasterized.height);
+                Glyph::empty(0.0, 0.0)
+            });
+
         self.cache.insert(key, glyph);
         glyph
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Proportions typical of a monospace font's reported metrics.
+    fn from_font(px: f32, overrides: &UnderlineConfig) -> UnderlineMetrics {
+        UnderlineMetrics::resolve(px, -px * 0.1, px / 16.0, overrides, 2.0)
+    }
+
+    #[test]
+    fn metrics_scale_with_font_size() {
+        let none = UnderlineConfig::default();
+        let small = from_font(20.0, &none);
+        let large = from_font(48.0, &none);
+        assert_eq!(small.thickness, 1.0);
+        assert_eq!(large.thickness, 3.0);
+        assert!(large.position > small.position);
+        assert!((large.position - 4.8).abs() < 1e-4);
+    }
+
+    #[test]
+    fn thickness_is_at_least_one_pixel() {
+        let m = from_font(8.0, &UnderlineConfig::default());
+        assert_eq!(m.thickness, 1.0);
+    }
+
+    #[test]
+    fn bad_font_metrics_fall_back_to_size() {
+        let m = UnderlineMetrics::resolve(28.0, 0.0, 0.0, &UnderlineConfig::default(), 1.0);
+        assert_eq!(m.thickness, 2.0);
+        assert!((m.position - 2.8).abs() < 1e-4);
+    }
+
+    #[test]
+    fn overrides_win_and_are_scaled() {
+        let overrides = UnderlineConfig { position: Some(3.0), thickness: Some(1.5) };
+        let m = from_font(48.0, &overrides);
+        assert_eq!(m, UnderlineMetrics { position: 6.0, thickness: 3.0 });
+
+        let only_thickness = UnderlineConfig { thickness: Some(2.0), ..Default::default() };
+        let m = from_font(48.0, &only_thickness);
+        assert_eq!(m.thickness, 4.0);
+        assert!((m.position - 4.8).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,1019 @@
+//! The cell walk behind `Renderer::draw_grid`, kept free of GL: it turns a
+//! `Term`'s visible cells into rects (merged into runs) and the glyphs to
+//! rasterize, so the output can be checked headlessly.
+
+use alacritty_terminal::event::EventListener;
+use alacritty_terminal::grid::Dimensions;
+use alacritty_terminal::index::{Column, Line, Point};
+use alacritty_terminal::selection::SelectionRange;
+use alacritty_terminal::term::cell::{Cell, Flags};
+use alacritty_terminal::term::color::{Colors, COUNT};
+use alacritty_terminal::term::{Term, TermDamage};
+use alacritty_terminal::vte::ansi::{Color, CursorShape, NamedColor};
+
+use crate::config::SelectionStyle;
+
+use super::atlas::Glyph;
+use super::color;
+use super::cursor::CursorCell;
+use super::glyph_cache::UnderlineMetrics;
+use super::rects::RectInstance;
+use super::runs::RectRuns;
+use super::text::GlyphInstance;
+use super::Theme;
+
+/// Cell geometry from the glyph cache, in physical pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CellMetrics {
+    pub cell_width: f32,
+    pub cell_height: f32,
+    pub descent: f32,
+    pub underline: UnderlineMetrics,
+    /// Width of a beam cursor and height of an underline cursor.
+    pub cursor_thickness: f32,
+}
+
+impl CellMetrics {
+    /// Top and height of the underline in a cell whose top edge is at
+    /// `cell_y`, kept inside the cell when the font puts it too low.
+    fn underline_rect(&self, cell_y: f32) -> (f32, f32) {
+        let baseline = cell_y + self.cell_height + self.descent;
+        let UnderlineMetrics { position, thickness } = self.underline;
+        let top = (baseline + position - thickness / 2.0).round();
+        (top.min(cell_y + self.cell_height - thickness), thickness)
+    }
+
+    /// Top and height of a strikethrough: underline-thick, across the
+    /// middle of the cell.
+    fn strikeout_rect(&self, cell_y: f32) -> (f32, f32) {
+        let thickness = self.underline.thickness;
+        ((cell_y + (self.cell_height - thickness) / 2.0).round(), thickness)
+    }
+}
+
+/// Push the underline and strikethrough rects for a cell with `flags`.
+/// Undercurl is drawn as a square wave, a step up and down per cell.
+fn push_decorations(
+    metrics: &CellMetrics,
+    flags: Flags,
+    (x, y, w): (f32, f32, f32),
+    color: [f32; 4],
+    out: &mut Vec<RectInstance>,
+) {
+    let (uy, t) = metrics.underline_rect(y);
+    if flags.contains(Flags::UNDERLINE) {
+        out.push(RectInstance::new(x, uy, w, t, color));
+    }
+    if flags.contains(Flags::DOUBLE_UNDERLINE) {
+        out.push(RectInstance::new(x, uy, w, t, color));
+        out.push(RectInstance::new(x, uy - 2.0 * t, w, t, color));
+    }
+    if flags.contains(Flags::UNDERCURL) {
+        let half = (w / 2.0).round();
+        out.push(RectInstance::new(x, uy, half, t, color));
+        out.push(RectInstance::new(x + half, uy - t, w - half, t, color));
+    }
+    if flags.contains(Flags::DASHED_UNDERLINE) {
+        // One dash centered in each cell, so the gaps fall at cell edges.
+        let dash = (w / 2.0).round();
+        out.push(RectInstance::new(x + ((w - dash) / 2.0).round(), uy, dash, t, color));
+    }
+    if flags.contains(Flags::DOTTED_UNDERLINE) {
+        let mut dot_x = x;
+        while dot_x + t <= x + w {
+            out.push(RectInstance::new(dot_x, uy, t, t, color));
+            dot_x += 2.0 * t;
+        }
+    }
+    if flags.contains(Flags::STRIKEOUT) {
+        let (sy, sh) = metrics.strikeout_rect(y);
+        out.push(RectInstance::new(x, sy, w, sh, color));
+    }
+}
+
+/// A glyph to draw: the top-left and width of its cell, and its style.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CellGlyph {
+    pub x: f32,
+    pub y: f32,
+    /// Two cells wide for a wide char.
+    pub width: f32,
+    pub c: char,
+    pub bold: bool,
+    pub italic: bool,
+    pub fg: [f32; 4],
+}
+
+/// Where and how to lay out a grid.
+#[derive(Clone, Copy, Debug)]
+pub struct GridOptions {
+    pub offset_x: f32,
+    pub offset_y: f32,
+    /// The cursor's cell, if it's drawn this frame.
+    pub cursor: Option<CursorCell>,
+    pub cursor_shape: CursorShape,
+    pub selection: SelectionStyle,
+    /// First and last cell of a link to underline, in grid coordinates.
+    pub link: Option<(Point, Point)>,
+}
+
+/// One grid's draw output. Rects are in paint order: backgrounds, then
+/// underlines, strikethroughs and a beam or underline cursor.
+#[derive(Default)]
+pub struct GridFrame {
+    pub rects: Vec<RectInstance>,
+    pub glyphs: Vec<CellGlyph>,
+    decorations: Vec<RectInstance>,
+}
+
+impl GridFrame {
+    pub fn clear(&mut self) {
+        self.rects.clear();
+        self.glyphs.clear();
+        self.decorations.clear();
+    }
+}
+
+/// A cell's final colors, and whether its background needs a rect. Only
+/// a cell left showing its own default background (`NamedColor::Background`)
+/// skips the rect; colors are never compared, so an explicit 256-color or
+/// truecolor background that happens to match the theme, or an inverse
+/// cell whose foreground does, is still filled. Once an app has set the
+/// pane's background with OSC 11, every cell is filled with it.
+///
+/// Precedence is cursor > selection > inverse, as in xterm and alacritty:
+/// each replaces the colors instead of layering over them, so a selected
+/// inverse-video status line stays readable. `block_cursor` is the
+/// cursor's color when it's a block over this cell; the text then takes
+/// the cell's background, or the theme's if that's too close to the
+/// cursor to read.
+fn cell_colors(
+    theme: &Theme,
+    colors: &PaneColors,
+    cell: &CellSnapshot,
+    block_cursor: Option<[f32; 3]>,
+    style: SelectionStyle,
+) -> ([f32; 4], [f32; 4], bool) {
+    let opaque = |c: [f32; 3]| [c[0], c[1], c[2], 1.0];
+    let rgb = |c: [f32; 4]| [c[0], c[1], c[2]];
+    if let Some(cursor) = block_cursor {
+        let inverse = cell.flags.contains(Flags::INVERSE);
+        let behind = colors.resolve(theme, if inverse { &cell.fg } else { &cell.bg });
+        let text = color::legible_on(cursor, &[rgb(behind), colors.get(theme, NamedColor::Background as usize)]);
+        return (opaque(text), opaque(cursor), true);
+    }
+    let selected = cell.selected;
+    if selected && style == SelectionStyle::Colors {
+        let sel = theme.selection;
+        let fg = theme.selection_fg.unwrap_or_else(|| colors.get(theme, NamedColor::Foreground as usize));
+        return (opaque(fg), [sel[0], sel[1], sel[2], 1.0], true);
+    }
+    let (fg, bg) = (colors.resolve(theme, &cell.fg), colors.resolve(theme, &cell.bg));
+    // Invert-style selection swaps back whatever inverse video swapped.
+    let swapped = cell.flags.contains(Flags::INVERSE) != selected;
+    if swapped {
+        (bg, fg, true)
+    } else {
+        (fg, bg, cell.bg != Color::Named(NamedColor::Background) || colors.sets(NamedColor::Background))
+    }
+}
+
+/// The quad for `glyph` in the cell at `(x, y)`, `width` wide: placed by
+/// its bearings and snapped to whole pixels so it isn't blurred. Italic
+/// and wide-bearing glyphs can overhang into the next cell; with `clip`
+/// (`font.strict_clipping`) the overhang is cut off, texture coordinates
+/// and all. None if there's nothing left to draw.
+///
+/// A wide char is centered across its two cells. Color emoji, which
+/// rasterize at whatever size the font draws them, are scaled down to fit
+/// their cells if they're bigger.
+pub fn glyph_quad(
+    glyph: &Glyph,
+    (x, y, width): (f32, f32, f32),
+    metrics: &CellMetrics,
+    fg: [f32; 4],
+    clip: bool,
+) -> Option<GlyphInstance> {
+    if glyph.width <= 0.0 {
+        return None;
+    }
+    let ch = metrics.cell_height;
+    let (mut w, mut h) = (glyph.width, glyph.height);
+    let (gx, gy) = if glyph.colored && (w > width || h > ch) {
+        let scale = (width / w).min(ch / h);
+        (w, h) = ((w * scale).round(), (h * scale).round());
+        ((x + (width - w) / 2.0).round(), (y + (ch - h) / 2.0).round())
+    } else {
+        let wide = width > metrics.cell_width;
+        let left = if wide { (width - w) / 2.0 } else { glyph.left };
+        ((x + left).round(), (y + ch + metrics.descent - glyph.top).round())
+    };
+    let mut quad = GlyphInstance {
+        x: gx,
+        y: gy,
+        w,
+        h,
+        uv_x: glyph.uv_x,
+        uv_y: glyph.uv_y,
+        uv_w: glyph.uv_w,
+        uv_h: glyph.uv_h,
+        r: fg[0],
+        g: fg[1],
+        b: fg[2],
+        a: fg[3],
+    };
+    if clip {
+        // Trim each axis to the cell, and the texture window with it.
+        let trim = |pos: &mut f32, len: &mut f32, uv: &mut f32, uv_len: &mut f32, lo: f32, hi: f32| {
+            let (start, end) = (pos.max(lo), (*pos + *len).min(hi));
+            *uv += (start - *pos) / *len * *uv_len;
+            *uv_len *= (end - start).max(0.0) / *len;
+            (*pos, *len) = (start, (end - start).max(0.0));
+        };
+        trim(&mut quad.x, &mut quad.w, &mut quad.uv_x, &mut quad.uv_w, x, x + width);
+        trim(&mut quad.y, &mut quad.h, &mut quad.uv_y, &mut quad.uv_h, y, y + ch);
+        if quad.w <= 0.0 || quad.h <= 0.0 {
+            return None;
+        }
+    }
+    Some(quad)
+}
+
+/// Everything that changes how every line of a grid is laid out. Output
+/// only damages the lines it touches; a change here damages them all.
+#[derive(Clone, PartialEq)]
+struct LayoutKey {
+    offset: (f32, f32),
+    size: (usize, usize),
+    display_offset: usize,
+    selection: SelectionStyle,
+    selection_range: Option<SelectionRange>,
+    link: Option<(Point, Point)>,
+    metrics: CellMetrics,
+    theme: Theme,
+    colors: PaneColors,
+}
+
+/// The colors a pane's apps have set over the theme, by alacritty's color
+/// index: OSC 4 sets the 256-color palette, OSC 10, 11 and 12 the
+/// foreground, background and cursor, and OSC 104 and 110-112 reset them.
+#[derive(Clone, PartialEq)]
+pub struct PaneColors([Option<[f32; 3]>; COUNT]);
+
+impl Default for PaneColors {
+    fn default() -> Self {
+        Self([None; COUNT])
+    }
+}
+
+impl PaneColors {
+    pub fn of(colors: &Colors) -> Self {
+        Self(std::array::from_fn(|i| colors[i].map(|c| color::rgb_to_f32([c.r, c.g, c.b]))))
+    }
+
+    fn sets(&self, named: NamedColor) -> bool {
+        self.0[named as usize].is_some()
+    }
+
+    /// `color` as this pane shows it: the app's choice, else the theme's.
+    pub fn resolve(&self, theme: &Theme, color: &Color) -> [f32; 4] {
+        let set = match *color {
+            Color::Named(named) => self.0[named as usize],
+            Color::Indexed(idx) => self.0[idx as usize],
+            Color::Spec(_) => None,
+        };
+        set.map_or_else(|| theme.resolve_color(color), |[r, g, b]| [r, g, b, 1.0])
+    }
+
+    /// The color at `index`, as a query for it (OSC 4, 10, 11 or 12)
+    /// should be answered.
+    pub fn get(&self, theme: &Theme, index: usize) -> [f32; 3] {
+        let color = match index {
+            0..=255 => Color::Indexed(index as u8),
+            256 => Color::Named(NamedColor::Foreground),
+            257 => Color::Named(NamedColor::Background),
+            _ => Color::Named(NamedColor::Cursor),
+        };
+        let [r, g, b, _] = self.resolve(theme, &color);
+        [r, g, b]
+    }
+}
+
+/// A cell as it was when its grid was snapshotted: what laying it out
+/// needs, taken while the terminal is locked.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CellSnapshot {
+    pub c: char,
+    pub fg: Color,
+    pub bg: Color,
+    pub flags: Flags,
+    pub selected: bool,
+    /// Part of the link under the mouse.
+    pub linked: bool,
+}
+
+impl CellSnapshot {
+    pub fn new(cell: &Cell, selected: bool, linked: bool) -> Self {
+        Self { c: cell.c, fg: cell.fg, bg: cell.bg, flags: cell.flags, selected, linked }
+    }
+}
+
+/// One pane's grid as last laid out, line by line, so that a frame only
+/// walks the lines that changed since the one before.
+///
+/// Drawing is in two steps: `snapshot` copies out the changed lines'
+/// cells while the terminal is locked, and `layout` lays them out after
+/// the lock is released, so the PTY thread isn't kept waiting on color
+/// resolution and glyph lookups.
+#[derive(Default)]
+pub struct GridCache {
+    key: Option<LayoutKey>,
+    /// The cursor's cell and shape.
+    cursor: Option<(CursorCell, CursorShape)>,
+    /// Per viewport line: its background runs, glyphs and decorations.
+    lines: Vec<GridFrame>,
+    /// Lines the last snapshot found changed.
+    damaged: Vec<bool>,
+    /// The changed lines' cells, row after row, reused between frames.
+    cells: Vec<CellSnapshot>,
+}
+
+impl GridCache {
+    /// Take `term`'s damage since the last snapshot and copy out the cells
+    /// of the lines it touched, or of every line if anything that affects
+    /// them all (size, scroll, selection, theme or the pane's own colors,
+    /// offset...) has changed.
+    /// The lines the cursor left or entered are taken too.
+    pub fn snapshot<T: EventListener>(
+        &mut self,
+        term: &mut Term<T>,
+        theme: &Theme,
+        metrics: &CellMetrics,
+        options: &GridOptions,
+    ) {
+        let rows = term.screen_lines();
+        self.damaged.clear();
+        self.damaged.resize(rows, false);
+        match term.damage() {
+            TermDamage::Full => self.damaged.fill(true),
+            TermDamage::Partial(lines) => {
+                for bounds in lines.filter(|b| b.line < rows) {
+                    self.damaged[bounds.line] = true;
+                }
+            }
+        }
+        term.reset_damage();
+        self.snapshot_damaged(term, theme, metrics, options);
+    }
+
+    /// `snapshot`, for the lines already marked in `damaged`.
+    fn snapshot_damaged<T: EventListener>(
+        &mut self,
+        term: &Term<T>,
+        theme: &Theme,
+        metrics: &CellMetrics,
+        options: &GridOptions,
+    ) {
+        let (cols, rows) = (term.columns(), term.screen_lines());
+        let display_offset = term.grid().display_offset();
+        let selection_range = term.selection.as_ref().and_then(|s| s.to_range(term));
+        let key = LayoutKey {
+            offset: (options.offset_x, options.offset_y),
+            size: (cols, rows),
+            display_offset,
+            selection: options.selection,
+            selection_range,
+            link: options.link,
+            metrics: *metrics,
+            theme: theme.clone(),
+            colors: PaneColors::of(term.colors()),
+        };
+        if self.key.as_ref() != Some(&key) {
+            self.damaged.fill(true);
+            self.lines.resize_with(rows, GridFrame::default);
+            self.key = Some(key);
+        }
+        // A blink or a shape change touches only the cursor's line.
+        let cursor = options.cursor.map(|c| (c, options.cursor_shape));
+        if self.cursor != cursor {
+            for (c, _) in [self.cursor, cursor].into_iter().flatten().filter(|(c, _)| c.line < rows) {
+                self.damaged[c.line] = true;
+            }
+            self.cursor = cursor;
+        }
+
+        self.cells.clear();
+        for line in (0..rows).filter(|&line| self.damaged[line]) {
+            let grid_line = Line(line as i32 - display_offset as i32);
+            let row = &term.grid()[grid_line];
+            self.cells.extend((0..cols).map(|col| {
+                let point = Point::new(grid_line, Column(col));
+                let selected = selection_range.is_some_and(|sel| sel.contains(point));
+                let linked = options.link.is_some_and(|(start, end)| (start..=end).contains(&point));
+                CellSnapshot::new(&row[Column(col)], selected, linked)
+            }));
+        }
+    }
+
+    /// Lay out the lines the last snapshot took, with the grid's top-left
+    /// at the offset, and put the whole grid into `frame`. Returns how many
+    /// lines were laid out afresh.
+    ///
+    /// Backgrounds, selection included, are merged into runs that break at
+    /// line ends and color changes. Wide chars cover two cells and their
+    /// spacer is skipped, so runs continue across them.
+    pub fn layout(&mut self, theme: &Theme, metrics: &CellMetrics, options: &GridOptions, frame: &mut GridFrame) -> usize {
+        let Some(key) = &self.key else { return 0 };
+        let damaged = self.damaged.iter().enumerate().filter(|(_, damaged)| **damaged).map(|(line, _)| line);
+        let mut laid_out = 0;
+        for (line, cells) in damaged.zip(self.cells.chunks(key.size.0.max(1))) {
+            let line_frame = &mut self.lines[line];
+            line_frame.clear();
+            layout_line(cells, theme, &key.colors, metrics, options, line, line_frame);
+            laid_out += 1;
+        }
+        for line_frame in &self.lines {
+            frame.rects.extend_from_slice(&line_frame.rects);
+            frame.glyphs.extend_from_slice(&line_frame.glyphs);
+        }
+        for line_frame in &self.lines {
+            frame.rects.extend_from_slice(&line_frame.decorations);
+        }
+        laid_out
+    }
+}
+
+/// Lay out viewport line `line`, whose cells are `cells`, into `out`:
+/// background runs in `rects`, underlines and the like in `decorations`,
+/// and glyphs.
+fn layout_line(
+    cells: &[CellSnapshot],
+    theme: &Theme,
+    colors: &PaneColors,
+    metrics: &CellMetrics,
+    options: &GridOptions,
+    line: usize,
+    out: &mut GridFrame,
+) {
+    let GridOptions { offset_x, offset_y, cursor, cursor_shape, selection, .. } = *options;
+    let cw = metrics.cell_width;
+    let ch = metrics.cell_height;
+    let cell_y = offset_y + line as f32 * ch;
+
+    let cursor_color = colors.get(theme, NamedColor::Cursor as usize);
+    let mut bg_runs = RectRuns::default();
+
+    for (col, cell) in cells.iter().enumerate() {
+        let cell_x = offset_x + col as f32 * cw;
+
+        // Skip spacer cells for wide characters (already drawn by the wide cell).
+        if cell.flags.contains(Flags::WIDE_CHAR_SPACER) {
+            continue;
+        }
+
+        // Wide chars (CJK, emoji) occupy two columns.
+        let is_wide = cell.flags.contains(Flags::WIDE_CHAR);
+        let draw_cw = if is_wide { cw * 2.0 } else { cw };
+
+        let at_cursor = cursor == Some(CursorCell { col, line });
+        let under_cursor = at_cursor && cursor_shape == CursorShape::Block;
+        let block_cursor = under_cursor.then_some(cursor_color);
+        let (fg_color, bg_color, draw_bg) = cell_colors(theme, colors, cell, block_cursor, selection);
+
+        if draw_bg {
+            out.rects.extend(bg_runs.push(cell_x, cell_y, draw_cw, ch, bg_color));
+        }
+
+        let mut flags = cell.flags;
+        if cell.linked && !flags.intersects(Flags::ALL_UNDERLINES) {
+            flags.insert(Flags::UNDERLINE);
+        }
+        if flags.intersects(Flags::ALL_UNDERLINES | Flags::STRIKEOUT) {
+            push_decorations(metrics, flags, (cell_x, cell_y, draw_cw), fg_color, &mut out.decorations);
+        }
+
+        // Beam, underline and hollow cursors sit over the cell without
+        // recoloring it.
+        if at_cursor && !under_cursor {
+            let color = [cursor_color[0], cursor_color[1], cursor_color[2], 1.0];
+            let t = metrics.cursor_thickness;
+            match cursor_shape {
+                CursorShape::Beam => out.decorations.push(RectInstance::new(cell_x, cell_y, t, ch, color)),
+                CursorShape::Underline => {
+                    out.decorations.push(RectInstance::new(cell_x, cell_y + ch - t, draw_cw, t, color))
+                }
+                CursorShape::HollowBlock => out.decorations.extend([
+                    RectInstance::new(cell_x, cell_y, draw_cw, t, color),
+                    RectInstance::new(cell_x, cell_y + ch - t, draw_cw, t, color),
+                    RectInstance::new(cell_x, cell_y + t, t, ch - 2.0 * t, color),
+                    RectInstance::new(cell_x + draw_cw - t, cell_y + t, t, ch - 2.0 * t, color),
+                ]),
+                _ => {}
+            }
+        }
+
+        let c = cell.c;
+        if c == ' ' || c == '\t' {
+            continue;
+        }
+
+        // Bold brightness boost
+        let fg = if cell.flags.contains(Flags::BOLD) {
+            [
+                (fg_color[0] * 1.15).min(1.0),
+                (fg_color[1] * 1.15).min(1.0),
+                (fg_color[2] * 1.15).min(1.0),
+                fg_color[3],
+            ]
+        } else if cell.flags.contains(Flags::DIM) {
+            [fg_color[0] * 0.66, fg_color[1] * 0.66, fg_color[2] * 0.66, fg_color[3]]
+        } else {
+            fg_color
+        };
+
+        out.glyphs.push(CellGlyph {
+            x: cell_x,
+            y: cell_y,
+            width: draw_cw,
+            c,
+            bold: cell.flags.contains(Flags::BOLD),
+            italic: cell.flags.contains(Flags::ITALIC),
+            fg,
+        });
+    }
+
+    out.rects.extend(bg_runs.finish());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alacritty_terminal::event::VoidListener;
+    use alacritty_terminal::index::{Column, Line, Point, Side};
+    use alacritty_terminal::selection::{Selection, SelectionType};
+    use alacritty_terminal::term::Config;
+    use alacritty_terminal::vte::ansi::{Processor, Rgb, StdSyncHandler};
+
+    use crate::terminal::TerminalSize;
+
+    const METRICS: CellMetrics = CellMetrics {
+        cell_width: 10.0,
+        cell_height: 20.0,
+        descent: -4.0,
+        underline: UnderlineMetrics { position: 2.0, thickness: 1.0 },
+        cursor_thickness: 2.0,
+    };
+
+    fn term_with(cols: usize, rows: usize, output: &str) -> Term<VoidListener> {
+        let mut term = Term::new(Config::default(), &TerminalSize::new(cols, rows), VoidListener);
+        let mut parser: Processor<StdSyncHandler> = Processor::new();
+        parser.advance(&mut term, output.as_bytes());
+        term
+    }
+
+    /// Every line of `term`, laid out through a fresh cache.
+    fn layout_grid(
+        term: &Term<VoidListener>,
+        theme: &Theme,
+        metrics: &CellMetrics,
+        options: &GridOptions,
+        frame: &mut GridFrame,
+    ) {
+        let mut cache = GridCache { damaged: vec![true; term.screen_lines()], ..GridCache::default() };
+        cache.snapshot_damaged(term, theme, metrics, options);
+        cache.layout(theme, metrics, options, frame);
+    }
+
+    fn layout_with(term: &Term<VoidListener>, cursor: Option<CursorCell>, selection: SelectionStyle) -> GridFrame {
+        layout_shaped(term, cursor, CursorShape::Block, selection)
+    }
+
+    fn layout_shaped(
+        term: &Term<VoidListener>,
+        cursor: Option<CursorCell>,
+        cursor_shape: CursorShape,
+        selection: SelectionStyle,
+    ) -> GridFrame {
+        let mut frame = GridFrame::default();
+        let options = GridOptions { offset_x: 0.0, offset_y: 0.0, cursor, cursor_shape, selection, link: None };
+        layout_grid(term, &Theme::latte(), &METRICS, &options, &mut frame);
+        frame
+    }
+
+    fn layout(term: &Term<VoidListener>) -> GridFrame {
+        layout_with(term, None, SelectionStyle::Colors)
+    }
+
+    fn select(term: &mut Term<VoidListener>, line: i32, from: usize, to: usize) {
+        let mut sel = Selection::new(SelectionType::Simple, Point::new(Line(line), Column(from)), Side::Left);
+        sel.update(Point::new(Line(line), Column(to)), Side::Right);
+        term.selection = Some(sel);
+    }
+
+    fn color(r: &RectInstance) -> [f32; 4] {
+        [r.r, r.g, r.b, r.a]
+    }
+
+    fn opaque(c: [f32; 3]) -> [f32; 4] {
+        [c[0], c[1], c[2], 1.0]
+    }
+
+    fn spans(frame: &GridFrame) -> Vec<(f32, f32, f32)> {
+        frame.rects.iter().map(|r| (r.x, r.y, r.w)).collect()
+    }
+
+    #[test]
+    fn plain_text_has_no_rects() {
+        let frame = layout(&term_with(20, 3, "hello world"));
+        assert!(frame.rects.is_empty());
+        assert_eq!(frame.glyphs.len(), 10);
+        assert_eq!(frame.glyphs[5].x, 60.0);
+    }
+
+    #[test]
+    fn colored_background_is_one_run_per_color() {
+        // Red "hello world" (spaces included), then blue "ab".
+        let frame = layout(&term_with(20, 3, "\x1b[41mhello world\x1b[44mab\x1b[0m"));
+        assert_eq!(spans(&frame), [(0.0, 0.0, 110.0), (110.0, 0.0, 20.0)]);
+    }
+
+    #[test]
+    fn runs_break_at_line_end() {
+        let frame = layout(&term_with(4, 3, "\x1b[41mabcdefg\x1b[0m"));
+        assert_eq!(spans(&frame), [(0.0, 0.0, 40.0), (0.0, 20.0, 30.0)]);
+    }
+
+    #[test]
+    fn wide_chars_continue_runs() {
+        let frame = layout(&term_with(20, 3, "\x1b[41m\u{4e2d}\u{6587}x\x1b[0m"));
+        assert_eq!(spans(&frame), [(0.0, 0.0, 50.0)]);
+        assert_eq!(frame.glyphs.len(), 3);
+        assert_eq!(frame.glyphs[1].x, 20.0);
+    }
+
+    #[test]
+    fn full_screen_selection_is_one_rect_per_row() {
+        let mut term = term_with(80, 24, "\x1b[41mred\x1b[0m plain");
+        let mut sel = Selection::new(SelectionType::Lines, Point::new(Line(0), Column(0)), Side::Left);
+        sel.update(Point::new(Line(23), Column(79)), Side::Right);
+        term.selection = Some(sel);
+        let frame = layout(&term);
+        // Selection replaces the red background, so each row is one run.
+        assert_eq!(frame.rects.len(), 24);
+        let sel = Theme::latte().selection;
+        let sel = [sel[0], sel[1], sel[2], 1.0];
+        assert!(frame.rects.iter().all(|r| color(r) == sel && r.w == 800.0));
+    }
+
+    #[test]
+    fn selection_boundary_breaks_run() {
+        let mut term = term_with(20, 2, "abcdef");
+        select(&mut term, 0, 2, 3);
+        assert_eq!(spans(&layout(&term)), [(20.0, 0.0, 20.0)]);
+    }
+
+    /// An inverse-video status line, as drawn by vim, tmux or less.
+    const STATUS_LINE: &str = "\x1b[7m main.rs [+]   12,4 \x1b[0m";
+
+    #[test]
+    fn selection_replaces_inverse_colors() {
+        let theme = Theme::latte();
+        let mut term = term_with(20, 2, STATUS_LINE);
+        select(&mut term, 0, 1, 4);
+        let frame = layout(&term);
+        // Inverse run, selected run, inverse run: no stacking.
+        assert_eq!(spans(&frame), [(0.0, 0.0, 10.0), (10.0, 0.0, 40.0), (50.0, 0.0, 150.0)]);
+        assert_eq!(color(&frame.rects[0]), opaque(theme.fg));
+        let sel = theme.selection;
+        assert_eq!(color(&frame.rects[1]), [sel[0], sel[1], sel[2], 1.0]);
+        // Selected glyphs use the selection foreground, not the inverse one.
+        let m = frame.glyphs.iter().find(|g| g.c == 'm').unwrap();
+        assert_eq!(m.fg, opaque(theme.selection_fg.unwrap()));
+        let plain = Theme { selection_fg: None, ..theme.clone() };
+        let options = GridOptions {
+            offset_x: 0.0,
+            offset_y: 0.0,
+            cursor: None,
+            cursor_shape: CursorShape::Block,
+            selection: SelectionStyle::Colors,
+            link: None,
+        };
+        let mut frame = GridFrame::default();
+        layout_grid(&term, &plain, &METRICS, &options, &mut frame);
+        let m = frame.glyphs.iter().find(|g| g.c == 'm').unwrap();
+        assert_eq!(m.fg, opaque(theme.fg));
+        let r = frame.glyphs.iter().find(|g| g.c == 'r').unwrap();
+        assert_eq!(r.fg, opaque(theme.bg));
+    }
+
+    #[test]
+    fn invert_style_swaps_the_cell_colors() {
+        let theme = Theme::latte();
+        let mut term = term_with(20, 2, STATUS_LINE);
+        select(&mut term, 0, 1, 4);
+        let frame = layout_with(&term, None, SelectionStyle::Invert);
+        // Selecting inverse text shows it the normal way round: the
+        // default background, so no rect where it's selected.
+        assert_eq!(spans(&frame), [(0.0, 0.0, 10.0), (50.0, 0.0, 150.0)]);
+        let m = frame.glyphs.iter().find(|g| g.c == 'm').unwrap();
+        assert_eq!(m.fg, opaque(theme.fg));
+    }
+
+    #[test]
+    fn cursor_wins_over_selection_and_inverse() {
+        let theme = Theme::latte();
+        let mut term = term_with(20, 2, STATUS_LINE);
+        select(&mut term, 0, 0, 19);
+        let cursor = Some(CursorCell { col: 2, line: 0 });
+        let frame = layout_with(&term, cursor, SelectionStyle::Colors);
+        let at_cursor = frame.rects.iter().find(|r| r.x == 20.0).unwrap();
+        assert_eq!((color(at_cursor), at_cursor.w), (opaque(theme.cursor), 10.0));
+        let a = frame.glyphs.iter().find(|g| g.x == 20.0).unwrap();
+        assert_eq!((a.c, a.fg), ('a', opaque(theme.bg)));
+        assert_eq!(frame.rects.len(), 3);
+    }
+
+    #[test]
+    fn only_the_cells_own_default_background_is_skipped() {
+        let colors = |cell: &Cell, theme: &Theme| cell_colors(theme, &PaneColors::default(), &CellSnapshot::new(cell, false, false), None, SelectionStyle::Colors);
+        for theme in [Theme::latte(), Theme::mocha()] {
+            assert_eq!(colors(&Cell::default(), &theme), (opaque(theme.fg), opaque(theme.bg), false));
+
+            // Inverse with default colors: a filled foreground-colored block.
+            let mut inverse = Cell { flags: Flags::INVERSE, ..Cell::default() };
+            assert_eq!(colors(&inverse, &theme), (opaque(theme.bg), opaque(theme.fg), true));
+
+            // Inverse whose foreground is the theme's background.
+            inverse.fg = Color::Named(NamedColor::Background);
+            assert!(colors(&inverse, &theme).2);
+
+            // Explicit backgrounds equal to the theme's are still filled.
+            let [r, g, b] = theme.bg.map(|c| (c * 255.0).round() as u8);
+            let truecolor = Cell { bg: Color::Spec(Rgb { r, g, b }), ..Cell::default() };
+            assert!(colors(&truecolor, &theme).2);
+            let indexed = Cell { bg: Color::Indexed(0), ..Cell::default() };
+            assert!(colors(&indexed, &theme).2);
+        }
+    }
+
+    #[test]
+    fn inverse_text_gets_a_background_in_every_theme() {
+        let term = term_with(20, 2, "\x1b[7minverted\x1b[0m");
+        for theme in [Theme::latte(), Theme::mocha()] {
+            let mut frame = GridFrame::default();
+            let options = GridOptions {
+                offset_x: 0.0,
+                offset_y: 0.0,
+                cursor: None,
+                cursor_shape: CursorShape::Block,
+                selection: SelectionStyle::Colors,
+                link: None,
+            };
+            layout_grid(&term, &theme, &METRICS, &options, &mut frame);
+            assert_eq!(spans(&frame), [(0.0, 0.0, 80.0)]);
+            assert_eq!(color(&frame.rects[0]), opaque(theme.fg));
+        }
+    }
+
+    #[test]
+    fn osc_12_colors_the_cursor_and_the_text_under_it_stays_legible() {
+        let theme = Theme::latte();
+        let cursor = Some(CursorCell { col: 0, line: 0 });
+        let a = |frame: &GridFrame| frame.glyphs.iter().find(|g| g.c == 'a').unwrap().fg;
+
+        let plain = layout_shaped(&term_with(20, 2, "ab"), cursor, CursorShape::Block, SelectionStyle::Colors);
+        assert_eq!(color(&plain.rects[0]), opaque(theme.cursor));
+        assert_eq!(a(&plain), opaque(theme.bg));
+
+        // Yellow is too light for Latte's background as text: black instead.
+        let term = term_with(20, 2, "\x1b]12;#ffff00\x07ab");
+        let yellow = layout_shaped(&term, cursor, CursorShape::Block, SelectionStyle::Colors);
+        assert_eq!(color(&yellow.rects[0]), [1.0, 1.0, 0.0, 1.0]);
+        assert_eq!(a(&yellow), [0.0, 0.0, 0.0, 1.0]);
+        let beam = layout_shaped(&term, cursor, CursorShape::Beam, SelectionStyle::Colors);
+        assert_eq!(color(beam.rects.last().unwrap()), [1.0, 1.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn osc_4_10_and_11_override_the_theme_for_their_grid() {
+        let term = term_with(4, 1, "\x1b]11;rgb:1e/1e/2e\x07\x1b]10;#ffffff\x07\x1b]4;1;#00ff00\x07a\x1b[31mb");
+        let frame = layout(&term);
+        let glyph = |c: char| frame.glyphs.iter().find(|g| g.c == c).unwrap().fg;
+        // Default-background cells are filled now, in one run.
+        assert_eq!(spans(&frame), [(0.0, 0.0, 40.0)]);
+        assert_eq!(color(&frame.rects[0]), [0x1e as f32 / 255.0, 0x1e as f32 / 255.0, 0x2e as f32 / 255.0, 1.0]);
+        assert_eq!(glyph('a'), [1.0, 1.0, 1.0, 1.0]);
+        assert_eq!(glyph('b'), [0.0, 1.0, 0.0, 1.0]);
+        // Another terminal keeps the theme's.
+        assert!(layout(&term_with(4, 1, "a")).rects.is_empty());
+    }
+
+    #[test]
+    fn beam_and_underline_cursors_leave_the_cell_colors() {
+        let theme = Theme::latte();
+        let term = term_with(20, 2, "\x1b[41mab\x1b[0m");
+        let cursor = Some(CursorCell { col: 1, line: 0 });
+        let cursor_color = opaque(theme.cursor);
+
+        let beam = layout_shaped(&term, cursor, CursorShape::Beam, SelectionStyle::Colors);
+        // The red run is unbroken and the beam is drawn over it.
+        assert_eq!(spans(&beam), [(0.0, 0.0, 20.0), (10.0, 0.0, 2.0)]);
+        assert_eq!(color(beam.rects.last().unwrap()), cursor_color);
+        assert_eq!(beam.rects.last().unwrap().h, 20.0);
+        let b = beam.glyphs.iter().find(|g| g.c == 'b').unwrap();
+        assert_ne!(b.fg, opaque(theme.bg), "the glyph keeps its own color");
+
+        let underline = layout_shaped(&term, cursor, CursorShape::Underline, SelectionStyle::Colors);
+        let bar = underline.rects.last().unwrap();
+        assert_eq!((bar.x, bar.y, bar.w, bar.h), (10.0, 18.0, 10.0, 2.0));
+
+        let block = layout_shaped(&term, cursor, CursorShape::Block, SelectionStyle::Colors);
+        assert_eq!(spans(&block), [(0.0, 0.0, 10.0), (10.0, 0.0, 10.0)]);
+        assert_eq!(color(&block.rects[1]), cursor_color);
+
+        // Hollow: the cell keeps its red, outlined on all four sides.
+        let hollow = layout_shaped(&term, cursor, CursorShape::HollowBlock, SelectionStyle::Colors);
+        let outline: Vec<_> = hollow.rects[1..].iter().map(|r| (r.x, r.y, r.w, r.h)).collect();
+        assert_eq!(outline, [(10.0, 0.0, 10.0, 2.0), (10.0, 18.0, 10.0, 2.0), (10.0, 2.0, 2.0, 16.0), (18.0, 2.0, 2.0, 16.0)]);
+        assert!(hollow.rects[1..].iter().all(|r| color(r) == cursor_color));
+    }
+
+    /// An italic 'f' as a font might rasterize it: a pixel left of its cell
+    /// and two past the right, with a one-pixel descender below it.
+    const ITALIC_F: Glyph = Glyph {
+        page: 0,
+        colored: false,
+        uv_x: 0.5,
+        uv_y: 0.25,
+        uv_w: 13.0 / 2048.0,
+        uv_h: 21.0 / 2048.0,
+        left: -1.0,
+        top: 16.0,
+        width: 13.0,
+        height: 21.0,
+    };
+
+    #[test]
+    fn italic_overhang_at_a_highlight_boundary() {
+        let frame = layout(&term_with(20, 2, "\x1b[3mf\x1b[43mX\x1b[0m"));
+        // The highlighted neighbor's background is a rect, and rects all
+        // paint before any glyph.
+        assert_eq!(spans(&frame), [(10.0, 0.0, 10.0)]);
+        let f = frame.glyphs[0];
+        assert_eq!((f.c, f.italic, f.x, f.width), ('f', true, 0.0, 10.0));
+        let cell = (f.x, f.y, f.width);
+
+        // By default the tail reaches into the highlighted cell.
+        let quad = glyph_quad(&ITALIC_F, cell, &METRICS, f.fg, false).unwrap();
+        assert_eq!((quad.x, quad.y, quad.w, quad.h), (-1.0, 0.0, 13.0, 21.0));
+        assert_eq!((quad.uv_x, quad.uv_w), (ITALIC_F.uv_x, ITALIC_F.uv_w));
+
+        // Strict clipping trims it, and the texture window, to the cell.
+        let quad = glyph_quad(&ITALIC_F, cell, &METRICS, f.fg, true).unwrap();
+        assert_eq!((quad.x, quad.y, quad.w, quad.h), (0.0, 0.0, 10.0, 20.0));
+        let px = 1.0 / 2048.0;
+        assert!((quad.uv_x - (ITALIC_F.uv_x + px)).abs() < 1e-6);
+        assert!((quad.uv_w - 10.0 * px).abs() < 1e-6);
+        assert!((quad.uv_h - 20.0 * px).abs() < 1e-6);
+        assert_eq!(quad.uv_y, ITALIC_F.uv_y);
+    }
+
+    #[test]
+    fn wide_and_color_glyphs_stay_in_their_cells() {
+        let frame = layout(&term_with(20, 2, "\u{1f980} \u{597d}x"));
+        let spans: Vec<_> = frame.glyphs.iter().map(|g| (g.c, g.x, g.width)).collect();
+        assert_eq!(spans, [('\u{1f980}', 0.0, 20.0), ('\u{597d}', 30.0, 20.0), ('x', 50.0, 10.0)]);
+        let fg = frame.glyphs[0].fg;
+
+        // A 28 px emoji bitmap shrinks to the 20x20 span, centered.
+        let crab = Glyph { colored: true, width: 28.0, height: 28.0, left: 1.0, top: 24.0, ..ITALIC_F };
+        let quad = glyph_quad(&crab, (0.0, 0.0, 20.0), &METRICS, fg, false).unwrap();
+        assert_eq!((quad.x, quad.y, quad.w, quad.h), (0.0, 0.0, 20.0, 20.0));
+        assert_eq!((quad.uv_w, quad.uv_h), (crab.uv_w, crab.uv_h), "the whole bitmap, scaled");
+
+        // One that fits is drawn as is on the baseline, centered across
+        // both cells.
+        let small = Glyph { width: 16.0, height: 16.0, top: 14.0, ..crab };
+        let quad = glyph_quad(&small, (0.0, 0.0, 20.0), &METRICS, fg, false).unwrap();
+        assert_eq!((quad.x, quad.y, quad.w, quad.h), (2.0, 2.0, 16.0, 16.0));
+
+        // A CJK glyph keeps its baseline and is centered, whatever its bearing.
+        let hao = Glyph { width: 18.0, height: 17.0, left: 0.0, top: 15.0, ..ITALIC_F };
+        let quad = glyph_quad(&hao, (30.0, 0.0, 20.0), &METRICS, fg, true).unwrap();
+        assert_eq!((quad.x, quad.y, quad.w, quad.h), (31.0, 1.0, 18.0, 17.0));
+    }
+
+    #[test]
+    fn underlines_paint_last() {
+        let frame = layout(&term_with(20, 2, "\x1b[4;41mab\x1b[0m"));
+        assert_eq!(frame.rects.len(), 3);
+        assert_eq!(frame.rects[0].w, 20.0);
+        // Baseline at 16, center 2 px below.
+        assert_eq!((frame.rects[1].y, frame.rects[1].h), (18.0, 1.0));
+    }
+
+    /// (x, y, w) of each decoration rect in a one-cell layout of `sgr`.
+    fn decorations(sgr: &str) -> Vec<(f32, f32, f32)> {
+        spans(&layout(&term_with(20, 2, &format!("\x1b[{sgr}mx\x1b[0m"))))
+    }
+
+    #[test]
+    fn strikeout_crosses_mid_cell() {
+        assert_eq!(decorations("9"), [(0.0, 10.0, 10.0)]);
+        // In the text's color, over any background.
+        let frame = layout(&term_with(20, 2, "\x1b[9;31;44mx\x1b[0m"));
+        assert_eq!(frame.rects.len(), 2);
+        assert_eq!(color(&frame.rects[1]), frame.glyphs[0].fg);
+    }
+
+    #[test]
+    fn underline_styles_are_distinct() {
+        let single = decorations("4");
+        assert_eq!(single, [(0.0, 18.0, 10.0)]);
+        assert_eq!(decorations("4:2"), [(0.0, 18.0, 10.0), (0.0, 16.0, 10.0)]);
+        assert_eq!(decorations("4:3"), [(0.0, 18.0, 5.0), (5.0, 17.0, 5.0)]);
+        assert_eq!(decorations("4:5"), [(3.0, 18.0, 5.0)]);
+        let dots = decorations("4:4");
+        assert_eq!(dots.len(), 5);
+        assert!(dots.iter().all(|&(_, y, w)| y == 18.0 && w == 1.0));
+        // Both at once.
+        assert_eq!(decorations("4;9").len(), 2);
+    }
+
+    #[test]
+    fn hovered_links_are_underlined_across_lines() {
+        let term = term_with(4, 3, "abcdefgh");
+        let mut frame = GridFrame::default();
+        let link = Some((Point::new(Line(0), Column(2)), Point::new(Line(1), Column(1))));
+        let options = GridOptions {
+            offset_x: 0.0,
+            offset_y: 0.0,
+            cursor: None,
+            cursor_shape: CursorShape::Block,
+            selection: SelectionStyle::Colors,
+            link,
+        };
+        layout_grid(&term, &Theme::latte(), &METRICS, &options, &mut frame);
+        assert_eq!(spans(&frame), [(20.0, 18.0, 10.0), (30.0, 18.0, 10.0), (0.0, 38.0, 10.0), (10.0, 38.0, 10.0)]);
+    }
+
+    #[test]
+    fn cached_layout_walks_only_changed_lines() {
+        let mut term = term_with(20, 4, "\x1b[41mone\x1b[0m\r\ntwo\r\nthree");
+        let mut options = GridOptions {
+            offset_x: 0.0,
+            offset_y: 0.0,
+            cursor: Some(CursorCell { col: 5, line: 2 }),
+            cursor_shape: CursorShape::Block,
+            selection: SelectionStyle::Colors,
+            link: None,
+        };
+        let mut cache = GridCache::default();
+        let mut cached = |term: &mut Term<VoidListener>, options: &GridOptions, theme: &Theme| {
+            let mut frame = GridFrame::default();
+            cache.snapshot(term, theme, &METRICS, options);
+            let laid_out = cache.layout(theme, &METRICS, options, &mut frame);
+            let mut full = GridFrame::default();
+            layout_grid(term, theme, &METRICS, options, &mut full);
+            assert_eq!(spans(&frame), spans(&full));
+            assert_eq!(frame.glyphs, full.glyphs);
+            laid_out
+        };
+        let latte = Theme::latte();
+        assert_eq!(cached(&mut term, &options, &latte), 4);
+        // Idle, only the cursor's line is redone.
+        assert_eq!(cached(&mut term, &options, &latte), 1);
+        // A blink off redoes the same line.
+        options.cursor = None;
+        assert_eq!(cached(&mut term, &options, &latte), 1);
+
+        let mut parser: Processor<StdSyncHandler> = Processor::new();
+        parser.advance(&mut term, b"\x1b[1;1Hzero");
+        // Line 0 for the text, line 2 for where the cursor was.
+        assert_eq!(cached(&mut term, &options, &latte), 2);
+        assert_eq!(cached(&mut term, &options, &Theme::mocha()), 4);
+    }
+
+    #[test]
+    fn a_snapshot_lays_out_without_the_terminal() {
+        let latte = Theme::latte();
+        let mut term = term_with(20, 2, "before");
+        let options = GridOptions {
+            offset_x: 0.0,
+            offset_y: 0.0,
+            cursor: None,
+            cursor_shape: CursorShape::Block,
+            selection: SelectionStyle::Colors,
+            link: None,
+        };
+        let mut cache = GridCache::default();
+        cache.snapshot(&mut term, &latte, &METRICS, &options);
+        // Output after the snapshot waits for the next one.
+        let mut parser: Processor<StdSyncHandler> = Processor::new();
+        parser.advance(&mut term, b"\rafter");
+        let mut frame = GridFrame::default();
+        cache.layout(&latte, &METRICS, &options, &mut frame);
+        let text: String = frame.glyphs.iter().map(|g| g.c).collect();
+        assert_eq!(text, "before");
+    }
+}
@@ -0,0 +1,172 @@
+//! Cell layout for overlay text (palette, badges, help), kept free of GL so
+//! the math can be unit tested. Positions are in cells; one char per cell,
+//! matching `Renderer::draw_string`.
+
+/// Tab stops every N cells unless told otherwise.
+pub const DEFAULT_TAB_WIDTH: usize = 8;
+
+const ELLIPSIS: char = '\u{2026}';
+
+/// Options for `Renderer::draw_text`.
+#[derive(Clone, Copy, Debug)]
+pub struct TextOptions {
+    /// `\t` advances to the next multiple of this many cells.
+    pub tab_width: usize,
+    /// Clip to this many cells, replacing the last visible cell with `…`.
+    pub max_cells: Option<usize>,
+}
+
+impl Default for TextOptions {
+    fn default() -> Self {
+        Self { tab_width: DEFAULT_TAB_WIDTH, max_cells: None }
+    }
+}
+
+impl TextOptions {
+    pub fn clipped(max_cells: usize) -> Self {
+        Self { max_cells: Some(max_cells), ..Self::default() }
+    }
+}
+
+/// Laid-out text: glyphs with their cell columns, plus the total width.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TextLayout {
+    /// Non-blank chars to draw and their column. Tabs and spaces are omitted.
+    pub glyphs: Vec<(usize, char)>,
+    /// Cells covered, including trailing whitespace and any tab expansion.
+    pub width: usize,
+}
+
+/// Expand tabs and clip `text` according to `opts`.
+pub fn layout_text(text: &str, opts: &TextOptions) -> TextLayout {
+    let tab_width = opts.tab_width.max(1);
+    let max = opts.max_cells.unwrap_or(usize::MAX);
+
+    let mut glyphs = Vec::new();
+    let mut col = 0;
+    for c in text.chars() {
+        let next = if c == '\t' { (col / tab_width + 1) * tab_width } else { col + 1 };
+        if next > max {
+            // Doesn't fit: the last cell that does becomes the ellipsis.
+            if max == 0 {
+                return TextLayout { glyphs, width: 0 };
+            }
+            glyphs.retain(|&(x, _)| x < max - 1);
+            glyphs.push((max - 1, ELLIPSIS));
+            return TextLayout { glyphs, width: max };
+        }
+        if c != ' ' && c != '\t' {
+            glyphs.push((col, c));
+        }
+        col = next;
+    }
+    TextLayout { glyphs, width: col }
+}
+
+/// Where to put a left-aligned label and a right-aligned hint in a row
+/// `width` cells wide, leaving at least `gap` cells between them.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TwoColumns {
+    /// Cells available to the left text; clip it to this.
+    pub left_max: usize,
+    /// Column the right text starts at.
+    pub right_col: usize,
+    /// Cells available to the right text (less than its width only when the
+    /// row is too narrow for it alone).
+    pub right_max: usize,
+}
+
+/// The right column wins: it is the shorter, fixed part (a shortcut or a
+/// count) and the left label is truncated to make room for it.
+pub fn two_columns(right_cells: usize, width: usize, gap: usize) -> TwoColumns {
+    let right_max = right_cells.min(width);
+    let right_col = width - right_max;
+    TwoColumns {
+        left_max: right_col.saturating_sub(if right_max > 0 { gap } else { 0 }),
+        right_col,
+        right_max,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cols(layout: &TextLayout) -> Vec<usize> {
+        layout.glyphs.iter().map(|&(x, _)| x).collect()
+    }
+
+    #[test]
+    fn plain_text_is_one_cell_per_char() {
+        let l = layout_text("ab c", &TextOptions::default());
+        assert_eq!(l.glyphs, vec![(0, 'a'), (1, 'b'), (3, 'c')]);
+        assert_eq!(l.width, 4);
+    }
+
+    #[test]
+    fn tabs_advance_to_next_stop() {
+        let opts = TextOptions { tab_width: 4, max_cells: None };
+        let l = layout_text("a\tb\t\tc", &opts);
+        assert_eq!(cols(&l), vec![0, 4, 12]);
+        assert_eq!(l.width, 13);
+
+        // A tab exactly on a stop still moves a full stop.
+        let l = layout_text("abcd\te", &opts);
+        assert_eq!(cols(&l), vec![0, 1, 2, 3, 8]);
+    }
+
+    #[test]
+    fn fitting_text_is_not_clipped() {
+        let l = layout_text("hello", &TextOptions::clipped(5));
+        assert_eq!(l.glyphs.last(), Some(&(4, 'o')));
+        assert_eq!(l.width, 5);
+    }
+
+    #[test]
+    fn overflow_ends_in_ellipsis() {
+        let text = |l: &TextLayout| l.glyphs.iter().map(|&(_, c)| c).collect::<String>();
+        let l = layout_text("hello world", &TextOptions::clipped(5));
+        assert_eq!(text(&l), "hell\u{2026}");
+        assert_eq!(l.glyphs.last(), Some(&(4, ELLIPSIS)));
+        assert_eq!(l.width, 5);
+
+        // The ellipsis may take the place of a blank.
+        let l = layout_text("hello world", &TextOptions::clipped(6));
+        assert_eq!(text(&l), "hello\u{2026}");
+    }
+
+    #[test]
+    fn tab_crossing_the_limit_clips() {
+        let opts = TextOptions { tab_width: 8, max_cells: Some(6) };
+        let l = layout_text("ab\tc", &opts);
+        assert_eq!(l.glyphs, vec![(0, 'a'), (1, 'b'), (5, ELLIPSIS)]);
+    }
+
+    #[test]
+    fn zero_width_clip_draws_nothing() {
+        let l = layout_text("abc", &TextOptions::clipped(0));
+        assert!(l.glyphs.is_empty());
+        assert_eq!(l.width, 0);
+        let l = layout_text("abc", &TextOptions::clipped(1));
+        assert_eq!(l.glyphs, vec![(0, ELLIPSIS)]);
+    }
+
+    #[test]
+    fn two_columns_right_aligns_hint() {
+        let c = two_columns(5, 40, 2);
+        assert_eq!(c, TwoColumns { left_max: 33, right_col: 35, right_max: 5 });
+    }
+
+    #[test]
+    fn two_columns_without_hint_gives_left_everything() {
+        assert_eq!(two_columns(0, 20, 2).left_max, 20);
+    }
+
+    #[test]
+    fn two_columns_narrow_row_keeps_hint() {
+        let c = two_columns(8, 9, 2);
+        assert_eq!(c, TwoColumns { left_max: 0, right_col: 1, right_max: 8 });
+        let c = two_columns(8, 5, 2);
+        assert_eq!(c, TwoColumns { left_max: 0, right_col: 0, right_max: 5 });
+    }
+}
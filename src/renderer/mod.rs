@@ -1,32 +1,57 @@
 pub mod atlas;
+pub mod color;
+pub mod cursor;
 pub mod glyph_cache;
+pub mod grid;
+pub mod layout;
 pub mod rects;
+pub mod runs;
 pub mod shader;
+pub mod tab_bar;
 pub mod text;
 
+use std::collections::HashMap;
+
 use alacritty_terminal::event::EventListener;
-use alacritty_terminal::term::cell::Flags;
+use alacritty_terminal::grid::Dimensions;
+use alacritty_terminal::index::Point;
+use alacritty_terminal::sync::FairMutex;
 use alacritty_terminal::term::Term;
-use alacritty_terminal::vte::ansi::{Color, NamedColor};
+use alacritty_terminal::vte::ansi::{Color, CursorShape, NamedColor};
 
+use crate::config::{FontConfig, SelectionStyle};
 use glyph_cache::GlyphCache;
+use grid::{CellMetrics, GridCache, GridFrame, GridOptions};
+use layout::TextOptions;
 use rects::{RectInstance, RectRenderer};
-use text::{GlyphInstance, TextRenderer};
+use text::TextRenderer;
 
 /// Terminal color theme.
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub struct Theme {
     pub colors: [[f32; 3]; 16],
     pub fg: [f32; 3],
     pub bg: [f32; 3],
-    pub surface0: [f32; 3],     // inactive tab bg
-    pub overlay0: [f32; 3],     // divider/separator
-    pub cursor: [f32; 3],       // cursor block
-    pub selection: [f32; 4],    // selection highlight
-    pub border: [f32; 4],       // active pane border
+    pub surface0: [f32; 3],             // inactive tab bg
+    pub overlay0: [f32; 3],             // separators
+    pub cursor: [f32; 3],               // cursor block
+    pub selection: [f32; 4],            // selection highlight
+    pub selection_fg: Option<[f32; 3]>, // selected text; None keeps fg
+    pub border: [f32; 4],               // active pane border
+    pub divider: [f32; 3],              // line between panes
+    pub divider_hover: [f32; 3],        // divider under the pointer
 }
 
+/// A named theme constructor.
+pub type ThemeEntry = (&'static str, fn() -> Theme);
+
 impl Theme {
+    /// Built-in themes by display name, in palette order.
+    pub const BUILTIN: &'static [ThemeEntry] = &[
+        ("Catppuccin Latte", Theme::latte),
+        ("Catppuccin Mocha", Theme::mocha),
+    ];
+
     pub fn latte() -> Self {
         Self {
             colors: [
@@ -53,7 +78,10 @@ impl Theme {
             overlay0: [0.725, 0.745, 0.792], // #b9bece (separators)
             cursor: [0.298, 0.310, 0.412],   // same as fg
             selection: [0.122, 0.471, 0.706, 0.3],
+            selection_fg: Some([0.937, 0.945, 0.961]), // bg on blue
             border: [0.122, 0.471, 0.706, 1.0],
+            divider: [0.725, 0.745, 0.792],       // overlay0
+            divider_hover: [0.122, 0.471, 0.706], // border
         }
     }
 
@@ -83,7 +111,10 @@ impl Theme {
             overlay0: [0.427, 0.443, 0.537], // #6c7086
             cursor: [0.804, 0.839, 0.957],   // same as fg
             selection: [0.537, 0.706, 0.980, 0.3],
+            selection_fg: Some([0.118, 0.118, 0.180]), // base on blue
             border: [0.537, 0.706, 0.980, 1.0],
+            divider: [0.427, 0.443, 0.537],       // overlay0
+            divider_hover: [0.537, 0.706, 0.980], // border
         }
     }
 
@@ -94,23 +125,91 @@ impl Theme {
     pub fn bg4(&self) -> [f32; 4] {
         [self.bg[0], self.bg[1], self.bg[2], 1.0]
     }
+
+    /// Dim a base ANSI color (indices 0-7) by 0.66.
+    fn dim_color(&self, base_idx: usize) -> [f32; 4] {
+        let c = self.colors[base_idx];
+        [c[0] * 0.66, c[1] * 0.66, c[2] * 0.66, 1.0]
+    }
+
+    /// Convert vte::ansi::Color to [f32; 4] RGBA.
+    pub fn resolve_color(&self, color: &Color) -> [f32; 4] {
+        match color {
+            Color::Named(named) => match color::palette_index(*named) {
+                Some(idx) => opaque(self.colors[idx]),
+                None => match named {
+                    NamedColor::Foreground | NamedColor::BrightForeground => self.fg4(),
+                    NamedColor::Background => self.bg4(),
+                    NamedColor::Cursor => opaque(self.cursor),
+                    NamedColor::DimForeground => {
+                        let d = 0.66;
+                        [self.fg[0] * d, self.fg[1] * d, self.fg[2] * d, 1.0]
+                    }
+                    // Dim variants: darken the base color by 0.66
+                    NamedColor::DimBlack => self.dim_color(0),
+                    NamedColor::DimRed => self.dim_color(1),
+                    NamedColor::DimGreen => self.dim_color(2),
+                    NamedColor::DimYellow => self.dim_color(3),
+                    NamedColor::DimBlue => self.dim_color(4),
+                    NamedColor::DimMagenta => self.dim_color(5),
+                    NamedColor::DimCyan => self.dim_color(6),
+                    NamedColor::DimWhite => self.dim_color(7),
+                    _ => self.fg4(),
+                },
+            },
+            Color::Spec(rgb) => opaque(color::rgb_to_f32([rgb.r, rgb.g, rgb.b])),
+            Color::Indexed(idx) if (*idx as usize) < 16 => opaque(self.colors[*idx as usize]),
+            Color::Indexed(idx) => opaque(color::index_to_rgb(*idx)),
+        }
+    }
 }
 
+fn opaque(c: [f32; 3]) -> [f32; 4] {
+    [c[0], c[1], c[2], 1.0]
+}
+
+/// Beam cursor width and underline cursor height, in logical pixels.
+const CURSOR_THICKNESS: f32 = 2.0;
+
+/// The bundled monospace face koi draws with.
+pub const FONT_FAMILY: &str = "IBM Plex Mono";
+
 pub struct Renderer {
-    pub glyph_cache: GlyphCache,
+    glyph_cache: GlyphCache,
     text_renderer: TextRenderer,
     rect_renderer: RectRenderer,
-    pub theme: Theme,
+    theme: Theme,
+    grid_frame: GridFrame,
+    /// Each pane's grid as last laid out, by pane id.
+    grid_caches: HashMap<usize, GridCache>,
+    /// Panes drawn since the last frame began; the rest's caches go.
+    grids_drawn: Vec<usize>,
+    /// Display scale factor, for sizes given in logical pixels.
+    scale: f32,
+    /// Cut grid glyphs off at their cell's edges (`font.strict_clipping`).
+    strict_clipping: bool,
 }
 
 impl Renderer {
-    pub fn new(font_family: &str, font_size: f32, scale: f32) -> Self {
-        Self::with_theme(font_family, font_size, scale, Theme::latte())
+    pub fn new(font_family: &str, font_size: f32, scale: f32, font: &FontConfig) -> Self {
+        Self::with_theme(font_family, font_size, scale, font, Theme::latte())
     }
 
-    pub fn with_theme(font_family: &str, font_size: f32, scale: f32, theme: Theme) -> Self {
+    /// Cell size in physical pixels that a renderer for this font would
+    /// have, measured without a GL context.
+    pub fn measure_cell(font_family: &str, font_size: f32, scale: f32) -> (f32, f32) {
+        glyph_cache::measure_cell(font_family, font_size * scale)
+    }
+
+    pub fn with_theme(
+        font_family: &str,
+        font_size: f32,
+        scale: f32,
+        font: &FontConfig,
+        theme: Theme,
+    ) -> Self {
         // Rasterize at physical pixel size so glyphs are sharp on HiDPI/Retina.
-        let glyph_cache = GlyphCache::new(font_family, font_size * scale);
+        let glyph_cache = GlyphCache::new(font_family, font_size * scale, &font.underline, scale);
         let text_renderer = TextRenderer::new();
         let rect_renderer = RectRenderer::new();
 
@@ -119,33 +218,82 @@ impl Renderer {
             text_renderer,
             rect_renderer,
             theme,
+            grid_frame: GridFrame::default(),
+            grid_caches: HashMap::new(),
+            grids_drawn: Vec::new(),
+            scale,
+            strict_clipping: font.strict_clipping,
         }
     }
 
-    pub fn cell_width(&self) -> f32 {
-        self.glyph_cache.cell_width
+    fn cell_metrics(&self) -> CellMetrics {
+        CellMetrics {
+            cell_width: self.glyph_cache.cell_width,
+            cell_height: self.glyph_cache.cell_height,
+            descent: self.glyph_cache.descent,
+            underline: self.glyph_cache.underline,
+            cursor_thickness: (CURSOR_THICKNESS * self.scale).round().max(1.0),
+        }
     }
+}
 
-    pub fn cell_height(&self) -> f32 {
-        self.glyph_cache.cell_height
-    }
+/// What koi draws on: the GL `Renderer`, or in tests a canvas that
+/// records what it was given. Implementors provide cell metrics, rects,
+/// single glyphs, terminal grids and flushes; labels, borders and the tab
+/// bar are built from those.
+pub trait Canvas {
+    fn theme(&self) -> &Theme;
+    fn set_theme(&mut self, theme: Theme);
+    fn cell_width(&self) -> f32;
+    fn cell_height(&self) -> f32;
 
     /// Draw a solid colored rectangle.
-    pub fn draw_rect(&mut self, x: f32, y: f32, w: f32, h: f32, color: [f32; 4]) {
-        self.rect_renderer.add(RectInstance {
-            x,
-            y,
-            w,
-            h,
-            r: color[0],
-            g: color[1],
-            b: color[2],
-            a: color[3],
-        });
-    }
+    fn draw_rect(&mut self, x: f32, y: f32, w: f32, h: f32, color: [f32; 4]);
+
+    /// Draw one overlay char into the cell at (cell_x, y). Overlays always
+    /// use the regular face.
+    fn draw_glyph(&mut self, cell_x: f32, y: f32, c: char, fg: [f32; 4]);
+
+    /// Draw pane `pane_id`'s terminal grid from alacritty_terminal state,
+    /// with the cursor `cursor` decides on, if any. Takes the
+    /// terminal's damage, to redo only what changed since the pane was
+    /// last drawn, and holds its lock only while copying that out.
+    #[allow(clippy::too_many_arguments)]
+    fn draw_grid<T: EventListener>(
+        &mut self,
+        pane_id: usize,
+        term: &FairMutex<Term<T>>,
+        offset_x: f32,
+        offset_y: f32,
+        cursor: cursor::CursorView,
+        selection: SelectionStyle,
+        link: Option<(Point, Point)>,
+    );
+
+    /// Start a `width` x `height` frame filled with `clear`.
+    fn begin_frame(&mut self, width: u32, height: u32, clear: [f32; 4]);
+
+    /// Flush all batched draw calls.
+    fn flush(&mut self, width: f32, height: f32);
+
+    /// Flush, drawing only inside the rect (x, y, w, h), given in window
+    /// pixels from the top left.
+    fn flush_clipped(&mut self, width: f32, height: f32, clip: (f32, f32, f32, f32));
+
+    /// Flush with alpha blending enabled for rects too (for overlays).
+    fn flush_blended(&mut self, width: f32, height: f32);
+
+    /// Forget every rasterized glyph, so each is drawn afresh.
+    fn invalidate_glyphs(&mut self);
+
+    /// Switch to `font_size` at display scale `scale`, keeping the theme.
+    fn rebuild(&mut self, font_size: f32, scale: f32, font: &FontConfig);
 
     /// Draw a string at pixel position (x, y) with given colors.
-    pub fn draw_string(
+    ///
+    /// Fast path for short labels like tab titles: no tab expansion or
+    /// clipping. Use `draw_text` when either is needed.
+    fn draw_string(
         &mut self,
         x: f32,
         y: f32,
@@ -153,58 +301,90 @@ impl Renderer {
         fg: [f32; 4],
         bg: [f32; 4],
     ) {
-        let cw = self.glyph_cache.cell_width;
-        let ch = self.glyph_cache.cell_height;
-        let descent = self.glyph_cache.descent;
+        let cw = self.cell_width();
+        let ch = self.cell_height();
 
         for (i, c) in text.chars().enumerate() {
             let cell_x = x + i as f32 * cw;
-            let cell_y = y;
 
             // Background
-            self.draw_rect(cell_x, cell_y, cw, ch, bg);
+            self.draw_rect(cell_x, y, cw, ch, bg);
 
             if c == ' ' {
                 continue;
             }
+            self.draw_glyph(cell_x, y, c, fg);
+        }
+    }
 
-            // Glyph (tab bar: always regular weight)
-            let glyph = self.glyph_cache.get_glyph(c, false, false);
-            if glyph.width > 0.0 {
-                // Snap to pixel grid to avoid bilinear filtering blur.
-                let gx = (cell_x + glyph.left).round();
-                let gy = (cell_y + ch + descent - glyph.top).round();
-
-                self.text_renderer.add(GlyphInstance {
-                    x: gx,
-                    y: gy,
-                    w: glyph.width,
-                    h: glyph.height,
-                    uv_x: glyph.uv_x,
-                    uv_y: glyph.uv_y,
-                    uv_w: glyph.uv_w,
-                    uv_h: glyph.uv_h,
-                    r: fg[0],
-                    g: fg[1],
-                    b: fg[2],
-                    a: fg[3],
-                });
-            }
+    /// Draw a string with tab expansion and optional ellipsis clipping.
+    /// Returns the number of cells covered.
+    fn draw_text(
+        &mut self,
+        x: f32,
+        y: f32,
+        text: &str,
+        fg: [f32; 4],
+        bg: [f32; 4],
+        opts: &TextOptions,
+    ) -> usize {
+        let cw = self.cell_width();
+        let ch = self.cell_height();
+        let laid_out = layout::layout_text(text, opts);
+        self.draw_rect(x, y, laid_out.width as f32 * cw, ch, bg);
+        for &(col, c) in &laid_out.glyphs {
+            self.draw_glyph(x + col as f32 * cw, y, c, fg);
         }
+        laid_out.width
     }
 
-    /// Draw the tab bar at the top of the window.
-    pub fn draw_tab_bar(&mut self, tab_manager: &crate::tabs::TabManager, width: f32) {
-        let ch = self.glyph_cache.cell_height;
+    /// Draw a row `width_cells` wide with `left` left-aligned and `right`
+    /// right-aligned, truncating `left` with an ellipsis when they collide.
+    fn draw_two_columns(
+        &mut self,
+        x: f32,
+        y: f32,
+        width_cells: usize,
+        left: (&str, [f32; 4]),
+        right: (&str, [f32; 4]),
+        bg: [f32; 4],
+    ) {
+        const GAP: usize = 2;
+        let cw = self.cell_width();
+        let cols = layout::two_columns(right.0.chars().count(), width_cells, GAP);
+        self.draw_text(x, y, left.0, left.1, bg, &TextOptions::clipped(cols.left_max));
+        let right_x = x + cols.right_col as f32 * cw;
+        self.draw_text(right_x, y, right.0, right.1, bg, &TextOptions::clipped(cols.right_max));
+    }
+
+    /// How many characters of a title fit in each of `count` tabs across
+    /// `width`.
+    fn tab_label_chars(&self, width: f32, count: usize) -> usize {
+        let cw = self.cell_width();
+        tab_bar::TabRect::new(0, count, width, cw).label_chars(cw)
+    }
+
+    /// Draw the tab bar at the top of the window, labelling tabs per
+    /// `template` (`tabs.title_template`) and outlining the tab at
+    /// `dragged` while it's being dragged to a new position.
+    fn draw_tab_bar(
+        &mut self,
+        tab_manager: &crate::tabs::TabManager,
+        width: f32,
+        template: &str,
+        dragged: Option<usize>,
+    ) {
+        let (cw, ch) = (self.cell_width(), self.cell_height());
         let count = tab_manager.count();
-        let tab_width = width / count as f32;
+        let label_chars = self.tab_label_chars(width, count);
 
-        let active_bg = self.theme.bg4();
-        let inactive_bg = [self.theme.surface0[0], self.theme.surface0[1], self.theme.surface0[2], 1.0];
-        let fg = self.theme.fg4();
+        let active_bg = self.theme().bg4();
+        let inactive_bg = [self.theme().surface0[0], self.theme().surface0[1], self.theme().surface0[2], 1.0];
+        let fg = self.theme().fg4();
 
         for (i, tab) in tab_manager.iter().enumerate() {
-            let x = i as f32 * tab_width;
+            let rect = tab_bar::TabRect::new(i, count, width, cw);
+            let (x, tab_width) = (rect.x, rect.width);
             let is_active = i == tab_manager.active_index();
             let bg = if is_active { active_bg } else { inactive_bg };
 
@@ -212,211 +392,46 @@ impl Renderer {
             self.draw_rect(x, 0.0, tab_width, ch, bg);
 
             // Tab title
-            let title = &tab.title;
-            let padding = 8.0;
-            self.draw_string(x + padding, 0.0, title, fg, bg);
+            let title = crate::title::tab_title(template, i, tab.label());
+            let title = crate::title::tab_label(&title, label_chars);
+            // A bell in a background tab turns its label red.
+            let bell = tab.activity.has_bell() && !is_active;
+            let label_fg = if bell { opaque(self.theme().colors[1]) } else { fg };
+            self.draw_string(x + tab_bar::PADDING, 0.0, title, label_fg, bg);
+            self.draw_string(rect.close_x, 0.0, tab_bar::CLOSE, fg, bg);
+
+            // A long command finished here while the tab was in the
+            // background (green), or it just printed something (blue).
+            let dot_color = if is_active {
+                None
+            } else if tab.activity.is_finished() {
+                Some(self.theme().colors[2])
+            } else if tab.activity.has_output() {
+                Some(self.theme().colors[4])
+            } else {
+                None
+            };
+            if let Some(c) = dot_color {
+                let dot = (ch * 0.3).round();
+                let dot_x = rect.close_x - tab_bar::PADDING / 2.0 - dot;
+                self.draw_rect(dot_x, ((ch - dot) / 2.0).round(), dot, dot, opaque(c));
+            }
 
             // Separator between tabs
             if i < count - 1 {
-                let sep = [self.theme.overlay0[0], self.theme.overlay0[1], self.theme.overlay0[2], 1.0];
+                let sep = [self.theme().overlay0[0], self.theme().overlay0[1], self.theme().overlay0[2], 1.0];
                 self.draw_rect(x + tab_width - 1.0, 0.0, 1.0, ch, sep);
             }
-        }
-    }
-
-    /// Draw the terminal grid from alacritty_terminal state.
-    pub fn draw_grid<T: EventListener>(
-        &mut self,
-        term: &Term<T>,
-        offset_x: f32,
-        offset_y: f32,
-        show_cursor: bool,
-    ) {
-        let cw = self.glyph_cache.cell_width;
-        let ch = self.glyph_cache.cell_height;
-        let descent = self.glyph_cache.descent;
-
-        let content = term.renderable_content();
-        // display_offset > 0 means we're scrolled into scrollback history.
-        // display_iter yields Line(-display_offset) as the topmost visible row.
-        // Normalize to 0-based viewport rows by adding display_offset.
-        let display_offset = content.display_offset as i32;
-        let in_scrollback = display_offset > 0;
-
-        for indexed in content.display_iter {
-            let col = indexed.point.column.0;
-            let line = indexed.point.line.0;
-            let row = (line + display_offset) as f32;
-
-            let cell_x = offset_x + col as f32 * cw;
-            let cell_y = offset_y + row * ch;
-
-            let cell = &indexed.cell;
-
-            // Skip spacer cells for wide characters (already drawn by the wide cell).
-            if cell.flags.contains(Flags::WIDE_CHAR_SPACER) {
-                continue;
-            }
 
-            // Wide chars (CJK, emoji) occupy two columns.
-            let is_wide = cell.flags.contains(Flags::WIDE_CHAR);
-            let draw_cw = if is_wide { cw * 2.0 } else { cw };
-
-            // Resolve colors, respecting INVERSE flag.
-            let (mut fg_color, mut bg_color) = (
-                self.resolve_color(&cell.fg),
-                self.resolve_color(&cell.bg),
-            );
-
-            if cell.flags.contains(Flags::INVERSE) {
-                std::mem::swap(&mut fg_color, &mut bg_color);
-            }
-
-            // Background — skip if it matches the theme's BG.
-            let is_default_bg = (bg_color[0] - self.theme.bg[0]).abs() < 1e-4
-                && (bg_color[1] - self.theme.bg[1]).abs() < 1e-4
-                && (bg_color[2] - self.theme.bg[2]).abs() < 1e-4;
-            if !is_default_bg {
-                self.draw_rect(cell_x, cell_y, draw_cw, ch, bg_color);
-            }
-
-            // Selection highlight
-            if let Some(ref sel) = content.selection {
-                let point = alacritty_terminal::index::Point::new(
-                    indexed.point.line,
-                    indexed.point.column,
-                );
-                if sel.contains(point) {
-                    self.draw_rect(cell_x, cell_y, draw_cw, ch, self.theme.selection);
-                }
-            }
-
-            let c = cell.c;
-            if c == ' ' || c == '\t' {
-                continue;
-            }
-
-            // Bold brightness boost
-            let fg = if cell.flags.contains(Flags::BOLD) {
-                [
-                    (fg_color[0] * 1.15).min(1.0),
-                    (fg_color[1] * 1.15).min(1.0),
-                    (fg_color[2] * 1.15).min(1.0),
-                    fg_color[3],
-                ]
-            } else if cell.flags.contains(Flags::DIM) {
-                [
-                    fg_color[0] * 0.66,
-                    fg_color[1] * 0.66,
-                    fg_color[2] * 0.66,
-                    fg_color[3],
-                ]
-            } else {
-                fg_color
-            };
-
-            let glyph = self.glyph_cache.get_glyph(
-                c,
-                cell.flags.contains(Flags::BOLD),
-                cell.flags.contains(Flags::ITALIC),
-            );
-            if glyph.width > 0.0 {
-                let gx = (cell_x + glyph.left).round();
-                let gy = (cell_y + ch + descent - glyph.top).round();
-
-                self.text_renderer.add(GlyphInstance {
-                    x: gx,
-                    y: gy,
-                    w: glyph.width,
-                    h: glyph.height,
-                    uv_x: glyph.uv_x,
-                    uv_y: glyph.uv_y,
-                    uv_w: glyph.uv_w,
-                    uv_h: glyph.uv_h,
-                    r: fg[0],
-                    g: fg[1],
-                    b: fg[2],
-                    a: fg[3],
-                });
-            }
-        }
-
-        // Draw cursor — hide when scrolled into history (cursor is below viewport).
-        if show_cursor && !in_scrollback {
-            let cursor = content.cursor;
-            let cursor_x = offset_x + cursor.point.column.0 as f32 * cw;
-            let cursor_y =
-                offset_y + (cursor.point.line.0 + display_offset) as f32 * ch;
-            self.draw_rect(cursor_x, cursor_y, cw, ch,
-                [self.theme.cursor[0], self.theme.cursor[1], self.theme.cursor[2], 0.7]);
-        }
-    }
-
-    /// Dim a base ANSI color (indices 0-7) by 0.66.
-    fn dim_color(&self, base_idx: usize) -> [f32; 4] {
-        let c = self.theme.colors[base_idx];
-        [c[0] * 0.66, c[1] * 0.66, c[2] * 0.66, 1.0]
-    }
-
-    /// Convert vte::ansi::Color to [f32; 4] RGBA.
-    fn resolve_color(&self, color: &Color) -> [f32; 4] {
-        match color {
-            Color::Named(named) => {
-                let idx = *named as usize;
-                if idx < 16 {
-                    let c = self.theme.colors[idx];
-                    [c[0], c[1], c[2], 1.0]
-                } else {
-                    match named {
-                        NamedColor::Foreground | NamedColor::BrightForeground => {
-                            self.theme.fg4()
-                        }
-                        NamedColor::Background => {
-                            self.theme.bg4()
-                        }
-                        NamedColor::Cursor => {
-                            self.theme.fg4()
-                        }
-                        NamedColor::DimForeground => {
-                            let d = 0.66;
-                            [self.theme.fg[0] * d, self.theme.fg[1] * d, self.theme.fg[2] * d, 1.0]
-                        }
-                        // Dim variants: darken the base color by 0.66
-                        NamedColor::DimBlack => self.dim_color(0),
-                        NamedColor::DimRed => self.dim_color(1),
-                        NamedColor::DimGreen => self.dim_color(2),
-                        NamedColor::DimYellow => self.dim_color(3),
-                        NamedColor::DimBlue => self.dim_color(4),
-                        NamedColor::DimMagenta => self.dim_color(5),
-                        NamedColor::DimCyan => self.dim_color(6),
-                        NamedColor::DimWhite => self.dim_color(7),
-                        _ => self.theme.fg4(),
-                    }
-                }
-            }
-            Color::Spec(rgb) => {
-                [
-                    rgb.r as f32 / 255.0,
-                    rgb.g as f32 / 255.0,
-                    rgb.b as f32 / 255.0,
-                    1.0,
-                ]
-            }
-            Color::Indexed(idx) => {
-                if (*idx as usize) < 16 {
-                    let c = self.theme.colors[*idx as usize];
-                    [c[0], c[1], c[2], 1.0]
-                } else {
-                    // 256-color: convert index to RGB
-                    let rgb = index_to_rgb(*idx);
-                    [rgb[0], rgb[1], rgb[2], 1.0]
-                }
+            if dragged == Some(i) {
+                let border = self.theme().border;
+                self.draw_pane_border(x, 0.0, tab_width, ch, 1.0, border);
             }
         }
     }
 
     /// Draw a rectangular border (4 thin rects forming the edges).
-    pub fn draw_pane_border(
+    fn draw_pane_border(
         &mut self,
         x: f32,
         y: f32,
@@ -434,55 +449,136 @@ impl Renderer {
         // Right edge
         self.draw_rect(x + w - thickness, y + thickness, thickness, h - 2.0 * thickness, color);
     }
+}
 
-    /// Flush all batched draw calls.
-    pub fn flush(&mut self, width: f32, height: f32) {
+impl Canvas for Renderer {
+    fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+    }
+
+    fn cell_width(&self) -> f32 {
+        self.glyph_cache.cell_width
+    }
+
+    fn cell_height(&self) -> f32 {
+        self.glyph_cache.cell_height
+    }
+
+    fn draw_rect(&mut self, x: f32, y: f32, w: f32, h: f32, color: [f32; 4]) {
+        self.rect_renderer.add(RectInstance::new(x, y, w, h, color));
+    }
+
+    fn draw_glyph(&mut self, cell_x: f32, y: f32, c: char, fg: [f32; 4]) {
+        let glyph = self.glyph_cache.get_glyph(c, false, false);
+        let metrics = self.cell_metrics();
+        if let Some(quad) = grid::glyph_quad(&glyph, (cell_x, y, metrics.cell_width), &metrics, fg, false) {
+            self.text_renderer.add(glyph.page, glyph.colored, quad);
+        }
+    }
+
+    fn draw_grid<T: EventListener>(
+        &mut self,
+        pane_id: usize,
+        term: &FairMutex<Term<T>>,
+        offset_x: f32,
+        offset_y: f32,
+        cursor: cursor::CursorView,
+        selection: SelectionStyle,
+        link: Option<(Point, Point)>,
+    ) {
+        let metrics = self.cell_metrics();
+        let cache = self.grid_caches.entry(pane_id).or_default();
+
+        let mut term = term.lock();
+        let cursor_shape = cursor.shape(&*term);
+        // Hide the cursor when scrolled into history (it's below the viewport).
+        let in_scrollback = term.grid().display_offset() > 0;
+        let cursor = cursor_shape.filter(|_| !in_scrollback).and_then(|_| cursor::cursor_cell(&*term));
+        let cursor_shape = cursor_shape.unwrap_or(CursorShape::Block);
+        let options = GridOptions { offset_x, offset_y, cursor, cursor_shape, selection, link };
+        cache.snapshot(&mut term, &self.theme, &metrics, &options);
+        let lines = term.screen_lines();
+        drop(term);
+
+        // Reuse the frame's buffers across calls.
+        let mut frame = std::mem::take(&mut self.grid_frame);
+        frame.clear();
+        let laid_out = cache.layout(&self.theme, &metrics, &options, &mut frame);
+        tracing::trace!(name: "grid_layout", pane_id, laid_out, lines);
+        self.grids_drawn.push(pane_id);
+
+        for rect in &frame.rects {
+            self.rect_renderer.add(*rect);
+        }
+        for cell in &frame.glyphs {
+            let glyph = self.glyph_cache.get_glyph(cell.c, cell.bold, cell.italic);
+            let quad = grid::glyph_quad(&glyph, (cell.x, cell.y, cell.width), &metrics, cell.fg, self.strict_clipping);
+            if let Some(quad) = quad {
+                self.text_renderer.add(glyph.page, glyph.colored, quad);
+            }
+        }
+        self.grid_frame = frame;
+    }
+
+    fn begin_frame(&mut self, width: u32, height: u32, clear: [f32; 4]) {
+        let drawn = std::mem::take(&mut self.grids_drawn);
+        self.grid_caches.retain(|pane_id, _| drawn.contains(pane_id));
+        unsafe {
+            crate::gl::Viewport(0, 0, width as i32, height as i32);
+            crate::gl::ClearColor(clear[0], clear[1], clear[2], clear[3]);
+            crate::gl::Clear(crate::gl::COLOR_BUFFER_BIT);
+        }
+    }
+
+    fn flush(&mut self, width: f32, height: f32) {
+        let _span = tracing::info_span!("flush").entered();
         // Backgrounds first (no blending)
         self.rect_renderer.flush(width, height);
         // Glyphs on top (with alpha blending)
-        let tex_id = self.glyph_cache.atlas_tex_id();
-        self.text_renderer.flush(tex_id, width, height);
+        self.text_renderer.flush(self.glyph_cache.atlas_tex_ids(), width, height);
     }
 
-    /// Flush with alpha blending enabled for rects too (for overlays).
-    pub fn flush_blended(&mut self, width: f32, height: f32) {
+    fn flush_clipped(&mut self, width: f32, height: f32, (x, y, w, h): (f32, f32, f32, f32)) {
+        unsafe {
+            crate::gl::Enable(crate::gl::SCISSOR_TEST);
+            crate::gl::Scissor(x as i32, (height - y - h) as i32, w as i32, h as i32);
+        }
+        self.flush(width, height);
+        unsafe {
+            crate::gl::Disable(crate::gl::SCISSOR_TEST);
+        }
+    }
+
+    fn flush_blended(&mut self, width: f32, height: f32) {
+        let _span = tracing::info_span!("flush_blended").entered();
         unsafe {
             crate::gl::Enable(crate::gl::BLEND);
-            crate::gl::BlendFunc(crate::gl::SRC_ALPHA, crate::gl::ONE_MINUS_SRC_ALPHA);
+            // Alpha adds up rather than squaring, so a translucent window
+            // stays as opaque under an overlay as it was.
+            crate::gl::BlendFuncSeparate(
+                crate::gl::SRC_ALPHA,
+                crate::gl::ONE_MINUS_SRC_ALPHA,
+                crate::gl::ONE,
+                crate::gl::ONE_MINUS_SRC_ALPHA,
+            );
         }
         self.rect_renderer.flush(width, height);
-        let tex_id = self.glyph_cache.atlas_tex_id();
-        self.text_renderer.flush(tex_id, width, height);
+        self.text_renderer.flush(self.glyph_cache.atlas_tex_ids(), width, height);
         unsafe {
             crate::gl::Disable(crate::gl::BLEND);
         }
     }
-}
-
-/// Map a single 6-level color-cube axis value (0-5) to its xterm byte value.
-/// xterm uses: [0x00, 0x5f, 0x87, 0xaf, 0xd7, 0xff]
-/// which is: if v == 0 { 0 } else { 55 + v * 40 }.
-fn cube_component(v: u8) -> u8 {
-    if v == 0 { 0 } else { 55 + v * 40 }
-}
 
-/// Convert 256-color index (16-255) to RGB floats using the standard xterm palette.
-fn index_to_rgb(idx: u8) -> [f32; 3] {
-    if idx < 16 {
-        // Should not reach here, handled by LATTE_COLORS
-        return [0.5, 0.5, 0.5];
+    fn invalidate_glyphs(&mut self) {
+        self.glyph_cache.invalidate();
     }
-    if idx < 232 {
-        // Color cube: 6x6x6 — each axis maps through cube_component()
-        let i = idx - 16;
-        let r = cube_component(i / 36);
-        let g = cube_component((i % 36) / 6);
-        let b = cube_component(i % 6);
-        [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0]
-    } else {
-        // Grayscale ramp: 24 shades, 8 + index * 10
-        let level = 8 + (idx - 232) as u16 * 10;
-        let v = level as f32 / 255.0;
-        [v, v, v]
+
+    fn rebuild(&mut self, font_size: f32, scale: f32, font: &FontConfig) {
+        let theme = self.theme.clone();
+        *self = Renderer::with_theme(FONT_FAMILY, font_size, scale, font, theme);
     }
 }
@@ -3,7 +3,9 @@ use crate::gl::types::*;
 
 use super::shader;
 
-const MAX_RECTS: usize = 65_536;
+/// Rects the GPU buffer holds; larger batches are drawn in several calls
+/// of this many.
+pub const MAX_RECTS: usize = 65_536;
 
 #[repr(C)]
 #[derive(Clone, Copy)]
@@ -18,6 +20,13 @@ pub struct RectInstance {
     pub a: f32,
 }
 
+impl RectInstance {
+    pub fn new(x: f32, y: f32, w: f32, h: f32, color: [f32; 4]) -> Self {
+        let [r, g, b, a] = color;
+        Self { x, y, w, h, r, g, b, a }
+    }
+}
+
 const VERT_SRC: &str = r#"
 #version 330 core
 
@@ -125,9 +134,7 @@ impl RectRenderer {
     }
 
     pub fn add(&mut self, rect: RectInstance) {
-        if self.batch.len() < MAX_RECTS {
-            self.batch.push(rect);
-        }
+        self.batch.push(rect);
     }
 
     pub fn flush(&mut self, width: f32, height: f32) {
@@ -147,19 +154,23 @@ impl RectRenderer {
 
             gl::BindVertexArray(self.vao);
             gl::BindBuffer(gl::ARRAY_BUFFER, self.instance_vbo);
-            gl::BufferSubData(
-                gl::ARRAY_BUFFER,
-                0,
-                (self.batch.len() * std::mem::size_of::<RectInstance>()) as isize,
-                self.batch.as_ptr() as *const _,
-            );
-
-            gl::DrawArraysInstanced(
-                gl::TRIANGLE_STRIP,
-                0,
-                4,
-                self.batch.len() as i32,
-            );
+            // Sequential draws over the buffer keep later rects on top.
+            for chunk in self.batch.chunks(MAX_RECTS) {
+                gl::BufferSubData(
+                    gl::ARRAY_BUFFER,
+                    0,
+                    std::mem::size_of_val(chunk) as isize,
+                    chunk.as_ptr() as *const _,
+                );
+
+                gl::DrawArraysInstanced(
+                    gl::TRIANGLE_STRIP,
+                    0,
+                    4,
+                    chunk.len() as i32,
+                );
+            }
+            tracing::trace!(name: "rect_batch", instances = self.batch.len(), draws = self.batch.len().div_ceil(MAX_RECTS));
 
             gl::BindVertexArray(0);
         }
@@ -0,0 +1,94 @@
+//! Run-length merging of cell rects: adjacent cells on a row with the same
+//! color become one rect. Cuts background rect counts by an order of
+//! magnitude on colorful output (prompts, `ls --color`, TUIs).
+
+use super::rects::RectInstance;
+
+/// Merges a stream of cell rects, in row-major order, into runs.
+#[derive(Default)]
+pub struct RectRuns {
+    run: Option<RectInstance>,
+}
+
+impl RectRuns {
+    /// Add a cell's rect. Returns the previous run if this cell doesn't
+    /// extend it (different row or color, or a gap in between).
+    pub fn push(&mut self, x: f32, y: f32, w: f32, h: f32, color: [f32; 4]) -> Option<RectInstance> {
+        if let Some(run) = self.run.as_mut() {
+            let same_color = [run.r, run.g, run.b, run.a] == color;
+            let adjacent = (run.x + run.w - x).abs() < 0.5;
+            if same_color && adjacent && run.y == y && run.h == h {
+                run.w += w;
+                return None;
+            }
+        }
+        self.run.replace(RectInstance::new(x, y, w, h, color))
+    }
+
+    /// Take the run in progress, if any.
+    pub fn finish(&mut self) -> Option<RectInstance> {
+        self.run.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+    const BLUE: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+
+    fn merge(cells: &[(usize, usize, [f32; 4])]) -> Vec<RectInstance> {
+        let mut runs = RectRuns::default();
+        let mut out: Vec<_> = cells
+            .iter()
+            .filter_map(|&(col, row, c)| runs.push(col as f32 * 10.0, row as f32 * 20.0, 10.0, 20.0, c))
+            .collect();
+        out.extend(runs.finish());
+        out
+    }
+
+    #[test]
+    fn same_color_row_is_one_rect() {
+        let rects = merge(&[(0, 0, RED), (1, 0, RED), (2, 0, RED)]);
+        assert_eq!(rects.len(), 1);
+        assert_eq!((rects[0].x, rects[0].w), (0.0, 30.0));
+    }
+
+    #[test]
+    fn color_change_gap_and_row_break_runs() {
+        let rects = merge(&[
+            (0, 0, RED),
+            (1, 0, BLUE),
+            (3, 0, BLUE), // gap at col 2 (default bg)
+            (4, 0, BLUE),
+            (5, 1, BLUE), // next row
+        ]);
+        let spans: Vec<_> = rects.iter().map(|r| (r.x, r.y, r.w)).collect();
+        assert_eq!(spans, [(0.0, 0.0, 10.0), (10.0, 0.0, 10.0), (30.0, 0.0, 20.0), (50.0, 20.0, 10.0)]);
+    }
+
+    #[test]
+    fn wide_cells_extend_runs() {
+        let mut runs = RectRuns::default();
+        assert!(runs.push(0.0, 0.0, 20.0, 20.0, RED).is_none());
+        assert!(runs.push(20.0, 0.0, 10.0, 20.0, RED).is_none());
+        let run = runs.finish().unwrap();
+        assert_eq!(run.w, 30.0);
+    }
+
+    /// Rect counts on a colored 200x60 test grid: each row has eight
+    /// 25-cell color bands, like a colored listing or a TUI status area.
+    #[test]
+    fn colored_grid_rect_reduction() {
+        let (cols, rows) = (200, 60);
+        let palette = [RED, BLUE, [0.0, 1.0, 0.0, 1.0], [1.0, 1.0, 0.0, 1.0]];
+        let cells: Vec<_> = (0..rows)
+            .flat_map(|row| (0..cols).map(move |col| (col, row, palette[(col / 25 + row) % 4])))
+            .collect();
+        let merged = merge(&cells);
+        assert_eq!(cells.len(), 12_000);
+        assert_eq!(merged.len(), 480);
+        assert!(cells.len() / merged.len() >= 10);
+    }
+}
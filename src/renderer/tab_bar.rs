@@ -0,0 +1,82 @@
+//! Where each tab and its close button sit in the tab bar, worked out in
+//! one place so what's drawn and what a click hits can't disagree.
+
+/// Space on either side of a tab's title, in pixels.
+pub const PADDING: f32 = 8.0;
+
+/// The close button glyph, one cell wide.
+pub const CLOSE: &str = "\u{d7}";
+
+/// One tab's span of the bar.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TabRect {
+    pub x: f32,
+    pub width: f32,
+    /// Left edge of the close button's cell.
+    pub close_x: f32,
+}
+
+impl TabRect {
+    /// Tab `index` of `count`, sharing a bar `bar_width` pixels wide.
+    pub fn new(index: usize, count: usize, bar_width: f32, cell_width: f32) -> Self {
+        let width = bar_width / count.max(1) as f32;
+        let x = index as f32 * width;
+        Self { x, width, close_x: x + width - PADDING / 2.0 - cell_width }
+    }
+
+    /// Columns left for the title between the padding and the close button.
+    pub fn label_chars(&self, cell_width: f32) -> usize {
+        let text_width = self.close_x - self.x - PADDING - PADDING / 2.0;
+        (text_width / cell_width).max(0.0) as usize
+    }
+}
+
+/// What a click at `x` along the bar lands on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TabHit {
+    Tab(usize),
+    Close(usize),
+}
+
+/// The tab (or its close button) at `x` in a bar of `count` tabs, or None
+/// past either end.
+pub fn hit(x: f32, count: usize, bar_width: f32, cell_width: f32) -> Option<TabHit> {
+    if x < 0.0 || count == 0 {
+        return None;
+    }
+    let index = (x / (bar_width / count as f32)) as usize;
+    if index >= count {
+        return None;
+    }
+    // The button's cell plus the padding around it, so it needn't be hit
+    // dead on.
+    let rect = TabRect::new(index, count, bar_width, cell_width);
+    Some(if x >= rect.close_x - PADDING / 2.0 { TabHit::Close(index) } else { TabHit::Tab(index) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clicks_find_the_tab_and_its_close_button() {
+        // Three 200px tabs of 10px cells: tab 1's button is at 386..396.
+        let rect = TabRect::new(1, 3, 600.0, 10.0);
+        assert_eq!(rect, TabRect { x: 200.0, width: 200.0, close_x: 386.0 });
+        assert_eq!(hit(201.0, 3, 600.0, 10.0), Some(TabHit::Tab(1)));
+        assert_eq!(hit(381.0, 3, 600.0, 10.0), Some(TabHit::Tab(1)));
+        assert_eq!(hit(382.0, 3, 600.0, 10.0), Some(TabHit::Close(1)));
+        assert_eq!(hit(399.0, 3, 600.0, 10.0), Some(TabHit::Close(1)));
+        assert_eq!(hit(400.0, 3, 600.0, 10.0), Some(TabHit::Tab(2)));
+        assert_eq!(hit(600.0, 3, 600.0, 10.0), None);
+        assert_eq!(hit(-1.0, 3, 600.0, 10.0), None);
+    }
+
+    #[test]
+    fn titles_stop_short_of_the_close_button() {
+        let rect = TabRect::new(0, 2, 400.0, 10.0);
+        // 8px padding, then 174px to the button's 4px margin.
+        assert_eq!(rect.label_chars(10.0), 17);
+        assert_eq!(TabRect::new(0, 40, 400.0, 10.0).label_chars(10.0), 0);
+    }
+}
@@ -3,7 +3,9 @@ use crate::gl::types::*;
 
 use super::shader;
 
-const MAX_INSTANCES: usize = 65_536;
+/// Instances the GPU buffer holds; larger batches are drawn in several
+/// calls of this many.
+pub const MAX_INSTANCES: usize = 65_536;
 
 // Per-instance data: position + glyph metrics + UV + color
 #[repr(C)]
@@ -54,6 +56,8 @@ const FRAG_SRC: &str = r#"
 #version 330 core
 
 uniform sampler2D uAtlas;
+// Drawing color emoji rather than coverage masks.
+uniform bool uColored;
 
 in vec2 vUV;
 flat in vec4 vColor;
@@ -62,6 +66,15 @@ layout(location = 0, index = 0) out vec4 FragColor;
 layout(location = 0, index = 1) out vec4 BlendFactor;
 
 void main() {
+    if (uColored) {
+        // The bitmap's own colors, premultiplied by the rasterizer. Undo
+        // that and blend by its alpha: plain straight-alpha compositing.
+        vec4 texel = texture(uAtlas, vUV);
+        vec3 color = texel.a > 0.0 ? texel.rgb / texel.a : vec3(0.0);
+        FragColor = vec4(color, 1.0);
+        BlendFactor = vec4(texel.a);
+        return;
+    }
     vec3 rgb = texture(uAtlas, vUV).rgb;
     // Grayscale coverage eliminates subpixel color fringing.
     // On Retina/HiDPI displays subpixel AA is unnecessary.
@@ -69,7 +82,7 @@ void main() {
     // Gamma-correct for proper font weight on sRGB displays.
     float corrected = pow(gray, 0.55);
     FragColor = vec4(vColor.rgb, 1.0);
-    BlendFactor = vec4(vec3(corrected), 1.0);
+    BlendFactor = vec4(vec3(corrected), corrected);
 }
 "#;
 
@@ -79,7 +92,17 @@ pub struct TextRenderer {
     quad_vbo: GLuint,
     instance_vbo: GLuint,
     loc_projection: GLint,
-    batch: Vec<GlyphInstance>,
+    loc_colored: GLint,
+    /// Instances by the atlas page their glyph is on, drawn one page at a
+    /// time.
+    batches: Vec<PageBatch>,
+}
+
+/// One atlas page's glyphs, split by how they're shaded.
+#[derive(Default)]
+struct PageBatch {
+    mono: Vec<GlyphInstance>,
+    colored: Vec<GlyphInstance>,
 }
 
 impl TextRenderer {
@@ -88,6 +111,7 @@ impl TextRenderer {
         let fs = shader::compile_shader(FRAG_SRC, gl::FRAGMENT_SHADER);
         let program = shader::link_program(vs, fs);
         let loc_projection = shader::get_uniform_location(program, "uProjection");
+        let loc_colored = shader::get_uniform_location(program, "uColored");
 
         let mut vao = 0;
         let mut quad_vbo = 0;
@@ -160,18 +184,26 @@ impl TextRenderer {
             quad_vbo,
             instance_vbo,
             loc_projection,
-            batch: Vec::with_capacity(MAX_INSTANCES),
+            loc_colored,
+            batches: vec![PageBatch { mono: Vec::with_capacity(MAX_INSTANCES), colored: Vec::new() }],
         }
     }
 
-    pub fn add(&mut self, instance: GlyphInstance) {
-        if self.batch.len() < MAX_INSTANCES {
-            self.batch.push(instance);
+    /// Queue a glyph from atlas page `page`; `colored` for a color
+    /// bitmap.
+    pub fn add(&mut self, page: usize, colored: bool, instance: GlyphInstance) {
+        if page >= self.batches.len() {
+            self.batches.resize_with(page + 1, PageBatch::default);
         }
+        let batch = &mut self.batches[page];
+        let batch = if colored { &mut batch.colored } else { &mut batch.mono };
+        batch.push(instance);
     }
 
-    pub fn flush(&mut self, tex_id: GLuint, width: f32, height: f32) {
-        if self.batch.is_empty() {
+    /// Draw the queued glyphs, binding each page's texture from `tex_ids`
+    /// in turn.
+    pub fn flush(&mut self, tex_ids: &[GLuint], width: f32, height: f32) {
+        if self.batches.iter().all(|b| b.mono.is_empty() && b.colored.is_empty()) {
             return;
         }
 
@@ -186,32 +218,52 @@ impl TextRenderer {
             );
 
             gl::ActiveTexture(gl::TEXTURE0);
-            gl::BindTexture(gl::TEXTURE_2D, tex_id);
 
             gl::Enable(gl::BLEND);
-            gl::BlendFunc(gl::SRC1_COLOR, gl::ONE_MINUS_SRC1_COLOR);
+            // Alpha by coverage too: over a translucent background a glyph
+            // is opaque where it's drawn, and its quad leaves no box.
+            gl::BlendFuncSeparate(gl::SRC1_COLOR, gl::ONE_MINUS_SRC1_COLOR, gl::SRC1_ALPHA, gl::ONE_MINUS_SRC1_ALPHA);
 
             gl::BindVertexArray(self.vao);
             gl::BindBuffer(gl::ARRAY_BUFFER, self.instance_vbo);
-            gl::BufferSubData(
-                gl::ARRAY_BUFFER,
-                0,
-                (self.batch.len() * std::mem::size_of::<GlyphInstance>()) as isize,
-                self.batch.as_ptr() as *const _,
-            );
 
-            gl::DrawArraysInstanced(
-                gl::TRIANGLE_STRIP,
-                0,
-                4,
-                self.batch.len() as i32,
-            );
+            let mut draws = 0;
+            for (batch, &tex_id) in self.batches.iter().zip(tex_ids) {
+                gl::BindTexture(gl::TEXTURE_2D, tex_id);
+                for (instances, colored) in [(&batch.mono, false), (&batch.colored, true)] {
+                    if instances.is_empty() {
+                        continue;
+                    }
+                    gl::Uniform1i(self.loc_colored, colored as i32);
+                    for chunk in instances.chunks(MAX_INSTANCES) {
+                        gl::BufferSubData(
+                            gl::ARRAY_BUFFER,
+                            0,
+                            std::mem::size_of_val(chunk) as isize,
+                            chunk.as_ptr() as *const _,
+                        );
+
+                        gl::DrawArraysInstanced(
+                            gl::TRIANGLE_STRIP,
+                            0,
+                            4,
+                            chunk.len() as i32,
+                        );
+                        draws += 1;
+                    }
+                }
+            }
+            let instances: usize = self.batches.iter().map(|b| b.mono.len() + b.colored.len()).sum();
+            tracing::trace!(name: "glyph_batch", instances, draws);
 
             gl::Disable(gl::BLEND);
             gl::BindVertexArray(0);
         }
 
-        self.batch.clear();
+        for batch in &mut self.batches {
+            batch.mono.clear();
+            batch.colored.clear();
+        }
     }
 }
 
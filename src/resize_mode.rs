@@ -0,0 +1,153 @@
+//! Keyboard resize mode: pick a divider, move it in cell steps with the
+//! arrow keys, then keep the result (Enter) or restore the ratios it had on
+//! entry (Escape).
+
+use crate::panes::{DividerInfo, PaneLayout, Split};
+
+/// Ratios are kept within this range, as for mouse drags.
+const MIN_RATIO: f32 = 0.1;
+const MAX_RATIO: f32 = 0.9;
+
+pub struct ResizeMode {
+    /// Index into the tab's dividers (`collect_dividers` order).
+    pub selected: usize,
+    /// Tab and panes the mode was entered with; any change ends the mode.
+    pub tab: usize,
+    pub pane_ids: Vec<usize>,
+    /// Every split's path and ratio at entry, for Escape.
+    original: Vec<(Vec<bool>, f32)>,
+}
+
+impl ResizeMode {
+    /// Enter the mode with the divider nearest `active` selected. None if
+    /// there's nothing to resize.
+    pub fn new(dividers: &[DividerInfo], active: &PaneLayout, tab: usize, pane_ids: Vec<usize>) -> Option<Self> {
+        let selected = nearest_divider(dividers, active)?;
+        let original = dividers.iter().map(|d| (d.path.clone(), d.ratio)).collect();
+        Some(Self { selected, tab, pane_ids, original })
+    }
+
+    /// Select the next divider, wrapping around.
+    pub fn cycle(&mut self, count: usize, backwards: bool) {
+        if count == 0 {
+            return;
+        }
+        self.selected = if backwards {
+            (self.selected + count - 1) % count
+        } else {
+            (self.selected + 1) % count
+        };
+    }
+
+    /// Ratios to restore on Escape.
+    pub fn original(&self) -> &[(Vec<bool>, f32)] {
+        &self.original
+    }
+}
+
+/// The divider bordering `active` that is closest to its center, or the
+/// closest divider overall if none borders it.
+pub fn nearest_divider(dividers: &[DividerInfo], active: &PaneLayout) -> Option<usize> {
+    let cx = active.x + active.width / 2.0;
+    let cy = active.y + active.height / 2.0;
+    let borders = |d: &DividerInfo| {
+        let (start, end, perp_start, perp_end) = match d.split {
+            Split::Vertical => (active.x, active.x + active.width, active.y, active.y + active.height),
+            Split::Horizontal => (active.y, active.y + active.height, active.x, active.x + active.width),
+        };
+        let on_edge = (d.position - start).abs() < 1.0 || (d.position - end).abs() < 1.0;
+        on_edge && d.perp_start < perp_end && perp_start < d.perp_end
+    };
+    let distance = |d: &DividerInfo| match d.split {
+        Split::Vertical => (d.position - cx).abs(),
+        Split::Horizontal => (d.position - cy).abs(),
+    };
+    dividers
+        .iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| {
+            (!borders(a), distance(a)).partial_cmp(&(!borders(b), distance(b))).unwrap()
+        })
+        .map(|(i, _)| i)
+}
+
+/// The ratio that moves `divider` by `delta` pixels, clamped like a drag.
+pub fn step_ratio(divider: &DividerInfo, delta: f32) -> f32 {
+    if divider.span < 1.0 {
+        return divider.ratio;
+    }
+    ((divider.position + delta - divider.origin) / divider.span).clamp(MIN_RATIO, MAX_RATIO)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::panes::PaneTree;
+
+    /// Panes 0 | (1 / 2) in an 800x600 viewport.
+    fn tree() -> PaneTree {
+        let mut tree = PaneTree::new(0);
+        tree.split_active(Split::Vertical, 1);
+        tree.split_active(Split::Horizontal, 2);
+        tree
+    }
+
+    fn layout_of(tree: &PaneTree, id: usize) -> PaneLayout {
+        tree.calculate_layouts(800.0, 600.0).into_iter().find(|l| l.pane_id == id).unwrap()
+    }
+
+    #[test]
+    fn picks_divider_bordering_active_pane() {
+        let tree = tree();
+        let dividers = tree.collect_dividers(800.0, 600.0);
+        // Pane 0 only touches the vertical divider.
+        let i = nearest_divider(&dividers, &layout_of(&tree, 0)).unwrap();
+        assert_eq!(dividers[i].split, Split::Vertical);
+        // Pane 2 touches both; the horizontal one is closer to its center.
+        let i = nearest_divider(&dividers, &layout_of(&tree, 2)).unwrap();
+        assert_eq!(dividers[i].split, Split::Horizontal);
+        assert!(nearest_divider(&[], &layout_of(&tree, 0)).is_none());
+    }
+
+    #[test]
+    fn steps_move_by_pixels_and_clamp() {
+        let tree = tree();
+        let dividers = tree.collect_dividers(800.0, 600.0);
+        let vertical = dividers.iter().find(|d| d.split == Split::Vertical).unwrap();
+        assert_eq!(vertical.position, 400.0);
+        assert!((step_ratio(vertical, 10.0) - 0.5125).abs() < 1e-6);
+        assert!((step_ratio(vertical, -10.0) - 0.4875).abs() < 1e-6);
+        assert_eq!(step_ratio(vertical, 1000.0), MAX_RATIO);
+        assert_eq!(step_ratio(vertical, -1000.0), MIN_RATIO);
+    }
+
+    #[test]
+    fn escape_restores_entry_ratios() {
+        let mut tree = tree();
+        let dividers = tree.collect_dividers(800.0, 600.0);
+        let mode = ResizeMode::new(&dividers, &layout_of(&tree, 1), 0, vec![0, 1, 2]).unwrap();
+        for d in &dividers {
+            tree.set_ratio_at(&d.path, step_ratio(d, 40.0));
+        }
+        assert_ne!(tree.collect_dividers(800.0, 600.0)[0].ratio, 0.5);
+        for (path, ratio) in mode.original() {
+            tree.set_ratio_at(path, *ratio);
+        }
+        let restored: Vec<_> = tree.collect_dividers(800.0, 600.0).iter().map(|d| d.ratio).collect();
+        assert_eq!(restored, [0.5, 0.5]);
+    }
+
+    #[test]
+    fn tab_cycles_with_wraparound() {
+        let tree = tree();
+        let dividers = tree.collect_dividers(800.0, 600.0);
+        let mut mode = ResizeMode::new(&dividers, &layout_of(&tree, 0), 0, vec![0, 1, 2]).unwrap();
+        assert_eq!(mode.selected, 0);
+        mode.cycle(dividers.len(), false);
+        assert_eq!(mode.selected, 1);
+        mode.cycle(dividers.len(), false);
+        assert_eq!(mode.selected, 0);
+        mode.cycle(dividers.len(), true);
+        assert_eq!(mode.selected, 1);
+    }
+}
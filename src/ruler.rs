@@ -0,0 +1,29 @@
+/// Left edges, relative to the pane, of ruler lines drawn after `columns`
+/// cells (a ruler at 80 sits between columns 80 and 81). Rulers that would
+/// fall outside a pane `pane_width` wide are dropped.
+pub fn ruler_offsets(columns: &[usize], cell_width: f32, pane_width: f32) -> Vec<f32> {
+    columns
+        .iter()
+        .filter(|&&col| col > 0)
+        .map(|&col| col as f32 * cell_width)
+        .filter(|&x| x + 1.0 <= pane_width)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rulers_sit_after_the_column() {
+        assert_eq!(ruler_offsets(&[80, 100], 10.0, 1200.0), [800.0, 1000.0]);
+    }
+
+    #[test]
+    fn rulers_outside_the_pane_are_clipped() {
+        // An 80-column pane with a partial-cell margin keeps its 80 ruler.
+        assert_eq!(ruler_offsets(&[80, 120], 10.0, 805.0), [800.0]);
+        assert!(ruler_offsets(&[80], 10.0, 800.0).is_empty());
+        assert!(ruler_offsets(&[0], 10.0, 800.0).is_empty());
+    }
+}
@@ -0,0 +1,129 @@
+//! Who moved a pane's view into scrollback. The scrollbar and the snap to
+//! the bottom on typing are for scrollback the user went to; offsets that
+//! search or a selection drag create in passing shouldn't flash the
+//! scrollbar or get yanked away.
+
+/// What last scrolled a pane's view.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScrollOrigin {
+    /// Wheel, trackpad or keys, or anything we don't track.
+    User,
+    /// Centering the current search match. Lasts while search is open.
+    Search,
+    /// Auto-scroll while a selection drag is past the pane's edge.
+    AutoScroll,
+}
+
+/// The one pane whose offset was last set by search or auto-scroll, if
+/// any; every other pane is `User`. Both only act on the active pane.
+#[derive(Debug, Default)]
+pub struct ScrollTracker {
+    owner: Option<(usize, ScrollOrigin)>,
+}
+
+impl ScrollTracker {
+    /// Record that `origin` just scrolled `pane`.
+    pub fn scrolled(&mut self, pane: usize, origin: ScrollOrigin) {
+        match origin {
+            ScrollOrigin::User if self.owner.is_some_and(|(id, _)| id != pane) => {}
+            ScrollOrigin::User => self.owner = None,
+            _ => self.owner = Some((pane, origin)),
+        }
+    }
+
+    pub fn origin(&self, pane: usize) -> ScrollOrigin {
+        match self.owner {
+            Some((id, origin)) if id == pane => origin,
+            _ => ScrollOrigin::User,
+        }
+    }
+
+    /// Search closed: wherever it left the view is now the user's.
+    pub fn search_closed(&mut self) {
+        self.hand_back(ScrollOrigin::Search);
+    }
+
+    /// The selection drag ended: the view it scrolled to is the user's.
+    pub fn drag_ended(&mut self) {
+        self.hand_back(ScrollOrigin::AutoScroll);
+    }
+
+    fn hand_back(&mut self, origin: ScrollOrigin) {
+        if self.owner.is_some_and(|(_, o)| o == origin) {
+            self.owner = None;
+        }
+    }
+
+    /// Show the scrollbar for `pane` at `display_offset`.
+    pub fn show_scrollbar(&self, pane: usize, display_offset: usize) -> bool {
+        display_offset > 0 && self.origin(pane) == ScrollOrigin::User
+    }
+
+    /// Jump `pane` back to live output when the user types into it.
+    pub fn snap_on_typing(&self, pane: usize, display_offset: usize) -> bool {
+        display_offset > 0 && self.origin(pane) != ScrollOrigin::Search
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_scroll_shows_scrollbar_and_snaps() {
+        let mut scroll = ScrollTracker::default();
+        scroll.scrolled(1, ScrollOrigin::User);
+        assert!(scroll.show_scrollbar(1, 10));
+        assert!(scroll.snap_on_typing(1, 10));
+        assert!(!scroll.show_scrollbar(1, 0));
+        assert!(!scroll.snap_on_typing(1, 0));
+    }
+
+    #[test]
+    fn search_owns_offset_until_closed() {
+        let mut scroll = ScrollTracker::default();
+        scroll.scrolled(1, ScrollOrigin::Search);
+        assert!(!scroll.show_scrollbar(1, 40));
+        assert!(!scroll.snap_on_typing(1, 40));
+        // Other panes are unaffected.
+        assert!(scroll.show_scrollbar(2, 5));
+
+        scroll.search_closed();
+        assert_eq!(scroll.origin(1), ScrollOrigin::User);
+        assert!(scroll.show_scrollbar(1, 40));
+        assert!(scroll.snap_on_typing(1, 40));
+    }
+
+    #[test]
+    fn user_scroll_during_search_takes_over() {
+        let mut scroll = ScrollTracker::default();
+        scroll.scrolled(1, ScrollOrigin::Search);
+        scroll.scrolled(1, ScrollOrigin::User);
+        assert!(scroll.show_scrollbar(1, 40));
+        // Navigating to the next match hands it back to search.
+        scroll.scrolled(1, ScrollOrigin::Search);
+        assert!(!scroll.show_scrollbar(1, 40));
+    }
+
+    #[test]
+    fn user_scroll_elsewhere_keeps_owner() {
+        let mut scroll = ScrollTracker::default();
+        scroll.scrolled(1, ScrollOrigin::Search);
+        scroll.scrolled(2, ScrollOrigin::User);
+        assert_eq!(scroll.origin(1), ScrollOrigin::Search);
+    }
+
+    #[test]
+    fn auto_scroll_hides_scrollbar_until_drag_ends() {
+        let mut scroll = ScrollTracker::default();
+        scroll.scrolled(1, ScrollOrigin::AutoScroll);
+        assert!(!scroll.show_scrollbar(1, 3));
+        // Typing still snaps: the drag doesn't pin the view.
+        assert!(scroll.snap_on_typing(1, 3));
+        // Closing a search that isn't the owner changes nothing.
+        scroll.search_closed();
+        assert_eq!(scroll.origin(1), ScrollOrigin::AutoScroll);
+        scroll.drag_ended();
+        assert!(scroll.show_scrollbar(1, 3));
+    }
+}
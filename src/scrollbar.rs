@@ -0,0 +1,148 @@
+//! The scrollbar along a pane's right edge while it shows history: where
+//! its thumb sits, and which offset dragging the thumb asks for.
+
+use std::time::{Duration, Instant};
+
+use alacritty_terminal::event::EventListener;
+use alacritty_terminal::grid::Dimensions;
+use alacritty_terminal::term::Term;
+
+use crate::overlay::Fade;
+
+/// Width of the bar in pixels.
+pub const WIDTH: f32 = 5.0;
+
+/// The pointer grabs the thumb this far left of the bar, so it needn't
+/// land on a 5px strip.
+pub const GRAB_MARGIN: f32 = 4.0;
+
+/// Shortest thumb, however long the history: anything smaller is hard to
+/// see and to grab.
+const MIN_THUMB: f32 = 16.0;
+
+/// How long the bar stays after the last scroll, and how long it then
+/// takes to fade out.
+const HOLD: Duration = Duration::from_millis(800);
+const FADE: Duration = Duration::from_millis(200);
+
+/// Opacity of a bar last scrolled at `last_scroll`, or None once gone.
+pub fn alpha(last_scroll: Instant) -> Option<f32> {
+    Fade::starting_at(last_scroll, HOLD, FADE).alpha()
+}
+
+/// A pane's scroll state, in lines.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Extent {
+    pub history: usize,
+    pub screen_lines: usize,
+    pub display_offset: usize,
+}
+
+impl Extent {
+    pub fn of<T: EventListener>(term: &Term<T>) -> Self {
+        Self {
+            history: term.grid().history_size(),
+            screen_lines: term.screen_lines(),
+            display_offset: term.grid().display_offset(),
+        }
+    }
+}
+
+/// The thumb's rectangle, in window pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Thumb {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+impl Thumb {
+    /// Whether `(px, py)` grabs the thumb.
+    pub fn hit(&self, px: f32, py: f32) -> bool {
+        px >= self.x - GRAB_MARGIN && px < self.x + self.w && py >= self.y && py < self.y + self.h
+    }
+}
+
+/// Height of the thumb in a bar `track` pixels tall: the share of the
+/// lines on screen.
+fn thumb_height(track: f32, extent: Extent) -> f32 {
+    let total = (extent.history + extent.screen_lines).max(1) as f32;
+    (track * extent.screen_lines as f32 / total).max(MIN_THUMB).min(track)
+}
+
+/// The thumb for a pane at `(x, y, width, height)`, or None when there's
+/// no history to scroll through.
+pub fn thumb((x, y, width, height): (f32, f32, f32, f32), extent: Extent) -> Option<Thumb> {
+    if extent.history == 0 {
+        return None;
+    }
+    let h = thumb_height(height, extent);
+    let travel = height - h;
+    let from_bottom = extent.display_offset.min(extent.history) as f32 / extent.history as f32;
+    Some(Thumb { x: x + width - WIDTH, y: y + travel * (1.0 - from_bottom), w: WIDTH, h })
+}
+
+/// The display offset that puts the thumb's top at `top` in a pane
+/// spanning `y..y + height`.
+pub fn offset_at(top: f32, (y, height): (f32, f32), extent: Extent) -> usize {
+    let travel = height - thumb_height(height, extent);
+    if travel <= 0.0 {
+        return 0;
+    }
+    let from_top = ((top - y) / travel).clamp(0.0, 1.0);
+    (extent.history as f32 * (1.0 - from_top)).round() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PANE: (f32, f32, f32, f32) = (100.0, 20.0, 400.0, 300.0);
+
+    fn extent(history: usize, display_offset: usize) -> Extent {
+        Extent { history, screen_lines: 30, display_offset }
+    }
+
+    #[test]
+    fn thumb_shows_the_share_on_screen_at_the_offset() {
+        // 30 of 120 lines on screen: a quarter of the bar.
+        let top = thumb(PANE, extent(90, 90)).unwrap();
+        assert_eq!(top, Thumb { x: 495.0, y: 20.0, w: WIDTH, h: 75.0 });
+        let bottom = thumb(PANE, extent(90, 0)).unwrap();
+        assert_eq!(bottom.y, 20.0 + 300.0 - 75.0);
+        let middle = thumb(PANE, extent(90, 45)).unwrap();
+        assert_eq!(middle.y, 20.0 + 225.0 / 2.0);
+
+        assert_eq!(thumb(PANE, extent(0, 0)), None);
+        // Long histories keep a thumb big enough to grab.
+        assert_eq!(thumb(PANE, extent(100_000, 5)).unwrap().h, MIN_THUMB);
+    }
+
+    #[test]
+    fn dragging_the_thumb_maps_back_to_the_offset() {
+        for offset in [0, 1, 44, 89, 90] {
+            let t = thumb(PANE, extent(90, offset)).unwrap();
+            assert_eq!(offset_at(t.y, (PANE.1, PANE.3), extent(90, 0)), offset);
+        }
+        // Past either end of the bar pins to the ends of history.
+        assert_eq!(offset_at(-50.0, (PANE.1, PANE.3), extent(90, 0)), 90);
+        assert_eq!(offset_at(900.0, (PANE.1, PANE.3), extent(90, 0)), 0);
+    }
+
+    #[test]
+    fn the_grab_area_reaches_left_of_the_bar() {
+        let t = thumb(PANE, extent(90, 90)).unwrap();
+        assert!(t.hit(499.0, 30.0));
+        assert!(t.hit(495.0 - GRAB_MARGIN, 30.0));
+        assert!(!t.hit(490.0, 30.0));
+        assert!(!t.hit(497.0, 20.0 + 75.0));
+    }
+
+    #[test]
+    fn fades_out_after_the_last_scroll() {
+        let now = Instant::now();
+        assert_eq!(alpha(now), Some(1.0));
+        assert_eq!(alpha(now - HOLD - FADE), None);
+    }
+}
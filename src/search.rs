@@ -0,0 +1,555 @@
+//! Cmd+F scrollback search: the query and matches, and the pane they
+//! belong to.
+
+use std::time::Instant;
+
+use alacritty_terminal::event::EventListener;
+use alacritty_terminal::grid::Dimensions;
+use alacritty_terminal::index::{Column, Line};
+use alacritty_terminal::term::cell::Flags;
+use alacritty_terminal::term::Term;
+use regex::{Regex, RegexBuilder};
+
+use crate::config::SearchFocus;
+use crate::panes::PaneLayout;
+use crate::renderer::rects::RectInstance;
+use crate::renderer::runs::RectRuns;
+
+/// A match: where it starts and how many columns it covers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SearchMatch {
+    pub line: Line,
+    pub col: usize,
+    pub len: usize,
+}
+
+/// The pattern for `query`: a regex if `regex` is set and the query
+/// compiles as one, else the query as literal, case-insensitive text.
+/// The flag says a regex failed to compile and was searched for literally.
+pub fn compile(query: &str, regex: bool) -> (Regex, bool) {
+    if regex {
+        if let Ok(pattern) = Regex::new(query) {
+            return (pattern, false);
+        }
+    }
+    let literal = RegexBuilder::new(&regex::escape(query))
+        .case_insensitive(true)
+        .build()
+        .expect("an escaped pattern compiles");
+    (literal, regex)
+}
+
+/// One grid line's text, and the byte offset and column of each char.
+/// Wide chars' spacer cells are left out.
+struct LineText {
+    text: String,
+    chars: Vec<(usize, usize)>,
+}
+
+impl LineText {
+    /// The column of the char starting at `byte`; `columns` past the end.
+    fn column_at(&self, byte: usize, columns: usize) -> usize {
+        self.chars.get(self.chars.partition_point(|&(b, _)| b < byte)).map_or(columns, |&(_, c)| c)
+    }
+}
+
+/// A grid's text (visible + scrollback), read once and searched for each
+/// keystroke until the pane's output changes it.
+pub struct GridText {
+    /// The grid line of `lines[0]`.
+    top: i32,
+    columns: usize,
+    screen_lines: usize,
+    lines: Vec<LineText>,
+}
+
+impl GridText {
+    pub fn capture<T: EventListener>(term: &Term<T>) -> Self {
+        let _span = tracing::info_span!("search_capture").entered();
+        let columns = term.grid().columns();
+        let screen_lines = term.screen_lines();
+        let top = term.topmost_line().0;
+        let lines = (top..=term.bottommost_line().0)
+            .map(|line| {
+                let row = &term.grid()[Line(line)];
+                let mut text = String::with_capacity(columns);
+                let mut chars = Vec::with_capacity(columns);
+                for col in 0..columns {
+                    let cell = &row[Column(col)];
+                    if cell.flags.intersects(Flags::WIDE_CHAR_SPACER | Flags::LEADING_WIDE_CHAR_SPACER) {
+                        continue;
+                    }
+                    chars.push((text.len(), col));
+                    text.push(cell.c);
+                }
+                LineText { text, chars }
+            })
+            .collect();
+        GridText { top, columns, screen_lines, lines }
+    }
+
+    /// Whether this is still `term`'s layout: same size, same history.
+    fn matches_layout<T: EventListener>(&self, term: &Term<T>) -> bool {
+        (self.top, self.columns, self.screen_lines)
+            == (term.topmost_line().0, term.columns(), term.screen_lines())
+    }
+
+    fn line(&self, line: Line) -> Option<&LineText> {
+        self.lines.get(usize::try_from(line.0 - self.top).ok()?)
+    }
+
+    /// All matches of `pattern`, topmost first. Matches don't cross line
+    /// ends; empty ones are skipped.
+    pub fn search(&self, pattern: &Regex) -> Vec<SearchMatch> {
+        let _span = tracing::info_span!("search_grid").entered();
+        let lines = (0..self.lines.len()).map(|i| Line(self.top + i as i32));
+        self.search_lines(pattern, lines)
+    }
+
+    /// The matches of literal `query`, given `previous`, the matches of a
+    /// prefix of it: a line holding the longer query holds the shorter one
+    /// too, so only lines with a previous match are searched again. Whole
+    /// lines, not just the previous starts: those skip overlapping spots
+    /// ("aa" in "aaab" hides the "aab" at column 1).
+    pub fn refine(&self, previous: &[SearchMatch], query: &str) -> Vec<SearchMatch> {
+        let _span = tracing::info_span!("search_refine").entered();
+        let (pattern, _) = compile(query, false);
+        let mut lines: Vec<Line> = previous.iter().map(|m| m.line).collect();
+        lines.dedup();
+        self.search_lines(&pattern, lines)
+    }
+
+    fn search_lines(&self, pattern: &Regex, lines: impl IntoIterator<Item = Line>) -> Vec<SearchMatch> {
+        let mut results = Vec::new();
+        for line_number in lines {
+            let Some(line) = self.line(line_number) else { continue };
+            for m in pattern.find_iter(&line.text).filter(|m| !m.is_empty()) {
+                let col = line.column_at(m.start(), self.columns);
+                let len = line.column_at(m.end(), self.columns) - col;
+                results.push(SearchMatch { line: line_number, col, len });
+            }
+        }
+        results
+    }
+}
+
+/// How `SearchState::rerun` found its matches.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rerun {
+    /// Searched every line.
+    Scanned,
+    /// Searched only the lines the last, shorter query matched on.
+    Refined,
+}
+
+/// The screen row grid `line` is on in a grid scrolled back
+/// `display_offset` lines and `screen_lines` tall, if it's on screen.
+pub fn viewport_row(line: Line, display_offset: usize, screen_lines: usize) -> Option<usize> {
+    usize::try_from(line.0 + display_offset as i32).ok().filter(|&row| row < screen_lines)
+}
+
+/// What an open search did when pane focus moved.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FocusChange {
+    /// Focus is still on the searched pane.
+    Unchanged,
+    /// The search now belongs to the new pane; run it there.
+    Rebound,
+    /// The search should close.
+    Close,
+}
+
+/// State for Cmd+F scrollback search.
+pub struct SearchState {
+    /// The pane being searched. Its matches are highlighted and, with more
+    /// than one pane, the search bar sits at its bottom edge.
+    pub pane_id: usize,
+    pub query: String,
+    /// Search for the query as a regex rather than as text (Cmd+R).
+    pub regex: bool,
+    /// The query isn't a valid regex, so it was searched for as text.
+    pub invalid: bool,
+    /// All matches, topmost first.
+    pub matches: Vec<SearchMatch>,
+    /// Index into matches for the current/focused match.
+    pub current: usize,
+    /// When a rerun put off while a key repeats is due.
+    pub due: Option<Instant>,
+    /// The searched pane's text, kept until its output changes.
+    text: Option<GridText>,
+    /// The literal query `matches` were found for in `text`, if any.
+    searched: Option<String>,
+}
+
+impl SearchState {
+    pub fn new(pane_id: usize) -> Self {
+        Self {
+            pane_id,
+            query: String::new(),
+            regex: false,
+            invalid: false,
+            matches: Vec::new(),
+            current: 0,
+            due: None,
+            text: None,
+            searched: None,
+        }
+    }
+
+    /// Search `term` for the query again, starting over at the first match.
+    /// The text read from `term` is reused until `invalidate`, and a
+    /// literal query that grew from the last one only rechecks the lines it
+    /// matched on.
+    pub fn rerun<T: EventListener>(&mut self, term: &Term<T>) -> Rerun {
+        let text = self.text.get_or_insert_with(|| GridText::capture(term));
+        let (pattern, invalid) = compile(&self.query, self.regex);
+        self.invalid = invalid;
+        self.due = None;
+        self.current = 0;
+        let grown = self.searched.as_deref().is_some_and(|q| !q.is_empty() && self.query.starts_with(q));
+        let rerun = if grown && !self.regex { Rerun::Refined } else { Rerun::Scanned };
+        self.matches = match rerun {
+            _ if self.query.is_empty() => Vec::new(),
+            Rerun::Refined => text.refine(&self.matches, &self.query),
+            Rerun::Scanned => text.search(&pattern),
+        };
+        self.searched = (!self.regex).then(|| self.query.clone());
+        rerun
+    }
+
+    /// The pane's text changed: read it afresh on the next rerun.
+    pub fn invalidate(&mut self) {
+        self.text = None;
+        self.searched = None;
+    }
+
+    /// Whether the matches still point at the right cells of `term`: it
+    /// hasn't printed or been resized since they were found.
+    pub fn is_fresh<T: EventListener>(&self, term: &Term<T>) -> bool {
+        self.query.is_empty() || self.text.as_ref().is_some_and(|text| text.matches_layout(term))
+    }
+
+    /// Find the matches in `term` afresh, staying on the current match's
+    /// index where there still is one.
+    pub fn refresh<T: EventListener>(&mut self, term: &Term<T>) {
+        let current = self.current;
+        self.invalidate();
+        self.rerun(term);
+        self.current = current.min(self.matches.len().saturating_sub(1));
+    }
+
+    /// Focus moved to `pane_id`. With `SearchFocus::Follow` the query moves
+    /// along and its old pane's matches are dropped; the caller reruns it.
+    pub fn focus_changed(&mut self, pane_id: usize, policy: SearchFocus) -> FocusChange {
+        if pane_id == self.pane_id {
+            return FocusChange::Unchanged;
+        }
+        match policy {
+            SearchFocus::Close => FocusChange::Close,
+            SearchFocus::Follow => {
+                self.pane_id = pane_id;
+                self.invalidate();
+                self.matches.clear();
+                self.current = 0;
+                FocusChange::Rebound
+            }
+        }
+    }
+
+    /// Highlights for the matches on screen in a grid scrolled back
+    /// `display_offset` lines and `screen_lines` tall, drawn at `origin`.
+    /// The current match is orange, the rest yellow; back-to-back matches
+    /// on a row share one rect.
+    pub fn highlights(
+        &self,
+        display_offset: usize,
+        screen_lines: usize,
+        origin: (f32, f32),
+        (cw, ch): (f32, f32),
+    ) -> Vec<RectInstance> {
+        let mut runs = RectRuns::default();
+        let mut highlights = Vec::new();
+        for (i, &SearchMatch { line, col, len }) in self.matches.iter().enumerate() {
+            if let Some(row) = viewport_row(line, display_offset, screen_lines) {
+                let vy = row as f32;
+                let color = if i == self.current {
+                    [1.0, 0.6, 0.0, 0.5] // orange for current
+                } else {
+                    [1.0, 0.9, 0.0, 0.3] // yellow for others
+                };
+                highlights.extend(runs.push(
+                    origin.0 + col as f32 * cw,
+                    origin.1 + vy * ch,
+                    len as f32 * cw,
+                    ch,
+                    color,
+                ));
+            }
+        }
+        highlights.extend(runs.finish());
+        highlights
+    }
+
+    /// The search bar's left edge, top and width. Docked to the bottom of
+    /// the searched pane's `layout` when the tab is split; along the bottom
+    /// of the window otherwise, or if the pane isn't laid out.
+    pub fn bar_rect(
+        layout: Option<&PaneLayout>,
+        split: bool,
+        (window_w, window_h): (f32, f32),
+        tab_bar_height: f32,
+        cell_height: f32,
+    ) -> (f32, f32, f32) {
+        match layout.filter(|_| split) {
+            Some(l) => {
+                let bottom = l.y + tab_bar_height + l.height;
+                (l.x, (bottom - cell_height).max(l.y + tab_bar_height), l.width)
+            }
+            None => (0.0, window_h - cell_height, window_w),
+        }
+    }
+
+    /// The bar's text: the mode, the query and, once it has one, the
+    /// match count.
+    pub fn status(&self) -> String {
+        let mode = if self.regex { "Regex" } else { "Search" };
+        let invalid = if self.invalid { " (invalid regex)" } else { "" };
+        if self.matches.is_empty() {
+            if self.query.is_empty() {
+                format!("{mode}: ")
+            } else {
+                format!("{mode}: {}{invalid} (no matches)", self.query)
+            }
+        } else {
+            format!("{mode}: {}{invalid} ({}/{})", self.query, self.current + 1, self.matches.len())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alacritty_terminal::event::VoidListener;
+    use alacritty_terminal::term::Config;
+    use alacritty_terminal::vte::ansi::{Processor, StdSyncHandler};
+
+    use super::*;
+    use crate::terminal::TerminalSize;
+
+    fn term(text: &str) -> Term<VoidListener> {
+        let mut term = Term::new(Config::default(), &TerminalSize::new(20, 4), VoidListener);
+        let mut parser: Processor<StdSyncHandler> = Processor::new();
+        parser.advance(&mut term, text.as_bytes());
+        term
+    }
+
+    #[test]
+    fn follow_rebinds_and_reruns_in_the_new_pane() {
+        let left = term("foo bar foo");
+        let right = term("one foo");
+        let mut search = SearchState::new(0);
+        search.query.push_str("foo");
+        search.rerun(&left);
+        search.current = 1;
+        assert_eq!(search.status(), "Search: foo (2/2)");
+
+        assert_eq!(search.focus_changed(0, SearchFocus::Follow), FocusChange::Unchanged);
+        assert_eq!(search.matches.len(), 2);
+
+        assert_eq!(search.focus_changed(1, SearchFocus::Follow), FocusChange::Rebound);
+        assert_eq!((search.pane_id, search.query.as_str()), (1, "foo"));
+        assert!(search.matches.is_empty(), "the old pane's matches don't carry over");
+        search.rerun(&right);
+        assert_eq!(search.matches, [SearchMatch { line: Line(0), col: 4, len: 3 }]);
+        assert_eq!(search.status(), "Search: foo (1/1)");
+    }
+
+    #[test]
+    fn regex_mode_highlights_exactly_what_matched() {
+        let t = term("ip 10.123.456.7 up");
+        let mut search = SearchState::new(0);
+        search.query.push_str(r"\d{3}\.\d{3}");
+        search.rerun(&t);
+        assert!(search.matches.is_empty(), "literal mode looks for the text itself");
+
+        search.regex = true;
+        search.rerun(&t);
+        assert_eq!(search.matches, [SearchMatch { line: Line(0), col: 6, len: 7 }]);
+        let rects = search.highlights(0, 4, (0.0, 0.0), (10.0, 20.0));
+        assert_eq!((rects[0].x, rects[0].w), (60.0, 70.0));
+        assert_eq!(search.status(), r"Regex: \d{3}\.\d{3} (1/1)");
+    }
+
+    #[test]
+    fn regex_mode_is_case_sensitive_unless_asked() {
+        let t = term("Error warn ERROR");
+        let mut search = SearchState::new(0);
+        search.regex = true;
+        search.query.push_str("error|warn");
+        search.rerun(&t);
+        assert_eq!(search.matches.len(), 1);
+        search.query.insert_str(0, "(?i)");
+        search.rerun(&t);
+        assert_eq!(search.matches.iter().map(|m| m.col).collect::<Vec<_>>(), [0, 6, 11]);
+    }
+
+    #[test]
+    fn invalid_regex_falls_back_to_text() {
+        let t = term("f(x) = (1");
+        let mut search = SearchState::new(0);
+        search.regex = true;
+        search.query.push('(');
+        search.rerun(&t);
+        assert!(search.invalid);
+        assert_eq!(search.matches.len(), 2);
+        assert_eq!(search.status(), "Regex: ( (invalid regex) (1/2)");
+    }
+
+    #[test]
+    fn wide_chars_count_as_two_columns() {
+        let t = term("日本 foo");
+        let mut search = SearchState::new(0);
+        search.query.push_str("本 f");
+        search.rerun(&t);
+        assert_eq!(search.matches, [SearchMatch { line: Line(0), col: 2, len: 4 }]);
+    }
+
+    /// A 10,000-line, 200-column scrollback of log lines, an error every
+    /// 97 lines.
+    fn synthetic_scrollback() -> GridText {
+        let columns = 200;
+        let lines = (0..10_000)
+            .map(|i| {
+                let level = if i % 97 == 0 { "ERROR" } else { "info" };
+                let mut text = format!("{i:05} {level} request served in {}ms", i % 300);
+                text.push_str(&" ".repeat(columns - text.len()));
+                let chars = (0..columns).map(|c| (c, c)).collect();
+                LineText { text, chars }
+            })
+            .collect();
+        GridText { top: -9_990, columns, screen_lines: 10, lines }
+    }
+
+    #[test]
+    fn typing_on_refines_the_last_matches_instead_of_rescanning() {
+        let t = term("");
+        let mut search = SearchState::new(0);
+        search.text = Some(synthetic_scrollback());
+
+        let mut paths = Vec::new();
+        for c in "error".chars() {
+            search.query.push(c);
+            paths.push(search.rerun(&t));
+        }
+        use Rerun::{Refined, Scanned};
+        assert_eq!(paths, [Scanned, Refined, Refined, Refined, Refined]);
+        let text = search.text.as_ref().unwrap();
+        let (pattern, _) = compile("error", false);
+        assert_eq!(search.matches, text.search(&pattern), "refining finds what a full scan does");
+        assert_eq!(search.matches.len(), 10_000usize.div_ceil(97));
+        assert_eq!(search.matches[1], SearchMatch { line: Line(-9_990 + 97), col: 6, len: 5 });
+
+        // Backspace, a regex, or new output means a full scan.
+        search.query.pop();
+        assert_eq!(search.rerun(&t), Scanned);
+        search.regex = true;
+        search.query.push('r');
+        assert_eq!(search.rerun(&t), Scanned);
+        search.regex = false;
+        search.rerun(&t);
+        search.invalidate();
+        search.query.push('!');
+        assert_eq!(search.rerun(&t), Scanned);
+    }
+
+    #[test]
+    fn refining_drops_overlaps_like_a_fresh_search() {
+        let t = term("aaaa ab");
+        let mut search = SearchState::new(0);
+        search.query.push('a');
+        search.rerun(&t);
+        assert_eq!(search.matches.len(), 5);
+        search.query.push('a');
+        assert_eq!(search.rerun(&t), Rerun::Refined);
+        assert_eq!(search.matches.iter().map(|m| m.col).collect::<Vec<_>>(), [0, 2]);
+    }
+
+    #[test]
+    fn refining_finds_matches_the_last_query_overlapped() {
+        let t = term("aaab");
+        let mut search = SearchState::new(0);
+        for c in "aab".chars() {
+            search.query.push(c);
+            search.rerun(&t);
+        }
+        assert_eq!(search.matches, [SearchMatch { line: Line(0), col: 1, len: 3 }]);
+    }
+
+    #[test]
+    fn matches_map_to_rows_of_the_scrolled_viewport() {
+        // At the bottom, grid line 0 is the top row.
+        assert_eq!(viewport_row(Line(0), 0, 24), Some(0));
+        assert_eq!(viewport_row(Line(23), 0, 24), Some(23));
+        assert_eq!(viewport_row(Line(-1), 0, 24), None);
+        // Scrolled back 10 lines, history line -10 is on top and the last
+        // 10 screen lines are below the viewport.
+        assert_eq!(viewport_row(Line(-10), 10, 24), Some(0));
+        assert_eq!(viewport_row(Line(0), 10, 24), Some(10));
+        assert_eq!(viewport_row(Line(13), 10, 24), Some(23));
+        assert_eq!(viewport_row(Line(14), 10, 24), None);
+        assert_eq!(viewport_row(Line(-11), 10, 24), None);
+
+        let mut search = SearchState::new(0);
+        search.matches = vec![
+            SearchMatch { line: Line(-12), col: 0, len: 2 },
+            SearchMatch { line: Line(-8), col: 4, len: 3 },
+            SearchMatch { line: Line(5), col: 1, len: 1 },
+        ];
+        search.current = 1;
+        let rects = search.highlights(10, 4, (100.0, 50.0), (10.0, 20.0));
+        assert_eq!(rects.len(), 1, "only line -8 is in lines -10..=-7");
+        assert_eq!((rects[0].x, rects[0].y, rects[0].w), (140.0, 50.0 + 2.0 * 20.0, 30.0));
+    }
+
+    #[test]
+    fn output_or_a_resize_makes_matches_stale() {
+        let mut t = term("foo\r\nbar foo");
+        let mut search = SearchState::new(0);
+        assert!(search.is_fresh(&t), "nothing to refresh without a query");
+        search.query.push_str("foo");
+        search.rerun(&t);
+        search.current = 1;
+        assert!(search.is_fresh(&t));
+
+        t.resize(TerminalSize::new(30, 4));
+        assert!(!search.is_fresh(&t));
+        search.refresh(&t);
+        assert!(search.is_fresh(&t));
+        assert_eq!((search.matches.len(), search.current), (2, 1), "still on the second match");
+
+        search.invalidate();
+        assert!(!search.is_fresh(&t));
+    }
+
+    #[test]
+    fn close_policy_leaves_state_for_the_caller_to_drop() {
+        let mut search = SearchState::new(3);
+        search.query.push('x');
+        assert_eq!(search.focus_changed(3, SearchFocus::Close), FocusChange::Unchanged);
+        assert_eq!(search.focus_changed(4, SearchFocus::Close), FocusChange::Close);
+        assert_eq!(search.pane_id, 3);
+    }
+
+    #[test]
+    fn bar_docks_to_the_searched_pane_when_split() {
+        let layout = PaneLayout { pane_id: 1, x: 400.0, y: 0.0, width: 400.0, height: 300.0 };
+        let window = (800.0, 620.0);
+        // Split: the pane's bottom row, below the 20 px tab bar.
+        assert_eq!(SearchState::bar_rect(Some(&layout), true, window, 20.0, 20.0), (400.0, 300.0, 400.0));
+        // One pane: the whole window's bottom row.
+        assert_eq!(SearchState::bar_rect(Some(&layout), false, window, 20.0, 20.0), (0.0, 600.0, 800.0));
+        assert_eq!(SearchState::bar_rect(None, true, window, 0.0, 20.0), (0.0, 600.0, 800.0));
+        // A pane shorter than a row keeps the bar inside its top.
+        let flat = PaneLayout { height: 10.0, ..layout };
+        assert_eq!(SearchState::bar_rect(Some(&flat), true, window, 0.0, 20.0), (400.0, 0.0, 400.0));
+    }
+}
@@ -0,0 +1,209 @@
+use alacritty_terminal::event::EventListener;
+use alacritty_terminal::grid::Dimensions;
+use alacritty_terminal::index::Side;
+use alacritty_terminal::selection::{Selection, SelectionType};
+use alacritty_terminal::term::Term;
+
+/// Bring the selection back inside the grid, or drop it if nothing it covered
+/// still exists.
+///
+/// Selections are rotated along with the grid as output scrolls, but once
+/// history is full the oldest lines are discarded while the anchors keep
+/// moving up — past `topmost_line()`. Resizes that shrink the grid can leave
+/// anchors below the last line the same way. Call this before any use of
+/// the selection (copying, or after a resize).
+pub fn revalidate<T: EventListener>(term: &mut Term<T>) {
+    let Some(selection) = term.selection.as_ref() else {
+        return;
+    };
+    let top = term.topmost_line();
+    let bottom = term.bottommost_line();
+
+    let above = selection.intersects_range(..top);
+    let below = selection.intersects_range(bottom + 1..);
+    if !above && !below {
+        return;
+    }
+    if !selection.intersects_range(top..=bottom) {
+        term.selection = None;
+        return;
+    }
+
+    // Rebuild from the clamped range, which keeps only the surviving lines.
+    let ty = selection.ty;
+    term.selection = selection.to_range(term).map(|range| {
+        let mut clamped = Selection::new(ty, range.start, Side::Left);
+        clamped.update(range.end, Side::Right);
+        clamped
+    });
+}
+
+/// The selection a click starts: by character, word (double click) or line
+/// (triple or quadruple click), or a rectangle when `block` (Option/Alt
+/// held). A line runs across the rows it wrapped onto.
+pub fn click_type(click_count: u8, block: bool) -> SelectionType {
+    match click_count {
+        _ if block => SelectionType::Block,
+        2 => SelectionType::Semantic,
+        3 | 4 => SelectionType::Lines,
+        _ => SelectionType::Simple,
+    }
+}
+
+/// Text of the selection after revalidating it.
+pub fn selection_text<T: EventListener>(term: &mut Term<T>) -> Option<String> {
+    revalidate(term);
+    term.selection_to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alacritty_terminal::event::VoidListener;
+    use alacritty_terminal::index::{Column, Line, Point};
+    use alacritty_terminal::term::Config;
+    use alacritty_terminal::vte::ansi::{Processor, StdSyncHandler};
+
+    use crate::terminal::TerminalSize;
+
+    const HISTORY: usize = 5;
+
+    fn in_grid(term: &Term<VoidListener>, point: Point) -> bool {
+        point.line >= term.topmost_line() && point.line <= term.bottommost_line()
+    }
+
+    fn term(cols: usize, rows: usize) -> Term<VoidListener> {
+        let config = Config { scrolling_history: HISTORY, ..Config::default() };
+        Term::new(config, &TerminalSize::new(cols, rows), VoidListener)
+    }
+
+    fn print_lines(term: &mut Term<VoidListener>, range: std::ops::Range<usize>) {
+        let mut parser: Processor<StdSyncHandler> = Processor::new();
+        for i in range {
+            parser.advance(term, format!("line{i}\r\n").as_bytes());
+        }
+    }
+
+    fn select(term: &mut Term<VoidListener>, start: Point, end: Point) {
+        let mut sel = Selection::new(SelectionType::Simple, start, Side::Left);
+        sel.update(end, Side::Right);
+        term.selection = Some(sel);
+    }
+
+    #[test]
+    fn valid_selection_is_untouched() {
+        let mut t = term(10, 3);
+        print_lines(&mut t, 0..2);
+        select(&mut t, Point::new(Line(0), Column(0)), Point::new(Line(0), Column(4)));
+        let before = t.selection.clone();
+        revalidate(&mut t);
+        assert_eq!(t.selection, before);
+        assert_eq!(selection_text(&mut t).as_deref(), Some("line0"));
+    }
+
+    #[test]
+    fn history_wrap_clamps_to_surviving_lines() {
+        let mut t = term(10, 3);
+        // Fill the screen and history: line0 is now the oldest history line.
+        print_lines(&mut t, 0..(HISTORY + 2));
+        let top = t.topmost_line();
+        select(&mut t, Point::new(top, Column(0)), Point::new(top + 1, Column(4)));
+        assert_eq!(selection_text(&mut t).as_deref(), Some("line0\nline1"));
+
+        // One more line drops line0 while the anchors keep rotating upward.
+        print_lines(&mut t, (HISTORY + 2)..(HISTORY + 3));
+        assert_eq!(t.topmost_line(), top);
+        assert_eq!(selection_text(&mut t).as_deref(), Some("line1"));
+        let range = t.selection.as_ref().unwrap().to_range(&t).unwrap();
+        assert!(in_grid(&t, range.start) && in_grid(&t, range.end));
+    }
+
+    #[test]
+    fn history_wrap_past_whole_selection_clears_it() {
+        let mut t = term(10, 3);
+        print_lines(&mut t, 0..(HISTORY + 2));
+        let top = t.topmost_line();
+        select(&mut t, Point::new(top, Column(0)), Point::new(top, Column(4)));
+
+        print_lines(&mut t, (HISTORY + 2)..(HISTORY + 4));
+        assert_eq!(selection_text(&mut t), None);
+        assert!(t.selection.is_none());
+    }
+
+    #[test]
+    fn resize_then_revalidate_stays_in_grid() {
+        let mut t = term(10, 6);
+        print_lines(&mut t, 0..4);
+        select(&mut t, Point::new(Line(1), Column(0)), Point::new(Line(3), Column(4)));
+
+        t.resize(TerminalSize::new(10, 2));
+        revalidate(&mut t);
+        if let Some(sel) = t.selection.as_ref() {
+            let range = sel.to_range(&t).unwrap();
+            assert!(in_grid(&t, range.start) && in_grid(&t, range.end));
+        }
+        // Copy never panics and yields only text that exists.
+        if let Some(text) = selection_text(&mut t) {
+            assert!(text.lines().all(|l| l.starts_with("line")));
+        }
+    }
+
+    #[test]
+    fn block_selection_copies_one_column() {
+        let mut t = term(40, 4);
+        let mut parser: Processor<StdSyncHandler> = Processor::new();
+        let ps = "USER   PID %CPU COMMAND\r\n\
+                  root     1  0.0 /sbin/init\r\n\
+                  me     812 12.5 koi\r\n";
+        parser.advance(&mut t, ps.as_bytes());
+        // Drag from the top of the PID column's left edge to its bottom right.
+        let mut sel = Selection::new(click_type(1, true), Point::new(Line(0), Column(7)), Side::Left);
+        sel.update(Point::new(Line(2), Column(9)), Side::Right);
+        t.selection = Some(sel);
+        assert_eq!(selection_text(&mut t).as_deref(), Some("PID\n  1\n812"));
+
+        // Rows outside the rectangle's columns aren't highlighted.
+        let range = t.selection.as_ref().unwrap().to_range(&t).unwrap();
+        assert!(range.contains(Point::new(Line(1), Column(9))));
+        assert!(!range.contains(Point::new(Line(1), Column(12))));
+        assert!(!range.contains(Point::new(Line(2), Column(0))));
+
+        // Surviving a history wrap keeps it a rectangle.
+        revalidate(&mut t);
+        assert_eq!(t.selection.as_ref().unwrap().ty, SelectionType::Block);
+    }
+
+    #[test]
+    fn click_types() {
+        assert_eq!(click_type(1, false), SelectionType::Simple);
+        assert_eq!(click_type(2, false), SelectionType::Semantic);
+        assert_eq!(click_type(3, false), SelectionType::Lines);
+        assert_eq!(click_type(4, false), SelectionType::Lines);
+        assert_eq!(click_type(1, true), SelectionType::Block);
+    }
+
+    #[test]
+    fn line_clicks_take_the_whole_wrapped_line() {
+        let mut t = term(10, 4);
+        let mut parser: Processor<StdSyncHandler> = Processor::new();
+        parser.advance(&mut t, b"first row and then some\r\nnext");
+        for count in [3, 4] {
+            t.selection = Some(Selection::new(click_type(count, false), Point::new(Line(1), Column(3)), Side::Left));
+            assert_eq!(selection_text(&mut t).as_deref(), Some("first row and then some\n"));
+        }
+    }
+
+    #[test]
+    fn word_boundaries_follow_the_escape_chars() {
+        let mut t = term(40, 2);
+        let mut parser: Processor<StdSyncHandler> = Processor::new();
+        parser.advance(&mut t, b"$ /usr/local/bin/python3 -V");
+        let word = |t: &mut Term<VoidListener>| {
+            t.selection = Some(Selection::new(click_type(2, false), Point::new(Line(0), Column(14)), Side::Left));
+            selection_text(t)
+        };
+        assert_eq!(word(&mut t).as_deref(), Some("/usr/local/bin/python3"));
+        t.set_options(Config { semantic_escape_chars: " /".into(), ..Config::default() });
+        assert_eq!(word(&mut t).as_deref(), Some("bin"));
+    }
+}
@@ -0,0 +1,245 @@
+//! The selection as runs of identically styled text, for copies that keep
+//! their colors. "Copy as ANSI" turns the runs back into SGR sequences.
+
+use std::fmt::Write as _;
+
+use alacritty_terminal::event::EventListener;
+use alacritty_terminal::grid::Dimensions;
+use alacritty_terminal::index::{Column, Line};
+use alacritty_terminal::term::cell::Flags;
+use alacritty_terminal::term::Term;
+use alacritty_terminal::vte::ansi::{Color, NamedColor};
+
+use crate::renderer::color::palette_index;
+
+/// The flags that change how text looks, as opposed to layout bookkeeping
+/// (wide-char spacers, soft wraps).
+const STYLE_FLAGS: Flags = Flags::INVERSE
+    .union(Flags::BOLD)
+    .union(Flags::ITALIC)
+    .union(Flags::DIM)
+    .union(Flags::HIDDEN)
+    .union(Flags::STRIKEOUT)
+    .union(Flags::ALL_UNDERLINES);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Style {
+    pub fg: Color,
+    pub bg: Color,
+    pub flags: Flags,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            fg: Color::Named(NamedColor::Foreground),
+            bg: Color::Named(NamedColor::Background),
+            flags: Flags::empty(),
+        }
+    }
+}
+
+/// One line of the selection: text runs with their style, in order.
+pub type StyledLine = Vec<(Style, String)>;
+
+/// The selection split into lines of style runs. Soft-wrapped rows join
+/// into one line, and trailing blank cells in the default style are left
+/// out, as in a plain-text copy.
+pub fn styled_selection<T: EventListener>(term: &mut Term<T>) -> Option<Vec<StyledLine>> {
+    crate::selection::revalidate(term);
+    let range = term.selection.as_ref()?.to_range(term)?;
+    let last_col = term.columns() - 1;
+    let mut lines = Vec::new();
+    let mut current = StyledLine::new();
+    for line in range.start.line.0..=range.end.line.0 {
+        let line = Line(line);
+        let row = &term.grid()[line];
+        let start = if range.is_block || line == range.start.line { range.start.column.0 } else { 0 };
+        let end = if range.is_block || line == range.end.line { range.end.column.0 } else { last_col };
+        for col in start..=end.min(last_col) {
+            let cell = &row[Column(col)];
+            if cell.flags.intersects(Flags::WIDE_CHAR_SPACER | Flags::LEADING_WIDE_CHAR_SPACER) {
+                continue;
+            }
+            let style = Style { fg: cell.fg, bg: cell.bg, flags: cell.flags & STYLE_FLAGS };
+            let text = match current.last_mut() {
+                Some((s, text)) if *s == style => text,
+                _ => {
+                    current.push((style, String::new()));
+                    &mut current.last_mut().unwrap().1
+                }
+            };
+            text.push(cell.c);
+            text.extend(cell.zerowidth().into_iter().flatten());
+        }
+        let wraps = !range.is_block && row[Column(last_col)].flags.contains(Flags::WRAPLINE);
+        if !wraps || line == range.end.line {
+            trim_trailing_blanks(&mut current);
+            lines.push(std::mem::take(&mut current));
+        }
+    }
+    Some(lines)
+}
+
+fn trim_trailing_blanks(line: &mut StyledLine) {
+    while let Some((style, text)) = line.last_mut() {
+        if *style != Style::default() {
+            return;
+        }
+        let kept = text.trim_end_matches(' ').len();
+        text.truncate(kept);
+        if !text.is_empty() {
+            return;
+        }
+        line.pop();
+    }
+}
+
+/// Styled lines as text with SGR sequences: each run starts from a reset
+/// so it doesn't depend on what came before, and each line that leaves a
+/// style set ends with a reset.
+pub fn to_ansi(lines: &[StyledLine]) -> String {
+    let mut out = String::new();
+    for (i, line) in lines.iter().enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        let mut styled = false;
+        for (style, text) in line {
+            if *style != Style::default() || styled {
+                sgr(&mut out, style);
+                styled = *style != Style::default();
+            }
+            out.push_str(text);
+        }
+        if styled {
+            out.push_str("\x1b[0m");
+        }
+    }
+    out
+}
+
+fn sgr(out: &mut String, style: &Style) {
+    out.push_str("\x1b[0");
+    let flags = [
+        (Flags::BOLD, "1"),
+        (Flags::DIM, "2"),
+        (Flags::ITALIC, "3"),
+        (Flags::UNDERLINE, "4"),
+        (Flags::DOUBLE_UNDERLINE, "4:2"),
+        (Flags::UNDERCURL, "4:3"),
+        (Flags::DOTTED_UNDERLINE, "4:4"),
+        (Flags::DASHED_UNDERLINE, "4:5"),
+        (Flags::INVERSE, "7"),
+        (Flags::HIDDEN, "8"),
+        (Flags::STRIKEOUT, "9"),
+    ];
+    for (flag, code) in flags {
+        if style.flags.contains(flag) {
+            out.push(';');
+            out.push_str(code);
+        }
+    }
+    color(out, style.fg, 30, 90, 38);
+    color(out, style.bg, 40, 100, 48);
+    out.push('m');
+}
+
+/// Append the SGR parameters for `color`: `base` + n for the normal eight,
+/// `bright` + n for the bright eight, `extended` for 256-color and RGB.
+/// The default colors need none after the reset.
+fn color(out: &mut String, color: Color, base: u8, bright: u8, extended: u8) {
+    match color {
+        Color::Named(named) => match palette_index(named) {
+            Some(i @ 0..8) => _ = write!(out, ";{}", base + i as u8),
+            Some(i) => _ = write!(out, ";{}", bright + i as u8 - 8),
+            None => {}
+        },
+        Color::Indexed(i) => _ = write!(out, ";{extended};5;{i}"),
+        Color::Spec(rgb) => _ = write!(out, ";{extended};2;{};{};{}", rgb.r, rgb.g, rgb.b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alacritty_terminal::event::VoidListener;
+    use alacritty_terminal::index::{Point, Side};
+    use alacritty_terminal::selection::{Selection, SelectionType};
+    use alacritty_terminal::term::Config;
+    use alacritty_terminal::vte::ansi::{Processor, StdSyncHandler};
+
+    use super::*;
+    use crate::terminal::TerminalSize;
+
+    fn term(cols: usize) -> Term<VoidListener> {
+        Term::new(Config::default(), &TerminalSize::new(cols, 6), VoidListener)
+    }
+
+    fn print(term: &mut Term<VoidListener>, text: &str) {
+        let mut parser: Processor<StdSyncHandler> = Processor::new();
+        parser.advance(term, text.as_bytes());
+    }
+
+    fn select_all(term: &mut Term<VoidListener>, ty: SelectionType, end: Point) {
+        let mut sel = Selection::new(ty, Point::new(Line(0), Column(0)), Side::Left);
+        sel.update(end, Side::Right);
+        term.selection = Some(sel);
+    }
+
+    fn cells(term: &Term<VoidListener>, line: i32, cols: usize) -> Vec<(char, Style)> {
+        (0..cols)
+            .map(|c| {
+                let cell = &term.grid()[Line(line)][Column(c)];
+                (cell.c, Style { fg: cell.fg, bg: cell.bg, flags: cell.flags & STYLE_FLAGS })
+            })
+            .collect()
+    }
+
+    const SAMPLE: &str = "plain \x1b[1;31mbold red\x1b[0m \x1b[38;5;208;48;2;1;2;3morange\x1b[0m\r\n\
+                          \x1b[3;4;92mitalic\x1b[0m \x1b[7minv\x1b[0m \x1b[9;2mgone\x1b[0m";
+
+    #[test]
+    fn ansi_copy_round_trips_cell_styles() {
+        let mut source = term(40);
+        print(&mut source, SAMPLE);
+        select_all(&mut source, SelectionType::Simple, Point::new(Line(1), Column(39)));
+        let ansi = to_ansi(&styled_selection(&mut source).unwrap());
+
+        let mut copy = term(40);
+        print(&mut copy, &ansi.replace('\n', "\r\n"));
+        for line in 0..2 {
+            assert_eq!(cells(&copy, line, 40), cells(&source, line, 40), "line {line}");
+        }
+    }
+
+    #[test]
+    fn runs_and_sgr_codes() {
+        let mut t = term(40);
+        print(&mut t, "ab\x1b[31mcd\x1b[0m  ");
+        select_all(&mut t, SelectionType::Simple, Point::new(Line(0), Column(39)));
+        let lines = styled_selection(&mut t).unwrap();
+        assert_eq!(lines.len(), 1);
+        let texts: Vec<_> = lines[0].iter().map(|(_, t)| t.as_str()).collect();
+        assert_eq!(texts, ["ab", "cd"]);
+        assert_eq!(to_ansi(&lines), "ab\x1b[0;31mcd\x1b[0m");
+
+        let bright = Style { fg: Color::Named(NamedColor::BrightBlue), ..Style::default() };
+        assert_eq!(to_ansi(&[vec![(bright, "x".into())]]), "\x1b[0;94mx\x1b[0m");
+    }
+
+    #[test]
+    fn soft_wraps_join_and_blocks_keep_columns() {
+        let mut t = term(4);
+        print(&mut t, "abcdef\r\nghij");
+        select_all(&mut t, SelectionType::Simple, Point::new(Line(2), Column(3)));
+        let plain = |lines: Vec<StyledLine>| -> Vec<String> {
+            lines.into_iter().map(|l| l.into_iter().map(|(_, t)| t).collect()).collect()
+        };
+        assert_eq!(plain(styled_selection(&mut t).unwrap()), ["abcdef", "ghij"]);
+
+        let mut sel = Selection::new(SelectionType::Block, Point::new(Line(0), Column(1)), Side::Left);
+        sel.update(Point::new(Line(2), Column(2)), Side::Right);
+        t.selection = Some(sel);
+        assert_eq!(plain(styled_selection(&mut t).unwrap()), ["bc", "f", "hi"]);
+    }
+}
@@ -1,134 +1,963 @@
+use std::cell::Cell;
 use std::collections::HashMap;
+use std::rc::Rc;
 use std::sync::Arc;
-use alacritty_terminal::event::WindowSize;
+use std::time::{Duration, Instant};
+use alacritty_terminal::event::{EventListener, WindowSize};
 use alacritty_terminal::event_loop::{EventLoop as PtyEventLoop, Msg, State as PtyState};
+use alacritty_terminal::grid::Dimensions;
 use alacritty_terminal::sync::FairMutex;
-use alacritty_terminal::term::{Config as TermConfig, Term};
+use alacritty_terminal::term::{Config as TermConfig, Term, TermMode};
 use alacritty_terminal::tty;
+use alacritty_terminal::vte::ansi::ClearMode;
 
-use crate::event::{EventProxy, Notifier};
+use crate::activity::{CommandTimer, TabActivity};
+use crate::bell::PaneBell;
+use crate::config::{self, TerminalConfig, WorkingDirectory, WorkingDirectoryConfig};
+use crate::event::{Backlog, EventProxy, InputClosed, Notifier, PtyInput, PtyResize, PtyShutdown};
+use crate::marks::Marks;
+use crate::process::Foreground;
 use crate::panes::{PaneLayout, PaneTree, Split};
 use crate::terminal::TerminalSize;
+use crate::title::tab_label;
 
-type PtyJoinHandle = std::thread::JoinHandle<(PtyEventLoop<tty::Pty, EventProxy>, PtyState)>;
+/// Turn off alternate scroll (DECSET 1007), which alacritty_terminal starts
+/// with on. Until an app asks for it, the wheel on the alternate screen
+/// sends nothing rather than arrow keys the app may read as something else.
+fn app_scroll_only<L: EventListener>(term: &mut Term<L>) {
+    use alacritty_terminal::vte::ansi::{Handler, NamedPrivateMode};
+    term.unset_private_mode(NamedPrivateMode::AlternateScroll.into());
+}
 
-/// A terminal pane with its own Term + PTY.
-pub struct Pane {
-    pub term: Arc<FairMutex<Term<EventProxy>>>,
-    pub notifier: Notifier,
-    _pty_thread: Option<PtyJoinHandle>,
+/// The PTY a pane's event loop reads. On Unix its output goes through the
+/// tmux passthrough unwrapper first.
+#[cfg(unix)]
+type KoiPty = crate::passthrough::PassthroughPty;
+#[cfg(not(unix))]
+type KoiPty = tty::Pty;
+
+type PtyJoinHandle = std::thread::JoinHandle<(PtyEventLoop<KoiPty, EventProxy>, PtyState)>;
+
+/// Grid size for a pane of the given pixel size. The single source of truth
+/// for both the Term and the PTY, so the shell's SIGWINCH size always
+/// matches the grid koi draws.
+pub fn pane_grid_size(width: f32, height: f32, cell_width: f32, cell_height: f32) -> (usize, usize) {
+    let cols = (width / cell_width) as usize;
+    let rows = (height / cell_height) as usize;
+    (cols.max(2), rows.max(1))
+}
+
+/// Smallest pane grid that is still usable. Below this in any pane, the
+/// window is too small for its layout.
+pub const MIN_PANE_COLS: usize = 10;
+pub const MIN_PANE_ROWS: usize = 3;
+
+/// Smallest grid a divider drag or a split may leave a pane with. Any less
+/// and the grid, which never goes under 2x1, would spill past the pane.
+pub const MIN_SPLIT_COLS: usize = 4;
+pub const MIN_SPLIT_ROWS: usize = 2;
+
+/// Most input the PTY thread may have waiting before a paste holds off.
+const MAX_INPUT_BACKLOG: usize = 4 * crate::paste::CHUNK;
+
+/// True if every layout holds at least the minimum pane grid.
+pub fn layouts_fit(layouts: &[PaneLayout], cell_width: f32, cell_height: f32) -> bool {
+    layouts.iter().all(|l| {
+        let cols = (l.width / cell_width) as usize;
+        let rows = (l.height / cell_height) as usize;
+        cols >= MIN_PANE_COLS && rows >= MIN_PANE_ROWS
+    })
+}
+
+/// The pixel size of a `MIN_SPLIT_COLS` x `MIN_SPLIT_ROWS` grid.
+fn min_pane_size(cell_width: f32, cell_height: f32) -> (f32, f32) {
+    (MIN_SPLIT_COLS as f32 * cell_width, MIN_SPLIT_ROWS as f32 * cell_height)
+}
+
+fn window_size(cols: usize, rows: usize, cell_width: f32, cell_height: f32) -> WindowSize {
+    WindowSize {
+        num_lines: rows as u16,
+        num_cols: cols as u16,
+        cell_width: cell_width as u16,
+        cell_height: cell_height as u16,
+    }
+}
+
+/// Drop a Term's scrollback history. On the alternate screen that history
+/// belongs to the primary grid behind it, which `Term` only reaches through
+/// its history limit, so that is set to nothing and back to `config`'s.
+fn clear_history<L: EventListener>(term: &mut Term<L>, config: &TermConfig) {
+    use alacritty_terminal::vte::ansi::Handler;
+    if term.mode().contains(TermMode::ALT_SCREEN) {
+        term.set_options(TermConfig { scrolling_history: 0, ..config.clone() });
+        term.set_options(config.clone());
+    } else {
+        term.clear_screen(ClearMode::Saved);
+    }
+}
+
+/// Resize one pane's Term and PTY to fit `layout`.
+fn resize_pane<L: EventListener>(
+    term: &FairMutex<Term<L>>,
+    pty: &impl PtyResize,
+    layout: &PaneLayout,
+    cell_width: f32,
+    cell_height: f32,
+) {
+    let (cols, rows) = pane_grid_size(layout.width, layout.height, cell_width, cell_height);
+    let mut term = term.lock();
+    term.resize(TerminalSize::new(cols, rows));
+    crate::selection::revalidate(&mut term);
+    drop(term);
+    pty.send_resize(window_size(cols, rows, cell_width, cell_height));
+}
+
+/// Resize the panes `tree` lays out in `width` x `height` to their layouts,
+/// looking each up with `pane`. Skipped while a split or close is still
+/// animating, so shells get one SIGWINCH for the new layout instead of one
+/// per frame; returns whether the panes were resized.
+fn resize_panes<'a, L: EventListener + 'a, P: PtyResize + 'a>(
+    tree: &PaneTree,
+    pane: impl Fn(usize) -> Option<(&'a FairMutex<Term<L>>, &'a P)>,
+    width: f32,
+    height: f32,
+    cell_width: f32,
+    cell_height: f32,
+) -> bool {
+    if tree.in_transition() {
+        return false;
+    }
+    for layout in &tree.calculate_layouts(width, height) {
+        if let Some((term, pty)) = pane(layout.pane_id) {
+            resize_pane(term, pty, layout, cell_width, cell_height);
+        }
+    }
+    true
+}
+
+/// A pane's shell: the PTY event loop thread and how to reach it.
+pub struct PaneProcess {
+    notifier: Notifier,
+    thread: Option<PtyJoinHandle>,
     #[cfg(unix)]
     shell_pid: u32,
+    /// Duplicate of the PTY master, for foreground process group queries.
+    #[cfg(unix)]
+    pty_master: std::fs::File,
+}
+
+impl PaneProcess {
+    /// A process with no PTY thread, whose input and resizes all go to
+    /// `channel`. It takes input off `backlog` as it writes it.
+    #[cfg(test)]
+    pub fn detached(channel: impl crate::event::PtyChannel + 'static, backlog: Backlog) -> Self {
+        PaneProcess {
+            notifier: Notifier(Box::new(channel), backlog),
+            thread: None,
+            #[cfg(unix)]
+            shell_pid: 0,
+            #[cfg(unix)]
+            pty_master: std::fs::File::open("/dev/null").expect("open /dev/null"),
+        }
+    }
+}
+
+/// Starts what runs behind each new pane: `PtyLauncher` in koi itself,
+/// a stand-in in tests.
+pub trait Launcher {
+    /// The process feeding `term`, at `size` and started per `opts`.
+    fn launch(
+        &self,
+        terminal: &TerminalConfig,
+        term: &Arc<FairMutex<Term<EventProxy>>>,
+        proxy: EventProxy,
+        size: WindowSize,
+        opts: &tty::Options,
+    ) -> PaneProcess;
+}
+
+/// Starts a shell on a new PTY, with its own event loop thread.
+pub struct PtyLauncher;
+
+impl Launcher for PtyLauncher {
+    fn launch(
+        &self,
+        terminal: &TerminalConfig,
+        term: &Arc<FairMutex<Term<EventProxy>>>,
+        proxy: EventProxy,
+        size: WindowSize,
+        opts: &tty::Options,
+    ) -> PaneProcess {
+        let pty = tty::new(opts, size, 0).expect("create PTY");
+        #[cfg(unix)]
+        let shell_pid = pty.child().id();
+        #[cfg(unix)]
+        let pty_master = pty.file().try_clone().expect("dup PTY master");
+        let backlog = Backlog::default();
+        #[cfg(unix)]
+        let pty = KoiPty::new(pty, terminal.tmux_passthrough, backlog.clone()).expect("dup PTY master");
+        #[cfg(not(unix))]
+        let _ = terminal;
+
+        let pty_event_loop = PtyEventLoop::new(
+            term.clone(),
+            proxy,
+            pty,
+            false,
+            false,
+        )
+        .expect("create PTY event loop");
+
+        let notifier = Notifier(Box::new(pty_event_loop.channel()), backlog);
+        let pty_thread = pty_event_loop.spawn();
+
+        PaneProcess {
+            notifier,
+            thread: Some(pty_thread),
+            #[cfg(unix)]
+            shell_pid,
+            #[cfg(unix)]
+            pty_master,
+        }
+    }
+}
+
+/// A terminal pane: a Term, and usually the shell on a PTY that feeds it.
+pub struct Pane {
+    pub term: Arc<FairMutex<Term<EventProxy>>>,
+    /// None for a buffer pane (`koi --pager`): its Term is fed from stdin,
+    /// and keys and resizes have nowhere to go.
+    process: Option<PaneProcess>,
+    /// Bell coalescing and flood auto-mute.
+    pub bell: PaneBell,
+    /// Lines marked with Cmd+M.
+    pub marks: Marks,
+    /// How long the current foreground command has been running.
+    pub command: CommandTimer,
+    /// The PTY thread panicked: the grid no longer updates and input goes
+    /// nowhere until the shell is restarted.
+    pub io_crashed: bool,
+    /// Input was refused because the PTY thread has stopped; the next
+    /// `reap_pty_threads` finds out why.
+    input_closed: Cell<bool>,
+    /// The shell's exit code, once its PTY loop has reported it.
+    exit_code: Arc<std::sync::OnceLock<i32>>,
+    /// The last title the shell set (OSC 0/2), to skip repeats of it.
+    title: Option<String>,
+    /// What runs in place of the login shell (`koi -e`), started again on
+    /// restart.
+    program: Option<(String, Vec<String>)>,
+    /// What the terminal was running when last polled, for tabs the shell
+    /// doesn't title and for pane headers.
+    pub foreground: Option<Foreground>,
 }
 
 impl Drop for Pane {
     fn drop(&mut self) {
+        // Already released by `TabManager::shutdown_all`.
+        if self.process.as_ref().is_none_or(|p| p.thread.is_none()) {
+            return;
+        }
+        self.request_shutdown();
+        self.release(false);
+    }
+}
+
+impl PtyShutdown for Pane {
+    fn request_shutdown(&mut self) {
+        let Some(process) = &self.process else { return };
         // On Unix, SIGHUP the shell's whole process group before we join.
         // alacritty_terminal's Pty::drop already SIGHUPs the shell PID, but not
         // the group — so a foreground child like tmux keeps the shell parked in
-        // wait4, which parks Pty::drop's child.wait(), which parks this join()
+        // wait4, which parks Pty::drop's child.wait(), which parks the join()
         // on the main thread. Signaling -pgid delivers SIGHUP to tmux too so
         // the shell can actually return from wait4 and exit.
         #[cfg(unix)]
         {
-            let pid = self.shell_pid as i32;
+            let pid = process.shell_pid as i32;
             if pid > 0 {
                 unsafe { libc::kill(-pid, libc::SIGHUP) };
             }
         }
-        let _ = self.notifier.0.send(Msg::Shutdown);
-        if let Some(handle) = self._pty_thread.take() {
-            let _ = handle.join();
+        let _ = process.notifier.0.send(Msg::Shutdown);
+    }
+
+    fn is_finished(&self) -> bool {
+        let thread = self.process.as_ref().and_then(|p| p.thread.as_ref());
+        thread.is_none_or(|handle| handle.is_finished())
+    }
+
+    fn release(&mut self, detach: bool) {
+        if let Some(handle) = self.process.as_mut().and_then(|p| p.thread.take()) {
+            if !detach {
+                let _ = handle.join();
+            }
+        }
+    }
+}
+
+impl PtyInput for Pane {
+    fn write(&self, bytes: Vec<u8>) -> Result<(), InputClosed> {
+        let Some(process) = &self.process else { return Ok(()) };
+        let sent = process.notifier.send_bytes(bytes);
+        if sent.is_err() {
+            self.input_closed.set(true);
+        }
+        sent
+    }
+
+    fn ready(&self) -> bool {
+        // Only the Unix PTY's writer keeps the backlog.
+        #[cfg(unix)]
+        {
+            use std::os::unix::io::AsRawFd;
+            self.process.as_ref().is_none_or(|p| {
+                p.notifier.1.get() < MAX_INPUT_BACKLOG && crate::process::writable(p.pty_master.as_raw_fd())
+            })
+        }
+        #[cfg(not(unix))]
+        {
+            true
+        }
+    }
+}
+
+impl PtyResize for Pane {
+    fn send_resize(&self, size: WindowSize) {
+        if let Some(process) = &self.process {
+            process.notifier.send_resize(size);
+        }
+    }
+}
+
+/// How a PTY thread that `TabManager::reap_pty_threads` joined ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PtyEnd {
+    /// It returned: the shell is gone, but no `ChildExit` said so.
+    Exited,
+    /// It panicked.
+    Crashed,
+}
+
+/// Join `thread` if it has finished, taking it. Err holds the message of
+/// the panic it died with.
+fn reap<T>(thread: &mut Option<std::thread::JoinHandle<T>>) -> Option<Result<(), String>> {
+    if !thread.as_ref()?.is_finished() {
+        return None;
+    }
+    let result = thread.take()?.join();
+    Some(result.map(drop).map_err(|payload| {
+        payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic payload".into())
+    }))
+}
+
+/// Shut down every pane in `panes` at once, giving their PTY threads until
+/// `timeout` to exit. Threads still running then are detached rather than
+/// holding up quit. Returns how many were.
+pub fn shutdown_panes<'a, P: PtyShutdown + 'a>(
+    panes: impl IntoIterator<Item = &'a mut P>,
+    timeout: Duration,
+) -> usize {
+    let mut panes: Vec<_> = panes.into_iter().collect();
+    for pane in &mut panes {
+        pane.request_shutdown();
+    }
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline && !panes.iter().all(|p| p.is_finished()) {
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    let mut stuck = 0;
+    for pane in panes {
+        let detach = !pane.is_finished();
+        stuck += detach as usize;
+        pane.release(detach);
+    }
+    stuck
+}
+
+impl Pane {
+    /// Input for the shell. Dropped by a buffer pane.
+    pub fn send_input(&self, data: &[u8]) {
+        let _ = self.write(data.to_vec());
+    }
+
+    /// Owned input for the shell, sent without copying.
+    pub fn send_bytes(&self, data: Vec<u8>) {
+        let _ = self.write(data);
+    }
+
+    /// Input was refused by a PTY thread that `reap_pty_threads` hasn't
+    /// dealt with yet.
+    pub fn input_lost(&self) -> bool {
+        self.input_closed.get()
+            && !self.io_crashed
+            && self.exit_code.get().is_none()
+            && self.process.as_ref().is_some_and(|p| p.thread.is_some())
+    }
+
+    /// True for a pane with no shell, showing piped input (`koi --pager`).
+    pub fn is_buffer(&self) -> bool {
+        self.process.is_none()
+    }
+
+    /// Ask the PTY thread to stop, without waiting for it.
+    fn stop(&self) {
+        if let Some(process) = &self.process {
+            let _ = process.notifier.0.send(Msg::Shutdown);
+        }
+    }
+
+    /// Hard-reset the terminal (RIS) without touching the shell process.
+    ///
+    /// Clears everything an app can leave wedged after crashing mid-session:
+    /// mouse reporting left on (the common "scrolling stopped working" case),
+    /// a stuck alternate screen, bracketed paste / app-cursor / app-keypad
+    /// modes, a shrunken scroll region, and a line-drawing charset. Scrollback
+    /// and the screen are wiped too. A resize nudge follows so a full-screen
+    /// app still in the foreground repaints itself.
+    pub fn reset(&self, cell_width: f32, cell_height: f32) {
+        use alacritty_terminal::vte::ansi::Handler;
+        let mut term = self.term.lock();
+        term.reset_state();
+        app_scroll_only(&mut term);
+        term.clear_screen(ClearMode::Saved);
+        let (cols, rows) = (term.columns(), term.screen_lines());
+        drop(term);
+        self.nudge_resize(cols, rows, cell_width, cell_height);
+    }
+
+    /// Drop the scrollback history, keeping the visible screen. Works while a
+    /// full-screen app owns the pane since it only touches koi's own grid.
+    /// `config` is the pane's `Term` configuration, put back afterwards.
+    pub fn clear_scrollback(&self, config: &TermConfig, cell_width: f32, cell_height: f32) {
+        let mut term = self.term.lock();
+        clear_history(&mut term, config);
+        let (cols, rows) = (term.columns(), term.screen_lines());
+        drop(term);
+        self.nudge_resize(cols, rows, cell_width, cell_height);
+    }
+
+    /// Wipe the screen and the scrollback history, cursor home. The shell,
+    /// if it's in the foreground, gets Ctrl+L to draw its prompt again;
+    /// anything else gets a resize nudge to repaint.
+    pub fn clear(&self, config: &TermConfig, cell_width: f32, cell_height: f32) {
+        use alacritty_terminal::vte::ansi::Handler;
+        let mut term = self.term.lock();
+        // Clearing the screen scrolls it into history, so that goes second.
+        term.clear_screen(ClearMode::All);
+        clear_history(&mut term, config);
+        term.goto(0, 0);
+        let (cols, rows) = (term.columns(), term.screen_lines());
+        drop(term);
+        if self.shell_in_foreground() == Some(true) {
+            self.send_input(b"\x0c");
+        } else {
+            self.nudge_resize(cols, rows, cell_width, cell_height);
+        }
+    }
+
+    /// Send a transient one-row-shorter size followed by the real one. The
+    /// kernel only raises SIGWINCH when the size actually changes, so this is
+    /// how a foreground app is coaxed into a full repaint.
+    fn nudge_resize(&self, cols: usize, rows: usize, cell_width: f32, cell_height: f32) {
+        let size = |lines: usize| window_size(cols, lines, cell_width, cell_height);
+        if rows > 1 {
+            self.send_resize(size(rows - 1));
+        }
+        self.send_resize(size(rows));
+    }
+
+    /// The directory of the pane's foreground process, or of its shell if
+    /// that can't be read. None for buffer panes and where the platform
+    /// can't tell.
+    pub fn cwd(&self) -> Option<std::path::PathBuf> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::io::AsRawFd;
+            let process = self.process.as_ref()?;
+            crate::process::foreground_pgid(process.pty_master.as_raw_fd())
+                .and_then(crate::process::cwd)
+                .or_else(|| crate::process::cwd(process.shell_pid as i32))
+        }
+        #[cfg(not(unix))]
+        {
+            None
+        }
+    }
+
+    /// The terminal's foreground process: its name and directory. None for
+    /// buffer panes and where the platform can't tell.
+    fn look_up_foreground(&self) -> Option<Foreground> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::io::AsRawFd;
+            let process = self.process.as_ref()?;
+            let pgid = crate::process::foreground_pgid(process.pty_master.as_raw_fd())?;
+            let name = crate::process::name(pgid)?;
+            Some(Foreground { name, cwd: crate::process::cwd(pgid) })
+        }
+        #[cfg(not(unix))]
+        {
+            None
+        }
+    }
+
+    /// Whether the shell itself (rather than a job it launched) owns the
+    /// terminal's foreground. None when that can't be determined.
+    pub fn shell_in_foreground(&self) -> Option<bool> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::io::AsRawFd;
+            let process = self.process.as_ref()?;
+            crate::process::foreground_pgid(process.pty_master.as_raw_fd())
+                .map(|pgid| pgid == process.shell_pid as i32)
+        }
+        #[cfg(not(unix))]
+        {
+            None
         }
     }
+
+    /// Description of modes a crashed app left set now that the shell is back
+    /// in the foreground, if any. A pane running a program rather than the
+    /// shell has no prompt to come back to: the program owns its modes.
+    pub fn stuck_modes(&self) -> Option<&'static str> {
+        if self.program.is_some() {
+            return None;
+        }
+        let mode = *self.term.lock().mode();
+        // Cheap check first: skip the syscall in the common all-clear case.
+        if !mode.intersects(TermMode::MOUSE_MODE | TermMode::ALT_SCREEN) {
+            return None;
+        }
+        crate::process::stuck_modes(mode, self.shell_in_foreground()?)
+    }
+}
+
+/// What a title from a pane changed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Retitle {
+    /// The pane sent the title its tab already has, or is gone.
+    Unchanged,
+    /// Tab `tab` took the title; `label_changed` if the tab bar shows it
+    /// and the visible part of the title differs.
+    Changed { tab: usize, label_changed: bool },
+}
+
+/// Record `title` as a pane's latest and its tab's title. None if the tab
+/// already had it; otherwise whether the first `label_chars` characters
+/// of the tab's title changed.
+fn retitle(pane_title: &mut Option<String>, tab_title: &mut String, title: String, label_chars: Option<usize>) -> Option<bool> {
+    if *tab_title == title {
+        *pane_title = Some(title);
+        return None;
+    }
+    let label_changed = label_chars.is_some_and(|n| tab_label(tab_title, n) != tab_label(&title, n));
+    *pane_title = Some(title.clone());
+    *tab_title = title;
+    Some(label_changed)
+}
+
+/// Where the tab at index `i` ends up when the tab at `from` moves to `to`.
+fn moved_index(i: usize, from: usize, to: usize) -> usize {
+    if i == from {
+        to
+    } else if from < i && i <= to {
+        i - 1
+    } else if to <= i && i < from {
+        i + 1
+    } else {
+        i
+    }
+}
+
+/// Where pane ids come from: one counter for every window, so a pane's
+/// events can find the window it belongs to.
+#[derive(Clone, Debug, Default)]
+pub struct PaneIds(Rc<Cell<usize>>);
+
+impl PaneIds {
+    fn next(&self) -> usize {
+        let id = self.0.get();
+        self.0.set(id + 1);
+        id
+    }
+}
+
+/// What a window's first pane runs.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum FirstPane {
+    /// The login shell.
+    #[default]
+    Shell,
+    /// Nothing: the pane shows koi's stdin (`koi --pager`).
+    Stdin,
+    /// A program and its arguments (`koi -e program args...`).
+    Command(String, Vec<String>),
+}
+
+impl FirstPane {
+    /// What koi's command line asks for. Everything after `-e` belongs to
+    /// the program, so its own flags aren't taken for koi's.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Self {
+        let mut first = FirstPane::Shell;
+        let mut args = args.into_iter().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--pager" => first = FirstPane::Stdin,
+                "-e" => {
+                    let Some(program) = args.next() else {
+                        log::warn!("-e needs a program to run; starting the shell");
+                        break;
+                    };
+                    return FirstPane::Command(program, args.collect());
+                }
+                _ => {}
+            }
+        }
+        first
+    }
+}
+
+/// Whether koi's command line has `flag` before any `-e`: like
+/// `FirstPane::from_args`, the arguments after it are the program's.
+pub fn has_flag(args: impl IntoIterator<Item = String>, flag: &str) -> bool {
+    args.into_iter().skip(1).take_while(|arg| arg != "-e").any(|arg| arg == flag)
 }
 
 /// A tab containing a tree of panes.
 pub struct Tab {
+    /// Stays the same while tabs open, close and move around, unlike the
+    /// tab's index.
+    pub id: usize,
+    /// The last title a pane in the tab set, or "Tab N" until one does.
     pub title: String,
+    /// A name the user gave the tab, which titles from the shell don't
+    /// replace.
+    pub user_title: Option<String>,
     pub pane_tree: PaneTree,
     pub panes: HashMap<usize, Pane>,
+    pub activity: TabActivity,
+    /// Typing and pastes go to every pane in the tab, not just the
+    /// focused one.
+    pub broadcast: bool,
+}
+
+impl Tab {
+    /// The tab's name in the tab bar: the user's, else the last title a
+    /// pane set, else what the focused pane is running.
+    pub fn label(&self) -> &str {
+        if let Some(title) = &self.user_title {
+            return title;
+        }
+        let untitled = self.panes.values().all(|p| p.title.is_none());
+        let running = self.panes.get(&self.pane_tree.active_pane_id()).and_then(|p| p.foreground.as_ref());
+        match running.filter(|_| untitled) {
+            Some(fg) => &fg.name,
+            None => &self.title,
+        }
+    }
 }
 
 pub struct TabManager {
     tabs: Vec<Tab>,
     active: usize,
-    next_pane_id: usize,
+    pane_ids: PaneIds,
+    next_tab_id: usize,
+    /// This window's id, for `KOI_WINDOW_ID`.
+    window_id: u64,
+    working_directory: WorkingDirectoryConfig,
+    terminal: TerminalConfig,
+    /// Animate splits and closes (`window.pane_animation`).
+    pane_animation: bool,
+    /// History lines each new pane keeps (`scrolling.lines`).
+    scrollback_lines: usize,
+    /// What ends a double-click's word (`selection.semantic_escape_chars`).
+    semantic_escape_chars: String,
+    /// Split panes get a header strip (`window.pane_headers`).
+    pane_headers: bool,
+    /// Its height: a cell's.
+    cell_height: f32,
+    /// `MIN_SPLIT_COLS` x `MIN_SPLIT_ROWS` in pixels at the current cell
+    /// size, for new tabs' pane trees.
+    min_pane: (f32, f32),
+    launcher: Box<dyn Launcher>,
 }
 
 impl TabManager {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         cols: usize,
         rows: usize,
         cell_width: f32,
         cell_height: f32,
         event_proxy: &EventProxy,
+        working_directory: WorkingDirectoryConfig,
+        terminal: TerminalConfig,
+        scrollback_lines: usize,
+        pane_animation: bool,
+        first: FirstPane,
+        window_id: u64,
+        pane_ids: PaneIds,
+        launcher: Box<dyn Launcher>,
     ) -> Self {
         let mut mgr = TabManager {
             tabs: Vec::new(),
             active: 0,
-            next_pane_id: 0,
+            pane_ids,
+            next_tab_id: 0,
+            window_id,
+            working_directory,
+            terminal,
+            pane_animation,
+            scrollback_lines,
+            semantic_escape_chars: alacritty_terminal::term::SEMANTIC_ESCAPE_CHARS.to_string(),
+            pane_headers: false,
+            cell_height,
+            min_pane: min_pane_size(cell_width, cell_height),
+            launcher,
         };
-        mgr.add_tab(cols, rows, cell_width, cell_height, event_proxy);
+        let program = match first {
+            FirstPane::Stdin => {
+                let (pane_id, pane) = mgr.spawn_stdin_pane(cols, rows, event_proxy);
+                mgr.push_pane_tab(pane_id, pane);
+                mgr.tabs[0].title = "stdin".into();
+                return mgr;
+            }
+            FirstPane::Shell => None,
+            FirstPane::Command(program, args) => Some((program, args)),
+        };
+        let cwd = mgr.start_dir(&mgr.working_directory.new_window);
+        mgr.push_tab(cols, rows, cell_width, cell_height, event_proxy, cwd, program);
         mgr
     }
 
+    /// Resolve a `working_directory` setting for a pane about to spawn.
+    /// "inherit" takes the focused pane's directory, falling back to home
+    /// when there's no pane yet or its directory can't be read.
+    fn start_dir(&self, setting: &WorkingDirectory) -> Option<std::path::PathBuf> {
+        let inherited = match setting {
+            WorkingDirectory::Inherit => self.active_pane().and_then(Pane::cwd),
+            _ => None,
+        };
+        setting.resolve(inherited.as_deref(), config::home_dir().as_deref())
+    }
+
+    #[allow(clippy::too_many_arguments)]
     fn spawn_pane(
         &mut self,
+        tab_id: usize,
         cols: usize,
         rows: usize,
         cell_width: f32,
         cell_height: f32,
         event_proxy: &EventProxy,
+        working_directory: Option<std::path::PathBuf>,
     ) -> (usize, Pane) {
-        let id = self.next_pane_id;
-        self.next_pane_id += 1;
+        self.spawn_pane_with_command(tab_id, cols, rows, cell_width, cell_height, event_proxy, working_directory, None)
+    }
+
+    /// Start a pane running `program` with its arguments, or the login
+    /// shell if None.
+    #[allow(clippy::too_many_arguments)]
+    fn spawn_pane_with_command(
+        &mut self,
+        tab_id: usize,
+        cols: usize,
+        rows: usize,
+        cell_width: f32,
+        cell_height: f32,
+        event_proxy: &EventProxy,
+        working_directory: Option<std::path::PathBuf>,
+        program: Option<(String, Vec<String>)>,
+    ) -> (usize, Pane) {
+        let id = self.pane_ids.next();
+        let pty_opts = self.pty_options(tab_id, id, working_directory, program.clone());
+        let mut pane = self.start_shell(id, cols, rows, cell_width, cell_height, event_proxy, &pty_opts);
+        pane.program = program;
+        (id, pane)
+    }
+
+    /// The `Term` configuration for a new pane, with the configured
+    /// scrollback and word boundaries.
+    pub fn term_config(&self) -> TermConfig {
+        TermConfig {
+            scrolling_history: self.scrollback_lines,
+            semantic_escape_chars: self.semantic_escape_chars.clone(),
+            ..self.terminal.term_config()
+        }
+    }
+
+    /// Change what ends a double-click's word, in open panes too.
+    pub fn set_semantic_escape_chars(&mut self, chars: &str) {
+        if chars == self.semantic_escape_chars {
+            return;
+        }
+        self.semantic_escape_chars = chars.to_string();
+        let config = self.term_config();
+        for pane in self.tabs.iter().flat_map(|tab| tab.panes.values()) {
+            pane.term.lock().set_options(config.clone());
+        }
+    }
 
+    /// How to start the shell for pane `pane_id` in tab `tab_id`: in
+    /// `working_directory`, and told where it is running.
+    fn pty_options(
+        &self,
+        tab_id: usize,
+        pane_id: usize,
+        working_directory: Option<std::path::PathBuf>,
+        program: Option<(String, Vec<String>)>,
+    ) -> tty::Options {
+        let env = [
+            ("KOI_WINDOW_ID", self.window_id.to_string()),
+            ("KOI_TAB_ID", tab_id.to_string()),
+            ("KOI_PANE_ID", pane_id.to_string()),
+        ];
+        tty::Options {
+            shell: program.map(|(program, args)| tty::Shell::new(program, args)),
+            working_directory,
+            env: env.into_iter().map(|(k, v)| (k.to_string(), v)).collect(),
+            ..tty::Options::default()
+        }
+    }
+
+    /// A shell on a new PTY, in a pane numbered `id`.
+    #[allow(clippy::too_many_arguments)]
+    fn start_shell(
+        &self,
+        id: usize,
+        cols: usize,
+        rows: usize,
+        cell_width: f32,
+        cell_height: f32,
+        event_proxy: &EventProxy,
+        pty_opts: &tty::Options,
+    ) -> Pane {
         let pane_proxy = event_proxy.with_pane_id(id);
 
         let term_size = TerminalSize::new(cols, rows);
-        let term = Term::new(TermConfig::default(), &term_size, pane_proxy.clone());
+        let mut term = Term::new(self.term_config(), &term_size, pane_proxy.clone());
+        app_scroll_only(&mut term);
         let term = Arc::new(FairMutex::new(term));
 
-        let window_size = WindowSize {
-            num_lines: rows as u16,
-            num_cols: cols as u16,
-            cell_width: cell_width as u16,
-            cell_height: cell_height as u16,
-        };
-        let pty_opts = tty::Options {
-            working_directory: std::env::var_os("HOME").map(std::path::PathBuf::from),
-            ..tty::Options::default()
+        let window_size = window_size(cols, rows, cell_width, cell_height);
+        let exit_code = pane_proxy.exit_code();
+        let process = self.launcher.launch(&self.terminal, &term, pane_proxy, window_size, pty_opts);
+
+        Pane {
+            term,
+            process: Some(process),
+            bell: PaneBell::default(),
+            marks: Marks::default(),
+            command: CommandTimer::default(),
+            io_crashed: false,
+            input_closed: Cell::new(false),
+            exit_code,
+            title: None,
+            foreground: None,
+            program: None,
+        }
+    }
+
+    /// Replace the active pane's shell, or the program it ran, with a new
+    /// one of the same size, started per `working_directory.new_tab`, under
+    /// a new pane id. The old one is hung up. Buffer panes are left alone.
+    pub fn restart_active_pane(&mut self, cell_width: f32, cell_height: f32, event_proxy: &EventProxy) {
+        let Some(old_id) = self.active_pane_id() else { return };
+        let Some(old) = self.active_pane().filter(|p| !p.is_buffer()) else { return };
+        let (cols, rows) = {
+            let term = old.term.lock();
+            (term.columns(), term.screen_lines())
         };
-        let pty = tty::new(&pty_opts, window_size, 0).expect("create PTY");
-        #[cfg(unix)]
-        let shell_pid = pty.child().id();
+        // A fresh id, so late events from the old shell can't reach the
+        // new one.
+        let id = self.pane_ids.next();
+        let program = old.program.clone();
+        let cwd = self.start_dir(&self.working_directory.new_tab);
+        let pty_opts = self.pty_options(self.tabs[self.active].id, id, cwd, program.clone());
+        let mut pane = self.start_shell(id, cols, rows, cell_width, cell_height, event_proxy, &pty_opts);
+        pane.program = program;
+        log::info!("Pane {} restarted as pane {}", old_id, id);
+        let tab = &mut self.tabs[self.active];
+        tab.panes.remove(&old_id);
+        tab.panes.insert(id, pane);
+        tab.pane_tree.renumber_active(id);
+    }
 
-        let pty_event_loop = PtyEventLoop::new(
-            term.clone(),
-            pane_proxy,
-            pty,
-            false,
-            false,
-        )
-        .expect("create PTY event loop");
+    /// Join the PTY threads that have stopped while their pane is still
+    /// open. Normally a shell's exit arrives as `ChildExit` and closes the
+    /// pane first; a thread that ends without one either panicked, leaving
+    /// the pane frozen, or saw the shell die with no exit status.
+    pub fn reap_pty_threads(&mut self) -> Vec<(usize, PtyEnd)> {
+        let mut ended = Vec::new();
+        for (&id, pane) in self.tabs.iter_mut().flat_map(|t| t.panes.iter_mut()) {
+            let Some(process) = &mut pane.process else { continue };
+            match reap(&mut process.thread) {
+                // Refusing input means the loop is on its way out; until it
+                // is joined, the pane is as dead as if it had crashed. Not
+                // if the shell's exit was reported: that closes the pane.
+                None if pane.input_closed.get() && !pane.io_crashed && pane.exit_code.get().is_none() => {
+                    log::error!("Pane {}: PTY thread stopped taking input", id);
+                    pane.io_crashed = true;
+                    ended.push((id, PtyEnd::Crashed));
+                }
+                None => {}
+                Some(Ok(())) => {
+                    log::warn!("Pane {}: PTY thread exited without an exit status", id);
+                    ended.push((id, PtyEnd::Exited));
+                }
+                Some(Err(message)) => {
+                    log::error!("Pane {}: terminal I/O thread panicked: {}", id, message);
+                    pane.io_crashed = true;
+                    ended.push((id, PtyEnd::Crashed));
+                }
+            }
+        }
+        ended
+    }
 
-        let notifier = Notifier(pty_event_loop.channel());
-        let pty_thread = pty_event_loop.spawn();
+    /// A buffer pane showing koi's stdin (`koi --pager`), fed by a reader
+    /// thread. The thread is left to finish on its own: it may be blocked
+    /// reading a pipe that never closes.
+    fn spawn_stdin_pane(&mut self, cols: usize, rows: usize, event_proxy: &EventProxy) -> (usize, Pane) {
+        let id = self.pane_ids.next();
 
-        (
-            id,
-            Pane {
-                term,
-                notifier,
-                _pty_thread: Some(pty_thread),
-                #[cfg(unix)]
-                shell_pid,
-            },
-        )
+        let pane_proxy = event_proxy.with_pane_id(id);
+        let mut term = Term::new(self.term_config(), &TerminalSize::new(cols, rows), pane_proxy.clone());
+        app_scroll_only(&mut term);
+        let term = Arc::new(FairMutex::new(term));
+        let reader_term = term.clone();
+        let spawned = std::thread::Builder::new().name("stdin reader".into()).spawn(move || {
+            if let Err(e) = crate::pager::feed(std::io::stdin().lock(), &reader_term, &pane_proxy) {
+                log::error!("Reading stdin: {}", e);
+            }
+        });
+        if let Err(e) = spawned {
+            log::error!("Can't start the stdin reader: {}", e);
+        }
+
+        let pane = Pane {
+            term,
+            process: None,
+            bell: PaneBell::default(),
+            marks: Marks::default(),
+            command: CommandTimer::default(),
+            io_crashed: false,
+            input_closed: Cell::new(false),
+            exit_code: Arc::default(),
+            title: None,
+            foreground: None,
+            program: None,
+        };
+        (id, pane)
     }
 
-    /// Add a new tab with one pane.
+    /// Add a new tab with one pane, started per `working_directory.new_tab`.
     pub fn add_tab(
         &mut self,
         cols: usize,
@@ -137,15 +966,44 @@ impl TabManager {
         cell_height: f32,
         event_proxy: &EventProxy,
     ) -> usize {
-        let (pane_id, pane) = self.spawn_pane(cols, rows, cell_width, cell_height, event_proxy);
+        let cwd = self.start_dir(&self.working_directory.new_tab);
+        self.push_tab(cols, rows, cell_width, cell_height, event_proxy, cwd, None)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn push_tab(
+        &mut self,
+        cols: usize,
+        rows: usize,
+        cell_width: f32,
+        cell_height: f32,
+        event_proxy: &EventProxy,
+        cwd: Option<std::path::PathBuf>,
+        program: Option<(String, Vec<String>)>,
+    ) -> usize {
+        let tab_id = self.next_tab_id;
+        let (pane_id, pane) =
+            self.spawn_pane_with_command(tab_id, cols, rows, cell_width, cell_height, event_proxy, cwd, program);
+        self.push_pane_tab(pane_id, pane)
+    }
 
+    fn push_pane_tab(&mut self, pane_id: usize, pane: Pane) -> usize {
         let mut panes = HashMap::new();
         panes.insert(pane_id, pane);
 
+        let id = self.next_tab_id;
+        self.next_tab_id += 1;
+        let mut pane_tree = PaneTree::new(pane_id);
+        pane_tree.set_min_pane_size(self.min_pane.0, self.min_pane.1);
+        pane_tree.set_header_height(self.header_height());
         let tab = Tab {
+            id,
             title: format!("Tab {}", self.tabs.len() + 1),
-            pane_tree: PaneTree::new(pane_id),
+            user_title: None,
+            pane_tree,
             panes,
+            activity: TabActivity::default(),
+            broadcast: false,
         };
 
         self.tabs.push(tab);
@@ -154,25 +1012,40 @@ impl TabManager {
         idx
     }
 
+    /// Shut down every pane in every tab before quitting, waiting up to
+    /// `timeout` for their PTY threads. Returns how many didn't exit in time.
+    pub fn shutdown_all(&mut self, timeout: Duration) -> usize {
+        shutdown_panes(self.tabs.iter_mut().flat_map(|t| t.panes.values_mut()), timeout)
+    }
+
     /// Close the active tab.
     pub fn close_active(&mut self) -> bool {
+        self.close_tab(self.active)
+    }
+
+    /// Close the tab at `index` and shut down its panes. Returns true if
+    /// it was the last tab, which is left in place for koi to quit with.
+    pub fn close_tab(&mut self, index: usize) -> bool {
         if self.tabs.len() <= 1 {
             // Shutdown all panes in the last tab
             if let Some(tab) = self.tabs.first() {
                 for pane in tab.panes.values() {
-                    let _ = pane.notifier.0.send(Msg::Shutdown);
+                    pane.stop();
                 }
             }
             return true;
         }
+        if index >= self.tabs.len() {
+            return false;
+        }
 
-        let tab = self.tabs.remove(self.active);
+        let tab = self.tabs.remove(index);
         for pane in tab.panes.values() {
-            let _ = pane.notifier.0.send(Msg::Shutdown);
+            pane.stop();
         }
 
-        if self.active >= self.tabs.len() {
-            self.active = self.tabs.len() - 1;
+        if index < self.active || self.active >= self.tabs.len() {
+            self.active -= 1;
         }
         false
     }
@@ -199,18 +1072,118 @@ impl TabManager {
         }
     }
 
+    /// Move the tab at `from` to index `to`, shifting the tabs between
+    /// along by one. The active tab stays active wherever it ends up.
+    pub fn move_tab(&mut self, from: usize, to: usize) {
+        if from >= self.tabs.len() || to >= self.tabs.len() || from == to {
+            return;
+        }
+        let tab = self.tabs.remove(from);
+        self.tabs.insert(to, tab);
+        self.active = moved_index(self.active, from, to);
+    }
+
     pub fn active_tab(&self) -> Option<&Tab> {
         self.tabs.get(self.active)
     }
 
-    /// Set the title of the tab containing the given pane.
-    pub fn set_tab_title_by_pane(&mut self, pane_id: usize, title: String) {
-        for tab in &mut self.tabs {
-            if tab.panes.contains_key(&pane_id) {
-                tab.title = title;
-                return;
+    /// The last title `pane_id` set, if it has set one.
+    pub fn title_for_pane(&self, pane_id: usize) -> Option<&str> {
+        self.pane(pane_id)?.title.as_deref()
+    }
+
+    /// Make `title`, just set by `pane_id`, the title of its tab. A title
+    /// the tab already has changes nothing. `label_chars` is how much of
+    /// a title the tab bar shows, None while it is hidden. A tab the user
+    /// named keeps showing that name.
+    pub fn set_tab_title_by_pane(&mut self, pane_id: usize, title: String, label_chars: Option<usize>) -> Retitle {
+        for (index, tab) in self.tabs.iter_mut().enumerate() {
+            if let Some(pane) = tab.panes.get_mut(&pane_id) {
+                let label_chars = label_chars.filter(|_| tab.user_title.is_none());
+                return match retitle(&mut pane.title, &mut tab.title, title, label_chars) {
+                    Some(label_changed) => Retitle::Changed { tab: index, label_changed },
+                    None => Retitle::Unchanged,
+                };
             }
         }
+        Retitle::Unchanged
+    }
+
+    /// Name the tab with id `tab_id`, or with None go back to showing the
+    /// shell's title.
+    pub fn set_user_title(&mut self, tab_id: usize, title: Option<String>) {
+        if let Some(tab) = self.tabs.iter_mut().find(|t| t.id == tab_id) {
+            tab.user_title = title;
+        }
+    }
+
+    /// Turn broadcasting input to the active tab's panes on or off.
+    pub fn toggle_broadcast(&mut self) {
+        if let Some(tab) = self.tabs.get_mut(self.active) {
+            tab.broadcast = !tab.broadcast;
+        }
+    }
+
+    /// The panes input meant for `pane_id` goes to: every pane in its tab
+    /// while the tab broadcasts, else just that one. In tree order.
+    pub fn input_panes(&self, pane_id: usize) -> Vec<usize> {
+        match self.tabs.iter().find(|t| t.panes.contains_key(&pane_id)) {
+            Some(tab) if tab.broadcast => tab.pane_tree.pane_ids(),
+            _ => vec![pane_id],
+        }
+    }
+
+    /// Check every pane for a command starting or finishing, and flag
+    /// background tabs where one that ran at least `threshold` ended.
+    /// Returns whether any tab was flagged.
+    pub fn poll_commands(&mut self, threshold: Duration, now: Instant) -> bool {
+        let active = self.active;
+        let mut flagged = false;
+        for (i, tab) in self.tabs.iter_mut().enumerate() {
+            for pane in tab.panes.values_mut() {
+                let Some(shell_in_foreground) = pane.shell_in_foreground() else { continue };
+                if let Some(ran) = pane.command.observe(!shell_in_foreground, now) {
+                    flagged |= tab.activity.command_finished(ran, threshold, i == active);
+                }
+            }
+        }
+        flagged
+    }
+
+    /// Look up what each pane is running. Returns whether any changed.
+    pub fn poll_foreground(&mut self) -> bool {
+        let mut changed = false;
+        for pane in self.panes_mut() {
+            let foreground = pane.look_up_foreground();
+            if pane.foreground != foreground {
+                pane.foreground = foreground;
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// `pane_id` printed something: mark its tab if it's in the
+    /// background. Returns whether that newly marked it.
+    pub fn note_output(&mut self, pane_id: usize) -> bool {
+        let active = self.active;
+        let Some(i) = self.tabs.iter().position(|t| t.panes.contains_key(&pane_id)) else { return false };
+        self.tabs[i].activity.output(i == active)
+    }
+
+    /// `pane_id` rang the bell: mark its tab if it's in the background.
+    /// Returns whether that newly marked it.
+    pub fn note_bell(&mut self, pane_id: usize) -> bool {
+        let active = self.active;
+        let Some(i) = self.tabs.iter().position(|t| t.panes.contains_key(&pane_id)) else { return false };
+        self.tabs[i].activity.bell(i == active)
+    }
+
+    /// The active tab is on screen: clear its activity indicators.
+    pub fn focus_active_tab(&mut self) {
+        if let Some(tab) = self.tabs.get_mut(self.active) {
+            tab.activity.focused();
+        }
     }
 
     pub fn active_index(&self) -> usize {
@@ -225,6 +1198,32 @@ impl TabManager {
         self.tabs.iter()
     }
 
+    /// All panes in all tabs.
+    pub fn panes_mut(&mut self) -> impl Iterator<Item = &mut Pane> {
+        self.tabs.iter_mut().flat_map(|tab| tab.panes.values_mut())
+    }
+
+    /// Some pane's PTY thread refused input, so `reap_pty_threads` shouldn't
+    /// wait for its next scheduled run.
+    pub fn input_lost(&self) -> bool {
+        self.tabs.iter().flat_map(|tab| tab.panes.values()).any(Pane::input_lost)
+    }
+
+    /// Find a pane in any tab.
+    pub fn pane(&self, pane_id: usize) -> Option<&Pane> {
+        self.tabs.iter().find_map(|tab| tab.panes.get(&pane_id))
+    }
+
+    /// Find a pane in any tab.
+    pub fn pane_mut(&mut self, pane_id: usize) -> Option<&mut Pane> {
+        self.tabs.iter_mut().find_map(|tab| tab.panes.get_mut(&pane_id))
+    }
+
+    /// Get the active pane's id (in the active tab).
+    pub fn active_pane_id(&self) -> Option<usize> {
+        self.active_tab().map(|tab| tab.pane_tree.active_pane_id())
+    }
+
     /// Get the active pane (in the active tab).
     pub fn active_pane(&self) -> Option<&Pane> {
         let tab = self.active_tab()?;
@@ -233,6 +1232,7 @@ impl TabManager {
     }
 
     /// Split the active pane in the active tab, then resize all panes to fit.
+    /// Returns false, starting no shell, if the pane is too small to halve.
     #[allow(clippy::too_many_arguments)]
     pub fn split_active(
         &mut self,
@@ -244,13 +1244,29 @@ impl TabManager {
         viewport_width: f32,
         viewport_height: f32,
         event_proxy: &EventProxy,
-    ) {
-        let (new_id, pane) = self.spawn_pane(cols, rows, cell_width, cell_height, event_proxy);
+    ) -> bool {
+        if !self.tabs[self.active].pane_tree.can_split_active(split, viewport_width, viewport_height) {
+            return false;
+        }
+        let cwd = self.start_dir(&self.working_directory.new_split);
+        let tab_id = self.tabs[self.active].id;
+        let (new_id, pane) = self.spawn_pane(tab_id, cols, rows, cell_width, cell_height, event_proxy, cwd);
         let tab = &mut self.tabs[self.active];
         tab.pane_tree.split_active(split, new_id);
+        if self.pane_animation {
+            // Only the new shell starts at its final size; the others keep
+            // theirs until the animation settles.
+            let layouts = tab.pane_tree.calculate_layouts(viewport_width, viewport_height);
+            if let Some(layout) = layouts.iter().find(|l| l.pane_id == new_id) {
+                resize_pane(&pane.term, &pane, layout, cell_width, cell_height);
+            }
+        } else {
+            tab.pane_tree.skip_transition();
+        }
         tab.panes.insert(new_id, pane);
         // Resize all panes to their actual layout dimensions
         Self::resize_tab_panes(tab, viewport_width, viewport_height, cell_width, cell_height);
+        true
     }
 
     /// Close the active pane in the active tab. Returns true if the whole tab should close.
@@ -262,10 +1278,13 @@ impl TabManager {
             // Last pane in tab - close the tab
             return self.close_active();
         }
+        if !self.pane_animation {
+            tab.pane_tree.skip_transition();
+        }
 
         // Shutdown the closed pane's PTY
         if let Some(pane) = tab.panes.remove(&pane_id) {
-            let _ = pane.notifier.0.send(Msg::Shutdown);
+            pane.stop();
         }
         false
     }
@@ -278,6 +1297,32 @@ impl TabManager {
         self.tabs[self.active].pane_tree.set_active(pane_id);
     }
 
+    /// Trade places between the active pane and `pane_id` in the active
+    /// tab, resizing both to their new spots. Returns true if they moved.
+    pub fn swap_active_pane(&mut self, pane_id: usize, width: f32, height: f32, cell_width: f32, cell_height: f32) -> bool {
+        let swapped = self.tabs[self.active].pane_tree.swap_active_with(pane_id);
+        if swapped {
+            self.resize_active_tab(width, height, cell_width, cell_height);
+        }
+        swapped
+    }
+
+    /// Even out the active tab's splits and resize its panes to match.
+    pub fn equalize_active_tab(&mut self, width: f32, height: f32, cell_width: f32, cell_height: f32) {
+        self.tabs[self.active].pane_tree.equalize();
+        self.resize_active_tab(width, height, cell_width, cell_height);
+    }
+
+    /// Flip the split around the active pane between side by side and
+    /// stacked, resizing its panes. Returns true if there was one.
+    pub fn rotate_active_split(&mut self, width: f32, height: f32, cell_width: f32, cell_height: f32) -> bool {
+        let rotated = self.tabs[self.active].pane_tree.rotate();
+        if rotated {
+            self.resize_active_tab(width, height, cell_width, cell_height);
+        }
+        rotated
+    }
+
     pub fn focus_next_pane(&mut self) {
         self.tabs[self.active].pane_tree.focus_next();
     }
@@ -286,6 +1331,26 @@ impl TabManager {
         self.tabs[self.active].pane_tree.focus_prev();
     }
 
+    /// True if the active tab's split layout fits the viewport (see
+    /// `MIN_PANE_COLS` / `MIN_PANE_ROWS`), zoomed or not.
+    pub fn active_layout_fits(&self, width: f32, height: f32, cell_width: f32, cell_height: f32) -> bool {
+        self.active_tab().is_none_or(|tab| {
+            layouts_fit(&tab.pane_tree.split_layouts(width, height), cell_width, cell_height)
+        })
+    }
+
+    /// Zoom each tab's active pane while its layout doesn't fit the
+    /// viewport, and unzoom once it does. Returns true if any tab changed,
+    /// in which case the panes need resizing.
+    pub fn fit_auto_zoom(&mut self, width: f32, height: f32, cell_width: f32, cell_height: f32) -> bool {
+        let mut changed = false;
+        for tab in &mut self.tabs {
+            let fits = layouts_fit(&tab.pane_tree.split_layouts(width, height), cell_width, cell_height);
+            changed |= tab.pane_tree.set_auto_zoomed(!fits);
+        }
+        changed
+    }
+
     /// Get divider info for the active tab.
     pub fn active_dividers(&self, width: f32, height: f32) -> Vec<crate::panes::DividerInfo> {
         match self.active_tab() {
@@ -339,7 +1404,7 @@ impl TabManager {
         if tab.pane_tree.pane_count() <= 1 {
             // Last pane — close the tab
             if let Some(pane) = tab.panes.remove(&pane_id) {
-                let _ = pane.notifier.0.send(Msg::Shutdown);
+                pane.stop();
             }
             if self.tabs.len() <= 1 {
                 return true;
@@ -358,41 +1423,68 @@ impl TabManager {
         tab.pane_tree.close_active();
         // Restore focus
         tab.pane_tree.set_active(saved_active);
+        if !self.pane_animation {
+            tab.pane_tree.skip_transition();
+        }
 
         if let Some(pane) = tab.panes.remove(&pane_id) {
-            let _ = pane.notifier.0.send(Msg::Shutdown);
+            pane.stop();
         }
         false
     }
 
     /// Resize panes in a single tab based on their layout dimensions.
     fn resize_tab_panes(tab: &Tab, width: f32, height: f32, cell_width: f32, cell_height: f32) {
-        let layouts = tab.pane_tree.calculate_layouts(width, height);
-        for layout in &layouts {
-            if let Some(pane) = tab.panes.get(&layout.pane_id) {
-                let cols = (layout.width / cell_width) as usize;
-                let rows = (layout.height / cell_height) as usize;
-                let cols = cols.max(2);
-                let rows = rows.max(1);
-                pane.term.lock().resize(TerminalSize::new(cols, rows));
-                let window_size = WindowSize {
-                    num_lines: rows as u16,
-                    num_cols: cols as u16,
-                    cell_width: cell_width as u16,
-                    cell_height: cell_height as u16,
-                };
-                pane.notifier.send_resize(window_size);
-            }
+        let pane = |id| tab.panes.get(&id).map(|p: &Pane| (&*p.term, p));
+        resize_panes(&tab.pane_tree, pane, width, height, cell_width, cell_height);
+    }
+
+    /// Show or hide split panes' header strips. Grids keep their size
+    /// until the next resize.
+    pub fn set_pane_headers(&mut self, on: bool) {
+        self.pane_headers = on;
+        let header = self.header_height();
+        for tab in &mut self.tabs {
+            tab.pane_tree.set_header_height(header);
         }
     }
 
-    /// Resize all panes in all tabs using per-pane layout dimensions.
-    pub fn resize_all(&self, width: f32, height: f32, cell_width: f32, cell_height: f32) {
+    fn header_height(&self) -> f32 {
+        if self.pane_headers { self.cell_height } else { 0.0 }
+    }
+
+    /// Resize all panes in all tabs using per-pane layout dimensions, at a
+    /// cell size that may have changed.
+    pub fn resize_all(&mut self, width: f32, height: f32, cell_width: f32, cell_height: f32) {
+        let _span = tracing::info_span!("resize_all").entered();
+        self.min_pane = min_pane_size(cell_width, cell_height);
+        self.cell_height = cell_height;
+        let header = self.header_height();
+        for tab in &mut self.tabs {
+            tab.pane_tree.set_min_pane_size(self.min_pane.0, self.min_pane.1);
+            tab.pane_tree.set_header_height(header);
+        }
         for tab in &self.tabs {
             Self::resize_tab_panes(tab, width, height, cell_width, cell_height);
         }
     }
 
+    /// Settle pane transitions that have finished by `now`, resizing their
+    /// tabs' panes. Returns whether the active tab's is still animating.
+    pub fn settle_transitions(&mut self, now: Instant, width: f32, height: f32, cell_width: f32, cell_height: f32) -> bool {
+        for tab in &mut self.tabs {
+            if tab.pane_tree.settle_transition(now) {
+                Self::resize_tab_panes(tab, width, height, cell_width, cell_height);
+            }
+        }
+        self.active_tab().is_some_and(|tab| tab.pane_tree.transition_running(now))
+    }
+
+    /// Layouts of the active tab's panes as drawn at `now`, mid-animation.
+    pub fn active_animated_layouts(&self, width: f32, height: f32, now: Instant) -> Vec<PaneLayout> {
+        self.active_tab().map(|tab| tab.pane_tree.animated_layouts(width, height, now)).unwrap_or_default()
+    }
+
     /// Resize only the active tab's panes (e.g. during divider drag).
     pub fn resize_active_tab(&self, width: f32, height: f32, cell_width: f32, cell_height: f32) {
         if let Some(tab) = self.active_tab() {
@@ -400,3 +1492,431 @@ impl TabManager {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    use alacritty_terminal::event::VoidListener;
+    use alacritty_terminal::index::{Column, Line};
+    use alacritty_terminal::term::cell::Flags;
+    use alacritty_terminal::term::Config as TermConfig;
+    use alacritty_terminal::vte::ansi::{Processor, StdSyncHandler};
+
+    const CW: f32 = 8.0;
+    const CH: f32 = 16.0;
+
+    /// Records every size sent to the "PTY".
+    #[derive(Default)]
+    struct MockPty(RefCell<Vec<WindowSize>>);
+
+    impl PtyResize for MockPty {
+        fn send_resize(&self, size: WindowSize) {
+            self.0.borrow_mut().push(size);
+        }
+    }
+
+    /// Records shutdown requests; `thread` stands in for the PTY thread.
+    struct MockShutdown {
+        requested: bool,
+        thread: Option<std::thread::JoinHandle<()>>,
+        /// Ignores the request, like a shell wedged in uninterruptible I/O.
+        stuck: bool,
+        released: Option<bool>,
+    }
+
+    impl MockShutdown {
+        fn new(stuck: bool) -> Self {
+            Self { requested: false, thread: None, stuck, released: None }
+        }
+    }
+
+    impl PtyShutdown for MockShutdown {
+        fn request_shutdown(&mut self) {
+            self.requested = true;
+        }
+
+        fn is_finished(&self) -> bool {
+            !self.stuck && self.thread.as_ref().is_none_or(|t| t.is_finished())
+        }
+
+        fn release(&mut self, detach: bool) {
+            if let Some(thread) = self.thread.take() {
+                if !detach {
+                    thread.join().unwrap();
+                }
+            }
+            self.released = Some(detach);
+        }
+    }
+
+    #[test]
+    fn shutdown_reaches_every_pane() {
+        let mut panes: Vec<_> = (0..3).map(|_| MockShutdown::new(false)).collect();
+        // One PTY thread is already gone; joining it must not block.
+        let mut gone = MockShutdown::new(false);
+        gone.thread = Some(std::thread::spawn(|| {}));
+        while !gone.is_finished() {
+            std::thread::yield_now();
+        }
+        panes.push(gone);
+
+        assert_eq!(shutdown_panes(&mut panes, Duration::from_secs(5)), 0);
+        assert!(panes.iter().all(|p| p.requested && p.released == Some(false)));
+    }
+
+    #[test]
+    fn shutdown_detaches_stuck_panes_after_timeout() {
+        let mut panes = vec![MockShutdown::new(false), MockShutdown::new(true)];
+        let start = Instant::now();
+        assert_eq!(shutdown_panes(&mut panes, Duration::from_millis(50)), 1);
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(panes[0].released, Some(false));
+        assert_eq!(panes[1].released, Some(true));
+        assert!(panes.iter().all(|p| p.requested));
+    }
+
+    #[test]
+    fn reaping_reports_how_finished_pty_threads_ended() {
+        let finished = |f: fn()| {
+            let thread = std::thread::spawn(f);
+            while !thread.is_finished() {
+                std::thread::yield_now();
+            }
+            Some(thread)
+        };
+        let mut panicked = finished(|| panic!("parser hit {}", "EOF"));
+        assert_eq!(reap(&mut panicked), Some(Err("parser hit EOF".into())));
+        assert!(panicked.is_none(), "a reaped thread is taken");
+        assert_eq!(reap(&mut panicked), None);
+
+        let mut panicked = finished(|| panic!("static message"));
+        assert_eq!(reap(&mut panicked), Some(Err("static message".into())));
+
+        let mut returned = finished(|| {});
+        assert_eq!(reap(&mut returned), Some(Ok(())));
+
+        let (tx, rx) = std::sync::mpsc::channel::<()>();
+        let mut running = Some(std::thread::spawn(move || rx.recv().unwrap_or_default()));
+        assert_eq!(reap(&mut running), None);
+        assert!(running.is_some(), "a live thread is left in place");
+        drop(tx);
+    }
+
+    #[test]
+    fn repeated_titles_are_dropped_and_only_visible_changes_redraw() {
+        let mut pane_title = None;
+        let mut tab_title = "Tab 1".to_string();
+        let redraws = (0..1000)
+            .map(|_| retitle(&mut pane_title, &mut tab_title, "~/src/koi".into(), Some(20)))
+            .filter(|r| *r == Some(true))
+            .count();
+        assert_eq!(redraws, 1);
+        assert_eq!(retitle(&mut pane_title, &mut tab_title, "~/src/koi".into(), Some(20)), None);
+
+        // Past what the tab shows, or with no tab bar: the title is kept
+        // but nothing needs drawing.
+        assert_eq!(retitle(&mut pane_title, &mut tab_title, "~/src/koi/src".into(), Some(5)), Some(false));
+        assert_eq!(tab_title, "~/src/koi/src");
+        assert_eq!(retitle(&mut pane_title, &mut tab_title, "vim".into(), None), Some(false));
+        assert_eq!(pane_title.as_deref(), Some("vim"));
+
+        // Another pane in the tab took over the title; this one's repeat
+        // takes it back.
+        let mut other_title = None;
+        assert_eq!(retitle(&mut other_title, &mut tab_title, "htop".into(), None), Some(false));
+        assert_eq!(retitle(&mut pane_title, &mut tab_title, "vim".into(), None), Some(false));
+        assert_eq!(tab_title, "vim");
+    }
+
+    #[test]
+    fn windows_draw_pane_ids_from_one_counter() {
+        let ids = PaneIds::default();
+        let other_window = ids.clone();
+        assert_eq!([ids.next(), other_window.next(), ids.next()], [0, 1, 2]);
+    }
+
+    /// Panes with no shell behind them, and nowhere for their events to go.
+    struct NoShell;
+
+    impl crate::event::PtyChannel for NoShell {
+        fn send(&self, _msg: Msg) -> Result<(), alacritty_terminal::event_loop::EventLoopSendError> {
+            Ok(())
+        }
+    }
+
+    impl crate::event::EventSink for NoShell {
+        fn send(&self, _event: crate::event::KoiEvent) {}
+    }
+
+    impl Launcher for NoShell {
+        fn launch(
+            &self,
+            _terminal: &TerminalConfig,
+            _term: &Arc<FairMutex<Term<EventProxy>>>,
+            _proxy: EventProxy,
+            _size: WindowSize,
+            _opts: &tty::Options,
+        ) -> PaneProcess {
+            PaneProcess::detached(NoShell, Backlog::default())
+        }
+    }
+
+    /// Window 3, with `tabs` tabs of one pane each.
+    fn manager(tabs: usize) -> TabManager {
+        let proxy = EventProxy::new(NoShell);
+        let mut mgr = TabManager::new(
+            80,
+            24,
+            CW,
+            CH,
+            &proxy,
+            WorkingDirectoryConfig::default(),
+            TerminalConfig::default(),
+            crate::terminal::DEFAULT_HISTORY_SIZE,
+            false,
+            FirstPane::Shell,
+            3,
+            PaneIds::default(),
+            Box::new(NoShell),
+        );
+        for _ in 1..tabs {
+            mgr.add_tab(80, 24, CW, CH, &proxy);
+        }
+        mgr
+    }
+
+    #[test]
+    fn closed_tabs_ids_are_not_reused() {
+        let mut mgr = manager(3);
+        // Closing the first tab shifts the others' indices but not their ids.
+        mgr.goto_tab(0);
+        mgr.close_active();
+        assert_eq!(mgr.tabs.iter().map(|t| t.id).collect::<Vec<_>>(), [1, 2]);
+        mgr.add_tab(80, 24, CW, CH, &EventProxy::new(NoShell));
+        assert_eq!(mgr.tabs.iter().map(|t| t.id).collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!(mgr.active_pane_id(), Some(3));
+    }
+
+    #[test]
+    fn refused_input_is_a_crash_only_without_an_exit_code() {
+        let mut mgr = manager(2);
+        for pane in mgr.panes_mut() {
+            pane.input_closed.set(true);
+        }
+        let _ = mgr.pane(0).unwrap().exit_code.set(0);
+        assert_eq!(mgr.reap_pty_threads(), [(1, PtyEnd::Crashed)]);
+        assert!(!mgr.pane(0).unwrap().io_crashed);
+        assert!(!mgr.input_lost());
+    }
+
+    #[test]
+    fn a_program_runs_in_place_of_the_login_shell() {
+        let mgr = manager(1);
+        let cwd = Some(std::path::PathBuf::from("/tmp"));
+        let shell = mgr.pty_options(0, 1, cwd.clone(), None);
+        assert_eq!(shell.working_directory, cwd);
+        assert_eq!(shell.shell, None, "the login shell");
+
+        let htop = mgr.pty_options(0, 1, None, Some(("htop".into(), vec!["-d".into(), "5".into()])));
+        assert_eq!(htop.shell, Some(tty::Shell::new("htop".into(), vec!["-d".into(), "5".into()])));
+    }
+
+    #[test]
+    fn the_command_line_picks_the_first_pane() {
+        let parse = |args: &[&str]| FirstPane::from_args(args.iter().map(|a| a.to_string()));
+        assert_eq!(parse(&["koi"]), FirstPane::Shell);
+        assert_eq!(parse(&["koi", "--profile", "--pager"]), FirstPane::Stdin);
+        assert_eq!(parse(&["koi", "-e", "htop"]), FirstPane::Command("htop".into(), vec![]));
+        // The program's flags are its own.
+        assert_eq!(
+            parse(&["koi", "--profile", "-e", "less", "--pager", "-e"]),
+            FirstPane::Command("less".into(), vec!["--pager".into(), "-e".into()]),
+        );
+        assert_eq!(parse(&["koi", "-e"]), FirstPane::Shell);
+
+        let flag = |args: &[&str]| has_flag(args.iter().map(|a| a.to_string()), "--shell-integration");
+        assert!(flag(&["koi", "--shell-integration"]));
+        assert!(!flag(&["koi", "-e", "mytool", "--shell-integration"]));
+        assert!(!flag(&["--shell-integration"]));
+    }
+
+    struct MockPane {
+        term: FairMutex<Term<VoidListener>>,
+        pty: MockPty,
+    }
+
+    impl MockPane {
+        fn new(cols: usize, rows: usize) -> Self {
+            let term = Term::new(TermConfig::default(), &TerminalSize::new(cols, rows), VoidListener);
+            Self { term: FairMutex::new(term), pty: MockPty::default() }
+        }
+
+        fn print(&self, text: &str) {
+            let mut parser: Processor<StdSyncHandler> = Processor::new();
+            parser.advance(&mut *self.term.lock(), text.as_bytes());
+        }
+
+        /// Logical lines (soft wraps joined) from the top of history down to
+        /// the cursor, trailing blanks trimmed.
+        fn logical_lines(&self) -> Vec<String> {
+            let term = self.term.lock();
+            let grid = term.grid();
+            let mut lines = Vec::new();
+            let mut current = String::new();
+            let last = grid.cursor.point.line.0;
+            for line in (term.topmost_line().0..=last).map(Line) {
+                let row = &grid[line];
+                for col in 0..term.columns() {
+                    let cell = &row[Column(col)];
+                    if !cell.flags.contains(Flags::WIDE_CHAR_SPACER) {
+                        current.push(cell.c);
+                    }
+                }
+                if !row[Column(term.columns() - 1)].flags.contains(Flags::WRAPLINE) {
+                    lines.push(current.trim_end().to_string());
+                    current.clear();
+                }
+            }
+            lines
+        }
+
+        /// The Term's grid agrees with the last size the PTY was sent.
+        fn assert_in_sync(&self) {
+            let term = self.term.lock();
+            let sent = *self.pty.0.borrow().last().expect("no resize sent");
+            assert_eq!(term.columns(), sent.num_cols as usize);
+            assert_eq!(term.screen_lines(), sent.num_lines as usize);
+        }
+    }
+
+    /// Resize every pane in `tree` the way `resize_tab_panes` does.
+    fn resize_tree(tree: &PaneTree, panes: &HashMap<usize, MockPane>, width: f32, height: f32) {
+        let lookup = |id| panes.get(&id).map(|p: &MockPane| (&p.term, &p.pty));
+        assert!(resize_panes(tree, lookup, width, height, CW, CH));
+        for layout in tree.calculate_layouts(width, height) {
+            let pane = &panes[&layout.pane_id];
+            let expected = pane_grid_size(layout.width, layout.height, CW, CH);
+            let term = pane.term.lock();
+            assert_eq!((term.columns(), term.screen_lines()), expected);
+            drop(term);
+            pane.assert_in_sync();
+        }
+    }
+
+    fn split_panes() -> (PaneTree, HashMap<usize, MockPane>) {
+        let mut tree = PaneTree::new(0);
+        tree.split_active(Split::Vertical, 1);
+        tree.skip_transition();
+        let panes = (0..2).map(|id| (id, MockPane::new(50, 30))).collect();
+        (tree, panes)
+    }
+
+    #[test]
+    fn resize_waits_for_the_pane_transition() {
+        let before = Instant::now();
+        let mut tree = PaneTree::new(0);
+        let panes: HashMap<_, _> = (0..2).map(|id| (id, MockPane::new(100, 30))).collect();
+        tree.split_active(Split::Vertical, 1);
+        let lookup = |id| panes.get(&id).map(|p: &MockPane| (&p.term, &p.pty));
+
+        // Mid-animation: no SIGWINCH, the old pane keeps its grid.
+        assert!(!resize_panes(&tree, lookup, 800.0, 480.0, CW, CH));
+        assert!(panes[&0].pty.0.borrow().is_empty());
+        assert_eq!(panes[&0].term.lock().columns(), 100);
+
+        // Settled: one resize to the final layout.
+        assert!(tree.settle_transition(before + crate::panes::TRANSITION * 2));
+        assert!(resize_panes(&tree, lookup, 800.0, 480.0, CW, CH));
+        assert_eq!(panes[&0].pty.0.borrow().len(), 1);
+        assert_eq!(panes[&0].term.lock().columns(), 50);
+        panes[&0].assert_in_sync();
+    }
+
+    #[test]
+    fn layouts_fit_checks_every_pane() {
+        let mut tree = PaneTree::new(0);
+        tree.split_active(Split::Vertical, 1);
+        tree.split_active(Split::Horizontal, 2);
+        // 10x20 cells: 40 columns per side, 15 rows per stacked pane.
+        assert!(layouts_fit(&tree.split_layouts(800.0, 600.0), 10.0, 20.0));
+        // 18 columns wide: each side gets 9.
+        assert!(!layouts_fit(&tree.split_layouts(180.0, 600.0), 10.0, 20.0));
+        // 100 px tall: the stacked panes get 2 rows each.
+        assert!(!layouts_fit(&tree.split_layouts(800.0, 100.0), 10.0, 20.0));
+    }
+
+    #[test]
+    fn grid_size_clamps_to_minimum() {
+        assert_eq!(pane_grid_size(800.0, 480.0, CW, CH), (100, 30));
+        assert_eq!(pane_grid_size(807.9, 495.9, CW, CH), (100, 30));
+        assert_eq!(pane_grid_size(4.0, 4.0, CW, CH), (2, 1));
+        assert_eq!(pane_grid_size(0.0, 0.0, CW, CH), (2, 1));
+    }
+
+    #[test]
+    fn divider_drag_keeps_term_and_pty_in_sync() {
+        let (mut tree, panes) = split_panes();
+        let ratios = (1..=9).chain((1..=9).rev()).map(|r| r as f32 / 10.0);
+        for ratio in ratios {
+            tree.set_ratio_at(&[], ratio);
+            resize_tree(&tree, &panes, 803.0, 487.0);
+        }
+        // Odd pixel sizes and a window smaller than a cell.
+        for (w, h) in [(1.0, 1.0), (17.5, 33.3), (1921.0, 1081.0), (800.0, 480.0)] {
+            resize_tree(&tree, &panes, w, h);
+        }
+    }
+
+    #[test]
+    fn zoom_resizes_to_full_viewport() {
+        let (mut tree, panes) = split_panes();
+        resize_tree(&tree, &panes, 800.0, 480.0);
+        tree.toggle_zoom();
+        resize_tree(&tree, &panes, 800.0, 480.0);
+        let active = &panes[&tree.active_pane_id()];
+        assert_eq!(active.term.lock().columns(), 100);
+        tree.toggle_zoom();
+        resize_tree(&tree, &panes, 800.0, 480.0);
+        assert!(active.term.lock().columns() < 100);
+    }
+
+    #[test]
+    fn content_survives_shrink_and_grow() {
+        let pane = MockPane::new(40, 10);
+        let long = "x".repeat(30);
+        let mut expected: Vec<String> = (0..15).map(|i| format!("line {i}")).collect();
+        expected.insert(7, long.clone());
+        for line in &expected {
+            pane.print(&format!("{line}\r\n"));
+        }
+        expected.push(String::new()); // the line the cursor sits on
+        assert_eq!(pane.logical_lines(), expected);
+
+        let sizes = [(20, 5), (40, 10), (7, 3), (13, 24), (40, 10), (3, 1), (40, 10)];
+        for (cols, rows) in sizes {
+            let layout = PaneLayout {
+                pane_id: 0,
+                x: 0.0,
+                y: 0.0,
+                width: cols as f32 * CW,
+                height: rows as f32 * CH,
+            };
+            resize_pane(&pane.term, &pane.pty, &layout, CW, CH);
+            pane.assert_in_sync();
+            assert_eq!(pane.logical_lines(), expected, "after resize to {cols}x{rows}");
+        }
+    }
+
+    #[test]
+    fn moving_a_tab_shifts_the_ones_between() {
+        // Tabs [a, b, c, d], c moved to the front: [c, a, b, d].
+        let moved: Vec<_> = (0..4).map(|i| moved_index(i, 2, 0)).collect();
+        assert_eq!(moved, [1, 2, 0, 3]);
+        // And back again.
+        let moved: Vec<_> = (0..4).map(|i| moved_index(i, 0, 2)).collect();
+        assert_eq!(moved, [2, 0, 1, 3]);
+        assert_eq!(moved_index(3, 1, 1), 3);
+    }
+}
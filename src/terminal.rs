@@ -1,7 +1,8 @@
 use alacritty_terminal::grid::Dimensions;
 
-/// Default scrollback history size (lines).
-const DEFAULT_HISTORY_SIZE: usize = 10_000;
+/// Default scrollback history size (lines), unless `scrolling.lines` says
+/// otherwise.
+pub const DEFAULT_HISTORY_SIZE: usize = 10_000;
 
 /// Terminal dimensions implementing alacritty_terminal's Dimensions trait.
 pub struct TerminalSize {
@@ -30,6 +31,52 @@ impl Dimensions for TerminalSize {
     }
 }
 
+/// Window inner size in physical pixels that fits exactly `cols` x `rows`
+/// cells, plus `extra_height` of chrome above the grid (the tab bar).
+///
+/// Rounds up so that flooring the size back into cells, as the resize path
+/// does, gives the same grid.
+pub fn window_size_for_grid(
+    cols: usize,
+    rows: usize,
+    cell_width: f32,
+    cell_height: f32,
+    extra_height: f32,
+) -> (u32, u32) {
+    let w = (cols as f32 * cell_width).ceil();
+    let h = (rows as f32 * cell_height + extra_height).ceil();
+    (w as u32, h as u32)
+}
+
+/// Inner size of a new window without configured dimensions, in logical
+/// pixels.
+pub const DEFAULT_WINDOW_SIZE: (f64, f64) = (960.0, 600.0);
+
+/// Inner size in physical pixels for a new window: enough for `grid`
+/// (columns, lines) of cells plus `extra_height` of chrome, or the logical
+/// default at `scale`. Clamped to `max`, the monitor's size, if known;
+/// a clamped window gets as many whole cells as fit.
+pub fn initial_window_size(
+    grid: Option<(usize, usize)>,
+    cell_width: f32,
+    cell_height: f32,
+    extra_height: f32,
+    scale: f64,
+    max: Option<(u32, u32)>,
+) -> (u32, u32) {
+    let (w, h) = match grid {
+        Some((cols, rows)) => window_size_for_grid(cols, rows, cell_width, cell_height, extra_height),
+        None => {
+            let (w, h) = DEFAULT_WINDOW_SIZE;
+            ((w * scale).round() as u32, (h * scale).round() as u32)
+        }
+    };
+    match max {
+        Some((max_w, max_h)) => (w.min(max_w), h.min(max_h)),
+        None => (w, h),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -47,4 +94,49 @@ mod tests {
         let size = TerminalSize::new(80, 24);
         assert_eq!(size.total_lines(), 24 + 10_000);
     }
+
+    /// Cells that fit in a window size, the way `KoiState::grid_size` counts.
+    fn grid_for(w: u32, h: u32, cw: f32, ch: f32, extra: f32) -> (usize, usize) {
+        ((w as f32 / cw) as usize, ((h as f32 - extra) / ch) as usize)
+    }
+
+    #[test]
+    fn window_size_round_trips_grid() {
+        for &(cw, ch) in &[(8.0, 17.0), (8.4, 17.5), (9.6, 19.2), (13.2, 26.4)] {
+            for &extra in &[0.0, ch] {
+                let (w, h) = window_size_for_grid(80, 24, cw, ch, extra);
+                assert_eq!(grid_for(w, h, cw, ch, extra), (80, 24), "cell {}x{}", cw, ch);
+            }
+        }
+    }
+
+    #[test]
+    fn initial_size_fits_configured_grid() {
+        // Cells are measured at the scaled font size, so HiDPI just means
+        // bigger cells; the grid comes back exact either way.
+        for &(cw, ch, scale) in &[(8.4, 17.5, 1.0), (16.8, 35.0, 2.0), (12.6, 26.25, 1.5)] {
+            for &extra in &[0.0, ch] {
+                let (w, h) = initial_window_size(Some((120, 34)), cw, ch, extra, scale, None);
+                assert_eq!(grid_for(w, h, cw, ch, extra), (120, 34), "cell {cw}x{ch} @{scale}");
+            }
+        }
+    }
+
+    #[test]
+    fn initial_size_defaults_and_clamps() {
+        assert_eq!(initial_window_size(None, 8.0, 16.0, 0.0, 1.0, None), (960, 600));
+        assert_eq!(initial_window_size(None, 16.0, 32.0, 0.0, 2.0, None), (1920, 1200));
+        // 300 columns of 10px won't fit a 1920px monitor.
+        let (w, h) = initial_window_size(Some((300, 40)), 10.0, 20.0, 0.0, 1.0, Some((1920, 1080)));
+        assert_eq!((w, h), (1920, 800));
+        assert_eq!(grid_for(w, h, 10.0, 20.0, 0.0), (192, 40));
+    }
+
+    #[test]
+    fn zoom_scales_window_not_grid() {
+        // 100x30 at 8x16 cells; zooming in to 10x20 cells grows the window.
+        let (w, h) = window_size_for_grid(100, 30, 10.0, 20.0, 20.0);
+        assert_eq!((w, h), (1000, 620));
+        assert_eq!(grid_for(w, h, 10.0, 20.0, 20.0), (100, 30));
+    }
 }
@@ -0,0 +1,255 @@
+//! The themes a window can switch between: the built-in ones, then every
+//! `*.toml` in the `themes` dir beside `koi.toml`, read once at startup.
+//!
+//! A theme file sets its colors as `#rrggbb` strings, either with koi's
+//! own flat keys or in Alacritty's `[colors.*]` tables:
+//!
+//! ```toml
+//! name = "Catppuccin Frappe"   # else the file name
+//! foreground = "#c6d0f5"
+//! background = "#303446"
+//! cursor = "#f2d5cf"
+//! selection_background = "#8caaee"
+//! selection_foreground = "#303446"
+//! border = "#8caaee"
+//! black = "#51576d"            # ... through bright_white
+//! ```
+//!
+//! Colors missing or unreadable come from Latte or Mocha, whichever
+//! matches the background.
+
+use std::path::{Path, PathBuf};
+
+use toml::Table;
+
+use crate::config::Config;
+use crate::renderer::color::{luminance, parse_hex};
+use crate::renderer::Theme;
+
+/// The sixteen ANSI color keys, in palette order.
+const ANSI: [&str; 16] = [
+    "black",
+    "red",
+    "green",
+    "yellow",
+    "blue",
+    "magenta",
+    "cyan",
+    "white",
+    "bright_black",
+    "bright_red",
+    "bright_green",
+    "bright_yellow",
+    "bright_blue",
+    "bright_magenta",
+    "bright_cyan",
+    "bright_white",
+];
+
+/// Each theme by name, built-ins first.
+#[derive(Clone)]
+pub struct ThemeSet {
+    themes: Vec<(String, Theme)>,
+}
+
+impl ThemeSet {
+    /// Only the built-in themes.
+    pub fn builtin() -> Self {
+        let themes = Theme::BUILTIN.iter().map(|&(name, theme)| (name.to_string(), theme())).collect();
+        Self { themes }
+    }
+
+    /// The built-ins and the user's theme files.
+    pub fn load() -> Self {
+        let mut set = Self::builtin();
+        if let Some(dir) = dir() {
+            set.load_dir(&dir);
+        }
+        set
+    }
+
+    /// Add every theme file in `dir`, by file name. One named like a theme
+    /// already in the set replaces it.
+    fn load_dir(&mut self, dir: &Path) {
+        let Ok(entries) = std::fs::read_dir(dir) else { return };
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|ext| ext == "toml"))
+            .collect();
+        paths.sort();
+        for path in paths {
+            let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+            match Theme::from_file(&path) {
+                Ok((name, theme)) => {
+                    let name = name.unwrap_or(stem);
+                    match self.find(&name) {
+                        Some(i) => self.themes[i].1 = theme,
+                        None => self.themes.push((name, theme)),
+                    }
+                }
+                Err(e) => log::warn!("Skipping theme {}: {}", path.display(), e),
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.themes.len()
+    }
+
+    pub fn name(&self, index: usize) -> &str {
+        &self.themes[index].0
+    }
+
+    pub fn theme(&self, index: usize) -> Theme {
+        self.themes[index].1.clone()
+    }
+
+    /// The index of the theme called `name`.
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.themes.iter().position(|(n, _)| n == name)
+    }
+
+    /// The index of the theme after `index`, wrapping around.
+    pub fn next(&self, index: usize) -> usize {
+        (index + 1) % self.themes.len()
+    }
+}
+
+/// `themes/` beside the config file.
+fn dir() -> Option<PathBuf> {
+    Config::path().and_then(|p| p.parent().map(|dir| dir.join("themes")))
+}
+
+impl Theme {
+    /// The theme in the TOML file at `path`, and the name it gives itself.
+    pub fn from_file(path: &Path) -> Result<(Option<String>, Theme), String> {
+        let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        Self::from_toml(&text)
+    }
+
+    fn from_toml(text: &str) -> Result<(Option<String>, Theme), String> {
+        let table: Table = toml::from_str(text).map_err(|e| e.to_string())?;
+        let name = table.get("name").and_then(|v| v.as_str()).map(String::from);
+        let file = ThemeFile { table };
+
+        let bg = file.color("background", &["primary", "background"]);
+        let light = bg.is_some_and(|bg| luminance(bg) > 0.5);
+        let mut theme = if light { Theme::latte() } else { Theme::mocha() };
+
+        theme.bg = bg.unwrap_or(theme.bg);
+        theme.fg = file.color("foreground", &["primary", "foreground"]).unwrap_or(theme.fg);
+        for (slot, key) in ANSI.iter().enumerate() {
+            let (table, short) = match key.strip_prefix("bright_") {
+                Some(short) => ("bright", short),
+                None => ("normal", *key),
+            };
+            if let Some(c) = file.color(key, &[table, short]) {
+                theme.colors[slot] = c;
+            }
+        }
+        theme.cursor = file.color("cursor", &["cursor", "cursor"]).unwrap_or(theme.cursor);
+        let selection = file.color("selection_background", &["selection", "background"]);
+        let selection_fg = file.color("selection_foreground", &["selection", "text"]);
+        if let Some([r, g, b]) = selection {
+            theme.selection = [r, g, b, theme.selection[3]];
+            theme.selection_fg = selection_fg;
+        } else if selection_fg.is_some() {
+            theme.selection_fg = selection_fg;
+        }
+        if let Some([r, g, b]) = file.color("border", &["border", "active"]) {
+            theme.border = [r, g, b, 1.0];
+        }
+        // Chrome between the two: tabs, separators and dividers.
+        if bg.is_some() {
+            let mix = |t: f32| std::array::from_fn(|i| theme.bg[i] + (theme.fg[i] - theme.bg[i]) * t);
+            theme.surface0 = mix(0.15);
+            theme.overlay0 = mix(0.45);
+            theme.divider = theme.overlay0;
+        }
+        theme.divider_hover = [theme.border[0], theme.border[1], theme.border[2]];
+        Ok((name, theme))
+    }
+}
+
+/// A parsed theme file.
+struct ThemeFile {
+    table: Table,
+}
+
+impl ThemeFile {
+    /// The color under koi's `key`, else under Alacritty's
+    /// `colors.<table>.<key>`. None, with a warning, if it's there but not
+    /// a color.
+    fn color(&self, key: &str, [table, alacritty_key]: &[&str; 2]) -> Option<[f32; 3]> {
+        let value = self.table.get(key).or_else(|| {
+            self.table.get("colors")?.get(table)?.get(alacritty_key)
+        })?;
+        let s = value.as_str().unwrap_or_default();
+        // Alacritty writes colors as 0xrrggbb too.
+        let color = parse_hex(s.strip_prefix("0x").unwrap_or(s));
+        if color.is_none() {
+            log::warn!("Theme color {} = {} is not #rrggbb; using the default", key, value);
+        }
+        color
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAPPE: &str = r##"
+        name = "Catppuccin Frappe"
+        foreground = "#c6d0f5"
+        background = "#303446"
+        red = "not a color"
+        bright_blue = "#8caaee"
+    "##;
+
+    #[test]
+    fn a_theme_file_falls_back_color_by_color() {
+        let (name, theme) = Theme::from_toml(FRAPPE).unwrap();
+        assert_eq!(name.as_deref(), Some("Catppuccin Frappe"));
+        assert_eq!(theme.bg, parse_hex("#303446").unwrap());
+        assert_eq!(theme.colors[12], parse_hex("#8caaee").unwrap());
+        // A dark background: what's missing or broken comes from Mocha.
+        assert_eq!(theme.colors[1], Theme::mocha().colors[1]);
+        assert_eq!(theme.cursor, Theme::mocha().cursor);
+
+        let (_, light) = Theme::from_toml("background = \"#eff1f5\"").unwrap();
+        assert_eq!(light.colors, Theme::latte().colors);
+        assert!(Theme::from_toml("background = ").is_err());
+    }
+
+    #[test]
+    fn alacritty_theme_tables_are_read() {
+        let (name, theme) = Theme::from_toml(
+            "[colors.primary]\nbackground = \"0x1d1f21\"\n[colors.normal]\nred = \"#cc6666\"\n\
+             [colors.bright]\nwhite = \"#ffffff\"\n[colors.cursor]\ncursor = \"#c5c8c6\"\n",
+        )
+        .unwrap();
+        assert_eq!(name, None);
+        assert_eq!(theme.bg, parse_hex("#1d1f21").unwrap());
+        assert_eq!(theme.colors[1], parse_hex("#cc6666").unwrap());
+        assert_eq!(theme.colors[15], [1.0, 1.0, 1.0]);
+        assert_eq!(theme.cursor, parse_hex("#c5c8c6").unwrap());
+    }
+
+    #[test]
+    fn theme_files_join_the_builtins_and_cycle() {
+        let dir = std::env::temp_dir().join(format!("koi-themes-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("frappe.toml"), FRAPPE).unwrap();
+        std::fs::write(dir.join("plain.toml"), "background = \"#000000\"").unwrap();
+        std::fs::write(dir.join("broken.toml"), "background = ").unwrap();
+        std::fs::write(dir.join("notes.txt"), "").unwrap();
+        let mut set = ThemeSet::builtin();
+        set.load_dir(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let names: Vec<&str> = (0..set.len()).map(|i| set.name(i)).collect();
+        assert_eq!(names, ["Catppuccin Latte", "Catppuccin Mocha", "Catppuccin Frappe", "plain"]);
+        assert_eq!(set.next(1), 2);
+        assert_eq!(set.next(3), 0);
+    }
+}
@@ -0,0 +1,122 @@
+//! Keeping up with OSC 0/2 titles without redrawing on every one. Some
+//! prompts retitle on every keystroke; the window title is set at most a
+//! few times a second, the last title winning.
+
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Shortest gap between two window title updates.
+pub const MIN_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The window title, rate-limited.
+#[derive(Debug, Default)]
+pub struct WindowTitle {
+    last_set: Option<Instant>,
+    /// A title that arrived too soon after the last one, to set once
+    /// `MIN_INTERVAL` has passed.
+    pending: Option<String>,
+}
+
+impl WindowTitle {
+    /// `title` should become the window title. Returns it if it can be set
+    /// at `now`; otherwise it is held for `flush`, replacing any title
+    /// already waiting.
+    pub fn update(&mut self, title: &str, now: Instant) -> Option<String> {
+        if self.last_set.is_some_and(|t| now.saturating_duration_since(t) < MIN_INTERVAL) {
+            self.pending = Some(title.to_string());
+            return None;
+        }
+        self.pending = None;
+        self.last_set = Some(now);
+        Some(title.to_string())
+    }
+
+    /// The held title, once it is due at `now`.
+    pub fn flush(&mut self, now: Instant) -> Option<String> {
+        if self.due().is_some_and(|due| now >= due) {
+            self.last_set = Some(now);
+            return self.pending.take();
+        }
+        None
+    }
+
+    /// When the held title can be set, if one is waiting.
+    pub fn due(&self) -> Option<Instant> {
+        self.pending.as_ref()?;
+        Some(self.last_set? + MIN_INTERVAL)
+    }
+}
+
+/// The part of `title` shown in a tab `chars` cells wide.
+pub fn tab_label(title: &str, chars: usize) -> &str {
+    match title.char_indices().nth(chars) {
+        Some((end, _)) => &title[..end],
+        None => title,
+    }
+}
+
+/// A tab's label per the `tabs.title_template` setting: `{index}` is the
+/// tab's 1-based position and `{title}` its name.
+pub fn tab_title(template: &str, index: usize, title: &str) -> String {
+    template.replace("{index}", &(index + 1).to_string()).replace("{title}", title)
+}
+
+/// The strip over a split pane: what runs in it, and where, with `home`
+/// shortened to `~`.
+pub fn pane_header(name: &str, cwd: Option<&Path>, home: Option<&Path>) -> String {
+    let Some(cwd) = cwd else { return format!(" {name}") };
+    let cwd = match home.and_then(|home| cwd.strip_prefix(home).ok()) {
+        Some(rest) if rest.as_os_str().is_empty() => "~".to_string(),
+        Some(rest) => format!("~/{}", rest.display()),
+        None => cwd.display().to_string(),
+    };
+    format!(" {name} \u{2014} {cwd}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: fn(u64) -> Duration = Duration::from_millis;
+
+    #[test]
+    fn bursts_set_the_first_title_and_then_the_last() {
+        let t0 = Instant::now();
+        let mut title = WindowTitle::default();
+        assert_eq!(title.update("a", t0).as_deref(), Some("a"));
+        assert_eq!(title.update("b", t0 + MS(10)), None);
+        assert_eq!(title.update("c", t0 + MS(20)), None);
+        assert_eq!(title.due(), Some(t0 + MIN_INTERVAL));
+        assert_eq!(title.flush(t0 + MS(100)), None);
+        assert_eq!(title.flush(t0 + MIN_INTERVAL).as_deref(), Some("c"));
+        assert_eq!(title.due(), None);
+        assert_eq!(title.flush(t0 + MS(1000)), None);
+        // Quiet long enough: straight through again.
+        assert_eq!(title.update("d", t0 + MS(600)).as_deref(), Some("d"));
+    }
+
+    #[test]
+    fn labels_cut_at_a_char_boundary() {
+        assert_eq!(tab_label("vim main.rs", 3), "vim");
+        assert_eq!(tab_label("~/é/ü", 3), "~/é");
+        assert_eq!(tab_label("zsh", 8), "zsh");
+        assert_eq!(tab_label("zsh", 0), "");
+    }
+
+    #[test]
+    fn templates_fill_in_the_position_and_name() {
+        assert_eq!(tab_title("{title}", 0, "vim"), "vim");
+        assert_eq!(tab_title("{index}: {title}", 2, "build"), "3: build");
+        // A title that looks like a placeholder is left as it is.
+        assert_eq!(tab_title("{title} {index}", 0, "{index}"), "{index} 1");
+    }
+
+    #[test]
+    fn pane_headers_shorten_home() {
+        let home = Some(Path::new("/Users/me"));
+        assert_eq!(pane_header("htop", Some(Path::new("/Users/me/src")), home), " htop \u{2014} ~/src");
+        assert_eq!(pane_header("zsh", Some(Path::new("/Users/me")), home), " zsh \u{2014} ~");
+        assert_eq!(pane_header("zsh", Some(Path::new("/Users/meg")), home), " zsh \u{2014} /Users/meg");
+        assert_eq!(pane_header("cat", None, home), " cat");
+    }
+}
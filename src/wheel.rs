@@ -0,0 +1,78 @@
+//! Mouse wheel and trackpad scrolling, turned into whole lines. Wheels
+//! report notches (sometimes fractions of one) and trackpads report pixels
+//! with momentum; both are summed in pixels so slow scrolls add up instead
+//! of being dropped.
+
+use winit::event::{MouseScrollDelta, TouchPhase};
+
+/// Scroll distance not yet a whole line.
+#[derive(Debug, Default)]
+pub struct Wheel {
+    pixels: f64,
+}
+
+impl Wheel {
+    /// Lines to scroll for `delta`, positive toward history, with
+    /// `line_height` pixel lines. Each notch (or its worth of trackpad
+    /// pixels) moves `multiplier` lines; `invert` flips the direction. The
+    /// rest carries over to the next event.
+    pub fn lines(&mut self, delta: MouseScrollDelta, phase: TouchPhase, line_height: f64, multiplier: f64, invert: bool) -> i32 {
+        // A new trackpad gesture owes nothing to the last one.
+        if phase == TouchPhase::Started {
+            self.pixels = 0.0;
+        }
+        let px = match delta {
+            MouseScrollDelta::LineDelta(_, y) => y as f64 * line_height,
+            MouseScrollDelta::PixelDelta(pos) => pos.y,
+        };
+        let px = px * multiplier * if invert { -1.0 } else { 1.0 };
+        // Turning back drops what was left over going the other way, so the
+        // reversal moves at once.
+        if px * self.pixels < 0.0 {
+            self.pixels = 0.0;
+        }
+        self.pixels += px;
+        let lines = (self.pixels / line_height).trunc();
+        self.pixels -= lines * line_height;
+        lines as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winit::dpi::PhysicalPosition;
+
+    const LINE: f64 = 14.0;
+
+    fn notch(wheel: &mut Wheel, notches: f32) -> i32 {
+        wheel.lines(MouseScrollDelta::LineDelta(0.0, notches), TouchPhase::Moved, LINE, 3.0, false)
+    }
+
+    fn pixels(wheel: &mut Wheel, px: f64, phase: TouchPhase) -> i32 {
+        wheel.lines(MouseScrollDelta::PixelDelta(PhysicalPosition::new(0.0, px)), phase, LINE, 1.0, false)
+    }
+
+    #[test]
+    fn a_notch_is_three_lines_and_fractions_add_up() {
+        let mut wheel = Wheel::default();
+        assert_eq!(notch(&mut wheel, 1.0), 3);
+        assert_eq!(notch(&mut wheel, -1.0), -3);
+        // Half a notch is a line and a half: one now, the half kept.
+        assert_eq!(notch(&mut wheel, 0.5), 1);
+        assert_eq!(notch(&mut wheel, 0.5), 2);
+        let inverted = Wheel::default().lines(MouseScrollDelta::LineDelta(0.0, 1.0), TouchPhase::Moved, LINE, 5.0, true);
+        assert_eq!(inverted, -5);
+    }
+
+    #[test]
+    fn slow_trackpad_motion_is_not_lost() {
+        let mut wheel = Wheel::default();
+        let moved: i32 = (0..14).map(|_| pixels(&mut wheel, 3.0, TouchPhase::Moved)).sum();
+        assert_eq!(moved, 3);
+        // Reversing drops the 0-13px carried the other way.
+        assert_eq!(pixels(&mut wheel, -14.0, TouchPhase::Moved), -1);
+        pixels(&mut wheel, 10.0, TouchPhase::Moved);
+        assert_eq!(pixels(&mut wheel, 5.0, TouchPhase::Started), 0);
+    }
+}
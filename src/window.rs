@@ -0,0 +1,172 @@
+//! The window operations koi's event handlers use, behind a trait so the
+//! handlers can run against a stand-in window in tests.
+
+use std::num::NonZeroU32;
+
+use glutin::prelude::*;
+use glutin::surface::WindowSurface;
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+use winit::window::{CursorIcon, Theme as Appearance, Window};
+
+/// A window koi draws into.
+pub trait WindowOps {
+    fn inner_size(&self) -> PhysicalSize<u32>;
+    fn request_redraw(&self);
+    fn set_title(&self, title: &str);
+    fn set_min_inner_size(&self, size: PhysicalSize<u32>);
+
+    /// Show `icon` as the pointer over the window.
+    fn set_cursor(&self, icon: CursorIcon);
+    fn scale_factor(&self) -> f64;
+
+    /// Top-left of the window frame on the desktop, where the platform
+    /// can say.
+    fn outer_position(&self) -> Option<PhysicalPosition<i32>>;
+
+    /// Ask for a new inner size. Some(size) if it was applied at once;
+    /// None if it lands later as a `Resized` event.
+    fn request_inner_size(&self, size: PhysicalSize<u32>) -> Option<PhysicalSize<u32>>;
+
+    /// Neither maximized nor fullscreen, so resizing to fit content is ours
+    /// to do.
+    fn is_free_size(&self) -> bool;
+
+    /// Whether the window fills the screen, either kind of fullscreen.
+    fn is_fullscreen(&self) -> bool;
+
+    /// Leave fullscreen, or enter it: on macOS without a Space transition
+    /// if `simple`.
+    fn toggle_fullscreen(&self, simple: bool);
+
+    /// Whether the system draws windows light or dark, where the platform
+    /// can say.
+    fn appearance(&self) -> Option<Appearance>;
+
+    /// Refresh rate of the monitor the window is on, if known.
+    fn refresh_rate_millihertz(&self) -> Option<u32>;
+
+    /// Draw the user's eye to the window (the bell on Linux).
+    fn request_user_attention(&self);
+
+    /// Match the drawing surface to a new window size.
+    fn resize_surface(&self, width: NonZeroU32, height: NonZeroU32);
+
+    /// Show the frame just drawn.
+    fn swap_buffers(&self);
+
+    /// Open `url` with the system's handler for it.
+    fn open_url(&self, url: &str);
+}
+
+/// A winit window with the GL context and surface koi renders through.
+pub struct GlWindow {
+    pub window: Window,
+    pub gl_context: glutin::context::PossiblyCurrentContext,
+    pub gl_surface: glutin::surface::Surface<WindowSurface>,
+}
+
+impl GlWindow {
+    /// Send GL calls to this window's context. Each window has its own, so
+    /// the app switches before handing a window its events.
+    pub fn make_current(&self) {
+        if let Err(e) = self.gl_context.make_current(&self.gl_surface) {
+            log::error!("make_current failed: {}", e);
+        }
+    }
+}
+
+impl WindowOps for GlWindow {
+    fn inner_size(&self) -> PhysicalSize<u32> {
+        self.window.inner_size()
+    }
+
+    fn request_redraw(&self) {
+        self.window.request_redraw();
+    }
+
+    fn set_title(&self, title: &str) {
+        self.window.set_title(title);
+    }
+
+    fn set_min_inner_size(&self, size: PhysicalSize<u32>) {
+        self.window.set_min_inner_size(Some(size));
+    }
+
+    fn set_cursor(&self, icon: CursorIcon) {
+        self.window.set_cursor(icon);
+    }
+
+    fn scale_factor(&self) -> f64 {
+        self.window.scale_factor()
+    }
+
+    fn outer_position(&self) -> Option<PhysicalPosition<i32>> {
+        self.window.outer_position().ok()
+    }
+
+    fn request_inner_size(&self, size: PhysicalSize<u32>) -> Option<PhysicalSize<u32>> {
+        self.window.request_inner_size(size)
+    }
+
+    fn is_free_size(&self) -> bool {
+        !self.window.is_maximized() && !self.is_fullscreen()
+    }
+
+    fn is_fullscreen(&self) -> bool {
+        #[cfg(target_os = "macos")]
+        if winit::platform::macos::WindowExtMacOS::simple_fullscreen(&self.window) {
+            return true;
+        }
+        self.window.fullscreen().is_some()
+    }
+
+    fn toggle_fullscreen(&self, simple: bool) {
+        #[cfg(target_os = "macos")]
+        {
+            use winit::platform::macos::WindowExtMacOS;
+            // Leave whichever kind the window is in, whatever `simple` says.
+            if self.window.simple_fullscreen() || (simple && self.window.fullscreen().is_none()) {
+                self.window.set_simple_fullscreen(!self.window.simple_fullscreen());
+                return;
+            }
+        }
+        let _ = simple;
+        let next = match self.window.fullscreen() {
+            Some(_) => None,
+            None => Some(winit::window::Fullscreen::Borderless(None)),
+        };
+        self.window.set_fullscreen(next);
+    }
+
+    fn appearance(&self) -> Option<Appearance> {
+        self.window.theme()
+    }
+
+    fn refresh_rate_millihertz(&self) -> Option<u32> {
+        self.window.current_monitor().and_then(|m| m.refresh_rate_millihertz())
+    }
+
+    fn request_user_attention(&self) {
+        use winit::window::UserAttentionType;
+        self.window.request_user_attention(Some(UserAttentionType::Critical));
+    }
+
+    fn resize_surface(&self, width: NonZeroU32, height: NonZeroU32) {
+        self.gl_surface.resize(&self.gl_context, width, height);
+    }
+
+    fn swap_buffers(&self) {
+        if let Err(e) = self.gl_surface.swap_buffers(&self.gl_context) {
+            log::error!("swap_buffers failed: {}", e);
+        }
+    }
+
+    fn open_url(&self, url: &str) {
+        #[cfg(target_os = "macos")]
+        let _ = std::process::Command::new("open").arg(url).spawn();
+        #[cfg(target_os = "windows")]
+        let _ = std::process::Command::new("cmd").args(["/c", "start", "", url]).spawn();
+        #[cfg(all(unix, not(target_os = "macos")))]
+        let _ = std::process::Command::new("xdg-open").arg(url).spawn();
+    }
+}
@@ -0,0 +1,121 @@
+//! The window's size, position, font size and theme, saved at exit in
+//! `state.toml` in the state dir and used for the next window koi opens.
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+
+/// Font sizes koi zooms between.
+pub const FONT_SIZES: std::ops::RangeInclusive<f32> = 8.0..=32.0;
+
+/// What the last window looked like.
+///
+/// The size is logical, so a window reopening on a monitor with another
+/// scale factor keeps its apparent size; the font size is in points and
+/// scales the same way. The position is physical, as the OS places
+/// windows, and only restored if it's still on a monitor.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WindowState {
+    pub width: f64,
+    pub height: f64,
+    pub x: Option<i32>,
+    pub y: Option<i32>,
+    pub font_size: f32,
+    /// Name of a built-in or user theme.
+    pub theme: Option<String>,
+}
+
+impl WindowState {
+    fn path() -> Option<PathBuf> {
+        config::state_dir().map(|dir| dir.join("state.toml"))
+    }
+
+    /// The saved state, or None if there isn't any usable.
+    pub fn load() -> Option<Self> {
+        let path = Self::path()?;
+        let text = std::fs::read_to_string(&path).ok()?;
+        let state = Self::parse(&text);
+        if state.is_none() {
+            log::debug!("Ignoring unreadable window state in {}", path.display());
+        }
+        state
+    }
+
+    /// Parse saved state, rejecting sizes no window should open at.
+    pub fn parse(text: &str) -> Option<Self> {
+        let state: Self = toml::from_str(text).ok()?;
+        let sane = state.width >= 1.0
+            && state.height >= 1.0
+            && state.width.is_finite()
+            && state.height.is_finite()
+            && FONT_SIZES.contains(&state.font_size);
+        sane.then_some(state)
+    }
+
+    /// The position, if it was saved.
+    pub fn position(&self) -> Option<(i32, i32)> {
+        self.x.zip(self.y)
+    }
+
+    /// Write the state for the next launch. Failure only costs the next
+    /// window its old geometry, so it's logged and otherwise ignored.
+    pub fn save(&self) {
+        let Some(path) = Self::path() else { return };
+        let text = match toml::to_string(self) {
+            Ok(text) => text,
+            Err(e) => {
+                log::warn!("Can't encode window state: {}", e);
+                return;
+            }
+        };
+        // Write then rename, so a crash mid-write can't leave half a file.
+        let tmp = path.with_extension("toml.tmp");
+        let written = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(&tmp, text))
+            .and_then(|_| std::fs::rename(&tmp, &path));
+        if let Err(e) = written {
+            log::warn!("Can't save window state to {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state() -> WindowState {
+        WindowState {
+            width: 1280.0,
+            height: 800.5,
+            x: Some(-1440),
+            y: Some(25),
+            font_size: 17.0,
+            theme: Some("Catppuccin Mocha".into()),
+        }
+    }
+
+    #[test]
+    fn round_trips_through_toml() {
+        let text = toml::to_string(&state()).unwrap();
+        assert_eq!(WindowState::parse(&text), Some(state()));
+        assert_eq!(state().position(), Some((-1440, 25)));
+
+        let no_position = WindowState { x: None, y: None, theme: None, ..state() };
+        let text = toml::to_string(&no_position).unwrap();
+        assert_eq!(WindowState::parse(&text), Some(no_position));
+    }
+
+    #[test]
+    fn corrupt_or_out_of_range_state_is_ignored() {
+        assert_eq!(WindowState::parse(""), None);
+        assert_eq!(WindowState::parse("width = \"wide\""), None);
+        assert_eq!(WindowState::parse("\0\u{1}garbage"), None);
+        for bad in [WindowState { width: 0.0, ..state() }, WindowState { font_size: 400.0, ..state() }] {
+            assert_eq!(WindowState::parse(&toml::to_string(&bad).unwrap()), None);
+        }
+    }
+}